@@ -50,10 +50,9 @@ fn bench_single_generation(c: &mut Criterion) {
 fn bench_protocol_versions(c: &mut Criterion) {
     let mut group = c.benchmark_group("protocol_versions");
 
-    for version_num in 0..=5 {
-        let version = Version::try_from(version_num).unwrap();
+    for version in Version::all() {
         group.bench_with_input(
-            BenchmarkId::from_parameter(version_num),
+            BenchmarkId::from_parameter(version),
             &version,
             |b, &version| {
                 b.iter(|| {
@@ -87,6 +86,35 @@ fn bench_batch_generation(c: &mut Criterion) {
     group.finish();
 }
 
+fn bench_generator_reuse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("generator_reuse");
+    let size = 1000;
+
+    group.throughput(Throughput::Elements(size));
+    group.bench_function("fresh_generator", |b| {
+        b.iter(|| {
+            for i in 0..size {
+                let mut gen = Generator::new(Version::V3).with_seed(42 + i);
+                black_box(gen.generate().unwrap());
+            }
+        });
+    });
+
+    group.bench_function("generate_into", |b| {
+        let mut gen = Generator::new(Version::V3);
+        let mut buf = Vec::new();
+        b.iter(|| {
+            for i in 0..size {
+                gen.set_seed(Some(42 + i));
+                gen.generate_into(&mut buf).unwrap();
+                black_box(&buf);
+            }
+        });
+    });
+
+    group.finish();
+}
+
 fn bench_deterministic_generation(c: &mut Criterion) {
     let mut group = c.benchmark_group("deterministic");
 
@@ -144,6 +172,7 @@ criterion_group!(
     bench_single_generation,
     bench_protocol_versions,
     bench_batch_generation,
+    bench_generator_reuse,
     bench_deterministic_generation,
     bench_opcode_complexity
 );
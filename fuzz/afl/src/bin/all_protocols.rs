@@ -0,0 +1,42 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! AFL++ counterpart of the `all_protocols` cargo-fuzz target.
+//!
+//! see [`pickle_fuzzer::fuzz_harness`] for the input layout.
+//!
+//! ```text
+//! cargo afl build --release
+//! cargo afl fuzz -i in -o out target/release/all_protocols
+//! ```
+
+use std::cell::RefCell;
+
+use pickle_fuzzer::fuzz_harness::run_all_protocols;
+use pickle_fuzzer::{Generator, Version};
+
+thread_local! {
+    // one generator and output buffer for the whole session; kept out of the
+    // closure because afl::fuzz! requires it to be unwind safe
+    static WORKER: RefCell<(Generator, Vec<u8>)> =
+        RefCell::new((Generator::new(Version::default()), Vec::new()));
+}
+
+fn main() {
+    afl::fuzz!(|data: &[u8]| {
+        WORKER.with_borrow_mut(|(gen, pickle)| run_all_protocols(gen, pickle, data));
+    });
+}
@@ -0,0 +1,33 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! AFL++ counterpart of the `round_trip` cargo-fuzz target.
+//!
+//! see [`pickle_fuzzer::fuzz_harness::run_round_trip`] for the input layout
+//! and checks.
+//!
+//! ```text
+//! cargo afl build --release
+//! cargo afl fuzz -i in -o out target/release/round_trip
+//! ```
+
+use pickle_fuzzer::fuzz_harness::run_round_trip;
+
+fn main() {
+    afl::fuzz!(|data: &[u8]| {
+        run_round_trip(data);
+    });
+}
@@ -15,38 +15,24 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
+//! fast protocol coverage target.
+//!
+//! see [`pickle_fuzzer::fuzz_harness`] for the input layout; the same inputs
+//! reproduce under the honggfuzz and AFL++ harnesses.
+
+use std::cell::RefCell;
 
 use libfuzzer_sys::fuzz_target;
+use pickle_fuzzer::fuzz_harness::run_all_protocols;
 use pickle_fuzzer::{Generator, Version};
 
+thread_local! {
+    // one generator and output buffer for the whole fuzzing session so
+    // iterations don't pay for fresh stack/memo/output allocations
+    static WORKER: RefCell<(Generator, Vec<u8>)> =
+        RefCell::new((Generator::new(Version::default()), Vec::new()));
+}
+
 fuzz_target!(|data: &[u8]| {
-    if data.is_empty() {
-        return;
-    }
-    
-    // use first byte to select protocol
-    let protocol = (data[0] % 6) as usize; // 0-5
-    let version = Version::try_from(protocol).unwrap();
-    
-    let mut gen = Generator::new(version);
-    
-    // use remaining bytes for generation
-    if let Ok(pickle) = gen.generate_from_arbitrary(&data[1..]) {
-        assert!(!pickle.is_empty());
-        assert_eq!(pickle[pickle.len() - 1], b'.');
-        
-        // protocol-specific validation
-        match version {
-            Version::V0 | Version::V1 => {
-                // no PROTO opcode in v0/v1
-                assert!(!pickle.starts_with(b"\x80"));
-            }
-            _ => {
-                // v2+ should have PROTO opcode
-                if pickle.len() > 2 {
-                    assert_eq!(pickle[0], 0x80, "missing PROTO in v{}", protocol);
-                }
-            }
-        }
-    }
+    WORKER.with_borrow_mut(|(gen, pickle)| run_all_protocols(gen, pickle, data));
 });
@@ -0,0 +1,36 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! differential target for the native validator.
+//!
+//! generates a pickle exactly like `validate_with_python` and asserts that
+//! [`pickle_fuzzer::disasm::validate`] accepts it if and only if Python's
+//! strict `pickletools` validation does. a disagreement is a bug in one of
+//! them: usually the native parser, which is what the CLI's `validate` and
+//! `dis` commands and the property tests rely on.
+//!
+//! # Input Format
+//!
+//! the same as `validate_with_python`, so the two targets share a corpus.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pickle_fuzzer_fuzz::harness::run_differential_target;
+use pickle_fuzzer_fuzz::pickletools::validate_in_subprocess;
+
+fuzz_target!(|data: &[u8]| {
+    run_differential_target(data, validate_in_subprocess);
+});
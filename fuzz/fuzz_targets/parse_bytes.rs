@@ -0,0 +1,31 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! parser robustness target.
+//!
+//! feeds the raw input to the native disassembler and validator
+//! ([`pickle_fuzzer::disasm`]) as a pickle, and asserts they never panic and
+//! agree with each other; see [`pickle_fuzzer::fuzz_harness::check_parser`].
+//! no generator is involved, so any byte string is a useful input and
+//! corpora of real pickles can be used as they are.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pickle_fuzzer::fuzz_harness::check_parser;
+
+fuzz_target!(|data: &[u8]| {
+    check_parser(data);
+});
@@ -0,0 +1,35 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! encode/decode symmetry target.
+//!
+//! generates a pickle from a [`pickle_fuzzer::FuzzConfig`] input with its
+//! mutators left out, decodes it into the typed `Opcode` IR, and asserts that
+//! re-encoding the opcodes reproduces the pickle byte for byte; see
+//! [`pickle_fuzzer::fuzz_harness::run_round_trip`]. a failure names the first
+//! opcode whose encoder and decoder disagree.
+//!
+//! # Input Format
+//!
+//! the same as `validate_with_python`, so the two targets share a corpus.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pickle_fuzzer::fuzz_harness::run_round_trip;
+
+fuzz_target!(|data: &[u8]| {
+    run_round_trip(data);
+});
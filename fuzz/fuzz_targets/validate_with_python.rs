@@ -20,7 +20,8 @@
 //! - protocol version (0-5)
 //! - opcode range (min/max opcodes to generate)
 //! - mutation rate (0.0-1.0)
-//! - mutator selection (via bit flags)
+//! - mutator selection
+//! - EXT, buffer, and persistent-id opcode opt-ins
 //! - arbitrary data seed for generation
 //!
 //! the generated pickles are validated using Python's `pickletools.dis()`
@@ -34,19 +35,8 @@
 //!
 //! # Input Format
 //!
-//! The fuzzer input is structured as follows:
-//! - Byte 0: Protocol version selector (modulo 6 → 0-5)
-//! - Bytes 1-2: Minimum opcode count (little-endian u16)
-//! - Bytes 3-4: Maximum opcode count (little-endian u16)
-//! - Byte 5: Mutation rate (0-255 → 0.0-1.0)
-//! - Byte 6: Mutator flags (bit field)
-//!   - Bit 0 (0x01): BitFlipMutator
-//!   - Bit 1 (0x02): BoundaryMutator
-//!   - Bit 2 (0x04): OffByOneMutator
-//!   - Bit 3 (0x08): StringLengthMutator
-//!   - Bit 4 (0x10): CharacterMutator
-//!   - Bit 5-7: Reserved (unused)
-//! - Bytes 7+: Arbitrary data seed for generation
+//! the input starts with a [`pickle_fuzzer::FuzzConfig`] decoded with `arbitrary`;
+//! the remaining bytes drive generation.
 //!
 //! # Validation
 //!
@@ -61,120 +51,9 @@
 #![no_main]
 
 use libfuzzer_sys::fuzz_target;
-use pickle_fuzzer_fuzz::python_env::{spawn_python_command, PythonEnvPolicy};
-use pickle_fuzzer::mutators::{
-    BitFlipMutator, BoundaryMutator, CharacterMutator, OffByOneMutator, StringLengthMutator,
-};
-use pickle_fuzzer::{Generator, Version};
-use std::io::Write;
-use std::process::Stdio;
-
-const STRICT_PICKLETOOLS_VALIDATOR: &str = r#"import io
-import pickletools
-import sys
-
-data = sys.stdin.buffer.read()
-stop_pos = None
-for _opcode, _arg, pos in pickletools.genops(data):
-    stop_pos = pos
-if stop_pos is None:
-    raise ValueError("pickle exhausted before seeing STOP")
-if stop_pos + 1 != len(data):
-    raise ValueError(f"trailing bytes after STOP: {len(data) - (stop_pos + 1)}")
-pickletools.dis(data, out=io.StringIO())
-"#;
-
-/// validate pickle using Python's pickletools plus a whole-file STOP check
-fn validate_with_python(pickle_bytes: &[u8]) -> bool {
-    let mut child = match spawn_python_command(PythonEnvPolicy::from_env_var())
-        .arg("-c")
-        .arg(STRICT_PICKLETOOLS_VALIDATOR)
-        .stdin(Stdio::piped())
-        .stdout(Stdio::null())
-        .stderr(Stdio::piped())
-        .spawn()
-    {
-        Ok(child) => child,
-        Err(err) => panic!("validate_with_python fuzz target requires python3 on PATH: {err}"),
-    };
-
-    if let Some(mut stdin) = child.stdin.take() {
-        let _ = stdin.write_all(pickle_bytes);
-    }
-
-    let output = child.wait_with_output().unwrap();
-    output.status.success()
-}
+use pickle_fuzzer_fuzz::harness::run_validation_target;
+use pickle_fuzzer_fuzz::pickletools::validate_in_subprocess;
 
 fuzz_target!(|data: &[u8]| {
-    // need at least 7 bytes for configuration + some arbitrary data
-    if data.len() < 7 {
-        return;
-    }
-
-    // byte 0: protocol version (0-5)
-    let protocol = (data[0] % 6) as usize;
-    let version = Version::try_from(protocol).unwrap();
-
-    let gen = Generator::new(version);
-
-    // bytes 1-4: opcode range (min/max)
-    let min_opcodes = u16::from_le_bytes([data[1], data[2]]) as usize;
-    let max_opcodes = u16::from_le_bytes([data[3], data[4]]) as usize;
-
-    // ensure valid range and cap at 1000 opcodes to prevent stack overflow
-    let mut gen = if min_opcodes > max_opcodes {
-        if min_opcodes > 1000 {
-            return;
-        }
-        gen.with_opcode_range(max_opcodes, min_opcodes)
-    } else {
-        if max_opcodes > 1000 {
-            return;
-        }
-        gen.with_opcode_range(min_opcodes, max_opcodes)
-    };
-
-    // byte 5: mutation rate configuration
-    let config = data[5];
-    let mutation_rate = (config as f64) / 255.0; // map 0-255 to 0.0-1.0
-    gen = gen.with_mutation_rate(mutation_rate);
-
-    // byte 6: mutator selection via bit flags
-    let mutator_flags = data[6];
-    if mutator_flags & 0x01 != 0 {
-        gen = gen.with_mutator(Box::new(BitFlipMutator));
-    }
-    if mutator_flags & 0x02 != 0 {
-        gen = gen.with_mutator(Box::new(BoundaryMutator));
-    }
-    if mutator_flags & 0x04 != 0 {
-        gen = gen.with_mutator(Box::new(OffByOneMutator));
-    }
-    if mutator_flags & 0x08 != 0 {
-        gen = gen.with_mutator(Box::new(StringLengthMutator));
-    }
-    if mutator_flags & 0x10 != 0 {
-        gen = gen.with_mutator(Box::new(CharacterMutator));
-    }
-    // note: MemoIndexMutator is intentionally excluded from fuzzing
-    // even in "safe" mode, it can generate invalid memo references (keys that don't exist)
-    // this fuzz target is only for validating the generator's output, so we omit it
-
-    // bytes 7+: arbitrary data seed for generation
-    if let Ok(pickle) = gen.generate_from_arbitrary(&data[7..]) {
-        // basic structural validation
-        assert!(!pickle.is_empty(), "generated pickle must not be empty");
-        assert_eq!(
-            pickle[pickle.len() - 1],
-            b'.',
-            "pickle must end with STOP opcode"
-        );
-
-        // validate with Python's pickletools
-        assert!(
-            validate_with_python(&pickle),
-            "generated pickle failed Python validation"
-        );
-    }
+    run_validation_target(data, validate_in_subprocess);
 });
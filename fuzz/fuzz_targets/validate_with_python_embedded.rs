@@ -0,0 +1,40 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! in-process variant of `validate_with_python`.
+//!
+//! runs the same strict `pickletools` validation, but inside a CPython
+//! interpreter embedded through pyo3 instead of a `python3` subprocess per
+//! input, which removes the process spawn from every iteration. the input
+//! format and checks are identical, so corpora can be shared between the two.
+//!
+//! requires the `embedded-python` feature and a Python installation with a
+//! shared `libpython`:
+//!
+//! ```text
+//! cargo fuzz run --features embedded-python validate_with_python_embedded
+//! ```
+//!
+//! `validate_with_python` remains the fallback wherever embedding isn't possible.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pickle_fuzzer_fuzz::harness::run_validation_target;
+use pickle_fuzzer_fuzz::pickletools::validate_embedded;
+
+fuzz_target!(|data: &[u8]| {
+    run_validation_target(data, validate_embedded);
+});
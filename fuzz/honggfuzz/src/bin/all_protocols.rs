@@ -0,0 +1,39 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! honggfuzz counterpart of the `all_protocols` cargo-fuzz target.
+//!
+//! see [`pickle_fuzzer::fuzz_harness`] for the input layout.
+//!
+//! ```text
+//! cargo hfuzz run all_protocols
+//! ```
+
+use honggfuzz::fuzz;
+use pickle_fuzzer::fuzz_harness::run_all_protocols;
+use pickle_fuzzer::{Generator, Version};
+
+fn main() {
+    // one generator and output buffer for the whole session
+    let mut gen = Generator::new(Version::default());
+    let mut pickle = Vec::new();
+
+    loop {
+        fuzz!(|data: &[u8]| {
+            run_all_protocols(&mut gen, &mut pickle, data);
+        });
+    }
+}
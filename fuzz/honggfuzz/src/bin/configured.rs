@@ -0,0 +1,36 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! honggfuzz target for the configured input layout.
+//!
+//! decodes the same configuration prefix as the cargo-fuzz
+//! `validate_with_python` target and checks structural validity of the
+//! result; see [`pickle_fuzzer::fuzz_harness`] for the layout.
+//!
+//! ```text
+//! cargo hfuzz run configured
+//! ```
+
+use honggfuzz::fuzz;
+use pickle_fuzzer::fuzz_harness::run_configured;
+
+fn main() {
+    loop {
+        fuzz!(|data: &[u8]| {
+            run_configured(data);
+        });
+    }
+}
@@ -0,0 +1,52 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! libFuzzer glue on top of [`pickle_fuzzer::fuzz_harness`].
+//!
+//! the input layout and generator configuration live in the library so the
+//! honggfuzz and AFL++ harnesses decode inputs identically; this module only
+//! adds the python-backed validation steps.
+
+use pickle_fuzzer::disasm::validate as validate_natively;
+use pickle_fuzzer::fuzz_harness::run_configured;
+pub use pickle_fuzzer::fuzz_harness::FuzzConfig;
+
+/// generate a pickle from a fuzzer input and assert that `validate` accepts it.
+pub fn run_validation_target(data: &[u8], validate: impl FnOnce(&[u8]) -> bool) {
+    if let Some(pickle) = run_configured(data) {
+        // validate with Python's pickletools
+        assert!(
+            validate(&pickle),
+            "generated pickle failed Python validation"
+        );
+    }
+}
+
+/// generate a pickle from a fuzzer input and assert that the native validator
+/// accepts it exactly when `validate` does.
+pub fn run_differential_target(data: &[u8], validate: impl FnOnce(&[u8]) -> bool) {
+    if let Some(pickle) = run_configured(data) {
+        let native = validate_natively(&pickle);
+        let python = validate(&pickle);
+        assert_eq!(
+            native.is_ok(),
+            python,
+            "native validator ({}) disagrees with Python's pickletools ({})",
+            native.map_or_else(|err| format!("rejects: {err}"), |()| "accepts".into()),
+            if python { "accepts" } else { "rejects" }
+        );
+    }
+}
@@ -14,4 +14,6 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod harness;
+pub mod pickletools;
 pub mod python_env;
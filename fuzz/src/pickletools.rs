@@ -0,0 +1,119 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! strict `pickletools` validation shared by the python-backed fuzz targets.
+//!
+//! the same python check runs either in a `python3` subprocess per input, which
+//! works everywhere, or in an interpreter embedded through pyo3 when the
+//! `embedded-python` feature is enabled, which skips the process spawn entirely.
+
+use std::io::Write;
+use std::process::Stdio;
+
+use crate::python_env::{spawn_python_command, PythonEnvPolicy};
+
+macro_rules! validator_source {
+    () => {
+        r#"import io
+import pickletools
+
+
+def validate(data):
+    stop_pos = None
+    for _opcode, _arg, pos in pickletools.genops(data):
+        stop_pos = pos
+    if stop_pos is None:
+        raise ValueError("pickle exhausted before seeing STOP")
+    if stop_pos + 1 != len(data):
+        raise ValueError(f"trailing bytes after STOP: {len(data) - (stop_pos + 1)}")
+    pickletools.dis(data, out=io.StringIO())
+"#
+    };
+}
+
+/// python source defining `validate(data)`, which raises if `data` is not a
+/// complete pickle ending exactly at its STOP opcode.
+pub const VALIDATOR_SOURCE: &str = validator_source!();
+
+const SUBPROCESS_VALIDATOR: &str = concat!(
+    validator_source!(),
+    r#"
+
+import sys
+
+validate(sys.stdin.buffer.read())
+"#
+);
+
+/// validate a pickle by piping it into a fresh `python3` process.
+///
+/// panics if `python3` cannot be started, since the fuzz target is meaningless
+/// without its reference implementation.
+pub fn validate_in_subprocess(pickle_bytes: &[u8]) -> bool {
+    let mut child = match spawn_python_command(PythonEnvPolicy::from_env_var())
+        .arg("-c")
+        .arg(SUBPROCESS_VALIDATOR)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(err) => panic!("python validation requires python3 on PATH: {err}"),
+    };
+
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = stdin.write_all(pickle_bytes);
+    }
+
+    let output = child.wait_with_output().unwrap();
+    output.status.success()
+}
+
+/// validate a pickle with the embedded interpreter.
+///
+/// the interpreter and the compiled `validate` function are created on first
+/// use and reused for every later call.
+#[cfg(feature = "embedded-python")]
+pub fn validate_embedded(pickle_bytes: &[u8]) -> bool {
+    use pyo3::prelude::*;
+    use pyo3::sync::PyOnceLock;
+    use pyo3::types::PyBytes;
+    use std::ffi::CString;
+
+    static VALIDATE: PyOnceLock<Py<PyAny>> = PyOnceLock::new();
+
+    Python::attach(|py| {
+        let validate = VALIDATE.get_or_init(py, || {
+            let source = CString::new(VALIDATOR_SOURCE).expect("validator source has no NUL bytes");
+            let module = PyModule::from_code(
+                py,
+                &source,
+                c"pickle_fuzz_validator.py",
+                c"pickle_fuzz_validator",
+            )
+            .unwrap_or_else(|err| panic!("failed to load embedded pickletools validator: {err}"));
+            module
+                .getattr("validate")
+                .expect("validator module defines validate()")
+                .unbind()
+        });
+
+        validate
+            .call1(py, (PyBytes::new(py, pickle_bytes),))
+            .is_ok()
+    })
+}
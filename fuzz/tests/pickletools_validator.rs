@@ -0,0 +1,92 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use pickle_fuzzer::disasm::validate;
+use pickle_fuzzer::{Generator, Version};
+use pickle_fuzzer_fuzz::harness::{run_differential_target, FuzzConfig};
+use pickle_fuzzer_fuzz::pickletools::validate_in_subprocess;
+
+fn sample_pickle() -> Vec<u8> {
+    Generator::new(Version::V4)
+        .with_seed(7)
+        .with_opcode_range(20, 40)
+        .generate()
+        .unwrap()
+}
+
+#[test]
+fn subprocess_validator_accepts_generated_pickle() {
+    assert!(validate_in_subprocess(&sample_pickle()));
+}
+
+#[test]
+fn subprocess_validator_rejects_trailing_bytes_and_truncation() {
+    let mut trailing = sample_pickle();
+    trailing.push(b'N');
+    assert!(!validate_in_subprocess(&trailing));
+
+    let pickle = sample_pickle();
+    assert!(!validate_in_subprocess(&pickle[..pickle.len() - 1]));
+}
+
+#[test]
+fn native_validator_matches_subprocess_validator() {
+    let pickle = sample_pickle();
+    let mut trailing = pickle.clone();
+    trailing.push(b'N');
+
+    for input in [
+        &pickle[..],
+        &trailing[..],
+        &pickle[..pickle.len() - 1],
+        &[][..],
+        &b"(."[..],
+    ] {
+        assert_eq!(validate(input).is_ok(), validate_in_subprocess(input));
+    }
+    run_differential_target(&[0x5a; 96], validate_in_subprocess);
+}
+
+#[cfg(feature = "embedded-python")]
+#[test]
+fn embedded_validator_matches_subprocess_validator() {
+    use pickle_fuzzer_fuzz::pickletools::validate_embedded;
+
+    let pickle = sample_pickle();
+    let mut trailing = pickle.clone();
+    trailing.push(b'N');
+
+    for input in [
+        &pickle[..],
+        &trailing[..],
+        &pickle[..pickle.len() - 1],
+        &[][..],
+    ] {
+        assert_eq!(validate_embedded(input), validate_in_subprocess(input));
+    }
+}
+
+#[test]
+fn fuzz_config_splits_config_from_entropy() {
+    let mut data = vec![0u8; 64];
+    data.extend_from_slice(b"entropy");
+    let (config, entropy) = FuzzConfig::decode(&data).unwrap();
+    assert!(entropy.ends_with(b"entropy"));
+
+    let gen = config.build();
+    assert_eq!(gen.min_opcodes, config.min_opcodes.min(config.max_opcodes));
+    assert!(gen.mutators.is_empty());
+}
@@ -0,0 +1,183 @@
+/*
+ * SPDX-License-Identifier: Apache-2.0
+ *
+ * Copyright 2025 Cisco Systems, Inc. and its affiliates
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+/*
+ * C API for pickle-fuzzer, built with `cargo build --release --features capi`
+ * (libpickle_fuzzer.so / .dylib / pickle_fuzzer.dll).
+ *
+ *     PickleFuzzerConfig config = {.struct_size = sizeof(PickleFuzzerConfig)};
+ *     pickle_fuzzer_config_default(&config);
+ *     config.protocol = 4;
+ *     config.has_seed = true;
+ *     config.seed = 42;
+ *
+ *     uint8_t *pickle;
+ *     size_t len;
+ *     if (pickle_fuzzer_generate(&config, &pickle, &len) != PICKLE_FUZZER_OK) {
+ *         fprintf(stderr, "%s\n", pickle_fuzzer_last_error());
+ *         return 1;
+ *     }
+ *     fwrite(pickle, 1, len, stdout);
+ *     pickle_fuzzer_free(pickle, len);
+ *
+ * Keep in sync with src/capi.rs.
+ */
+
+#ifndef PICKLE_FUZZER_H
+#define PICKLE_FUZZER_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* return codes */
+#define PICKLE_FUZZER_OK 0
+#define PICKLE_FUZZER_ERR_NULL (-1)     /* a required pointer was NULL */
+#define PICKLE_FUZZER_ERR_CONFIG (-2)   /* the configuration is invalid */
+#define PICKLE_FUZZER_ERR_GENERATE (-3) /* generation failed (budget too small, ...) */
+#define PICKLE_FUZZER_ERR_PANIC (-4)    /* internal error */
+
+/* bits for PickleFuzzerConfig.mutators */
+#define PICKLE_FUZZER_MUTATOR_BITFLIP (UINT64_C(1) << 0)
+#define PICKLE_FUZZER_MUTATOR_BOUNDARY (UINT64_C(1) << 1)
+#define PICKLE_FUZZER_MUTATOR_OFFBYONE (UINT64_C(1) << 2)
+#define PICKLE_FUZZER_MUTATOR_STRINGLEN (UINT64_C(1) << 3)
+#define PICKLE_FUZZER_MUTATOR_CHARACTER (UINT64_C(1) << 4)
+#define PICKLE_FUZZER_MUTATOR_MEMOINDEX (UINT64_C(1) << 5)     /* needs unsafe_mutations */
+#define PICKLE_FUZZER_MUTATOR_TYPECONFUSION (UINT64_C(1) << 6) /* needs unsafe_mutations */
+#define PICKLE_FUZZER_MUTATOR_BROKENQUOTING (UINT64_C(1) << 7) /* needs unsafe_mutations */
+#define PICKLE_FUZZER_MUTATOR_TEXTNUMBER (UINT64_C(1) << 8)
+#define PICKLE_FUZZER_MUTATOR_DICTIONARY (UINT64_C(1) << 9)
+#define PICKLE_FUZZER_MUTATOR_HAVOC (UINT64_C(1) << 10)        /* needs unsafe_mutations */
+#define PICKLE_FUZZER_MUTATOR_ENCODINGCONFUSION (UINT64_C(1) << 11)
+#define PICKLE_FUZZER_MUTATOR_MEMOORDER (UINT64_C(1) << 12)    /* needs unsafe_mutations */
+#define PICKLE_FUZZER_MUTATOR_LENGTHBOUNDARY (UINT64_C(1) << 13)
+#define PICKLE_FUZZER_MUTATOR_HOMOGLYPH (UINT64_C(1) << 14)
+#define PICKLE_FUZZER_MUTATOR_WHITESPACE (UINT64_C(1) << 15)
+
+/* values for PickleFuzzerConfig.cleanup_policy */
+#define PICKLE_FUZZER_CLEANUP_TUPLE 0
+#define PICKLE_FUZZER_CLEANUP_KEEP_ROOT 1
+
+/* values for PickleFuzzerConfig.container_sizes */
+#define PICKLE_FUZZER_SIZES_NONE 0
+#define PICKLE_FUZZER_SIZES_CONSTANT 1 /* always container_size_max */
+#define PICKLE_FUZZER_SIZES_UNIFORM 2  /* container_size_min to container_size_max */
+#define PICKLE_FUZZER_SIZES_ZIPF 3     /* 0 to container_size_max, container_size_exponent */
+
+/* bits for PickleFuzzerConfig.ndarray_dtypes, named by numpy type code */
+#define PICKLE_FUZZER_DTYPE_B1 (UINT32_C(1) << 0)
+#define PICKLE_FUZZER_DTYPE_I1 (UINT32_C(1) << 1)
+#define PICKLE_FUZZER_DTYPE_I2 (UINT32_C(1) << 2)
+#define PICKLE_FUZZER_DTYPE_I4 (UINT32_C(1) << 3)
+#define PICKLE_FUZZER_DTYPE_I8 (UINT32_C(1) << 4)
+#define PICKLE_FUZZER_DTYPE_U1 (UINT32_C(1) << 5)
+#define PICKLE_FUZZER_DTYPE_U2 (UINT32_C(1) << 6)
+#define PICKLE_FUZZER_DTYPE_U4 (UINT32_C(1) << 7)
+#define PICKLE_FUZZER_DTYPE_U8 (UINT32_C(1) << 8)
+#define PICKLE_FUZZER_DTYPE_F2 (UINT32_C(1) << 9)
+#define PICKLE_FUZZER_DTYPE_F4 (UINT32_C(1) << 10)
+#define PICKLE_FUZZER_DTYPE_F8 (UINT32_C(1) << 11)
+#define PICKLE_FUZZER_DTYPE_C8 (UINT32_C(1) << 12)
+#define PICKLE_FUZZER_DTYPE_C16 (UINT32_C(1) << 13)
+
+/*
+ * Generator configuration, mirroring the Rust builder options. Set
+ * struct_size to sizeof(PickleFuzzerConfig), initialize the rest with
+ * pickle_fuzzer_config_default(), then override fields. The library reads and
+ * writes only the first struct_size bytes, so a program built against an
+ * older header keeps working with a newer library: fields it doesn't know
+ * keep their defaults. New fields are only ever appended.
+ */
+typedef struct PickleFuzzerConfig {
+    size_t struct_size;        /* sizeof(PickleFuzzerConfig), set by the caller */
+    uint32_t protocol;         /* pickle protocol version, 0-5 */
+    bool has_seed;             /* use seed; otherwise draw OS entropy */
+    uint64_t seed;             /* seed for reproducible output */
+    size_t min_opcodes;        /* minimum number of opcodes */
+    size_t max_opcodes;        /* maximum number of opcodes */
+    size_t max_size;           /* maximum pickle size in bytes, 0 for no limit */
+    uint64_t mutators;         /* PICKLE_FUZZER_MUTATOR_* bitmask */
+    double mutation_rate;      /* mutation probability, 0.0-1.0 */
+    bool unsafe_mutations;     /* allow mutators that produce invalid pickles */
+    bool allow_ext;            /* allow EXT1/EXT2/EXT4 */
+    bool allow_buffer;         /* allow NEXT_BUFFER/READONLY_BUFFER */
+    bool allow_persistent_ids; /* allow PERSID/BINPERSID */
+    size_t max_stack_depth;    /* maximum pickle stack size, 0 for no limit */
+    uint32_t cleanup_policy;   /* PICKLE_FUZZER_CLEANUP_* */
+    bool integer_boundaries;   /* bias integers toward encoding boundaries */
+    bool strict_checks;        /* verify invariants at every emission */
+    bool indirect_stack_globals; /* build STACK_GLOBAL names indirectly */
+    bool interesting_patterns;   /* emit multi-opcode idioms as one step */
+    bool canonical;              /* pickle a random object like CPython does */
+    bool diverse_encodings;      /* ... with a random valid encoding per value */
+    uint32_t container_sizes;       /* PICKLE_FUZZER_SIZES_* */
+    size_t container_size_min;      /* smallest uniform size */
+    size_t container_size_max;      /* largest (or constant) size */
+    double container_size_exponent; /* zipf exponent, default 1.0 */
+    bool oversized_batches;         /* batches over CPython's 1000 items */
+    uint32_t ndarray_dtypes;        /* PICKLE_FUZZER_DTYPE_* bitmask, 0 for no arrays */
+    size_t ndarray_max_dims;        /* most array dimensions, default 3 */
+    size_t ndarray_max_len;         /* longest array dimension, default 8 */
+    bool torch_tensors;             /* torch.save tensors, needs allow_persistent_ids */
+    bool sklearn_estimators;        /* joblib-pickled sklearn estimators */
+} PickleFuzzerConfig;
+
+/*
+ * Fill the first config->struct_size bytes of *config with the defaults,
+ * leaving struct_size alone. Fails with PICKLE_FUZZER_ERR_CONFIG if
+ * struct_size is not set.
+ */
+int32_t pickle_fuzzer_config_default(PickleFuzzerConfig *config);
+
+/*
+ * Generate one pickle. On success, *out_buf and *out_len receive a buffer
+ * that must be released with pickle_fuzzer_free().
+ */
+int32_t pickle_fuzzer_generate(const PickleFuzzerConfig *config, uint8_t **out_buf,
+                               size_t *out_len);
+
+/*
+ * Generate one pickle whose decisions are drawn from data (for
+ * coverage-guided fuzz targets). data may be NULL when data_len is 0.
+ */
+int32_t pickle_fuzzer_generate_from_bytes(const PickleFuzzerConfig *config,
+                                          const uint8_t *data, size_t data_len,
+                                          uint8_t **out_buf, size_t *out_len);
+
+/* Release a buffer returned by a generate function. NULL is ignored. */
+void pickle_fuzzer_free(uint8_t *buf, size_t len);
+
+/*
+ * Describe the last error on the calling thread. The string is owned by the
+ * library and valid until the next failing call on the same thread.
+ */
+const char *pickle_fuzzer_last_error(void);
+
+/* Output format version (GENERATOR_FORMAT_VERSION). */
+uint32_t pickle_fuzzer_format_version(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* PICKLE_FUZZER_H */
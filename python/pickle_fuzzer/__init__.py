@@ -13,7 +13,13 @@
 # limitations under the License.
 #
 # SPDX-License-Identifier: Apache-2.0
-from pickle_fuzzer._native import Generator
+from pickle_fuzzer._native import (
+    GENERATOR_FORMAT_VERSION,
+    Generator,
+    disassemble,
+    mutate,
+    validate,
+)
 
 __version__ = "0.1.0"
-__all__ = ["Generator"]
+__all__ = ["GENERATOR_FORMAT_VERSION", "Generator", "disassemble", "mutate", "validate"]
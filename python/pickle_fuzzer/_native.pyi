@@ -13,7 +13,19 @@
 # limitations under the License.
 #
 # SPDX-License-Identifier: Apache-2.0
-from typing import Optional
+import os
+from typing import Any, Iterator, List, Optional, Tuple, Union, overload
+
+GENERATOR_FORMAT_VERSION: int
+
+def mutate(data: bytes, max_size: int, seed: int) -> bytes: ...
+def disassemble(data: bytes) -> List[Tuple[str, Any, int]]: ...
+def validate(data: bytes) -> Tuple[bool, str]: ...
+
+class SampleIterator(Iterator[bytes]):
+    def __iter__(self) -> "SampleIterator": ...
+    def __next__(self) -> bytes: ...
+    def __len__(self) -> int: ...
 
 class Generator:
     def __init__(
@@ -21,8 +33,52 @@ class Generator:
         protocol: int = 3,
         seed: Optional[int] = None,
         allow_persistent_ids: bool = False,
+        *,
+        min_opcodes: Optional[int] = None,
+        max_opcodes: Optional[int] = None,
+        mutators: Optional[List[str]] = None,
+        mutation_rate: Optional[float] = None,
+        unsafe_mutations: bool = False,
+        allow_ext: bool = False,
+        allow_buffer: bool = False,
     ) -> None: ...
     def generate(self, max_size: Optional[int] = None) -> bytes: ...
     def generate_from_bytes(self, data: bytes, max_size: Optional[int] = None) -> bytes: ...
+    @overload
+    def generate_batch(self, n: int, dir: None = None, jobs: Optional[int] = None) -> List[bytes]: ...
+    @overload
+    def generate_batch(
+        self, n: int, dir: Union[str, os.PathLike[str]], jobs: Optional[int] = None
+    ) -> List[str]: ...
+    def samples(self, count: Optional[int] = None) -> SampleIterator: ...
+    def __iter__(self) -> SampleIterator: ...
+    @property
+    def protocol(self) -> int: ...
+    def set_protocol(self, protocol: int) -> None: ...
+    @property
+    def seed(self) -> Optional[int]: ...
+    def set_seed(self, seed: Optional[int] = None) -> None: ...
+    @property
+    def min_opcodes(self) -> int: ...
+    @property
+    def max_opcodes(self) -> int: ...
     def set_opcode_range(self, min: int, max: int) -> None: ...
+    @property
+    def mutators(self) -> List[str]: ...
+    def set_mutators(self, names: List[str]) -> None: ...
+    @property
+    def mutation_rate(self) -> float: ...
+    def set_mutation_rate(self, rate: float) -> None: ...
+    @property
+    def unsafe_mutations(self) -> bool: ...
+    def set_unsafe_mutations(self, unsafe_mutations: bool) -> None: ...
+    @property
+    def allow_ext(self) -> bool: ...
+    def set_allow_ext(self, allow: bool) -> None: ...
+    @property
+    def allow_buffer(self) -> bool: ...
+    def set_allow_buffer(self, allow: bool) -> None: ...
+    @property
+    def allow_persistent_ids(self) -> bool: ...
+    def set_allow_persistent_ids(self, allow: bool) -> None: ...
     def reset(self) -> None: ...
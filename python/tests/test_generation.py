@@ -14,6 +14,10 @@
 #
 # SPDX-License-Identifier: Apache-2.0
 
+from pathlib import Path
+
+import pytest
+
 import pickle_fuzzer
 
 
@@ -60,3 +64,154 @@ def test_generate_from_bytes_respects_max_size():
 
     assert len(data) <= 32
     assert data[-1] == ord(".")
+
+
+def test_constructor_exposes_builder_options():
+    gen = pickle_fuzzer.Generator(
+        protocol=2,
+        seed=7,
+        min_opcodes=20,
+        max_opcodes=40,
+        mutators=["bitflip", "boundary"],
+        mutation_rate=0.5,
+        allow_ext=True,
+        allow_buffer=True,
+    )
+
+    assert gen.protocol == 2
+    assert gen.seed == 7
+    assert (gen.min_opcodes, gen.max_opcodes) == (20, 40)
+    assert gen.mutators == ["bitflip", "boundary"]
+    assert gen.mutation_rate == 0.5
+    assert not gen.unsafe_mutations
+    assert gen.allow_ext and gen.allow_buffer
+    assert not gen.allow_persistent_ids
+
+
+def test_setters_preserve_existing_configuration():
+    gen = pickle_fuzzer.Generator(protocol=3, seed=42, mutators=["all"])
+    mutators = gen.mutators
+    first = gen.generate()
+
+    gen.set_opcode_range(10, 30)
+    gen.set_mutation_rate(0.2)
+    gen.set_allow_persistent_ids(True)
+    gen.set_protocol(1)
+
+    assert gen.seed == 42
+    assert gen.mutators == mutators
+    assert (gen.min_opcodes, gen.max_opcodes) == (10, 30)
+    assert gen.allow_persistent_ids
+
+    gen.set_protocol(3)
+    gen.set_opcode_range(60, 300)
+    gen.set_mutation_rate(0.1)
+    gen.set_allow_persistent_ids(False)
+    assert gen.generate() == first
+
+
+def test_unsafe_mutators_require_unsafe_mode():
+    with pytest.raises(ValueError):
+        pickle_fuzzer.Generator(mutators=["memoindex"])
+
+    gen = pickle_fuzzer.Generator(mutators=["bitflip"])
+    with pytest.raises(ValueError):
+        gen.set_mutators(["typeconfusion"])
+    assert gen.mutators == ["bitflip"]
+
+    gen.set_unsafe_mutations(True)
+    gen.set_mutators(["typeconfusion"])
+    assert gen.mutators == ["typeconfusion"]
+    with pytest.raises(ValueError):
+        gen.set_unsafe_mutations(False)
+    assert gen.unsafe_mutations
+
+
+def test_invalid_options_raise_value_error():
+    with pytest.raises(ValueError):
+        pickle_fuzzer.Generator(mutators=["nope"])
+    with pytest.raises(ValueError):
+        pickle_fuzzer.Generator(mutation_rate=1.5)
+    with pytest.raises(ValueError):
+        pickle_fuzzer.Generator().set_protocol(6)
+
+
+def test_generate_batch_matches_per_sample_seeds():
+    gen = pickle_fuzzer.Generator(protocol=4, seed=100, mutators=["bitflip"])
+    batch = gen.generate_batch(8, jobs=2)
+
+    assert len(batch) == 8
+    for idx, data in enumerate(batch):
+        single = pickle_fuzzer.Generator(protocol=4, seed=100 + idx, mutators=["bitflip"])
+        assert data == single.generate()
+    assert gen.generate_batch(8) == batch
+
+
+def test_generate_batch_writes_files(tmp_path):
+    gen = pickle_fuzzer.Generator(protocol=2, seed=5)
+    paths = gen.generate_batch(3, dir=tmp_path / "corpus")
+
+    assert [Path(path).name for path in paths] == ["0.pkl", "1.pkl", "2.pkl"]
+    assert Path(paths[0]).read_bytes() == gen.generate_batch(1)[0]
+
+
+def test_mutate_returns_valid_pickles_within_budget():
+    corpus = pickle_fuzzer.Generator(protocol=4, seed=9).generate()
+    mutants = [pickle_fuzzer.mutate(corpus, 256, seed) for seed in range(8)]
+
+    for mutant in mutants:
+        assert len(mutant) <= 256
+        assert mutant[:2] == b"\x80\x04"
+        assert mutant[-1] == ord(".")
+    assert len(set(mutants)) > 1
+    assert pickle_fuzzer.mutate(corpus, 256, 3) == mutants[3]
+
+
+def test_disassemble_matches_pickletools():
+    import pickletools
+
+    data = pickle_fuzzer.Generator(protocol=4, seed=11).generate()
+    expected = [(op.name, arg, pos) for op, arg, pos in pickletools.genops(data)]
+    assert pickle_fuzzer.disassemble(data) == expected
+
+    assert pickle_fuzzer.disassemble(b"L123456789012345678901234567890L\n.")[0][1] == (
+        123456789012345678901234567890
+    )
+    with pytest.raises(ValueError):
+        pickle_fuzzer.disassemble(b"N")
+
+
+def test_validate_reports_the_first_problem():
+    assert pickle_fuzzer.validate(pickle_fuzzer.Generator(seed=1).generate()) == (True, "")
+
+    ok, reason = pickle_fuzzer.validate(b"N.N")
+    assert not ok
+    assert "trailing bytes after STOP" in reason
+
+    ok, reason = pickle_fuzzer.validate(b"g0\n.")
+    assert not ok
+    assert "has never been stored into" in reason
+
+
+def test_samples_stream_lazily_with_batch_seeds():
+    gen = pickle_fuzzer.Generator(protocol=4, seed=21)
+    samples = gen.samples(5)
+
+    assert len(samples) == 5
+    assert list(samples) == gen.generate_batch(5)
+    assert len(samples) == 0
+    assert list(samples) == []
+
+    stream = iter(gen)
+    first = [next(stream) for _ in range(3)]
+    assert first == gen.generate_batch(3)
+    with pytest.raises(TypeError):
+        len(stream)
+
+
+def test_samples_keep_the_configuration_they_started_with():
+    gen = pickle_fuzzer.Generator(protocol=2, seed=3)
+    stream = gen.samples(2)
+    gen.set_protocol(0)
+
+    assert all(data[:2] == b"\x80\x02" for data in stream)
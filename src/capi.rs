@@ -0,0 +1,768 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! C bindings for pickle-fuzzer generator.
+//!
+//! This module exports a small C ABI so C, C++, and Go harnesses can embed the
+//! generator without going through Python. The matching declarations live in
+//! `include/pickle_fuzzer.h`; keep the two in sync.
+//!
+//! [`PickleFuzzerConfig`] mirrors the `Generator` builder options and starts
+//! with its own size, so a caller built against an older header keeps working
+//! when later versions add fields. Output
+//! buffers are allocated by the library and must be released with
+//! [`pickle_fuzzer_free`]. Functions return [`PICKLE_FUZZER_OK`] or a negative
+//! error code, and [`pickle_fuzzer_last_error`] describes the most recent
+//! failure on the calling thread.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use crate::mutators::MutatorKind;
+use crate::{
+    CleanupPolicy, Dtype, Generator, NdarraySpec, SizeDistribution, Version,
+    GENERATOR_FORMAT_VERSION,
+};
+
+/// The call succeeded.
+pub const PICKLE_FUZZER_OK: i32 = 0;
+/// A required pointer argument was null.
+pub const PICKLE_FUZZER_ERR_NULL: i32 = -1;
+/// The configuration is invalid.
+pub const PICKLE_FUZZER_ERR_CONFIG: i32 = -2;
+/// Generation failed, e.g. because the size or opcode budget is too small.
+pub const PICKLE_FUZZER_ERR_GENERATE: i32 = -3;
+/// The generator panicked; this is a bug.
+pub const PICKLE_FUZZER_ERR_PANIC: i32 = -4;
+
+/// Mutator bits for [`PickleFuzzerConfig::mutators`], in header order.
+///
+/// Bits are part of the ABI: new mutators get new bits, existing bits never move.
+const MUTATOR_BITS: [MutatorKind; 16] = [
+    MutatorKind::Bitflip,
+    MutatorKind::Boundary,
+    MutatorKind::Offbyone,
+    MutatorKind::Stringlen,
+    MutatorKind::Character,
+    MutatorKind::Memoindex,
+    MutatorKind::Typeconfusion,
+    MutatorKind::Brokenquoting,
+    MutatorKind::Textnumber,
+    MutatorKind::Dictionary,
+    MutatorKind::Havoc,
+    MutatorKind::Encodingconfusion,
+    MutatorKind::Memoorder,
+    MutatorKind::Lengthboundary,
+    MutatorKind::Homoglyph,
+    MutatorKind::Whitespace,
+];
+
+/// Generator configuration passed across the C ABI.
+///
+/// Set `struct_size` to `sizeof(PickleFuzzerConfig)`, initialize the rest with
+/// [`pickle_fuzzer_config_default`], and then override fields. The library
+/// reads and writes only the first `struct_size` bytes: fields a caller's
+/// header predates keep their defaults, and fields the library predates are
+/// ignored. New fields are only ever appended.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PickleFuzzerConfig {
+    /// Size of the caller's `PickleFuzzerConfig` in bytes.
+    pub struct_size: usize,
+    /// Pickle protocol version (0-5).
+    pub protocol: u32,
+    /// Whether `seed` is used; without it generation draws OS entropy.
+    pub has_seed: bool,
+    /// Seed for reproducible output.
+    pub seed: u64,
+    /// Minimum number of opcodes.
+    pub min_opcodes: usize,
+    /// Maximum number of opcodes.
+    pub max_opcodes: usize,
+    /// Maximum pickle size in bytes (0 for no limit).
+    pub max_size: usize,
+    /// Bitmask of `PICKLE_FUZZER_MUTATOR_*` values.
+    pub mutators: u64,
+    /// Mutation probability (0.0-1.0).
+    pub mutation_rate: f64,
+    /// Allow mutators that produce invalid pickles.
+    pub unsafe_mutations: bool,
+    /// Allow EXT1/EXT2/EXT4.
+    pub allow_ext: bool,
+    /// Allow NEXT_BUFFER/READONLY_BUFFER.
+    pub allow_buffer: bool,
+    /// Allow PERSID/BINPERSID.
+    pub allow_persistent_ids: bool,
+    /// Maximum number of items on the pickle stack (0 for no limit).
+    pub max_stack_depth: usize,
+    /// 0 wraps leftover stack items into tuples, 1 pops them (keep-root).
+    pub cleanup_policy: u32,
+    /// Bias integer opcodes toward their encoding boundaries.
+    pub integer_boundaries: bool,
+    /// Verify stack and argument invariants at every emission.
+    pub strict_checks: bool,
+    /// Sometimes build STACK_GLOBAL's module and name indirectly.
+    pub indirect_stack_globals: bool,
+    /// Sometimes emit a common multi-opcode idiom as one step.
+    pub interesting_patterns: bool,
+    /// Pickle a random object exactly like CPython's `pickle.dumps`.
+    pub canonical: bool,
+    /// Pickle a random object with a random valid encoding for every value.
+    pub diverse_encodings: bool,
+    /// Container size distribution: 0 none, 1 constant (`container_size_max`),
+    /// 2 uniform (`container_size_min` to `container_size_max`), 3 zipf
+    /// (`container_size_max` and `container_size_exponent`).
+    pub container_sizes: u32,
+    /// Smallest size of a uniform container size distribution.
+    pub container_size_min: usize,
+    /// Largest (or constant) size of a container size distribution.
+    pub container_size_max: usize,
+    /// Exponent of a zipf container size distribution.
+    pub container_size_exponent: f64,
+    /// Fill containers of more than 1000 items in batches of more than 1000.
+    pub oversized_batches: bool,
+    /// Dtypes of the numpy arrays to sometimes emit, one bit per
+    /// [`Dtype::ALL`] entry; 0 emits none.
+    pub ndarray_dtypes: u32,
+    /// Most dimensions of an emitted numpy array.
+    pub ndarray_max_dims: usize,
+    /// Longest dimension of an emitted numpy array.
+    pub ndarray_max_len: usize,
+    /// Sometimes emit a torch tensor rebuilt from a persistent id storage.
+    pub torch_tensors: bool,
+    /// Sometimes emit a scikit-learn estimator the way joblib pickles it.
+    pub sklearn_estimators: bool,
+}
+
+impl Default for PickleFuzzerConfig {
+    fn default() -> Self {
+        let defaults = Generator::default();
+        let ndarray_defaults = NdarraySpec::default();
+        Self {
+            struct_size: std::mem::size_of::<Self>(),
+            protocol: Version::default() as u32,
+            has_seed: false,
+            seed: 0,
+            min_opcodes: defaults.min_opcodes,
+            max_opcodes: defaults.max_opcodes,
+            max_size: 0,
+            mutators: 0,
+            mutation_rate: defaults.mutation_rate,
+            unsafe_mutations: false,
+            allow_ext: false,
+            allow_buffer: false,
+            allow_persistent_ids: false,
+            max_stack_depth: 0,
+            cleanup_policy: 0,
+            integer_boundaries: false,
+            strict_checks: false,
+            indirect_stack_globals: false,
+            interesting_patterns: false,
+            canonical: false,
+            diverse_encodings: false,
+            container_sizes: 0,
+            container_size_min: 0,
+            container_size_max: 0,
+            container_size_exponent: 1.0,
+            oversized_batches: false,
+            ndarray_dtypes: 0,
+            ndarray_max_dims: ndarray_defaults.max_dims,
+            ndarray_max_len: ndarray_defaults.max_len,
+            torch_tensors: false,
+            sklearn_estimators: false,
+        }
+    }
+}
+
+impl PickleFuzzerConfig {
+    /// The number of bytes of `config` the library may read and write: its
+    /// `struct_size`, capped at the library's own size.
+    ///
+    /// # Safety
+    ///
+    /// `config` must be non-null and its `struct_size` readable.
+    unsafe fn shared_size(config: *const Self) -> Result<usize, String> {
+        let struct_size = config.cast::<usize>().read_unaligned();
+        if struct_size < std::mem::size_of::<usize>() {
+            return Err(format!(
+                "struct_size must be set to sizeof(PickleFuzzerConfig), got {struct_size}"
+            ));
+        }
+        Ok(struct_size.min(std::mem::size_of::<Self>()))
+    }
+
+    /// Reads the caller's configuration, taking fields past its
+    /// `struct_size` from the defaults.
+    ///
+    /// # Safety
+    ///
+    /// `config` must be non-null and point to `struct_size` readable bytes
+    /// that hold valid field values.
+    unsafe fn read(config: *const Self) -> Result<Self, String> {
+        let len = Self::shared_size(config)?;
+        let mut read = Self::default();
+        std::ptr::copy_nonoverlapping(
+            config.cast::<u8>(),
+            std::ptr::from_mut(&mut read).cast::<u8>(),
+            len,
+        );
+        read.struct_size = std::mem::size_of::<Self>();
+        Ok(read)
+    }
+
+    /// Builds the generator this configuration describes.
+    fn build(&self) -> Result<Generator, String> {
+        let version = Version::try_from(self.protocol as usize)
+            .map_err(|_| format!("protocol must be 0-5, got {}", self.protocol))?;
+
+        let known_bits = (1u64 << MUTATOR_BITS.len()) - 1;
+        if self.mutators & !known_bits != 0 {
+            return Err(format!(
+                "unknown mutator bits {:#x}",
+                self.mutators & !known_bits
+            ));
+        }
+        let kinds: Vec<MutatorKind> = MUTATOR_BITS
+            .iter()
+            .enumerate()
+            .filter(|(bit, _)| self.mutators & (1 << bit) != 0)
+            .map(|(_, &kind)| kind)
+            .collect();
+        if let Some(kind) = kinds
+            .iter()
+            .find(|kind| kind.requires_unsafe_mutations() && !self.unsafe_mutations)
+        {
+            return Err(format!("mutator {kind:?} requires unsafe_mutations"));
+        }
+        if self.torch_tensors && !self.allow_persistent_ids {
+            return Err("torch_tensors requires allow_persistent_ids".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.mutation_rate) {
+            return Err(format!(
+                "mutation_rate must be between 0.0 and 1.0, got {}",
+                self.mutation_rate
+            ));
+        }
+        let cleanup_policy = match self.cleanup_policy {
+            0 => CleanupPolicy::Tuple,
+            1 => CleanupPolicy::KeepRoot,
+            other => return Err(format!("unknown cleanup_policy {other}")),
+        };
+        let (min, max) = (self.container_size_min, self.container_size_max);
+        let container_sizes = match self.container_sizes {
+            0 => None,
+            1 => Some(format!("constant:{max}")),
+            2 => Some(format!("uniform:{min}-{max}")),
+            3 => Some(format!("zipf:{max}:{}", self.container_size_exponent)),
+            other => return Err(format!("unknown container_sizes {other}")),
+        }
+        .map(|sizes| {
+            sizes
+                .parse::<SizeDistribution>()
+                .map_err(|e| format!("invalid container sizes: {e}"))
+        })
+        .transpose()?;
+
+        let known_dtypes = (1u32 << Dtype::ALL.len()) - 1;
+        if self.ndarray_dtypes & !known_dtypes != 0 {
+            return Err(format!(
+                "unknown ndarray dtype bits {:#x}",
+                self.ndarray_dtypes & !known_dtypes
+            ));
+        }
+        let ndarrays = if self.ndarray_dtypes == 0 {
+            None
+        } else {
+            let codes: Vec<&str> = Dtype::ALL
+                .iter()
+                .enumerate()
+                .filter(|(bit, _)| self.ndarray_dtypes & (1 << bit) != 0)
+                .map(|(_, dtype)| dtype.code())
+                .collect();
+            let spec = format!(
+                "{}:{}:{}",
+                codes.join(","),
+                self.ndarray_max_dims,
+                self.ndarray_max_len
+            );
+            Some(
+                spec.parse::<NdarraySpec>()
+                    .map_err(|e| format!("invalid ndarray shape: {e}"))?,
+            )
+        };
+
+        let mut generator = Generator::new(version)
+            .with_opcode_range(self.min_opcodes, self.max_opcodes)
+            .with_ext_opcodes(self.allow_ext)
+            .with_buffer_opcodes(self.allow_buffer)
+            .with_persistent_id_opcodes(self.allow_persistent_ids)
+            .with_cleanup_policy(cleanup_policy)
+            .with_integer_boundaries(self.integer_boundaries)
+            .with_strict_checks(self.strict_checks)
+            .with_indirect_stack_globals(self.indirect_stack_globals)
+            .with_interesting_patterns(self.interesting_patterns)
+            .with_canonical(self.canonical)
+            .with_diverse_encodings(self.diverse_encodings)
+            .with_oversized_batches(self.oversized_batches)
+            .with_torch_tensors(self.torch_tensors)
+            .with_sklearn_estimators(self.sklearn_estimators);
+        if self.has_seed {
+            generator = generator.with_seed(self.seed);
+        }
+        if self.max_size != 0 {
+            generator = generator.with_buffer_size(self.max_size);
+        }
+        if self.max_stack_depth != 0 {
+            generator = generator.with_max_stack_depth(self.max_stack_depth);
+        }
+        if let Some(sizes) = container_sizes {
+            generator = generator.with_container_sizes(sizes);
+        }
+        if let Some(spec) = ndarrays {
+            generator = generator.with_ndarrays(spec);
+        }
+        if !kinds.is_empty() {
+            generator = generator
+                .with_mutators(
+                    kinds
+                        .iter()
+                        .map(|kind| kind.create(self.unsafe_mutations))
+                        .collect(),
+                )
+                .with_mutation_rate(self.mutation_rate)
+                .with_unsafe_mutations(self.unsafe_mutations);
+        }
+        Ok(generator)
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+fn set_last_error(message: &str) {
+    // interior NULs would truncate the message on the C side anyway
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+}
+
+/// Runs `generate` for a C entry point: validates pointers, catches panics,
+/// records errors, and hands the pickle to the caller.
+///
+/// # Safety
+///
+/// `config` must be null or point to a valid [`PickleFuzzerConfig`] of
+/// `struct_size` bytes, and `out_buf`/`out_len` must be null or valid for
+/// writes.
+unsafe fn generate_with(
+    config: *const PickleFuzzerConfig,
+    out_buf: *mut *mut u8,
+    out_len: *mut usize,
+    generate: impl FnOnce(&mut Generator) -> color_eyre::Result<Vec<u8>>,
+) -> i32 {
+    if config.is_null() || out_buf.is_null() || out_len.is_null() {
+        set_last_error("config, out_buf, and out_len must not be null");
+        return PICKLE_FUZZER_ERR_NULL;
+    }
+    let config = match PickleFuzzerConfig::read(config) {
+        Ok(config) => config,
+        Err(e) => {
+            set_last_error(&e);
+            return PICKLE_FUZZER_ERR_CONFIG;
+        }
+    };
+
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        let mut generator = config.build().map_err(|e| (PICKLE_FUZZER_ERR_CONFIG, e))?;
+        generate(&mut generator).map_err(|e| (PICKLE_FUZZER_ERR_GENERATE, e.to_string()))
+    }));
+
+    match result {
+        Ok(Ok(pickle)) => {
+            let pickle = pickle.into_boxed_slice();
+            *out_len = pickle.len();
+            *out_buf = Box::into_raw(pickle).cast::<u8>();
+            PICKLE_FUZZER_OK
+        }
+        Ok(Err((code, message))) => {
+            set_last_error(&message);
+            code
+        }
+        Err(_) => {
+            set_last_error("pickle-fuzzer panicked during generation");
+            PICKLE_FUZZER_ERR_PANIC
+        }
+    }
+}
+
+/// Fills the first `config.struct_size` bytes of `config` with the default
+/// configuration, leaving `struct_size` as the caller set it.
+///
+/// # Safety
+///
+/// `config` must be null or point to `struct_size` bytes valid for reads and
+/// writes.
+#[no_mangle]
+pub unsafe extern "C" fn pickle_fuzzer_config_default(config: *mut PickleFuzzerConfig) -> i32 {
+    if config.is_null() {
+        set_last_error("config must not be null");
+        return PICKLE_FUZZER_ERR_NULL;
+    }
+    let len = match PickleFuzzerConfig::shared_size(config) {
+        Ok(len) => len,
+        Err(e) => {
+            set_last_error(&e);
+            return PICKLE_FUZZER_ERR_CONFIG;
+        }
+    };
+    let defaults = PickleFuzzerConfig::default();
+    let size = std::mem::size_of::<usize>();
+    std::ptr::copy_nonoverlapping(
+        std::ptr::from_ref(&defaults).cast::<u8>().add(size),
+        config.cast::<u8>().add(size),
+        len - size,
+    );
+    PICKLE_FUZZER_OK
+}
+
+/// Generates one pickle from the PRNG (seeded by `config.seed` if `has_seed`).
+///
+/// On success `*out_buf` and `*out_len` receive the pickle, which the caller
+/// releases with [`pickle_fuzzer_free`].
+///
+/// # Safety
+///
+/// `config` must point to a valid [`PickleFuzzerConfig`], and `out_buf` and
+/// `out_len` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn pickle_fuzzer_generate(
+    config: *const PickleFuzzerConfig,
+    out_buf: *mut *mut u8,
+    out_len: *mut usize,
+) -> i32 {
+    generate_with(config, out_buf, out_len, |generator| generator.generate())
+}
+
+/// Generates one pickle whose decisions are drawn from `data`, like
+/// `Generator::generate_from_arbitrary`, for coverage-guided fuzz targets.
+///
+/// # Safety
+///
+/// As for [`pickle_fuzzer_generate`]; additionally `data` must point to
+/// `data_len` readable bytes (it may be null when `data_len` is 0).
+#[no_mangle]
+pub unsafe extern "C" fn pickle_fuzzer_generate_from_bytes(
+    config: *const PickleFuzzerConfig,
+    data: *const u8,
+    data_len: usize,
+    out_buf: *mut *mut u8,
+    out_len: *mut usize,
+) -> i32 {
+    let data = if data_len == 0 {
+        &[][..]
+    } else if data.is_null() {
+        set_last_error("data must not be null when data_len is not 0");
+        return PICKLE_FUZZER_ERR_NULL;
+    } else {
+        std::slice::from_raw_parts(data, data_len)
+    };
+    generate_with(config, out_buf, out_len, |generator| {
+        generator.generate_from_arbitrary(data)
+    })
+}
+
+/// Releases a pickle returned by one of the generate functions.
+///
+/// # Safety
+///
+/// `buf` and `len` must come from the same successful generate call, and each
+/// buffer may only be freed once. A null `buf` is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn pickle_fuzzer_free(buf: *mut u8, len: usize) {
+    if !buf.is_null() {
+        drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(buf, len)));
+    }
+}
+
+/// Describes the most recent error on the calling thread.
+///
+/// The string is owned by the library and stays valid until the next failing
+/// call on the same thread. It is empty if no call has failed yet.
+#[no_mangle]
+pub extern "C" fn pickle_fuzzer_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ptr())
+}
+
+/// Returns `GENERATOR_FORMAT_VERSION`.
+#[no_mangle]
+pub extern "C" fn pickle_fuzzer_format_version() -> u32 {
+    GENERATOR_FORMAT_VERSION
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    fn last_error() -> String {
+        unsafe { CStr::from_ptr(pickle_fuzzer_last_error()) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn mutator_mask(kinds: &[MutatorKind]) -> u64 {
+        kinds
+            .iter()
+            .map(|kind| 1 << MUTATOR_BITS.iter().position(|bit| bit == kind).unwrap())
+            .sum()
+    }
+
+    fn generate(config: &PickleFuzzerConfig) -> Result<Vec<u8>, (i32, String)> {
+        let mut buf = std::ptr::null_mut();
+        let mut len = 0;
+        let code = unsafe { pickle_fuzzer_generate(config, &mut buf, &mut len) };
+        if code != PICKLE_FUZZER_OK {
+            return Err((code, last_error()));
+        }
+        let pickle = unsafe { std::slice::from_raw_parts(buf, len) }.to_vec();
+        unsafe { pickle_fuzzer_free(buf, len) };
+        Ok(pickle)
+    }
+
+    #[test]
+    fn generate_matches_the_builder() {
+        let mut config = PickleFuzzerConfig::default();
+        assert_eq!(
+            unsafe { pickle_fuzzer_config_default(&mut config) },
+            PICKLE_FUZZER_OK
+        );
+        config.protocol = 4;
+        config.has_seed = true;
+        config.seed = 11;
+        config.max_size = 512;
+        config.mutators = mutator_mask(&MutatorKind::all_mutators(false));
+        config.cleanup_policy = 1;
+
+        let expected = Generator::new(Version::V4)
+            .with_seed(11)
+            .with_buffer_size(512)
+            .with_mutators(
+                MutatorKind::all_mutators(false)
+                    .iter()
+                    .map(|kind| kind.create(false))
+                    .collect(),
+            )
+            .with_mutation_rate(0.1)
+            .with_cleanup_policy(CleanupPolicy::KeepRoot)
+            .generate()
+            .unwrap();
+        assert_eq!(generate(&config).unwrap(), expected);
+    }
+
+    #[test]
+    fn config_layout_matches_the_header() {
+        // sizeof/offsetof from include/pickle_fuzzer.h on 64-bit targets
+        #[cfg(target_pointer_width = "64")]
+        {
+            assert_eq!(std::mem::offset_of!(PickleFuzzerConfig, struct_size), 0);
+            assert_eq!(std::mem::size_of::<PickleFuzzerConfig>(), 152);
+            assert_eq!(std::mem::offset_of!(PickleFuzzerConfig, cleanup_policy), 80);
+            assert_eq!(std::mem::offset_of!(PickleFuzzerConfig, strict_checks), 85);
+            assert_eq!(
+                std::mem::offset_of!(PickleFuzzerConfig, indirect_stack_globals),
+                86
+            );
+            assert_eq!(
+                std::mem::offset_of!(PickleFuzzerConfig, interesting_patterns),
+                87
+            );
+            assert_eq!(std::mem::offset_of!(PickleFuzzerConfig, canonical), 88);
+            assert_eq!(
+                std::mem::offset_of!(PickleFuzzerConfig, diverse_encodings),
+                89
+            );
+            assert_eq!(
+                std::mem::offset_of!(PickleFuzzerConfig, container_sizes),
+                92
+            );
+            assert_eq!(
+                std::mem::offset_of!(PickleFuzzerConfig, container_size_exponent),
+                112
+            );
+            assert_eq!(
+                std::mem::offset_of!(PickleFuzzerConfig, oversized_batches),
+                120
+            );
+            assert_eq!(
+                std::mem::offset_of!(PickleFuzzerConfig, ndarray_dtypes),
+                124
+            );
+            assert_eq!(
+                std::mem::offset_of!(PickleFuzzerConfig, ndarray_max_len),
+                136
+            );
+            assert_eq!(std::mem::offset_of!(PickleFuzzerConfig, torch_tensors), 144);
+            assert_eq!(
+                std::mem::offset_of!(PickleFuzzerConfig, sklearn_estimators),
+                145
+            );
+        }
+    }
+
+    #[test]
+    fn only_struct_size_bytes_are_touched() {
+        // a caller whose header predates torch_tensors and sklearn_estimators
+        #[repr(C, align(8))]
+        struct Caller([u8; 160]);
+        let old_size = std::mem::offset_of!(PickleFuzzerConfig, torch_tensors);
+        let mut caller = Caller([0xaa; 160]);
+        caller.0[..8].copy_from_slice(&old_size.to_ne_bytes());
+        let config = caller.0.as_mut_ptr().cast::<PickleFuzzerConfig>();
+
+        assert_eq!(
+            unsafe { pickle_fuzzer_config_default(config) },
+            PICKLE_FUZZER_OK
+        );
+        assert!(caller.0[old_size..].iter().all(|&b| b == 0xaa));
+        unsafe {
+            std::ptr::addr_of_mut!((*config).protocol).write(2);
+            std::ptr::addr_of_mut!((*config).has_seed).write(true);
+            std::ptr::addr_of_mut!((*config).seed).write(5);
+        }
+
+        let (mut buf, mut len) = (std::ptr::null_mut(), 0);
+        let code = unsafe { pickle_fuzzer_generate(config, &mut buf, &mut len) };
+        assert_eq!(code, PICKLE_FUZZER_OK, "{}", last_error());
+        let pickle = unsafe { std::slice::from_raw_parts(buf, len) }.to_vec();
+        unsafe { pickle_fuzzer_free(buf, len) };
+        let expected = Generator::new(Version::V2).with_seed(5).generate().unwrap();
+        assert_eq!(pickle, expected);
+
+        let mut unset = PickleFuzzerConfig {
+            struct_size: 0,
+            ..Default::default()
+        };
+        assert_eq!(
+            unsafe { pickle_fuzzer_config_default(&mut unset) },
+            PICKLE_FUZZER_ERR_CONFIG
+        );
+        assert!(last_error().contains("struct_size"));
+        assert_eq!(generate(&unset).unwrap_err().0, PICKLE_FUZZER_ERR_CONFIG);
+    }
+
+    #[test]
+    fn every_mutator_has_a_bit() {
+        use clap::ValueEnum;
+
+        let kinds: Vec<MutatorKind> = MutatorKind::value_variants()
+            .iter()
+            .copied()
+            .filter(|kind| *kind != MutatorKind::All)
+            .collect();
+        assert_eq!(mutator_mask(&kinds), (1 << MUTATOR_BITS.len()) - 1);
+    }
+
+    #[test]
+    fn generate_from_bytes_matches_generate_from_arbitrary() {
+        let config = PickleFuzzerConfig {
+            protocol: 2,
+            ..Default::default()
+        };
+        let data: Vec<u8> = (0..200u8).map(|b| b.wrapping_mul(13)).collect();
+        let (mut buf, mut len) = (std::ptr::null_mut(), 0);
+        let code = unsafe {
+            pickle_fuzzer_generate_from_bytes(
+                &config,
+                data.as_ptr(),
+                data.len(),
+                &mut buf,
+                &mut len,
+            )
+        };
+        assert_eq!(code, PICKLE_FUZZER_OK);
+        let pickle = unsafe { std::slice::from_raw_parts(buf, len) }.to_vec();
+        unsafe { pickle_fuzzer_free(buf, len) };
+
+        let expected = Generator::new(Version::V2)
+            .generate_from_arbitrary(&data)
+            .unwrap();
+        assert_eq!(pickle, expected);
+    }
+
+    #[test]
+    fn errors_are_reported_with_codes_and_messages() {
+        let invalid = [
+            PickleFuzzerConfig {
+                protocol: 6,
+                ..Default::default()
+            },
+            PickleFuzzerConfig {
+                mutators: 1 << 5, // memoindex without unsafe_mutations
+                ..Default::default()
+            },
+            PickleFuzzerConfig {
+                mutators: 1 << 40,
+                ..Default::default()
+            },
+            PickleFuzzerConfig {
+                cleanup_policy: 2,
+                ..Default::default()
+            },
+            PickleFuzzerConfig {
+                container_sizes: 4,
+                ..Default::default()
+            },
+            PickleFuzzerConfig {
+                container_sizes: 2,
+                container_size_min: 9,
+                container_size_max: 2,
+                ..Default::default()
+            },
+            PickleFuzzerConfig {
+                torch_tensors: true,
+                ..Default::default()
+            },
+            PickleFuzzerConfig {
+                ndarray_dtypes: 1 << 14,
+                ..Default::default()
+            },
+            PickleFuzzerConfig {
+                ndarray_dtypes: 1,
+                ndarray_max_dims: 33,
+                ..Default::default()
+            },
+        ];
+        for config in invalid {
+            let (code, message) = generate(&config).unwrap_err();
+            assert_eq!(code, PICKLE_FUZZER_ERR_CONFIG);
+            assert!(!message.is_empty());
+        }
+
+        let too_small = PickleFuzzerConfig {
+            max_size: 2,
+            ..Default::default()
+        };
+        let (code, message) = generate(&too_small).unwrap_err();
+        assert_eq!(code, PICKLE_FUZZER_ERR_GENERATE);
+        assert!(message.contains("buffer size"), "{message}");
+
+        let (mut buf, mut len) = (std::ptr::null_mut(), 0);
+        let code = unsafe { pickle_fuzzer_generate(std::ptr::null(), &mut buf, &mut len) };
+        assert_eq!(code, PICKLE_FUZZER_ERR_NULL);
+        assert_eq!(pickle_fuzzer_format_version(), GENERATOR_FORMAT_VERSION);
+    }
+}
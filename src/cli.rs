@@ -16,22 +16,125 @@
 
 use std::{ffi::OsString, path::PathBuf};
 
-use clap::{Parser, ValueEnum};
+use clap::builder::{PossibleValue, PossibleValuesParser, TypedValueParser};
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{
+    ArgAction, ArgGroup, ArgMatches, Args, CommandFactory, FromArgMatches, Parser, Subcommand,
+    ValueEnum,
+};
+use serde_json::{Map, Value};
+use toml_edit::DocumentMut;
+
+use crate::generator::{
+    CleanupPolicy, MutationPolicy, MutationTarget, NdarraySpec, ProtoHeader, SizeDistribution,
+    MAX_STRESS_OPS,
+};
+use crate::mutators::{registered_mutators, CharacterSet, MutatorChoice, MutatorKind};
+use crate::opcodes::OpcodeInfo;
+use crate::output::{Compression, NameTemplate};
+use crate::protocol::{ProtocolMix, Version};
 
 /// Parse and validate a pickle protocol version string.
 ///
-/// Accepts version numbers 0-5 (inclusive).
-fn parse_version(s: &str) -> Result<usize, String> {
-    let v = s
-        .parse::<usize>()
-        .map_err(|_| format!("invalid version: {}", s))?;
-    if v <= 5 {
-        Ok(v)
-    } else {
-        Err(format!("version must be 0-5, got {}", v))
+/// Accepts version numbers 0-5 (inclusive), optionally prefixed with `v`.
+fn parse_version(s: &str) -> Result<Version, String> {
+    s.parse::<Version>().map_err(|e| e.to_string())
+}
+
+/// Parse a weighted protocol mix such as `0:10,2:20,4:40,5:30`.
+fn parse_protocol_mix(s: &str) -> Result<ProtocolMix, String> {
+    s.parse::<ProtocolMix>().map_err(|e| e.to_string())
+}
+
+/// Parse a container size distribution such as `zipf:1000` or `uniform:0-8`.
+fn parse_size_distribution(s: &str) -> Result<SizeDistribution, String> {
+    s.parse::<SizeDistribution>().map_err(|e| e.to_string())
+}
+
+/// Parse a numpy array spec such as `f4,f8` or `all:2:64`.
+fn parse_ndarray_spec(s: &str) -> Result<NdarraySpec, String> {
+    s.parse::<NdarraySpec>().map_err(|e| e.to_string())
+}
+
+/// Parse a sibling count for `--variants`, which must be at least 1.
+fn parse_variant_count(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(0) => Err("variants must be at least 1".to_string()),
+        Ok(count) => Ok(count),
+        Err(_) => Err(format!("invalid variant count: {}", s)),
     }
 }
 
+/// Parse a fraction for `--min-validity`, which must be between 0 and 1.
+fn parse_rate(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(rate) if (0.0..=1.0).contains(&rate) => Ok(rate),
+        Ok(_) => Err("rate must be between 0 and 1".to_string()),
+        Err(_) => Err(format!("invalid rate: {}", s)),
+    }
+}
+
+/// Parse a batch sample name template such as `proto{proto}_{idx}.pkl`.
+fn parse_name_template(s: &str) -> Result<NameTemplate, String> {
+    s.parse::<NameTemplate>().map_err(|e| e.to_string())
+}
+
+/// Parse a shard directory count for `--shard-dirs`, which must be at least 1.
+fn parse_shard_count(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(0) => Err("shard-dirs must be at least 1".to_string()),
+        Ok(count) => Ok(count),
+        Err(_) => Err(format!("invalid shard count: {}", s)),
+    }
+}
+
+/// Parse an extension length for `--stringlen-max-extend`, which must be at
+/// least 1.
+fn parse_max_extend(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(0) => Err("stringlen-max-extend must be at least 1".to_string()),
+        Ok(len) => Ok(len),
+        Err(_) => Err(format!("invalid extension length: {}", s)),
+    }
+}
+
+fn parse_stress_intensity(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(ops @ 1..=MAX_STRESS_OPS) => Ok(ops),
+        Ok(_) => Err(format!("mark-stress must be 1-{MAX_STRESS_OPS}")),
+        Err(_) => Err(format!("invalid mark stress intensity: {}", s)),
+    }
+}
+
+/// accept the builtin mutator names plus any registered with
+/// [`register_mutator`](crate::register_mutator) before parsing.
+fn mutator_parser() -> impl TypedValueParser<Value = MutatorChoice> {
+    let builtin = MutatorKind::value_variants()
+        .iter()
+        .filter_map(ValueEnum::to_possible_value);
+    let registered = registered_mutators(true)
+        .into_iter()
+        .map(PossibleValue::new);
+    PossibleValuesParser::new(builtin.chain(registered)).map(|name| {
+        name.parse::<MutatorChoice>()
+            .expect("possible values are known mutators")
+    })
+}
+
+/// Parse an opcode name such as `REDUCE` or `stack_global`, as `pickletools`
+/// spells it.
+fn parse_opcode_name(s: &str) -> Result<&'static str, String> {
+    OpcodeInfo::by_name(&s.to_ascii_uppercase())
+        .map(|info| info.name)
+        .ok_or_else(|| format!("unknown opcode: {}", s))
+}
+
+/// Parse a mutation policy: `first`, `all`, or `random:N`.
+fn parse_mutation_policy(s: &str) -> Result<MutationPolicy, String> {
+    s.parse::<MutationPolicy>().map_err(|e| e.to_string())
+}
+
 fn normalize_mutator_args<I>(args: I) -> Vec<OsString>
 where
     I: IntoIterator<Item = OsString>,
@@ -54,7 +157,7 @@ where
                 if value == "--" || value.starts_with('-') {
                     break;
                 }
-                if crate::mutators::MutatorKind::from_str(value, true).is_err() {
+                if value.parse::<MutatorChoice>().is_err() {
                     break;
                 }
 
@@ -76,20 +179,218 @@ where
     normalized
 }
 
+/// Arguments a configuration file can't set.
+const NON_CONFIG_ARGS: [&str; 3] = ["config", "help", "version"];
+
+/// Put the settings of the `--config` file in `args`, if there is one, in
+/// front of the command-line arguments.
+///
+/// Every key is the long name of a flag (`protocol-mix`, or `protocol_mix`)
+/// or `file` for the output path. Flags given on the command line win, so
+/// their keys are left out.
+fn apply_config_file(args: Vec<OsString>) -> Result<Vec<OsString>, String> {
+    // a lenient first pass finds --config and the flags given next to it;
+    // missing required arguments may come from the file
+    let Ok(matches) = Cli::command()
+        .ignore_errors(true)
+        .try_get_matches_from(&args)
+    else {
+        return Ok(args);
+    };
+    // the file's settings go right after `generate`, or the program name
+    let (matches, insert_at) = match matches.subcommand() {
+        None => (&matches, args.len().min(1)),
+        Some(("generate", generate)) => {
+            let subcommand = args.iter().position(|arg| arg == "generate");
+            (generate, subcommand.map_or(1, |idx| idx + 1))
+        }
+        Some(_) => return Ok(args),
+    };
+    let Some(path) = matches.get_one::<PathBuf>("config") else {
+        return Ok(args);
+    };
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+    let document = text
+        .parse::<DocumentMut>()
+        .map_err(|e| format!("invalid config file {}: {}", path.display(), e))?;
+
+    let command = Cli::command();
+    let mut from_file = Vec::new();
+    for (key, item) in document.iter() {
+        let name = key.replace('_', "-");
+        let arg = command
+            .get_arguments()
+            .filter(|arg| !NON_CONFIG_ARGS.contains(&arg.get_id().as_str()))
+            .find(|arg| match arg.get_long() {
+                Some(long) => long == name,
+                None => arg.get_id() == name.as_str(),
+            })
+            .ok_or_else(|| format!("unknown config key: {}", key))?;
+        if matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine) {
+            continue;
+        }
+
+        let flag = arg
+            .get_long()
+            .map(|long| OsString::from(format!("--{}", long)));
+        let value = item
+            .as_value()
+            .ok_or_else(|| format!("config key {} must be a value, not a table", key))?;
+        match arg.get_action() {
+            ArgAction::SetTrue => {
+                let set = value
+                    .as_bool()
+                    .ok_or_else(|| format!("config key {} must be true or false", key))?;
+                if set {
+                    from_file.extend(flag);
+                }
+            }
+            action => {
+                let values = match value.as_array() {
+                    Some(array) if matches!(action, ArgAction::Append) => {
+                        array.iter().map(config_scalar).collect()
+                    }
+                    Some(_) => return Err(format!("config key {} takes one value", key)),
+                    None => vec![config_scalar(value)],
+                };
+                for value in values {
+                    let value =
+                        value.ok_or_else(|| format!("config key {} has an invalid value", key))?;
+                    from_file.extend(flag.clone());
+                    from_file.push(OsString::from(value));
+                }
+            }
+        }
+    }
+
+    let mut args = args;
+    args.splice(insert_at..insert_at, from_file);
+    Ok(args)
+}
+
+/// The command-line text of a TOML string, integer, or float.
+fn config_scalar(value: &toml_edit::Value) -> Option<String> {
+    match value {
+        toml_edit::Value::String(s) => Some(s.value().clone()),
+        toml_edit::Value::Integer(i) => Some(i.value().to_string()),
+        toml_edit::Value::Float(f) => Some(f.value().to_string()),
+        _ => None,
+    }
+}
+
+/// Every setting of a parsed run, given or defaulted, keyed like a config file.
+fn effective_config(matches: &ArgMatches) -> Map<String, Value> {
+    let mut config = Map::new();
+    for arg in Cli::command().get_arguments() {
+        let id = arg.get_id().as_str();
+        if NON_CONFIG_ARGS.contains(&id) {
+            continue;
+        }
+        let key = arg.get_long().unwrap_or(id).to_string();
+        let value = match arg.get_action() {
+            ArgAction::SetTrue => Value::Bool(matches.get_flag(id)),
+            action => {
+                let Some(raw) = matches.get_raw(id) else {
+                    continue;
+                };
+                let mut values = raw.map(|raw| config_json(&raw.to_string_lossy()));
+                if matches!(action, ArgAction::Append) {
+                    Value::Array(values.collect())
+                } else {
+                    values.next().unwrap_or(Value::Null)
+                }
+            }
+        };
+        config.insert(key, value);
+    }
+    config
+}
+
+/// A command-line value as JSON: numbers as numbers, the rest as strings.
+fn config_json(raw: &str) -> Value {
+    if let Ok(int) = raw.parse::<i64>() {
+        return Value::from(int);
+    }
+    raw.parse::<f64>()
+        .ok()
+        .and_then(serde_json::Number::from_f64)
+        .map_or_else(|| Value::from(raw), Value::Number)
+}
+
+/// How log lines are written to stderr.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// one human-readable line per event
+    #[default]
+    Text,
+    /// one JSON object per event, with its fields and enclosing spans
+    Json,
+}
+
 /// Command-line interface for pickle-fuzzer.
 ///
-/// Supports two modes:
+/// Without a subcommand, the arguments are those of `generate`, so
+/// `pickle-fuzzer out.pkl` keeps working:
 /// - Single file mode: Generate one pickle file
 /// - Batch mode: Generate multiple pickle files in a directory
 #[derive(Parser, Debug)]
 #[command(name = "pickle-fuzzer")]
 #[command(version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct Cli {
-    /// path to a single pickle output file
+    /// run a subcommand instead of generating pickles
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub generate: GenerateArgs,
+
+    /// write log lines to stderr as text or JSON; RUST_LOG (e.g. "debug" or
+    /// "pickle_fuzzer=trace") picks which are written, info and up by
+    /// default
+    #[arg(long, value_enum, global = true, default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat,
+}
+
+/// Subcommands.
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// generate one pickle file or a directory of them (the default)
+    Generate(Box<GenerateArgs>),
+    /// write a structure-aware mutant of an existing pickle
+    Mutate(MutateArgs),
+    /// check that every pickle in a directory (or one file) is well-formed
+    Validate(ValidateArgs),
+    /// report protocols, opcodes, sizes, globals, and invalid files of a corpus
+    Analyze(AnalyzeArgs),
+    /// copy the smallest subset of a corpus that covers all its opcode
+    /// bigrams, globals, and protocols
+    Distill(DistillArgs),
+    /// list the pickles of a corpus that import given globals or use given
+    /// opcodes
+    Grep(GrepArgs),
+    /// print the opcodes of a pickle, like `python -m pickletools`
+    Dis(DisArgs),
+    /// shrink a recorded trace while a command keeps failing on its pickle
+    Minimize(Box<MinimizeArgs>),
+    /// label every pickle of a corpus with its protocol, validity, risk, and
+    /// globals, as JSON lines for scanner benchmarks
+    Dataset(DatasetArgs),
+    /// serve pickles over HTTP: POST /generate with a JSON configuration returns one pickle
+    #[cfg(feature = "serve")]
+    Serve(ServeArgs),
+}
+
+/// Options for `pickle-fuzzer generate`, and for `pickle-fuzzer` without a
+/// subcommand.
+#[derive(Args, Debug)]
+pub struct GenerateArgs {
+    /// path to a single pickle output file; `-` writes the pickle to stdout
     #[arg(
         value_name = "FILE",
         conflicts_with = "dir",
-        required_unless_present = "dir"
+        required_unless_present_any = ["dir", "list_opcodes"]
     )]
     pub file: Option<PathBuf>,
 
@@ -100,22 +401,207 @@ pub struct Cli {
         short = 'd',
         value_name = "DIR",
         conflicts_with = "file",
-        required_unless_present = "file"
+        required_unless_present_any = ["file", "list_opcodes"]
     )]
     pub dir: Option<PathBuf>,
 
-    /// pickle protocol version (0-5)
-    #[arg(short, long, value_name="PROTOCOL", value_parser = parse_version)]
-    pub protocol: Option<usize>,
+    /// print every opcode with its argument format, first protocol, and stack
+    /// effect, then exit
+    #[arg(long, conflicts_with_all = ["file", "dir"])]
+    pub list_opcodes: bool,
 
     /// number of pickle samples to generate in batch mode
     #[arg(short, long, default_value_t = 10_000, requires = "dir")]
     pub samples: usize,
 
+    /// number of worker threads for batch generation (0 uses one per CPU)
+    #[arg(
+        short,
+        long,
+        value_name = "JOBS",
+        default_value_t = 0,
+        requires = "dir"
+    )]
+    pub jobs: usize,
+
+    /// write N siblings per sample, IDX-0.pkl to IDX-(N-1).pkl, that share one
+    /// opcode skeleton and differ in leaf values and mutations (batch mode)
+    #[arg(
+        long,
+        value_name = "N",
+        requires = "dir",
+        conflicts_with = "value_seed",
+        value_parser = parse_variant_count
+    )]
+    pub variants: Option<usize>,
+
+    /// write a JSON-lines manifest describing every generated sample (batch mode)
+    #[arg(long, value_name = "FILE", requires = "dir")]
+    pub manifest: Option<PathBuf>,
+
+    /// compress every sample, writing IDX.pkl.gz or IDX.pkl.zst (batch mode);
+    /// the manifest names the compressed files and keeps the pickle sizes
+    #[arg(long, value_name = "FORMAT", value_enum, requires = "dir")]
+    pub compress: Option<Compression>,
+
+    /// pack every sample into one zstd-compressed tar archive,
+    /// DIR/corpus.tar.zst, instead of a file each (batch mode); the manifest
+    /// names the archive entries
+    #[cfg(feature = "zstd")]
+    #[arg(
+        long,
+        requires = "dir",
+        conflicts_with_all = ["compress", "resume", "dry_run"]
+    )]
+    pub archive: bool,
+
+    /// name batch samples from a template of {idx}, {proto}, {seed}, and
+    /// {variant}, e.g. "proto{proto}_seed{seed}_{idx}.pkl" (batch mode)
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        requires = "dir",
+        value_parser = parse_name_template
+    )]
+    pub name_template: Option<NameTemplate>,
+
+    /// spread batch samples round-robin over N numbered subdirectories of
+    /// DIR; manifest file names include the subdirectory
+    #[arg(
+        long,
+        value_name = "N",
+        requires = "dir",
+        value_parser = parse_shard_count
+    )]
+    pub shard_dirs: Option<usize>,
+
+    /// generate as usual but write nothing, printing aggregate statistics
+    /// (sizes, protocol mix, opcode histogram, mutations, throughput) instead
+    #[arg(long, conflicts_with_all = ["manifest", "record_trace", "annotations"])]
+    pub dry_run: bool,
+
+    /// continue an interrupted batch run into DIR, generating only the samples
+    /// whose files (and manifest lines) are missing; the derived seeds make the
+    /// finished corpus match an uninterrupted run (batch mode)
+    #[arg(long, requires = "seed", conflicts_with = "dry_run")]
+    pub resume: bool,
+
+    /// regenerate a sample whose content repeats an earlier one, with seeds
+    /// derived from its own, and skip it if it keeps repeating; the summary
+    /// reports the dedupe rate (batch mode)
+    #[arg(long, conflicts_with = "resume")]
+    pub dedupe: bool,
+
+    /// like --dedupe, but a sample repeats an earlier one when their opcode
+    /// sequences match, whatever their literal values (batch mode)
+    #[arg(long, conflicts_with = "resume")]
+    pub dedupe_structural: bool,
+
+    /// warn when fewer than RATE (0-1) of the last --monitor-window samples
+    /// validate (batch mode)
+    #[arg(long, value_name = "RATE", requires = "dir", value_parser = parse_rate)]
+    pub min_validity: Option<f64>,
+
+    /// warn when the last --monitor-window samples average fewer than BYTES
+    /// (batch mode)
+    #[arg(long, value_name = "BYTES", requires = "dir")]
+    pub min_average_size: Option<f64>,
+
+    /// warn when the last --monitor-window samples average more than BYTES
+    /// (batch mode)
+    #[arg(long, value_name = "BYTES", requires = "dir")]
+    pub max_average_size: Option<f64>,
+
+    /// warn when the opcodes of the last --monitor-window samples have less
+    /// than BITS of entropy, i.e. generation collapsed onto a few opcodes
+    /// (batch mode)
+    #[arg(long, value_name = "BITS", requires = "dir")]
+    pub min_opcode_entropy: Option<f64>,
+
+    /// how many of the latest samples the --min-validity, --min-average-size,
+    /// --max-average-size, and --min-opcode-entropy checks cover
+    #[arg(long, value_name = "N", default_value_t = 1000, requires = "dir")]
+    pub monitor_window: usize,
+
+    /// stop the run with an error when one of those checks fails, instead
+    /// of warning
+    #[arg(long, requires = "dir")]
+    pub abort_on_anomaly: bool,
+
+    /// keep Prometheus counters of the run (samples, bytes, invalid and failed
+    /// samples, mutator applications) in FILE, rewritten after every chunk of
+    /// samples, e.g. for the node exporter's textfile collector (batch mode)
+    #[arg(
+        long,
+        value_name = "FILE",
+        requires = "dir",
+        conflicts_with = "dry_run"
+    )]
+    pub metrics_file: Option<PathBuf>,
+
+    /// write the entropy decisions behind the pickle to FILE as JSON
+    /// (single-file mode)
+    #[arg(long, value_name = "FILE", conflicts_with = "replay_trace")]
+    pub record_trace: Option<PathBuf>,
+
+    /// rebuild the pickle from the entropy decisions in FILE instead of the
+    /// PRNG, with the options of the recording run (single-file mode)
+    #[arg(long, value_name = "FILE")]
+    pub replay_trace: Option<PathBuf>,
+
+    /// write a JSON map of the pickle's opcodes to FILE: each one's offset,
+    /// MARK depth, the generation step that emitted it, and the mutators that
+    /// changed it; `dis --annotations FILE` shows it (single-file mode)
+    #[arg(long, value_name = "FILE")]
+    pub annotations: Option<PathBuf>,
+
+    /// load every pickle with `python3` and fail when the object
+    /// `pickle.loads` builds differs from the one the generator's stack
+    /// simulation expected (needs --loadable)
+    #[arg(long, requires = "loadable")]
+    pub check_loads: bool,
+
+    /// read settings from a TOML file whose keys are the long flag names
+    /// (e.g. `protocol-mix = "0:10,5:90"`); flags on the command line override
+    /// it, and the batch manifest starts with the effective settings
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// every setting of the run when it was configured with `--config`
+    #[arg(skip)]
+    pub effective_config: Option<Map<String, Value>>,
+
+    #[command(flatten)]
+    pub options: GeneratorOptions,
+}
+
+/// How pickles are generated, shared by `generate` and `minimize`.
+#[derive(Args, Debug, Clone)]
+pub struct GeneratorOptions {
+    /// pickle protocol version (0-5)
+    #[arg(short, long, value_name="PROTOCOL", value_parser = parse_version)]
+    pub protocol: Option<Version>,
+
+    /// weighted protocol mix for generated samples, e.g. "0:10,2:20,4:40,5:30".
+    /// conflicts with --protocol
+    #[arg(
+        long,
+        value_name = "MIX",
+        conflicts_with = "protocol",
+        value_parser = parse_protocol_mix
+    )]
+    pub protocol_mix: Option<ProtocolMix>,
+
     /// seed for reproducible generation
     #[arg(long)]
     pub seed: Option<u64>,
 
+    /// seed for leaf values (integers, floats, strings, bytes, mutations),
+    /// drawn apart from the structure --seed picks; batch mode adds the sample
+    /// index the way it does to --seed
+    #[arg(long, value_name = "SEED")]
+    pub value_seed: Option<u64>,
+
     /// minimum number of opcodes to generate
     #[arg(long, default_value_t = 60)]
     pub min_opcodes: usize,
@@ -128,14 +614,63 @@ pub struct Cli {
     #[arg(
         long = "mutators",
         value_name = "MUTATOR",
+        value_parser = mutator_parser(),
         action = clap::ArgAction::Append
     )]
-    pub mutators: Vec<crate::mutators::MutatorKind>,
+    pub mutators: Vec<MutatorChoice>,
 
     /// mutation rate (0.0-1.0, probability of applying mutation)
     #[arg(long, default_value_t = 0.1)]
     pub mutation_rate: f64,
 
+    /// how mutators combine on one value: first (only the first that triggers),
+    /// all (every mutator in order), or random:N (up to N random mutators)
+    #[arg(
+        long,
+        value_name = "POLICY",
+        value_parser = parse_mutation_policy,
+        default_value = "first"
+    )]
+    pub mutation_policy: MutationPolicy,
+
+    /// only let mutators touch these argument classes (comma-separated or
+    /// repeated; default: every opcode)
+    #[arg(
+        long,
+        value_name = "TARGET",
+        value_enum,
+        value_delimiter = ',',
+        action = clap::ArgAction::Append
+    )]
+    pub mutation_scope: Vec<MutationTarget>,
+
+    /// extra tokens for the dictionary mutator, in AFL dictionary format
+    #[arg(long, value_name = "FILE")]
+    pub dictionary: Option<PathBuf>,
+
+    /// most characters or bytes the stringlen mutator appends when it extends
+    /// a value (default 9)
+    #[arg(long, value_name = "N", value_parser = parse_max_extend)]
+    pub stringlen_max_extend: Option<usize>,
+
+    /// never let the stringlen mutator truncate a value to nothing
+    #[arg(long)]
+    pub stringlen_no_empty: bool,
+
+    /// largest memo index the memoindex mutator picks with --unsafe-mutations
+    /// (default 999)
+    #[arg(long, value_name = "INDEX")]
+    pub memoindex_max: Option<usize>,
+
+    /// characters the character mutator writes into strings (default printable)
+    #[arg(long, value_name = "SET", value_enum)]
+    pub character_set: Option<CharacterSet>,
+
+    /// longest value the lengthboundary mutator writes, in bytes; boundary
+    /// lengths past it are skipped (default 65536)
+    #[arg(long, value_name = "LEN")]
+    pub lengthboundary_max: Option<usize>,
+
     /// allow unsafe mutations that may produce invalid pickles
     #[arg(long)]
     pub unsafe_mutations: bool,
@@ -151,13 +686,311 @@ pub struct Cli {
     /// allow PERSID/BINPERSID opcodes (requires persistent_load support in unpickler)
     #[arg(long)]
     pub allow_persistent_ids: bool,
+
+    /// maximum number of items on the pickle stack, MARKs included
+    #[arg(long, value_name = "DEPTH")]
+    pub max_stack_depth: Option<usize>,
+
+    /// bias integer opcodes toward their encoding boundaries (255/256 for
+    /// BININT1, 65535/65536 for BININT2, ±2^31 for BININT, non-minimal LONG1/LONG4)
+    #[arg(long)]
+    pub integer_boundaries: bool,
+
+    /// sometimes emit a common multi-opcode idiom (GLOBAL+EMPTY_TUPLE+REDUCE,
+    /// MARK+items+APPENDS, a dict of such calls, BUILD chains, an object shared
+    /// through memo GETs, a container that GETs itself) as one step
+    #[arg(long)]
+    pub interesting_patterns: bool,
+
+    /// sometimes build STACK_GLOBAL's module and name from memo GETs, escaped
+    /// UNICODE strings, or DUP/POP pairs instead of literals (protocol 4+)
+    #[arg(long)]
+    pub indirect_stack_globals: bool,
+
+    /// pickle a random object exactly like CPython's pickle.dumps (memoized
+    /// repeats, shortest integer opcodes, batched APPENDS/SETITEMS, framing)
+    #[arg(long, conflicts_with = "mutators")]
+    pub canonical: bool,
+
+    /// pickle a random object like --canonical, but with a random valid
+    /// encoding for every value (LONG4 for small ints, BINUNICODE8, PUT instead
+    /// of MEMOIZE, redundant PUTs, random batches, optional framing)
+    #[arg(long, conflicts_with_all = ["mutators", "canonical"])]
+    pub diverse_encodings: bool,
+
+    /// draw container sizes from a distribution: constant:N, uniform:MIN-MAX,
+    /// or zipf:MAX[:EXPONENT]. sized lists, dicts, and sets are batched like
+    /// CPython; raise --max-opcodes for large sizes
+    #[arg(long, value_name = "DIST", value_parser = parse_size_distribution)]
+    pub container_sizes: Option<SizeDistribution>,
+
+    /// fill containers of more than 1000 items in batches larger than
+    /// CPython's 1000 (with --container-sizes or --diverse-encodings)
+    #[arg(long)]
+    pub oversized_batches: bool,
+
+    /// sometimes emit a numpy array (numpy.core.multiarray._reconstruct and
+    /// BUILD) of the given dtypes and shape: DTYPES[:MAX_DIMS[:MAX_LEN]],
+    /// where DTYPES is `all` or type codes such as f4,f8,i8
+    #[arg(long, value_name = "SPEC", value_parser = parse_ndarray_spec)]
+    pub ndarrays: Option<NdarraySpec>,
+
+    /// sometimes emit a torch tensor like torch.save (_rebuild_tensor_v2 on
+    /// a BINPERSID storage); needs --allow-persistent-ids
+    #[arg(long, requires = "allow_persistent_ids")]
+    pub torch_tensors: bool,
+
+    /// sometimes emit a scikit-learn estimator the way joblib pickles it,
+    /// with NumpyArrayWrapper array attributes
+    #[arg(long)]
+    pub sklearn_estimators: bool,
+
+    /// sometimes emit an old-style class instance the way Python 2 pickles
+    /// it, with INST or OBJ and a BUILD of its __dict__ (protocol 2 and below)
+    #[arg(long)]
+    pub legacy_instances: bool,
+
+    /// only import the globals listed in FILE, one `module name` or
+    /// `module.name` per line, like what a RestrictedUnpickler permits; EXT
+    /// opcodes are never emitted
+    #[arg(long, value_name = "FILE")]
+    pub global_allowlist: Option<PathBuf>,
+
+    /// never emit the opcodes that call a global (REDUCE, NEWOBJ, NEWOBJ_EX,
+    /// OBJ, INST), nor the patterns built around them
+    #[arg(long, conflicts_with_all = ["canonical", "diverse_encodings"])]
+    pub forbid_reduce: bool,
+
+    /// only generate pickles that pickle.loads unpickles: import builtin
+    /// constructors and copyreg._reconstructor, call them only with
+    /// arguments they accept, hash only hashable keys, and keep protocol 0
+    /// strings ASCII
+    #[arg(long, conflicts_with_all = ["canonical", "diverse_encodings", "unsafe_mutations"])]
+    pub loadable: bool,
+
+    /// call builtin and stdlib types like frozenset, complex, or Decimal
+    /// with any arguments, not just ones their signature accepts
+    #[arg(long)]
+    pub ignore_signatures: bool,
+
+    /// let DUP copy a MARK and sometimes close two nested batches outer
+    /// first, to probe MARK handling; the output may fail pickletools and
+    /// Python 3 loaders
+    #[arg(long, conflicts_with_all = ["canonical", "diverse_encodings", "loadable"])]
+    pub unsafe_marks: bool,
+
+    /// sometimes emit a dense burst of up to OPS MARK, POP, POP_MARK, and DUP
+    /// opcodes that closes every MARK it opens (protocol 1+, OPS at most 64);
+    /// with --unsafe-marks bursts also DUP their MARKs
+    #[arg(long, value_name = "OPS", value_parser = parse_stress_intensity)]
+    pub mark_stress: Option<usize>,
+
+    /// which protocol PROTO announces: the body's, one above it with a body
+    /// older readers parse too (compatible), or a lower one (downgraded)
+    #[arg(long, value_name = "MODE", value_enum, default_value_t = ProtoHeader::Honest)]
+    pub proto_header: ProtoHeader,
+
+    /// sometimes emit FRAME between body opcodes, empty, cut short, overlong,
+    /// nested, or before protocol 4; most unpicklers reject the output
+    #[arg(long, conflicts_with_all = ["canonical", "diverse_encodings", "loadable"])]
+    pub unsafe_frames: bool,
+
+    /// sometimes hand BINPERSID a str, int, bytes, or tuple id like the ones
+    /// persistent_load implementations expect; needs --allow-persistent-ids
+    #[arg(long, requires = "allow_persistent_ids")]
+    pub persistent_id_payloads: bool,
+
+    /// give half of the PERSID ids a CRLF ending, a non-ASCII character, or
+    /// an embedded newline; needs --allow-persistent-ids
+    #[arg(long, requires = "allow_persistent_ids")]
+    pub unsafe_persistent_ids: bool,
+
+    /// how leftover stack items are reduced before STOP: fold them into tuples,
+    /// or pop them so the first object built stays the root
+    #[arg(long, value_name = "POLICY", value_enum, default_value_t = CleanupPolicy::Tuple)]
+    pub cleanup_policy: CleanupPolicy,
+
+    /// give up on a pickle still being generated after MS milliseconds; batch
+    /// mode regenerates it with a fresh seed and notes it in the manifest
+    #[arg(long, value_name = "MS")]
+    pub sample_timeout: Option<u64>,
+}
+
+/// Options for `pickle-fuzzer mutate`.
+#[derive(Args, Debug, Clone, PartialEq, Eq)]
+pub struct MutateArgs {
+    /// pickle to mutate; `-` reads it from stdin
+    #[arg(value_name = "FILE")]
+    pub input: PathBuf,
+
+    /// where to write the mutant; `-` writes it to stdout
+    #[arg(short, long, value_name = "FILE")]
+    pub output: PathBuf,
+
+    /// seed picking the edits; different seeds give different mutants
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// largest mutant to write, in bytes
+    #[arg(long, value_name = "BYTES", default_value_t = 65_536)]
+    pub max_size: usize,
+}
+
+/// Options for `pickle-fuzzer validate`.
+#[derive(Args, Debug, Clone, PartialEq, Eq)]
+pub struct ValidateArgs {
+    /// directory of pickles (subdirectories included), or a single pickle file
+    #[arg(value_name = "PATH")]
+    pub path: PathBuf,
+}
+
+/// Options for `pickle-fuzzer analyze`.
+#[derive(Args, Debug, Clone, PartialEq, Eq)]
+pub struct AnalyzeArgs {
+    /// directory of pickles (subdirectories included), or a single pickle file
+    #[arg(value_name = "PATH")]
+    pub path: PathBuf,
+
+    /// most common globals to list (0 lists them all)
+    #[arg(long, value_name = "N", default_value_t = 20)]
+    pub top: usize,
+}
+
+/// Options for `pickle-fuzzer distill`.
+#[derive(Args, Debug, Clone, PartialEq, Eq)]
+pub struct DistillArgs {
+    /// directory of pickles (subdirectories included)
+    #[arg(value_name = "DIR")]
+    pub input: PathBuf,
+
+    /// directory to copy the selected pickles to, keeping their paths below DIR
+    #[arg(short, long, value_name = "OUT")]
+    pub output: PathBuf,
+}
+
+/// Options for `pickle-fuzzer grep`.
+#[derive(Args, Debug, Clone, PartialEq, Eq)]
+#[command(group(ArgGroup::new("pattern").args(["globals", "opcodes"]).multiple(true).required(true)))]
+pub struct GrepArgs {
+    /// directory of pickles (subdirectories included), or a single pickle file
+    #[arg(value_name = "PATH")]
+    pub path: PathBuf,
+
+    /// global the pickle imports, like `os.system`, or `subprocess.*` for any
+    /// name of a module (repeatable)
+    #[arg(long = "global", value_name = "MODULE.NAME")]
+    pub globals: Vec<String>,
+
+    /// opcode the pickle uses, like `REDUCE` (repeatable)
+    #[arg(long = "opcode", value_name = "NAME", value_parser = parse_opcode_name)]
+    pub opcodes: Vec<&'static str>,
+
+    /// list pickles matching any pattern, not all of them
+    #[arg(long)]
+    pub any: bool,
+}
+
+/// Options for `pickle-fuzzer dis`.
+#[derive(Args, Debug, Clone, PartialEq, Eq)]
+pub struct DisArgs {
+    /// pickle to disassemble; `-` reads it from stdin
+    #[arg(value_name = "FILE")]
+    pub file: PathBuf,
+
+    /// annotations written by `--annotations` for this pickle, shown after
+    /// each opcode
+    #[arg(long, value_name = "MAP")]
+    pub annotations: Option<PathBuf>,
+}
+
+/// Options for `pickle-fuzzer minimize`.
+#[derive(Args, Debug, Clone)]
+pub struct MinimizeArgs {
+    /// trace written by `--record-trace`; pass the generator options of the
+    /// recording run so it replays the same pickle
+    #[arg(value_name = "TRACE")]
+    pub trace: PathBuf,
+
+    /// where to write the smallest failing pickle
+    #[arg(short, long, value_name = "FILE")]
+    pub output: PathBuf,
+
+    /// write the shrunk trace to FILE as JSON
+    #[arg(long, value_name = "FILE")]
+    pub output_trace: Option<PathBuf>,
+
+    /// most times to run the command
+    #[arg(long, value_name = "RUNS", default_value_t = 1_000)]
+    pub max_runs: usize,
+
+    /// command that fails (exits non-zero) on an interesting pickle; `{}` in
+    /// an argument is replaced with the pickle's path, which is appended when
+    /// no argument has one
+    #[arg(last = true, required = true, value_name = "COMMAND")]
+    pub command: Vec<OsString>,
+
+    #[command(flatten)]
+    pub options: GeneratorOptions,
+}
+
+/// Options for `pickle-fuzzer dataset`.
+#[derive(Args, Debug, Clone, PartialEq, Eq)]
+pub struct DatasetArgs {
+    /// directory of pickles (subdirectories included), or a single pickle file
+    #[arg(value_name = "PATH")]
+    pub path: PathBuf,
+
+    /// where to write the labels, one JSON line per pickle; `-` writes them
+    /// to stdout
+    #[arg(short, long, value_name = "FILE")]
+    pub output: PathBuf,
+}
+
+/// Options for `pickle-fuzzer serve`.
+#[cfg(feature = "serve")]
+#[derive(Args, Debug, Clone, PartialEq, Eq)]
+pub struct ServeArgs {
+    /// address to listen on
+    #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:8000")]
+    pub bind: String,
 }
 
 impl Cli {
     pub fn parse_args() -> Self {
-        Self::parse_from(normalize_mutator_args(std::env::args_os()))
+        Self::try_parse_args_from(std::env::args_os()).unwrap_or_else(|e| e.exit())
+    }
+
+    /// Parse `args` like [`parse_args`](Self::parse_args) parses the process
+    /// arguments, `--config` file included.
+    pub fn try_parse_args_from<I>(args: I) -> Result<Self, clap::Error>
+    where
+        I: IntoIterator<Item = OsString>,
+    {
+        let mut command = Self::command();
+        let args = apply_config_file(normalize_mutator_args(args))
+            .map_err(|e| command.error(ErrorKind::InvalidValue, e))?;
+        let matches = command.try_get_matches_from_mut(args)?;
+        let mut cli = Self::from_arg_matches(&matches).map_err(|e| e.format(&mut command))?;
+        let (generate, matches) = match (&mut cli.command, matches.subcommand()) {
+            (None, _) => (&mut cli.generate, &matches),
+            (Some(Command::Generate(generate)), Some((_, matches))) => (generate.as_mut(), matches),
+            _ => return Ok(cli),
+        };
+        if generate.config.is_some() {
+            generate.effective_config = Some(effective_config(matches));
+        }
+        Ok(cli)
     }
 
+    /// The subcommand to run: `generate` with the top-level arguments when
+    /// none was given.
+    pub fn into_command(self) -> Command {
+        self.command
+            .unwrap_or_else(|| Command::Generate(Box::new(self.generate)))
+    }
+}
+
+impl GenerateArgs {
     /// Check if running in batch mode (generating multiple files).
     pub fn is_batch_mode(&self) -> bool {
         self.dir.is_some()
@@ -172,12 +1005,14 @@ impl Cli {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::generator::Dtype;
 
     #[test]
     fn test_parse_version_valid() {
-        assert_eq!(parse_version("0").unwrap(), 0);
-        assert_eq!(parse_version("3").unwrap(), 3);
-        assert_eq!(parse_version("5").unwrap(), 5);
+        assert_eq!(parse_version("0").unwrap(), Version::V0);
+        assert_eq!(parse_version("3").unwrap(), Version::V3);
+        assert_eq!(parse_version("5").unwrap(), Version::V5);
+        assert_eq!(parse_version("v4").unwrap(), Version::V4);
     }
 
     #[test]
@@ -194,48 +1029,828 @@ mod tests {
     }
 
     #[test]
-    fn test_cli_mode_detection() {
-        use std::path::PathBuf;
-
-        let cli_single = Cli {
-            file: Some(PathBuf::from("test.pkl")),
-            dir: None,
-            protocol: None,
-            samples: 10_000,
-            seed: None,
-            min_opcodes: 60,
-            max_opcodes: 300,
-            mutators: vec![],
-            mutation_rate: 0.1,
-            unsafe_mutations: false,
-            allow_ext: false,
-            allow_buffer: false,
-            allow_persistent_ids: false,
+    fn test_parse_protocol_mix() {
+        let mix = parse_protocol_mix("0:10,2:20,4:40,5:30").unwrap();
+        assert_eq!(mix.total_weight(), 100);
+        assert!(parse_protocol_mix("7:1").is_err());
+        assert!(parse_protocol_mix("nonsense").is_err());
+    }
+
+    #[test]
+    fn test_container_sizes_flag() {
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "out.pkl"]).unwrap();
+        assert_eq!(cli.generate.options.container_sizes, None);
+
+        let cli =
+            Cli::try_parse_from(["pickle-fuzzer", "--container-sizes", "zipf:500", "out.pkl"])
+                .unwrap();
+        assert_eq!(
+            cli.generate.options.container_sizes,
+            Some(SizeDistribution::Zipf {
+                max: 500,
+                exponent: 1.0
+            })
+        );
+        assert!(
+            Cli::try_parse_from(["pickle-fuzzer", "--container-sizes", "many", "out.pkl"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_oversized_batches_flag() {
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "out.pkl"]).unwrap();
+        assert!(!cli.generate.options.oversized_batches);
+
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "--oversized-batches", "out.pkl"]).unwrap();
+        assert!(cli.generate.options.oversized_batches);
+    }
+
+    #[test]
+    fn test_ndarrays_flag() {
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "out.pkl"]).unwrap();
+        assert_eq!(cli.generate.options.ndarrays, None);
+
+        let cli =
+            Cli::try_parse_from(["pickle-fuzzer", "--ndarrays", "f4,i8:2", "out.pkl"]).unwrap();
+        let spec = cli.generate.options.ndarrays.unwrap();
+        assert_eq!(spec.dtypes, [Dtype::Float32, Dtype::Int64]);
+        assert_eq!(spec.max_dims, 2);
+        assert!(Cli::try_parse_from(["pickle-fuzzer", "--ndarrays", "f3", "out.pkl"]).is_err());
+    }
+
+    #[test]
+    fn test_torch_tensors_flag() {
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "out.pkl"]).unwrap();
+        assert!(!cli.generate.options.torch_tensors);
+
+        let cli = Cli::try_parse_from([
+            "pickle-fuzzer",
+            "--torch-tensors",
+            "--allow-persistent-ids",
+            "out.pkl",
+        ])
+        .unwrap();
+        assert!(cli.generate.options.torch_tensors);
+        assert!(Cli::try_parse_from(["pickle-fuzzer", "--torch-tensors", "out.pkl"]).is_err());
+    }
+
+    #[test]
+    fn test_sklearn_estimators_flag() {
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "out.pkl"]).unwrap();
+        assert!(!cli.generate.options.sklearn_estimators);
+
+        let cli =
+            Cli::try_parse_from(["pickle-fuzzer", "--sklearn-estimators", "out.pkl"]).unwrap();
+        assert!(cli.generate.options.sklearn_estimators);
+    }
+
+    #[test]
+    fn test_legacy_instances_flag() {
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "out.pkl"]).unwrap();
+        assert!(!cli.generate.options.legacy_instances);
+
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "--legacy-instances", "out.pkl"]).unwrap();
+        assert!(cli.generate.options.legacy_instances);
+    }
+
+    #[test]
+    fn test_variants_flag() {
+        let cli =
+            Cli::try_parse_from(["pickle-fuzzer", "--dir", "out", "--variants", "4"]).unwrap();
+        assert_eq!(cli.generate.variants, Some(4));
+        assert!(Cli::try_parse_from(["pickle-fuzzer", "--dir", "out", "--variants", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_list_opcodes_flag() {
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "--list-opcodes"]).unwrap();
+        assert!(cli.generate.list_opcodes);
+        assert!(
+            !Cli::try_parse_from(["pickle-fuzzer", "out.pkl"])
+                .unwrap()
+                .generate
+                .list_opcodes
+        );
+        assert!(Cli::try_parse_from(["pickle-fuzzer", "--list-opcodes", "out.pkl"]).is_err());
+        assert!(Cli::try_parse_from(["pickle-fuzzer"]).is_err());
+    }
+
+    #[test]
+    fn test_value_seed_flag() {
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "out.pkl"]).unwrap();
+        assert_eq!(cli.generate.options.value_seed, None);
+
+        let cli = Cli::try_parse_from([
+            "pickle-fuzzer",
+            "--seed",
+            "1",
+            "--value-seed",
+            "7",
+            "out.pkl",
+        ])
+        .unwrap();
+        assert_eq!(
+            (cli.generate.options.seed, cli.generate.options.value_seed),
+            (Some(1), Some(7))
+        );
+        assert!(Cli::try_parse_from([
+            "pickle-fuzzer",
+            "--dir",
+            "out",
+            "--variants",
+            "2",
+            "--value-seed",
+            "7",
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_trace_flags() {
+        let cli =
+            Cli::try_parse_from(["pickle-fuzzer", "--record-trace", "t.json", "out.pkl"]).unwrap();
+        assert_eq!(cli.generate.record_trace, Some(PathBuf::from("t.json")));
+        assert_eq!(cli.generate.replay_trace, None);
+
+        let cli =
+            Cli::try_parse_from(["pickle-fuzzer", "--replay-trace", "t.json", "out.pkl"]).unwrap();
+        assert_eq!(cli.generate.replay_trace, Some(PathBuf::from("t.json")));
+        assert!(Cli::try_parse_from([
+            "pickle-fuzzer",
+            "--record-trace",
+            "a.json",
+            "--replay-trace",
+            "b.json",
+            "out.pkl",
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_annotations_flag() {
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "out.pkl"]).unwrap();
+        assert_eq!(cli.generate.annotations, None);
+        let cli =
+            Cli::try_parse_from(["pickle-fuzzer", "--annotations", "a.json", "out.pkl"]).unwrap();
+        assert_eq!(cli.generate.annotations, Some(PathBuf::from("a.json")));
+
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "dis", "--annotations", "a.json", "x.pkl"])
+            .unwrap();
+        let Some(Command::Dis(args)) = cli.command else {
+            panic!("not dis: {:?}", cli.command);
         };
+        assert_eq!(args.annotations, Some(PathBuf::from("a.json")));
+    }
 
-        assert!(cli_single.is_single_file_mode());
-        assert!(!cli_single.is_batch_mode());
+    #[test]
+    fn test_dry_run_flag() {
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "--dry-run", "--dir", "out"]).unwrap();
+        assert!(cli.generate.dry_run);
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "--dir", "out"]).unwrap();
+        assert!(!cli.generate.dry_run);
+
+        // nothing is written, so there is no manifest or trace to write either
+        for args in [
+            &["--dir", "out", "--manifest", "m.jsonl"][..],
+            &["--record-trace", "t.json", "out.pkl"][..],
+            &["--annotations", "a.json", "out.pkl"][..],
+        ] {
+            let result =
+                Cli::try_parse_from(["pickle-fuzzer", "--dry-run"].iter().chain(args.iter()));
+            assert!(result.is_err(), "{args:?}");
+        }
+    }
+
+    #[test]
+    fn test_resume_flag() {
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "--dir", "out", "--seed", "1", "--resume"])
+            .unwrap();
+        assert!(cli.generate.resume);
+
+        // without a seed the regenerated samples couldn't match the first run
+        assert!(Cli::try_parse_from(["pickle-fuzzer", "--dir", "out", "--resume"]).is_err());
+        assert!(Cli::try_parse_from([
+            "pickle-fuzzer",
+            "--dir",
+            "out",
+            "--seed",
+            "1",
+            "--resume",
+            "--dry-run",
+        ])
+        .is_err());
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_archive_flag() {
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "--dir", "out", "--archive"]).unwrap();
+        assert!(cli.generate.archive);
+        let cli =
+            Cli::try_parse_from(["pickle-fuzzer", "--dir", "out", "--compress", "zstd"]).unwrap();
+        assert_eq!(cli.generate.compress, Some(Compression::Zstd));
+
+        // the archive is compressed as a whole and can't be resumed into
+        for args in [
+            &["--archive"][..],
+            &["--dir", "out", "--archive", "--compress", "gzip"][..],
+            &["--dir", "out", "--seed", "1", "--archive", "--resume"][..],
+        ] {
+            let result = Cli::try_parse_from(["pickle-fuzzer"].iter().chain(args.iter()));
+            assert!(result.is_err(), "{args:?}");
+        }
+    }
+
+    #[test]
+    fn test_dedupe_flag() {
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "--dir", "out", "--dedupe"]).unwrap();
+        assert!(cli.generate.dedupe);
+        let cli =
+            Cli::try_parse_from(["pickle-fuzzer", "--dir", "out", "--dedupe-structural"]).unwrap();
+        assert!(cli.generate.dedupe_structural && !cli.generate.dedupe);
+        // resumed samples were never hashed
+        assert!(Cli::try_parse_from([
+            "pickle-fuzzer",
+            "--dir",
+            "out",
+            "--seed",
+            "1",
+            "--resume",
+            "--dedupe",
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_protocol_mix_conflicts_with_protocol() {
+        let result = Cli::try_parse_from([
+            "pickle-fuzzer",
+            "--protocol",
+            "2",
+            "--protocol-mix",
+            "2:1,3:1",
+            "out.pkl",
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_integer_boundaries_flag() {
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "out.pkl"]).unwrap();
+        assert!(!cli.generate.options.integer_boundaries);
+
+        let cli =
+            Cli::try_parse_from(["pickle-fuzzer", "--integer-boundaries", "out.pkl"]).unwrap();
+        assert!(cli.generate.options.integer_boundaries);
+    }
+
+    #[test]
+    fn test_canonical_flag() {
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "out.pkl"]).unwrap();
+        assert!(!cli.generate.options.canonical);
+
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "--canonical", "out.pkl"]).unwrap();
+        assert!(cli.generate.options.canonical);
+
+        let result = Cli::try_parse_from([
+            "pickle-fuzzer",
+            "--canonical",
+            "--mutators",
+            "bitflip",
+            "out.pkl",
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_diverse_encodings_flag() {
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "out.pkl"]).unwrap();
+        assert!(!cli.generate.options.diverse_encodings);
+
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "--diverse-encodings", "out.pkl"]).unwrap();
+        assert!(cli.generate.options.diverse_encodings);
+
+        let result = Cli::try_parse_from([
+            "pickle-fuzzer",
+            "--diverse-encodings",
+            "--canonical",
+            "out.pkl",
+        ]);
+        assert!(result.is_err());
+    }
 
-        let cli_batch = Cli {
-            file: None,
-            dir: Some(PathBuf::from("output")),
-            protocol: None,
-            samples: 10_000,
-            seed: None,
-            min_opcodes: 60,
-            max_opcodes: 300,
-            mutators: vec![],
-            mutation_rate: 0.1,
-            unsafe_mutations: false,
-            allow_ext: false,
-            allow_buffer: false,
-            allow_persistent_ids: false,
+    #[test]
+    fn test_grep_subcommand() {
+        let cli = Cli::try_parse_from([
+            "pickle-fuzzer",
+            "grep",
+            "--global",
+            "subprocess.*",
+            "--opcode",
+            "reduce",
+            "corpus",
+        ])
+        .unwrap();
+        let Some(Command::Grep(args)) = cli.command else {
+            panic!("not grep: {:?}", cli.command);
+        };
+        assert_eq!(args.globals, ["subprocess.*"]);
+        assert_eq!(args.opcodes, ["REDUCE"]);
+        assert!(!args.any);
+
+        // a pattern is required, and opcodes have to exist
+        assert!(Cli::try_parse_from(["pickle-fuzzer", "grep", "corpus"]).is_err());
+        assert!(
+            Cli::try_parse_from(["pickle-fuzzer", "grep", "--opcode", "REDUX", "corpus"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_dataset_subcommand() {
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "dataset", "corpus", "-o", "labels.jsonl"])
+            .unwrap();
+        let Some(Command::Dataset(args)) = cli.command else {
+            panic!("not dataset: {:?}", cli.command);
         };
+        assert_eq!(args.path, PathBuf::from("corpus"));
+        assert_eq!(args.output, PathBuf::from("labels.jsonl"));
+
+        assert!(Cli::try_parse_from(["pickle-fuzzer", "dataset", "corpus"]).is_err());
+    }
+
+    #[test]
+    fn test_interesting_patterns_flag() {
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "out.pkl"]).unwrap();
+        assert!(!cli.generate.options.interesting_patterns);
+
+        let cli =
+            Cli::try_parse_from(["pickle-fuzzer", "--interesting-patterns", "out.pkl"]).unwrap();
+        assert!(cli.generate.options.interesting_patterns);
+    }
+
+    #[test]
+    fn test_restriction_flags() {
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "out.pkl"]).unwrap();
+        assert_eq!(cli.generate.options.global_allowlist, None);
+        assert!(!cli.generate.options.forbid_reduce);
+
+        let cli = Cli::try_parse_from([
+            "pickle-fuzzer",
+            "--global-allowlist",
+            "allowed.txt",
+            "--forbid-reduce",
+            "out.pkl",
+        ])
+        .unwrap();
+        assert_eq!(
+            cli.generate.options.global_allowlist,
+            Some(PathBuf::from("allowed.txt"))
+        );
+        assert!(cli.generate.options.forbid_reduce);
+        assert!(Cli::try_parse_from([
+            "pickle-fuzzer",
+            "--forbid-reduce",
+            "--canonical",
+            "out.pkl"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_loadable_flag() {
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "out.pkl"]).unwrap();
+        assert!(!cli.generate.options.loadable);
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "--loadable", "out.pkl"]).unwrap();
+        assert!(cli.generate.options.loadable);
+        for conflict in ["--canonical", "--diverse-encodings", "--unsafe-mutations"] {
+            assert!(
+                Cli::try_parse_from(["pickle-fuzzer", "--loadable", conflict, "out.pkl"]).is_err(),
+                "{conflict}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_monitor_flags() {
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "--dir", "out"]).unwrap();
+        assert_eq!(cli.generate.min_validity, None);
+        assert_eq!(cli.generate.monitor_window, 1000);
+        assert!(!cli.generate.abort_on_anomaly);
+
+        let cli = Cli::try_parse_from([
+            "pickle-fuzzer",
+            "--dir",
+            "out",
+            "--min-validity",
+            "0.9",
+            "--max-average-size",
+            "4096",
+            "--min-opcode-entropy",
+            "3",
+            "--monitor-window",
+            "50",
+            "--abort-on-anomaly",
+        ])
+        .unwrap();
+        assert_eq!(cli.generate.min_validity, Some(0.9));
+        assert_eq!(cli.generate.max_average_size, Some(4096.0));
+        assert_eq!(cli.generate.min_opcode_entropy, Some(3.0));
+        assert_eq!(cli.generate.monitor_window, 50);
+        assert!(cli.generate.abort_on_anomaly);
 
+        assert!(
+            Cli::try_parse_from(["pickle-fuzzer", "--dir", "out", "--min-validity", "1.5"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_metrics_file_flag() {
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "--dir", "out"]).unwrap();
+        assert_eq!(cli.generate.metrics_file, None);
+        let cli = Cli::try_parse_from([
+            "pickle-fuzzer",
+            "--dir",
+            "out",
+            "--metrics-file",
+            "run.prom",
+        ])
+        .unwrap();
+        assert_eq!(cli.generate.metrics_file, Some(PathBuf::from("run.prom")));
+        assert!(Cli::try_parse_from([
+            "pickle-fuzzer",
+            "--dir",
+            "out",
+            "--metrics-file",
+            "run.prom",
+            "--dry-run",
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_log_format_flag() {
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "out.pkl"]).unwrap();
+        assert_eq!(cli.log_format, LogFormat::Text);
+        let cli =
+            Cli::try_parse_from(["pickle-fuzzer", "--log-format", "json", "out.pkl"]).unwrap();
+        assert_eq!(cli.log_format, LogFormat::Json);
+        // global, so subcommands take it too
+        let cli = Cli::try_parse_from([
+            "pickle-fuzzer",
+            "validate",
+            "--log-format",
+            "json",
+            "corpus",
+        ])
+        .unwrap();
+        assert_eq!(cli.log_format, LogFormat::Json);
+        assert!(Cli::try_parse_from(["pickle-fuzzer", "--log-format", "xml", "out.pkl"]).is_err());
+    }
+
+    #[test]
+    fn test_check_loads_flag() {
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "--loadable", "out.pkl"]).unwrap();
+        assert!(!cli.generate.check_loads);
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "--loadable", "--check-loads", "out.pkl"])
+            .unwrap();
+        assert!(cli.generate.check_loads);
+        assert!(Cli::try_parse_from(["pickle-fuzzer", "--check-loads", "out.pkl"]).is_err());
+    }
+
+    #[test]
+    fn test_ignore_signatures_flag() {
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "out.pkl"]).unwrap();
+        assert!(!cli.generate.options.ignore_signatures);
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "--ignore-signatures", "out.pkl"]).unwrap();
+        assert!(cli.generate.options.ignore_signatures);
+    }
+
+    #[test]
+    fn test_mark_stress_flag() {
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "out.pkl"]).unwrap();
+        assert_eq!(cli.generate.options.mark_stress, None);
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "--mark-stress", "24", "out.pkl"]).unwrap();
+        assert_eq!(cli.generate.options.mark_stress, Some(24));
+        for intensity in ["0", "65", "many"] {
+            assert!(
+                Cli::try_parse_from(["pickle-fuzzer", "--mark-stress", intensity, "out.pkl"])
+                    .is_err(),
+                "{intensity}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_unsafe_marks_flag() {
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "out.pkl"]).unwrap();
+        assert!(!cli.generate.options.unsafe_marks);
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "--unsafe-marks", "out.pkl"]).unwrap();
+        assert!(cli.generate.options.unsafe_marks);
+        for conflict in ["--canonical", "--diverse-encodings", "--loadable"] {
+            assert!(
+                Cli::try_parse_from(["pickle-fuzzer", "--unsafe-marks", conflict, "out.pkl"])
+                    .is_err(),
+                "{conflict}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_indirect_stack_globals_flag() {
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "out.pkl"]).unwrap();
+        assert!(!cli.generate.options.indirect_stack_globals);
+
+        let cli =
+            Cli::try_parse_from(["pickle-fuzzer", "--indirect-stack-globals", "out.pkl"]).unwrap();
+        assert!(cli.generate.options.indirect_stack_globals);
+    }
+
+    #[cfg(feature = "serve")]
+    #[test]
+    fn test_serve_subcommand() {
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "serve"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::Serve(args)) if args.bind == "127.0.0.1:8000"));
+
+        let cli =
+            Cli::try_parse_from(["pickle-fuzzer", "serve", "--bind", "0.0.0.0:9000"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::Serve(args)) if args.bind == "0.0.0.0:9000"));
+
+        // the file modes still work, and still need a FILE or --dir
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "out.pkl"]).unwrap();
+        assert!(cli.command.is_none());
+        assert!(Cli::try_parse_from(["pickle-fuzzer"]).is_err());
+    }
+
+    #[test]
+    fn test_proto_header_flag() {
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "out.pkl"]).unwrap();
+        assert_eq!(cli.generate.options.proto_header, ProtoHeader::Honest);
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "--proto-header", "downgraded", "out.pkl"])
+            .unwrap();
+        assert_eq!(cli.generate.options.proto_header, ProtoHeader::Downgraded);
+        assert!(Cli::try_parse_from(["pickle-fuzzer", "--proto-header", "2", "out.pkl"]).is_err());
+    }
+
+    #[test]
+    fn test_persistent_id_flags() {
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "out.pkl"]).unwrap();
+        assert!(!cli.generate.options.persistent_id_payloads);
+        assert!(!cli.generate.options.unsafe_persistent_ids);
+
+        let cli = Cli::try_parse_from([
+            "pickle-fuzzer",
+            "--allow-persistent-ids",
+            "--persistent-id-payloads",
+            "--unsafe-persistent-ids",
+            "out.pkl",
+        ])
+        .unwrap();
+        assert!(cli.generate.options.persistent_id_payloads);
+        assert!(cli.generate.options.unsafe_persistent_ids);
+        for flag in ["--persistent-id-payloads", "--unsafe-persistent-ids"] {
+            assert!(
+                Cli::try_parse_from(["pickle-fuzzer", flag, "out.pkl"]).is_err(),
+                "{flag}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_unsafe_frames_flag() {
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "out.pkl"]).unwrap();
+        assert!(!cli.generate.options.unsafe_frames);
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "--unsafe-frames", "out.pkl"]).unwrap();
+        assert!(cli.generate.options.unsafe_frames);
+        for conflict in ["--canonical", "--diverse-encodings", "--loadable"] {
+            assert!(
+                Cli::try_parse_from(["pickle-fuzzer", "--unsafe-frames", conflict, "out.pkl"])
+                    .is_err(),
+                "{conflict}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_cleanup_policy_flag() {
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "out.pkl"]).unwrap();
+        assert_eq!(cli.generate.options.cleanup_policy, CleanupPolicy::Tuple);
+
+        let cli =
+            Cli::try_parse_from(["pickle-fuzzer", "--cleanup-policy", "keep-root", "out.pkl"])
+                .unwrap();
+        assert_eq!(cli.generate.options.cleanup_policy, CleanupPolicy::KeepRoot);
+
+        assert!(
+            Cli::try_parse_from(["pickle-fuzzer", "--cleanup-policy", "pop", "out.pkl"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_cli_mode_detection() {
+        let cli_single = Cli::try_parse_from(["pickle-fuzzer", "test.pkl"])
+            .unwrap()
+            .generate;
+        assert!(cli_single.is_single_file_mode());
+        assert!(!cli_single.is_batch_mode());
+
+        let cli_batch = Cli::try_parse_from(["pickle-fuzzer", "--dir", "output"])
+            .unwrap()
+            .generate;
         assert!(!cli_batch.is_single_file_mode());
         assert!(cli_batch.is_batch_mode());
     }
 
+    #[test]
+    fn test_mutation_policy_flag() {
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "out.pkl"]).unwrap();
+        assert_eq!(cli.generate.options.mutation_policy, MutationPolicy::First);
+
+        for (flag, policy) in [
+            ("all", MutationPolicy::All),
+            ("random:2", MutationPolicy::Random(2)),
+        ] {
+            let cli = Cli::try_parse_from(["pickle-fuzzer", "--mutation-policy", flag, "out.pkl"])
+                .unwrap();
+            assert_eq!(cli.generate.options.mutation_policy, policy);
+        }
+
+        for flag in ["random:0", "random", "some"] {
+            assert!(
+                Cli::try_parse_from(["pickle-fuzzer", "--mutation-policy", flag, "out.pkl"])
+                    .is_err()
+            );
+        }
+    }
+
+    #[test]
+    fn test_mutation_scope_flag() {
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "out.pkl"]).unwrap();
+        assert!(cli.generate.options.mutation_scope.is_empty());
+
+        let cli = Cli::try_parse_from([
+            "pickle-fuzzer",
+            "--mutation-scope",
+            "strings,length-prefixed",
+            "--mutation-scope",
+            "memo",
+            "out.pkl",
+        ])
+        .unwrap();
+        assert_eq!(
+            cli.generate.options.mutation_scope,
+            vec![
+                MutationTarget::Strings,
+                MutationTarget::LengthPrefixed,
+                MutationTarget::Memo
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mutator_setting_flags() {
+        let cli = Cli::try_parse_from(["pickle-fuzzer", "out.pkl"]).unwrap();
+        assert_eq!(cli.generate.options.stringlen_max_extend, None);
+        assert!(!cli.generate.options.stringlen_no_empty);
+        assert_eq!(cli.generate.options.character_set, None);
+
+        let cli = Cli::try_parse_from([
+            "pickle-fuzzer",
+            "--stringlen-max-extend",
+            "64",
+            "--stringlen-no-empty",
+            "--memoindex-max",
+            "100000",
+            "--character-set",
+            "unicode",
+            "out.pkl",
+        ])
+        .unwrap();
+        assert_eq!(cli.generate.options.stringlen_max_extend, Some(64));
+        assert!(cli.generate.options.stringlen_no_empty);
+        assert_eq!(cli.generate.options.memoindex_max, Some(100000));
+        assert_eq!(
+            cli.generate.options.character_set,
+            Some(CharacterSet::Unicode)
+        );
+        assert_eq!(cli.generate.options.lengthboundary_max, None);
+
+        assert!(
+            Cli::try_parse_from(["pickle-fuzzer", "--stringlen-max-extend", "0", "out.pkl"])
+                .is_err()
+        );
+
+        let cli = parse_with_config(
+            "stringlen-max-extend = 20\ncharacter-set = \"ascii\"\nlengthboundary-max = 256\n",
+            &["out.pkl"],
+        )
+        .unwrap();
+        assert_eq!(cli.generate.options.stringlen_max_extend, Some(20));
+        assert_eq!(
+            cli.generate.options.character_set,
+            Some(CharacterSet::Ascii)
+        );
+        assert_eq!(cli.generate.options.lengthboundary_max, Some(256));
+    }
+
+    fn parse_with_config(config: &str, args: &[&str]) -> Result<Cli, clap::Error> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("run.toml");
+        std::fs::write(&path, config).unwrap();
+        let mut argv = vec![OsString::from("pickle-fuzzer"), OsString::from("--config")];
+        argv.push(path.into_os_string());
+        argv.extend(args.iter().map(OsString::from));
+        Cli::try_parse_args_from(argv)
+    }
+
+    #[test]
+    fn test_config_file_sets_flags() {
+        let cli = parse_with_config(
+            "dir = \"out\"\nsamples = 5\nprotocol_mix = \"0:1,4:3\"\n\
+             mutators = [\"bitflip\", \"boundary\"]\nmutation-rate = 0.5\n\
+             mutation-policy = \"random:2\"\ninteresting-patterns = true\n\
+             allow-ext = false\n",
+            &[],
+        )
+        .unwrap();
+        assert_eq!(cli.generate.dir, Some(PathBuf::from("out")));
+        assert_eq!(cli.generate.samples, 5);
+        assert_eq!(cli.generate.options.protocol_mix.unwrap().total_weight(), 4);
+        assert_eq!(
+            cli.generate.options.mutators,
+            vec![
+                MutatorChoice::Builtin(MutatorKind::Bitflip),
+                MutatorChoice::Builtin(MutatorKind::Boundary)
+            ]
+        );
+        assert_eq!(cli.generate.options.mutation_rate, 0.5);
+        assert_eq!(
+            cli.generate.options.mutation_policy,
+            MutationPolicy::Random(2)
+        );
+        assert!(cli.generate.options.interesting_patterns);
+        assert!(!cli.generate.options.allow_ext);
+
+        let config = cli.generate.effective_config.unwrap();
+        assert_eq!(config["samples"], 5);
+        assert_eq!(
+            config["mutators"],
+            serde_json::json!(["bitflip", "boundary"])
+        );
+        assert_eq!(config["mutation-rate"], 0.5);
+        assert_eq!(config["min-opcodes"], 60);
+        assert_eq!(config["interesting-patterns"], true);
+        assert!(!config.contains_key("config"));
+    }
+
+    #[test]
+    fn test_command_line_overrides_config_file() {
+        let cli = parse_with_config(
+            "file = \"from-config.pkl\"\nseed = 1\nmutators = [\"bitflip\"]\n",
+            &["--seed", "2", "--mutators", "havoc", "given.pkl"],
+        )
+        .unwrap();
+        assert_eq!(cli.generate.file, Some(PathBuf::from("given.pkl")));
+        assert_eq!(cli.generate.options.seed, Some(2));
+        assert_eq!(
+            cli.generate.options.mutators,
+            vec![MutatorChoice::Builtin(MutatorKind::Havoc)]
+        );
+        assert_eq!(cli.generate.effective_config.unwrap()["seed"], 2);
+
+        let cli =
+            Cli::try_parse_args_from(["pickle-fuzzer", "out.pkl"].map(OsString::from)).unwrap();
+        assert!(cli.generate.effective_config.is_none());
+    }
+
+    #[test]
+    fn test_config_file_errors() {
+        for config in [
+            "no-such-flag = 1\n",
+            "config = \"other.toml\"\n",
+            "canonical = \"yes\"\n",
+            "seed = [1, 2]\n",
+            "[generator]\nseed = 1\n",
+            "seed = \"many\"\n",
+            "seed =\n",
+        ] {
+            assert!(
+                parse_with_config(config, &["out.pkl"]).is_err(),
+                "{config:?} should be rejected"
+            );
+        }
+        assert!(Cli::try_parse_args_from(
+            [
+                "pickle-fuzzer",
+                "--config",
+                "/nonexistent/run.toml",
+                "out.pkl"
+            ]
+            .map(OsString::from)
+        )
+        .is_err());
+    }
+
     #[test]
     fn test_normalize_mutator_args_keeps_output_path_positional() {
         let normalized = normalize_mutator_args([
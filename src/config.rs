@@ -0,0 +1,512 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! serializable generator configuration.
+//!
+//! [`GeneratorConfig`] describes a generator the way the CLI flags do, so
+//! services and bindings that receive configuration as data (JSON today) can
+//! build the same generator the CLI would.
+
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::mutators::{CharacterSet, MutatorChoice, MutatorConfig, MutatorKind};
+use crate::{
+    CleanupPolicy, Generator, MutationPolicy, MutationScope, MutationTarget, NdarraySpec,
+    ProtoHeader, SizeDistribution, Version, MAX_STRESS_OPS,
+};
+
+/// a generator configuration that can be read from JSON.
+///
+/// field names follow the CLI flags; every field is optional and unset fields
+/// take the CLI defaults. unknown fields are rejected.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GeneratorConfig {
+    /// protocol version (0-5); picked from `seed`, or at random, when unset
+    pub protocol: Option<usize>,
+    /// seed for reproducible output
+    pub seed: Option<u64>,
+    /// minimum opcode count (default 60)
+    pub min_opcodes: Option<usize>,
+    /// maximum opcode count (default 300)
+    pub max_opcodes: Option<usize>,
+    /// maximum pickle size in bytes
+    pub max_size: Option<usize>,
+    /// mutator names as accepted by `--mutators`, including `all`
+    pub mutators: Vec<String>,
+    /// mutation probability (default 0.1)
+    pub mutation_rate: Option<f64>,
+    /// `first` (default), `all`, or `random:N`
+    pub mutation_policy: Option<String>,
+    /// argument classes mutators may touch, as accepted by `--mutation-scope`
+    /// (default: every opcode)
+    pub mutation_scope: Vec<String>,
+    /// most characters or bytes the stringlen mutator appends (default 9)
+    pub stringlen_max_extend: Option<usize>,
+    /// never let the stringlen mutator truncate a value to nothing
+    pub stringlen_no_empty: bool,
+    /// largest memo index the unsafe memoindex mutator picks (default 999)
+    pub memoindex_max: Option<usize>,
+    /// `printable` (default), `ascii`, or `unicode`: what the character
+    /// mutator writes into strings
+    pub character_set: Option<String>,
+    /// longest value the lengthboundary mutator writes (default 65536)
+    pub lengthboundary_max: Option<usize>,
+    /// allow unsafe mutators
+    pub unsafe_mutations: bool,
+    /// allow EXT1/EXT2/EXT4
+    pub allow_ext: bool,
+    /// allow NEXT_BUFFER/READONLY_BUFFER
+    pub allow_buffer: bool,
+    /// allow PERSID/BINPERSID
+    pub allow_persistent_ids: bool,
+    /// maximum number of items on the pickle stack
+    pub max_stack_depth: Option<usize>,
+    /// `tuple` (default) or `keep-root`
+    pub cleanup_policy: Option<String>,
+    /// bias integer opcodes toward their encoding boundaries
+    pub integer_boundaries: bool,
+    /// sometimes emit a common multi-opcode idiom
+    pub interesting_patterns: bool,
+    /// build STACK_GLOBAL's module and name indirectly
+    pub indirect_stack_globals: bool,
+    /// pickle a random object exactly like CPython
+    pub canonical: bool,
+    /// pickle a random object with a random valid encoding for every value
+    pub diverse_encodings: bool,
+    /// container size distribution as accepted by `--container-sizes`
+    pub container_sizes: Option<String>,
+    /// fill containers of more than 1000 items in batches of more than 1000
+    pub oversized_batches: bool,
+    /// numpy array spec as accepted by `--ndarrays`
+    pub ndarrays: Option<String>,
+    /// sometimes emit a torch tensor rebuilt from a persistent id storage
+    pub torch_tensors: bool,
+    /// sometimes emit a scikit-learn estimator the way joblib pickles it
+    pub sklearn_estimators: bool,
+    /// sometimes emit an old-style class instance the way Python 2 pickles it
+    pub legacy_instances: bool,
+    /// the only globals to import, each `module name` or `module.name`
+    pub global_allowlist: Option<Vec<String>>,
+    /// never emit the opcodes that call a global
+    pub forbid_reduce: bool,
+    /// only generate pickles that `pickle.loads` unpickles
+    pub loadable: bool,
+    /// call listed callables with any arguments, not just fitting ones
+    pub ignore_signatures: bool,
+    /// duplicate MARKs and close nested batches out of order
+    pub unsafe_marks: bool,
+    /// most opcodes per mark stress burst, 1-64
+    pub mark_stress: Option<usize>,
+    /// `honest` (default), `compatible`, or `downgraded`
+    pub proto_header: Option<String>,
+    /// emit FRAME opcodes where picklers never put them
+    pub unsafe_frames: bool,
+    /// hand BINPERSID ids shaped like the ones `persistent_load` expects
+    pub persistent_id_payloads: bool,
+    /// break the PERSID id line with CRLF, non-ASCII text, or a newline
+    pub unsafe_persistent_ids: bool,
+}
+
+impl GeneratorConfig {
+    /// parse a configuration from JSON.
+    pub fn from_json(json: &[u8]) -> Result<Self, String> {
+        serde_json::from_slice(json).map_err(|e| e.to_string())
+    }
+
+    /// the protocol version this configuration generates.
+    ///
+    /// without an explicit `protocol` it is derived from `seed`, like the CLI
+    /// does, or picked at random (the default version without `os-rng`).
+    pub fn version(&self) -> Result<Version, String> {
+        match (self.protocol, self.seed) {
+            (Some(protocol), _) => Version::try_from(protocol)
+                .map_err(|_| format!("protocol must be 0-5, got {protocol}")),
+            (None, Some(seed)) => Ok(Version::select_for_seed(seed)),
+            #[cfg(feature = "os-rng")]
+            (None, None) => Ok(Version::select(rand::random())),
+            #[cfg(not(feature = "os-rng"))]
+            (None, None) => Ok(Version::default()),
+        }
+    }
+
+    /// build a generator for this configuration, or explain why it is invalid.
+    pub fn build(&self) -> Result<Generator, String> {
+        let mut choices = Vec::with_capacity(self.mutators.len());
+        for name in &self.mutators {
+            let choice = name.parse::<MutatorChoice>()?;
+            if choice.requires_unsafe_mutations() && !self.unsafe_mutations {
+                return Err(format!("mutator {name:?} requires unsafe_mutations"));
+            }
+            choices.push(choice);
+        }
+        let choices = MutatorChoice::expand(&choices, self.unsafe_mutations);
+        let mutator_config = self.mutator_config(&choices)?;
+        if (self.canonical || self.diverse_encodings) && !choices.is_empty() {
+            return Err("canonical and diverse encoding modes do not support mutators".to_string());
+        }
+
+        if self.torch_tensors && !self.allow_persistent_ids {
+            return Err("torch_tensors requires allow_persistent_ids".to_string());
+        }
+        if self.persistent_id_payloads && !self.allow_persistent_ids {
+            return Err("persistent_id_payloads requires allow_persistent_ids".to_string());
+        }
+        if self.unsafe_persistent_ids && !self.allow_persistent_ids {
+            return Err("unsafe_persistent_ids requires allow_persistent_ids".to_string());
+        }
+
+        let mutation_rate = self.mutation_rate.unwrap_or(0.1);
+        if !(0.0..=1.0).contains(&mutation_rate) {
+            return Err(format!(
+                "mutation_rate must be between 0.0 and 1.0, got {mutation_rate}"
+            ));
+        }
+
+        let mutation_policy = match &self.mutation_policy {
+            Some(policy) => policy
+                .parse::<MutationPolicy>()
+                .map_err(|e| format!("invalid mutation_policy: {e}"))?,
+            None => MutationPolicy::default(),
+        };
+
+        let mutation_scope = if self.mutation_scope.is_empty() {
+            MutationScope::ALL
+        } else {
+            self.mutation_scope
+                .iter()
+                .map(|name| {
+                    MutationTarget::from_str(name, true)
+                        .map_err(|_| format!("unknown mutation_scope target {name:?}"))
+                })
+                .collect::<Result<MutationScope, String>>()?
+        };
+
+        let cleanup_policy = match &self.cleanup_policy {
+            Some(name) => CleanupPolicy::from_str(name, true)
+                .map_err(|_| format!("unknown cleanup_policy {name:?}"))?,
+            None => CleanupPolicy::default(),
+        };
+
+        let proto_header = match &self.proto_header {
+            Some(name) => ProtoHeader::from_str(name, true)
+                .map_err(|_| format!("unknown proto_header {name:?}"))?,
+            None => ProtoHeader::default(),
+        };
+
+        let container_sizes = match &self.container_sizes {
+            Some(sizes) => Some(
+                sizes
+                    .parse::<SizeDistribution>()
+                    .map_err(|e| format!("invalid container_sizes: {e}"))?,
+            ),
+            None => None,
+        };
+
+        let ndarrays = match &self.ndarrays {
+            Some(spec) => Some(
+                spec.parse::<NdarraySpec>()
+                    .map_err(|e| format!("invalid ndarrays: {e}"))?,
+            ),
+            None => None,
+        };
+
+        if self.forbid_reduce && (self.canonical || self.diverse_encodings) {
+            return Err(
+                "canonical and diverse encoding modes do not support forbid_reduce".to_string(),
+            );
+        }
+        if self.loadable && (self.canonical || self.diverse_encodings || self.unsafe_mutations) {
+            return Err(
+                "loadable is incompatible with canonical, diverse encoding, and unsafe mutation modes"
+                    .to_string(),
+            );
+        }
+        if self.unsafe_marks && (self.canonical || self.diverse_encodings || self.loadable) {
+            return Err(
+                "unsafe_marks is incompatible with canonical, diverse encoding, and loadable modes"
+                    .to_string(),
+            );
+        }
+        if self.unsafe_frames && (self.canonical || self.diverse_encodings || self.loadable) {
+            return Err(
+                "unsafe_frames is incompatible with canonical, diverse encoding, and loadable modes"
+                    .to_string(),
+            );
+        }
+        if self
+            .mark_stress
+            .is_some_and(|ops| !(1..=MAX_STRESS_OPS).contains(&ops))
+        {
+            return Err(format!("mark_stress must be 1-{MAX_STRESS_OPS}"));
+        }
+        let global_allowlist = match &self.global_allowlist {
+            Some(globals) => Some(
+                Generator::parse_global_allowlist(&globals.join("\n"))
+                    .map_err(|e| format!("invalid global_allowlist: {e}"))?,
+            ),
+            None => None,
+        };
+
+        let defaults = Generator::default();
+        let mut generator = Generator::new(self.version()?)
+            .with_opcode_range(
+                self.min_opcodes.unwrap_or(defaults.min_opcodes),
+                self.max_opcodes.unwrap_or(defaults.max_opcodes),
+            )
+            .with_ext_opcodes(self.allow_ext)
+            .with_buffer_opcodes(self.allow_buffer)
+            .with_persistent_id_opcodes(self.allow_persistent_ids)
+            .with_cleanup_policy(cleanup_policy)
+            .with_proto_header(proto_header)
+            .with_integer_boundaries(self.integer_boundaries)
+            .with_interesting_patterns(self.interesting_patterns)
+            .with_indirect_stack_globals(self.indirect_stack_globals)
+            .with_canonical(self.canonical)
+            .with_diverse_encodings(self.diverse_encodings)
+            .with_oversized_batches(self.oversized_batches)
+            .with_torch_tensors(self.torch_tensors)
+            .with_sklearn_estimators(self.sklearn_estimators)
+            .with_legacy_instances(self.legacy_instances)
+            .with_forbid_reduce(self.forbid_reduce)
+            .with_loadable(self.loadable)
+            .with_signatures(!self.ignore_signatures)
+            .with_unsafe_marks(self.unsafe_marks)
+            .with_unsafe_frames(self.unsafe_frames)
+            .with_persistent_id_payloads(self.persistent_id_payloads)
+            .with_unsafe_persistent_ids(self.unsafe_persistent_ids);
+        if let Some(globals) = global_allowlist {
+            generator = generator.with_global_allowlist(globals);
+        }
+        if let Some(seed) = self.seed {
+            generator = generator.with_seed(seed);
+        }
+        if let Some(size) = self.max_size {
+            generator = generator.with_buffer_size(size);
+        }
+        if let Some(depth) = self.max_stack_depth {
+            generator = generator.with_max_stack_depth(depth);
+        }
+        if let Some(sizes) = container_sizes {
+            generator = generator.with_container_sizes(sizes);
+        }
+        if let Some(spec) = ndarrays {
+            generator = generator.with_ndarrays(spec);
+        }
+        if let Some(intensity) = self.mark_stress {
+            generator = generator.with_mark_stress(intensity);
+        }
+        if !choices.is_empty() {
+            generator = generator
+                .with_mutators(
+                    choices
+                        .iter()
+                        .map(|choice| choice.create_with(self.unsafe_mutations, &mutator_config))
+                        .collect(),
+                )
+                .with_mutation_rate(mutation_rate)
+                .with_mutation_policy(mutation_policy)
+                .with_mutation_scope(mutation_scope)
+                .with_unsafe_mutations(self.unsafe_mutations);
+        }
+        Ok(generator)
+    }
+
+    /// the settings of the configured builtin mutators; each needs its
+    /// mutator among `choices`.
+    fn mutator_config(&self, choices: &[MutatorChoice]) -> Result<MutatorConfig, String> {
+        let requires = |kind, name: &str| {
+            let choice = MutatorChoice::Builtin(kind);
+            if choices.contains(&choice) {
+                Ok(())
+            } else {
+                Err(format!("{name} requires the {choice} mutator"))
+            }
+        };
+        let mut config = MutatorConfig::default();
+        if self.stringlen_max_extend.is_some() || self.stringlen_no_empty {
+            requires(
+                MutatorKind::Stringlen,
+                "stringlen_max_extend and stringlen_no_empty",
+            )?;
+            let mut stringlen = config.stringlen.with_allow_empty(!self.stringlen_no_empty);
+            if let Some(max_extend) = self.stringlen_max_extend {
+                if max_extend == 0 {
+                    return Err("stringlen_max_extend must be at least 1".to_string());
+                }
+                stringlen = stringlen.with_max_extend(max_extend);
+            }
+            config = config.with_stringlen(stringlen);
+        }
+        if let Some(max_index) = self.memoindex_max {
+            requires(MutatorKind::Memoindex, "memoindex_max")?;
+            config = config.with_memoindex(config.memoindex.with_max_index(max_index));
+        }
+        if let Some(name) = &self.character_set {
+            requires(MutatorKind::Character, "character_set")?;
+            let charset = CharacterSet::from_str(name, true)
+                .map_err(|_| format!("unknown character_set {name:?}"))?;
+            config = config.with_character(config.character.with_charset(charset));
+        }
+        if let Some(max_len) = self.lengthboundary_max {
+            requires(MutatorKind::Lengthboundary, "lengthboundary_max")?;
+            config = config.with_lengthboundary(config.lengthboundary.with_max_len(max_len));
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_json_fills_unset_fields_with_defaults() {
+        let config =
+            GeneratorConfig::from_json(br#"{"protocol": 1, "mutators": ["all"]}"#).unwrap();
+        assert_eq!(config.protocol, Some(1));
+        assert_eq!(config.seed, None);
+
+        let generator = config.build().unwrap();
+        assert_eq!(generator.state.version, Version::V1);
+        assert_eq!(
+            generator.mutators.len(),
+            crate::MutatorKind::all_mutators(false).len()
+        );
+        assert_eq!((generator.min_opcodes, generator.max_opcodes), (60, 300));
+    }
+
+    #[test]
+    fn mutator_settings_reach_their_mutators() {
+        let config = GeneratorConfig::from_json(
+            br#"{"mutators": ["all"], "unsafe_mutations": true, "stringlen_max_extend": 30,
+                 "memoindex_max": 5000, "character_set": "unicode"}"#,
+        )
+        .unwrap();
+        let generator = config.build().unwrap();
+        let described: Vec<String> = generator
+            .mutators
+            .iter()
+            .map(|mutator| format!("{mutator:?}"))
+            .collect();
+        for setting in ["max_extend: 30", "max_index: 5000", "charset: Unicode"] {
+            assert!(
+                described.iter().any(|d| d.contains(setting)),
+                "{setting} missing from {described:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn global_allowlists_take_both_spellings() {
+        let config = GeneratorConfig::from_json(
+            br#"{"global_allowlist": ["builtins set", "datetime.date"], "forbid_reduce": true}"#,
+        )
+        .unwrap();
+        let generator = config.build().unwrap();
+        assert_eq!(
+            generator.global_allowlist,
+            Some(vec![
+                ("builtins".to_string(), "set".to_string()),
+                ("datetime".to_string(), "date".to_string()),
+            ])
+        );
+        assert!(generator.forbid_reduce);
+    }
+
+    #[test]
+    fn version_follows_the_seed_without_a_protocol() {
+        let config = GeneratorConfig {
+            seed: Some(10),
+            ..Default::default()
+        };
+        assert_eq!(config.version(), Ok(Version::V4));
+    }
+
+    #[test]
+    fn invalid_configurations_explain_themselves() {
+        for (json, reason) in [
+            (r#"{"protocol": 9}"#, "protocol"),
+            (r#"{"mutators": ["nope"]}"#, "unknown mutator"),
+            (r#"{"mutators": ["memoindex"]}"#, "unsafe_mutations"),
+            (r#"{"mutation_rate": 2.0}"#, "mutation_rate"),
+            (r#"{"mutation_policy": "random:0"}"#, "mutation_policy"),
+            (
+                r#"{"mutation_scope": ["names", "opcodes"]}"#,
+                "mutation_scope",
+            ),
+            (r#"{"cleanup_policy": "pop"}"#, "cleanup_policy"),
+            (r#"{"proto_header": "lying"}"#, "proto_header"),
+            (r#"{"container_sizes": "zipf:-1"}"#, "container_sizes"),
+            (r#"{"ndarrays": "f4:99"}"#, "ndarrays"),
+            (r#"{"torch_tensors": true}"#, "allow_persistent_ids"),
+            (
+                r#"{"persistent_id_payloads": true}"#,
+                "persistent_id_payloads requires",
+            ),
+            (
+                r#"{"unsafe_persistent_ids": true}"#,
+                "unsafe_persistent_ids requires",
+            ),
+            (
+                r#"{"canonical": true, "mutators": ["bitflip"]}"#,
+                "canonical",
+            ),
+            (
+                r#"{"diverse_encodings": true, "mutators": ["bitflip"]}"#,
+                "diverse encoding",
+            ),
+            (
+                r#"{"canonical": true, "forbid_reduce": true}"#,
+                "forbid_reduce",
+            ),
+            (r#"{"global_allowlist": ["builtins"]}"#, "global_allowlist"),
+            (
+                r#"{"loadable": true, "unsafe_mutations": true}"#,
+                "loadable",
+            ),
+            (
+                r#"{"unsafe_marks": true, "canonical": true}"#,
+                "unsafe_marks",
+            ),
+            (r#"{"mark_stress": 0}"#, "mark_stress"),
+            (
+                r#"{"stringlen_max_extend": 20, "mutators": ["bitflip"]}"#,
+                "requires the stringlen mutator",
+            ),
+            (
+                r#"{"stringlen_max_extend": 0, "mutators": ["stringlen"]}"#,
+                "stringlen_max_extend",
+            ),
+            (
+                r#"{"character_set": "ebcdic", "mutators": ["character"]}"#,
+                "character_set",
+            ),
+            (
+                r#"{"unsafe_frames": true, "loadable": true}"#,
+                "unsafe_frames",
+            ),
+        ] {
+            let error = GeneratorConfig::from_json(json.as_bytes())
+                .unwrap()
+                .build()
+                .unwrap_err();
+            assert!(error.contains(reason), "{json}: {error}");
+        }
+        assert!(GeneratorConfig::from_json(br#"{"colour": "blue"}"#).is_err());
+    }
+}
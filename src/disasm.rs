@@ -0,0 +1,1066 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! native pickle disassembler and validator.
+//!
+//! [`disassemble`] walks a pickle the way `pickletools.genops` does, decoding
+//! every opcode argument into an [`Argument`], and [`validate`] adds the stack
+//! and memo checks of `pickletools.dis` plus the whole-input STOP boundary the
+//! fuzz harnesses enforce. errors use `pickletools`' wording where it has one,
+//! so samples can be triaged, and alternative pickle implementations tested,
+//! without a Python interpreter.
+//!
+//! Python decodes text arguments into `str`, which may hold lone surrogates
+//! (`\ud800`); they become U+FFFD here.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use color_eyre::eyre::eyre;
+use color_eyre::Result;
+
+use crate::opcodes::{ArgFormat, Opcode, OpcodeInfo, OpcodeKind};
+
+/// a decoded opcode argument, holding what `pickletools.genops` yields.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Argument {
+    /// the opcode has no inline argument
+    None,
+    /// `INT` with the `00`/`01` spellings of False and True
+    Bool(bool),
+    /// an integer that fits in an i128
+    Int(i128),
+    /// a larger integer, as minimal little-endian two's complement bytes
+    BigInt(Vec<u8>),
+    /// `FLOAT` and `BINFLOAT`
+    Float(f64),
+    /// `BINBYTES`, `SHORT_BINBYTES`, and `BINBYTES8`
+    Bytes(Vec<u8>),
+    /// `BYTEARRAY8`
+    ByteArray(Vec<u8>),
+    /// text arguments (strings, unicode, `GLOBAL`/`INST` as `"module name"`)
+    Str(String),
+}
+
+impl fmt::Display for Argument {
+    /// formats the argument like Python's `repr` of the `genops` value.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Argument::None => write!(f, "None"),
+            Argument::Bool(true) => write!(f, "True"),
+            Argument::Bool(false) => write!(f, "False"),
+            Argument::Int(value) => write!(f, "{value}"),
+            Argument::BigInt(bytes) => write!(f, "{}", big_int_to_decimal(bytes)),
+            Argument::Float(value) if value.is_nan() => write!(f, "nan"),
+            Argument::Float(value) if value.is_infinite() => {
+                write!(f, "{}", if *value > 0.0 { "inf" } else { "-inf" })
+            }
+            Argument::Float(value) => write!(f, "{value:?}"),
+            Argument::Bytes(bytes) => write!(f, "b'{}'", bytes.escape_ascii()),
+            Argument::ByteArray(bytes) => write!(f, "bytearray(b'{}')", bytes.escape_ascii()),
+            Argument::Str(text) => write!(f, "'{}'", text.escape_debug()),
+        }
+    }
+}
+
+/// one opcode of a disassembled pickle.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction {
+    /// opcode byte
+    pub code: u8,
+    /// opcode name as `pickletools` spells it (e.g. `"SHORT_BINUNICODE"`)
+    pub name: &'static str,
+    /// decoded inline argument
+    pub arg: Argument,
+    /// offset of the opcode byte in the pickle
+    pub pos: usize,
+}
+
+/// python's `repr` of a bytes value, for error messages.
+fn bytes_repr(bytes: &[u8]) -> String {
+    format!("b'{}'", bytes.escape_ascii())
+}
+
+/// reads opcode arguments off the front of a pickle.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+
+    fn take(&mut self, len: usize, what: &str) -> Result<&'a [u8]> {
+        if len > self.remaining() {
+            return Err(eyre!(
+                "expected {len} bytes in a {what}, but only {} remain",
+                self.remaining()
+            ));
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn uint(&mut self, width: usize, what: &str) -> Result<u64> {
+        let bytes = self
+            .take(width, what)
+            .map_err(|_| eyre!("not enough data in stream to read {what}"))?;
+        let mut value = [0u8; 8];
+        value[..width].copy_from_slice(bytes);
+        Ok(u64::from_le_bytes(value))
+    }
+
+    fn int4(&mut self, what: &str) -> Result<i32> {
+        Ok(self.uint(4, what)? as u32 as i32)
+    }
+
+    /// a newline-terminated line, without the newline.
+    fn line(&mut self, what: &str) -> Result<&'a [u8]> {
+        let rest = &self.data[self.pos..];
+        let len = rest
+            .iter()
+            .position(|&b| b == b'\n')
+            .ok_or_else(|| eyre!("no newline found when trying to read {what}"))?;
+        self.pos += len + 1;
+        Ok(&rest[..len])
+    }
+
+    /// an argument with an unsigned length prefix `width` bytes wide.
+    fn counted(&mut self, width: usize, what: &str) -> Result<&'a [u8]> {
+        let len = self.uint(width, what)?;
+        let len =
+            usize::try_from(len).map_err(|_| eyre!("{what} byte count > sys.maxsize: {len}"))?;
+        self.take(len, what)
+    }
+
+    /// an argument with a signed 4-byte length prefix.
+    fn counted_int4(&mut self, what: &str) -> Result<&'a [u8]> {
+        let len = self.int4(what)?;
+        if len < 0 {
+            return Err(eyre!("{what} byte count < 0: {len}"));
+        }
+        self.take(len as usize, what)
+    }
+
+    fn argument(&mut self, format: ArgFormat) -> Result<Argument> {
+        Ok(match format {
+            ArgFormat::None => Argument::None,
+            ArgFormat::Uint1 => Argument::Int(self.uint(1, "uint1")?.into()),
+            ArgFormat::Uint2 => Argument::Int(self.uint(2, "uint2")?.into()),
+            ArgFormat::Int4 => Argument::Int(self.int4("int4")?.into()),
+            ArgFormat::Uint4 => Argument::Int(self.uint(4, "uint4")?.into()),
+            ArgFormat::Uint8 => Argument::Int(self.uint(8, "uint8")?.into()),
+            ArgFormat::DecimalNlShort => match self.line("stringnl")? {
+                b"00" => Argument::Bool(false),
+                b"01" => Argument::Bool(true),
+                line => parse_int(line)?,
+            },
+            ArgFormat::DecimalNlLong => {
+                let line = self.line("stringnl")?;
+                parse_int(line.strip_suffix(b"L").unwrap_or(line))?
+            }
+            ArgFormat::FloatNl => Argument::Float(parse_float(self.line("stringnl")?)?),
+            ArgFormat::Float8 => {
+                let bytes = self
+                    .take(8, "float8")
+                    .map_err(|_| eyre!("not enough data in stream to read float8"))?;
+                Argument::Float(f64::from_be_bytes(bytes.try_into().expect("8 bytes")))
+            }
+            ArgFormat::StringNl => Argument::Str(ascii(&escape_decode(strip_quotes(
+                self.line("stringnl")?,
+            )?)?)?),
+            ArgFormat::StringNlNoEscape => {
+                Argument::Str(ascii(&escape_decode(self.line("stringnl")?)?)?)
+            }
+            ArgFormat::StringNlNoEscapePair => {
+                let module = ascii(&escape_decode(self.line("stringnl")?)?)?;
+                let name = ascii(&escape_decode(self.line("stringnl")?)?)?;
+                Argument::Str(format!("{module} {name}"))
+            }
+            ArgFormat::UnicodeStringNl => {
+                Argument::Str(raw_unicode_escape(self.line("unicodestringnl")?)?)
+            }
+            ArgFormat::String1 => Argument::Str(latin1(self.counted(1, "string1")?)),
+            ArgFormat::String4 => Argument::Str(latin1(self.counted_int4("string4")?)),
+            ArgFormat::Bytes1 => Argument::Bytes(self.counted(1, "bytes1")?.to_vec()),
+            ArgFormat::Bytes4 => Argument::Bytes(self.counted(4, "bytes4")?.to_vec()),
+            ArgFormat::Bytes8 => Argument::Bytes(self.counted(8, "bytes8")?.to_vec()),
+            ArgFormat::ByteArray8 => Argument::ByteArray(self.counted(8, "bytearray8")?.to_vec()),
+            ArgFormat::UnicodeString1 => {
+                Argument::Str(utf8_surrogatepass(self.counted(1, "unicodestring1")?)?)
+            }
+            ArgFormat::UnicodeString4 => {
+                Argument::Str(utf8_surrogatepass(self.counted(4, "unicodestring4")?)?)
+            }
+            ArgFormat::UnicodeString8 => {
+                Argument::Str(utf8_surrogatepass(self.counted(8, "unicodestring8")?)?)
+            }
+            ArgFormat::Long1 => int_from_le_bytes(self.counted(1, "long1")?),
+            ArgFormat::Long4 => int_from_le_bytes(self.counted_int4("long4")?),
+        })
+    }
+}
+
+/// whitespace as python's `int()` and `float()` strip it from bytes.
+fn is_py_space(byte: u8) -> bool {
+    matches!(byte, b' ' | b'\t' | b'\n' | b'\r' | b'\x0b' | b'\x0c')
+}
+
+fn trim_py_space(mut text: &[u8]) -> &[u8] {
+    while let [first, rest @ ..] = text {
+        if !is_py_space(*first) {
+            break;
+        }
+        text = rest;
+    }
+    while let [rest @ .., last] = text {
+        if !is_py_space(*last) {
+            break;
+        }
+        text = rest;
+    }
+    text
+}
+
+/// drops the `_` separators python allows between two digits, or returns
+/// `None` if one is anywhere else.
+fn strip_digit_separators(text: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len());
+    for (i, &byte) in text.iter().enumerate() {
+        if byte == b'_' {
+            let digit_before = i > 0 && text[i - 1].is_ascii_digit();
+            let digit_after = text.get(i + 1).is_some_and(u8::is_ascii_digit);
+            if !(digit_before && digit_after) {
+                return None;
+            }
+        } else {
+            out.push(byte);
+        }
+    }
+    Some(out)
+}
+
+/// python's `int(text)` for a base-10 literal.
+pub(crate) fn parse_int(text: &[u8]) -> Result<Argument> {
+    let invalid = || {
+        eyre!(
+            "invalid literal for int() with base 10: {}",
+            bytes_repr(text)
+        )
+    };
+    let trimmed = trim_py_space(text);
+    let (negative, digits) = match trimmed {
+        [b'-', rest @ ..] => (true, rest),
+        [b'+', rest @ ..] => (false, rest),
+        _ => (false, trimmed),
+    };
+    if !digits.first().is_some_and(u8::is_ascii_digit) {
+        return Err(invalid());
+    }
+    let digits = strip_digit_separators(digits).ok_or_else(invalid)?;
+    if !digits.iter().all(u8::is_ascii_digit) {
+        return Err(invalid());
+    }
+    Ok(int_from_decimal(&digits, negative))
+}
+
+/// python's `float(text)`.
+fn parse_float(text: &[u8]) -> Result<f64> {
+    let invalid = || eyre!("could not convert string to float: {}", bytes_repr(text));
+    let digits = strip_digit_separators(trim_py_space(text)).ok_or_else(invalid)?;
+    std::str::from_utf8(&digits)
+        .ok()
+        .and_then(|text| text.parse().ok())
+        .ok_or_else(invalid)
+}
+
+/// builds an integer argument from ASCII decimal digits.
+fn int_from_decimal(digits: &[u8], negative: bool) -> Argument {
+    let small = digits.iter().try_fold(0i128, |value, &digit| {
+        value.checked_mul(10)?.checked_add(i128::from(digit - b'0'))
+    });
+    if let Some(value) = small {
+        return Argument::Int(if negative { -value } else { value });
+    }
+
+    // little-endian magnitude, grown one decimal digit at a time
+    let mut magnitude: Vec<u8> = Vec::new();
+    for &digit in digits {
+        let mut carry = u16::from(digit - b'0');
+        for byte in magnitude.iter_mut() {
+            let value = u16::from(*byte) * 10 + carry;
+            *byte = value as u8;
+            carry = value >> 8;
+        }
+        if carry > 0 {
+            magnitude.push(carry as u8);
+        }
+    }
+    magnitude.push(0); // sign byte
+    if negative {
+        let mut carry = true;
+        for byte in magnitude.iter_mut() {
+            let (value, overflow) = (!*byte).overflowing_add(u8::from(carry));
+            *byte = value;
+            carry = carry && overflow;
+        }
+    }
+    int_from_le_bytes(&magnitude)
+}
+
+/// builds an integer argument from little-endian two's complement bytes, the
+/// `LONG1`/`LONG4` encoding.
+pub(crate) fn int_from_le_bytes(bytes: &[u8]) -> Argument {
+    let negative = bytes.last().is_some_and(|&b| b & 0x80 != 0);
+    let sign = if negative { 0xff } else { 0x00 };
+    let mut len = bytes.len();
+    // drop sign bytes the value doesn't need
+    while len > 1 && bytes[len - 1] == sign && (bytes[len - 2] & 0x80 != 0) == negative {
+        len -= 1;
+    }
+    let bytes = &bytes[..len];
+
+    if bytes.len() <= 16 {
+        let mut value = [sign; 16];
+        value[..bytes.len()].copy_from_slice(bytes);
+        Argument::Int(i128::from_le_bytes(value))
+    } else {
+        Argument::BigInt(bytes.to_vec())
+    }
+}
+
+/// decimal digits of a little-endian two's complement integer.
+pub(crate) fn big_int_to_decimal(bytes: &[u8]) -> String {
+    let negative = bytes.last().is_some_and(|&b| b & 0x80 != 0);
+    let mut magnitude = bytes.to_vec();
+    if negative {
+        let mut carry = true;
+        for byte in magnitude.iter_mut() {
+            let (value, overflow) = (!*byte).overflowing_add(u8::from(carry));
+            *byte = value;
+            carry = carry && overflow;
+        }
+    }
+
+    let mut digits = Vec::new();
+    while magnitude.iter().any(|&b| b != 0) {
+        let mut remainder = 0u16;
+        for byte in magnitude.iter_mut().rev() {
+            let value = (remainder << 8) | u16::from(*byte);
+            *byte = (value / 10) as u8;
+            remainder = value % 10;
+        }
+        digits.push(b'0' + remainder as u8);
+    }
+    if digits.is_empty() {
+        digits.push(b'0');
+    }
+    if negative {
+        digits.push(b'-');
+    }
+    digits.reverse();
+    String::from_utf8(digits).expect("ASCII digits")
+}
+
+/// removes the matching quotes around a `STRING` argument.
+pub(crate) fn strip_quotes(line: &[u8]) -> Result<&[u8]> {
+    for quote in [b'"', b'\''] {
+        if line.first() == Some(&quote) {
+            if line.last() != Some(&quote) {
+                return Err(eyre!(
+                    "strinq quote {} not found at both ends of {}",
+                    bytes_repr(&[quote]),
+                    bytes_repr(line)
+                ));
+            }
+            // a lone quote is both ends of itself
+            return Ok(line.get(1..line.len() - 1).unwrap_or_default());
+        }
+    }
+    Err(eyre!("no string quotes around {}", bytes_repr(line)))
+}
+
+/// python's `codecs.escape_decode`, the escapes of a bytes literal.
+pub(crate) fn escape_decode(text: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len());
+    let mut i = 0;
+    while i < text.len() {
+        let byte = text[i];
+        i += 1;
+        if byte != b'\\' {
+            out.push(byte);
+            continue;
+        }
+        let Some(&escape) = text.get(i) else {
+            return Err(eyre!("Trailing \\ in string"));
+        };
+        i += 1;
+        match escape {
+            b'\n' => {}
+            b'\\' | b'\'' | b'"' => out.push(escape),
+            b'b' => out.push(0x08),
+            b'f' => out.push(0x0c),
+            b't' => out.push(b'\t'),
+            b'n' => out.push(b'\n'),
+            b'r' => out.push(b'\r'),
+            b'v' => out.push(0x0b),
+            b'a' => out.push(0x07),
+            b'0'..=b'7' => {
+                let mut value = u32::from(escape - b'0');
+                for _ in 0..2 {
+                    match text.get(i) {
+                        Some(&digit @ b'0'..=b'7') => {
+                            value = value * 8 + u32::from(digit - b'0');
+                            i += 1;
+                        }
+                        _ => break,
+                    }
+                }
+                out.push(value as u8);
+            }
+            b'x' => {
+                let value = text
+                    .get(i..i + 2)
+                    .and_then(|hex| std::str::from_utf8(hex).ok())
+                    .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .ok_or_else(|| eyre!("invalid \\x escape at position {}", i - 2))?;
+                out.push(value);
+                i += 2;
+            }
+            _ => out.extend_from_slice(&[b'\\', escape]),
+        }
+    }
+    Ok(out)
+}
+
+fn ascii(bytes: &[u8]) -> Result<String> {
+    match bytes.iter().position(|b| !b.is_ascii()) {
+        Some(i) => Err(eyre!(
+            "'ascii' codec can't decode byte {:#04x} in position {i}: ordinal not in range(128)",
+            bytes[i]
+        )),
+        None => Ok(bytes.iter().map(|&b| b as char).collect()),
+    }
+}
+
+fn latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| b as char).collect()
+}
+
+/// a code point from an escape, with surrogates replaced.
+fn code_point(value: u32) -> char {
+    char::from_u32(value).unwrap_or(char::REPLACEMENT_CHARACTER)
+}
+
+/// python's `raw-unicode-escape` decoding: latin-1 apart from `\uXXXX` and
+/// `\UXXXXXXXX` after an odd number of backslashes.
+pub(crate) fn raw_unicode_escape(text: &[u8]) -> Result<String> {
+    let mut out = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < text.len() {
+        if text[i] != b'\\' {
+            out.push(text[i] as char);
+            i += 1;
+            continue;
+        }
+        let run = text[i..].iter().take_while(|&&b| b == b'\\').count();
+        i += run;
+        let escape = text.get(i).copied().filter(|b| matches!(b, b'u' | b'U'));
+        let Some(escape) = escape.filter(|_| run % 2 == 1) else {
+            out.extend(std::iter::repeat_n('\\', run));
+            continue;
+        };
+        out.extend(std::iter::repeat_n('\\', run - 1));
+
+        let start = i - 1;
+        let width = if escape == b'u' { 4 } else { 8 };
+        let hex = text
+            .get(i + 1..i + 1 + width)
+            .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+            .ok_or_else(|| {
+                let escape = if width == 4 { "\\uXXXX" } else { "\\UXXXXXXXX" };
+                eyre!("'rawunicodeescape' codec can't decode bytes in position {start}: truncated {escape} escape")
+            })?;
+        let value = u32::from_str_radix(std::str::from_utf8(hex).expect("hex digits"), 16)
+            .expect("hex digits");
+        if value > 0x10ffff {
+            return Err(eyre!(
+                "'rawunicodeescape' codec can't decode bytes in position {start}-{}: \\Uxxxxxxxx out of range",
+                i + width
+            ));
+        }
+        out.push(code_point(value));
+        i += 1 + width;
+    }
+    Ok(out)
+}
+
+/// python's `str(bytes, 'utf-8', 'surrogatepass')`.
+pub(crate) fn utf8_surrogatepass(mut bytes: &[u8]) -> Result<String> {
+    let mut out = String::with_capacity(bytes.len());
+    let mut offset = 0;
+    loop {
+        match std::str::from_utf8(bytes) {
+            Ok(text) => {
+                out.push_str(text);
+                return Ok(out);
+            }
+            Err(error) => {
+                let valid = error.valid_up_to();
+                out.push_str(std::str::from_utf8(&bytes[..valid]).expect("valid prefix"));
+                // a UTF-8 encoded surrogate: ED A0..BF 80..BF
+                match bytes[valid..] {
+                    [0xed, 0xa0..=0xbf, 0x80..=0xbf, ..] => {
+                        out.push(char::REPLACEMENT_CHARACTER);
+                        bytes = &bytes[valid + 3..];
+                        offset += valid + 3;
+                    }
+                    _ => {
+                        return Err(eyre!(
+                            "'utf-8' codec can't decode byte {:#04x} in position {}",
+                            bytes[valid],
+                            offset + valid
+                        ))
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// decode `pickle` opcode by opcode through its first STOP, like
+/// `pickletools.genops`.
+///
+/// fails on an unknown opcode, an argument that doesn't decode, or input that
+/// ends before STOP. bytes after the STOP are not read.
+pub fn disassemble(pickle: &[u8]) -> Result<Vec<Instruction>> {
+    let mut reader = Reader {
+        data: pickle,
+        pos: 0,
+    };
+    let mut instructions = Vec::new();
+    loop {
+        if reader.remaining() == 0 {
+            return Err(eyre!("pickle exhausted before seeing STOP"));
+        }
+        let instruction = read_instruction(&mut reader)?;
+        let stop = instruction.name == "STOP";
+        instructions.push(instruction);
+        if stop {
+            return Ok(instructions);
+        }
+    }
+}
+
+/// decode every opcode in `fragment`, a run of whole opcodes cut from a
+/// pickle, without requiring a STOP.
+///
+/// fails if an opcode or argument doesn't decode or the last argument runs
+/// past the end of the fragment.
+pub(crate) fn disassemble_fragment(fragment: &[u8]) -> Result<Vec<Instruction>> {
+    let mut reader = Reader {
+        data: fragment,
+        pos: 0,
+    };
+    let mut instructions = Vec::new();
+    while reader.remaining() > 0 {
+        instructions.push(read_instruction(&mut reader)?);
+    }
+    Ok(instructions)
+}
+
+/// decode the opcode at the reader's position and its argument.
+fn read_instruction(reader: &mut Reader<'_>) -> Result<Instruction> {
+    let pos = reader.pos;
+    let code = reader.data[pos];
+    let info = OpcodeInfo::by_code(code)
+        .ok_or_else(|| eyre!("at position {pos}, opcode {} unknown", bytes_repr(&[code])))?;
+    reader.pos += 1;
+    let arg = reader
+        .argument(info.arg)
+        .map_err(|e| eyre!("{} at position {pos}: {e}", info.name))?;
+    Ok(Instruction {
+        code,
+        name: info.name,
+        arg,
+        pos,
+    })
+}
+
+/// decode the opcode at the front of `data` into the typed [`Opcode`] IR,
+/// and how many bytes it took.
+///
+/// the argument is checked the way [`disassemble`] checks it; `STRING`,
+/// `BINSTRING`, `LONG1` and `LONG4` then keep their raw bytes.
+pub(crate) fn read_opcode(data: &[u8]) -> Result<(Opcode, usize)> {
+    use OpcodeKind as Op;
+
+    if data.is_empty() {
+        return Err(eyre!("no opcode to decode"));
+    }
+    let mut reader = Reader { data, pos: 0 };
+    let instruction = read_instruction(&mut reader)?;
+    let len = reader.pos;
+    let kind = OpcodeKind::from_u8(instruction.code).expect("read_instruction knows the opcode");
+    let name = instruction.name;
+    // the argument's raw bytes, for the opcodes whose IR keeps them
+    let mut raw = Reader { data, pos: 1 };
+
+    let int = |arg: &Argument| -> Result<i128> {
+        match arg {
+            Argument::Int(value) => Ok(*value),
+            Argument::Bool(value) => Ok(*value as i128),
+            _ => Err(eyre!("{name} argument {arg} does not fit in an i128")),
+        }
+    };
+    let narrow = |value: i128| -> Result<u32> {
+        u32::try_from(value).map_err(|_| eyre!("{name} memo index out of range: {value}"))
+    };
+    let text = |arg: Argument| -> String {
+        match arg {
+            Argument::Str(text) => text,
+            _ => unreachable!("{name} has a text argument"),
+        }
+    };
+    let bytes = |arg: Argument| -> Vec<u8> {
+        match arg {
+            Argument::Bytes(bytes) | Argument::ByteArray(bytes) => bytes,
+            _ => unreachable!("{name} has a bytes argument"),
+        }
+    };
+    let float = |arg: &Argument| -> f64 {
+        match arg {
+            Argument::Float(value) => *value,
+            _ => unreachable!("{name} has a float argument"),
+        }
+    };
+    let arg = instruction.arg;
+
+    let opcode = match kind {
+        Op::Int => match arg {
+            Argument::Bool(value) => Opcode::IntBool(value),
+            ref arg => Opcode::Int(int(arg)?),
+        },
+        Op::BinInt => Opcode::BinInt(int(&arg)? as i32),
+        Op::BinInt1 => Opcode::BinInt1(int(&arg)? as u8),
+        Op::BinInt2 => Opcode::BinInt2(int(&arg)? as u16),
+        Op::Long => Opcode::Long(int(&arg)?),
+        Op::Long1 => Opcode::Long1(raw.counted(1, "long1")?.to_vec()),
+        Op::Long4 => Opcode::Long4(raw.counted_int4("long4")?.to_vec()),
+        Op::String => Opcode::String(escape_decode(strip_quotes(raw.line("stringnl")?)?)?),
+        Op::BinString => Opcode::BinString(raw.counted_int4("string4")?.to_vec()),
+        Op::ShortBinString => Opcode::ShortBinString(raw.counted(1, "string1")?.to_vec()),
+        Op::BinBytes => Opcode::BinBytes(bytes(arg)),
+        Op::ShortBinBytes => Opcode::ShortBinBytes(bytes(arg)),
+        Op::BinBytes8 => Opcode::BinBytes8(bytes(arg)),
+        Op::ByteArray8 => Opcode::ByteArray8(bytes(arg)),
+        Op::NextBuffer => Opcode::NextBuffer,
+        Op::ReadOnlyBuffer => Opcode::ReadOnlyBuffer,
+        Op::None => Opcode::None,
+        Op::NewTrue => Opcode::NewTrue,
+        Op::NewFalse => Opcode::NewFalse,
+        Op::Unicode => Opcode::Unicode(text(arg)),
+        Op::ShortBinUnicode => Opcode::ShortBinUnicode(text(arg)),
+        Op::BinUnicode => Opcode::BinUnicode(text(arg)),
+        Op::BinUnicode8 => Opcode::BinUnicode8(text(arg)),
+        Op::Float => Opcode::Float(float(&arg)),
+        Op::BinFloat => Opcode::BinFloat(float(&arg)),
+        Op::EmptyList => Opcode::EmptyList,
+        Op::Append => Opcode::Append,
+        Op::Appends => Opcode::Appends,
+        Op::List => Opcode::List,
+        Op::EmptyTuple => Opcode::EmptyTuple,
+        Op::Tuple => Opcode::Tuple,
+        Op::Tuple1 => Opcode::Tuple1,
+        Op::Tuple2 => Opcode::Tuple2,
+        Op::Tuple3 => Opcode::Tuple3,
+        Op::EmptyDict => Opcode::EmptyDict,
+        Op::Dict => Opcode::Dict,
+        Op::SetItem => Opcode::SetItem,
+        Op::SetItems => Opcode::SetItems,
+        Op::EmptySet => Opcode::EmptySet,
+        Op::AddItems => Opcode::AddItems,
+        Op::FrozenSet => Opcode::FrozenSet,
+        Op::Pop => Opcode::Pop,
+        Op::Dup => Opcode::Dup,
+        Op::Mark => Opcode::Mark,
+        Op::PopMark => Opcode::PopMark,
+        Op::Get => Opcode::Get(narrow(int(&arg)?)?),
+        Op::BinGet => Opcode::BinGet(int(&arg)? as u8),
+        Op::LongBinGet => Opcode::LongBinGet(int(&arg)? as u32),
+        Op::Put => Opcode::Put(narrow(int(&arg)?)?),
+        Op::BinPut => Opcode::BinPut(int(&arg)? as u8),
+        Op::LongBinPut => Opcode::LongBinPut(int(&arg)? as u32),
+        Op::Memoize => Opcode::Memoize,
+        Op::Ext1 => Opcode::Ext1(int(&arg)? as u8),
+        Op::Ext2 => Opcode::Ext2(int(&arg)? as u16),
+        Op::Ext4 => Opcode::Ext4(int(&arg)? as i32),
+        Op::Global | Op::Inst => {
+            let module = ascii(&escape_decode(raw.line("stringnl")?)?)?;
+            let name = ascii(&escape_decode(raw.line("stringnl")?)?)?;
+            if kind == Op::Global {
+                Opcode::Global(module, name)
+            } else {
+                Opcode::Inst(module, name)
+            }
+        }
+        Op::StackGlobal => Opcode::StackGlobal,
+        Op::Reduce => Opcode::Reduce,
+        Op::Build => Opcode::Build,
+        Op::Obj => Opcode::Obj,
+        Op::NewObj => Opcode::NewObj,
+        Op::NewObjEx => Opcode::NewObjEx,
+        Op::Proto => Opcode::Proto(int(&arg)? as u8),
+        Op::Stop => Opcode::Stop,
+        Op::Frame => Opcode::Frame(int(&arg)? as u64),
+        Op::PersID => Opcode::PersID(text(arg)),
+        Op::BinPersID => Opcode::BinPersID,
+    };
+    Ok((opcode, len))
+}
+
+/// a simulated stack slot.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Slot {
+    Mark,
+    Object,
+}
+
+/// a memo key: the integer argument of a PUT or GET.
+#[derive(PartialEq, Eq, Hash)]
+enum MemoKey {
+    Int(i128),
+    Big(Vec<u8>),
+}
+
+impl MemoKey {
+    fn new(arg: &Argument) -> Self {
+        match arg {
+            Argument::Bool(value) => MemoKey::Int(i128::from(*value)),
+            Argument::Int(value) => MemoKey::Int(*value),
+            Argument::BigInt(bytes) => MemoKey::Big(bytes.clone()),
+            other => unreachable!("memo opcodes take integer arguments, got {other}"),
+        }
+    }
+}
+
+/// a fingerprint of `pickle`'s structure: its opcode sequence, with every
+/// argument left out.
+///
+/// pickles that differ only in literal values (numbers, strings, memo indices,
+/// `GLOBAL` names) share a fingerprint, and the fingerprint of a pickle never
+/// changes, so corpora can be pruned of structural repeats. it is the 64-bit
+/// FNV-1a hash of the opcode bytes through STOP, and fails like
+/// [`disassemble`].
+///
+/// # Examples
+///
+/// ```
+/// use pickle_fuzzer::disasm::structural_fingerprint;
+///
+/// let one = structural_fingerprint(b"K\x01K\x02\x86.").unwrap();
+/// let other = structural_fingerprint(b"K\x07K\x00\x86.").unwrap();
+/// assert_eq!(one, other);
+/// assert_ne!(one, structural_fingerprint(b"K\x01K\x02K\x03\x87.").unwrap());
+/// ```
+pub fn structural_fingerprint(pickle: &[u8]) -> Result<u64> {
+    const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    let instructions = disassemble(pickle)?;
+    Ok(instructions
+        .iter()
+        .fold(FNV_OFFSET_BASIS, |hash, instruction| {
+            (hash ^ u64::from(instruction.code)).wrapping_mul(FNV_PRIME)
+        }))
+}
+
+/// check that `pickle` is one complete, consistent pickle.
+///
+/// runs the checks the fuzz harnesses' Python validator runs: the input
+/// disassembles through STOP with no trailing bytes, and the
+/// `pickletools.dis` simulation passes: pops find enough items, MARK-consuming
+/// opcodes find a MARK, memo keys are stored once before they are read and
+/// never hold a MARK, and the stack is empty after STOP.
+pub fn validate(pickle: &[u8]) -> Result<()> {
+    let instructions = disassemble(pickle)?;
+    let end = instructions.last().map_or(0, |stop| stop.pos + 1);
+    if end != pickle.len() {
+        return Err(eyre!("trailing bytes after STOP: {}", pickle.len() - end));
+    }
+
+    let mut check = StackCheck::default();
+    for instruction in &instructions {
+        check.step(instruction)?;
+    }
+    if !check.stack.is_empty() {
+        return Err(eyre!(
+            "stack not empty after STOP: {} items",
+            check.stack.len()
+        ));
+    }
+    Ok(())
+}
+
+/// the `pickletools.dis` stack and memo simulation, one opcode at a time.
+#[derive(Default)]
+pub(crate) struct StackCheck {
+    stack: Vec<Slot>,
+    marks: usize,
+    memo: HashSet<MemoKey>,
+}
+
+impl StackCheck {
+    /// start from an existing stack (bottom first, `true` for a MARK) and
+    /// the memo keys already stored.
+    pub(crate) fn resume(
+        stack: impl IntoIterator<Item = bool>,
+        memo: impl IntoIterator<Item = usize>,
+    ) -> Self {
+        let stack: Vec<Slot> = stack
+            .into_iter()
+            .map(|is_mark| if is_mark { Slot::Mark } else { Slot::Object })
+            .collect();
+        StackCheck {
+            marks: stack.iter().filter(|&&slot| slot == Slot::Mark).count(),
+            stack,
+            memo: memo
+                .into_iter()
+                .map(|key| MemoKey::Int(key as i128))
+                .collect(),
+        }
+    }
+
+    /// apply one opcode, failing where `pickletools.dis` would.
+    pub(crate) fn step(&mut self, instruction: &Instruction) -> Result<()> {
+        let StackCheck { stack, marks, memo } = self;
+        let info = OpcodeInfo::by_code(instruction.code).expect("disassembled opcodes are known");
+        let fail =
+            |message: String| eyre!("{} at position {}: {message}", info.name, instruction.pos);
+
+        let mut pops = info.stack.pops;
+        // POP of a MARK pops it like POP_MARK does
+        let pops_mark = info.stack.below_mark.is_some()
+            || (info.name == "POP" && stack.last() == Some(&Slot::Mark));
+        if pops_mark {
+            if *marks == 0 {
+                return Err(fail("no MARK exists on stack".to_string()));
+            }
+            *marks -= 1;
+            // a fixed-arity opcode may already have popped the MARK as an item
+            let mark = stack
+                .iter()
+                .rposition(|&slot| slot == Slot::Mark)
+                .ok_or_else(|| fail("the MARK was popped by an earlier opcode".to_string()))?;
+            stack.truncate(mark);
+            pops = info.stack.below_mark.unwrap_or(0);
+        }
+
+        match info.name {
+            "PUT" | "BINPUT" | "LONG_BINPUT" | "MEMOIZE" => {
+                let key = match info.name {
+                    "MEMOIZE" => MemoKey::Int(memo.len() as i128),
+                    _ => MemoKey::new(&instruction.arg),
+                };
+                if memo.contains(&key) {
+                    return Err(fail(format!(
+                        "memo key {} already defined",
+                        instruction.arg
+                    )));
+                } else if stack.is_empty() {
+                    return Err(fail("stack is empty -- can't store into memo".to_string()));
+                } else if stack.last() == Some(&Slot::Mark) {
+                    return Err(fail("can't store markobject in the memo".to_string()));
+                }
+                memo.insert(key);
+            }
+            "GET" | "BINGET" | "LONG_BINGET" if !memo.contains(&MemoKey::new(&instruction.arg)) => {
+                return Err(fail(format!(
+                    "memo key {} has never been stored into",
+                    instruction.arg
+                )));
+            }
+            _ => {}
+        }
+
+        if stack.len() < pops {
+            return Err(fail(format!(
+                "tries to pop {pops} items from stack with only {} items",
+                stack.len()
+            )));
+        }
+        stack.truncate(stack.len() - pops);
+        if info.stack.pushes_mark {
+            *marks += 1;
+            stack.push(Slot::Mark);
+        }
+        stack.extend(std::iter::repeat_n(Slot::Object, info.stack.pushes));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Generator, Version};
+
+    fn args(pickle: &[u8]) -> Vec<(&'static str, Argument)> {
+        disassemble(pickle)
+            .unwrap()
+            .into_iter()
+            .map(|instruction| (instruction.name, instruction.arg))
+            .collect()
+    }
+
+    #[test]
+    fn disassembles_binary_arguments() {
+        // pickle.dumps([1, 'a', 2**70, 1.5], 2)
+        let pickle = b"\x80\x02]q\x00(K\x01X\x01\x00\x00\x00aq\x01\x8a\t\x00\x00\x00\x00\x00\x00\x00\x00@G?\xf8\x00\x00\x00\x00\x00\x00e.";
+        let instructions = disassemble(pickle).unwrap();
+        assert_eq!(instructions[0].pos, 0);
+        assert_eq!(instructions[2].pos, 3);
+        assert_eq!(
+            args(pickle),
+            [
+                ("PROTO", Argument::Int(2)),
+                ("EMPTY_LIST", Argument::None),
+                ("BINPUT", Argument::Int(0)),
+                ("MARK", Argument::None),
+                ("BININT1", Argument::Int(1)),
+                ("BINUNICODE", Argument::Str("a".into())),
+                ("BINPUT", Argument::Int(1)),
+                ("LONG1", Argument::Int(1 << 70)),
+                ("BINFLOAT", Argument::Float(1.5)),
+                ("APPENDS", Argument::None),
+                ("STOP", Argument::None),
+            ]
+        );
+        assert!(validate(pickle).is_ok());
+    }
+
+    #[test]
+    fn decodes_text_arguments_like_python() {
+        let pickle = b"(I01\nI 1_0 \nL-99999999999999999999999999999999999999999L\nF-inf\nS'\\x41\\n\\q'\nVa\\u00e9\\\\u0041\ncos\nsystem\nt.";
+        let decoded = args(pickle);
+        assert_eq!(decoded[1], ("INT", Argument::Bool(true)));
+        assert_eq!(decoded[2], ("INT", Argument::Int(10)));
+        assert!(matches!(decoded[3].1, Argument::BigInt(_)));
+        assert_eq!(
+            decoded[3].1.to_string(),
+            "-99999999999999999999999999999999999999999"
+        );
+        assert_eq!(decoded[4], ("FLOAT", Argument::Float(f64::NEG_INFINITY)));
+        assert_eq!(decoded[5], ("STRING", Argument::Str("A\n\\q".into())));
+        assert_eq!(
+            decoded[6],
+            ("UNICODE", Argument::Str("a\u{e9}\\\\u0041".into()))
+        );
+        assert_eq!(decoded[7], ("GLOBAL", Argument::Str("os system".into())));
+        assert!(validate(pickle).is_ok());
+    }
+
+    #[test]
+    fn reports_malformed_input() {
+        for (pickle, message) in [
+            (&b""[..], "pickle exhausted before seeing STOP"),
+            (b"N", "pickle exhausted before seeing STOP"),
+            (b"\xff", "opcode b'\\xff' unknown"),
+            (b"I1__0\n.", "invalid literal for int()"),
+            (b"Sabc\n.", "no string quotes around b'abc'"),
+            (b"S'\\x4'\n.", "invalid \\x escape"),
+            (b"V\\u12\n.", "truncated \\uXXXX escape"),
+            (
+                b"X\x01\x00\x00\x00\xff.",
+                "'utf-8' codec can't decode byte 0xff",
+            ),
+            (b"\x80\x02\x8b\xff\xff\xff\xff.", "long4 byte count < 0"),
+            (b"K", "not enough data in stream to read uint1"),
+        ] {
+            let error = disassemble(pickle).unwrap_err().to_string();
+            assert!(error.contains(message), "{pickle:?}: {error}");
+        }
+    }
+
+    #[test]
+    fn validation_follows_pickletools_dis() {
+        for (pickle, message) in [
+            (&b"N.N"[..], "trailing bytes after STOP: 1"),
+            (b"0.", "tries to pop 1 items from stack with only 0 items"),
+            (b"l.", "no MARK exists on stack"),
+            (b"N(p0\n.", "can't store markobject in the memo"),
+            (b"Np0\np0\n.", "memo key 0 already defined"),
+            (b"g0\n.", "memo key 0 has never been stored into"),
+            (b"(\x85l.", "the MARK was popped by an earlier opcode"),
+            (b"NN.", "stack not empty after STOP: 1 items"),
+        ] {
+            let error = validate(pickle).unwrap_err().to_string();
+            assert!(error.contains(message), "{pickle:?}: {error}");
+        }
+
+        // POP of a MARK pops it, and GET of a PUT bool key finds it
+        assert!(validate(b"(0Np1\ng01\n0.").is_ok());
+    }
+
+    #[test]
+    fn generated_pickles_validate() {
+        for version in Version::all() {
+            let mut generator = Generator::new(version).with_seed(version.as_u8().into());
+            for _ in 0..20 {
+                let pickle = generator.generate().unwrap();
+                validate(&pickle).unwrap_or_else(|e| panic!("protocol {version}: {e}"));
+            }
+        }
+    }
+
+    #[test]
+    fn fingerprints_ignore_arguments() {
+        // the same opcodes with other literals, memo indices, and GLOBAL names
+        let fingerprint = structural_fingerprint(b"\x80\x02X\x01\x00\x00\x00aq\x00cos\nsystem\n.");
+        assert_eq!(
+            fingerprint.unwrap(),
+            structural_fingerprint(b"\x80\x04X\x03\x00\x00\x00xyzq\x07cbuiltins\neval\n.").unwrap()
+        );
+        // a different encoding of the same value is a different structure
+        assert_ne!(
+            structural_fingerprint(b"K\x01.").unwrap(),
+            structural_fingerprint(b"M\x01\x00.").unwrap()
+        );
+        assert!(structural_fingerprint(b"K\x01").is_err());
+    }
+
+    #[test]
+    fn big_integers_round_trip_through_decimal() {
+        for text in [
+            "0",
+            "-1",
+            "170141183460469231731687303715884105728",
+            "-340282366920938463463374607431768211457",
+        ] {
+            let value = int_from_decimal(
+                text.trim_start_matches('-').as_bytes(),
+                text.starts_with('-'),
+            );
+            assert_eq!(value.to_string(), text);
+        }
+        assert_eq!(int_from_le_bytes(&[0xff, 0x00]), Argument::Int(255));
+        assert_eq!(int_from_le_bytes(&[0xff; 20]), Argument::Int(-1));
+        assert_eq!(int_from_le_bytes(&[]), Argument::Int(0));
+    }
+}
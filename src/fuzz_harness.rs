@@ -0,0 +1,898 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! input decoding and checks shared by the fuzz harnesses.
+//!
+//! the libFuzzer (`cargo fuzz`), honggfuzz (`cargo hfuzz`) and AFL++
+//! (`cargo afl`) entry points all turn raw fuzzer bytes into a generator
+//! configuration the same way, so a crash found by one engine reproduces in
+//! the others from the same input file.
+//!
+//! two input layouts are supported:
+//!
+//! - **protocol-only** ([`run_all_protocols`]): byte 0 selects the protocol
+//!   version (modulo 6), the remaining bytes seed `generate_from_arbitrary`.
+//! - **configured** ([`run_configured`]): a [`FuzzConfig`] decoded with
+//!   `arbitrary` from the front of the input, the remaining bytes seed
+//!   `generate_from_arbitrary`.
+//!
+//! [`run_all_protocols`] uses the generator's own [`ExhaustionPolicy`] and
+//! [`run_configured_with`] takes one, so each target decides what happens
+//! when an input's generation entropy runs out.
+//!
+//! the checks panic on failure, which is how every supported engine detects
+//! a finding.
+//!
+//! [`check_parser`] fuzzes the native disassembler and validator
+//! ([`crate::disasm`]) with the raw input as a pickle instead, and
+//! [`run_round_trip`] checks that the typed [`Opcode`] IR re-encodes
+//! generated pickles byte for byte.
+//!
+//! [`mutate_pickle`] is the other direction: a custom-mutator hook for engines
+//! (Atheris, libFuzzer's `LLVMFuzzerCustomMutator`) whose corpus holds
+//! pickles rather than generator entropy.
+
+use arbitrary::{Arbitrary, Unstructured};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::disasm::{disassemble, structural_fingerprint, validate};
+use crate::mutators::{
+    registered_mutators, BitFlipMutator, BoundaryMutator, CharacterMutator, DictionaryMutator,
+    Mutator, MutatorChoice, OffByOneMutator, StringLengthMutator,
+};
+use crate::{ExhaustionPolicy, Generator, Opcode, Version};
+
+/// largest opcode count a [`FuzzConfig`] may ask for.
+///
+/// keeps individual iterations fast and the simulated stack shallow.
+pub const MAX_FUZZ_OPCODES: usize = 1000;
+
+/// map a selector byte onto one of the six protocol versions.
+pub fn version_from_byte(byte: u8) -> Version {
+    Version::select(byte.into())
+}
+
+/// the safe mutators a fuzz input can switch on.
+///
+/// `MemoIndexMutator` is intentionally absent: even in "safe" mode it can
+/// generate memo references to keys that don't exist, and these harnesses
+/// validate the generator's output. for the same reason `registered` only
+/// picks from mutators registered with
+/// [`register_mutator`](crate::register_mutator), never from unsafe ones.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Arbitrary)]
+pub struct FuzzMutators {
+    pub bit_flip: bool,
+    pub boundary: bool,
+    pub off_by_one: bool,
+    pub string_length: bool,
+    pub character: bool,
+    pub dictionary: bool,
+    /// 0 for none, otherwise selects one registered mutator (wrapping)
+    pub registered: u8,
+}
+
+impl FuzzMutators {
+    /// instantiate the selected mutators.
+    pub fn build(&self) -> Vec<Box<dyn Mutator>> {
+        let mut mutators: Vec<Box<dyn Mutator>> = Vec::new();
+        if self.bit_flip {
+            mutators.push(Box::new(BitFlipMutator));
+        }
+        if self.boundary {
+            mutators.push(Box::new(BoundaryMutator));
+        }
+        if self.off_by_one {
+            mutators.push(Box::new(OffByOneMutator));
+        }
+        if self.string_length {
+            mutators.push(Box::new(StringLengthMutator::default()));
+        }
+        if self.character {
+            mutators.push(Box::new(CharacterMutator::default()));
+        }
+        if self.dictionary {
+            mutators.push(Box::new(DictionaryMutator::default()));
+        }
+        if self.registered != 0 {
+            let names = registered_mutators(false);
+            if !names.is_empty() {
+                let name = names[(self.registered as usize - 1) % names.len()];
+                mutators.push(MutatorChoice::Registered(name).create(false));
+            }
+        }
+        mutators
+    }
+}
+
+/// generator configuration decoded from the front of a fuzzer input.
+///
+/// every field is decoded into its valid range, so any byte string yields a
+/// usable configuration: opcode counts are capped at [`MAX_FUZZ_OPCODES`] and
+/// the mutation rate is a byte mapped onto 0.0-1.0.
+#[derive(Debug, Clone, PartialEq, Arbitrary)]
+pub struct FuzzConfig {
+    /// protocol version to generate
+    pub version: Version,
+    /// lower bound of the opcode range
+    #[arbitrary(with = opcode_count)]
+    pub min_opcodes: usize,
+    /// upper bound of the opcode range
+    #[arbitrary(with = opcode_count)]
+    pub max_opcodes: usize,
+    /// probability of applying a mutator per opcode
+    #[arbitrary(with = mutation_rate)]
+    pub mutation_rate: f64,
+    /// which mutators are enabled
+    pub mutators: FuzzMutators,
+    /// allow EXT1/EXT2/EXT4
+    pub ext_opcodes: bool,
+    /// allow NEXT_BUFFER/READONLY_BUFFER
+    pub buffer_opcodes: bool,
+    /// allow PERSID/BINPERSID
+    pub persistent_id_opcodes: bool,
+}
+
+fn opcode_count(u: &mut Unstructured<'_>) -> arbitrary::Result<usize> {
+    u.int_in_range(0..=MAX_FUZZ_OPCODES)
+}
+
+fn mutation_rate(u: &mut Unstructured<'_>) -> arbitrary::Result<f64> {
+    Ok(u8::arbitrary(u)? as f64 / 255.0) // map 0-255 to 0.0-1.0
+}
+
+impl FuzzConfig {
+    /// split a fuzzer input into a configuration and the generator's entropy.
+    ///
+    /// returns `None` if the configuration can't be decoded.
+    pub fn decode(data: &[u8]) -> Option<(Self, &[u8])> {
+        let mut u = Unstructured::new(data);
+        let config = Self::arbitrary(&mut u).ok()?;
+        Some((config, u.take_rest()))
+    }
+
+    /// build a generator with this configuration.
+    pub fn build(&self) -> Generator {
+        Generator::new(self.version)
+            .with_opcode_range(self.min_opcodes, self.max_opcodes)
+            .with_mutation_rate(self.mutation_rate)
+            .with_mutators(self.mutators.build())
+            .with_ext_opcodes(self.ext_opcodes)
+            .with_buffer_opcodes(self.buffer_opcodes)
+            .with_persistent_id_opcodes(self.persistent_id_opcodes)
+    }
+}
+
+/// assert the structural invariants every generated pickle must satisfy.
+///
+/// the pickle must be non-empty and end with STOP. protocol 0/1 pickles must
+/// not start with PROTO, and protocol 2+ pickles longer than two bytes must.
+pub fn check_structure(pickle: &[u8], version: Version) {
+    assert!(!pickle.is_empty(), "generated pickle must not be empty");
+    assert_eq!(
+        pickle[pickle.len() - 1],
+        b'.',
+        "pickle must end with STOP opcode"
+    );
+
+    match version {
+        Version::V0 | Version::V1 => {
+            // no PROTO opcode in v0/v1
+            assert!(!pickle.starts_with(b"\x80"), "PROTO emitted in {version:?}");
+        }
+        _ => {
+            // v2+ should have PROTO opcode
+            if pickle.len() > 2 {
+                assert_eq!(pickle[0], 0x80, "missing PROTO in {version:?}");
+            }
+        }
+    }
+}
+
+/// run one protocol-only iteration with a reusable generator and buffer.
+///
+/// byte 0 picks the protocol, the rest is generation entropy. the generated
+/// pickle is left in `out` after passing [`check_structure`].
+pub fn run_all_protocols(gen: &mut Generator, out: &mut Vec<u8>, data: &[u8]) {
+    let Some((&selector, entropy)) = data.split_first() else {
+        return;
+    };
+
+    let version = version_from_byte(selector);
+    gen.set_version(version);
+
+    if gen.generate_from_arbitrary_into(entropy, out).is_ok() {
+        check_structure(out, version);
+    }
+}
+
+/// run one configured iteration and return the pickle for further checks.
+///
+/// returns `None` if the input doesn't decode to a [`FuzzConfig`] or the
+/// generator rejects the entropy. a returned pickle has already passed
+/// [`check_structure`].
+pub fn run_configured(data: &[u8]) -> Option<Vec<u8>> {
+    run_configured_with(data, ExhaustionPolicy::default())
+}
+
+/// [`run_configured`] with the [`ExhaustionPolicy`] a target picks for inputs
+/// whose generation entropy runs out.
+///
+/// the policy is the target's choice rather than part of the decoded
+/// [`FuzzConfig`], so existing corpora keep decoding to the same configuration.
+pub fn run_configured_with(data: &[u8], policy: ExhaustionPolicy) -> Option<Vec<u8>> {
+    let (config, entropy) = FuzzConfig::decode(data)?;
+
+    let pickle = config
+        .build()
+        .with_exhaustion_policy(policy)
+        .generate_from_arbitrary(entropy)
+        .ok()?;
+    check_structure(&pickle, config.version);
+    Some(pickle)
+}
+
+/// feed raw bytes to the native parser and assert it holds together.
+///
+/// besides not panicking, [`disassemble`], [`structural_fingerprint`] and
+/// [`validate`] must agree: the fingerprint fails exactly when disassembly
+/// does, a disassembly starts at offset 0 and ends at its only STOP, every
+/// argument renders, and a pickle that validates disassembles to its last
+/// byte.
+pub fn check_parser(data: &[u8]) {
+    let instructions = disassemble(data);
+    assert_eq!(
+        instructions.is_ok(),
+        structural_fingerprint(data).is_ok(),
+        "fingerprint and disassembly disagree"
+    );
+    let valid = validate(data).is_ok();
+
+    let Ok(instructions) = instructions else {
+        assert!(!valid, "validated a pickle that doesn't disassemble");
+        return;
+    };
+    let (stop, body) = instructions
+        .split_last()
+        .expect("a disassembly holds at least STOP");
+    assert_eq!(stop.name, "STOP", "disassembly ends before STOP");
+    assert!(body.iter().all(|instruction| instruction.name != "STOP"));
+    assert_eq!(instructions[0].pos, 0, "disassembly skips the first byte");
+    assert!(
+        instructions
+            .windows(2)
+            .all(|pair| pair[0].pos < pair[1].pos),
+        "offsets go backwards"
+    );
+    assert!(stop.pos < data.len());
+    for instruction in &instructions {
+        let _ = instruction.arg.to_string();
+    }
+    if valid {
+        assert_eq!(stop.pos + 1, data.len(), "validated trailing bytes");
+    }
+}
+
+/// run one configured iteration without mutators and [`check_round_trip`]
+/// the pickle.
+///
+/// the input decodes like [`run_configured`]'s, so the two share a corpus;
+/// only the mutators and mutation rate are ignored, since mutators may
+/// respell an opcode in a way the IR doesn't keep.
+pub fn run_round_trip(data: &[u8]) {
+    let Some((config, entropy)) = FuzzConfig::decode(data) else {
+        return;
+    };
+    let pickle = config
+        .build()
+        .with_mutators(Vec::new())
+        .generate_from_arbitrary(entropy);
+    if let Ok(pickle) = pickle {
+        check_round_trip(&pickle);
+    }
+}
+
+/// assert that decoding `pickle` into [`Opcode`]s and encoding them again
+/// reproduces it exactly, naming the first opcode that doesn't.
+///
+/// holds for anything the generator emits without mutators, diverse
+/// encodings, escaped stack-global names, or unsafe options, all of which
+/// spell opcodes in ways the IR normalizes away.
+pub fn check_round_trip(pickle: &[u8]) {
+    let mut pos = 0;
+    loop {
+        let (opcode, len) = Opcode::decode(&pickle[pos..])
+            .unwrap_or_else(|e| panic!("opcode at {pos} doesn't decode: {e}"));
+        let original = &pickle[pos..pos + len];
+        let encoded = opcode.encode();
+        assert!(
+            encoded == original,
+            "{} at {pos} re-encodes as b'{}' instead of b'{}'",
+            opcode.kind().name(),
+            encoded.escape_ascii(),
+            original.escape_ascii()
+        );
+        pos += len;
+        if opcode == Opcode::Stop {
+            break;
+        }
+    }
+    assert_eq!(pos, pickle.len(), "trailing bytes after STOP");
+}
+
+/// largest number of edits [`mutate_pickle`] applies to its input.
+pub const MAX_MUTATION_EDITS: usize = 4;
+
+/// most mutants [`mutate_pickle`] builds from a decoded input before it
+/// falls back to generation.
+pub const MAX_MUTATION_ATTEMPTS: usize = 32;
+
+/// most opcodes of a generated subsequence [`mutate_pickle`] splices in.
+const MAX_SPLICE_OPCODES: usize = 8;
+
+/// smallest valid pickle, returned when nothing larger fits the size limit.
+const FALLBACK_PICKLE: &[u8] = b"N.";
+
+/// characters [`mutate_pickle`] writes into unicode arguments.
+const INTERESTING_CHARS: [char; 8] = [
+    'a',
+    '\0',
+    '\n',
+    '\\',
+    '\u{e9}',
+    '\u{fffd}',
+    '\u{ff21}',
+    '\u{1d538}',
+];
+
+/// the protocol a pickle declares with its leading PROTO opcode, if any.
+pub fn declared_version(pickle: &[u8]) -> Option<Version> {
+    match pickle {
+        [0x80, protocol, ..] => Version::try_from(*protocol).ok(),
+        _ => None,
+    }
+}
+
+/// structure-aware custom mutation of a corpus pickle.
+///
+/// the input is decoded into [`Opcode`]s and gets one to
+/// [`MAX_MUTATION_EDITS`] seeded edits: an argument replaced with a nearby or
+/// boundary value of the same encoding, an opcode deleted, duplicated, or
+/// swapped with its neighbour, or a short generated subsequence spliced in
+/// (followed by a POP, with its memo keys moved past the input's). the mutant
+/// is re-encoded under the input's PROTO, with one FRAME around the body if
+/// the input had any, and kept only if it differs from the input, passes
+/// [`validate`], and fits in `max_size` bytes; up to [`MAX_MUTATION_ATTEMPTS`] mutants are tried.
+///
+/// when the input doesn't decode, or no mutant passes, the input perturbed
+/// with byte edits drives `generate_from_arbitrary` instead, for the protocol
+/// the input declares with PROTO, or one picked from `seed` when there is
+/// none. either way the output is a well-formed pickle of at most `max_size`
+/// bytes, and different seeds give different mutants of the same input.
+///
+/// when `max_size` is too small for any generated pickle the result is the
+/// two-byte `None` pickle, or an empty input if even that does not fit.
+pub fn mutate_pickle(data: &[u8], max_size: usize, seed: u64) -> Vec<u8> {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+
+    if let Ok(opcodes) = Opcode::decode_all(data) {
+        let input = DecodedPickle::new(opcodes);
+        for _ in 0..MAX_MUTATION_ATTEMPTS {
+            let mut body = input.body.clone();
+            for _ in 0..rng.random_range(1..=MAX_MUTATION_EDITS) {
+                edit_opcodes(&mut body, input.splice_version(), &mut rng);
+            }
+            let mutant = input.encode(&body);
+            if mutant != data && mutant.len() <= max_size && validate(&mutant).is_ok() {
+                return mutant;
+            }
+        }
+    }
+
+    let version = declared_version(data).unwrap_or_else(|| version_from_byte(rng.random()));
+    let entropy = perturbed_entropy(data, &mut rng);
+    let mut gen = Generator::new(version).with_buffer_size(max_size);
+    match gen.generate_from_arbitrary(&entropy) {
+        Ok(pickle) => pickle,
+        Err(_) if FALLBACK_PICKLE.len() <= max_size => FALLBACK_PICKLE.to_vec(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// a decoded pickle split into its header and the body between it and STOP.
+struct DecodedPickle {
+    proto: Option<u8>,
+    framed: bool,
+    body: Vec<Opcode>,
+}
+
+impl DecodedPickle {
+    fn new(opcodes: Vec<Opcode>) -> Self {
+        let proto = match opcodes.first() {
+            Some(Opcode::Proto(protocol)) => Some(*protocol),
+            _ => None,
+        };
+        let framed = opcodes
+            .iter()
+            .any(|opcode| matches!(opcode, Opcode::Frame(_)));
+        let body = opcodes
+            .into_iter()
+            .skip(usize::from(proto.is_some()))
+            .filter(|opcode| !matches!(opcode, Opcode::Frame(_) | Opcode::Stop))
+            .collect();
+        Self {
+            proto,
+            framed,
+            body,
+        }
+    }
+
+    /// the protocol spliced subsequences are generated for; protocol 0's
+    /// opcodes are valid in a pickle without PROTO whatever its protocol.
+    fn splice_version(&self) -> Version {
+        self.proto
+            .and_then(|protocol| Version::try_from(protocol).ok())
+            .unwrap_or(Version::V0)
+    }
+
+    /// `body` as a pickle with this one's PROTO and framing.
+    fn encode(&self, body: &[Opcode]) -> Vec<u8> {
+        let mut framed = Opcode::encode_all(body);
+        Opcode::Stop.encode_into(&mut framed);
+
+        let mut pickle = Vec::new();
+        if let Some(protocol) = self.proto {
+            Opcode::Proto(protocol).encode_into(&mut pickle);
+        }
+        if self.framed {
+            Opcode::Frame(framed.len() as u64).encode_into(&mut pickle);
+        }
+        pickle.extend_from_slice(&framed);
+        pickle
+    }
+}
+
+/// apply one random edit to `body`.
+fn edit_opcodes(body: &mut Vec<Opcode>, version: Version, rng: &mut ChaCha8Rng) {
+    let at = rng.random_range(0..=body.len());
+    match rng.random_range(0..5u8) {
+        // the first opcode with an argument from `at` on, wrapping around
+        0 => {
+            let mut order = (at..body.len()).chain(0..at);
+            order.any(|i| mutate_argument(&mut body[i], rng));
+        }
+        1 if at < body.len() => {
+            body.remove(at);
+        }
+        2 if at < body.len() => body.insert(at, body[at].clone()),
+        3 if at + 1 < body.len() => body.swap(at, at + 1),
+        _ => {
+            let segment = generated_segment(body, version, rng.random());
+            body.splice(at..at, segment);
+        }
+    }
+}
+
+/// replace `opcode`'s argument with a nearby or boundary value that keeps its
+/// encoding; false if it has no argument to replace.
+fn mutate_argument(opcode: &mut Opcode, rng: &mut ChaCha8Rng) -> bool {
+    match opcode {
+        Opcode::Int(value) | Opcode::Long(value) => {
+            *value = pick(
+                rng,
+                &[
+                    0,
+                    -1,
+                    value.wrapping_add(1),
+                    value.wrapping_sub(1),
+                    value.wrapping_neg(),
+                    i128::from(i32::MAX) + 1,
+                    i128::from(i64::MIN) - 1,
+                ],
+            )
+        }
+        Opcode::IntBool(value) => *value = !*value,
+        Opcode::BinInt(value) => {
+            *value = pick(
+                rng,
+                &[
+                    0,
+                    -1,
+                    value.wrapping_add(1),
+                    value.wrapping_sub(1),
+                    i32::MIN,
+                    i32::MAX,
+                ],
+            )
+        }
+        Opcode::BinInt1(value) | Opcode::Ext1(value) => {
+            *value = pick(
+                rng,
+                &[0, u8::MAX, value.wrapping_add(1), value.wrapping_sub(1)],
+            )
+        }
+        Opcode::BinInt2(value) | Opcode::Ext2(value) => {
+            *value = pick(
+                rng,
+                &[0, u16::MAX, value.wrapping_add(1), value.wrapping_sub(1)],
+            )
+        }
+        Opcode::Ext4(value) => *value = pick(rng, &[0, i32::MAX, value.wrapping_add(1)]),
+        Opcode::Float(value) | Opcode::BinFloat(value) => {
+            *value = pick(
+                rng,
+                &[
+                    0.0,
+                    -0.0,
+                    f64::INFINITY,
+                    f64::NEG_INFINITY,
+                    f64::NAN,
+                    f64::MAX,
+                    f64::MIN_POSITIVE,
+                    -*value,
+                    *value * 2.0,
+                ],
+            )
+        }
+        Opcode::Long1(bytes) | Opcode::ShortBinString(bytes) | Opcode::ShortBinBytes(bytes) => {
+            edit_bytes(bytes, u8::MAX.into(), rng)
+        }
+        Opcode::Long4(bytes)
+        | Opcode::String(bytes)
+        | Opcode::BinString(bytes)
+        | Opcode::BinBytes(bytes)
+        | Opcode::BinBytes8(bytes)
+        | Opcode::ByteArray8(bytes) => edit_bytes(bytes, usize::MAX, rng),
+        Opcode::ShortBinUnicode(text) => edit_text(text, u8::MAX.into(), rng),
+        Opcode::Unicode(text) | Opcode::BinUnicode(text) | Opcode::BinUnicode8(text) => {
+            edit_text(text, usize::MAX, rng)
+        }
+        _ => return false,
+    }
+    true
+}
+
+/// one of `choices`, picked by `rng`.
+fn pick<T: Copy>(rng: &mut ChaCha8Rng, choices: &[T]) -> T {
+    choices[rng.random_range(0..choices.len())]
+}
+
+/// flip a bit of, insert into, or delete from `bytes`, keeping it at most
+/// `max_len` long.
+fn edit_bytes(bytes: &mut Vec<u8>, max_len: usize, rng: &mut ChaCha8Rng) {
+    match rng.random_range(0..3u8) {
+        0 if !bytes.is_empty() => {
+            let at = rng.random_range(0..bytes.len());
+            bytes[at] ^= 1 << rng.random_range(0..8);
+        }
+        1 if !bytes.is_empty() => {
+            bytes.remove(rng.random_range(0..bytes.len()));
+        }
+        _ if bytes.len() < max_len => {
+            let at = rng.random_range(0..=bytes.len());
+            bytes.insert(at, rng.random());
+        }
+        _ => bytes.truncate(max_len / 2),
+    }
+}
+
+/// replace, insert, or delete a character of `text`, keeping its UTF-8
+/// encoding at most `max_len` bytes long.
+fn edit_text(text: &mut String, max_len: usize, rng: &mut ChaCha8Rng) {
+    let mut chars: Vec<char> = text.chars().collect();
+    let c = pick(rng, &INTERESTING_CHARS);
+    match rng.random_range(0..3u8) {
+        0 if !chars.is_empty() => {
+            let at = rng.random_range(0..chars.len());
+            chars[at] = c;
+        }
+        1 if !chars.is_empty() => {
+            chars.remove(rng.random_range(0..chars.len()));
+        }
+        _ => chars.insert(rng.random_range(0..=chars.len()), c),
+    }
+    let edited: String = chars.into_iter().collect();
+    if edited.len() <= max_len {
+        *text = edited;
+    }
+}
+
+/// the body of a short pickle generated for `version` from `seed`, followed
+/// by a POP so it leaves the stack as it found it, with its memo keys moved
+/// past every key `host` stores.
+fn generated_segment(host: &[Opcode], version: Version, seed: u64) -> Vec<Opcode> {
+    let Ok(pickle) = Generator::new(version)
+        .with_seed(seed)
+        .with_opcode_range(1, MAX_SPLICE_OPCODES)
+        .generate()
+    else {
+        return Vec::new();
+    };
+    let Ok(opcodes) = Opcode::decode_all(&pickle) else {
+        return Vec::new();
+    };
+
+    // keys past every explicit key of the host, and past every key its
+    // MEMOIZEs could take
+    let base = host
+        .iter()
+        .filter_map(|opcode| match *opcode {
+            Opcode::Put(key) | Opcode::LongBinPut(key) => Some(key + 1),
+            Opcode::BinPut(key) => Some(u32::from(key) + 1),
+            Opcode::Memoize => Some(1),
+            _ => None,
+        })
+        .fold(0u32, u32::saturating_add);
+
+    let mut stored = 0;
+    let mut segment = Vec::new();
+    for opcode in DecodedPickle::new(opcodes).body {
+        let moved = |key: u32| base.saturating_add(key);
+        segment.push(match opcode {
+            Opcode::Memoize => {
+                stored += 1;
+                memo_put(version, moved(stored - 1))
+            }
+            Opcode::Put(key) | Opcode::LongBinPut(key) => {
+                stored += 1;
+                memo_put(version, moved(key))
+            }
+            Opcode::BinPut(key) => {
+                stored += 1;
+                memo_put(version, moved(key.into()))
+            }
+            Opcode::Get(key) | Opcode::LongBinGet(key) => memo_get(version, moved(key)),
+            Opcode::BinGet(key) => memo_get(version, moved(key.into())),
+            opcode => opcode,
+        });
+    }
+    segment.push(Opcode::Pop);
+    segment
+}
+
+/// the opcode storing the stack top under `key` in `version`.
+fn memo_put(version: Version, key: u32) -> Opcode {
+    match version {
+        Version::V0 => Opcode::Put(key),
+        _ => Opcode::LongBinPut(key),
+    }
+}
+
+/// the opcode pushing memo `key` in `version`.
+fn memo_get(version: Version, key: u32) -> Opcode {
+    match version {
+        Version::V0 => Opcode::Get(key),
+        _ => Opcode::LongBinGet(key),
+    }
+}
+
+/// `data` with one to [`MAX_MUTATION_EDITS`] byte edits (overwrite, insert,
+/// delete, append), as generation entropy.
+fn perturbed_entropy(data: &[u8], rng: &mut ChaCha8Rng) -> Vec<u8> {
+    let mut entropy = data.to_vec();
+    for _ in 0..rng.random_range(1..=MAX_MUTATION_EDITS) {
+        let edit = if entropy.is_empty() {
+            3
+        } else {
+            rng.random_range(0..4u8)
+        };
+        match edit {
+            0 => {
+                let idx = rng.random_range(0..entropy.len());
+                entropy[idx] = rng.random();
+            }
+            1 => {
+                let idx = rng.random_range(0..=entropy.len());
+                entropy.insert(idx, rng.random());
+            }
+            2 => {
+                let idx = rng.random_range(0..entropy.len());
+                entropy.remove(idx);
+            }
+            _ => {
+                let len = rng.random_range(1..=16);
+                entropy.extend((0..len).map(|_| rng.random::<u8>()));
+            }
+        }
+    }
+    entropy
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opcodes::OpcodeKind;
+
+    #[test]
+    fn version_selector_wraps_modulo_six() {
+        assert_eq!(version_from_byte(0), Version::V0);
+        assert_eq!(version_from_byte(5), Version::V5);
+        assert_eq!(version_from_byte(6), Version::V0);
+        assert_eq!(version_from_byte(255), Version::V3);
+    }
+
+    #[test]
+    fn any_input_decodes_to_a_valid_config() {
+        for input in [&[][..], &[0xff; 64][..], &[0x20; 3][..]] {
+            let (config, _) = FuzzConfig::decode(input).unwrap();
+            assert!(config.min_opcodes <= MAX_FUZZ_OPCODES);
+            assert!(config.max_opcodes <= MAX_FUZZ_OPCODES);
+            assert!((0.0..=1.0).contains(&config.mutation_rate));
+        }
+
+        let input = [0u8; 64];
+        let (config, entropy) = FuzzConfig::decode(&input).unwrap();
+        assert!(entropy.len() < input.len());
+        assert!(config.build().mutators.is_empty());
+    }
+
+    #[test]
+    fn harness_iterations_produce_well_formed_pickles() {
+        let mut gen = Generator::new(Version::default());
+        let mut out = Vec::new();
+        for selector in 0..6u8 {
+            let mut input = vec![selector];
+            input.extend((0..64u8).map(|b| b.wrapping_mul(37)));
+
+            run_all_protocols(&mut gen, &mut out, &input);
+            assert_eq!(out.last(), Some(&b'.'));
+
+            let mut configured = input.clone();
+            configured.extend_from_slice(&input);
+            let pickle = run_configured(&configured).expect("configured input generates");
+            assert_eq!(pickle.last(), Some(&b'.'));
+        }
+    }
+
+    #[test]
+    fn configured_targets_choose_an_exhaustion_policy() {
+        let input = [0x11u8; 32];
+        assert_eq!(
+            run_configured_with(&input, ExhaustionPolicy::Fallback),
+            run_configured(&input)
+        );
+        for policy in [ExhaustionPolicy::Stop, ExhaustionPolicy::SwitchToPrng] {
+            let pickle = run_configured_with(&input, policy).expect("configured input generates");
+            assert_eq!(run_configured_with(&input, policy), Some(pickle));
+        }
+    }
+
+    #[test]
+    fn parser_check_holds_for_pickles_and_their_wreckage() {
+        for version in Version::all() {
+            let pickle = Generator::new(version).with_seed(9).generate().unwrap();
+            for end in 0..=pickle.len() {
+                check_parser(&pickle[..end]);
+            }
+            let mut trailing = pickle.clone();
+            trailing.extend_from_slice(b"N.");
+            check_parser(&trailing);
+            for (i, byte) in pickle.iter().enumerate() {
+                let mut flipped = pickle.clone();
+                flipped[i] = byte ^ 0x5a;
+                check_parser(&flipped);
+            }
+        }
+        check_parser(b"");
+        check_parser(b"\xff");
+    }
+
+    #[test]
+    fn generated_pickles_round_trip_through_the_ir() {
+        for seed in 0..64u8 {
+            let input: Vec<u8> = (0..256u32)
+                .map(|i| (i as u8).wrapping_mul(seed | 1) ^ seed)
+                .collect();
+            run_round_trip(&input);
+        }
+        for version in Version::all() {
+            let pickle = Generator::new(version)
+                .with_seed(2)
+                .with_interesting_patterns(true)
+                .with_ext_opcodes(true)
+                .with_buffer_opcodes(true)
+                .with_persistent_id_opcodes(true)
+                .generate()
+                .unwrap();
+            check_round_trip(&pickle);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "INT at 0 re-encodes as b'I7\\n' instead of b'I007\\n'")]
+    fn round_trip_check_names_a_respelled_opcode() {
+        check_round_trip(b"I007\n.");
+    }
+
+    #[test]
+    fn mutate_pickle_keeps_the_declared_protocol_and_size_limit() {
+        let corpus = Generator::new(Version::V4).with_seed(3).generate().unwrap();
+
+        let mutants: Vec<Vec<u8>> = (0..16)
+            .map(|seed| mutate_pickle(&corpus, 512, seed))
+            .collect();
+        for mutant in &mutants {
+            assert!(mutant.len() <= 512);
+            check_structure(mutant, Version::V4);
+            assert_eq!(declared_version(mutant), Some(Version::V4));
+        }
+        assert!(mutants.iter().any(|mutant| mutant != &mutants[0]));
+        assert_eq!(mutate_pickle(&corpus, 512, 7), mutants[7]);
+    }
+
+    #[test]
+    fn mutate_pickle_edits_the_decoded_opcodes() {
+        for version in Version::all() {
+            let corpus = Generator::new(version).with_seed(5).generate().unwrap();
+            let kinds = |pickle: &[u8]| -> Vec<OpcodeKind> {
+                let mut kinds: Vec<OpcodeKind> = Opcode::decode_all(pickle)
+                    .unwrap()
+                    .iter()
+                    .map(Opcode::kind)
+                    .collect();
+                kinds.sort_by_key(|kind| kind.as_u8());
+                kinds
+            };
+            let original = kinds(&corpus);
+
+            for seed in 0..32 {
+                let mutant = mutate_pickle(&corpus, 1 << 16, seed);
+                validate(&mutant).unwrap();
+                assert_ne!(mutant, corpus);
+                assert_eq!(declared_version(&mutant), declared_version(&corpus));
+                // at most one opcode lost per edit; everything else is kept
+                let mut mutated = kinds(&mutant);
+                let lost = original
+                    .iter()
+                    .filter(|kind| match mutated.iter().position(|k| k == *kind) {
+                        Some(i) => {
+                            mutated.remove(i);
+                            false
+                        }
+                        None => true,
+                    })
+                    .count();
+                assert!(lost <= MAX_MUTATION_EDITS, "{version:?} seed {seed}");
+            }
+        }
+    }
+
+    #[test]
+    fn spliced_segments_leave_the_stack_and_memo_consistent() {
+        for version in Version::all() {
+            let corpus = Generator::new(version).with_seed(8).generate().unwrap();
+            let input = DecodedPickle::new(Opcode::decode_all(&corpus).unwrap());
+            assert_eq!(input.encode(&input.body), corpus);
+
+            for seed in 0..16 {
+                let segment = generated_segment(&input.body, input.splice_version(), seed);
+                assert_eq!(segment.last(), Some(&Opcode::Pop));
+                let mut body = input.body.clone();
+                body.extend(segment);
+                validate(&input.encode(&body)).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn mutate_pickle_falls_back_when_nothing_fits() {
+        assert_eq!(mutate_pickle(b"", 2, 0), FALLBACK_PICKLE);
+        // decodes, but no mutant fits
+        assert_eq!(mutate_pickle(b"N.", 2, 0), FALLBACK_PICKLE);
+        assert!(mutate_pickle(b"\x80\x05", 1, 0).is_empty());
+        assert_eq!(mutate_pickle(b"", 64, 1).last(), Some(&b'.'));
+    }
+}
@@ -0,0 +1,378 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! where every byte of a generated pickle came from.
+//!
+//! with annotations on, a run keeps a map from byte offsets to the opcode
+//! there, how many MARKs were open before it, the step of the generation loop
+//! that emitted it (the header, a randomly chosen opcode, a named pattern,
+//! cleanup, ...), and the mutators that changed that step. `dis` prints the
+//! map next to the opcodes, and a harness can blame a failing byte on its
+//! emitter: "0x1f3: BININT1 at depth 2 from opcode, mutated by bitflip".
+//!
+//! during generation only the start of each step and the mutators that fire
+//! are recorded; the opcodes are decoded from the output once it is complete,
+//! or chunk by chunk as `generate_to` streams it out. a mutation that breaks
+//! an opcode's encoding leaves the rest of its step as one undecodable
+//! annotation, and decoding picks up again at the next step.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::Generator;
+use crate::disasm;
+use crate::opcodes::OpcodeKind;
+
+/// what emitted one opcode of a pickle, and how it was changed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Annotation {
+    /// offset of the opcode's first byte in the pickle
+    pub offset: usize,
+    /// bytes the opcode and its argument take
+    pub len: usize,
+    /// the opcode's `pickletools` name, or `None` for bytes that don't decode
+    pub opcode: Option<String>,
+    /// MARKs open before the opcode
+    pub depth: usize,
+    /// the generation step that emitted it: `proto`, `frame`, `opcode`, a
+    /// pattern such as `torch tensor`, `unsafe frame`, `cleanup`, `stop`,
+    /// `canonical`, or `diverse encodings`
+    pub origin: String,
+    /// mutators that changed the step, in the order they fired
+    pub mutations: Vec<String>,
+}
+
+impl fmt::Display for Annotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#x}: ", self.offset)?;
+        match &self.opcode {
+            Some(opcode) => f.write_str(opcode)?,
+            None => write!(f, "{} undecodable bytes", self.len)?,
+        }
+        write!(f, " at depth {} from {}", self.depth, self.origin)?;
+        if !self.mutations.is_empty() {
+            write!(f, ", mutated by {}", self.mutations.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// one step of the generation loop: where its output starts, and the
+/// mutators that fired while it ran.
+#[derive(Debug)]
+pub(super) struct Step {
+    start: usize,
+    origin: &'static str,
+    mutations: Vec<String>,
+}
+
+/// the stack of the annotated output so far, as far as MARK depth needs
+/// it: its length and where its MARKs are.
+///
+/// it follows the opcodes' stack effects the way `pickletools.dis` does, but
+/// never fails, so mutated output just gets a best-effort depth.
+#[derive(Debug, Default)]
+pub(super) struct MarkDepth {
+    len: usize,
+    marks: Vec<usize>,
+}
+
+impl MarkDepth {
+    fn apply(&mut self, opcode: OpcodeKind) {
+        let effect = opcode.info().stack;
+        let mut pops = effect.pops;
+        // POP of a MARK pops it like POP_MARK does
+        let top_is_mark = self.marks.last().is_some_and(|&mark| mark + 1 == self.len);
+        if effect.below_mark.is_some() || (opcode == OpcodeKind::Pop && top_is_mark) {
+            if let Some(mark) = self.marks.pop() {
+                self.len = mark;
+            }
+            pops = effect.below_mark.unwrap_or(0);
+        }
+        self.len = self.len.saturating_sub(pops);
+        while self.marks.last().is_some_and(|&mark| mark >= self.len) {
+            self.marks.pop();
+        }
+        if effect.pushes_mark {
+            self.marks.push(self.len);
+            self.len += 1;
+        }
+        self.len += effect.pushes;
+    }
+}
+
+impl Generator {
+    /// keep an offset→annotation map of each pickle (off by default).
+    ///
+    /// after a run, [`annotations`](Self::annotations) lists every opcode of
+    /// the pickle with its offset, MARK depth, the generation step that
+    /// emitted it, and the mutators that changed it. scripted pickles
+    /// (`begin`) aren't annotated. the output is unchanged either way.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pickle_fuzzer::{Generator, Version};
+    ///
+    /// let mut gen = Generator::new(Version::V2).with_seed(3).with_annotations(true);
+    /// let pickle = gen.generate().unwrap();
+    /// let last = gen.annotation_at(pickle.len() - 1).unwrap();
+    /// assert_eq!(last.opcode.as_deref(), Some("STOP"));
+    /// assert_eq!(last.origin, "stop");
+    /// ```
+    pub fn with_annotations(mut self, enabled: bool) -> Self {
+        self.annotate = enabled;
+        self
+    }
+
+    /// the annotations of the most recent run, in offset order; empty unless
+    /// annotations are on.
+    ///
+    /// they cover every byte of the pickle exactly once.
+    pub fn annotations(&self) -> &[Annotation] {
+        &self.annotations
+    }
+
+    /// the annotation of the opcode that holds byte `offset`, if any.
+    pub fn annotation_at(&self, offset: usize) -> Option<&Annotation> {
+        let index = self
+            .annotations
+            .partition_point(|annotation| annotation.offset + annotation.len <= offset);
+        self.annotations
+            .get(index)
+            .filter(|annotation| annotation.offset <= offset)
+    }
+
+    /// start a new generation step named `origin` at the current end of the
+    /// output, handing the mutators that fired since to the previous one.
+    pub(super) fn begin_step(&mut self, origin: &'static str) {
+        if !self.annotate {
+            return;
+        }
+        self.close_step();
+        self.steps.push(Step {
+            start: self.output_len(),
+            origin,
+            mutations: Vec::new(),
+        });
+    }
+
+    /// move the steps starting at the first offset of a pair in `moves` to
+    /// its second, after a mutator resized opcodes they start behind.
+    pub(super) fn move_steps(&mut self, moves: &[(usize, usize)]) {
+        let Some(&(first, _)) = moves.first() else {
+            return;
+        };
+        for step in self.steps.iter_mut().rev() {
+            if step.start < first {
+                break;
+            }
+            if let Some(&(_, to)) = moves.iter().find(|&&(from, _)| from == step.start) {
+                step.start = to;
+            }
+        }
+    }
+
+    /// record that `mutator` changed the current step.
+    pub(super) fn note_mutation(&self, mutator: &str) {
+        tracing::trace!(mutator, offset = self.output_len(), "mutation");
+        self.count_mutation(mutator);
+        if self.annotate {
+            self.fired_mutators.borrow_mut().push(mutator.to_string());
+        }
+    }
+
+    fn close_step(&mut self) {
+        let fired = self.fired_mutators.take();
+        if let Some(step) = self.steps.last_mut() {
+            step.mutations.extend(fired);
+        }
+    }
+
+    /// annotate the output past what is already annotated. called before
+    /// buffered output is streamed out and once the pickle is complete.
+    pub(super) fn annotate_output(&mut self) {
+        if !self.annotate {
+            return;
+        }
+        self.close_step();
+
+        let end = self.output_len();
+        let (mut pos, mut stack) = std::mem::take(&mut self.annotated);
+        while pos < end {
+            let step = self.steps.partition_point(|step| step.start <= pos);
+            let Some(current) = step.checked_sub(1).map(|index| &self.steps[index]) else {
+                break;
+            };
+            let step_end = self.steps.get(step).map_or(end, |next| next.start.min(end));
+            let data = &self.output[pos - self.streamed_len..step_end - self.streamed_len];
+
+            let (opcode, len) = match disasm::read_opcode(data) {
+                Ok((_, len)) => (OpcodeKind::from_u8(data[0]), len),
+                Err(_) => (None, data.len()),
+            };
+            self.annotations.push(Annotation {
+                offset: pos,
+                len,
+                opcode: opcode.map(|opcode| opcode.name().to_string()),
+                depth: stack.marks.len(),
+                origin: current.origin.to_string(),
+                mutations: current.mutations.clone(),
+            });
+            if let Some(opcode) = opcode {
+                stack.apply(opcode);
+            }
+            pos += len;
+        }
+        self.annotated = (end, stack);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::disasm::disassemble;
+    use crate::mutators::{BitFlipMutator, HomoglyphMutator};
+    use crate::opcodes::OpcodeKind;
+    use crate::{Annotation, Generator, Version};
+
+    /// assert `annotations` tile `pickle` from the first byte to the last.
+    fn assert_covers(annotations: &[Annotation], pickle: &[u8]) {
+        let mut offset = 0;
+        for annotation in annotations {
+            assert_eq!(annotation.offset, offset, "{annotation}");
+            assert!(annotation.len > 0, "{annotation}");
+            offset += annotation.len;
+        }
+        assert_eq!(offset, pickle.len());
+    }
+
+    #[test]
+    fn annotations_name_every_opcode_and_its_step() {
+        let mut patterns = false;
+        for version in Version::all() {
+            for seed in 0..8 {
+                let mut gen = Generator::new(version)
+                    .with_seed(seed)
+                    .with_interesting_patterns(true)
+                    .with_annotations(true);
+                let pickle = gen.generate().unwrap();
+                let annotations = gen.annotations();
+                assert_covers(annotations, &pickle);
+
+                let instructions = disassemble(&pickle).unwrap();
+                assert_eq!(instructions.len(), annotations.len());
+                for (instruction, annotation) in instructions.iter().zip(annotations) {
+                    assert_eq!(instruction.pos, annotation.offset);
+                    assert_eq!(annotation.opcode.as_deref(), Some(instruction.name));
+                    assert!(annotation.mutations.is_empty());
+                }
+                if version >= Version::V2 {
+                    assert_eq!(annotations[0].origin, "proto");
+                }
+                assert_eq!(annotations.last().unwrap().origin, "stop");
+                assert_eq!(annotations.last().unwrap().depth, 0);
+                patterns |= annotations.iter().any(|a| {
+                    !["proto", "frame", "opcode", "cleanup", "stop"].contains(&&*a.origin)
+                });
+            }
+        }
+        assert!(patterns);
+    }
+
+    #[test]
+    fn annotations_blame_mutators() {
+        let mut blamed = false;
+        for seed in 0..8 {
+            let mut gen = Generator::new(Version::V3)
+                .with_seed(seed)
+                .with_mutators(vec![Box::new(BitFlipMutator)])
+                .with_mutation_rate(0.5)
+                .with_unsafe_mutations(true)
+                .with_annotations(true);
+            let pickle = gen.generate().unwrap();
+            assert_covers(gen.annotations(), &pickle);
+            for annotation in gen.annotations() {
+                if annotation.mutations.iter().any(|m| m == "bitflip") {
+                    assert_eq!(annotation.origin, "opcode");
+                    blamed = true;
+                }
+            }
+        }
+        assert!(blamed);
+    }
+
+    #[test]
+    fn annotations_follow_respelled_stack_global_operands() {
+        let mut blamed = false;
+        for seed in 0..8 {
+            let mut gen = Generator::new(Version::V4)
+                .with_seed(seed)
+                .with_mutators(vec![Box::new(HomoglyphMutator::new(true))])
+                .with_mutation_rate(1.0)
+                .with_unsafe_mutations(true)
+                .with_opcode_filter(|_, opcode| {
+                    use OpcodeKind::*;
+                    matches!(opcode, ShortBinUnicode | Memoize | StackGlobal | Pop)
+                })
+                .with_annotations(true);
+            let pickle = gen.generate().unwrap();
+            let annotations = gen.annotations();
+            assert_covers(annotations, &pickle);
+
+            let instructions = disassemble(&pickle).unwrap();
+            assert_eq!(instructions.len(), annotations.len());
+            for (instruction, annotation) in instructions.iter().zip(annotations) {
+                assert_eq!(annotation.opcode.as_deref(), Some(instruction.name));
+                if annotation.mutations.iter().any(|m| m == "homoglyph") {
+                    assert_eq!(instruction.name, "STACK_GLOBAL");
+                    blamed = true;
+                }
+            }
+        }
+        assert!(blamed);
+    }
+
+    #[test]
+    fn streamed_pickles_get_the_same_annotations() {
+        let mut gen = Generator::new(Version::V2)
+            .with_seed(11)
+            .with_opcode_range(20_000, 20_000)
+            .with_max_stack_depth(16)
+            .with_annotations(true);
+        let pickle = gen.generate().unwrap();
+        let annotations = gen.annotations().to_vec();
+
+        let mut streamed = Vec::new();
+        gen.generate_to(&mut streamed).unwrap();
+        assert_eq!(streamed, pickle);
+        assert_eq!(gen.annotations(), annotations);
+    }
+
+    #[test]
+    fn annotations_leave_output_unchanged() {
+        let plain = Generator::new(Version::V4).with_seed(5).generate().unwrap();
+        let mut gen = Generator::new(Version::V4)
+            .with_seed(5)
+            .with_annotations(true);
+        assert_eq!(gen.generate().unwrap(), plain);
+
+        let mut gen = Generator::new(Version::V4).with_seed(5);
+        gen.generate().unwrap();
+        assert!(gen.annotations().is_empty());
+        assert!(gen.annotation_at(0).is_none());
+    }
+}
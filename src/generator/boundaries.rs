@@ -0,0 +1,172 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! integer encoding-boundary targeting (with_integer_boundaries).
+//!
+//! every binary integer opcode has a value range, and the unpickler switches
+//! readers at its edges: BININT1 ends at 255, BININT2 at 65535, BININT at
+//! ±2^31, and LONG1/LONG4 accept any length, including non-minimal encodings
+//! padded with sign bytes. these helpers pick values (and LONG encodings) at
+//! those edges instead of a uniformly random i32.
+
+use super::emission::{encode_long_arg, encode_long_minimal};
+use super::source::{EntropySource, GenerationSource};
+use crate::opcodes::OpcodeKind;
+
+/// values at or next to the edges of BININT1's unsigned byte.
+const BININT1_BOUNDARIES: [u8; 6] = [0, 1, 0x7f, 0x80, 0xfe, 0xff];
+
+/// values just above BININT1 and at the edges of BININT2's unsigned u16.
+const BININT2_BOUNDARIES: [u16; 6] = [0x100, 0x101, 0x7fff, 0x8000, 0xfffe, 0xffff];
+
+/// values just above BININT2 and at the edges of BININT's signed i32.
+const BININT_BOUNDARIES: [i32; 7] = [
+    0x1_0000,
+    -1,
+    -0x1_0000,
+    i32::MIN,
+    i32::MIN + 1,
+    i32::MAX - 1,
+    i32::MAX,
+];
+
+/// edges of the smaller encodings and of i32/i64/u64, for the unbounded
+/// INT/LONG/LONG1/LONG4 opcodes.
+const LONG_BOUNDARIES: [i128; 21] = [
+    0,
+    -1,
+    0x7f,
+    0x80,
+    -0x80,
+    -0x81,
+    0xff,
+    0x100,
+    0x7fff,
+    0x8000,
+    0xffff,
+    0x1_0000,
+    i32::MAX as i128,
+    i32::MAX as i128 + 1,
+    i32::MIN as i128,
+    i32::MIN as i128 - 1,
+    i64::MAX as i128,
+    i64::MAX as i128 + 1,
+    i64::MIN as i128,
+    i64::MIN as i128 - 1,
+    u64::MAX as i128 + 1,
+];
+
+/// largest number of redundant sign bytes a non-minimal LONG1/LONG4 carries.
+const MAX_LONG_PADDING: usize = 4;
+
+/// `value` as LONG1/LONG4 value bytes, either minimal or padded with sign
+/// bytes that every unpickler must ignore.
+fn encode_long_bytes(value: i128, source: &mut GenerationSource) -> Vec<u8> {
+    let mut bytes = encode_long_minimal(value);
+    if source.gen_bool() {
+        let sign = if value < 0 { 0xff } else { 0x00 };
+        let padding = source.gen_range(1, MAX_LONG_PADDING + 1);
+        bytes.resize(bytes.len() + padding, sign);
+    }
+    bytes
+}
+
+/// argument bytes for integer `opcode` holding a boundary value.
+///
+/// returns `None` for opcodes that aren't integer opcodes.
+pub(super) fn boundary_int_arg(
+    opcode: OpcodeKind,
+    source: &mut GenerationSource,
+) -> Option<Vec<u8>> {
+    let arg = match opcode {
+        OpcodeKind::BinInt1 => {
+            vec![BININT1_BOUNDARIES[source.choose_index(BININT1_BOUNDARIES.len())]]
+        }
+        OpcodeKind::BinInt2 => BININT2_BOUNDARIES[source.choose_index(BININT2_BOUNDARIES.len())]
+            .to_le_bytes()
+            .to_vec(),
+        OpcodeKind::BinInt => BININT_BOUNDARIES[source.choose_index(BININT_BOUNDARIES.len())]
+            .to_le_bytes()
+            .to_vec(),
+        OpcodeKind::Int => {
+            let value = LONG_BOUNDARIES[source.choose_index(LONG_BOUNDARIES.len())];
+            format!("{value}\n").into_bytes()
+        }
+        OpcodeKind::Long => {
+            let value = LONG_BOUNDARIES[source.choose_index(LONG_BOUNDARIES.len())];
+            encode_long_arg(opcode, value, &[])
+        }
+        OpcodeKind::Long1 | OpcodeKind::Long4 => {
+            let value = LONG_BOUNDARIES[source.choose_index(LONG_BOUNDARIES.len())];
+            encode_long_arg(opcode, value, &encode_long_bytes(value, source))
+        }
+        _ => return None,
+    };
+    Some(arg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    #[test]
+    fn boundary_args_stay_in_each_opcodes_range() {
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        let mut source = GenerationSource::Rand(&mut rng);
+        let mut padded = false;
+
+        for _ in 0..500 {
+            assert_eq!(
+                boundary_int_arg(OpcodeKind::BinInt1, &mut source)
+                    .unwrap()
+                    .len(),
+                1
+            );
+            let binint2 = boundary_int_arg(OpcodeKind::BinInt2, &mut source).unwrap();
+            assert!(u16::from_le_bytes([binint2[0], binint2[1]]) > 0xff);
+
+            let int = boundary_int_arg(OpcodeKind::Int, &mut source).unwrap();
+            assert_eq!(int.last(), Some(&b'\n'));
+
+            let long1 = boundary_int_arg(OpcodeKind::Long1, &mut source).unwrap();
+            assert_eq!(long1[0] as usize, long1.len() - 1);
+            let value = &long1[1..];
+            padded |= value != encode_long_minimal(decode(value));
+
+            let long4 = boundary_int_arg(OpcodeKind::Long4, &mut source).unwrap();
+            let size = u32::from_le_bytes([long4[0], long4[1], long4[2], long4[3]]) as usize;
+            assert_eq!(size, long4.len() - 4);
+        }
+        assert!(padded, "some LONG1 values should be non-minimal");
+        assert_eq!(boundary_int_arg(OpcodeKind::Float, &mut source), None);
+    }
+
+    /// two's complement little-endian decode, like pickle.decode_long.
+    fn decode(bytes: &[u8]) -> i128 {
+        let mut value = 0i128;
+        for (i, &b) in bytes.iter().take(16).enumerate() {
+            value |= (b as i128) << (8 * i);
+        }
+        if let Some(&last) = bytes.last() {
+            if last & 0x80 != 0 && bytes.len() < 16 {
+                value -= 1i128 << (8 * bytes.len());
+            }
+        }
+        value
+    }
+}
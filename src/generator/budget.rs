@@ -0,0 +1,134 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! a wall-clock budget for one generation run (with_time_budget).
+//!
+//! pathological configurations, like huge opcode counts with heavy mutators
+//! on top, can make a single pickle take very long. with a budget set, the
+//! generation loop checks the clock before every opcode and gives up with a
+//! [`TimeBudgetExceeded`] error once the budget is spent, so a batch caller
+//! can tell a timeout from other failures and retry with another seed.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use color_eyre::Result;
+
+use super::Generator;
+
+/// the error a run returns when it outlasts [`Generator::with_time_budget`].
+///
+/// find it in a generation error with
+/// `report.downcast_ref::<TimeBudgetExceeded>()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeBudgetExceeded {
+    /// the budget the run exceeded
+    pub budget: Duration,
+}
+
+impl fmt::Display for TimeBudgetExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "generation exceeded its time budget of {:?}",
+            self.budget
+        )
+    }
+}
+
+impl std::error::Error for TimeBudgetExceeded {}
+
+impl Generator {
+    /// give every generation run at most `budget` of wall-clock time.
+    ///
+    /// a run that is still choosing opcodes when the budget runs out fails
+    /// with a [`TimeBudgetExceeded`] error; cleanup and STOP are not timed, so
+    /// a run can overshoot by the time they take. a streaming run may already
+    /// have written part of the pickle when it gives up. the clock is only
+    /// read with a budget set, which matters on targets without one, like
+    /// wasm32-unknown-unknown.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use pickle_fuzzer::{Generator, TimeBudgetExceeded, Version};
+    ///
+    /// let mut gen = Generator::new(Version::V4)
+    ///     .with_seed(1)
+    ///     .with_time_budget(Duration::ZERO);
+    /// let error = gen.generate().unwrap_err();
+    /// assert!(error.downcast_ref::<TimeBudgetExceeded>().is_some());
+    /// ```
+    pub fn with_time_budget(mut self, budget: Duration) -> Self {
+        self.time_budget = Some(budget);
+        self
+    }
+
+    /// start the clock of the current run, if it has a budget.
+    pub(super) fn start_time_budget(&mut self) {
+        self.deadline = self.time_budget.map(|budget| (Instant::now(), budget));
+    }
+
+    /// fail once the current run has used up its time budget.
+    pub(super) fn check_time_budget(&self) -> Result<()> {
+        match self.deadline {
+            Some((started, budget)) if started.elapsed() >= budget => {
+                Err(TimeBudgetExceeded { budget }.into())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::disasm::validate;
+    use crate::Version;
+
+    #[test]
+    fn runs_within_the_budget_are_unchanged() {
+        for version in Version::all() {
+            let plain = Generator::new(version).with_seed(7).generate().unwrap();
+            let budgeted = Generator::new(version)
+                .with_seed(7)
+                .with_time_budget(Duration::from_secs(60))
+                .generate()
+                .unwrap();
+            assert_eq!(plain, budgeted, "protocol {version}");
+        }
+    }
+
+    #[test]
+    fn an_exhausted_budget_fails_the_run() {
+        let mut generator = Generator::new(Version::V2)
+            .with_seed(3)
+            .with_time_budget(Duration::ZERO);
+        let error = generator.generate().unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<TimeBudgetExceeded>(),
+            Some(&TimeBudgetExceeded {
+                budget: Duration::ZERO
+            })
+        );
+        // the generator is usable again once the budget allows a run
+        generator.time_budget = None;
+        validate(&generator.generate().unwrap()).unwrap();
+    }
+}
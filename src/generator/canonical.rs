@@ -0,0 +1,1971 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! canonical pickler mode (`with_canonical`) and its diverse-encoding
+//! counterpart (`with_diverse_encodings`).
+//!
+//! the normal generator picks opcodes and keeps track of whatever object they
+//! happen to build. canonical mode works the other way round: it builds a
+//! random python object first, then writes exactly the bytes CPython's
+//! `pickle.dumps(obj, protocol)` (the C pickler, as of 3.11) produces for it.
+//!
+//! # Pickler Rules
+//!
+//! - every str, bytes, container, global, and reduced object is memoized the
+//!   first time it is written, and written again as a memo GET. like in
+//!   CPython, each interned module or attribute name, empty or one-character
+//!   str or bytes value, and global is one object wherever it appears
+//! - integers take the shortest opcode: BININT1, BININT2, or BININT, then
+//!   LONG1/LONG4 (protocol 2+) or the INT and LONG text forms
+//! - lists, dicts, and sets are filled in batches of at most 1000 items. a
+//!   single list or dict item uses APPEND/SETITEM, and a dict or set whose size
+//!   is a multiple of 1000 ends with an empty batch, as in the C pickler
+//! - protocol 4+ output is framed: a frame is closed before the first object
+//!   that starts once it holds 64 KiB, and frames under 4 bytes go without a
+//!   FRAME opcode
+//! - bytes (below protocol 3), sets and frozensets (below protocol 4), and
+//!   bytearrays (below protocol 5) are written as their `__reduce__` calls, and
+//!   globals below protocol 3 are renamed through `_compat_pickle`, as
+//!   `fix_imports=True` does
+//!
+//! # Diverse Encodings
+//!
+//! with diverse encodings the same pickler picks a random encoding wherever
+//! several load to the same value: every int opcode that holds the value, any
+//! str or bytes opcode of the protocol, any PUT or GET variant (plus redundant
+//! PUTs), TUPLE instead of TUPLE1-3, containers built inline or filled in
+//! batches of random size, OBJ or NEWOBJ_EX for instances, and small frames or
+//! no framing. the canonical encoding is always the first option, so both modes
+//! share one code path.
+//!
+//! # Objects
+//!
+//! the objects are None, bools, ints, floats, str, bytes, bytearrays, lists,
+//! tuples, dicts, sets, frozensets, stdlib globals, plain class instances (which
+//! `object.__reduce_ex__` turns into NEWOBJ, or `copyreg._reconstructor` below
+//! protocol 2, plus a BUILD of their `__dict__`), and objects whose
+//! `__reduce__` returns `(callable, args, state)`. stdlib instances are
+//! `datetime.datetime`, `datetime.date`, `datetime.timedelta`,
+//! `decimal.Decimal`, `complex`, and `collections.OrderedDict` values, saved
+//! with the reduce forms their C types return. objects built earlier are
+//! sometimes reused, so the memo sees repeated objects. dict keys are distinct
+//! str or int values kept in insertion order, and set elements are ints from 0
+//! to 7, the one case where CPython's set iteration order is fixed.
+//!
+//! nested containers hold up to six elements, unless `with_container_sizes`
+//! draws their size; a set of drawn size `n` holds the ints 0 to `n - 1`,
+//! which CPython also iterates in order.
+
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::rc::Rc;
+use std::sync::OnceLock;
+
+use color_eyre::eyre::eyre;
+use color_eyre::Result;
+
+use super::patterns::escape_all;
+use super::source::{EntropySource, ExhaustionPolicy, GenerationSource};
+use super::strict::encode_arg;
+use super::Generator;
+use super::Version;
+use crate::opcodes::{python_float_repr, raw_unicode_escape, OpcodeKind};
+
+/// CPython's `_BATCHSIZE`.
+pub(super) const BATCH_SIZE: usize = 1000;
+
+/// CPython's `_FRAME_SIZE_TARGET`.
+const FRAME_SIZE_TARGET: usize = 64 * 1024;
+
+/// CPython's `_FRAME_SIZE_MIN`.
+const FRAME_SIZE_MIN: usize = 4;
+
+/// FRAME opcode and its 8-byte length.
+const FRAME_HEADER_SIZE: usize = 9;
+
+/// rough number of opcodes an object costs, to turn the opcode budget into an
+/// object budget.
+const OPCODES_PER_OBJECT: usize = 2;
+
+/// deepest container nesting below the root.
+const MAX_NESTING: usize = 4;
+
+/// most elements in a container below the root.
+const MAX_CONTAINER_LEN: usize = 6;
+
+/// one in this many values is an object built earlier.
+const REUSE_ODDS: usize = 8;
+
+/// key draws a sized dict makes per entry before it settles for fewer entries.
+const KEY_DRAWS_PER_ENTRY: usize = 8;
+
+/// the C types `stdlib_instance` builds, as module and name.
+const STDLIB_TYPES: [(&str, &str); 6] = [
+    ("datetime", "datetime"),
+    ("datetime", "date"),
+    ("datetime", "timedelta"),
+    ("decimal", "Decimal"),
+    ("builtins", "complex"),
+    ("collections", "OrderedDict"),
+];
+
+/// characters mixed into generated strings besides printable ASCII: the ones
+/// protocol 0 escapes, and non-ASCII ones of every UTF-8 length.
+const SPECIAL_CHARS: [char; 8] = ['\\', '\n', '\0', '\r', '\x1a', 'é', 'ā', '😀'];
+
+/// floats worth hitting on purpose.
+const SPECIAL_FLOATS: [f64; 10] = [
+    0.0,
+    -0.0,
+    1.0,
+    1e16,
+    1.5e-5,
+    f64::MAX,
+    f64::MIN_POSITIVE,
+    f64::INFINITY,
+    f64::NEG_INFINITY,
+    f64::NAN,
+];
+
+static COMPAT_MAPPINGS: OnceLock<CompatMappings> = OnceLock::new();
+
+/// `_compat_pickle.REVERSE_NAME_MAPPING` and `REVERSE_IMPORT_MAPPING`.
+struct CompatMappings {
+    names: HashMap<(String, String), (String, String)>,
+    modules: HashMap<String, String>,
+}
+
+fn compat_mappings() -> &'static CompatMappings {
+    COMPAT_MAPPINGS.get_or_init(|| {
+        let content = include_str!("../../data/compat_pickle.txt");
+        let mut mappings = CompatMappings {
+            names: HashMap::new(),
+            modules: HashMap::new(),
+        };
+        for line in content.lines() {
+            match line.split('\t').collect::<Vec<_>>()[..] {
+                [module, name, old_module, old_name] => {
+                    mappings.names.insert(
+                        (module.to_string(), name.to_string()),
+                        (old_module.to_string(), old_name.to_string()),
+                    );
+                }
+                [module, old_module] => {
+                    mappings
+                        .modules
+                        .insert(module.to_string(), old_module.to_string());
+                }
+                _ => {}
+            }
+        }
+        mappings
+    })
+}
+
+/// the python 2 module and name CPython writes for a global below protocol 3.
+fn fix_imports(module: &str, name: &str) -> (String, String) {
+    let mappings = compat_mappings();
+    if let Some((module, name)) = mappings.names.get(&(module.to_string(), name.to_string())) {
+        return (module.clone(), name.clone());
+    }
+    let module = mappings.modules.get(module).map_or(module, String::as_str);
+    (module.to_string(), name.to_string())
+}
+
+/// a python object in canonical mode's object model.
+#[derive(Debug)]
+enum Value {
+    None,
+    Bool(bool),
+    Int(i128),
+    Float(f64),
+    Str(String),
+    /// an interned str: a module, attribute, or other identifier
+    Name(String),
+    Bytes(Vec<u8>),
+    ByteArray(Vec<u8>),
+    List(Vec<ValueRef>),
+    Tuple(Vec<ValueRef>),
+    Dict(Vec<(ValueRef, ValueRef)>),
+    Set(Vec<ValueRef>),
+    FrozenSet(Vec<ValueRef>),
+    Global {
+        module: String,
+        name: String,
+    },
+    /// an instance of a plain class, with its non-empty `__dict__`
+    Object {
+        class: ValueRef,
+        state: Option<ValueRef>,
+    },
+    /// a `collections.OrderedDict`
+    OrderedDict(Vec<(ValueRef, ValueRef)>),
+    /// an object whose `__reduce__` returns `(callable, args, state)`
+    Reduce {
+        callable: ValueRef,
+        args: ValueRef,
+        state: Option<ValueRef>,
+    },
+}
+
+/// a shared reference to a value; `Rc` identity is python object identity.
+type ValueRef = Rc<Value>;
+
+fn global(module: &str, name: &str) -> ValueRef {
+    Rc::new(Value::Global {
+        module: module.to_string(),
+        name: name.to_string(),
+    })
+}
+
+fn tuple(items: Vec<ValueRef>) -> ValueRef {
+    Rc::new(Value::Tuple(items))
+}
+
+/// what the pickler's memo identifies an object by.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum MemoKey {
+    Identity(usize),
+    /// interned strings are one object per value
+    Name(String),
+    /// and so are the empty string and each latin-1 character, though an
+    /// interned "a" is not the same object as `chr(97)`
+    Str(String),
+    /// so are empty and single-byte bytes
+    Bytes(Vec<u8>),
+    /// and every reference to a global is the same object
+    Global(String, String),
+}
+
+fn memo_key(value: &ValueRef) -> MemoKey {
+    match &**value {
+        Value::Name(text) => MemoKey::Name(text.clone()),
+        Value::Str(text) if is_str_singleton(text) => MemoKey::Str(text.clone()),
+        Value::Bytes(bytes) if bytes.len() <= 1 => MemoKey::Bytes(bytes.clone()),
+        Value::Global { module, name } => MemoKey::Global(module.clone(), name.clone()),
+        _ => MemoKey::Identity(Rc::as_ptr(value) as usize),
+    }
+}
+
+/// CPython keeps one object for the empty string and each latin-1 character.
+fn is_str_singleton(text: &str) -> bool {
+    let mut chars = text.chars();
+    match (chars.next(), chars.next()) {
+        (None, _) => true,
+        (Some(c), None) => u32::from(c) < 0x100,
+        _ => false,
+    }
+}
+
+/// builds a random object, spending one unit of `budget` per value.
+struct ObjectBuilder<'g> {
+    generator: &'g Generator,
+    budget: usize,
+    /// objects built so far that the memo would track, for reuse
+    built: Vec<ValueRef>,
+}
+
+impl<'g> ObjectBuilder<'g> {
+    fn new(generator: &'g Generator, budget: usize) -> Self {
+        Self {
+            generator,
+            budget,
+            built: Vec::new(),
+        }
+    }
+
+    /// a root container that keeps growing until the budget is spent.
+    fn root(&mut self, source: &mut GenerationSource) -> Result<ValueRef> {
+        if self.budget == 0 {
+            return Ok(Rc::new(Value::None));
+        }
+        self.budget -= 1;
+        Ok(match source.choose_index(4) {
+            0 => {
+                let mut items = Vec::new();
+                while self.budget > 0 {
+                    items.push(self.value(1, source)?);
+                }
+                Rc::new(Value::List(items))
+            }
+            1 => self.dict(1, None, false, source)?,
+            2 => {
+                let class = self.global(source)?;
+                let state = self.dict(1, None, true, source)?;
+                Rc::new(Value::Object {
+                    class,
+                    state: Some(state),
+                })
+            }
+            _ => {
+                let mut items = Vec::new();
+                while self.budget > 0 {
+                    items.push(self.value(1, source)?);
+                }
+                tuple(items)
+            }
+        })
+    }
+
+    fn value(&mut self, depth: usize, source: &mut GenerationSource) -> Result<ValueRef> {
+        if self.generator.exhaustion_policy == ExhaustionPolicy::Stop && source.is_exhausted() {
+            // the fuzzer input ran out: finish the object with what it decided
+            self.budget = 0;
+        }
+        if !self.built.is_empty() && source.choose_index(REUSE_ODDS) == 0 {
+            // repeats spend budget too, or a source stuck on index 0 (an
+            // exhausted input, a replayed trace) would never finish
+            self.budget = self.budget.saturating_sub(1);
+            return Ok(self.built[source.choose_index(self.built.len())].clone());
+        }
+        self.budget = self.budget.saturating_sub(1);
+        if self.budget == 0 || depth >= MAX_NESTING {
+            return Ok(self.scalar(source));
+        }
+
+        let choice = source.choose_index(12);
+        let len = match choice {
+            0..=5 => return Ok(self.scalar(source)),
+            6..=8 => self.container_len(source),
+            9 => match self.generator.container_sizes {
+                Some(_) => self.container_len(source),
+                None => Some(0),
+            },
+            10 => return self.global(source),
+            _ => return self.instance(depth, source),
+        };
+        let Some(len) = len else {
+            // the drawn size does not fit the budget
+            return Ok(self.scalar(source));
+        };
+
+        let value = match choice {
+            6 => Value::List(self.values(len, depth, source)?),
+            7 => Value::Tuple(self.values(len, depth, source)?),
+            8 => return self.dict(depth + 1, Some(len), false, source),
+            _ => {
+                let elements = match self.generator.container_sizes {
+                    Some(_) => {
+                        self.budget -= len;
+                        (0..len as i128)
+                            .map(|int| Rc::new(Value::Int(int)))
+                            .collect()
+                    }
+                    None => self.small_int_set(source),
+                };
+                if source.gen_bool() {
+                    Value::Set(elements)
+                } else {
+                    Value::FrozenSet(elements)
+                }
+            }
+        };
+        Ok(self.remember(value))
+    }
+
+    /// the length of a nested list, tuple, dict, or set: up to
+    /// `MAX_CONTAINER_LEN`, or drawn from `with_container_sizes`, in which case
+    /// `None` if it does not fit the budget.
+    fn container_len(&self, source: &mut GenerationSource) -> Option<usize> {
+        match self.generator.container_sizes {
+            Some(sizes) => Some(sizes.sample(source)).filter(|len| *len <= self.budget),
+            None => Some(source.choose_index(MAX_CONTAINER_LEN + 1)),
+        }
+    }
+
+    fn values(
+        &mut self,
+        len: usize,
+        depth: usize,
+        source: &mut GenerationSource,
+    ) -> Result<Vec<ValueRef>> {
+        (0..len).map(|_| self.value(depth + 1, source)).collect()
+    }
+
+    fn remember(&mut self, value: Value) -> ValueRef {
+        let value = Rc::new(value);
+        let atomic = matches!(
+            *value,
+            Value::None | Value::Bool(_) | Value::Int(_) | Value::Float(_)
+        );
+        let empty_tuple = matches!(&*value, Value::Tuple(items) if items.is_empty());
+        if !atomic && !empty_tuple {
+            self.built.push(value.clone());
+        }
+        value
+    }
+
+    fn scalar(&mut self, source: &mut GenerationSource) -> ValueRef {
+        let value = match source.choose_index(8) {
+            0 => Value::None,
+            1 => Value::Bool(source.with_values(|source| source.gen_bool())),
+            2 | 3 => Value::Int(self.int(source)),
+            4 => Value::Float(self.float(source)),
+            5 | 6 => Value::Str(self.text(source)),
+            _ => {
+                let len = source.choose_index(17);
+                let bytes = source.gen_bytes(len);
+                if source.choose_index(4) == 0 {
+                    Value::ByteArray(bytes)
+                } else {
+                    Value::Bytes(bytes)
+                }
+            }
+        };
+        self.remember(value)
+    }
+
+    fn int(&self, source: &mut GenerationSource) -> i128 {
+        source.with_values(|source| match source.choose_index(5) {
+            0 => i128::from(source.gen_u8()),
+            1 => i128::from(source.gen_u16()),
+            2 => i128::from(source.gen_i32()),
+            3 => i128::from(source.gen_i64()),
+            // past 64 bits
+            _ => (i128::from(source.gen_i64()) << 32) | i128::from(source.gen_u32()),
+        })
+    }
+
+    fn float(&self, source: &mut GenerationSource) -> f64 {
+        source.with_values(|source| {
+            if source.choose_index(4) == 0 {
+                return SPECIAL_FLOATS[source.choose_index(SPECIAL_FLOATS.len())];
+            }
+            let magnitude = source.gen_f64() * 2f64.powi(source.choose_index(64) as i32 - 16);
+            if source.gen_bool() {
+                -magnitude
+            } else {
+                magnitude
+            }
+        })
+    }
+
+    fn text(&self, source: &mut GenerationSource) -> String {
+        let len = source.choose_index(13);
+        (0..len)
+            .map(|_| {
+                source.with_values(|source| {
+                    if source.choose_index(4) == 0 {
+                        SPECIAL_CHARS[source.choose_index(SPECIAL_CHARS.len())]
+                    } else {
+                        source.gen_ascii_char()
+                    }
+                })
+            })
+            .collect()
+    }
+
+    /// a dict of `len` entries with distinct str or int keys, or interned str
+    /// keys for an instance `__dict__`. a `len` of `None` keeps adding entries
+    /// until the budget is spent.
+    fn dict(
+        &mut self,
+        depth: usize,
+        len: Option<usize>,
+        attributes: bool,
+        source: &mut GenerationSource,
+    ) -> Result<ValueRef> {
+        let entries = self.dict_entries(depth, len, attributes, source)?;
+        Ok(self.remember(Value::Dict(entries)))
+    }
+
+    /// the entries of a `dict`.
+    fn dict_entries(
+        &mut self,
+        depth: usize,
+        len: Option<usize>,
+        attributes: bool,
+        source: &mut GenerationSource,
+    ) -> Result<Vec<(ValueRef, ValueRef)>> {
+        let mut seen = HashSet::new();
+        let mut entries = Vec::new();
+        // a source stuck on one key (an exhausted input, a replayed trace)
+        // never fills a sized dict, so give up after so many draws per entry
+        let mut draws = 0;
+        while len.map_or(self.budget > 0, |len| {
+            entries.len() < len && draws < len * KEY_DRAWS_PER_ENTRY
+        }) {
+            draws += 1;
+            let key = if attributes {
+                Value::Name(self.identifier(source))
+            } else if source.choose_index(4) == 0 {
+                Value::Int(i128::from(source.gen_i32() % 1000))
+            } else {
+                Value::Str(self.identifier(source))
+            };
+            let unseen = match &key {
+                Value::Int(value) => seen.insert((false, value.to_string())),
+                Value::Str(text) | Value::Name(text) => seen.insert((true, text.clone())),
+                _ => unreachable!("dict keys are ints and strings"),
+            };
+            let value = self.value(depth, source)?;
+            if unseen {
+                entries.push((Rc::new(key), value));
+            }
+        }
+        if attributes && entries.is_empty() {
+            entries.push((Rc::new(Value::Name("x".to_string())), self.scalar(source)));
+        }
+        Ok(entries)
+    }
+
+    /// a short identifier, so keys repeat often enough to exercise the memo.
+    fn identifier(&self, source: &mut GenerationSource) -> String {
+        let len = 1 + source.choose_index(6);
+        (0..len)
+            .map(|_| char::from(b'a' + source.choose_index(8) as u8))
+            .collect()
+    }
+
+    /// up to `MAX_CONTAINER_LEN` distinct ints from 0 to 7 in ascending order,
+    /// which is how CPython iterates a set of them.
+    fn small_int_set(&mut self, source: &mut GenerationSource) -> Vec<ValueRef> {
+        let bits = source.gen_u8();
+        (0..8)
+            .filter(|bit| bits & (1 << bit) != 0)
+            .take(MAX_CONTAINER_LEN)
+            .map(|bit| Rc::new(Value::Int(bit)))
+            .collect()
+    }
+
+    fn global(&mut self, source: &mut GenerationSource) -> Result<ValueRef> {
+        let global = self.generator.get_random_module(source)?;
+        let (module, name) = global
+            .trim_end()
+            .split_once('\n')
+            .expect("get_random_module returns two lines");
+        Ok(self.remember(Value::Global {
+            module: module.to_string(),
+            name: name.to_string(),
+        }))
+    }
+
+    /// a plain class instance, an object with its own `__reduce__`, or a
+    /// stdlib instance.
+    fn instance(&mut self, depth: usize, source: &mut GenerationSource) -> Result<ValueRef> {
+        if source.choose_index(3) == 0 {
+            return self.stdlib_instance(depth, source);
+        }
+        if source.gen_bool() {
+            let class = self.global(source)?;
+            let state = if source.gen_bool() {
+                // an instance `__dict__` is never empty, or it would not be written
+                let len = 1 + source.choose_index(MAX_CONTAINER_LEN);
+                Some(self.dict(depth + 1, Some(len), true, source)?)
+            } else {
+                None
+            };
+            return Ok(self.remember(Value::Object { class, state }));
+        }
+
+        // CPython writes a callable named __newobj__ or __newobj_ex__ as NEWOBJ
+        let callable = loop {
+            let callable = self.global(source)?;
+            if !matches!(&*callable, Value::Global { name, .. } if name.starts_with("__newobj")) {
+                break callable;
+            }
+        };
+        let len = source.choose_index(4);
+        let args = tuple(self.values(len, depth, source)?);
+        let state = if source.choose_index(4) == 0 {
+            let len = source.choose_index(MAX_CONTAINER_LEN + 1);
+            Some(self.dict(depth + 1, Some(len), false, source)?)
+        } else {
+            None
+        };
+        Ok(self.remember(Value::Reduce {
+            callable,
+            args,
+            state,
+        }))
+    }
+
+    /// a datetime, date, timedelta, Decimal, complex, or OrderedDict, reduced
+    /// the way CPython reduces them, so the corpus holds the instances
+    /// scanners have to allow.
+    fn stdlib_instance(&mut self, depth: usize, source: &mut GenerationSource) -> Result<ValueRef> {
+        let choice = source.choose_index(STDLIB_TYPES.len());
+        let args = match choice {
+            0 => {
+                let mut state = self.date_state(source);
+                source.with_values(|source| {
+                    state.extend([
+                        source.choose_index(24) as u8,
+                        source.choose_index(60) as u8,
+                        source.choose_index(60) as u8,
+                    ]);
+                    state.extend(&(source.choose_index(1_000_000) as u32).to_be_bytes()[1..]);
+                    // protocol 4+ keeps `fold` in the month's top bit
+                    if self.generator.state.version >= Version::V4 && source.choose_index(8) == 0 {
+                        state[2] |= 0x80;
+                    }
+                });
+                vec![Rc::new(Value::Bytes(state))]
+            }
+            1 => {
+                let state = self.date_state(source);
+                vec![Rc::new(Value::Bytes(state))]
+            }
+            2 => {
+                let days = i128::from(source.gen_i32() % 1_000_000_000);
+                let (seconds, microseconds) = source.with_values(|source| {
+                    let seconds = source.choose_index(86_400) as i128;
+                    (seconds, source.choose_index(1_000_000) as i128)
+                });
+                let args = [days, seconds, microseconds].map(|int| Rc::new(Value::Int(int)));
+                args.to_vec()
+            }
+            3 => {
+                let text = self.decimal_text(source);
+                vec![Rc::new(Value::Str(text))]
+            }
+            4 => {
+                let parts = [self.float(source), self.float(source)];
+                let args = parts.map(|part| Rc::new(Value::Float(part)));
+                args.to_vec()
+            }
+            _ => {
+                let Some(len) = self.container_len(source) else {
+                    return Ok(self.scalar(source));
+                };
+                let entries = self.dict_entries(depth + 1, Some(len), false, source)?;
+                return Ok(self.remember(Value::OrderedDict(entries)));
+            }
+        };
+        self.budget = self.budget.saturating_sub(args.len());
+        let (module, name) = STDLIB_TYPES[choice];
+        Ok(self.remember(Value::Reduce {
+            callable: global(module, name),
+            args: tuple(args),
+            state: None,
+        }))
+    }
+
+    /// the first four bytes of a date or datetime's pickled state: the year
+    /// (big-endian), month, and day.
+    fn date_state(&self, source: &mut GenerationSource) -> Vec<u8> {
+        source.with_values(|source| {
+            let year = 1 + source.choose_index(9999) as u16;
+            let month = 1 + source.choose_index(12) as u8;
+            let leap =
+                year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400));
+            let days = match month {
+                2 if leap => 29,
+                2 => 28,
+                4 | 6 | 9 | 11 => 30,
+                _ => 31,
+            };
+            let day = 1 + source.choose_index(days) as u8;
+            let [high, low] = year.to_be_bytes();
+            vec![high, low, month, day]
+        })
+    }
+
+    /// a `str(Decimal)`: a special value, or a fixed-point number with up to
+    /// six places, which `str` gives back as written.
+    fn decimal_text(&self, source: &mut GenerationSource) -> String {
+        const SPECIAL: [&str; 6] = ["NaN", "sNaN", "Infinity", "-Infinity", "-0", "1E+3"];
+        source.with_values(|source| {
+            if source.choose_index(4) == 0 {
+                return SPECIAL[source.choose_index(SPECIAL.len())].to_string();
+            }
+            let sign = if source.gen_bool() { "-" } else { "" };
+            let whole = source.gen_u32() >> source.choose_index(32);
+            match source.choose_index(7) {
+                0 => format!("{sign}{whole}"),
+                places => {
+                    let fraction = source.gen_u32() % 10u32.pow(places as u32);
+                    format!("{sign}{whole}.{fraction:0places$}")
+                }
+            }
+        })
+    }
+}
+
+/// one in this many values is followed by a PUT of its own, with diverse
+/// encodings.
+const REDUNDANT_PUT_ODDS: usize = 8;
+
+/// frame size for the small frames diverse encodings sometimes use.
+const SMALL_FRAME_SIZE: usize = 256;
+
+/// ways to write a class instance, canonical ones first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InstanceForm {
+    NewObj,
+    Reconstructor,
+    Obj,
+    NewObjEx,
+}
+
+/// writes a value the way CPython's pickler does, or with diverse encodings,
+/// through the generator's stack simulation.
+struct CanonicalPickler<'g, 's> {
+    generator: &'g mut Generator,
+    /// where diverse encodings draw from; `None` writes the canonical encoding
+    diverse: Option<&'g mut GenerationSource<'s>>,
+    /// memo indices each memoized object is stored at
+    memo: HashMap<MemoKey, Vec<usize>>,
+    /// memo slots taken so far, redundant PUTs included
+    memo_len: usize,
+    /// memoized values, kept alive so no later value reuses their address
+    retained: Vec<ValueRef>,
+    /// frame size after which the open frame is closed, `None` for no framing
+    frame_target: Option<usize>,
+    /// output offset of the open frame's reserved header
+    frame_start: Option<usize>,
+    opcodes: usize,
+}
+
+impl<'g, 's> CanonicalPickler<'g, 's> {
+    fn new(generator: &'g mut Generator, diverse: Option<&'g mut GenerationSource<'s>>) -> Self {
+        let framing = generator.state.version >= Version::V4;
+        let mut pickler = Self {
+            generator,
+            diverse,
+            memo: HashMap::new(),
+            memo_len: 0,
+            retained: Vec::new(),
+            frame_target: None,
+            frame_start: None,
+            opcodes: 0,
+        };
+        if framing {
+            pickler.frame_target =
+                pickler.choose(&[Some(FRAME_SIZE_TARGET), None, Some(SMALL_FRAME_SIZE)]);
+        }
+        pickler
+    }
+
+    /// which of `count` encodings to use: the first, canonical one unless
+    /// encodings are diverse.
+    fn pick(&mut self, count: usize) -> usize {
+        match self.diverse.as_deref_mut() {
+            Some(source) => source.choose_index(count),
+            None => 0,
+        }
+    }
+
+    fn choose<T: Copy>(&mut self, options: &[T]) -> T {
+        options[self.pick(options.len())]
+    }
+
+    fn version(&self) -> Version {
+        self.generator.state.version
+    }
+
+    /// protocol 1+, which CPython calls `bin`.
+    fn bin(&self) -> bool {
+        self.version() >= Version::V1
+    }
+
+    /// pickle `root` and STOP, returning the number of opcodes written (FRAMEs
+    /// included, PROTO not).
+    fn dump(mut self, root: &ValueRef) -> usize {
+        self.save(root);
+        self.write(OpcodeKind::Stop, None);
+        self.commit_frame();
+        self.opcodes
+    }
+
+    fn write(&mut self, opcode: OpcodeKind, arg: Option<&[u8]>) {
+        let output = &mut self.generator.output;
+        if self.frame_target.is_some() && self.frame_start.is_none() {
+            self.frame_start = Some(output.len());
+            output.extend_from_slice(&[0; FRAME_HEADER_SIZE]);
+        }
+        output.push(opcode.as_u8());
+        if let Some(arg) = arg {
+            let encoded = encode_arg(opcode, arg).expect("canonical arguments fit their opcode");
+            output.extend_from_slice(&encoded);
+        }
+        self.generator.process_stack_ops(opcode, arg);
+        self.opcodes += 1;
+    }
+
+    fn frame_len(&self) -> Option<usize> {
+        self.frame_start
+            .map(|start| self.generator.output.len() - start - FRAME_HEADER_SIZE)
+    }
+
+    /// fill in the open frame's header, or drop it if the frame is too short.
+    fn commit_frame(&mut self) {
+        let (Some(start), Some(len)) = (self.frame_start, self.frame_len()) else {
+            return;
+        };
+        self.frame_start = None;
+        let output = &mut self.generator.output;
+        if len >= FRAME_SIZE_MIN {
+            output[start] = OpcodeKind::Frame.as_u8();
+            output[start + 1..start + FRAME_HEADER_SIZE]
+                .copy_from_slice(&(len as u64).to_le_bytes());
+            self.opcodes += 1;
+        } else {
+            output.drain(start..start + FRAME_HEADER_SIZE);
+        }
+    }
+
+    fn save(&mut self, value: &ValueRef) {
+        if let (Some(target), Some(len)) = (self.frame_target, self.frame_len()) {
+            if len >= target {
+                self.commit_frame();
+            }
+        }
+
+        // atoms are never memoized
+        match **value {
+            Value::None => self.write(OpcodeKind::None, None),
+            Value::Bool(flag) => self.save_bool(flag),
+            Value::Int(int) => self.save_int(int),
+            Value::Float(float) => self.save_float(float),
+            _ => match self.memo.get(&memo_key(value)).cloned() {
+                Some(indices) => {
+                    let index = self.choose(&indices);
+                    self.write_get(index);
+                }
+                None => self.save_new(value),
+            },
+        }
+
+        if self.diverse.is_some() && self.pick(REDUNDANT_PUT_ODDS) == 0 {
+            let index = self.take_memo_index();
+            self.write_put(index);
+            if let Some(indices) = self.memo.get_mut(&memo_key(value)) {
+                indices.push(index);
+            }
+        }
+    }
+
+    fn save_new(&mut self, value: &ValueRef) {
+        match &**value {
+            Value::Str(text) | Value::Name(text) => self.save_str(text, value),
+            Value::Bytes(bytes) => self.save_bytes(bytes, value),
+            Value::ByteArray(bytes) => self.save_bytearray(bytes, value),
+            Value::List(items) => self.save_list(items, value),
+            Value::Tuple(items) => self.save_tuple(items, value),
+            Value::Dict(entries) => self.save_dict(entries, value),
+            Value::OrderedDict(entries) => self.save_ordered_dict(entries, value),
+            Value::Set(items) => self.save_set(items, value),
+            Value::FrozenSet(items) => self.save_frozenset(items, value),
+            Value::Global { module, name } => self.save_global(module, name, value),
+            Value::Object { class, state } => self.save_object(class, state.as_ref(), value),
+            Value::Reduce {
+                callable,
+                args,
+                state,
+            } => self.save_reduce(callable, args, state.as_ref(), value),
+            Value::None | Value::Bool(_) | Value::Int(_) | Value::Float(_) => unreachable!(),
+        }
+    }
+
+    fn take_memo_index(&mut self) -> usize {
+        self.memo_len += 1;
+        self.memo_len - 1
+    }
+
+    fn memoize(&mut self, value: &ValueRef) {
+        let index = self.take_memo_index();
+        self.write_put(index);
+        self.memo.insert(memo_key(value), vec![index]);
+        self.retained.push(value.clone());
+    }
+
+    /// store the top of the stack at memo `index`, the next free slot.
+    fn write_put(&mut self, index: usize) {
+        let mut options = Vec::new();
+        if self.version() >= Version::V4 {
+            options.push(OpcodeKind::Memoize);
+        }
+        if self.bin() {
+            if index <= 0xff {
+                options.push(OpcodeKind::BinPut);
+            }
+            options.push(OpcodeKind::LongBinPut);
+        }
+        options.push(OpcodeKind::Put);
+
+        match self.choose(&options) {
+            OpcodeKind::Memoize => self.write(OpcodeKind::Memoize, None),
+            OpcodeKind::BinPut => self.write(OpcodeKind::BinPut, Some(&[index as u8])),
+            OpcodeKind::LongBinPut => {
+                self.write(OpcodeKind::LongBinPut, Some(&(index as u32).to_le_bytes()))
+            }
+            _ => self.write(OpcodeKind::Put, Some(format!("{index}\n").as_bytes())),
+        }
+    }
+
+    fn write_get(&mut self, index: usize) {
+        let mut options = Vec::new();
+        if self.bin() {
+            if index <= 0xff {
+                options.push(OpcodeKind::BinGet);
+            }
+            options.push(OpcodeKind::LongBinGet);
+        }
+        options.push(OpcodeKind::Get);
+
+        match self.choose(&options) {
+            OpcodeKind::BinGet => self.write(OpcodeKind::BinGet, Some(&[index as u8])),
+            OpcodeKind::LongBinGet => {
+                self.write(OpcodeKind::LongBinGet, Some(&(index as u32).to_le_bytes()))
+            }
+            _ => self.write(OpcodeKind::Get, Some(format!("{index}\n").as_bytes())),
+        }
+    }
+
+    fn save_bool(&mut self, flag: bool) {
+        if self.version() >= Version::V2 && self.pick(2) == 0 {
+            let opcode = if flag {
+                OpcodeKind::NewTrue
+            } else {
+                OpcodeKind::NewFalse
+            };
+            self.write(opcode, None);
+        } else {
+            let arg: &[u8] = if flag { b"01\n" } else { b"00\n" };
+            self.write(OpcodeKind::Int, Some(arg));
+        }
+    }
+
+    fn save_int(&mut self, int: i128) {
+        use OpcodeKind::*;
+
+        let encoded = super::emission::encode_long_minimal(int);
+        let mut options = Vec::new();
+        if self.bin() {
+            if u8::try_from(int).is_ok() {
+                options.push(BinInt1);
+            }
+            if u16::try_from(int).is_ok() {
+                options.push(BinInt2);
+            }
+            if i32::try_from(int).is_ok() {
+                options.push(BinInt);
+            }
+        }
+        if self.version() >= Version::V2 {
+            if encoded.len() < 0x100 {
+                options.push(Long1);
+            }
+            options.push(Long4);
+        }
+        // python 3 reads INT of any size, CPython only writes it for i32s
+        if i32::try_from(int).is_ok() {
+            options.extend([Int, Long]);
+        } else {
+            options.extend([Long, Int]);
+        }
+
+        match self.choose(&options) {
+            BinInt1 => self.write(BinInt1, Some(&[int as u8])),
+            BinInt2 => self.write(BinInt2, Some(&(int as u16).to_le_bytes())),
+            BinInt => self.write(BinInt, Some(&(int as i32).to_le_bytes())),
+            Int => self.write(Int, Some(format!("{int}\n").as_bytes())),
+            opcode => {
+                let arg = super::emission::encode_long_arg(opcode, int, &encoded);
+                self.write(opcode, Some(&arg));
+            }
+        }
+    }
+
+    fn save_float(&mut self, float: f64) {
+        // BINFLOAT, then FLOAT with python's repr or rust's exponent notation,
+        // which python's float() reads just as well
+        let choice = if self.bin() {
+            self.pick(3)
+        } else {
+            1 + self.pick(2)
+        };
+        let line = match choice {
+            0 => return self.write(OpcodeKind::BinFloat, Some(&float.to_be_bytes())),
+            1 => format!("{}\n", python_float_repr(float)),
+            _ => format!("{float:e}\n"),
+        };
+        self.write(OpcodeKind::Float, Some(line.as_bytes()));
+    }
+
+    fn save_str(&mut self, text: &str, value: &ValueRef) {
+        let mut options = Vec::new();
+        if self.bin() {
+            if text.len() <= 0xff && self.version() >= Version::V4 {
+                options.push(OpcodeKind::ShortBinUnicode);
+            }
+            // BINUNICODE8 only takes over past 4 GiB in CPython
+            options.push(OpcodeKind::BinUnicode);
+            if self.version() >= Version::V4 {
+                options.push(OpcodeKind::BinUnicode8);
+            }
+        }
+        options.push(OpcodeKind::Unicode);
+
+        // one more choice: UNICODE with every character escaped
+        match options.get(self.pick(options.len() + 1)) {
+            Some(OpcodeKind::Unicode) => {
+                self.write(OpcodeKind::Unicode, Some(&raw_unicode_escape(text)));
+            }
+            Some(&opcode) => self.write(opcode, Some(text.as_bytes())),
+            None => {
+                let line = format!("{}\n", escape_all(text));
+                self.write(OpcodeKind::Unicode, Some(line.as_bytes()));
+            }
+        }
+        self.memoize(value);
+    }
+
+    fn save_bytes(&mut self, bytes: &[u8], value: &ValueRef) {
+        if self.version() < Version::V3 {
+            // no bytes opcodes yet: CPython writes bytes() or
+            // _codecs.encode(latin-1 text, 'latin1')
+            let (callable, args) = if bytes.is_empty() {
+                (global("builtins", "bytes"), tuple(Vec::new()))
+            } else {
+                let text = bytes.iter().map(|&byte| char::from(byte)).collect();
+                let args = vec![
+                    Rc::new(Value::Str(text)),
+                    Rc::new(Value::Name("latin1".to_string())),
+                ];
+                (global("_codecs", "encode"), tuple(args))
+            };
+            return self.save_reduce(&callable, &args, None, value);
+        }
+
+        let mut options = Vec::new();
+        if bytes.len() <= 0xff {
+            options.push(OpcodeKind::ShortBinBytes);
+        }
+        options.push(OpcodeKind::BinBytes);
+        if self.version() >= Version::V4 {
+            options.push(OpcodeKind::BinBytes8);
+        }
+        let opcode = self.choose(&options);
+        self.write(opcode, Some(bytes));
+        self.memoize(value);
+    }
+
+    fn save_bytearray(&mut self, bytes: &[u8], value: &ValueRef) {
+        if self.version() < Version::V5 {
+            let args = if bytes.is_empty() {
+                Vec::new()
+            } else {
+                vec![Rc::new(Value::Bytes(bytes.to_vec()))]
+            };
+            return self.save_reduce(&global("builtins", "bytearray"), &tuple(args), None, value);
+        }
+        self.write(OpcodeKind::ByteArray8, Some(bytes));
+        self.memoize(value);
+    }
+
+    fn save_tuple(&mut self, items: &[ValueRef], value: &ValueRef) {
+        if items.is_empty() {
+            if self.bin() && self.pick(2) == 0 {
+                self.write(OpcodeKind::EmptyTuple, None);
+            } else {
+                self.write(OpcodeKind::Mark, None);
+                self.write(OpcodeKind::Tuple, None);
+            }
+            return;
+        }
+
+        // objects are built bottom-up, so a tuple never contains itself and
+        // CPython's POP/POP_MARK recursion fallback never applies
+        if items.len() <= 3 && self.version() >= Version::V2 && self.pick(2) == 0 {
+            for item in items {
+                self.save(item);
+            }
+            let opcode = match items.len() {
+                1 => OpcodeKind::Tuple1,
+                2 => OpcodeKind::Tuple2,
+                _ => OpcodeKind::Tuple3,
+            };
+            self.write(opcode, None);
+        } else {
+            self.write(OpcodeKind::Mark, None);
+            for item in items {
+                self.save(item);
+            }
+            self.write(OpcodeKind::Tuple, None);
+        }
+        self.memoize(value);
+    }
+
+    /// how to start a list or dict: 0 for the empty opcode, 1 for MARK and the
+    /// building opcode, 2 for MARK, the items, and the building opcode.
+    fn container_start(&mut self) -> usize {
+        if self.bin() {
+            self.pick(3)
+        } else {
+            1 + self.pick(2)
+        }
+    }
+
+    fn save_list(&mut self, items: &[ValueRef], value: &ValueRef) {
+        match self.container_start() {
+            0 => self.write(OpcodeKind::EmptyList, None),
+            1 => {
+                self.write(OpcodeKind::Mark, None);
+                self.write(OpcodeKind::List, None);
+            }
+            _ => {
+                self.write(OpcodeKind::Mark, None);
+                for item in items {
+                    self.save(item);
+                }
+                self.write(OpcodeKind::List, None);
+                return self.memoize(value);
+            }
+        }
+        self.memoize(value);
+
+        if self.diverse.is_some() {
+            return self.diverse_batches(
+                items,
+                |pickler, item| pickler.save(item),
+                Some(OpcodeKind::Append),
+                OpcodeKind::Appends,
+            );
+        }
+        if !self.bin() || items.len() == 1 {
+            for item in items {
+                self.save(item);
+                self.write(OpcodeKind::Append, None);
+            }
+            return;
+        }
+        for batch in items.chunks(BATCH_SIZE) {
+            self.write(OpcodeKind::Mark, None);
+            for item in batch {
+                self.save(item);
+            }
+            self.write(OpcodeKind::Appends, None);
+        }
+    }
+
+    fn save_entry(&mut self, (key, item): &(ValueRef, ValueRef)) {
+        self.save(key);
+        self.save(item);
+    }
+
+    fn save_dict(&mut self, entries: &[(ValueRef, ValueRef)], value: &ValueRef) {
+        match self.container_start() {
+            0 => self.write(OpcodeKind::EmptyDict, None),
+            1 => {
+                self.write(OpcodeKind::Mark, None);
+                self.write(OpcodeKind::Dict, None);
+            }
+            _ => {
+                self.write(OpcodeKind::Mark, None);
+                for entry in entries {
+                    self.save_entry(entry);
+                }
+                self.write(OpcodeKind::Dict, None);
+                return self.memoize(value);
+            }
+        }
+        self.memoize(value);
+
+        if self.diverse.is_some() {
+            return self.diverse_batches(
+                entries,
+                Self::save_entry,
+                Some(OpcodeKind::SetItem),
+                OpcodeKind::SetItems,
+            );
+        }
+        if !self.bin() || entries.len() == 1 {
+            for entry in entries {
+                self.save_entry(entry);
+                self.write(OpcodeKind::SetItem, None);
+            }
+            return;
+        }
+        if entries.is_empty() {
+            return;
+        }
+        for batch in entries.chunks(BATCH_SIZE) {
+            self.write(OpcodeKind::Mark, None);
+            for entry in batch {
+                self.save_entry(entry);
+            }
+            self.write(OpcodeKind::SetItems, None);
+        }
+        // the C pickler starts another batch after every full one
+        if entries.len().is_multiple_of(BATCH_SIZE) {
+            self.write(OpcodeKind::Mark, None);
+            self.write(OpcodeKind::SetItems, None);
+        }
+    }
+
+    /// `OrderedDict.__reduce__` returns `(OrderedDict, (), None, None,
+    /// iter(items))`, and the C pickler batches an iterator's items without
+    /// looking ahead: a lone last item gets SETITEM, and a full last batch no
+    /// empty one.
+    fn save_ordered_dict(&mut self, entries: &[(ValueRef, ValueRef)], value: &ValueRef) {
+        let callable = global("collections", "OrderedDict");
+        self.save_reduce(&callable, &tuple(Vec::new()), None, value);
+        if self.diverse.is_some() {
+            return self.diverse_batches(
+                entries,
+                Self::save_entry,
+                Some(OpcodeKind::SetItem),
+                OpcodeKind::SetItems,
+            );
+        }
+        let batch_size = if self.bin() { BATCH_SIZE } else { 1 };
+        for batch in entries.chunks(batch_size) {
+            if let [entry] = batch {
+                self.save_entry(entry);
+                self.write(OpcodeKind::SetItem, None);
+            } else {
+                self.write(OpcodeKind::Mark, None);
+                for entry in batch {
+                    self.save_entry(entry);
+                }
+                self.write(OpcodeKind::SetItems, None);
+            }
+        }
+    }
+
+    fn save_set(&mut self, items: &[ValueRef], value: &ValueRef) {
+        if self.version() < Version::V4 {
+            let args = tuple(vec![Rc::new(Value::List(items.to_vec()))]);
+            return self.save_reduce(&global("builtins", "set"), &args, None, value);
+        }
+
+        self.write(OpcodeKind::EmptySet, None);
+        self.memoize(value);
+        if self.diverse.is_some() {
+            return self.diverse_batches(
+                items,
+                |pickler, item| pickler.save(item),
+                None,
+                OpcodeKind::AddItems,
+            );
+        }
+        if items.is_empty() {
+            return;
+        }
+        for batch in items.chunks(BATCH_SIZE) {
+            self.write(OpcodeKind::Mark, None);
+            for item in batch {
+                self.save(item);
+            }
+            self.write(OpcodeKind::AddItems, None);
+        }
+        if items.len().is_multiple_of(BATCH_SIZE) {
+            self.write(OpcodeKind::Mark, None);
+            self.write(OpcodeKind::AddItems, None);
+        }
+    }
+
+    /// add `items` to the container on top of the stack in batches of random
+    /// size, sometimes with the one-item opcode, and sometimes an empty batch.
+    /// with oversized batches, every batch that can be over 1000 items is.
+    fn diverse_batches<T>(
+        &mut self,
+        items: &[T],
+        save_item: fn(&mut Self, &T),
+        single: Option<OpcodeKind>,
+        batch: OpcodeKind,
+    ) {
+        // protocol 0 only has the one-item opcodes
+        let batches = self.bin();
+        let mut rest = items;
+        while !rest.is_empty() {
+            let len = if self.generator.oversized_batches && rest.len() > BATCH_SIZE {
+                BATCH_SIZE + 1 + self.pick(rest.len() - BATCH_SIZE)
+            } else {
+                1 + self.pick(rest.len().min(BATCH_SIZE))
+            };
+            let (chunk, tail) = rest.split_at(len);
+            rest = tail;
+            match single {
+                Some(single) if !batches || (len == 1 && self.pick(2) == 0) => {
+                    for item in chunk {
+                        save_item(self, item);
+                        self.write(single, None);
+                    }
+                }
+                _ => {
+                    self.write(OpcodeKind::Mark, None);
+                    for item in chunk {
+                        save_item(self, item);
+                    }
+                    self.write(batch, None);
+                }
+            }
+        }
+        if batches && self.pick(4) == 0 {
+            self.write(OpcodeKind::Mark, None);
+            self.write(batch, None);
+        }
+    }
+
+    fn save_frozenset(&mut self, items: &[ValueRef], value: &ValueRef) {
+        if self.version() < Version::V4 {
+            let args = tuple(vec![Rc::new(Value::List(items.to_vec()))]);
+            return self.save_reduce(&global("builtins", "frozenset"), &args, None, value);
+        }
+
+        self.write(OpcodeKind::Mark, None);
+        for item in items {
+            self.save(item);
+        }
+        self.write(OpcodeKind::FrozenSet, None);
+        self.memoize(value);
+    }
+
+    fn save_global(&mut self, module: &str, name: &str, value: &ValueRef) {
+        if self.version() >= Version::V4 && self.pick(2) == 0 {
+            self.save(&Rc::new(Value::Name(module.to_string())));
+            // a C type's `__qualname__` is a new str on every access, so it is
+            // never found in the memo
+            let name = if STDLIB_TYPES.contains(&(module, name)) {
+                Value::Str(name.to_string())
+            } else {
+                Value::Name(name.to_string())
+            };
+            self.save(&Rc::new(name));
+            self.write(OpcodeKind::StackGlobal, None);
+        } else {
+            let (module, name) = if self.version() < Version::V3 {
+                fix_imports(module, name)
+            } else {
+                (module.to_string(), name.to_string())
+            };
+            self.write(
+                OpcodeKind::Global,
+                Some(format!("{module}\n{name}\n").as_bytes()),
+            );
+        }
+        self.memoize(value);
+    }
+
+    fn save_object(&mut self, class: &ValueRef, state: Option<&ValueRef>, value: &ValueRef) {
+        use InstanceForm::*;
+
+        // every form ends up calling class.__new__(class)
+        let options: &[InstanceForm] = match self.version() {
+            Version::V0 => &[Reconstructor],
+            Version::V1 => &[Reconstructor, Obj],
+            Version::V2 | Version::V3 => &[NewObj, Reconstructor, Obj],
+            _ => &[NewObj, Reconstructor, Obj, NewObjEx],
+        };
+        match self.choose(options) {
+            Reconstructor => {
+                // copyreg._reduce_ex
+                let args = tuple(vec![
+                    class.clone(),
+                    global("builtins", "object"),
+                    Rc::new(Value::None),
+                ]);
+                let callable = global("copyreg", "_reconstructor");
+                return self.save_reduce(&callable, &args, state, value);
+            }
+            Obj => {
+                self.write(OpcodeKind::Mark, None);
+                self.save(class);
+                self.write(OpcodeKind::Obj, None);
+            }
+            form => {
+                // copyreg.__newobj__ with no arguments
+                self.save(class);
+                self.save(&tuple(Vec::new()));
+                if form == NewObjEx {
+                    self.write(OpcodeKind::EmptyDict, None);
+                    self.write(OpcodeKind::NewObjEx, None);
+                } else {
+                    self.write(OpcodeKind::NewObj, None);
+                }
+            }
+        }
+        self.memoize(value);
+        if let Some(state) = state {
+            self.save(state);
+            self.write(OpcodeKind::Build, None);
+        }
+    }
+
+    fn save_reduce(
+        &mut self,
+        callable: &ValueRef,
+        args: &ValueRef,
+        state: Option<&ValueRef>,
+        value: &ValueRef,
+    ) {
+        self.save(callable);
+        self.save(args);
+        self.write(OpcodeKind::Reduce, None);
+        self.memoize(value);
+        if let Some(state) = state {
+            self.save(state);
+            self.write(OpcodeKind::Build, None);
+        }
+    }
+}
+
+impl Generator {
+    /// generate one pickle of a random object, canonical or with diverse
+    /// encodings.
+    ///
+    /// the opcode range sets the size of the object rather than an exact count:
+    /// an object whose pickle overruns the maximum opcode count, the byte
+    /// limit, or the stack depth limit is rebuilt with half the budget, down to
+    /// a bare None.
+    pub(super) fn generate_from_object(
+        &mut self,
+        source: &mut GenerationSource,
+        sink: Option<&mut dyn Write>,
+    ) -> Result<()> {
+        if !self.mutators.is_empty() {
+            return Err(eyre!(
+                "canonical and diverse encoding modes do not support mutators"
+            ));
+        }
+
+        let (configured_min, configured_max) = self.normalized_opcode_range();
+        let target_total_opcodes = if configured_min == configured_max {
+            configured_max
+        } else {
+            source.gen_range(configured_min, configured_max + 1)
+        };
+        let mut budget = target_total_opcodes.saturating_sub(self.fixed_opcode_count(false))
+            / OPCODES_PER_OBJECT;
+
+        self.begin_step("proto");
+        self.emit_proto(source);
+        self.begin_step(if self.diverse_encodings {
+            "diverse encodings"
+        } else {
+            "canonical"
+        });
+        let proto_opcodes = usize::from(self.state.proto_emitted);
+        let start_state = self.state.clone();
+        let start_len = self.output.len();
+        let diverse = self.diverse_encodings;
+        loop {
+            let root = ObjectBuilder::new(self, budget).root(source)?;
+            let pickler = CanonicalPickler::new(self, diverse.then_some(&mut *source));
+            let total_opcodes = proto_opcodes + pickler.dump(&root);
+
+            let fits = total_opcodes <= configured_max
+                && self.bufsize.is_none_or(|limit| self.output.len() <= limit)
+                && self
+                    .max_stack_depth
+                    .is_none_or(|limit| self.state.stack.peak_len() <= limit);
+            if fits || budget == 0 {
+                self.emitted_opcodes = total_opcodes;
+                break;
+            }
+            self.state = start_state.clone();
+            self.output.truncate(start_len);
+            self.strict_violation = None;
+            budget /= 2;
+        }
+        self.take_strict_violation()?;
+
+        match sink {
+            Some(sink) => self.stream_output(sink)?,
+            None => self.annotate_output(),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disasm::{disassemble, validate};
+    use crate::generator::SizeDistribution;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    /// pickle `value` on a fresh, strictly checked generator, with encodings
+    /// drawn from `diverse_seed` if there is one.
+    fn dump_with(version: Version, value: &ValueRef, diverse_seed: Option<u64>) -> Vec<u8> {
+        let mut generator = Generator::new(version).with_strict_checks(true);
+        let mut rng = ChaCha8Rng::seed_from_u64(diverse_seed.unwrap_or(7));
+        let mut source = GenerationSource::Rand(&mut rng);
+
+        generator.emit_proto(&mut source);
+        let diverse = diverse_seed.map(|_| &mut source);
+        CanonicalPickler::new(&mut generator, diverse).dump(value);
+        generator.take_strict_violation().unwrap();
+        validate(&generator.output).unwrap();
+        generator.output
+    }
+
+    fn dump(version: Version, value: &ValueRef) -> Vec<u8> {
+        dump_with(version, value, None)
+    }
+
+    fn str(text: &str) -> ValueRef {
+        Rc::new(Value::Str(text.to_string()))
+    }
+
+    fn name(text: &str) -> ValueRef {
+        Rc::new(Value::Name(text.to_string()))
+    }
+
+    fn int(value: i128) -> ValueRef {
+        Rc::new(Value::Int(value))
+    }
+
+    fn list(items: Vec<ValueRef>) -> ValueRef {
+        Rc::new(Value::List(items))
+    }
+
+    /// how often each opcode in `names` occurs in `pickle`.
+    fn counts(pickle: &[u8], names: &[&str]) -> Vec<usize> {
+        let instructions = disassemble(pickle).unwrap();
+        names
+            .iter()
+            .map(|name| instructions.iter().filter(|i| i.name == *name).count())
+            .collect()
+    }
+
+    // the expected bytes below are what CPython 3.11's pickle.dumps writes
+
+    #[test]
+    fn repeated_and_singleton_strings_share_memo_entries() {
+        // [x, x, chr(97), y, z] with x = chr(97) and y, z two separate "ab"s
+        let a = str("a");
+        let value = list(vec![a.clone(), a, str("a"), str("ab"), str("ab")]);
+        assert_eq!(
+            dump(Version::V0, &value),
+            b"(lp0\nVa\np1\nag1\nag1\naVab\np2\naVab\np3\na."
+        );
+        assert_eq!(
+            dump(Version::V2, &value),
+            b"\x80\x02]q\x00(X\x01\x00\x00\x00aq\x01h\x01h\x01X\x02\x00\x00\x00abq\x02X\x02\x00\x00\x00abq\x03e."
+        );
+        assert_eq!(
+            dump(Version::V4, &value),
+            b"\x80\x04\x95\x17\x00\x00\x00\x00\x00\x00\x00]\x94(\x8c\x01a\x94h\x01h\x01\x8c\x02ab\x94\x8c\x02ab\x94e."
+        );
+    }
+
+    #[test]
+    fn integers_take_the_shortest_opcode() {
+        let value = list(
+            [
+                0,
+                255,
+                256,
+                65535,
+                65536,
+                -1,
+                (1 << 31) - 1,
+                1 << 31,
+                -(1 << 31) - 1,
+            ]
+            .into_iter()
+            .chain([1 << 64, -(1 << 100)])
+            .map(int)
+            .collect(),
+        );
+        assert_eq!(
+            dump(Version::V0, &value),
+            b"(lp0\nI0\naI255\naI256\naI65535\naI65536\naI-1\naI2147483647\naL2147483648L\naL-2147483649L\naL18446744073709551616L\naL-1267650600228229401496703205376L\na."
+        );
+        assert_eq!(
+            dump(Version::V1, &value),
+            b"]q\x00(K\x00K\xffM\x00\x01M\xff\xffJ\x00\x00\x01\x00J\xff\xff\xff\xffJ\xff\xff\xff\x7fL2147483648L\nL-2147483649L\nL18446744073709551616L\nL-1267650600228229401496703205376L\ne."
+        );
+        assert_eq!(
+            dump(Version::V2, &value),
+            b"\x80\x02]q\x00(K\x00K\xffM\x00\x01M\xff\xffJ\x00\x00\x01\x00J\xff\xff\xff\xffJ\xff\xff\xff\x7f\x8a\x05\x00\x00\x00\x80\x00\x8a\x05\xff\xff\xff\x7f\xff\x8a\t\x00\x00\x00\x00\x00\x00\x00\x00\x01\x8a\r\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\xf0e."
+        );
+    }
+
+    #[test]
+    fn protocol_0_escapes_unicode_like_cpython() {
+        assert_eq!(
+            dump(Version::V0, &str("a\\\n\0\r\x1a\u{e9}\u{101}\u{1f600}")),
+            b"Va\\u005c\\u000a\\u0000\\u000d\\u001a\xe9\\u0101\\U0001f600\np0\n."
+        );
+    }
+
+    #[test]
+    fn instances_follow_object_reduce_ex() {
+        // an instance of class m.C with o.a = 1
+        let class = global("m", "C");
+        let state = Rc::new(Value::Dict(vec![(name("a"), int(1))]));
+        let object = Rc::new(Value::Object {
+            class: class.clone(),
+            state: Some(state),
+        });
+        let expected: [&[u8]; 6] = [
+            b"ccopy_reg\n_reconstructor\np0\n(cm\nC\np1\nc__builtin__\nobject\np2\nNtp3\nRp4\n(dp5\nVa\np6\nI1\nsb.",
+            b"ccopy_reg\n_reconstructor\nq\x00(cm\nC\nq\x01c__builtin__\nobject\nq\x02Ntq\x03Rq\x04}q\x05X\x01\x00\x00\x00aq\x06K\x01sb.",
+            b"\x80\x02cm\nC\nq\x00)\x81q\x01}q\x02X\x01\x00\x00\x00aq\x03K\x01sb.",
+            b"\x80\x03cm\nC\nq\x00)\x81q\x01}q\x02X\x01\x00\x00\x00aq\x03K\x01sb.",
+            b"\x80\x04\x95\x18\x00\x00\x00\x00\x00\x00\x00\x8c\x01m\x94\x8c\x01C\x94\x93\x94)\x81\x94}\x94\x8c\x01a\x94K\x01sb.",
+            b"\x80\x05\x95\x18\x00\x00\x00\x00\x00\x00\x00\x8c\x01m\x94\x8c\x01C\x94\x93\x94)\x81\x94}\x94\x8c\x01a\x94K\x01sb.",
+        ];
+        for (version, expected) in Version::all().zip(expected) {
+            assert_eq!(
+                dump(version, &object),
+                expected,
+                "protocol {}",
+                version as u8
+            );
+        }
+
+        let empty = Rc::new(Value::Object { class, state: None });
+        assert_eq!(
+            dump(Version::V2, &empty),
+            b"\x80\x02cm\nC\nq\x00)\x81q\x01."
+        );
+    }
+
+    #[test]
+    fn older_protocols_reduce_newer_types() {
+        let bytes = list(vec![
+            Rc::new(Value::Bytes(b"ab".to_vec())),
+            Rc::new(Value::Bytes(Vec::new())),
+        ]);
+        assert_eq!(
+            dump(Version::V2, &bytes),
+            b"\x80\x02]q\x00(c_codecs\nencode\nq\x01X\x02\x00\x00\x00abq\x02X\x06\x00\x00\x00latin1q\x03\x86q\x04Rq\x05c__builtin__\nbytes\nq\x06)Rq\x07e."
+        );
+
+        let frozenset = Rc::new(Value::FrozenSet(vec![int(1), int(2)]));
+        assert_eq!(
+            dump(Version::V3, &frozenset),
+            b"\x80\x03cbuiltins\nfrozenset\nq\x00]q\x01(K\x01K\x02e\x85q\x02Rq\x03."
+        );
+
+        let set = Rc::new(Value::Set(vec![int(1), int(2)]));
+        assert_eq!(
+            dump(Version::V2, &set),
+            b"\x80\x02c__builtin__\nset\nq\x00]q\x01(K\x01K\x02e\x85q\x02Rq\x03."
+        );
+        assert_eq!(
+            dump(Version::V4, &set),
+            b"\x80\x04\x95\t\x00\x00\x00\x00\x00\x00\x00\x8f\x94(K\x01K\x02\x90."
+        );
+
+        let bytearray = Rc::new(Value::ByteArray(b"ab".to_vec()));
+        assert_eq!(
+            dump(Version::V4, &bytearray),
+            b"\x80\x04\x95#\x00\x00\x00\x00\x00\x00\x00\x8c\x08builtins\x94\x8c\tbytearray\x94\x93\x94C\x02ab\x94\x85\x94R\x94."
+        );
+        assert_eq!(
+            dump(Version::V5, &bytearray),
+            b"\x80\x05\x95\r\x00\x00\x00\x00\x00\x00\x00\x96\x02\x00\x00\x00\x00\x00\x00\x00ab\x94."
+        );
+        assert_eq!(
+            dump(Version::V3, &Rc::new(Value::ByteArray(Vec::new()))),
+            b"\x80\x03cbuiltins\nbytearray\nq\x00)Rq\x01."
+        );
+    }
+
+    #[test]
+    fn globals_are_renamed_below_protocol_3() {
+        let range = global("builtins", "range");
+        assert_eq!(
+            dump(
+                Version::V2,
+                &list(vec![range.clone(), global("builtins", "range")])
+            ),
+            b"\x80\x02]q\x00(c__builtin__\nxrange\nq\x01h\x01e."
+        );
+        assert_eq!(
+            dump(Version::V4, &range),
+            b"\x80\x04\x95\x16\x00\x00\x00\x00\x00\x00\x00\x8c\x08builtins\x94\x8c\x05range\x94\x93\x94."
+        );
+        assert_eq!(
+            fix_imports("copyreg", "_reconstructor"),
+            ("copy_reg".to_string(), "_reconstructor".to_string())
+        );
+    }
+
+    #[test]
+    fn tuples_and_dicts_match_cpython() {
+        let empty = tuple(Vec::new());
+        let one = tuple(vec![int(1)]);
+        let value = tuple(vec![
+            empty.clone(),
+            one.clone(),
+            tuple(vec![int(1), int(2), int(3)]),
+            tuple(vec![int(1), int(2), int(3), int(4)]),
+        ]);
+        assert_eq!(
+            dump(Version::V2, &value),
+            b"\x80\x02()K\x01\x85q\x00K\x01K\x02K\x03\x87q\x01(K\x01K\x02K\x03K\x04tq\x02tq\x03."
+        );
+        assert_eq!(
+            dump(Version::V0, &tuple(vec![empty, one])),
+            b"((t(I1\ntp0\ntp1\n."
+        );
+
+        let true_ = Rc::new(Value::Bool(true));
+        let dict = Rc::new(Value::Dict(vec![
+            (str("k"), true_.clone()),
+            (str("j"), Rc::new(Value::Bool(false))),
+        ]));
+        assert_eq!(
+            dump(Version::V0, &dict),
+            b"(dp0\nVk\np1\nI01\nsVj\np2\nI00\ns."
+        );
+        let dict = Rc::new(Value::Dict(vec![(str("k"), true_)]));
+        assert_eq!(
+            dump(Version::V1, &dict),
+            b"}q\x00X\x01\x00\x00\x00kq\x01I01\ns."
+        );
+    }
+
+    #[test]
+    fn stdlib_instances_match_cpython() {
+        let reduce = |module, name, args| {
+            Rc::new(Value::Reduce {
+                callable: global(module, name),
+                args: tuple(args),
+                state: None,
+            })
+        };
+        let bytes = |bytes: &[u8]| Rc::new(Value::Bytes(bytes.to_vec()));
+        let float = |float| Rc::new(Value::Float(float));
+        let ordered_dict = |entries| Rc::new(Value::OrderedDict(entries));
+        // [datetime(2024, 5, 6, 7, 8, 9, 123456), date(2020, 1, 2),
+        //  timedelta(-3, 2, 1), Decimal('1.25'), 1+2j,
+        //  OrderedDict(a=1, b=2), OrderedDict(c=3)]
+        let value = list(vec![
+            reduce(
+                "datetime",
+                "datetime",
+                vec![bytes(b"\x07\xe8\x05\x06\x07\x08\x09\x01\xe2\x40")],
+            ),
+            reduce("datetime", "date", vec![bytes(b"\x07\xe4\x01\x02")]),
+            reduce("datetime", "timedelta", vec![int(-3), int(2), int(1)]),
+            reduce("decimal", "Decimal", vec![str("1.25")]),
+            reduce("builtins", "complex", vec![float(1.0), float(2.0)]),
+            ordered_dict(vec![(str("a"), int(1)), (str("b"), int(2))]),
+            ordered_dict(vec![(str("c"), int(3))]),
+        ]);
+
+        assert_eq!(
+            dump(Version::V0, &value),
+            b"(lp0\ncdatetime\ndatetime\np1\n(c_codecs\nencode\np2\n(V\x07\xe8\x05\x06\x07\x08\t\x01\xe2@\np3\nVlatin1\np4\ntp5\nRp6\ntp7\nRp8\nacdatetime\ndate\np9\n(g2\n(V\x07\xe4\x01\x02\np10\ng4\ntp11\nRp12\ntp13\nRp14\nacdatetime\ntimedelta\np15\n(I-3\nI2\nI1\ntp16\nRp17\nacdecimal\nDecimal\np18\n(V1.25\np19\ntp20\nRp21\nac__builtin__\ncomplex\np22\n(F1.0\nF2.0\ntp23\nRp24\naccollections\nOrderedDict\np25\n(tRp26\nVa\np27\nI1\nsVb\np28\nI2\nsag25\n(tRp29\nVc\np30\nI3\nsa."
+        );
+        assert_eq!(
+            dump(Version::V2, &value),
+            b"\x80\x02]q\x00(cdatetime\ndatetime\nq\x01c_codecs\nencode\nq\x02X\x0c\x00\x00\x00\x07\xc3\xa8\x05\x06\x07\x08\t\x01\xc3\xa2@q\x03X\x06\x00\x00\x00latin1q\x04\x86q\x05Rq\x06\x85q\x07Rq\x08cdatetime\ndate\nq\th\x02X\x05\x00\x00\x00\x07\xc3\xa4\x01\x02q\nh\x04\x86q\x0bRq\x0c\x85q\rRq\x0ecdatetime\ntimedelta\nq\x0fJ\xfd\xff\xff\xffK\x02K\x01\x87q\x10Rq\x11cdecimal\nDecimal\nq\x12X\x04\x00\x00\x001.25q\x13\x85q\x14Rq\x15c__builtin__\ncomplex\nq\x16G?\xf0\x00\x00\x00\x00\x00\x00G@\x00\x00\x00\x00\x00\x00\x00\x86q\x17Rq\x18ccollections\nOrderedDict\nq\x19)Rq\x1a(X\x01\x00\x00\x00aq\x1bK\x01X\x01\x00\x00\x00bq\x1cK\x02uh\x19)Rq\x1dX\x01\x00\x00\x00cq\x1eK\x03se."
+        );
+        // the interned module names come back from the memo, the C types'
+        // names do not
+        assert_eq!(
+            dump(Version::V4, &value),
+            b"\x80\x04\x95\xea\x00\x00\x00\x00\x00\x00\x00]\x94(\x8c\x08datetime\x94\x8c\x08datetime\x94\x93\x94C\n\x07\xe8\x05\x06\x07\x08\t\x01\xe2@\x94\x85\x94R\x94h\x01\x8c\x04date\x94\x93\x94C\x04\x07\xe4\x01\x02\x94\x85\x94R\x94h\x01\x8c\ttimedelta\x94\x93\x94J\xfd\xff\xff\xffK\x02K\x01\x87\x94R\x94\x8c\x07decimal\x94\x8c\x07Decimal\x94\x93\x94\x8c\x041.25\x94\x85\x94R\x94\x8c\x08builtins\x94\x8c\x07complex\x94\x93\x94G?\xf0\x00\x00\x00\x00\x00\x00G@\x00\x00\x00\x00\x00\x00\x00\x86\x94R\x94\x8c\x0bcollections\x94\x8c\x0bOrderedDict\x94\x93\x94)R\x94(\x8c\x01a\x94K\x01\x8c\x01b\x94K\x02uh\x1d)R\x94\x8c\x01c\x94K\x03se."
+        );
+    }
+
+    #[test]
+    fn batches_hold_at_most_1000_items() {
+        let ints = |len: i128| (0..len).map(int).collect::<Vec<_>>();
+        let names = ["MARK", "APPENDS", "SETITEMS", "ADDITEMS", "APPEND"];
+
+        assert_eq!(
+            counts(&dump(Version::V2, &list(ints(1))), &names),
+            [0, 0, 0, 0, 1]
+        );
+        assert_eq!(
+            counts(&dump(Version::V2, &list(ints(1000))), &names),
+            [1, 1, 0, 0, 0]
+        );
+        assert_eq!(
+            counts(&dump(Version::V2, &list(ints(1001))), &names),
+            [2, 2, 0, 0, 0]
+        );
+
+        // a full last batch is followed by an empty one for dicts and sets
+        let entries = ints(1000)
+            .into_iter()
+            .map(|key| (key, Rc::new(Value::None)))
+            .collect();
+        let dict = Rc::new(Value::Dict(entries));
+        assert_eq!(counts(&dump(Version::V2, &dict), &names), [2, 0, 2, 0, 0]);
+        let set = Rc::new(Value::Set(ints(1000)));
+        assert_eq!(counts(&dump(Version::V4, &set), &names), [2, 0, 0, 2, 0]);
+    }
+
+    #[test]
+    fn frames_close_once_they_reach_64_kib() {
+        // [bytes([i]) * 1000 for i in range(70)]
+        let value = list(
+            (0..70)
+                .map(|byte| Rc::new(Value::Bytes(vec![byte; 1000])))
+                .collect(),
+        );
+        let pickle = dump(Version::V4, &value);
+        assert_eq!(pickle.len(), 70445);
+
+        let frame_len = |at: usize| {
+            assert_eq!(pickle[at], OpcodeKind::Frame.as_u8());
+            u64::from_le_bytes(pickle[at + 1..at + FRAME_HEADER_SIZE].try_into().unwrap())
+        };
+        assert_eq!(frame_len(2), 66399);
+        assert_eq!(frame_len(2 + FRAME_HEADER_SIZE + 66399), 4026);
+    }
+
+    /// opcode names across diverse pickles of `value` for 64 seeds.
+    fn diverse_opcodes(version: Version, value: &ValueRef) -> HashSet<&'static str> {
+        (0..64)
+            .flat_map(|seed| disassemble(&dump_with(version, value, Some(seed))).unwrap())
+            .map(|instruction| instruction.name)
+            .collect()
+    }
+
+    #[test]
+    fn diverse_encodings_cover_every_valid_encoding() {
+        let seven = int(7);
+        let opcodes = diverse_opcodes(Version::V4, &list(vec![seven.clone(), seven]));
+        for name in [
+            "BININT1", "BININT2", "BININT", "LONG1", "LONG4", "INT", "LONG",
+        ] {
+            assert!(opcodes.contains(name), "{name} missing from {opcodes:?}");
+        }
+
+        let a = str("a");
+        let value = list(vec![a.clone(), a, Rc::new(Value::Bytes(b"b".to_vec()))]);
+        let opcodes = diverse_opcodes(Version::V4, &value);
+        for name in [
+            "SHORT_BINUNICODE",
+            "BINUNICODE",
+            "BINUNICODE8",
+            "UNICODE",
+            "SHORT_BINBYTES",
+            "BINBYTES",
+            "BINBYTES8",
+            "MEMOIZE",
+            "BINPUT",
+            "LONG_BINPUT",
+            "PUT",
+            "BINGET",
+            "LONG_BINGET",
+            "GET",
+            "APPEND",
+            "APPENDS",
+            "LIST",
+        ] {
+            assert!(opcodes.contains(name), "{name} missing from {opcodes:?}");
+        }
+
+        let object = Rc::new(Value::Object {
+            class: global("m", "C"),
+            state: None,
+        });
+        let opcodes = diverse_opcodes(Version::V4, &object);
+        for name in [
+            "NEWOBJ",
+            "NEWOBJ_EX",
+            "OBJ",
+            "REDUCE",
+            "STACK_GLOBAL",
+            "GLOBAL",
+        ] {
+            assert!(opcodes.contains(name), "{name} missing from {opcodes:?}");
+        }
+    }
+
+    #[test]
+    fn diverse_encodings_only_sometimes_frame() {
+        let value = list((0..8).map(int).collect());
+        let framed = (0..32)
+            .map(|seed| dump_with(Version::V5, &value, Some(seed)))
+            .filter(|pickle| pickle[2] == OpcodeKind::Frame.as_u8())
+            .count();
+        assert!(0 < framed && framed < 32, "{framed} of 32 framed");
+    }
+
+    #[test]
+    fn diverse_batches_exceed_the_batch_size_only_when_oversized() {
+        let value = list((0..2500).map(int).collect());
+        for oversized in [false, true] {
+            let mut largest = 0;
+            for seed in 0..8 {
+                let mut generator = Generator::new(Version::V4).with_oversized_batches(oversized);
+                let mut rng = ChaCha8Rng::seed_from_u64(seed);
+                let mut source = GenerationSource::Rand(&mut rng);
+                generator.emit_proto(&mut source);
+                CanonicalPickler::new(&mut generator, Some(&mut source)).dump(&value);
+                validate(&generator.output).unwrap();
+
+                let mut batch = 0;
+                for instruction in disassemble(&generator.output).unwrap() {
+                    match instruction.name {
+                        "MARK" => batch = 0,
+                        "APPENDS" => largest = largest.max(batch),
+                        "BININT1" | "BININT2" | "BININT" | "INT" | "LONG1" | "LONG4" | "LONG" => {
+                            batch += 1
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            assert_eq!(largest > BATCH_SIZE, oversized, "largest batch {largest}");
+        }
+    }
+
+    #[test]
+    fn drawn_container_sizes_apply_to_nested_containers() {
+        /// lengths of every list, tuple, dict, and set below the root, and the
+        /// instance states and arguments, which keep their own sizes even when
+        /// they are reused as values
+        fn walk(
+            value: &ValueRef,
+            depth: usize,
+            lens: &mut Vec<(*const Value, usize)>,
+            own_sizes: &mut HashSet<*const Value>,
+        ) {
+            let children: Vec<ValueRef> = match &**value {
+                Value::List(items)
+                | Value::Tuple(items)
+                | Value::Set(items)
+                | Value::FrozenSet(items) => items.clone(),
+                Value::Dict(entries) => entries.iter().map(|(_, value)| value.clone()).collect(),
+                Value::Object { state, .. } => state.iter().cloned().collect(),
+                Value::Reduce { args, state, .. } => {
+                    std::iter::once(args).chain(state).cloned().collect()
+                }
+                _ => return,
+            };
+            if matches!(&**value, Value::Object { .. } | Value::Reduce { .. }) {
+                own_sizes.extend(children.iter().map(Rc::as_ptr));
+            } else if depth > 0 {
+                lens.push((Rc::as_ptr(value), children.len()));
+            }
+            for child in &children {
+                walk(child, depth + 1, lens, own_sizes);
+            }
+        }
+
+        let generator =
+            Generator::new(Version::V4).with_container_sizes(SizeDistribution::Constant(3));
+        let (mut lens, mut own_sizes) = (Vec::new(), HashSet::new());
+        for seed in 0..16 {
+            let mut rng = ChaCha8Rng::seed_from_u64(seed);
+            let mut source = GenerationSource::Rand(&mut rng);
+            let root = ObjectBuilder::new(&generator, 200)
+                .root(&mut source)
+                .unwrap();
+            walk(&root, 0, &mut lens, &mut own_sizes);
+        }
+        lens.retain(|(value, _)| !own_sizes.contains(value));
+        assert!(lens.len() > 16, "{lens:?}");
+        assert!(lens.iter().all(|&(_, len)| len == 3), "{lens:?}");
+    }
+}
@@ -14,142 +14,229 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
+use std::io::Write;
+
 use color_eyre::Result;
 
-use super::source::{EntropySource, GenerationSource};
+use super::source::{EntropySource, ExhaustionPolicy, GenerationSource};
 use super::Generator;
 use super::Version;
 use crate::opcodes::OpcodeKind;
-use crate::stack::StackObject;
+use crate::stack::ContainerKind;
+
+/// bytes the output buffer collects before a streaming run hands them to its writer.
+const STREAM_CHUNK_SIZE: usize = 64 * 1024;
 
 impl Generator {
-    fn fixed_opcode_count(&self, use_frame: bool) -> usize {
-        usize::from(!matches!(self.state.version, Version::V0 | Version::V1))
-            + usize::from(use_frame)
-            + 1
+    pub(super) fn fixed_opcode_count(&self, use_frame: bool) -> usize {
+        usize::from(self.writes_proto()) + usize::from(use_frame) + 1
     }
 
     fn minimum_total_opcode_count(&self, use_frame: bool) -> usize {
-        self.fixed_opcode_count(use_frame) + Self::cleanup_opcode_count_for_shape(Vec::new())
+        self.fixed_opcode_count(use_frame) + self.cleanup_opcode_count_for(0, 0, None)
     }
 
-    fn current_cleanup_opcode_count(&self) -> usize {
-        let stack_shape = self
-            .state
-            .stack
-            .inner
-            .iter()
-            .map(|obj| matches!(*obj.borrow(), StackObject::Mark))
-            .collect();
+    /// smallest complete pickle in bytes: optional PROTO (2 bytes), NONE and STOP.
+    pub(super) fn minimum_pickle_size(&self) -> usize {
+        let proto_size = if self.writes_proto() { 2 } else { 0 };
 
-        Self::cleanup_opcode_count_for_shape(stack_shape)
+        proto_size + 2
     }
 
-    fn cleanup_opcode_count_after(&self, opcode: OpcodeKind) -> usize {
-        let mut stack_shape: Vec<bool> = self
-            .state
-            .stack
-            .inner
-            .iter()
-            .map(|obj| matches!(*obj.borrow(), StackObject::Mark))
-            .collect();
-
-        Self::apply_abstract_stack_effect(&mut stack_shape, opcode);
-        Self::cleanup_opcode_count_for_shape(stack_shape)
+    /// whether the output still fits the byte limit once the current stack has been
+    /// cleaned up and STOP appended. cleanup opcodes are all single-byte, and the
+    /// strict comparison leaves room for the STOP byte.
+    pub(super) fn fits_byte_limit(&self, cleanup_opcodes: usize) -> bool {
+        self.bufsize
+            .is_none_or(|limit| self.output_len() + cleanup_opcodes < limit)
     }
 
-    fn cleanup_opcode_count_for_shape(mut stack_shape: Vec<bool>) -> usize {
-        let mut cleanup_opcodes = 0;
+    /// length of the pickle so far, including bytes already streamed out.
+    pub(super) fn output_len(&self) -> usize {
+        self.streamed_len + self.output.len()
+    }
 
-        while let Some(mark_idx) = stack_shape.iter().rposition(|is_mark| *is_mark) {
-            stack_shape.truncate(mark_idx);
-            stack_shape.push(false);
-            cleanup_opcodes += 1;
-        }
+    /// hand the buffered output to `sink` and start a new chunk.
+    pub(super) fn stream_output(&mut self, sink: &mut dyn Write) -> Result<()> {
+        self.annotate_output();
+        sink.write_all(&self.output)?;
+        self.streamed_len += self.output.len();
+        self.output.clear();
+        Ok(())
+    }
 
-        if stack_shape.is_empty() {
-            cleanup_opcodes + 1
-        } else {
-            cleanup_opcodes + (stack_shape.len() / 2)
-        }
+    pub(super) fn current_cleanup_opcode_count(&self) -> usize {
+        let stack = &self.state.stack;
+        self.cleanup_opcode_count_for(stack.len(), stack.mark_positions().len(), None)
+    }
+
+    /// container kind of the object directly below the stack slot at `pos`.
+    fn kind_below(&self, pos: usize) -> Option<ContainerKind> {
+        let below = pos.checked_sub(1)?;
+        self.state.stack.items()[below].borrow().container_kind()
     }
 
-    fn apply_abstract_stack_effect(stack_shape: &mut Vec<bool>, opcode: OpcodeKind) {
+    /// cleanup cost of the stack as it would look after emitting `opcode`.
+    ///
+    /// only the stack length, MARK positions, and the containers directly below
+    /// MARKs matter for cleanup, so instead of simulating the opcode this applies
+    /// its abstract effect to those: pop some items (dropping any MARKs among
+    /// them), or pop through the topmost MARK, then optionally push one item.
+    /// opcodes never touch the objects below the MARKs that survive them.
+    pub(super) fn cleanup_opcode_count_after(&self, opcode: OpcodeKind) -> usize {
         use OpcodeKind::*;
 
-        match opcode {
-            Pop => {
-                stack_shape.pop();
-            }
-            Dup => {
-                if !stack_shape.last().copied().unwrap_or(false) {
-                    stack_shape.push(false);
-                }
-            }
-            Mark => {
-                stack_shape.push(true);
-            }
-            Append => {
-                stack_shape.pop();
-            }
-            Appends | SetItems | AddItems => {
-                while let Some(is_mark) = stack_shape.pop() {
-                    if is_mark {
-                        break;
-                    }
-                }
-            }
-            Tuple | List | FrozenSet | Dict | Inst | Obj => {
-                while let Some(is_mark) = stack_shape.pop() {
-                    if is_mark {
-                        break;
-                    }
-                }
-                stack_shape.push(false);
-            }
-            PopMark => {
-                while let Some(is_mark) = stack_shape.pop() {
-                    if is_mark {
-                        break;
-                    }
-                }
-            }
-            Tuple1 | Memoize | BinPersID => {
-                stack_shape.pop();
-                stack_shape.push(false);
-            }
-            Tuple2 | Reduce | NewObj | Build | StackGlobal => {
-                stack_shape.pop();
-                stack_shape.pop();
-                stack_shape.push(false);
+        let marks = self.state.stack.mark_positions();
+        let mut len = self.state.stack.len();
+        // marks[..kept] are the MARKs still on the stack after the opcode
+        let mut kept = marks.len();
+
+        let pop = |len: &mut usize, kept: &mut usize, count: usize| {
+            *len = len.saturating_sub(count);
+            while *kept > 0 && marks[*kept - 1] >= *len {
+                *kept -= 1;
             }
-            Tuple3 | NewObjEx => {
-                stack_shape.pop();
-                stack_shape.pop();
-                stack_shape.pop();
-                stack_shape.push(false);
+        };
+        let pop_through_mark = |len: &mut usize, kept: &mut usize| {
+            if *kept > 0 {
+                *kept -= 1;
+                *len = marks[*kept];
+            } else {
+                *len = 0;
             }
-            SetItem => {
-                stack_shape.pop();
-                stack_shape.pop();
+        };
+
+        // (items popped before the push, whether the opcode pops through a MARK,
+        // what it pushes: None for nothing, Some(true) for a MARK)
+        let (popped, through_mark, pushed) = match opcode {
+            Pop | Append => (1, false, Option::None),
+            Dup => {
+                let top_is_mark = marks.last().is_some_and(|&idx| idx + 1 == len);
+                (0, false, (len > 0 && !top_is_mark).then_some(false))
             }
+            Mark => (0, false, Some(true)),
+            Appends | SetItems | AddItems | PopMark => (0, true, Option::None),
+            Tuple | List | FrozenSet | Dict | Inst | Obj => (0, true, Some(false)),
+            Tuple1 | Memoize | BinPersID => (1, false, Some(false)),
+            Tuple2 | Reduce | NewObj | Build | StackGlobal => (2, false, Some(false)),
+            Tuple3 | NewObjEx => (3, false, Some(false)),
+            SetItem => (2, false, Option::None),
             Get | BinGet | LongBinGet | None | NewTrue | NewFalse | Int | Long | Long1 | Long4
             | BinInt | BinInt1 | BinInt2 | Float | BinFloat | String | BinString
             | ShortBinString | Unicode | ShortBinUnicode | BinUnicode | BinUnicode8
             | ShortBinBytes | BinBytes | BinBytes8 | ByteArray8 | EmptyList | EmptyDict
             | EmptyTuple | EmptySet | Global | PersID | Ext1 | Ext2 | Ext4 | NextBuffer => {
-                stack_shape.push(false);
+                (0, false, Some(false))
+            }
+            Put | BinPut | LongBinPut | Proto | ReadOnlyBuffer | Stop | Frame => {
+                (0, false, Option::None)
             }
-            Put | BinPut | LongBinPut | Proto | ReadOnlyBuffer | Stop | Frame => {}
+        };
+
+        if through_mark {
+            pop_through_mark(&mut len, &mut kept);
+        } else {
+            pop(&mut len, &mut kept, popped);
+        }
+
+        let mut pushed_mark = Option::None;
+        if let Some(is_mark) = pushed {
+            if is_mark {
+                pushed_mark = Some((len, self.kind_below(len)));
+            }
+            len += 1;
         }
+
+        self.cleanup_opcode_count_for(len, kept, pushed_mark)
     }
 
+    /// number of opcodes `cleanup_for_stop` emits for a stack of `len` items whose
+    /// MARKs are the first `kept` current MARK positions, plus an optional MARK
+    /// pushed on top at the given position with the given container below it.
+    ///
+    /// every MARK costs exactly one closing opcode. walking from the topmost MARK
+    /// down tells which ones close by folding into the container below (leaving
+    /// nothing behind) and which leave a tuple, which fixes how many plain items
+    /// remain for the final POP/TUPLE reduction.
+    pub(super) fn cleanup_opcode_count_for(
+        &self,
+        len: usize,
+        kept: usize,
+        pushed_mark: Option<(usize, Option<ContainerKind>)>,
+    ) -> usize {
+        let marks = &self.state.stack.mark_positions()[..kept];
+        let mark_count = kept + usize::from(pushed_mark.is_some());
+
+        // (mark position, container below it) from the top down
+        let mut remaining = len;
+        let mut segment_end = len;
+        let mut produced = 0;
+        let top_down = pushed_mark
+            .into_iter()
+            .chain(marks.iter().rev().map(|&pos| (pos, self.kind_below(pos))));
+        for (pos, below) in top_down {
+            let items = segment_end - pos - 1 + produced;
+            produced = usize::from(self.mark_closing_opcode(below, items) == OpcodeKind::Tuple);
+            segment_end = pos;
+            remaining = pos + produced;
+        }
+
+        let reduction = if remaining == 0 {
+            1
+        } else if self.pops_extra_items() {
+            remaining - 1
+        } else {
+            remaining / 2
+        };
+        mark_count + reduction
+    }
+
+    /// run one full generation pass, leaving the finished pickle in `self.output`.
+    ///
+    /// with a `sink`, completed emissions are written to it in chunks instead and
+    /// `self.output` ends up empty. a framed pickle is held back until STOP, since
+    /// its FRAME length is only known then.
     pub(super) fn generate_internal(
         &mut self,
         source: &mut GenerationSource,
-        target_total_override: Option<usize>,
-        force_frame: Option<bool>,
-    ) -> Result<Vec<u8>> {
+        sink: Option<&mut dyn Write>,
+    ) -> Result<()> {
+        let _generate = tracing::debug_span!(
+            "generate",
+            protocol = self.state.version as u8,
+            seed = self.seed
+        )
+        .entered();
+        let result = self.generate_pass(source, sink);
+        match &result {
+            Ok(()) => tracing::debug!(
+                bytes = self.output_len(),
+                mutated_emissions = self.mutated_emissions,
+                "generated"
+            ),
+            Err(error) => tracing::debug!(%error, "generation failed"),
+        }
+        result
+    }
+
+    fn generate_pass(
+        &mut self,
+        source: &mut GenerationSource,
+        mut sink: Option<&mut dyn Write>,
+    ) -> Result<()> {
+        self.start_time_budget();
+        if let Some(limit) = self.bufsize {
+            let minimum_size = self.minimum_pickle_size();
+            if limit < minimum_size {
+                return Err(color_eyre::eyre::eyre!(
+                    "buffer size {} is too small for protocol {} (minimum valid pickle size is {})",
+                    limit,
+                    self.state.version as u8,
+                    minimum_size
+                ));
+            }
+        }
+
         let (configured_min, configured_max) = self.normalized_opcode_range();
         let minimum_total_without_frame = self.minimum_total_opcode_count(false);
         if configured_max < minimum_total_without_frame {
@@ -162,24 +249,26 @@ impl Generator {
             ));
         }
 
-        let requested_budget = target_total_override.unwrap_or(configured_max);
-        let use_frame = match force_frame {
-            Some(force_frame) => {
-                force_frame
-                    && self.state.version >= Version::V4
-                    && requested_budget >= self.minimum_total_opcode_count(true)
-            }
-            None => {
-                self.state.version >= Version::V4
-                    && requested_budget >= self.minimum_total_opcode_count(true)
-                    && source.gen_bool()
-            }
-        };
+        if self.canonical || self.diverse_encodings {
+            return self.generate_from_object(source, sink);
+        }
+
+        // a FRAME costs 9 bytes, so only consider one if the byte limit can still
+        // hold the smallest framed pickle
+        let frame_fits = self
+            .bufsize
+            .is_none_or(|limit| limit >= self.minimum_pickle_size() + 9);
+        let use_frame = self.state.version >= Version::V4
+            && frame_fits
+            && configured_max >= self.minimum_total_opcode_count(true)
+            && source.gen_bool();
 
+        self.begin_step("proto");
         self.emit_proto(source);
 
         // reserve space for FRAME if we're going to use it
         let frame_position = if use_frame {
+            self.begin_step("frame");
             let pos = self.output.len();
             // reserve 9 bytes: 1 for opcode + 8 for frame size
             self.output.extend_from_slice(&[0u8; 9]);
@@ -189,26 +278,10 @@ impl Generator {
         };
 
         // choose a target total budget for the full emitted opcode stream
-        let minimum_total = if target_total_override.is_some() {
-            self.minimum_total_opcode_count(use_frame)
-        } else {
-            configured_min.max(self.minimum_total_opcode_count(use_frame))
-        };
-        let max_total = target_total_override
-            .unwrap_or(configured_max)
-            .max(minimum_total);
-        if max_total < minimum_total {
-            return Err(color_eyre::eyre::eyre!(
-                "opcode budget {}..={} cannot fit a valid protocol {} pickle; minimum total is {} opcodes",
-                configured_min,
-                configured_max,
-                self.state.version as u8,
-                minimum_total
-            ));
-        }
+        let minimum_total = configured_min.max(self.minimum_total_opcode_count(use_frame));
+        let max_total = configured_max.max(minimum_total);
 
-        let target_total_opcodes = if target_total_override.is_some() || minimum_total == max_total
-        {
+        let target_total_opcodes = if minimum_total == max_total {
             max_total
         } else {
             source.gen_range(minimum_total, max_total + 1)
@@ -216,11 +289,31 @@ impl Generator {
         let body_and_cleanup_budget =
             target_total_opcodes.saturating_sub(self.fixed_opcode_count(use_frame));
         let mut emitted_body_opcodes = 0;
+        // attempts that emitted nothing (a rolled-back mutation) still use up
+        // the budget, so generation always terminates
+        let mut dropped_emissions = 0;
 
         // generation phase - allow stack to grow and build complex structures
         loop {
+            self.check_time_budget()?;
+            if frame_position.is_none() && self.output.len() >= STREAM_CHUNK_SIZE {
+                if let Some(sink) = sink.as_deref_mut() {
+                    self.stream_output(sink)?;
+                }
+            }
+
             let cleanup_budget = self.current_cleanup_opcode_count();
-            if emitted_body_opcodes + cleanup_budget >= body_and_cleanup_budget {
+            if emitted_body_opcodes + dropped_emissions + cleanup_budget >= body_and_cleanup_budget
+            {
+                break;
+            }
+            if !self.fits_byte_limit(cleanup_budget + 1) {
+                // not even a single-byte opcode fits any more
+                break;
+            }
+
+            if self.exhaustion_policy == ExhaustionPolicy::Stop && source.is_exhausted() {
+                // the fuzzer input ran out: finish with what it decided
                 break;
             }
 
@@ -230,24 +323,64 @@ impl Generator {
                 break;
             }
 
-            let remaining_budget = body_and_cleanup_budget - emitted_body_opcodes;
-            let budgeted_ops: Vec<_> = valid_ops
-                .into_iter()
-                .filter(|opcode| self.cleanup_opcode_count_after(*opcode) < remaining_budget)
-                .collect();
+            let remaining_budget =
+                body_and_cleanup_budget - emitted_body_opcodes - dropped_emissions;
+            if let Some(opcodes) = self.emit_interesting_pattern(remaining_budget, source)? {
+                self.take_strict_violation()?;
+                emitted_body_opcodes += opcodes;
+                continue;
+            }
+            if let Some(opcodes) = self.emit_misplaced_frame(remaining_budget, source)? {
+                self.take_strict_violation()?;
+                emitted_body_opcodes += opcodes;
+                continue;
+            }
+
+            let mut budgeted_ops = valid_ops;
+            budgeted_ops.retain(|opcode| {
+                let cleanup_after = self.cleanup_opcode_count_after(opcode);
+                // every opcode takes at least one byte, so this is a lower bound
+                // on the output size after emitting it
+                cleanup_after < remaining_budget && self.fits_byte_limit(cleanup_after + 1)
+            });
             if budgeted_ops.is_empty() {
                 break;
             }
 
             let chosen = self.weighted_choice(budgeted_ops, source);
-            self.emit_and_process(chosen, source)?;
+
+            // opcodes with arguments can overshoot the lower bound above, so keep
+            // enough to undo the emission. opcodes that mutate containers in place
+            // are all argument-free and never get here with an overshoot.
+            let rollback = self.bufsize.map(|_| self.state.clone());
+
+            self.begin_step("opcode");
+            let output_len = self.emit_and_process(chosen, source)?;
+            self.take_strict_violation()?;
+            if self.output.len() == output_len {
+                dropped_emissions += 1;
+                continue;
+            }
+
+            if !self.fits_byte_limit(self.current_cleanup_opcode_count()) {
+                if let Some(state) = rollback {
+                    self.state = state;
+                    self.output.truncate(output_len);
+                }
+                // close to the limit: stop emitting and move on to cleanup
+                break;
+            }
             emitted_body_opcodes += 1;
         }
 
         // cleanup phase - reduce stack to exactly 1 item for STOP
+        let cleanup_opcodes = self.current_cleanup_opcode_count();
+        self.begin_step("cleanup");
         self.cleanup_for_stop();
 
+        self.begin_step("stop");
         self.emit_opcode(OpcodeKind::Stop);
+        self.take_strict_violation()?;
 
         // if we reserved space for FRAME, fill it in now with the correct size
         if let Some(pos) = frame_position {
@@ -262,6 +395,166 @@ impl Generator {
             self.output[pos + 1..pos + 9].copy_from_slice(&frame_size.to_le_bytes());
         }
 
-        Ok(self.output.clone())
+        match sink {
+            Some(sink) => self.stream_output(sink)?,
+            None => self.annotate_output(),
+        }
+
+        self.emitted_opcodes =
+            self.fixed_opcode_count(use_frame) + emitted_body_opcodes + cleanup_opcodes;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::CleanupPolicy;
+    use crate::opcodes::PICKLE_OPCODES;
+    use crate::stack::{Items, StackObject};
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use std::collections::{HashMap, HashSet};
+
+    fn cleanup_len(version: Version, items: usize) -> usize {
+        let mut generator = Generator::new(version);
+        for value in 0..items {
+            generator.push(StackObject::Int(value as i64));
+        }
+        let before = generator.output.len();
+        generator.cleanup_for_stop();
+        generator.output.len() - before
+    }
+
+    #[test]
+    fn cleanup_count_matches_emitted_cleanup() {
+        for version in [Version::V0, Version::V1, Version::V2, Version::V4] {
+            for items in 0..8 {
+                let generator = Generator::new(version);
+                assert_eq!(
+                    generator.cleanup_opcode_count_for(items, 0, None),
+                    cleanup_len(version, items),
+                    "protocol {} with {} items",
+                    version as u8,
+                    items
+                );
+            }
+        }
+    }
+
+    /// generator whose stack holds a string followed by `layout`: `m` is a MARK,
+    /// `l`/`d`/`s` an empty list/dict/set, anything else a string.
+    fn with_layout(version: Version, layout: &str) -> Generator {
+        let mut generator = Generator::new(version).with_persistent_id_opcodes(true);
+        generator.push(StackObject::String("builtins".to_string()));
+        for slot in layout.chars() {
+            generator.push(match slot {
+                'm' => StackObject::Mark,
+                'l' => StackObject::List(Items::new()),
+                'd' => StackObject::Dict(HashMap::new()),
+                's' => StackObject::Set(HashSet::new()),
+                _ => StackObject::String("object".to_string()),
+            });
+        }
+        generator
+    }
+
+    const LAYOUTS: [&str; 10] = [
+        "", "ooo", "omoo", "mmo", "oomom", "lmoo", "dmoo", "dmo", "smoo", "lmdmoosm",
+    ];
+
+    fn cleanup_opcodes(generator: &mut Generator) -> Vec<u8> {
+        let before = generator.output.len();
+        generator.cleanup_for_stop();
+        generator.output[before..].to_vec()
+    }
+
+    #[test]
+    fn cleanup_count_matches_emitted_cleanup_with_marks() {
+        for policy in [CleanupPolicy::Tuple, CleanupPolicy::KeepRoot] {
+            for version in [Version::V0, Version::V1, Version::V3, Version::V4] {
+                for layout in LAYOUTS {
+                    let mut generator = with_layout(version, layout).with_cleanup_policy(policy);
+                    let predicted = generator.current_cleanup_opcode_count();
+                    assert_eq!(
+                        predicted,
+                        cleanup_opcodes(&mut generator).len(),
+                        "{layout:?} for protocol {} with {policy:?}",
+                        version as u8
+                    );
+                    assert_eq!(generator.state.stack.len(), 1);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn cleanup_closes_marks_into_containers() {
+        use OpcodeKind::*;
+        let tuple = CleanupPolicy::Tuple;
+        let keep_root = CleanupPolicy::KeepRoot;
+        let expected: [(&str, Version, CleanupPolicy, &[OpcodeKind]); 11] = [
+            ("lmoo", Version::V4, tuple, &[Appends, Tuple2]),
+            ("dmoo", Version::V4, tuple, &[SetItems, Tuple2]),
+            ("dmo", Version::V4, tuple, &[Tuple, Tuple3]),
+            ("smoo", Version::V4, tuple, &[AddItems, Tuple2]),
+            ("smoo", Version::V3, tuple, &[Tuple, Tuple3]),
+            ("om", Version::V2, tuple, &[PopMark, Tuple2]),
+            ("lmoo", Version::V0, tuple, &[Tuple, Pop, Pop]),
+            ("dmoo", Version::V4, keep_root, &[SetItems, Pop]),
+            ("dmo", Version::V4, keep_root, &[PopMark, Pop]),
+            ("omoo", Version::V2, keep_root, &[PopMark, Pop]),
+            ("lmoo", Version::V0, keep_root, &[Tuple, Pop, Pop]),
+        ];
+
+        for (layout, version, policy, opcodes) in expected {
+            let mut generator = with_layout(version, layout).with_cleanup_policy(policy);
+            let opcodes: Vec<u8> = opcodes.iter().map(|op| op.as_u8()).collect();
+            assert_eq!(
+                cleanup_opcodes(&mut generator),
+                opcodes,
+                "{layout:?} for protocol {} with {policy:?}",
+                version as u8
+            );
+        }
+    }
+
+    #[test]
+    fn cleanup_count_after_matches_simulated_opcode() {
+        for (version, policy) in [
+            (Version::V1, CleanupPolicy::Tuple),
+            (Version::V4, CleanupPolicy::Tuple),
+            (Version::V5, CleanupPolicy::Tuple),
+            (Version::V4, CleanupPolicy::KeepRoot),
+        ] {
+            let table = PICKLE_OPCODES.get(&(version as u8)).unwrap();
+            for layout in LAYOUTS {
+                for &opcode in table.iter() {
+                    let mut generator = with_layout(version, layout).with_cleanup_policy(policy);
+                    if !generator.can_emit(opcode) {
+                        continue;
+                    }
+                    let predicted = generator.cleanup_opcode_count_after(opcode);
+
+                    let mut rng = ChaCha8Rng::seed_from_u64(1);
+                    let mut source = GenerationSource::Rand(&mut rng);
+                    generator.emit_and_process(opcode, &mut source).unwrap();
+                    assert_eq!(
+                        predicted,
+                        generator.current_cleanup_opcode_count(),
+                        "{opcode:?} on {layout:?} for protocol {} with {policy:?}",
+                        version as u8
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn byte_limit_accounts_for_stop() {
+        let mut generator = Generator::new(Version::V2).with_buffer_size(4);
+        generator.output.extend_from_slice(&[0x80, 0x02]);
+        assert!(generator.fits_byte_limit(1));
+        assert!(!generator.fits_byte_limit(2));
     }
 }
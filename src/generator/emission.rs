@@ -49,10 +49,18 @@ use std::sync::OnceLock;
 use color_eyre::eyre::eyre;
 use color_eyre::Result;
 
+use super::boundaries::boundary_int_arg;
 use super::source::{EntropySource, GenerationSource};
 use super::Generator;
-use super::Version;
-use crate::opcodes::{OpcodeKind, PICKLE_OPCODES};
+use crate::opcodes::{
+    python_float_repr, raw_unicode_escape, repr_string_literal, OpcodeKind, PICKLE_OPCODES,
+};
+
+/// one in this many LONG values is widened past 64 bits.
+const WIDE_LONG_ODDS: usize = 4;
+
+/// how many classes INST draws looking for one that takes its arguments.
+const INST_DRAWS: usize = 8;
 
 static STDLIB_GLOBALS: OnceLock<Vec<(String, String)>> = OnceLock::new();
 
@@ -64,6 +72,35 @@ fn parse_stdlib_global(line: &str) -> Option<(String, String)> {
     Some((module.to_string(), attr.to_string()))
 }
 
+/// minimal little-endian two's complement encoding of `value`, as pickle's
+/// `encode_long` produces it (empty for 0).
+pub(super) fn encode_long_minimal(value: i128) -> Vec<u8> {
+    let mut bytes = value.to_le_bytes().to_vec();
+    while let [.., prev, last] = bytes[..] {
+        let redundant = (last == 0x00 && prev & 0x80 == 0) || (last == 0xff && prev & 0x80 != 0);
+        if !redundant {
+            break;
+        }
+        bytes.pop();
+    }
+    if bytes == [0] {
+        bytes.clear();
+    }
+    bytes
+}
+
+/// argument bytes for a LONG (decimal text), or LONG1/LONG4 carrying
+/// `value_bytes` behind their length prefix.
+pub(super) fn encode_long_arg(opcode: OpcodeKind, value: i128, value_bytes: &[u8]) -> Vec<u8> {
+    let mut arg = match opcode {
+        OpcodeKind::Long1 => vec![value_bytes.len() as u8],
+        OpcodeKind::Long4 => (value_bytes.len() as u32).to_le_bytes().to_vec(),
+        _ => return format!("{value}L\n").into_bytes(),
+    };
+    arg.extend_from_slice(value_bytes);
+    arg
+}
+
 fn normalize_ext4_code(code: u32) -> u32 {
     (code & 0x7FFF_FFFF).max(1)
 }
@@ -98,7 +135,8 @@ impl Generator {
     /// - `source`: entropy source for random values and mutation decisions
     ///
     /// # Returns
-    /// `Ok(())` on success.
+    /// the output length the emission starts at, past the operands of a
+    /// STACK_GLOBAL if mutators respelled them.
     ///
     /// # Errors
     /// returns an error if:
@@ -107,12 +145,15 @@ impl Generator {
         &mut self,
         opcode: OpcodeKind,
         source: &mut GenerationSource,
-    ) -> Result<()> {
+    ) -> Result<usize> {
         use OpcodeKind::*;
 
-        // create snapshot before emission
-        let pre_emission_state = self.state.clone();
-        let snapshot = self.create_snapshot();
+        // create snapshot before emission. the state copy is only needed to
+        // re-simulate mutator rewrites; skipping it otherwise avoids cloning (and
+        // later dropping) a reference to every stack and memo object per opcode
+        let pre_emission_state =
+            (!self.mutators.is_empty() || !self.emit_hooks.is_empty()).then(|| self.state.clone());
+        let mut snapshot = self.create_snapshot();
 
         // emit the opcode and any required arguments
         match opcode {
@@ -123,15 +164,15 @@ impl Generator {
 
             // float opcodes
             Float => {
-                let value = self.mutate_float(source.gen_f64(), source);
+                let value = self.mutate_float(Float, source.gen_f64(), source);
                 self.output.push(Float.as_u8());
-                let float_str = format!("{}\n", value);
+                let float_str = format!("{}\n", python_float_repr(value));
                 let arg_bytes = float_str.as_bytes();
                 self.output.extend_from_slice(arg_bytes);
                 self.process_stack_ops(Float, Some(arg_bytes));
             }
             BinFloat => {
-                let value = self.mutate_float(source.gen_f64(), source);
+                let value = self.mutate_float(BinFloat, source.gen_f64(), source);
                 self.output.push(BinFloat.as_u8());
                 let arg_bytes = value.to_be_bytes();
                 self.output.extend_from_slice(&arg_bytes);
@@ -181,7 +222,7 @@ impl Generator {
                 keys.sort_unstable();
                 if !keys.is_empty() {
                     let index = keys[source.gen_range(0, keys.len())];
-                    let mutated_index = self.mutate_memo_index(index, source);
+                    let mutated_index = self.mutate_memo_index(Get, index, source);
                     // in unsafe mode, allow any mutated index; otherwise validate it exists
                     let index =
                         if self.unsafe_mutations || self.state.memo.contains_key(&mutated_index) {
@@ -207,7 +248,7 @@ impl Generator {
                 valid_indices.sort_unstable();
                 if !valid_indices.is_empty() {
                     let index = valid_indices[source.gen_range(0, valid_indices.len())];
-                    let mutated_index = self.mutate_memo_index(index, source).min(255);
+                    let mutated_index = self.mutate_memo_index(BinGet, index, source).min(255);
                     // in unsafe mode, allow any mutated index; otherwise validate it exists and fits in u8
                     let index = if self.unsafe_mutations
                         || (mutated_index < 256 && self.state.memo.contains_key(&mutated_index))
@@ -226,7 +267,7 @@ impl Generator {
                 keys.sort_unstable();
                 if !keys.is_empty() {
                     let index = keys[source.gen_range(0, keys.len())];
-                    let mutated_index = self.mutate_memo_index(index, source);
+                    let mutated_index = self.mutate_memo_index(LongBinGet, index, source);
                     // in unsafe mode, allow any mutated index; otherwise validate it exists
                     let index =
                         if self.unsafe_mutations || self.state.memo.contains_key(&mutated_index) {
@@ -274,16 +315,25 @@ impl Generator {
 
             // persid needs a persistent ID string
             PersID => {
-                let pid = format!("pid_{}\n", source.gen_u32());
+                let pid = self.persistent_id_line(source);
                 self.output.push(PersID.as_u8());
-                let arg_bytes = pid.as_bytes();
-                self.output.extend_from_slice(arg_bytes);
-                self.process_stack_ops(PersID, Some(arg_bytes));
+                self.output.extend_from_slice(&pid);
+                self.process_stack_ops(PersID, Some(&pid));
             }
 
             // inst needs module and class name
             Inst => {
-                if let Ok(module_class) = self.get_random_module(source) {
+                // redraw classes whose signature rejects the arguments above
+                // the MARK, a few times before giving up on the emission
+                let fitting = (0..INST_DRAWS)
+                    .map_while(|_| self.get_random_module(source).ok())
+                    .find(|module_class| {
+                        let mut parts = module_class.split('\n');
+                        let (module, name) =
+                            (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+                        self.inst_fits(module, name)
+                    });
+                if let Some(module_class) = fitting {
                     self.output.push(Inst.as_u8());
                     let arg_bytes = module_class.as_bytes();
                     self.output.extend_from_slice(arg_bytes);
@@ -291,10 +341,11 @@ impl Generator {
                 }
             }
 
-            // frame is handled specially in generate() - not emitted during normal generation
+            // the pickle-wide frame is handled specially in generate(); this is
+            // a misplaced one, which can_emit only allows with unsafe frames
             Frame => {
-                // this should never be called since can_emit returns false for Frame
-                unreachable!("Frame should not be emitted during generation");
+                let len = self.adversarial_frame_len(source);
+                self.emit_frame(len);
             }
 
             // opcodes without arguments - just emit directly
@@ -303,10 +354,26 @@ impl Generator {
             }
         }
 
-        // post-process mutations
-        self.post_process_emission(snapshot, pre_emission_state, source);
+        // post-process mutations, then make sure they kept the emission valid.
+        // respelled STACK_GLOBAL operands move the emission along with them
+        let rewritten =
+            self.post_process_emission(&mut snapshot, pre_emission_state.as_ref(), source);
+        let output_len = snapshot.output_len;
+        let value_mutated = self.value_mutated.take();
+        if let Some(pre_emission_state) = &pre_emission_state {
+            let mutated = rewritten || value_mutated;
+            let mut kept = !mutated
+                || (self.enforce_safe_emission(output_len, pre_emission_state)
+                    && self.enforce_restrictions(output_len, pre_emission_state));
+            if !self.emit_hooks.is_empty() {
+                kept &= self.run_emit_hooks(snapshot, pre_emission_state);
+            }
+            if mutated && kept {
+                self.mutated_emissions += 1;
+            }
+        }
 
-        Ok(())
+        Ok(output_len)
     }
 
     /// emit a string opcode with protocol-specific formatting.
@@ -340,31 +407,29 @@ impl Generator {
         let s: std::string::String = (0..len).map(|_| source.gen_ascii_char()).collect();
 
         // apply mutations
-        let s = self.mutate_string(s, source);
+        let mut s = self.mutate_string(opcode, s, source);
+        if self.loadable && opcode == String {
+            // python 2 str is decoded as ASCII by default
+            s = s
+                .chars()
+                .map(|c| if c.is_ascii() { c } else { '?' })
+                .collect();
+        }
 
         match opcode {
             String => {
-                // string opcode (protocol 0) requires properly escaped and quoted python string
+                // string opcode (protocol 0) takes a quoted, escaped python 2 str literal
                 self.output.push(opcode.as_u8());
-                // escape special characters: backslash, single quote, newline, tab, etc.
-                let escaped = s
-                    .replace('\\', "\\\\") // backslash must be first
-                    .replace('\'', "\\'")
-                    .replace('\n', "\\n")
-                    .replace('\r', "\\r")
-                    .replace('\t', "\\t");
-                let quoted = format!("'{}'\n", escaped);
-                let arg_bytes = quoted.into_bytes();
+                let mut arg_bytes = repr_string_literal(s.as_bytes());
+                arg_bytes.push(b'\n');
                 self.output.extend_from_slice(&arg_bytes);
                 self.process_stack_ops(opcode, Some(&arg_bytes));
             }
             Unicode => {
-                // unicode opcode (protocol 0) - uses rawunicodeescape encoding
-                // must escape backslashes to avoid invalid unicode escape sequences like \U
+                // unicode opcode (protocol 0) - raw-unicode-escape, with backslashes
+                // and line breaks as \u escapes like CPython writes them
                 self.output.push(opcode.as_u8());
-                let escaped = s.replace('\\', "\\\\");
-                let s_with_newline = format!("{}\n", escaped);
-                let arg_bytes = s_with_newline.into_bytes();
+                let arg_bytes = raw_unicode_escape(&s);
                 self.output.extend_from_slice(&arg_bytes);
                 self.process_stack_ops(opcode, Some(&arg_bytes));
             }
@@ -429,7 +494,11 @@ impl Generator {
         let bytes: Vec<u8> = (0..len).map(|_| source.gen_u8()).collect();
 
         // apply mutations
-        let bytes = self.mutate_bytes(bytes, source);
+        let mut bytes = self.mutate_bytes(opcode, bytes, source);
+        if self.loadable && matches!(opcode, BinString | ShortBinString) {
+            // python 2 str is decoded as ASCII by default
+            bytes.iter_mut().for_each(|byte| *byte &= 0x7f);
+        }
 
         match opcode {
             BinString => {
@@ -440,22 +509,16 @@ impl Generator {
                 self.output.extend_from_slice(&bytes);
                 self.process_stack_ops(opcode, Some(&bytes));
             }
-            ShortBinString | ShortBinBytes => match u8::try_from(bytes.len()) {
-                Ok(len) => {
-                    // short_binstring and short_binbytes use 1-byte length
+            ShortBinString | ShortBinBytes => {
+                // short_binstring and short_binbytes use 1-byte length; values
+                // mutated past 255 bytes are dropped, like SHORT_BINUNICODE
+                if let Ok(len) = u8::try_from(bytes.len()) {
                     self.output.push(opcode.as_u8());
                     self.output.push(len);
                     self.output.extend_from_slice(&bytes);
                     self.process_stack_ops(opcode, Some(&bytes));
                 }
-                Err(_) => {
-                    debug_assert!(
-                        false,
-                        "short bin opcodes require <=255 bytes, got {}",
-                        bytes.len()
-                    );
-                }
-            },
+            }
             BinBytes => {
                 // binbytes uses 4-byte unsigned int for length (protocol 3)
                 self.output.push(opcode.as_u8());
@@ -497,12 +560,13 @@ impl Generator {
     /// protocols 2 and above. it must be the first opcode in the pickle stream.
     /// this method ensures PROTO is only emitted once.
     ///
-    /// protocols 0 and 1 don't use PROTO and are identified by their opcodes.
+    /// protocols 0 and 1 don't use PROTO and are identified by their opcodes,
+    /// unless `with_proto_header` asks for a header anyway.
     ///
     /// # Parameters
-    /// - `_source`: entropy source (currently unused, reserved for future use)
-    pub(super) fn emit_proto(&mut self, _source: &mut GenerationSource) {
-        if self.state.version == Version::V0 || self.state.version == Version::V1 {
+    /// - `source`: entropy source for a downgraded header's protocol
+    pub(super) fn emit_proto(&mut self, source: &mut GenerationSource) {
+        if !self.writes_proto() {
             return;
         }
 
@@ -510,8 +574,9 @@ impl Generator {
             return; // already emitted
         }
 
+        let header = self.header_version(source);
         self.output.push(OpcodeKind::Proto.as_u8());
-        self.output.push(self.state.version as u8);
+        self.output.push(header);
         self.state.proto_emitted = true;
     }
 
@@ -547,13 +612,16 @@ impl Generator {
     ///
     /// - **Int** (protocol 0): ASCII decimal with newline
     /// - **Long** (protocol 0): ASCII decimal with 'L' suffix and newline
-    /// - **Long1** (protocol 1): 1-byte size + little-endian bytes
-    /// - **Long4** (protocol 1): 4-byte size + little-endian bytes
+    /// - **Long1** (protocol 2): 1-byte size + minimal little-endian two's complement
+    /// - **Long4** (protocol 2): 4-byte size + minimal little-endian two's complement
     /// - **BinInt** (protocol 1): 4-byte signed little-endian
     /// - **BinInt1** (protocol 1): 1-byte unsigned (0-255)
     /// - **BinInt2** (protocol 1): 2-byte unsigned little-endian
     ///
-    /// applies integer mutations before encoding.
+    /// LONG variants carry an i64 (sometimes widened past 64 bits, see `gen_long`)
+    /// run through the long mutators; the others an i32 run through the integer
+    /// mutators. `with_integer_boundaries` can replace either with a boundary
+    /// value, which bypasses mutators.
     ///
     /// # Parameters
     /// - `source`: entropy source for random value generation and mutations
@@ -592,27 +660,34 @@ impl Generator {
         // write the opcode byte directly (don't use emit_opcode which would process stack ops prematurely)
         self.output.push(chosen.as_u8());
 
-        let int = self.mutate_int(source.gen_i32(), source);
+        let boundary = source.with_values(|source| {
+            if self.integer_boundaries && source.gen_bool() {
+                boundary_int_arg(chosen, source)
+            } else {
+                None
+            }
+        });
+        if let Some(arg) = boundary {
+            self.output.extend_from_slice(&arg);
+            self.process_stack_ops(chosen, Some(&arg));
+            return Ok(());
+        }
+
+        if matches!(
+            chosen,
+            OpcodeKind::Long | OpcodeKind::Long1 | OpcodeKind::Long4
+        ) {
+            let value = self.gen_long(chosen, source);
+            let arg = encode_long_arg(chosen, value, &encode_long_minimal(value));
+            self.output.extend_from_slice(&arg);
+            self.process_stack_ops(chosen, Some(&arg));
+            return Ok(());
+        }
+
+        let int = self.mutate_int(chosen, source.gen_i32(), source);
 
         let arg: Vec<u8> = match chosen {
             OpcodeKind::Int => format!("{int}\n").into_bytes(),
-            OpcodeKind::Long => format!("{int}L\n").into_bytes(),
-            OpcodeKind::Long1 => {
-                let size = 4u8;
-                let bytes = int.to_le_bytes();
-                let mut v = vec![size];
-                v.extend_from_slice(&bytes);
-                v
-            }
-            OpcodeKind::Long4 => {
-                let size = 4u32;
-                let size_bytes = size.to_le_bytes();
-                let int_bytes = int.to_le_bytes();
-                let mut v = Vec::with_capacity(8);
-                v.extend_from_slice(&size_bytes);
-                v.extend_from_slice(&int_bytes);
-                v
-            }
             OpcodeKind::BinInt => int.to_le_bytes().to_vec(),
             OpcodeKind::BinInt1 => vec![(int & 0xFF) as u8],
             OpcodeKind::BinInt2 => {
@@ -632,6 +707,19 @@ impl Generator {
         Ok(())
     }
 
+    /// draw a value for LONG/LONG1/LONG4.
+    ///
+    /// starts from a random i64 run through the long mutators, then one time in
+    /// four sets random bits above bit 63, so unpicklers see values that overflow
+    /// a machine word as well as ones that fit.
+    fn gen_long(&self, opcode: OpcodeKind, source: &mut GenerationSource) -> i128 {
+        let value = self.mutate_long(opcode, source.gen_i64(), source);
+        if source.with_values(|source| source.choose_index(WIDE_LONG_ODDS)) != 0 {
+            return i128::from(value);
+        }
+        (i128::from(source.gen_i32()) << 64) | i128::from(value as u64)
+    }
+
     /// get a random module and name from the Python standard library.
     ///
     /// uses embedded `stdlib_complete.txt` data (cached after first access) which contains
@@ -644,6 +732,14 @@ impl Generator {
     /// # Returns
     /// a string formatted as "module\nname\n".
     pub(super) fn get_random_module(&self, source: &mut GenerationSource) -> Result<String> {
+        if self.global_allowlist.is_some() || self.loadable {
+            let globals = self.importable_globals();
+            if globals.is_empty() {
+                return Err(eyre!("the global allowlist is empty"));
+            }
+            let (module, attr) = globals[source.choose_index(globals.len())];
+            return Ok(format!("{}\n{}\n", module, attr));
+        }
         let globals = load_stdlib_complete();
         if globals.is_empty() {
             return Err(eyre!(
@@ -662,6 +758,61 @@ impl Generator {
 mod tests {
     use super::normalize_ext4_code;
     use super::parse_stdlib_global;
+    use super::{encode_long_arg, encode_long_minimal};
+    use crate::generator::{GenerationSource, Generator};
+    use crate::mutators::BoundaryMutator;
+    use crate::opcodes::OpcodeKind;
+    use crate::Version;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    #[test]
+    fn minimal_long_encodings_match_pickle() {
+        // expected values from pickle.encode_long
+        assert_eq!(encode_long_minimal(0), b"");
+        assert_eq!(encode_long_minimal(-1), b"\xff");
+        assert_eq!(encode_long_minimal(0x7f), b"\x7f");
+        assert_eq!(encode_long_minimal(0x80), b"\x80\x00");
+        assert_eq!(encode_long_minimal(-0x80), b"\x80");
+        assert_eq!(encode_long_minimal(-0x81), b"\x7f\xff");
+        assert_eq!(encode_long_minimal(0xff), b"\xff\x00");
+        assert_eq!(encode_long_minimal(i32::MIN as i128), b"\x00\x00\x00\x80");
+        assert_eq!(
+            encode_long_minimal(u64::MAX as i128 + 1),
+            b"\x00\x00\x00\x00\x00\x00\x00\x00\x01"
+        );
+    }
+
+    #[test]
+    fn encode_long_arg_prefixes_the_value_length() {
+        assert_eq!(encode_long_arg(OpcodeKind::Long, -5, &[]), b"-5L\n");
+        assert_eq!(encode_long_arg(OpcodeKind::Long1, 0, &[]), b"\x00");
+        assert_eq!(
+            encode_long_arg(OpcodeKind::Long4, 0x80, &[0x80, 0x00]),
+            b"\x02\x00\x00\x00\x80\x00"
+        );
+    }
+
+    #[test]
+    fn long_opcodes_carry_mutated_64_bit_and_wider_values() {
+        let mut generator = Generator::new(Version::V2)
+            .with_mutators(vec![Box::new(BoundaryMutator)])
+            .with_mutation_rate(1.0);
+        let mut rng = ChaCha8Rng::seed_from_u64(3);
+        let mut source = GenerationSource::Rand(&mut rng);
+
+        let mut long1_sizes = Vec::new();
+        for _ in 0..400 {
+            generator.output.clear();
+            generator.emit_int(&mut source).unwrap();
+            if generator.output[0] == OpcodeKind::Long1.as_u8() {
+                long1_sizes.push(generator.output[1]);
+            }
+        }
+        // i64::MAX/i64::MIN from the boundary mutator, and widened values
+        assert!(long1_sizes.contains(&8), "{long1_sizes:?}");
+        assert!(long1_sizes.iter().any(|&size| size > 8), "{long1_sizes:?}");
+    }
 
     #[test]
     fn parse_stdlib_global_accepts_tab_delimited_entries() {
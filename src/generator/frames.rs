@@ -0,0 +1,169 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! FRAME opcodes where picklers never put them.
+//!
+//! CPython frames protocol 4+ pickles in back-to-back FRAMEs that each hold
+//! whole opcodes, and the generator writes one FRAME around the whole body.
+//! unpicklers handle everything else differently: Python's `_Unframer`
+//! raises on a FRAME inside an unfinished frame, the C unpickler only checks
+//! that the frame's bytes are there, and `pickletools` ignores framing. with
+//! unsafe frames the generator also emits FRAMEs between body opcodes:
+//!
+//! - with a length of 0
+//! - with a short length that ends partway through the next opcodes
+//! - with a length far past the end of the pickle
+//! - nested, a FRAME whose length covers exactly a second FRAME and its bytes
+//! - in protocols 0-3, which have no FRAME at all
+//!
+//! FRAME doesn't touch the stack, so the simulation is unaffected; under the
+//! pickle-wide FRAME of protocol 4+ every one of them is nested too.
+
+use color_eyre::Result;
+
+use super::source::{EntropySource, GenerationSource};
+use super::Generator;
+use crate::opcodes::OpcodeKind;
+
+/// one in this many loop iterations emits a misplaced FRAME.
+const FRAME_ODDS: usize = 32;
+
+/// longest of the short frame lengths.
+const MAX_SHORT_FRAME: usize = 16;
+
+impl Generator {
+    /// emit FRAME opcodes at adversarial positions (off by default).
+    ///
+    /// about one in thirty-two steps of the generation loop emits a FRAME
+    /// between two body opcodes, whatever the protocol, that is empty,
+    /// ends inside the opcodes after it, runs past the end of the pickle, or
+    /// holds a second FRAME; on protocol 4+ FRAME also joins the opcodes the
+    /// loop picks from. like unsafe mutations this is for negative tests:
+    /// most unpicklers reject the output. canonical and diverse-encoding
+    /// output ignore it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pickle_fuzzer::{Generator, Version};
+    ///
+    /// let mut gen = Generator::new(Version::V2).with_seed(3).with_unsafe_frames(true);
+    /// let pickle = gen.generate().unwrap();
+    /// assert_eq!(pickle.last(), Some(&b'.'));
+    /// ```
+    pub fn with_unsafe_frames(mut self, enabled: bool) -> Self {
+        self.unsafe_frames = enabled;
+        self
+    }
+
+    /// a FRAME length no pickler writes: 0, a few bytes, or far more than
+    /// the pickle holds.
+    pub(super) fn adversarial_frame_len(&self, source: &mut GenerationSource) -> u64 {
+        match source.choose_index(3) {
+            0 => 0,
+            1 => 1 + source.choose_index(MAX_SHORT_FRAME) as u64,
+            _ => u64::MAX - source.choose_index(MAX_SHORT_FRAME) as u64,
+        }
+    }
+
+    /// emit a FRAME of `len` bytes, bypassing mutators.
+    pub(super) fn emit_frame(&mut self, len: u64) {
+        let arg_bytes = len.to_le_bytes();
+        self.output.push(OpcodeKind::Frame.as_u8());
+        self.output.extend_from_slice(&arg_bytes);
+        self.process_stack_ops(OpcodeKind::Frame, Some(&arg_bytes));
+    }
+
+    /// occasionally emit a misplaced FRAME, or a FRAME holding another, if
+    /// it fits `remaining_budget` and the byte limit. with unsafe frames off
+    /// this draws no entropy, so the output is unchanged.
+    ///
+    /// # Returns
+    /// the number of opcodes emitted, or `None` if no FRAME was emitted.
+    pub(super) fn emit_misplaced_frame(
+        &mut self,
+        remaining_budget: usize,
+        source: &mut GenerationSource,
+    ) -> Result<Option<usize>> {
+        if !self.unsafe_frames || source.choose_index(FRAME_ODDS) != 0 {
+            return Ok(None);
+        }
+
+        let nested = source.choose_index(4) == 0;
+        let opcodes = 1 + usize::from(nested);
+        let cleanup = self.current_cleanup_opcode_count();
+        if opcodes + cleanup > remaining_budget || !self.fits_byte_limit(cleanup + 9 * opcodes) {
+            return Ok(None);
+        }
+
+        let len = self.adversarial_frame_len(source);
+        self.begin_step("unsafe frame");
+        if nested {
+            // the outer frame ends right where the inner FRAME's length field does
+            self.emit_frame(9);
+        }
+        self.emit_frame(len);
+        Ok(Some(opcodes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::disasm::{disassemble, validate};
+    use crate::{Generator, Version};
+
+    #[test]
+    fn unsafe_frames_misplace_frames_in_every_protocol() {
+        for version in Version::all() {
+            let frames = (0..16).any(|seed| {
+                let pickle = Generator::new(version)
+                    .with_seed(seed)
+                    .with_opcode_range(60, 120)
+                    .with_unsafe_frames(true)
+                    .with_strict_checks(true)
+                    .generate()
+                    .unwrap();
+                let instructions = disassemble(&pickle).unwrap();
+                // skip the pickle-wide FRAME of protocol 4+
+                let body = &instructions[1..instructions.len() - 1];
+                body.iter().skip(1).any(|i| i.name == "FRAME")
+            });
+            assert!(frames, "{version:?}");
+        }
+    }
+
+    #[test]
+    fn frames_stay_out_unless_unsafe() {
+        for seed in 0..8 {
+            let pickle = Generator::new(Version::V2)
+                .with_seed(seed)
+                .with_unsafe_frames(false)
+                .generate()
+                .unwrap();
+            validate(&pickle).unwrap();
+            let instructions = disassemble(&pickle).unwrap();
+            assert!(instructions.iter().all(|i| i.name != "FRAME"));
+        }
+
+        let plain = Generator::new(Version::V4).with_seed(2).generate().unwrap();
+        let safe = Generator::new(Version::V4)
+            .with_seed(2)
+            .with_unsafe_frames(false)
+            .generate()
+            .unwrap();
+        assert_eq!(plain, safe);
+    }
+}
@@ -0,0 +1,163 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! PROTO headers that disagree with the protocol of the body.
+//!
+//! the PROTO opcode only announces a protocol: CPython's unpickler records
+//! it and checks it is at most `HIGHEST_PROTOCOL`, but loads any opcode it
+//! knows whatever the header said, and `pickletools` does the same. scanners
+//! and alternative unpicklers often trust the header instead, picking an
+//! opcode table or skipping checks by it, so a header naming another
+//! protocol than the body uses is where they and the loader disagree.
+//!
+//! the body is always generated for the generator's protocol; only the
+//! PROTO byte changes, and for protocol 0 and 1 bodies, whether there is one.
+
+use clap::ValueEnum;
+
+use super::source::{EntropySource, GenerationSource};
+use super::Generator;
+use super::Version;
+
+/// which protocol the PROTO header announces.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ProtoHeader {
+    /// the body's protocol, and no PROTO below protocol 2, as picklers write.
+    #[default]
+    Honest,
+    /// one protocol above the body's (protocol 5 stays 5), e.g. PROTO 2
+    /// followed only by protocol 1 opcodes. every unpickler that knows the
+    /// header's protocol loads these, so the output stays valid.
+    Compatible,
+    /// a random protocol below the body's, e.g. PROTO 2 followed by
+    /// protocol 4 opcodes. CPython loads these; readers that go by the
+    /// header reject or misparse them. protocol 0 bodies get no header.
+    Downgraded,
+}
+
+impl Generator {
+    /// choose what the PROTO header announces (honest by default).
+    ///
+    /// with a compatible or downgraded header protocol 0 and 1 bodies start
+    /// with PROTO too. the opcode budget counts the header like any other
+    /// PROTO.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pickle_fuzzer::{Generator, ProtoHeader, Version};
+    ///
+    /// let mut gen = Generator::new(Version::V1)
+    ///     .with_seed(3)
+    ///     .with_proto_header(ProtoHeader::Compatible);
+    /// let pickle = gen.generate().unwrap();
+    /// assert_eq!(&pickle[..2], b"\x80\x02");
+    /// ```
+    pub fn with_proto_header(mut self, header: ProtoHeader) -> Self {
+        self.proto_header = header;
+        self
+    }
+
+    /// whether the pickle starts with PROTO.
+    pub(super) fn writes_proto(&self) -> bool {
+        match self.proto_header {
+            ProtoHeader::Honest => self.state.version >= Version::V2,
+            ProtoHeader::Compatible => true,
+            ProtoHeader::Downgraded => self.state.version >= Version::V1,
+        }
+    }
+
+    /// the protocol PROTO announces; draws entropy only for a downgraded
+    /// header.
+    pub(super) fn header_version(&self, source: &mut GenerationSource) -> u8 {
+        let version = self.state.version as u8;
+        match self.proto_header {
+            ProtoHeader::Honest => version,
+            ProtoHeader::Compatible => (version + 1).min(Version::V5 as u8),
+            ProtoHeader::Downgraded => source.choose_index(usize::from(version)) as u8,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ProtoHeader;
+    use crate::disasm::{disassemble, validate, Argument};
+    use crate::{Generator, Version};
+
+    fn header_of(pickle: &[u8]) -> Option<i128> {
+        let instructions = disassemble(pickle).unwrap();
+        match (instructions[0].name, &instructions[0].arg) {
+            ("PROTO", Argument::Int(version)) => Some(*version),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn headers_name_the_chosen_protocol() {
+        for version in Version::all() {
+            for seed in 0..8 {
+                let generate = |header| {
+                    let pickle = Generator::new(version)
+                        .with_seed(seed)
+                        .with_proto_header(header)
+                        .generate()
+                        .unwrap();
+                    validate(&pickle).unwrap();
+                    header_of(&pickle)
+                };
+                let body = version as i128;
+                let honest = generate(ProtoHeader::Honest);
+                assert_eq!(honest, (version >= Version::V2).then_some(body));
+                assert_eq!(
+                    generate(ProtoHeader::Compatible),
+                    Some((body + 1).min(5)),
+                    "{version:?}"
+                );
+                let downgraded = generate(ProtoHeader::Downgraded);
+                if version == Version::V0 {
+                    assert_eq!(downgraded, None);
+                } else {
+                    assert!(downgraded.is_some_and(|proto| proto < body), "{version:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn honest_headers_leave_output_unchanged() {
+        let plain = Generator::new(Version::V4).with_seed(9).generate().unwrap();
+        let honest = Generator::new(Version::V4)
+            .with_seed(9)
+            .with_proto_header(ProtoHeader::Honest)
+            .generate()
+            .unwrap();
+        assert_eq!(plain, honest);
+    }
+
+    #[test]
+    fn headers_fit_the_byte_limit() {
+        for header in [ProtoHeader::Compatible, ProtoHeader::Downgraded] {
+            let pickle = Generator::new(Version::V1)
+                .with_seed(1)
+                .with_buffer_size(4)
+                .with_proto_header(header)
+                .generate()
+                .unwrap();
+            assert!(pickle.len() <= 4, "{header:?} {pickle:?}");
+        }
+    }
+}
@@ -0,0 +1,307 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! caller code that steers generation without patching it.
+//!
+//! an emission hook runs once per randomly chosen opcode, after mutators have
+//! post-processed it and safe mode has checked the result, so it sees the
+//! bytes that end up in the pickle. vetoing an emission rolls it back like a
+//! rejected mutation: the generator moves on as if it had emitted nothing.
+//!
+//! an opcode filter runs earlier, while the next opcode is being chosen: the
+//! opcodes it rejects are dropped from the candidates `can_emit` allows, so
+//! they are never picked in the first place.
+
+use std::fmt;
+
+use super::Generator;
+use crate::mutators::EmissionSnapshot;
+use crate::opcodes::OpcodeKind;
+use crate::state::State;
+
+/// what an emission hook decides about the emission it saw.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmitVerdict {
+    /// keep the emission
+    Keep,
+    /// roll the emission back; generation continues without it
+    Veto,
+}
+
+/// the closure type [`Generator::with_emit_hook`] takes.
+type HookFn = dyn Fn(&EmissionSnapshot, &OpcodeKind, &[u8]) -> EmitVerdict + Send + Sync;
+
+/// the closure type [`Generator::with_opcode_filter`] takes.
+type FilterFn = dyn Fn(&State, OpcodeKind) -> bool + Send + Sync;
+
+/// one registered emission hook.
+pub(super) struct EmitHook(Box<HookFn>);
+
+impl fmt::Debug for EmitHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EmitHook")
+    }
+}
+
+/// one registered opcode filter.
+pub(super) struct OpcodeFilter(Box<FilterFn>);
+
+impl fmt::Debug for OpcodeFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("OpcodeFilter")
+    }
+}
+
+impl Generator {
+    /// add a hook that sees every emission and may veto it.
+    ///
+    /// the hook gets the emission's snapshot with its deltas filled in, the
+    /// opcode that ended up in the output (a mutator may have changed it), and
+    /// the emitted bytes, after post-processing. returning
+    /// [`EmitVerdict::Veto`] rolls the emission back. hooks run in the order
+    /// they were added, and one veto skips the rest.
+    ///
+    /// hooks see the opcodes generation picks one at a time; the opcodes of
+    /// multi-opcode patterns, cleanup, PROTO, FRAME, STOP, scripted
+    /// [`emit`](Self::emit) calls, and canonical and diverse-encoding output
+    /// aren't emissions in this sense. a hook that vetoes everything leaves
+    /// the smallest pickle cleanup can write.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::Arc;
+    /// use pickle_fuzzer::{EmitVerdict, Generator, OpcodeKind, Version};
+    ///
+    /// let seen = Arc::new(AtomicUsize::new(0));
+    /// let counter = Arc::clone(&seen);
+    /// let mut gen = Generator::new(Version::V4)
+    ///     .with_seed(1)
+    ///     .with_emit_hook(move |_, opcode, _| {
+    ///         counter.fetch_add(1, Ordering::Relaxed);
+    ///         if *opcode == OpcodeKind::Global {
+    ///             EmitVerdict::Veto
+    ///         } else {
+    ///             EmitVerdict::Keep
+    ///         }
+    ///     });
+    /// let pickle = gen.generate().unwrap();
+    /// let opcodes = pickle_fuzzer::disasm::disassemble(&pickle).unwrap();
+    /// assert!(opcodes.iter().all(|op| op.name != "GLOBAL"));
+    /// assert!(seen.load(Ordering::Relaxed) > 0);
+    /// ```
+    pub fn with_emit_hook(
+        mut self,
+        hook: impl Fn(&EmissionSnapshot, &OpcodeKind, &[u8]) -> EmitVerdict + Send + Sync + 'static,
+    ) -> Self {
+        self.emit_hooks.push(EmitHook(Box::new(hook)));
+        self
+    }
+
+    /// add a filter on which opcodes generation may pick next.
+    ///
+    /// the filter gets the generator state (stack, memo, protocol) and a
+    /// candidate opcode that `can_emit` already allows, and returns whether it
+    /// may be picked. candidates have to pass every filter. a filter only
+    /// narrows the choice of single opcodes: patterns, cleanup, and scripted
+    /// [`emit`](Self::emit) calls are not filtered, and when a filter rejects
+    /// every candidate the generator moves on to cleanup.
+    ///
+    /// a filter that needs history, like "at most 3 GLOBALs", can count with an
+    /// [emit hook](Self::with_emit_hook) sharing its counter.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pickle_fuzzer::{Generator, OpcodeKind, Version};
+    ///
+    /// // no POP right after a MARK
+    /// let mut gen = Generator::new(Version::V2)
+    ///     .with_seed(5)
+    ///     .with_opcode_filter(|state, opcode| {
+    ///         let top = state.stack.len().checked_sub(1);
+    ///         let top_is_mark = top.is_some_and(|top| state.stack.top_mark() == Some(top));
+    ///         !(opcode == OpcodeKind::Pop && top_is_mark)
+    ///     });
+    /// gen.generate().unwrap();
+    /// ```
+    pub fn with_opcode_filter(
+        mut self,
+        filter: impl Fn(&State, OpcodeKind) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.opcode_filters.push(OpcodeFilter(Box::new(filter)));
+        self
+    }
+
+    /// whether every opcode filter lets generation pick `opcode`.
+    pub(super) fn passes_opcode_filters(&self, opcode: OpcodeKind) -> bool {
+        self.opcode_filters
+            .iter()
+            .all(|filter| (filter.0)(&self.state, opcode))
+    }
+
+    /// run the emit hooks on the emission since `snapshot`, rolling it back to
+    /// `pre_emission_state` if one vetoes it.
+    ///
+    /// # Returns
+    /// `true` if the emission was kept.
+    pub(super) fn run_emit_hooks(
+        &mut self,
+        mut snapshot: EmissionSnapshot,
+        pre_emission_state: &State,
+    ) -> bool {
+        let Some(opcode) = self
+            .output
+            .get(snapshot.output_len)
+            .and_then(|&byte| OpcodeKind::from_u8(byte))
+        else {
+            // nothing was emitted, or havoc left no opcode to name
+            return true;
+        };
+        self.fill_deltas(&mut snapshot);
+
+        let emitted = &self.output[snapshot.output_len..];
+        let vetoed = self
+            .emit_hooks
+            .iter()
+            .any(|hook| (hook.0)(&snapshot, &opcode, emitted) == EmitVerdict::Veto);
+        if vetoed {
+            self.state = pre_emission_state.clone();
+            self.output.truncate(snapshot.output_len);
+        }
+        !vetoed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::disasm::{disassemble, validate};
+    use crate::mutators::MutatorKind;
+    use crate::Version;
+
+    #[test]
+    fn hooks_see_post_processed_bytes() {
+        let emissions = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&emissions);
+        let mut generator = Generator::new(Version::V2)
+            .with_seed(3)
+            .with_mutators(vec![MutatorKind::Typeconfusion.create(true)])
+            .with_mutation_rate(1.0)
+            .with_unsafe_mutations(true)
+            .with_emit_hook(move |snapshot, opcode, bytes| {
+                assert_eq!(snapshot.output_delta, bytes);
+                assert_eq!(bytes[0], opcode.as_u8());
+                log.lock().unwrap().push(bytes.to_vec());
+                EmitVerdict::Keep
+            });
+        let pickle = generator.generate().unwrap();
+
+        let emissions = emissions.lock().unwrap();
+        assert!(!emissions.is_empty());
+        // every emission is in the pickle as the hook saw it, in order
+        let mut rest = &pickle[..];
+        for emission in emissions.iter() {
+            let at = rest
+                .windows(emission.len())
+                .position(|window| window == emission.as_slice())
+                .expect("emission in the pickle");
+            rest = &rest[at + emission.len()..];
+        }
+    }
+
+    #[test]
+    fn vetoed_emissions_are_rolled_back() {
+        for version in Version::all() {
+            let mut generator =
+                Generator::new(version)
+                    .with_seed(11)
+                    .with_emit_hook(|_, opcode, _| {
+                        if matches!(opcode, OpcodeKind::EmptyList | OpcodeKind::Append) {
+                            EmitVerdict::Veto
+                        } else {
+                            EmitVerdict::Keep
+                        }
+                    });
+            for _ in 0..10 {
+                let pickle = generator.generate().unwrap();
+                validate(&pickle).unwrap_or_else(|e| panic!("protocol {version}: {e}"));
+                let opcodes = disassemble(&pickle).unwrap();
+                assert!(opcodes
+                    .iter()
+                    .all(|op| op.name != "EMPTY_LIST" && op.name != "APPEND"));
+            }
+        }
+    }
+
+    #[test]
+    fn filtered_opcodes_are_never_picked() {
+        for version in Version::all() {
+            let mut generator = Generator::new(version)
+                .with_seed(6)
+                .with_opcode_filter(|_, opcode| opcode != OpcodeKind::Mark)
+                .with_opcode_filter(|state, opcode| {
+                    opcode != OpcodeKind::Pop || state.stack.len() > 3
+                });
+            for _ in 0..10 {
+                let pickle = generator.generate().unwrap();
+                validate(&pickle).unwrap_or_else(|e| panic!("protocol {version}: {e}"));
+                let opcodes = disassemble(&pickle).unwrap();
+                assert!(
+                    opcodes.iter().all(|op| op.name != "MARK"),
+                    "protocol {version}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn filters_can_count_with_an_emit_hook() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let globals = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&globals);
+        let mut generator = Generator::new(Version::V2)
+            .with_seed(2)
+            .with_opcode_range(200, 300)
+            .with_opcode_filter(move |_, opcode| {
+                opcode != OpcodeKind::Global || globals.load(Ordering::Relaxed) < 3
+            })
+            .with_emit_hook(move |_, opcode, _| {
+                if *opcode == OpcodeKind::Global {
+                    counted.fetch_add(1, Ordering::Relaxed);
+                }
+                EmitVerdict::Keep
+            });
+        let pickle = generator.generate().unwrap();
+        let opcodes = disassemble(&pickle).unwrap();
+        assert!(opcodes.iter().filter(|op| op.name == "GLOBAL").count() <= 3);
+    }
+
+    #[test]
+    fn hooks_do_not_change_kept_output() {
+        let plain = Generator::new(Version::V3).with_seed(4).generate().unwrap();
+        let hooked = Generator::new(Version::V3)
+            .with_seed(4)
+            .with_emit_hook(|_, _, _| EmitVerdict::Keep)
+            .generate()
+            .unwrap();
+        assert_eq!(plain, hooked);
+    }
+}
@@ -0,0 +1,282 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! generation for pickles that `pickle.loads` accepts.
+//!
+//! a structurally valid pickle still fails to load when it calls a random
+//! global with random arguments, uses a list as a dict key, or holds a
+//! protocol 0 str that isn't ASCII. in loadable mode globals come from a
+//! table of builtin constructors and `copyreg._reconstructor`, REDUCE and
+//! NEWOBJ are only picked when the arguments on the stack are ones the
+//! callable accepts, dict keys and set items have to be hashable, and
+//! STRING and BINSTRING payloads stay ASCII. the calls that can't be checked
+//! that way (BUILD, INST, OBJ, and NEWOBJ_EX) are never picked, and the
+//! patterns that call random globals are skipped for one that calls a
+//! constructor with arguments built to fit it.
+//!
+//! extension registries, persistent ids, and out-of-band buffers depend on
+//! the loader, so EXT opcodes are never picked and the other two stay off
+//! unless enabled.
+
+use super::restrict::calls_global;
+use super::signatures::is_hashable;
+use super::Generator;
+use crate::opcodes::{Opcode, OpcodeKind};
+use crate::StackObjectRef;
+
+/// builtin types every Python 3 `pickle.loads` can import and call.
+pub(super) const LOADABLE_TYPES: [&str; 6] =
+    ["list", "dict", "set", "frozenset", "complex", "bytearray"];
+
+/// the function object pickling uses to rebuild instances of builtin
+/// subclasses, `copyreg._reconstructor(cls, base, state)`.
+pub(super) const RECONSTRUCTOR: (&str, &str) = ("copyreg", "_reconstructor");
+
+/// whether `module.name` is one of the globals loadable mode imports.
+pub(super) fn loadable_global(module: &str, name: &str) -> bool {
+    (module == "builtins" && LOADABLE_TYPES.contains(&name)) || (module, name) == RECONSTRUCTOR
+}
+
+impl Generator {
+    /// only generate pickles that `pickle.loads` unpickles without an error.
+    ///
+    /// GLOBAL and STACK_GLOBAL import `builtins` list, dict, set, frozenset,
+    /// complex, and bytearray, and `copyreg._reconstructor`. REDUCE is picked
+    /// only when the arguments on the stack construct the callable, and
+    /// NEWOBJ only when `cls.__new__` takes them; BUILD, INST, OBJ,
+    /// NEWOBJ_EX, and EXT opcodes are never picked. dict keys, set items, and
+    /// frozenset items have to be hashable, and STRING, BINSTRING, and
+    /// SHORT_BINSTRING payloads are ASCII so the default `encoding='ASCII'`
+    /// decodes them.
+    ///
+    /// patterns that call random globals (the global call, dict of reduces,
+    /// and setstate chain idioms, ndarrays, torch tensors, and sklearn
+    /// estimators) are skipped, and a constructor call pattern with
+    /// arguments built to fit takes their place. a global allowlist narrows
+    /// the constructors further. mutated emissions that call, import, or
+    /// build a key are rolled back. unsafe mutations, EXT, persistent-id,
+    /// and buffer opcodes can still make output fail to load, and canonical
+    /// and diverse-encoding output ignore this.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pickle_fuzzer::{disasm, Generator, Version};
+    ///
+    /// let mut gen = Generator::new(Version::V2).with_seed(7).with_loadable(true);
+    /// let pickle = gen.generate().unwrap();
+    /// for op in disasm::disassemble(&pickle).unwrap() {
+    ///     assert!(!["BUILD", "INST", "OBJ"].contains(&op.name));
+    /// }
+    /// ```
+    pub fn with_loadable(mut self, enabled: bool) -> Self {
+        self.loadable = enabled;
+        self
+    }
+
+    /// whether loadable mode lets generation pick `opcode` with the stack as
+    /// it is.
+    pub(super) fn loadable_allows(&self, opcode: OpcodeKind) -> bool {
+        use OpcodeKind as Op;
+
+        match opcode {
+            Op::Build | Op::Inst | Op::Obj | Op::NewObjEx => false,
+            // unlike plain signature checks, callables without a signature
+            // aren't called
+            Op::Reduce | Op::NewObj => self.signature_verdict(opcode).unwrap_or(false),
+            Op::SetItem => self.peek_at(1).is_some_and(is_hashable),
+            Op::SetItems | Op::Dict => self.items_above_mark_hashable(2),
+            Op::AddItems | Op::FrozenSet => self.items_above_mark_hashable(1),
+            _ => true,
+        }
+    }
+
+    /// whether every `step`-th item above the topmost MARK, starting with
+    /// the first, is hashable: every key of a SETITEMS or DICT, or every
+    /// item of an ADDITEMS or FROZENSET.
+    fn items_above_mark_hashable(&self, step: usize) -> bool {
+        let Some(mark) = self.state.stack.top_mark() else {
+            return false;
+        };
+        self.state.stack.items()[mark + 1..]
+            .iter()
+            .step_by(step)
+            .all(is_hashable)
+    }
+
+    /// whether a mutated emission is one loadable mode keeps: no calls,
+    /// imports, hashed items, or non-ASCII protocol 0 strings.
+    pub(super) fn loadable_keeps(&self, opcode: &Opcode) -> bool {
+        if !self.loadable {
+            return true;
+        }
+        use OpcodeKind as Op;
+
+        match opcode {
+            Opcode::String(bytes) | Opcode::BinString(bytes) | Opcode::ShortBinString(bytes) => {
+                bytes.is_ascii()
+            }
+            _ => {
+                let kind = opcode.kind();
+                !calls_global(kind)
+                    && !matches!(
+                        kind,
+                        Op::Build
+                            | Op::SetItem
+                            | Op::SetItems
+                            | Op::Dict
+                            | Op::AddItems
+                            | Op::FrozenSet
+                    )
+            }
+        }
+    }
+
+    /// the object the STOP of the most recent pickle returns, as the stack
+    /// simulation built it.
+    ///
+    /// `None` before the first run and after runs that don't end with exactly
+    /// one object on the stack. [`crate::loadcheck`] compares it with the
+    /// object `pickle.loads` builds.
+    pub fn simulated_root(&self) -> Option<&StackObjectRef> {
+        match self.state.stack.items() {
+            [root] => Some(root),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disasm::{read_opcode, validate};
+    use crate::risk::{self, Risk};
+    use crate::Version;
+
+    /// the globals `pickle` imports with GLOBAL, as `module name`.
+    fn imports(pickle: &[u8]) -> Vec<(String, String)> {
+        let mut rest = pickle;
+        let mut imports = Vec::new();
+        while let Ok((opcode, len)) = read_opcode(rest) {
+            rest = &rest[len..];
+            if let Opcode::Global(module, name) = opcode {
+                imports.push((module, name));
+            }
+        }
+        imports
+    }
+
+    #[test]
+    fn loadable_generation_only_imports_constructors() {
+        for version in Version::all() {
+            for seed in 0..20 {
+                let pickle = Generator::new(version)
+                    .with_seed(seed)
+                    .with_loadable(true)
+                    .with_interesting_patterns(true)
+                    .generate()
+                    .unwrap();
+                validate(&pickle).unwrap_or_else(|e| panic!("protocol {version}: {e}"));
+                for (module, name) in imports(&pickle) {
+                    assert!(loadable_global(&module, &name), "{module}.{name}");
+                }
+                assert!(risk::classify(&pickle) <= Risk::Call, "protocol {version}");
+            }
+        }
+    }
+
+    #[test]
+    fn calls_need_fitting_arguments() {
+        let mut generator = Generator::new(Version::V2).with_loadable(true);
+        generator.begin().unwrap();
+        generator
+            .emit(Opcode::Global("builtins".into(), "complex".into()))
+            .unwrap();
+        generator.emit(Opcode::Int(1)).unwrap();
+        generator.emit(Opcode::Tuple1).unwrap();
+        assert!(!generator.loadable_allows(OpcodeKind::Reduce));
+
+        let mut generator = Generator::new(Version::V2).with_loadable(true);
+        generator.begin().unwrap();
+        generator
+            .emit(Opcode::Global("builtins".into(), "set".into()))
+            .unwrap();
+        generator.emit(Opcode::Mark).unwrap();
+        generator.emit(Opcode::BinInt1(1)).unwrap();
+        generator.emit(Opcode::EmptyList).unwrap();
+        generator.emit(Opcode::Tuple).unwrap();
+        generator.emit(Opcode::Tuple1).unwrap();
+        // set(((1, []),)) hashes the list, but set.__new__ ignores its arguments
+        assert!(!generator.loadable_allows(OpcodeKind::Reduce));
+        assert!(generator.loadable_allows(OpcodeKind::NewObj));
+
+        let mut generator = Generator::new(Version::V2).with_loadable(true);
+        generator.begin().unwrap();
+        for opcode in [
+            Opcode::Global("copyreg".into(), "_reconstructor".into()),
+            Opcode::Global("builtins".into(), "frozenset".into()),
+            Opcode::Global("builtins".into(), "frozenset".into()),
+            Opcode::ShortBinString(b"ab".to_vec()),
+            Opcode::Tuple3,
+        ] {
+            generator.emit(opcode).unwrap();
+        }
+        assert!(generator.loadable_allows(OpcodeKind::Reduce));
+        assert!(!generator.loadable_allows(OpcodeKind::NewObj));
+    }
+
+    #[test]
+    fn unhashable_keys_are_never_set() {
+        let mut generator = Generator::new(Version::V4).with_loadable(true);
+        generator.begin().unwrap();
+        generator.emit(Opcode::EmptyDict).unwrap();
+        generator.emit(Opcode::EmptyList).unwrap();
+        generator.emit(Opcode::None).unwrap();
+        assert!(!generator.loadable_allows(OpcodeKind::SetItem));
+
+        let mut generator = Generator::new(Version::V4).with_loadable(true);
+        generator.begin().unwrap();
+        generator.emit(Opcode::EmptySet).unwrap();
+        generator.emit(Opcode::Mark).unwrap();
+        generator.emit(Opcode::BinInt1(1)).unwrap();
+        generator.emit(Opcode::EmptyTuple).unwrap();
+        assert!(generator.loadable_allows(OpcodeKind::AddItems));
+        generator.emit(Opcode::EmptyDict).unwrap();
+        assert!(!generator.loadable_allows(OpcodeKind::AddItems));
+        assert!(!generator.loadable_allows(OpcodeKind::FrozenSet));
+    }
+
+    #[test]
+    fn protocol_0_strings_stay_ascii() {
+        for seed in 0..20 {
+            let pickle = Generator::new(Version::V1)
+                .with_seed(seed)
+                .with_loadable(true)
+                .generate()
+                .unwrap();
+            let mut rest = &pickle[..];
+            while let Ok((opcode, len)) = read_opcode(rest) {
+                rest = &rest[len..];
+                if let Opcode::String(bytes)
+                | Opcode::BinString(bytes)
+                | Opcode::ShortBinString(bytes) = opcode
+                {
+                    assert!(bytes.is_ascii(), "seed {seed}: {bytes:?}");
+                }
+            }
+        }
+    }
+}
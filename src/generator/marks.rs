@@ -0,0 +1,307 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! deliberately confusing MARK handling.
+//!
+//! generation normally keeps MARKs tidy: DUP never copies one, and every
+//! MARK-consuming opcode closes a MARK opened for what it builds. unpicklers
+//! disagree on exactly these cases. Python 2's pure-Python unpickler kept the
+//! mark object on the stack, so DUP copied it and the next MARK-consuming
+//! opcode stopped at the copy; Python 3 keeps marks on a separate stack and
+//! raises on a DUP with nothing above the MARK; `pickletools` pops the MARK
+//! as an ordinary item and then can't find it. with unsafe marks the
+//! generator emits those cases, simulating a duplicated MARK the way
+//! Python 2 did, so later opcodes keep a stack to work against.
+//!
+//! the tangled marks pattern nests two batches and closes them in the order
+//! they were opened instead of innermost first, so each batch opcode closes
+//! the MARK meant for the other container.
+//!
+//! mark stress bursts are dense runs of MARK, POP, POP_MARK, DUP, and
+//! scalars, planned here so that every MARK they open is closed again and
+//! the burst leaves exactly one new object, whatever order the steps come
+//! in. unpicklers track MARKs on a stack of their own (Python 3's
+//! `metastack`, a separate mark list in most C and Rust parsers), and runs
+//! like `MARK MARK POP POP_MARK` are where that bookkeeping and the value
+//! stack drift apart. with unsafe marks a burst may also DUP a MARK it
+//! opened, so POP_MARKs outnumber MARKs.
+
+use super::source::{EntropySource, GenerationSource};
+use super::Generator;
+use crate::opcodes::OpcodeKind;
+use crate::stack::StackObject;
+
+/// most opcodes one mark stress burst may have.
+pub const MAX_STRESS_OPS: usize = 64;
+
+/// one opcode of a mark stress burst.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum StressOp {
+    Mark,
+    /// any scalar
+    Push,
+    Dup,
+    Pop,
+    PopMark,
+}
+
+impl StressOp {
+    const ALL: [StressOp; 5] = [
+        StressOp::Mark,
+        StressOp::Push,
+        StressOp::Dup,
+        StressOp::Pop,
+        StressOp::PopMark,
+    ];
+}
+
+/// what a burst has on the stack: the items above what was there, then per
+/// MARK it opened (or duplicated) the items above that MARK.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Levels(Vec<usize>);
+
+impl Levels {
+    fn new() -> Self {
+        Levels(vec![0])
+    }
+
+    /// apply `op`, or return `false` if it would touch what the burst didn't
+    /// push, or copy a MARK without `unsafe_marks`.
+    fn apply(&mut self, op: StressOp, unsafe_marks: bool) -> bool {
+        let opened = self.0.len() > 1;
+        let top = self.0.len() - 1;
+        match op {
+            StressOp::Mark => self.0.push(0),
+            StressOp::Push => self.0[top] += 1,
+            StressOp::Dup if self.0[top] > 0 => self.0[top] += 1,
+            StressOp::Dup if opened && unsafe_marks => self.0.push(0),
+            StressOp::Pop if self.0[top] > 0 => self.0[top] -= 1,
+            // a POP with nothing above the MARK pops the MARK
+            StressOp::Pop | StressOp::PopMark if opened => {
+                self.0.pop();
+            }
+            StressOp::Dup | StressOp::Pop | StressOp::PopMark => return false,
+        }
+        true
+    }
+
+    /// opcodes it takes to close every MARK and leave exactly one item.
+    fn closing_cost(&self) -> usize {
+        let base = self.0[0];
+        (self.0.len() - 1) + if base == 0 { 1 } else { base - 1 }
+    }
+
+    /// items and MARKs above what was there.
+    fn height(&self) -> usize {
+        self.0.iter().sum::<usize>() + self.0.len() - 1
+    }
+}
+
+/// a planned mark stress burst.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct StressBurst {
+    ops: [StressOp; MAX_STRESS_OPS],
+    len: usize,
+}
+
+impl StressBurst {
+    /// a burst of no opcodes, to plan over.
+    pub(super) const EMPTY: StressBurst = StressBurst {
+        ops: [StressOp::Push; MAX_STRESS_OPS],
+        len: 0,
+    };
+
+    /// plan a burst of at most `target` opcodes: random steps while there
+    /// is budget left to close what they open, then POP_MARKs for the
+    /// MARKs still open and a POP or scalar so that one item is left.
+    pub(super) fn plan(target: usize, unsafe_marks: bool, source: &mut GenerationSource) -> Self {
+        let target = target.clamp(1, MAX_STRESS_OPS);
+        let mut burst = StressBurst::EMPTY;
+        let mut levels = Levels::new();
+        loop {
+            let fits: Vec<StressOp> = StressOp::ALL
+                .into_iter()
+                .filter(|&op| {
+                    let mut next = levels.clone();
+                    next.apply(op, unsafe_marks) && burst.len + 1 + next.closing_cost() <= target
+                })
+                .collect();
+            if fits.is_empty() {
+                break;
+            }
+            let op = fits[source.choose_index(fits.len())];
+            levels.apply(op, unsafe_marks);
+            burst.push(op);
+        }
+        while levels.0.len() > 1 {
+            levels.apply(StressOp::PopMark, unsafe_marks);
+            burst.push(StressOp::PopMark);
+        }
+        let closing = if levels.0[0] == 0 {
+            StressOp::Push
+        } else {
+            StressOp::Pop
+        };
+        while levels.0[0] != 1 {
+            levels.apply(closing, unsafe_marks);
+            burst.push(closing);
+        }
+        burst
+    }
+
+    fn push(&mut self, op: StressOp) {
+        self.ops[self.len] = op;
+        self.len += 1;
+    }
+
+    pub(super) fn ops(&self) -> &[StressOp] {
+        &self.ops[..self.len]
+    }
+
+    /// most items and MARKs the burst has on the stack at once.
+    pub(super) fn peak_stack_growth(&self) -> usize {
+        let mut levels = Levels::new();
+        self.ops()
+            .iter()
+            .map(|&op| {
+                levels.apply(op, true);
+                levels.height()
+            })
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+impl Generator {
+    /// emit deliberately confusing MARK sequences (off by default).
+    ///
+    /// DUP may duplicate a MARK on top of the stack, which the simulation
+    /// tracks as a second MARK, and about one in sixteen steps of the
+    /// generation loop (protocol 1+) opens two batches, say an APPENDS inside
+    /// a SETITEMS, and closes the outer one first, so APPENDS and SETITEMS
+    /// each consume the other's MARK. pickles with a duplicated MARK fail
+    /// `pickletools.dis` and Python 3's unpickler, so like unsafe mutations
+    /// this is meant for probing how parsers and scanners handle MARKs, not
+    /// for corpora that have to load. canonical and diverse-encoding output
+    /// ignore it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pickle_fuzzer::{Generator, Version};
+    ///
+    /// let mut gen = Generator::new(Version::V2).with_seed(3).with_unsafe_marks(true);
+    /// let pickle = gen.generate().unwrap();
+    /// assert_eq!(pickle.last(), Some(&b'.'));
+    /// ```
+    pub fn with_unsafe_marks(mut self, enabled: bool) -> Self {
+        self.unsafe_marks = enabled;
+        self
+    }
+
+    /// sometimes emit a dense burst of MARK, POP, POP_MARK, DUP, and scalars
+    /// of up to `intensity` opcodes, at most [`MAX_STRESS_OPS`] (protocol 1+).
+    ///
+    /// about one in sixteen steps of the generation loop emits a burst like
+    /// `MARK None MARK DUP POP_MARK POP POP True`; every MARK it opens is
+    /// closed inside it and it leaves exactly one new object, so the pickle
+    /// stays valid. with unsafe marks bursts also DUP the MARKs they open,
+    /// which Python 3 and `pickletools` reject.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pickle_fuzzer::{Generator, Version};
+    ///
+    /// let mut gen = Generator::new(Version::V2).with_seed(3).with_mark_stress(16);
+    /// let pickle = gen.generate().unwrap();
+    /// assert_eq!(pickle.last(), Some(&b'.'));
+    /// ```
+    pub fn with_mark_stress(mut self, intensity: usize) -> Self {
+        self.mark_stress = Some(intensity.clamp(1, MAX_STRESS_OPS));
+        self
+    }
+
+    /// whether DUP may copy the top of the stack: anything but a MARK, or a
+    /// MARK too with unsafe marks.
+    pub(super) fn dup_allowed(&self) -> bool {
+        self.peek()
+            .is_some_and(|top| self.unsafe_marks || !matches!(*top.borrow(), StackObject::Mark))
+    }
+
+    /// whether strict checks let `opcode` close whatever MARK is on top,
+    /// whatever sits below it: tangled marks do that on purpose.
+    pub(super) fn closes_any_mark(&self, opcode: OpcodeKind) -> bool {
+        use OpcodeKind::*;
+
+        self.unsafe_marks && self.has_mark() && matches!(opcode, Appends | SetItems | AddItems)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::disasm::{disassemble, validate};
+    use crate::opcodes::{Opcode, OpcodeKind};
+    use crate::{Generator, Version};
+
+    #[test]
+    fn dup_copies_marks_only_when_unsafe() {
+        for unsafe_marks in [false, true] {
+            let mut generator = Generator::new(Version::V2)
+                .with_seed(1)
+                .with_unsafe_marks(unsafe_marks);
+            generator.begin().unwrap();
+            generator.emit(Opcode::Mark).unwrap();
+            assert_eq!(generator.can_emit(OpcodeKind::Dup), unsafe_marks);
+            if !unsafe_marks {
+                continue;
+            }
+            generator.emit(Opcode::Dup).unwrap();
+            assert_eq!(generator.state.stack.mark_positions(), &[0, 1]);
+
+            // TUPLE stops at the copy, and the original MARK is left
+            generator.emit(Opcode::BinInt1(1)).unwrap();
+            generator.emit(Opcode::Tuple).unwrap();
+            assert_eq!(generator.state.stack.mark_positions(), &[0]);
+            let pickle = generator.finish().unwrap();
+            assert!(validate(&pickle).is_err());
+        }
+    }
+
+    #[test]
+    fn unsafe_marks_sometimes_dup_a_mark() {
+        let duplicated = (0..40).any(|seed| {
+            let pickle = Generator::new(Version::V2)
+                .with_seed(seed)
+                .with_unsafe_marks(true)
+                .generate()
+                .unwrap();
+            let instructions = disassemble(&pickle).unwrap();
+            instructions
+                .windows(2)
+                .any(|pair| pair[0].name == "MARK" && pair[1].name == "DUP")
+        });
+        assert!(duplicated);
+
+        let plain = Generator::new(Version::V2).with_seed(5).generate().unwrap();
+        let safe = Generator::new(Version::V2)
+            .with_seed(5)
+            .with_unsafe_marks(false)
+            .generate()
+            .unwrap();
+        assert_eq!(plain, safe);
+    }
+}
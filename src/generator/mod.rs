@@ -23,23 +23,79 @@
 //!
 //! - `source`: entropy source abstraction (rand vs arbitrary)
 //! - `core`: main generation loop and PROTO/FRAME handling
+//! - `header`: PROTO headers naming another protocol than the body (with_proto_header)
 //! - `emission`: opcode emission methods (emit_int, emit_string, etc.)
+//! - `boundaries`: integer encoding-boundary values (with_integer_boundaries)
 //! - `validation`: opcode validation (can_emit, get_valid_opcodes)
 //! - `stack_ops`: stack simulation (process_stack_ops, cleanup_for_stop)
 //! - `utils`: helper methods (peek, push, pop, has_mark, is_*_at)
-//! - `mutation`: mutation support (mutate_*, create_snapshot)
+//! - `patterns`: multi-opcode emission patterns (with_interesting_patterns, with_indirect_stack_globals)
+//! - `canonical`: object pickler modes (with_canonical, with_diverse_encodings)
+//! - `sizes`: container size distributions (with_container_sizes)
+//! - `ndarray`: numpy array shapes and dtypes (with_ndarrays)
+//! - `mutation`: mutation support (mutate_*, create_snapshot, MutationPolicy, MutationScope)
+//! - `strict`: opt-in invariant checks (with_strict_checks)
+//! - `stats`: per-run statistics (GenerationStats)
+//! - `annotate`: offset→annotation maps of generated pickles (with_annotations)
+//! - `budget`: per-run time budget (with_time_budget, TimeBudgetExceeded)
+//! - `restrict`: generation for hardened unpicklers (with_global_allowlist, with_forbid_reduce)
+//! - `loadable`: generation for pickles `pickle.loads` accepts (with_loadable)
+//! - `signatures`: argument shapes of well-known callables (with_signatures)
+//! - `frames`: FRAME opcodes where picklers never put them (with_unsafe_frames)
+//! - `persid`: persistent id shapes (with_persistent_id_payloads, with_unsafe_persistent_ids)
+//! - `marks`: deliberately confusing MARK handling and mark stress bursts
+//!   (with_unsafe_marks, with_mark_stress)
+//! - `pool`: per-thread generators for multithreaded embedders (GeneratorPool)
 
+mod annotate;
+mod boundaries;
+mod budget;
+mod canonical;
 mod core;
 mod emission;
+mod frames;
+mod header;
+mod hook;
+mod loadable;
+mod marks;
 mod mutation;
+mod ndarray;
+mod patterns;
+mod persid;
+mod pool;
+mod restrict;
+mod script;
+mod shrink;
+mod signatures;
+mod sizes;
 mod source;
 mod stack_ops;
+mod stats;
+mod strict;
+mod trace;
 mod utils;
 mod validation;
 
-pub use source::{EntropySource, GenerationSource};
+pub use annotate::Annotation;
+pub use budget::TimeBudgetExceeded;
+pub use header::ProtoHeader;
+pub use hook::EmitVerdict;
+pub use marks::MAX_STRESS_OPS;
+pub use mutation::{MutationPolicy, MutationScope, MutationTarget};
+pub use ndarray::{Dtype, NdarraySpec};
+pub use pool::{GeneratorPool, PooledGenerator};
+pub use shrink::Shrunk;
+pub use sizes::SizeDistribution;
+pub use source::{EntropySource, ExhaustionPolicy, GenerationSource};
+pub use stack_ops::CleanupPolicy;
+pub use stats::GenerationStats;
+pub use trace::{Decision, EntropyTrace};
 
 // ---8<--- module declarations above; Generator definition and imports below ---8<---
+use std::cell::{Cell, RefCell};
+use std::io::Write;
+use std::time::{Duration, Instant};
+
 use arbitrary::Unstructured;
 use color_eyre::Result;
 use rand::SeedableRng;
@@ -51,6 +107,19 @@ use super::state::State;
 
 const MAX_OPCODE_RANGE_BOUND: usize = 50_000;
 
+/// default number of elements a simulated container tracks before it is summarized.
+pub const DEFAULT_CONTAINER_SIZE_LIMIT: usize = 1024;
+
+/// version of the generator's output format.
+///
+/// for a fixed seed (or fuzzer input) and configuration, the generator produces
+/// byte-identical output on every platform for as long as this value is unchanged,
+/// including across crate releases. any change that alters the bytes produced for an
+/// existing configuration - entropy draw order, opcode selection, encodings - must
+/// bump it and regenerate the golden corpus in `tests/golden/corpus.jsonl` with
+/// `UPDATE_GOLDEN=1 cargo test --test golden_test`.
+pub const GENERATOR_FORMAT_VERSION: u32 = 15;
+
 fn normalize_opcode_range(min: usize, max: usize) -> (usize, usize) {
     let min = min.min(MAX_OPCODE_RANGE_BOUND);
     let max = max.min(MAX_OPCODE_RANGE_BOUND);
@@ -62,6 +131,14 @@ fn normalize_opcode_range(min: usize, max: usize) -> (usize, usize) {
     }
 }
 
+/// the value stream of sibling `variant` of `seed`: ChaCha8 stream
+/// `variant + 1`, so it never overlaps the structure stream (stream 0).
+fn value_stream(seed: u64, variant: u64) -> ChaCha8Rng {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    rng.set_stream(variant + 1);
+    rng
+}
+
 /// stateful pickle generator that produces valid pickle bytecode.
 ///
 /// the generator maintains a simulated pickle virtual machine (PVM) stack and memo
@@ -110,9 +187,12 @@ pub struct Generator {
     /// generated output bytecode
     pub output: Vec<u8>,
 
-    /// optional seed for the PRNG (if None, uses OS entropy)
+    /// optional seed for the PRNG (if None, uses OS entropy; requires the `os-rng` feature)
     pub seed: Option<u64>,
 
+    /// optional seed for a leaf value stream of its own (see `with_value_seed`)
+    pub value_seed: Option<u64>,
+
     /// maximum pickle size for generated output
     pub bufsize: Option<usize>,
 
@@ -125,9 +205,21 @@ pub struct Generator {
     /// active mutators for argument mutation
     pub mutators: Vec<Box<dyn Mutator>>,
 
+    /// hooks that see every emission after post-processing (see `with_emit_hook`)
+    emit_hooks: Vec<hook::EmitHook>,
+
+    /// filters on which opcodes may be picked next (see `with_opcode_filter`)
+    opcode_filters: Vec<hook::OpcodeFilter>,
+
     /// mutation rate (0.0-1.0)
     pub mutation_rate: f64,
 
+    /// how many mutators may compose on one value
+    pub mutation_policy: MutationPolicy,
+
+    /// which opcode argument classes mutators may touch
+    pub mutation_scope: MutationScope,
+
     /// allow unsafe mutations that may violate pickle validity
     pub unsafe_mutations: bool,
 
@@ -139,6 +231,127 @@ pub struct Generator {
 
     /// allow PERSID/BINPERSID opcodes (requires persistent_load support)
     pub allow_persistent_id_opcodes: bool,
+
+    /// number of elements a simulated container keeps before only its size is tracked
+    pub container_size_limit: usize,
+
+    /// how cleanup reduces the stack to the object STOP returns
+    pub cleanup_policy: CleanupPolicy,
+
+    /// largest number of items the stack may hold (None for no limit)
+    pub max_stack_depth: Option<usize>,
+
+    /// bias integer opcodes toward the edges of their encodings
+    pub integer_boundaries: bool,
+
+    /// sometimes emit a multi-opcode idiom instead of a single opcode
+    pub interesting_patterns: bool,
+
+    /// sometimes feed STACK_GLOBAL indirectly built module and name strings
+    pub indirect_stack_globals: bool,
+
+    /// the only globals generation may import, as module and name (None for any)
+    pub global_allowlist: Option<Vec<(String, String)>>,
+
+    /// never emit the opcodes that call a global
+    pub forbid_reduce: bool,
+
+    /// only emit what `pickle.loads` unpickles without an error
+    pub loadable: bool,
+
+    /// only call listed callables with arguments their signature accepts
+    pub signatures: bool,
+
+    /// duplicate MARKs and close nested batches out of order
+    pub unsafe_marks: bool,
+
+    /// most opcodes per mark stress burst, if bursts are emitted
+    pub mark_stress: Option<usize>,
+
+    /// which protocol the PROTO header announces
+    pub proto_header: ProtoHeader,
+
+    /// emit FRAME opcodes mid-pickle, nested, empty, or before protocol 4
+    pub unsafe_frames: bool,
+
+    /// build the ids BINPERSID pops as strs, ints, bytes, or tuples
+    pub persistent_id_payloads: bool,
+
+    /// give PERSID ids newlines and non-ASCII characters
+    pub unsafe_persistent_ids: bool,
+
+    /// keep an offset→annotation map of each pickle
+    pub annotate: bool,
+
+    /// verify stack, precondition, and argument invariants at every emission
+    pub strict_checks: bool,
+
+    /// wall-clock time one run may take (see `with_time_budget`)
+    pub time_budget: Option<Duration>,
+
+    /// pickle a random object exactly like CPython instead of choosing opcodes
+    pub canonical: bool,
+
+    /// pickle a random object with a random valid encoding for every value
+    pub diverse_encodings: bool,
+
+    /// distribution deliberately sized containers draw their size from
+    pub container_sizes: Option<SizeDistribution>,
+
+    /// fill containers of more than 1000 items in batches of more than 1000
+    pub oversized_batches: bool,
+
+    /// dtypes and shapes of the numpy arrays the ndarray pattern builds
+    pub ndarrays: Option<NdarraySpec>,
+
+    /// sometimes emit a torch tensor rebuilt from a persistent id storage
+    pub torch_tensors: bool,
+
+    /// sometimes emit a scikit-learn estimator the way joblib pickles it
+    pub sklearn_estimators: bool,
+
+    /// sometimes emit an old-style class instance the way Python 2 pickles it
+    pub legacy_instances: bool,
+
+    /// what generation from fuzzer bytes does once they are all consumed
+    pub exhaustion_policy: ExhaustionPolicy,
+
+    /// first strict-check violation of the current run, if any
+    strict_violation: Option<String>,
+
+    /// opcodes emitted by the last completed run, reported by `stats()`
+    emitted_opcodes: usize,
+
+    /// set when a value mutator changed an argument of the current emission
+    value_mutated: Cell<bool>,
+
+    /// mutated emissions of the current run that were kept, reported by `stats()`
+    mutated_emissions: usize,
+
+    /// how often each mutator fired in the current run, reported by
+    /// `mutator_applications()`; entries stay allocated across runs
+    mutator_applications: RefCell<Vec<(String, usize)>>,
+
+    /// when the current run started and the budget it has, if any
+    deadline: Option<(Instant, Duration)>,
+
+    /// bytes of the current pickle already written out by `generate_to`
+    streamed_len: usize,
+
+    /// PRNG `fill` draws from while a scripted pickle is open (see `begin`)
+    script_rng: Option<ChaCha8Rng>,
+
+    /// generation steps of the current run, when annotating
+    steps: Vec<annotate::Step>,
+
+    /// mutators that fired since the current step began, when annotating
+    fired_mutators: RefCell<Vec<String>>,
+
+    /// annotations of the current run, reported by `annotations()`
+    annotations: Vec<Annotation>,
+
+    /// bytes of the current pickle already annotated, and the stack after them
+    annotated: (usize, annotate::MarkDepth),
 }
 
 impl Default for Generator {
@@ -147,15 +360,60 @@ impl Default for Generator {
             state: State::default(),
             output: Vec::new(),
             seed: None,
+            value_seed: None,
             bufsize: None,
             min_opcodes: 60,
             max_opcodes: 300,
             mutators: Vec::new(),
+            emit_hooks: Vec::new(),
+            opcode_filters: Vec::new(),
             mutation_rate: 0.1,
+            mutation_policy: MutationPolicy::default(),
+            mutation_scope: MutationScope::default(),
             unsafe_mutations: false,
             allow_ext_opcodes: false,
             allow_buffer_opcodes: false,
             allow_persistent_id_opcodes: false,
+            container_size_limit: DEFAULT_CONTAINER_SIZE_LIMIT,
+            cleanup_policy: CleanupPolicy::default(),
+            max_stack_depth: None,
+            integer_boundaries: false,
+            interesting_patterns: false,
+            canonical: false,
+            diverse_encodings: false,
+            container_sizes: None,
+            oversized_batches: false,
+            ndarrays: None,
+            torch_tensors: false,
+            sklearn_estimators: false,
+            legacy_instances: false,
+            exhaustion_policy: ExhaustionPolicy::default(),
+            indirect_stack_globals: false,
+            global_allowlist: None,
+            forbid_reduce: false,
+            loadable: false,
+            signatures: true,
+            unsafe_marks: false,
+            mark_stress: None,
+            proto_header: ProtoHeader::default(),
+            unsafe_frames: false,
+            persistent_id_payloads: false,
+            unsafe_persistent_ids: false,
+            annotate: false,
+            strict_checks: false,
+            time_budget: None,
+            strict_violation: None,
+            emitted_opcodes: 0,
+            value_mutated: Cell::new(false),
+            mutated_emissions: 0,
+            mutator_applications: RefCell::new(Vec::new()),
+            deadline: None,
+            streamed_len: 0,
+            script_rng: None,
+            steps: Vec::new(),
+            fired_mutators: RefCell::new(Vec::new()),
+            annotations: Vec::new(),
+            annotated: Default::default(),
         }
     }
 }
@@ -196,6 +454,41 @@ impl Generator {
     pub fn reset(&mut self) {
         self.state.reset();
         self.output.clear();
+        self.strict_violation = None;
+        self.emitted_opcodes = 0;
+        self.mutated_emissions = 0;
+        for (_, count) in self.mutator_applications.get_mut() {
+            *count = 0;
+        }
+        self.streamed_len = 0;
+        self.script_rng = None;
+        self.steps.clear();
+        self.fired_mutators.get_mut().clear();
+        self.annotations.clear();
+        self.annotated = Default::default();
+    }
+
+    /// change the seed used by subsequent `generate()` calls.
+    ///
+    /// `None` switches back to OS entropy. unlike `with_seed`, this works on a
+    /// generator that is being reused across samples.
+    pub fn set_seed(&mut self, seed: Option<u64>) {
+        self.seed = seed;
+    }
+
+    /// change the value seed used by subsequent `generate()` calls.
+    ///
+    /// `None` goes back to drawing values from the seed's one stream.
+    pub fn set_value_seed(&mut self, value_seed: Option<u64>) {
+        self.value_seed = value_seed;
+    }
+
+    /// change the protocol version used by subsequent generation calls.
+    ///
+    /// resets the generator, keeping its allocated buffers and configuration.
+    pub fn set_version(&mut self, version: Version) {
+        self.state.version = version;
+        self.reset();
     }
 
     /// set a seed for the PRNG (for reproducible generation).
@@ -207,7 +500,32 @@ impl Generator {
         self
     }
 
+    /// draw leaf values from a ChaCha8 stream seeded with `value_seed`, apart
+    /// from the structure the seed picks.
+    ///
+    /// the seed (or OS entropy) then only makes the structural draws: which
+    /// opcode comes next, container and pattern sizes, memo slots, globals.
+    /// integers, floats, strings, bytes, and mutations come from a stream of
+    /// `value_seed`, so fixing the seed and changing `value_seed` rewrites the
+    /// arguments of the same opcodes, and fixing `value_seed` and changing the
+    /// seed puts the same values into a new skeleton. the value stream is the
+    /// one [`generate_variant`](Self::generate_variant) gives sibling 0, so
+    /// `with_seed(s).with_value_seed(s)` generates `generate_variant(s, 0)`.
+    ///
+    /// without a value seed, structure and values share the seed's stream,
+    /// which is what every earlier release did.
+    pub fn with_value_seed(mut self, value_seed: u64) -> Self {
+        self.value_seed = Some(value_seed);
+        self
+    }
+
     /// set a maximum pickle size for generated output.
+    ///
+    /// the limit covers the complete pickle, including cleanup opcodes and STOP.
+    /// once the next opcode would no longer fit, generation stops emitting and
+    /// moves to cleanup, so a tight limit takes precedence over `min_opcodes`.
+    /// generation fails if the limit is smaller than the smallest valid pickle
+    /// for the protocol.
     pub fn with_buffer_size(mut self, size: usize) -> Self {
         self.bufsize = Some(size);
         self
@@ -281,6 +599,28 @@ impl Generator {
         self
     }
 
+    /// choose how value-level mutators combine.
+    ///
+    /// the default, [`MutationPolicy::First`], applies only the first mutator
+    /// that triggers. [`MutationPolicy::All`] lets every mutator take a turn on
+    /// the same value in registration order, and [`MutationPolicy::Random`]
+    /// picks a random subset, so mutations such as boundary then bitflip stack.
+    pub fn with_mutation_policy(mut self, policy: MutationPolicy) -> Self {
+        self.mutation_policy = policy;
+        self
+    }
+
+    /// restrict mutators to opcodes with arguments in the given classes.
+    ///
+    /// by default mutators may touch every emission. a narrower scope, e.g.
+    /// `MutationScope::only(&[MutationTarget::Strings])`, applies value
+    /// mutations and post-emission rewrites only to matching opcodes, so a
+    /// string mutator can be kept away from memo indices or GLOBAL names.
+    pub fn with_mutation_scope(mut self, scope: MutationScope) -> Self {
+        self.mutation_scope = scope;
+        self
+    }
+
     /// enable unsafe mutations that may violate pickle validity.
     ///
     /// when enabled, allows mutations that can produce invalid pickles.
@@ -320,69 +660,267 @@ impl Generator {
         self
     }
 
-    fn minimum_pickle_size(&self) -> usize {
-        let proto_size = if self.state.version >= Version::V2 {
-            2
-        } else {
-            0
-        };
+    /// cap how many elements a simulated container tracks.
+    ///
+    /// containers that grow past `limit` are replaced by a summary that only keeps
+    /// their type and element count, which keeps simulation memory and time linear
+    /// in the opcode count for very large pickles. opcode selection only looks at
+    /// container types, so the limit never changes the generated bytes.
+    ///
+    /// defaults to [`DEFAULT_CONTAINER_SIZE_LIMIT`].
+    pub fn with_container_size_limit(mut self, limit: usize) -> Self {
+        self.container_size_limit = limit;
+        self
+    }
 
-        proto_size + 2
+    /// choose how the stack is reduced to a single object before STOP.
+    ///
+    /// the default, [`CleanupPolicy::Tuple`], wraps leftover items into tuples.
+    /// [`CleanupPolicy::KeepRoot`] pops them instead, so the root is the first
+    /// object generation built (often a container it kept filling) rather than a
+    /// synthetic tuple. opcode and size budgets account for either policy.
+    pub fn with_cleanup_policy(mut self, policy: CleanupPolicy) -> Self {
+        self.cleanup_policy = policy;
+        self
     }
 
-    fn generate_with_bufsize<F>(&mut self, max_size: usize, mut attempt: F) -> Result<Vec<u8>>
-    where
-        F: FnMut(&mut Self, Option<usize>, Option<bool>) -> Result<Vec<u8>>,
-    {
-        let minimum_size = self.minimum_pickle_size();
-        if max_size < minimum_size {
-            self.reset();
-            return Err(color_eyre::eyre::eyre!(
-                "buffer size {} is too small for protocol {} (minimum valid pickle size is {})",
-                max_size,
-                self.state.version as u8,
-                minimum_size
-            ));
-        }
+    /// cap the number of items on the simulated stack, MARKs included.
+    ///
+    /// once the stack holds `depth` items, opcodes that would grow it are no
+    /// longer chosen, so the unpickler never sees a deeper stack. the peak depth
+    /// a run actually reached is reported by [`stats`](Self::stats). a limit of
+    /// 0 is treated as 1, since STOP needs an object on the stack.
+    pub fn with_max_stack_depth(mut self, depth: usize) -> Self {
+        self.max_stack_depth = Some(depth.max(1));
+        self
+    }
 
-        let (_, max_budget) = self.normalized_opcode_range();
-        let mut last_error = None;
+    /// target the encoding boundaries of the integer opcodes.
+    ///
+    /// when enabled, half of the integer opcodes carry a value at the edge of
+    /// their encoding instead of a random one: 0/127/128/255 for BININT1,
+    /// 256/32767/32768/65535 for BININT2, 65536 and ±2^31 for BININT, and the
+    /// i32/i64/u64 limits for INT, LONG, LONG1 and LONG4. LONG1/LONG4 values are
+    /// encoded either minimally or padded with redundant sign bytes. unlike
+    /// [`BoundaryMutator`](crate::mutators::BoundaryMutator), which replaces a
+    /// random i32 with its limits, the values are picked per opcode and bypass
+    /// mutators.
+    pub fn with_integer_boundaries(mut self, enabled: bool) -> Self {
+        self.integer_boundaries = enabled;
+        self
+    }
 
-        let mut frame_modes = vec![None];
-        if self.state.version >= Version::V4 {
-            frame_modes.push(Some(false));
-        }
+    /// sometimes emit a common multi-opcode idiom instead of a single opcode.
+    ///
+    /// uniform single-opcode choice almost never lines up the opcodes real
+    /// picklers write together. when enabled, about one in sixteen steps of the
+    /// generation loop emits one of these as a unit instead:
+    ///
+    /// - GLOBAL, EMPTY_TUPLE, REDUCE (a stdlib call with no arguments)
+    /// - EMPTY_LIST, MARK, up to eight scalars, APPENDS (protocol 1+)
+    /// - EMPTY_DICT, MARK, up to four keys mapped to such calls, SETITEMS
+    /// - such a call followed by up to three dict states, each applied by BUILD
+    /// - a list holding a small memoized list, then up to three one-item tuples
+    ///   that fetch the same list with memo GETs, so it unpickles as one shared
+    ///   object
+    /// - a memoized list or dict of up to eight items, one of which is a memo
+    ///   GET of the container itself, like a pickled `a.append(a)`
+    ///
+    /// protocol 0 spells the empty tuple and dicts with MARK TUPLE and
+    /// MARK ... DICT. the patterns stay valid, bypass mutators, and respect the
+    /// opcode, byte, and stack depth limits. the output is unchanged when it is
+    /// off.
+    pub fn with_interesting_patterns(mut self, enabled: bool) -> Self {
+        self.interesting_patterns = enabled;
+        self
+    }
 
-        for force_frame in frame_modes {
-            self.reset();
-            match attempt(self, None, force_frame) {
-                Ok(bytes) if bytes.len() <= max_size => return Ok(bytes),
-                Ok(_) => {}
-                Err(error) => last_error = Some(error),
-            }
+    /// write the pickle CPython's `pickle.dumps` produces for a random object.
+    ///
+    /// instead of choosing opcodes one at a time, the generator builds a random
+    /// object (nested containers, shared references, stdlib globals, class
+    /// instances, and reduced objects) and pickles it the way the C pickler of
+    /// CPython 3.11 does: repeated objects are memoized, integers take their
+    /// shortest opcode, list, dict, and set items go in batches of up to 1000,
+    /// and protocol 4+ output is framed. this is the corpus real parsers see
+    /// most, and a baseline to diff other picklers against.
+    ///
+    /// the opcode range sets the rough size of the object: the maximum, the
+    /// byte limit, and the stack depth limit are respected, but the minimum is
+    /// not. mutators are rejected, and the other opcode-level options have no
+    /// effect.
+    pub fn with_canonical(mut self, enabled: bool) -> Self {
+        self.canonical = enabled;
+        self
+    }
 
-            for target_total in (0..=max_budget).rev() {
-                self.reset();
-                match attempt(self, Some(target_total), force_frame) {
-                    Ok(bytes) if bytes.len() <= max_size => return Ok(bytes),
-                    Ok(_) => {}
-                    Err(error) => last_error = Some(error),
-                }
-            }
-        }
+    /// pickle a random object like `with_canonical`, but with a random valid
+    /// encoding for every value instead of the one CPython picks.
+    ///
+    /// the values stay the same and the pickle still loads, but nothing is
+    /// canonical any more: small ints come as LONG4 or INT text, short strings
+    /// as BINUNICODE8 or fully escaped UNICODE, memo slots are filled with PUT
+    /// variants instead of MEMOIZE and sometimes twice, instances use OBJ,
+    /// NEWOBJ_EX, or `copyreg._reconstructor`, containers are built inline or
+    /// filled in batches of random size, and protocol 4+ output is framed in
+    /// small frames or not at all. parsers that assume CPython's encodings trip
+    /// over this corpus. the limits and restrictions of `with_canonical`
+    /// apply, and this takes precedence over it.
+    pub fn with_diverse_encodings(mut self, enabled: bool) -> Self {
+        self.diverse_encodings = enabled;
+        self
+    }
 
-        self.reset();
-        match last_error {
-            Some(error) => Err(color_eyre::eyre::eyre!(
-                "failed to generate pickle within {} bytes: {}",
-                max_size,
-                error
-            )),
-            None => Err(color_eyre::eyre::eyre!(
-                "failed to generate pickle within {} bytes",
-                max_size
-            )),
-        }
+    /// draw the size of containers from `sizes` instead of leaving it to
+    /// chance.
+    ///
+    /// opcode by opcode, about one in sixteen steps of the generation loop
+    /// emits a list, dict, or (protocol 4+) set of a drawn size, filled the
+    /// way CPython writes it: in APPENDS, SETITEMS, or ADDITEMS batches of up
+    /// to 1000 items. in the canonical and diverse modes, every nested list,
+    /// tuple, dict, and set value takes a drawn size; the root and instance
+    /// states still grow as before. so `constant:1` gives many tiny containers and
+    /// `zipf:20000` a few huge ones among small ones.
+    ///
+    /// a container that does not fit the remaining opcode budget, byte limit,
+    /// or stack depth limit is not emitted, so large sizes need a matching
+    /// `with_opcode_range`. the output is unchanged when this is not set.
+    pub fn with_container_sizes(mut self, sizes: SizeDistribution) -> Self {
+        self.container_sizes = Some(sizes);
+        self
+    }
+
+    /// fill containers of more than 1000 items in batches larger than
+    /// CPython's 1000-item `_BATCHSIZE`.
+    ///
+    /// CPython never puts more than 1000 items between a MARK and its
+    /// APPENDS, SETITEMS, or ADDITEMS, and reimplementations that hard-code
+    /// that limit size their buffers or reject the pickle accordingly, though
+    /// the unpickler accepts batches of any size. with this enabled, the
+    /// containers `with_container_sizes` draws past 1000 items are batched
+    /// with a random batch size over 1000 (up to the whole container in one
+    /// batch), and diverse encodings draw batch sizes over 1000 as well.
+    /// canonical output stays CPython's. the output is unchanged when this is
+    /// disabled.
+    pub fn with_oversized_batches(mut self, enabled: bool) -> Self {
+        self.oversized_batches = enabled;
+        self
+    }
+
+    /// sometimes emit a numpy array reconstruction, with a dtype and shape
+    /// drawn from `spec`.
+    ///
+    /// pickled numpy arrays are the bulk of what ML model scanners see, and
+    /// they always take the same shape: `_reconstruct` from
+    /// `numpy.core.multiarray` (`numpy._core.multiarray` since numpy 2)
+    /// called with `(numpy.ndarray, (0,), b'b')`, then a BUILD of the shape,
+    /// a `numpy.dtype` object with its own BUILD, the Fortran-order flag, and
+    /// the raw data bytes. random GLOBALs never line these up. with a spec,
+    /// about one in sixteen steps of the generation loop emits such an array,
+    /// encoded the way CPython's pickler would at the chosen protocol.
+    ///
+    /// arrays that don't fit the remaining opcode budget, byte limit, or
+    /// stack depth limit are left out. the output is unchanged when this is
+    /// not set.
+    pub fn with_ndarrays(mut self, spec: NdarraySpec) -> Self {
+        self.ndarrays = Some(spec);
+        self
+    }
+
+    /// sometimes emit a torch tensor the way `torch.save` pickles it
+    /// (protocol 1+).
+    ///
+    /// a PyTorch checkpoint's pickle is a tree of `_rebuild_tensor_v2` calls
+    /// from `torch._utils`, each on a storage that the pickle only names: a
+    /// BINPERSID of `('storage', torch.FloatStorage, key, location, numel)`
+    /// that `torch.load` looks up in the checkpoint archive. with this
+    /// enabled, about one in sixteen steps of the generation loop emits such a
+    /// tensor, with the storage offset, size and stride tuples,
+    /// `requires_grad`, and an empty `OrderedDict` of backward hooks. dtypes,
+    /// shapes up to four dimensions, `cpu` and `cuda:0` locations, and shared
+    /// storage keys are drawn at random.
+    ///
+    /// loading the output needs a `persistent_load` that resolves the storage,
+    /// as `torch.load` and model scanners have, so the tensors are only
+    /// emitted along with `with_persistent_id_opcodes`. the output is
+    /// unchanged when this is disabled.
+    pub fn with_torch_tensors(mut self, enabled: bool) -> Self {
+        self.torch_tensors = enabled;
+        self
+    }
+
+    /// sometimes emit a scikit-learn estimator the way `joblib.dump` pickles
+    /// it.
+    ///
+    /// sklearn model files are pickles of estimator classes from private
+    /// `sklearn.*` modules, created without `__init__` (NEWOBJ, or
+    /// `copy_reg._reconstructor` below protocol 2) and filled in with a BUILD
+    /// of their `__dict__`. with this enabled, about one in sixteen steps of
+    /// the generation loop emits such an estimator (`LinearRegression`,
+    /// `LogisticRegression`, `StandardScaler`, `PCA`, or `KMeans`) with its
+    /// default hyperparameters and `_sklearn_version`. most are fitted, and
+    /// also hold `n_features_in_` and, for every array attribute, the
+    /// `joblib.numpy_pickle.NumpyArrayWrapper` joblib pickles in the array's
+    /// place, its subclass, shape, order, and `numpy.dtype` set with a BUILD.
+    ///
+    /// joblib writes each array's bytes straight into the file after its
+    /// wrapper, so joblib files are not plain pickles; those bytes are left
+    /// out, and the output stays one. the output is unchanged when this is
+    /// disabled.
+    pub fn with_sklearn_estimators(mut self, enabled: bool) -> Self {
+        self.sklearn_estimators = enabled;
+        self
+    }
+
+    /// sometimes emit an old-style class instance the way Python 2's pickler
+    /// writes one.
+    ///
+    /// Python 2 pickled instances of classic classes with `save_inst`: MARK,
+    /// the arguments `__getinitargs__` returns (usually none), and INST with
+    /// the class name inline on protocol 0, or the class as a GLOBAL and OBJ
+    /// on protocols 1 and 2; then the instance's `__dict__` and BUILD. the
+    /// class, the instance, the dict, and its str keys are all memoized.
+    /// plenty of those pickles are still around, and INST and OBJ are the
+    /// opcodes loaders and scanners handle least consistently. with this
+    /// enabled, about one in sixteen steps of the generation loop on protocol
+    /// 2 and below emits one for a random stdlib class, with int, float,
+    /// bool, or None arguments and attributes. the output is unchanged when
+    /// this is disabled.
+    pub fn with_legacy_instances(mut self, enabled: bool) -> Self {
+        self.legacy_instances = enabled;
+        self
+    }
+
+    /// choose what [`generate_from_arbitrary`](Self::generate_from_arbitrary)
+    /// does once the fuzzer's bytes are all consumed.
+    ///
+    /// the default, [`ExhaustionPolicy::Fallback`], keeps drawing the fallback
+    /// values an empty input gives, which turns the tail of a short input into a
+    /// long run of the same few opcodes. [`ExhaustionPolicy::Stop`] emits no
+    /// more opcodes once the input is empty and finishes the pickle with
+    /// cleanup and STOP, ignoring the minimum opcode count, and
+    /// [`ExhaustionPolicy::SwitchToPrng`] draws everything after the input from
+    /// a ChaCha8 stream seeded with a hash of it, so the tail is as varied as
+    /// PRNG output and the same input still gives the same pickle. PRNG-backed
+    /// generation is unaffected.
+    pub fn with_exhaustion_policy(mut self, policy: ExhaustionPolicy) -> Self {
+        self.exhaustion_policy = policy;
+        self
+    }
+
+    /// sometimes emit STACK_GLOBAL with indirectly pushed module and name
+    /// strings (protocol 4+).
+    ///
+    /// when enabled, about one in sixteen steps of the generation loop emits a
+    /// stdlib global as a short sequence instead of a single opcode: each of
+    /// the two strings reaches STACK_GLOBAL through a memo GET (stored and
+    /// popped earlier in the sequence), a UNICODE with every character
+    /// `\uXXXX`-escaped, or a literal followed by DUP and POP. scanners that
+    /// only match a literal SHORT_BINUNICODE directly in front of STACK_GLOBAL
+    /// miss all three. the output is unchanged when it is off.
+    pub fn with_indirect_stack_globals(mut self, enabled: bool) -> Self {
+        self.indirect_stack_globals = enabled;
+        self
     }
 
     /// generate a random, but valid pickle opcode stream using PRNG.
@@ -392,33 +930,143 @@ impl Generator {
     /// Returns the generated pickle bytecode. The pickle will be valid according
     /// to the protocol version specified when the generator was created.
     pub fn generate(&mut self) -> Result<Vec<u8>> {
-        self.reset();
+        self.run_rand(None)?;
+        Ok(self.output.clone())
+    }
 
-        if let Some(max_size) = self.bufsize {
-            let seed = self.seed;
-            return self.generate_with_bufsize(
-                max_size,
-                move |generator, target_total, force_frame| {
-                    let mut rng = if let Some(seed) = seed {
-                        ChaCha8Rng::seed_from_u64(seed)
-                    } else {
-                        ChaCha8Rng::from_os_rng()
-                    };
-                    let mut source = GenerationSource::Rand(&mut rng);
-                    generator.generate_internal(&mut source, target_total, force_frame)
-                },
-            );
-        }
+    /// generate a pickle into a caller-provided buffer.
+    ///
+    /// behaves like [`generate`](Self::generate), but replaces the contents of `out`
+    /// instead of returning a fresh `Vec`. together with the stack, memo, and output
+    /// buffers that `reset()` keeps allocated, reusing one generator and one buffer
+    /// across samples avoids per-sample allocations in hot loops.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use pickle_fuzzer::{Generator, Version};
+    ///
+    /// let mut gen = Generator::new(Version::V4);
+    /// let mut buf = Vec::new();
+    /// for seed in 0..1000 {
+    ///     gen.set_seed(Some(seed));
+    ///     gen.generate_into(&mut buf).unwrap();
+    ///     // use buf...
+    /// }
+    /// ```
+    pub fn generate_into(&mut self, out: &mut Vec<u8>) -> Result<()> {
+        self.run_rand(None)?;
+        out.clear();
+        out.extend_from_slice(&self.output);
+        Ok(())
+    }
 
-        let mut rng = if let Some(seed) = self.seed {
-            ChaCha8Rng::seed_from_u64(seed)
-        } else {
-            ChaCha8Rng::from_os_rng()
-        };
+    /// generate a pickle straight into a writer.
+    ///
+    /// produces the same bytes as [`generate`](Self::generate), but hands them to
+    /// `writer` in chunks of up to 64 KiB while generating instead of building the
+    /// whole pickle in memory, so large pickles can go directly to a file, socket,
+    /// or compressor. the exception is a pickle with a FRAME (protocol 4+, chosen
+    /// at random): the frame's length comes before the bytes it counts, so that
+    /// output is held back until STOP.
+    ///
+    /// if generation or a write fails, `writer` may already hold part of a pickle.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::io::BufWriter;
+    /// use pickle_fuzzer::{Generator, Version};
+    ///
+    /// let file = std::fs::File::create("large.pkl").unwrap();
+    /// let mut gen = Generator::new(Version::V2).with_opcode_range(40_000, 50_000);
+    /// gen.generate_to(BufWriter::new(file)).unwrap();
+    /// ```
+    pub fn generate_to<W: Write>(&mut self, mut writer: W) -> Result<()> {
+        self.run_rand(Some(&mut writer))?;
+        writer.flush()?;
+        Ok(())
+    }
 
-        let mut source = GenerationSource::Rand(&mut rng);
+    /// generate `n` sibling pickles that share one structural skeleton.
+    ///
+    /// every sibling makes the same structural draws (which opcode comes next,
+    /// container and pattern sizes, memo slots, globals) from a stream seeded
+    /// with `base_seed`, and draws its leaf values (integers, floats, strings,
+    /// bytes) and mutations from a stream of its own. the siblings have the
+    /// same opcodes in the same order and differ only in their arguments, so a
+    /// scanner should give all of them the same verdict, which makes them
+    /// metamorphic test cases. the generator's own seed is not used.
+    ///
+    /// canonical and diverse-encoding modes pick some opcodes by value (the
+    /// shortest integer encoding, for one), and a byte limit or a mutator that
+    /// rewrites opcodes cuts or changes the skeleton of some siblings, so only
+    /// the default mode without them keeps every sibling's opcode sequence
+    /// identical.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use pickle_fuzzer::{Generator, Version};
+    ///
+    /// let mut gen = Generator::new(Version::V4);
+    /// let siblings = gen.variants(42, 8).unwrap();
+    /// assert_eq!(siblings.len(), 8);
+    /// ```
+    pub fn variants(&mut self, base_seed: u64, n: usize) -> Result<Vec<Vec<u8>>> {
+        (0..n)
+            .map(|variant| self.generate_variant(base_seed, variant))
+            .collect()
+    }
+
+    /// generate sibling `variant` of [`variants`](Self::variants), on its own.
+    ///
+    /// `variants(base_seed, n)` is `generate_variant(base_seed, i)` for every
+    /// `i` below `n`, so a caller can write siblings one at a time and read
+    /// `stats()` for each.
+    pub fn generate_variant(&mut self, base_seed: u64, variant: usize) -> Result<Vec<u8>> {
+        self.run_variant(base_seed, variant as u64)?;
+        Ok(self.output.clone())
+    }
+
+    /// generate a pickle like [`generate`](Self::generate) and return the
+    /// entropy decisions that built it.
+    ///
+    /// the trace lists every answer the run got from the PRNG, in order.
+    /// [`replay`](Self::replay) rebuilds the same pickle from it with the same
+    /// configuration, whatever PRNG produced the decisions.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pickle_fuzzer::{Generator, Version};
+    ///
+    /// let mut gen = Generator::new(Version::V4).with_seed(7);
+    /// let (pickle, trace) = gen.generate_recorded().unwrap();
+    /// assert_eq!(gen.replay(&trace).unwrap(), pickle);
+    /// ```
+    pub fn generate_recorded(&mut self) -> Result<(Vec<u8>, EntropyTrace)> {
+        let mut decisions = Vec::new();
+        self.run_rand_traced(None, Some(&mut decisions))?;
+        Ok((self.output.clone(), EntropyTrace { decisions }))
+    }
 
-        self.generate_internal(&mut source, None, None)
+    /// generate a pickle from recorded entropy decisions instead of a PRNG.
+    ///
+    /// each draw takes the next decision of `trace`. a trace recorded with
+    /// [`generate_recorded`](Self::generate_recorded) under the same
+    /// configuration gives back the recorded pickle, and an edited one gives the
+    /// pickle with that decision changed: everything before it is kept, and the
+    /// rest follows the remaining decisions as far as they still fit. a decision
+    /// of the wrong kind, or a draw past the end, gets the value an exhausted
+    /// fuzzer input gives (0, `false`, the lowest index), and a recorded index
+    /// is clamped into the range the draw asks for, so any trace replays to a
+    /// valid pickle. with [`ExhaustionPolicy::Stop`] the pickle is finished
+    /// where the trace ends, which makes cutting decisions off the end a way to
+    /// shrink a sample.
+    pub fn replay(&mut self, trace: &EntropyTrace) -> Result<Vec<u8>> {
+        self.run_replay(&trace.decisions)?;
+        Ok(self.output.clone())
     }
 
     /// generate a pickle opcode stream from fuzzer-provided bytes.
@@ -436,19 +1084,136 @@ impl Generator {
     /// let pickle = gen.generate_from_arbitrary(fuzzer_input).unwrap();
     /// ```
     pub fn generate_from_arbitrary(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        self.run_arbitrary(data)?;
+        Ok(self.output.clone())
+    }
+
+    /// generate a pickle from fuzzer-provided bytes into a caller-provided buffer.
+    ///
+    /// the buffer-reusing counterpart of
+    /// [`generate_from_arbitrary`](Self::generate_from_arbitrary), intended for fuzz
+    /// targets that keep one generator alive across iterations.
+    pub fn generate_from_arbitrary_into(&mut self, data: &[u8], out: &mut Vec<u8>) -> Result<()> {
+        self.run_arbitrary(data)?;
+        out.clear();
+        out.extend_from_slice(&self.output);
+        Ok(())
+    }
+
+    fn run_rand(&mut self, sink: Option<&mut dyn Write>) -> Result<()> {
+        self.run_rand_traced(sink, None)
+    }
+
+    /// generate from the PRNG, logging every decision to `trace` if given.
+    fn run_rand_traced(
+        &mut self,
+        sink: Option<&mut dyn Write>,
+        trace: Option<&mut Vec<Decision>>,
+    ) -> Result<()> {
         self.reset();
 
-        if let Some(max_size) = self.bufsize {
-            return self.generate_with_bufsize(max_size, |generator, target_total, force_frame| {
-                let mut u = Unstructured::new(data);
-                let mut source = GenerationSource::Arbitrary(&mut u);
-                generator.generate_internal(&mut source, target_total, force_frame)
-            });
+        let mut rng = match self.seed {
+            Some(seed) => ChaCha8Rng::seed_from_u64(seed),
+            #[cfg(feature = "os-rng")]
+            None => ChaCha8Rng::from_os_rng(),
+            #[cfg(not(feature = "os-rng"))]
+            None => {
+                return Err(color_eyre::eyre::eyre!(
+                    "no seed set and OS entropy is unavailable (built without the os-rng feature)"
+                ))
+            }
+        };
+
+        let mut values = self
+            .value_seed
+            .map(|value_seed| value_stream(value_seed, 0));
+        let mut source = match &mut values {
+            Some(values) => GenerationSource::Split {
+                structure: &mut rng,
+                values,
+            },
+            None => GenerationSource::Rand(&mut rng),
+        };
+        let result = match trace {
+            Some(trace) => {
+                let mut recording = GenerationSource::Recording {
+                    inner: &mut source,
+                    trace,
+                };
+                self.generate_internal(&mut recording, sink)
+            }
+            None => self.generate_internal(&mut source, sink),
+        };
+        self.reset_on_error(result)
+    }
+
+    /// generate sibling `variant` of `base_seed`: the structure stream is the
+    /// seed's first ChaCha8 stream, the value stream its `variant + 1`th.
+    fn run_variant(&mut self, base_seed: u64, variant: u64) -> Result<()> {
+        self.reset();
+
+        let mut structure = ChaCha8Rng::seed_from_u64(base_seed);
+        let mut values = value_stream(base_seed, variant);
+        let mut source = GenerationSource::Split {
+            structure: &mut structure,
+            values: &mut values,
+        };
+        let result = self.generate_internal(&mut source, None);
+        self.reset_on_error(result)
+    }
+
+    /// generate from `decisions`, returning how many of them the run used.
+    fn run_replay(&mut self, decisions: &[Decision]) -> Result<usize> {
+        self.reset();
+
+        let mut source = GenerationSource::Replay {
+            decisions,
+            position: 0,
+        };
+        let result = self.generate_internal(&mut source, None);
+        self.reset_on_error(result)?;
+        match source {
+            GenerationSource::Replay { position, .. } => Ok(position.min(decisions.len())),
+            _ => unreachable!("the source is a replay"),
         }
+    }
+
+    fn run_arbitrary(&mut self, data: &[u8]) -> Result<()> {
+        self.reset();
 
         let mut u = Unstructured::new(data);
-        let mut source = GenerationSource::Arbitrary(&mut u);
-        self.generate_internal(&mut source, None, None)
+        let result = match self.exhaustion_policy {
+            ExhaustionPolicy::SwitchToPrng => {
+                let mut rng = ChaCha8Rng::seed_from_u64(Self::exhaustion_seed(data));
+                let mut source = GenerationSource::ArbitraryThenRand {
+                    input: &mut u,
+                    rng: &mut rng,
+                };
+                self.generate_internal(&mut source, None)
+            }
+            ExhaustionPolicy::Fallback | ExhaustionPolicy::Stop => {
+                self.generate_internal(&mut GenerationSource::Arbitrary(&mut u), None)
+            }
+        };
+        self.reset_on_error(result)
+    }
+
+    /// the PRNG seed `ExhaustionPolicy::SwitchToPrng` derives from an input:
+    /// its 64-bit FNV-1a hash, so inputs that share a prefix still get
+    /// unrelated tails.
+    fn exhaustion_seed(data: &[u8]) -> u64 {
+        data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        })
+    }
+
+    /// discard partial output when generation fails so a failed call never leaves
+    /// a half-written pickle behind.
+    fn reset_on_error(&mut self, result: Result<()>) -> Result<()> {
+        if result.is_err() {
+            self.reset();
+        }
+        result
     }
 
     pub(crate) fn normalized_opcode_range(&self) -> (usize, usize) {
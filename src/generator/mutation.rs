@@ -26,8 +26,9 @@
 //! mutations are applied at two levels:
 //!
 //! 1. **value-level mutations**: applied to individual values (ints, floats, strings,
-//!    bytes, memo indices) as they are generated. only one mutator is applied per
-//!    value, chosen based on the mutation rate.
+//!    bytes, memo indices) as they are generated. the [`MutationPolicy`] decides
+//!    how many mutators get a turn on each value: by default only the first one
+//!    that triggers is applied.
 //!
 //! 2. **post-emission mutations**: applied after an opcode is emitted, allowing
 //!    mutators to modify the raw bytecode in the output buffer. this enables
@@ -36,200 +37,400 @@
 //! # Mutation Rate
 //!
 //! the `mutation_rate` parameter (0.0 to 1.0) controls how frequently mutations
-//! are applied. each mutator checks this rate independently, so multiple mutators
-//! can be active but only one mutation is applied per value.
+//! are applied. each mutator checks this rate independently. under
+//! [`MutationPolicy::First`] multiple mutators can be active but only one
+//! mutation is applied per value; [`MutationPolicy::All`] and
+//! [`MutationPolicy::Random`] let mutations compose, each mutator seeing the
+//! value left by the previous one.
 //!
 //! # Safety
 //!
 //! some mutations can produce invalid pickles (marked with `is_unsafe()` in the
 //! mutator trait). these are useful for finding parser bugs but may cause
 //! unpickling to fail.
+//!
+//! without unsafe mutations, every emission a mutator touched is re-validated
+//! before generation moves on (see `enforce_safe_emission`), and one that no
+//! longer decodes or breaks the stack or memo is rolled back. this holds the
+//! safe-mode guarantee for every mutator, including registered ones.
+
+use std::fmt;
+use std::str::FromStr;
 
-use super::source::GenerationSource;
+use clap::ValueEnum;
+
+use super::source::{EntropySource, GenerationSource};
 use super::Generator;
-use crate::mutators::EmissionSnapshot;
+use crate::disasm::{self, Instruction, StackCheck};
+use crate::mutators::{
+    operand_texts, stack_global_operands, EmissionSnapshot, Mutator, PostProcessEmission,
+};
+use crate::opcodes::{OpcodeKind, PICKLE_OPCODES};
+use crate::stack::StackObject;
 use crate::state::State;
 
+/// how value-level mutators are combined on a single value.
+///
+/// post-emission mutations are unaffected: every mutator always gets to
+/// post-process each opcode.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MutationPolicy {
+    /// apply the first mutator, in registration order, that triggers
+    #[default]
+    First,
+    /// offer the value to every mutator in order, composing their mutations
+    /// (e.g. boundary then bitflip)
+    All,
+    /// offer the value to up to `n` distinct mutators picked at random, in
+    /// random order, composing their mutations
+    Random(usize),
+}
+
+impl FromStr for MutationPolicy {
+    type Err = color_eyre::eyre::Error;
+
+    /// parse `first`, `all`, or `random:N` with N >= 1.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "first" => Ok(MutationPolicy::First),
+            "all" => Ok(MutationPolicy::All),
+            other => {
+                let count = other
+                    .strip_prefix("random:")
+                    .ok_or_else(|| {
+                        color_eyre::eyre::eyre!("expected first, all, or random:N, got {:?}", other)
+                    })?
+                    .trim();
+                match count.parse::<usize>() {
+                    Ok(n) if n > 0 => Ok(MutationPolicy::Random(n)),
+                    _ => Err(color_eyre::eyre::eyre!(
+                        "random:N needs a positive mutator count, got {:?}",
+                        count
+                    )),
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for MutationPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MutationPolicy::First => f.write_str("first"),
+            MutationPolicy::All => f.write_str("all"),
+            MutationPolicy::Random(n) => write!(f, "random:{n}"),
+        }
+    }
+}
+
+/// a class of opcode arguments that a [`MutationScope`] can select.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum MutationTarget {
+    /// integer arguments (INT, BININT*, LONG*)
+    Ints,
+    /// float arguments (FLOAT, BINFLOAT)
+    Floats,
+    /// text arguments (STRING, UNICODE, *BINUNICODE*)
+    Strings,
+    /// byte string arguments (BINSTRING, SHORT_BINSTRING, *BINBYTES*, BYTEARRAY8)
+    Bytes,
+    /// memo indices (GET/PUT families)
+    Memo,
+    /// any argument carrying a length prefix (binary strings and bytes, LONG1/LONG4)
+    LengthPrefixed,
+    /// module and attribute names (GLOBAL, INST, STACK_GLOBAL)
+    Names,
+}
+
+impl MutationTarget {
+    const fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// the opcode argument classes mutators may touch.
+///
+/// both value mutations and post-emission rewrites are filtered by the
+/// emitted opcode: it is in scope if any of its argument classes is. the
+/// default, [`MutationScope::ALL`], also covers opcodes without an argument
+/// class (NONE, TUPLE, ...); any narrower scope leaves those alone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MutationScope {
+    targets: u8,
+}
+
+impl MutationScope {
+    /// every opcode, including those without an argument class.
+    pub const ALL: MutationScope = MutationScope { targets: u8::MAX };
+
+    /// only opcodes with an argument in one of `targets`.
+    pub fn only(targets: &[MutationTarget]) -> Self {
+        targets.iter().copied().collect()
+    }
+
+    /// returns whether mutations of `target` arguments are allowed.
+    pub fn includes(&self, target: MutationTarget) -> bool {
+        self.targets & target.bit() != 0
+    }
+
+    /// returns whether mutators may touch an emission of `opcode`.
+    pub(crate) fn covers(&self, opcode: OpcodeKind) -> bool {
+        *self == Self::ALL || self.targets & argument_classes(opcode) != 0
+    }
+}
+
+impl Default for MutationScope {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+impl FromIterator<MutationTarget> for MutationScope {
+    fn from_iter<I: IntoIterator<Item = MutationTarget>>(targets: I) -> Self {
+        MutationScope {
+            targets: targets
+                .into_iter()
+                .fold(0, |bits, target| bits | target.bit()),
+        }
+    }
+}
+
+/// the argument classes of `opcode`, as a [`MutationTarget`] bitmask.
+fn argument_classes(opcode: OpcodeKind) -> u8 {
+    use MutationTarget::*;
+    use OpcodeKind as Op;
+
+    match opcode {
+        Op::Int | Op::BinInt | Op::BinInt1 | Op::BinInt2 | Op::Long => Ints.bit(),
+        Op::Long1 | Op::Long4 => Ints.bit() | LengthPrefixed.bit(),
+        Op::Float | Op::BinFloat => Floats.bit(),
+        Op::String | Op::Unicode => Strings.bit(),
+        Op::ShortBinUnicode | Op::BinUnicode | Op::BinUnicode8 => {
+            Strings.bit() | LengthPrefixed.bit()
+        }
+        Op::BinString
+        | Op::ShortBinString
+        | Op::ShortBinBytes
+        | Op::BinBytes
+        | Op::BinBytes8
+        | Op::ByteArray8 => Bytes.bit() | LengthPrefixed.bit(),
+        Op::Get | Op::BinGet | Op::LongBinGet | Op::Put | Op::BinPut | Op::LongBinPut => Memo.bit(),
+        Op::Global | Op::Inst | Op::StackGlobal => Names.bit(),
+        _ => 0,
+    }
+}
+
 impl Generator {
+    /// offer `value`, an argument of `opcode`, to the mutators selected by the
+    /// mutation policy.
+    ///
+    /// nothing happens if `opcode` is outside the mutation scope. unsafe mutators are skipped unless unsafe mutations are enabled. under
+    /// [`MutationPolicy::First`] this stops at the first mutator that returns
+    /// a value; the other policies feed each result into the next mutator.
+    /// `mutate` receives its own copy of the value because mutators consume it.
+    fn apply_mutators<T: Clone>(
+        &self,
+        opcode: OpcodeKind,
+        value: T,
+        source: &mut GenerationSource,
+        mutate: impl Fn(&dyn Mutator, T, &mut GenerationSource, f64) -> Option<T>,
+    ) -> T {
+        if self.mutators.is_empty() || !self.mutation_scope.covers(opcode) {
+            return value;
+        }
+
+        // mutations are values: a split source draws them from its value
+        // stream, so whether one fires never moves the structure stream
+        source.with_values(|source| {
+            let eligible = self
+                .mutators
+                .iter()
+                .map(Box::as_ref)
+                .filter(|mutator| self.unsafe_mutations || !mutator.is_unsafe());
+            let mut result = value;
+            match self.mutation_policy {
+                MutationPolicy::First => {
+                    for mutator in eligible {
+                        if let Some(mutated) =
+                            mutate(mutator, result.clone(), source, self.mutation_rate)
+                        {
+                            result = mutated;
+                            self.value_mutated.set(true);
+                            self.note_mutation(mutator.name());
+                            break; // Apply only one mutation
+                        }
+                    }
+                }
+                MutationPolicy::All => {
+                    for mutator in eligible {
+                        if let Some(mutated) =
+                            mutate(mutator, result.clone(), source, self.mutation_rate)
+                        {
+                            result = mutated;
+                            self.value_mutated.set(true);
+                            self.note_mutation(mutator.name());
+                        }
+                    }
+                }
+                MutationPolicy::Random(count) => {
+                    // partial Fisher-Yates shuffle: the first `count` slots end up
+                    // holding distinct mutators in random order
+                    let mut picks: Vec<&dyn Mutator> = eligible.collect();
+                    let count = count.min(picks.len());
+                    for slot in 0..count {
+                        let chosen = slot + source.choose_index(picks.len() - slot);
+                        picks.swap(slot, chosen);
+                        if let Some(mutated) =
+                            mutate(picks[slot], result.clone(), source, self.mutation_rate)
+                        {
+                            result = mutated;
+                            self.value_mutated.set(true);
+                            self.note_mutation(picks[slot].name());
+                        }
+                    }
+                }
+            }
+            result
+        })
+    }
+
     /// apply mutations to an integer value.
     ///
-    /// iterates through all registered mutators and applies the first mutation
-    /// that triggers (based on mutation rate). returns the original value if no
-    /// mutators are registered or none trigger.
+    /// offers the value to the registered mutators as the [`MutationPolicy`]
+    /// dictates (by default, the first mutation that triggers based on the
+    /// mutation rate wins). returns the original value if no mutators are
+    /// registered or none trigger.
     ///
     /// # Parameters
+    /// - `opcode`: the opcode the value is an argument of
     /// - `value`: the original integer value
     /// - `source`: entropy source for random mutation decisions
     ///
     /// # Returns
     /// the mutated value, or the original if no mutation applied.
-    pub(super) fn mutate_int(&self, value: i32, source: &mut GenerationSource) -> i32 {
-        if self.mutators.is_empty() {
-            return value;
-        }
-
-        let mut result = value;
-        for mutator in &self.mutators {
-            if !self.unsafe_mutations && mutator.is_unsafe() {
-                continue;
-            }
-            if let Some(mutated) = mutator.mutate_int(result, source, self.mutation_rate) {
-                result = mutated;
-                break; // Apply only one mutation
-            }
-        }
-        result
+    pub(super) fn mutate_int(
+        &self,
+        opcode: OpcodeKind,
+        value: i32,
+        source: &mut GenerationSource,
+    ) -> i32 {
+        self.apply_mutators(opcode, value, source, |mutator, value, source, rate| {
+            mutator.mutate_int(value, source, rate)
+        })
     }
 
     /// apply mutations to a long integer value.
     ///
-    /// similar to `mutate_int()` but for 64-bit integers. applies the first
-    /// mutation that triggers from the registered mutators.
+    /// similar to `mutate_int()` but for 64-bit integers.
     ///
     /// # Parameters
+    /// - `opcode`: the opcode the value is an argument of
     /// - `value`: the original long integer value
     /// - `source`: entropy source for random mutation decisions
     ///
     /// # Returns
     /// the mutated value, or the original if no mutation applied.
-    pub(super) fn _mutate_long(&self, value: i64, source: &mut GenerationSource) -> i64 {
-        // unused right now, keeping around for completeness/future use
-        if self.mutators.is_empty() {
-            return value;
-        }
-
-        let mut result = value;
-        for mutator in &self.mutators {
-            if !self.unsafe_mutations && mutator.is_unsafe() {
-                continue;
-            }
-            if let Some(mutated) = mutator.mutate_long(result, source, self.mutation_rate) {
-                result = mutated;
-                break;
-            }
-        }
-        result
+    pub(super) fn mutate_long(
+        &self,
+        opcode: OpcodeKind,
+        value: i64,
+        source: &mut GenerationSource,
+    ) -> i64 {
+        self.apply_mutators(opcode, value, source, |mutator, value, source, rate| {
+            mutator.mutate_long(value, source, rate)
+        })
     }
+
     /// apply mutations to a float value.
     ///
     /// applies mutations to floating-point values, potentially injecting special
-    /// values like NaN, infinity, or boundary values. applies the first mutation
-    /// that triggers from the registered mutators.
+    /// values like NaN, infinity, or boundary values.
     ///
     /// # Parameters
+    /// - `opcode`: the opcode the value is an argument of
     /// - `value`: the original float value
     /// - `source`: entropy source for random mutation decisions
     ///
     /// # Returns
     /// the mutated value, or the original if no mutation applied.
-    pub(super) fn mutate_float(&self, value: f64, source: &mut GenerationSource) -> f64 {
-        if self.mutators.is_empty() {
-            return value;
-        }
-
-        let mut result = value;
-        for mutator in &self.mutators {
-            if !self.unsafe_mutations && mutator.is_unsafe() {
-                continue;
-            }
-            if let Some(mutated) = mutator.mutate_float(result, source, self.mutation_rate) {
-                result = mutated;
-                break;
-            }
-        }
-        result
+    pub(super) fn mutate_float(
+        &self,
+        opcode: OpcodeKind,
+        value: f64,
+        source: &mut GenerationSource,
+    ) -> f64 {
+        self.apply_mutators(opcode, value, source, |mutator, value, source, rate| {
+            mutator.mutate_float(value, source, rate)
+        })
     }
 
     /// apply mutations to a string value.
     ///
     /// applies mutations to string values, potentially modifying length, inserting
-    /// special characters, or corrupting content. applies the first mutation that
-    /// triggers from the registered mutators.
+    /// special characters, or corrupting content.
     ///
     /// # Parameters
+    /// - `opcode`: the opcode the value is an argument of
     /// - `value`: the original string value
     /// - `source`: entropy source for random mutation decisions
     ///
     /// # Returns
     /// the mutated string, or the original if no mutation applied.
-    pub(super) fn mutate_string(&self, value: String, source: &mut GenerationSource) -> String {
-        if self.mutators.is_empty() {
-            return value;
-        }
-
-        let mut result = value;
-        for mutator in &self.mutators {
-            if !self.unsafe_mutations && mutator.is_unsafe() {
-                continue;
-            }
-            if let Some(mutated) = mutator.mutate_string(result.clone(), source, self.mutation_rate)
-            {
-                result = mutated;
-                break;
-            }
-        }
-        result
+    pub(super) fn mutate_string(
+        &self,
+        opcode: OpcodeKind,
+        value: String,
+        source: &mut GenerationSource,
+    ) -> String {
+        self.apply_mutators(opcode, value, source, |mutator, value, source, rate| {
+            mutator.mutate_string(value, source, rate)
+        })
     }
 
     /// apply mutations to a bytes value.
     ///
     /// applies mutations to byte sequences, potentially modifying length, flipping
-    /// bits, or corrupting content. applies the first mutation that triggers from
-    /// the registered mutators.
+    /// bits, or corrupting content.
     ///
     /// # Parameters
+    /// - `opcode`: the opcode the value is an argument of
     /// - `value`: the original byte vector
     /// - `source`: entropy source for random mutation decisions
     ///
     /// # Returns
     /// the mutated bytes, or the original if no mutation applied.
-    pub(super) fn mutate_bytes(&self, value: Vec<u8>, source: &mut GenerationSource) -> Vec<u8> {
-        if self.mutators.is_empty() {
-            return value;
-        }
-
-        let mut result = value;
-        for mutator in &self.mutators {
-            if !self.unsafe_mutations && mutator.is_unsafe() {
-                continue;
-            }
-            if let Some(mutated) = mutator.mutate_bytes(result.clone(), source, self.mutation_rate)
-            {
-                result = mutated;
-                break;
-            }
-        }
-        result
+    pub(super) fn mutate_bytes(
+        &self,
+        opcode: OpcodeKind,
+        value: Vec<u8>,
+        source: &mut GenerationSource,
+    ) -> Vec<u8> {
+        self.apply_mutators(opcode, value, source, |mutator, value, source, rate| {
+            mutator.mutate_bytes(value, source, rate)
+        })
     }
 
     /// apply mutations to a memo index.
     ///
     /// applies mutations to memoization indices, potentially creating invalid
-    /// references or off-by-one errors. applies the first mutation that triggers
-    /// from the registered mutators.
+    /// references or off-by-one errors.
     ///
     /// # Parameters
+    /// - `opcode`: the memo opcode the index is an argument of
     /// - `index`: the original memo index
     /// - `source`: entropy source for random mutation decisions
     ///
     /// # Returns
     /// the mutated index, or the original if no mutation applied.
-    pub(super) fn mutate_memo_index(&self, index: usize, source: &mut GenerationSource) -> usize {
-        if self.mutators.is_empty() {
-            return index;
-        }
-
-        let mut result = index;
-        for mutator in &self.mutators {
-            if !self.unsafe_mutations && mutator.is_unsafe() {
-                continue;
-            }
-            if let Some(mutated) = mutator.mutate_memo_index(result, source, self.mutation_rate) {
-                result = mutated;
-                break;
-            }
-        }
-        result
+    pub(super) fn mutate_memo_index(
+        &self,
+        opcode: OpcodeKind,
+        index: usize,
+        source: &mut GenerationSource,
+    ) -> usize {
+        self.apply_mutators(opcode, index, source, |mutator, index, source, rate| {
+            mutator.mutate_memo_index(index, source, rate)
+        })
     }
 
     /// create a snapshot of current generator state before emitting an opcode.
@@ -249,6 +450,82 @@ impl Generator {
             stack_delta: Vec::new(),
             output_delta: Vec::new(),
             memo_delta: Vec::new(),
+            operands_len: None,
+        }
+    }
+
+    /// fill in what changed since `snapshot` was created.
+    pub(super) fn fill_deltas(&self, snapshot: &mut EmissionSnapshot) {
+        // Stack can shrink (items popped), so only capture new items if stack grew
+        snapshot.stack_delta.clear();
+        if self.state.stack.len() > snapshot.stack_depth {
+            snapshot.stack_delta = self.state.stack.items()[snapshot.stack_depth..].to_vec();
+        }
+
+        // Output always grows (or stays same)
+        snapshot.output_delta.clear();
+        if self.output.len() >= snapshot.output_len {
+            snapshot.output_delta = self.output[snapshot.output_len..].to_vec();
+        }
+
+        // Memo delta: find new indices (memo only grows)
+        snapshot.memo_delta = (snapshot.memo_size..self.state.memo.len()).collect();
+
+        snapshot.operands_len = self.stack_global_operands_len(snapshot);
+    }
+
+    /// output length before the module and name pushes of the STACK_GLOBAL
+    /// emitted since `snapshot`, if they directly precede it.
+    ///
+    /// the pushed global names the texts to look for; each candidate span is
+    /// decoded to make sure it is whole push opcodes rather than the tail of
+    /// some other argument.
+    fn stack_global_operands_len(&self, snapshot: &EmissionSnapshot) -> Option<usize> {
+        if snapshot.output_delta != [OpcodeKind::StackGlobal.as_u8()] {
+            return None;
+        }
+        // STACK_GLOBAL shrinks the stack, so the global isn't in stack_delta
+        let pushed = self.state.stack.peek()?;
+        let (module, name) = match &*pushed.borrow() {
+            StackObject::Callable(global) => match &*global.borrow() {
+                StackObject::Global { module, name } => (module.clone(), name.clone()),
+                _ => return None,
+            },
+            _ => return None,
+        };
+
+        // SHORT_BINUNICODE, BINUNICODE, or BINUNICODE8, then nothing,
+        // MEMOIZE, BINPUT, or LONG_BINPUT
+        let spans = |text: &str| -> Vec<usize> {
+            [2, 5, 9]
+                .iter()
+                .flat_map(|header| [0, 1, 2, 5].map(|memo| header + text.len() + memo))
+                .collect()
+        };
+        let name_spans = spans(&name);
+        spans(&module)
+            .into_iter()
+            .flat_map(|module_span| {
+                name_spans
+                    .iter()
+                    .map(move |name_span| module_span + name_span)
+            })
+            .filter_map(|span| snapshot.output_len.checked_sub(span))
+            .find(|&start| {
+                stack_global_operands(&self.output[start..snapshot.output_len])
+                    .is_some_and(|operands| operand_texts(&operands) == [&module, &name])
+            })
+    }
+
+    /// give the operands on top of `state` the texts `operands` now push.
+    ///
+    /// the stack items are rewritten in place, so memo entries that share
+    /// them follow, as they would in the unpickler.
+    fn respell_operands(state: &State, operands: &[Instruction]) {
+        for (depth, text) in operand_texts(operands).into_iter().rev().enumerate() {
+            if let Some(item) = state.stack.peek_at(depth) {
+                *item.borrow_mut() = StackObject::String(text.to_string());
+            }
         }
     }
 
@@ -264,77 +541,197 @@ impl Generator {
     /// - **memo_delta**: new indices added to the memo table
     ///
     /// each mutator can inspect these deltas and modify the output buffer based
-    /// on the mutation rate.
+    /// on the mutation rate. emissions outside the mutation scope are left as is.
+    ///
+    /// a STACK_GLOBAL whose module and name pushes directly precede it carries
+    /// their offset in `operands_len`, and mutators may respell them too. the
+    /// emission then moves with them: `snapshot.output_len` follows it, and
+    /// the operands on the pre-emission stack are given the new texts before
+    /// STACK_GLOBAL is replayed.
     ///
     /// # Parameters
     /// - `snapshot`: the pre-emission snapshot to compare against
+    /// - `pre_emission_state`: state to re-simulate from, only captured when mutators are active
     /// - `source`: entropy source for random mutation decisions
+    ///
+    /// # Returns
+    /// `true` if a rewrite was kept, `false` if the emission is unchanged.
     pub(super) fn post_process_emission(
         &mut self,
-        mut snapshot: EmissionSnapshot,
-        pre_emission_state: State,
+        snapshot: &mut EmissionSnapshot,
+        pre_emission_state: Option<&State>,
         source: &mut GenerationSource,
-    ) {
+    ) -> bool {
+        let Some(pre_emission_state) = pre_emission_state else {
+            return false;
+        };
         if self.mutators.is_empty() {
-            return;
-        }
-
-        // Calculate deltas
-        // Stack can shrink (items popped), so only capture new items if stack grew
-        if self.state.stack.len() > snapshot.stack_depth {
-            snapshot.stack_delta = self.state.stack.inner[snapshot.stack_depth..].to_vec();
+            return false;
         }
-
-        // Output always grows (or stays same)
-        if self.output.len() >= snapshot.output_len {
-            snapshot.output_delta = self.output[snapshot.output_len..].to_vec();
+        if self.mutation_scope != MutationScope::ALL {
+            let emitted = self
+                .output
+                .get(snapshot.output_len)
+                .and_then(|&byte| OpcodeKind::from_u8(byte));
+            if !emitted.is_some_and(|opcode| self.mutation_scope.covers(opcode)) {
+                return false;
+            }
         }
 
-        // Memo delta: find new indices (memo only grows)
-        for idx in snapshot.memo_size..self.state.memo.len() {
-            snapshot.memo_delta.push(idx);
-        }
+        self.fill_deltas(snapshot);
 
         let original_output_delta = snapshot.output_delta.clone();
+        let original_output_len = snapshot.output_len;
+        let rewrite_start = snapshot.operands_len.unwrap_or(snapshot.output_len);
+        let original_output = self.output[rewrite_start..].to_vec();
         let mut synchronized_emission = None;
+        let mut rewriters = Vec::new();
 
         // Let each mutator post-process
-        for mutator in &self.mutators {
-            if !self.unsafe_mutations && mutator.is_unsafe() {
-                continue;
-            }
+        source.with_values(|source| {
+            for mutator in &self.mutators {
+                if !self.unsafe_mutations && mutator.is_unsafe() {
+                    continue;
+                }
 
-            let emitted_before = self.output[snapshot.output_len..].to_vec();
-            mutator.post_process(&snapshot, &mut self.output, source, self.mutation_rate);
-            let emitted_after = self.output[snapshot.output_len..].to_vec();
+                let operands_before = self.output[rewrite_start..snapshot.output_len].to_vec();
+                let emitted_before = self.output[snapshot.output_len..].to_vec();
+                mutator.post_process(snapshot, &mut self.output, source, self.mutation_rate);
 
-            if emitted_after != emitted_before {
-                synchronized_emission =
-                    mutator.describe_post_process(&snapshot, emitted_after.as_slice());
+                if self.output.get(rewrite_start..snapshot.output_len)
+                    != Some(operands_before.as_slice())
+                {
+                    // respelled operands must leave the emission after them as it is
+                    if self.output.ends_with(&emitted_before) {
+                        snapshot.output_len = self.output.len() - emitted_before.len();
+                        rewriters.push(mutator.name().to_string());
+                    } else {
+                        self.output.truncate(rewrite_start);
+                        self.output.extend_from_slice(&operands_before);
+                        self.output.extend_from_slice(&emitted_before);
+                    }
+                    continue;
+                }
+
+                let emitted_after = self.output[snapshot.output_len..].to_vec();
+                if emitted_after != emitted_before {
+                    synchronized_emission =
+                        mutator.describe_post_process(snapshot, emitted_after.as_slice());
+                    rewriters.push(mutator.name().to_string());
+                }
             }
-        }
+        });
 
-        let rewritten_output = self.output[snapshot.output_len..].to_vec();
-        if rewritten_output == original_output_delta {
-            return;
+        if self.output[rewrite_start..] == original_output[..] {
+            return false;
         }
 
-        if let Some(emission) = synchronized_emission {
-            self.state = pre_emission_state;
+        // an emission left as it was only had its operands respelled, which
+        // only a STACK_GLOBAL has
+        let emission_rewritten = self.output[snapshot.output_len..] != original_output_delta[..];
+        let emission = if emission_rewritten {
+            synchronized_emission
+        } else {
+            Some(PostProcessEmission {
+                opcode: OpcodeKind::StackGlobal,
+                arg_bytes: None,
+            })
+        };
+        // respelled operands must still be the same opcodes, pushing new texts
+        let original_operands = &original_output[..original_output_len - rewrite_start];
+        let operands_rewritten =
+            self.output[rewrite_start..snapshot.output_len] != *original_operands;
+        let operands = operands_rewritten.then(|| {
+            let before = stack_global_operands(original_operands)?;
+            let after = stack_global_operands(&self.output[rewrite_start..snapshot.output_len])?;
+            let codes = |operands: &[Instruction]| -> Vec<u8> {
+                operands.iter().map(|operand| operand.code).collect()
+            };
+            (codes(&before) == codes(&after)).then_some((before, after))
+        });
+        let emission = emission.filter(|_| !matches!(operands, Some(None)));
+
+        if let Some(emission) = emission {
+            if let Some(Some((before, after))) = &operands {
+                Self::respell_operands(pre_emission_state, after);
+                // annotation steps start at opcodes, which moved with the operands
+                let streamed = self.output_len() - self.output.len();
+                let at = |pos: usize| streamed + rewrite_start + pos;
+                let moves: Vec<(usize, usize)> = before
+                    .iter()
+                    .zip(after)
+                    .map(|(before, after)| (at(before.pos), at(after.pos)))
+                    .chain([(
+                        streamed + original_output_len,
+                        streamed + snapshot.output_len,
+                    )])
+                    .collect();
+                self.move_steps(&moves);
+            }
+            self.state = pre_emission_state.clone();
             self.process_stack_ops(emission.opcode, emission.arg_bytes.as_deref());
+            for rewriter in rewriters {
+                self.note_mutation(&rewriter);
+            }
+            true
         } else {
-            self.output.truncate(snapshot.output_len);
-            self.output.extend_from_slice(&original_output_delta);
+            self.output.truncate(rewrite_start);
+            self.output.extend_from_slice(&original_output);
+            snapshot.output_len = original_output_len;
+            false
         }
     }
+
+    /// enforce the safe-mode guarantee on a mutated emission.
+    ///
+    /// without unsafe mutations, the bytes emitted since `output_len` are
+    /// decoded with the pickletools argument readers and re-simulated against
+    /// `pre_emission_state`: every opcode must belong to the protocol, and
+    /// stack, MARK, and memo use must pass the `pickletools.dis` checks. an
+    /// emission that fails is rolled back entirely.
+    ///
+    /// # Returns
+    /// `true` if the emission was kept.
+    pub(super) fn enforce_safe_emission(
+        &mut self,
+        output_len: usize,
+        pre_emission_state: &State,
+    ) -> bool {
+        if self.unsafe_mutations || self.safe_emission(output_len, pre_emission_state) {
+            return true;
+        }
+        self.state = pre_emission_state.clone();
+        self.output.truncate(output_len);
+        false
+    }
+
+    /// whether the bytes emitted since `output_len` are valid on top of
+    /// `pre_emission_state`.
+    fn safe_emission(&self, output_len: usize, pre_emission_state: &State) -> bool {
+        let Ok(instructions) = disasm::disassemble_fragment(&self.output[output_len..]) else {
+            return false;
+        };
+        let protocol_opcodes = PICKLE_OPCODES[&(pre_emission_state.version as u8)];
+        let mut check = StackCheck::resume(
+            pre_emission_state
+                .stack
+                .items()
+                .iter()
+                .map(|item| matches!(*item.borrow(), StackObject::Mark)),
+            pre_emission_state.memo.keys().copied(),
+        );
+        instructions.iter().all(|instruction| {
+            OpcodeKind::from_u8(instruction.code)
+                .is_some_and(|opcode| protocol_opcodes.contains(&opcode))
+                && check.step(instruction).is_ok()
+        })
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::mutators::{Mutator, PostProcessEmission};
-    use crate::opcodes::OpcodeKind;
-    use crate::stack::StackObject;
     use crate::Version;
     use rand::SeedableRng;
     use rand_chacha::ChaCha8Rng;
@@ -439,4 +836,277 @@ mod tests {
             StackObject::None
         ));
     }
+
+    #[test]
+    fn test_post_process_respells_stack_global_operands() {
+        use crate::mutators::HomoglyphMutator;
+
+        let mut respelled = 0;
+        for seed in 0..20 {
+            let mut generator = Generator::new(Version::V4)
+                .with_mutator(Box::new(HomoglyphMutator::new(true)))
+                .with_mutation_rate(1.0)
+                .with_unsafe_mutations(true);
+            for text in ["os", "system"] {
+                generator.output.push(OpcodeKind::ShortBinUnicode.as_u8());
+                generator.output.push(text.len() as u8);
+                generator.output.extend_from_slice(text.as_bytes());
+                generator.process_stack_ops(OpcodeKind::ShortBinUnicode, Some(text.as_bytes()));
+                generator.output.push(OpcodeKind::Memoize.as_u8());
+                generator.process_stack_ops(OpcodeKind::Memoize, None);
+            }
+            let mut rng = ChaCha8Rng::seed_from_u64(seed);
+            let mut source = GenerationSource::Rand(&mut rng);
+            generator
+                .emit_and_process(OpcodeKind::StackGlobal, &mut source)
+                .expect("emission should succeed");
+
+            // the replayed global and the memoized strings name what the
+            // respelled pushes push
+            let (&opcode, pushes) = generator.output.split_last().unwrap();
+            assert_eq!(opcode, OpcodeKind::StackGlobal.as_u8());
+            let operands = stack_global_operands(pushes).expect("operand pushes");
+            let texts = operand_texts(&operands);
+            respelled += usize::from(texts != ["os", "system"]);
+            let top = generator.peek().expect("stack item").borrow().clone();
+            let StackObject::Callable(global) = top else {
+                panic!("no global on the stack");
+            };
+            assert!(matches!(
+                &*global.borrow(),
+                StackObject::Global { module, name } if texts == [module, name]
+            ));
+            for (index, text) in texts.iter().enumerate() {
+                assert!(matches!(
+                    &*generator.state.memo[&index].borrow(),
+                    StackObject::String(memoized) if memoized == text
+                ));
+            }
+        }
+        assert!(respelled > 0);
+    }
+
+    #[derive(Debug)]
+    struct AddOne;
+
+    impl Mutator for AddOne {
+        fn name(&self) -> &str {
+            "add-one"
+        }
+
+        fn mutate_int(
+            &self,
+            value: i32,
+            _source: &mut GenerationSource,
+            _rate: f64,
+        ) -> Option<i32> {
+            Some(value + 1)
+        }
+    }
+
+    #[derive(Debug)]
+    struct Double;
+
+    impl Mutator for Double {
+        fn name(&self) -> &str {
+            "double"
+        }
+
+        fn mutate_int(
+            &self,
+            value: i32,
+            _source: &mut GenerationSource,
+            _rate: f64,
+        ) -> Option<i32> {
+            Some(value * 2)
+        }
+    }
+
+    fn mutate_five(policy: MutationPolicy, seed: u64) -> i32 {
+        let generator = Generator::new(Version::V4)
+            .with_mutators(vec![Box::new(AddOne), Box::new(Double)])
+            .with_mutation_policy(policy);
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let mut source = GenerationSource::Rand(&mut rng);
+        generator.mutate_int(OpcodeKind::BinInt, 5, &mut source)
+    }
+
+    #[test]
+    fn test_mutation_policy_controls_composition() {
+        assert_eq!(mutate_five(MutationPolicy::First, 0), 6);
+        assert_eq!(mutate_five(MutationPolicy::All, 0), 12);
+
+        let single: Vec<i32> = (0..32)
+            .map(|seed| mutate_five(MutationPolicy::Random(1), seed))
+            .collect();
+        assert!(single.iter().all(|value| [6, 10].contains(value)));
+        assert!(single.contains(&6) && single.contains(&10));
+
+        // more picks than mutators applies each one exactly once, in random order
+        let both: Vec<i32> = (0..32)
+            .map(|seed| mutate_five(MutationPolicy::Random(5), seed))
+            .collect();
+        assert!(both.iter().all(|value| [11, 12].contains(value)));
+        assert!(both.contains(&11) && both.contains(&12));
+    }
+
+    #[test]
+    fn test_mutation_policy_round_trips_through_strings() {
+        for policy in [
+            MutationPolicy::First,
+            MutationPolicy::All,
+            MutationPolicy::Random(3),
+        ] {
+            assert_eq!(
+                policy.to_string().parse::<MutationPolicy>().unwrap(),
+                policy
+            );
+        }
+        assert!("random:0".parse::<MutationPolicy>().is_err());
+        assert!("random:x".parse::<MutationPolicy>().is_err());
+        assert!("every".parse::<MutationPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_mutation_scope_covers_opcodes_by_argument_class() {
+        assert!(MutationScope::ALL.covers(OpcodeKind::None));
+        assert!(MutationScope::default().covers(OpcodeKind::Global));
+
+        let strings = MutationScope::only(&[MutationTarget::Strings]);
+        assert!(strings.covers(OpcodeKind::Unicode));
+        assert!(strings.covers(OpcodeKind::BinUnicode8));
+        assert!(!strings.covers(OpcodeKind::BinInt));
+        assert!(!strings.covers(OpcodeKind::Global));
+        assert!(!strings.covers(OpcodeKind::None));
+
+        let prefixed = MutationScope::only(&[MutationTarget::LengthPrefixed]);
+        assert!(prefixed.covers(OpcodeKind::ShortBinUnicode));
+        assert!(prefixed.covers(OpcodeKind::Long4));
+        assert!(prefixed.covers(OpcodeKind::BinBytes));
+        assert!(!prefixed.covers(OpcodeKind::Unicode));
+        assert!(!prefixed.covers(OpcodeKind::Long));
+        assert!(prefixed.includes(MutationTarget::LengthPrefixed));
+        assert!(!prefixed.includes(MutationTarget::Bytes));
+    }
+
+    #[test]
+    fn test_mutation_scope_filters_value_mutations() {
+        let generator = Generator::new(Version::V4)
+            .with_mutator(Box::new(AddOne))
+            .with_mutation_scope(MutationScope::only(&[MutationTarget::Memo]));
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let mut source = GenerationSource::Rand(&mut rng);
+        assert_eq!(generator.mutate_int(OpcodeKind::BinInt, 5, &mut source), 5);
+
+        let generator = generator.with_mutation_scope(MutationScope::only(&[MutationTarget::Ints]));
+        assert_eq!(generator.mutate_int(OpcodeKind::BinInt, 5, &mut source), 6);
+    }
+
+    #[test]
+    fn test_out_of_scope_mutators_leave_output_unchanged() {
+        let mut plain = Generator::new(Version::V4).with_seed(7);
+        let mut scoped = Generator::new(Version::V4)
+            .with_seed(7)
+            .with_mutator(Box::new(crate::mutators::CharacterMutator::default()))
+            .with_mutation_rate(1.0)
+            .with_mutation_scope(MutationScope::only(&[MutationTarget::Ints]));
+        assert_eq!(plain.generate().unwrap(), scoped.generate().unwrap());
+    }
+
+    /// breaks memo reads by pointing them past every stored key.
+    #[derive(Debug)]
+    struct MemoOverrunMutator;
+
+    impl Mutator for MemoOverrunMutator {
+        fn name(&self) -> &str {
+            "memo-overrun"
+        }
+
+        fn mutate_memo_index(
+            &self,
+            index: usize,
+            _source: &mut GenerationSource,
+            _rate: f64,
+        ) -> Option<usize> {
+            Some(index + 1000)
+        }
+    }
+
+    fn memo_overrun_generator(unsafe_mutations: bool, seed: u64) -> Generator {
+        Generator::new(Version::V0)
+            .with_seed(seed)
+            .with_mutator(Box::new(MemoOverrunMutator))
+            .with_mutation_rate(1.0)
+            .with_unsafe_mutations(unsafe_mutations)
+    }
+
+    #[test]
+    fn test_safe_mode_rolls_back_invalid_mutations() {
+        let mut broken = 0;
+        for seed in 0..20 {
+            let mut generator = memo_overrun_generator(false, seed);
+            let pickle = generator.generate().unwrap();
+            disasm::validate(&pickle).unwrap_or_else(|e| panic!("seed {seed}: {e}"));
+            assert_eq!(
+                generator.stats().opcodes,
+                disasm::disassemble(&pickle).unwrap().len()
+            );
+
+            let unchecked = memo_overrun_generator(true, seed).generate().unwrap();
+            broken += usize::from(disasm::validate(&unchecked).is_err());
+        }
+        // the same mutation left unchecked does break pickles
+        assert!(broken > 0);
+    }
+
+    #[test]
+    fn test_stats_count_kept_mutated_emissions() {
+        let mut plain = Generator::new(Version::V0).with_seed(2);
+        plain.generate().unwrap();
+        assert_eq!(plain.stats().mutated_emissions, 0);
+
+        let mut unchecked = memo_overrun_generator(true, 2);
+        unchecked.generate().unwrap();
+        assert!(unchecked.stats().mutated_emissions > 0);
+        assert!(unchecked.stats().mutated_emissions < unchecked.stats().opcodes);
+    }
+
+    #[test]
+    fn test_mutator_applications_count_each_mutator() {
+        let mut plain = Generator::new(Version::V0).with_seed(2);
+        plain.generate().unwrap();
+        assert!(plain.mutator_applications().is_empty());
+
+        let mut safe = memo_overrun_generator(false, 2);
+        safe.generate().unwrap();
+        let applications = safe.mutator_applications();
+        assert!(
+            matches!(&applications[..], [(name, count)] if name == "memo-overrun" && *count > 0)
+        );
+        assert!(applications[0].1 >= safe.stats().mutated_emissions);
+
+        // counts start over with every run
+        let mut unchecked = memo_overrun_generator(true, 2);
+        unchecked.generate().unwrap();
+        let first = unchecked.mutator_applications();
+        unchecked.generate().unwrap();
+        assert_eq!(unchecked.mutator_applications(), first);
+    }
+
+    #[test]
+    fn test_enforce_safe_emission_keeps_valid_emissions() {
+        let mut generator = Generator::new(Version::V4);
+        let pre_emission_state = generator.state.clone();
+        generator.output.push(OpcodeKind::None.as_u8());
+        generator.process_stack_ops(OpcodeKind::None, None);
+        assert!(generator.enforce_safe_emission(0, &pre_emission_state));
+        assert_eq!(generator.output, [OpcodeKind::None.as_u8()]);
+
+        // POP on an empty stack doesn't survive re-simulation
+        let mut generator = Generator::new(Version::V4);
+        let pre_emission_state = generator.state.clone();
+        generator.output.push(OpcodeKind::Pop.as_u8());
+        assert!(!generator.enforce_safe_emission(0, &pre_emission_state));
+        assert!(generator.output.is_empty());
+    }
 }
@@ -0,0 +1,323 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! numpy array shapes and dtypes (with_ndarrays).
+//!
+//! an [`NdarraySpec`] says which dtypes and how large a shape the ndarray
+//! pattern may pick, so a corpus can stick to the float arrays of a model
+//! checkpoint or spread over every dtype numpy pickles by name.
+
+use std::str::FromStr;
+
+use color_eyre::eyre::{eyre, Error};
+
+use super::source::{EntropySource, GenerationSource};
+
+/// most dimensions an array may have, numpy 1.x's `NPY_MAXDIMS`.
+pub const MAX_NDARRAY_DIMS: usize = 32;
+
+/// most elements a drawn shape holds; longer dimensions are cut short.
+pub const MAX_NDARRAY_ELEMENTS: usize = 1 << 20;
+
+/// a numpy dtype with a fixed item size, named by its type code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dtype {
+    Bool,
+    Int8,
+    Int16,
+    Int32,
+    Int64,
+    UInt8,
+    UInt16,
+    UInt32,
+    UInt64,
+    Float16,
+    Float32,
+    Float64,
+    Complex64,
+    Complex128,
+}
+
+impl Dtype {
+    /// every dtype, in type code order.
+    pub const ALL: [Dtype; 14] = [
+        Dtype::Bool,
+        Dtype::Int8,
+        Dtype::Int16,
+        Dtype::Int32,
+        Dtype::Int64,
+        Dtype::UInt8,
+        Dtype::UInt16,
+        Dtype::UInt32,
+        Dtype::UInt64,
+        Dtype::Float16,
+        Dtype::Float32,
+        Dtype::Float64,
+        Dtype::Complex64,
+        Dtype::Complex128,
+    ];
+
+    /// the type code `numpy.dtype` is called with when the dtype is unpickled.
+    pub fn code(self) -> &'static str {
+        match self {
+            Dtype::Bool => "b1",
+            Dtype::Int8 => "i1",
+            Dtype::Int16 => "i2",
+            Dtype::Int32 => "i4",
+            Dtype::Int64 => "i8",
+            Dtype::UInt8 => "u1",
+            Dtype::UInt16 => "u2",
+            Dtype::UInt32 => "u4",
+            Dtype::UInt64 => "u8",
+            Dtype::Float16 => "f2",
+            Dtype::Float32 => "f4",
+            Dtype::Float64 => "f8",
+            Dtype::Complex64 => "c8",
+            Dtype::Complex128 => "c16",
+        }
+    }
+
+    /// bytes per element.
+    pub fn itemsize(self) -> usize {
+        match self {
+            Dtype::Bool | Dtype::Int8 | Dtype::UInt8 => 1,
+            Dtype::Int16 | Dtype::UInt16 | Dtype::Float16 => 2,
+            Dtype::Int32 | Dtype::UInt32 | Dtype::Float32 => 4,
+            Dtype::Int64 | Dtype::UInt64 | Dtype::Float64 | Dtype::Complex64 => 8,
+            Dtype::Complex128 => 16,
+        }
+    }
+}
+
+impl FromStr for Dtype {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let code = s.trim();
+        Dtype::ALL
+            .into_iter()
+            .find(|dtype| dtype.code() == code)
+            .ok_or_else(|| eyre!("unknown dtype {:?} (expected a code like f8 or i4)", s))
+    }
+}
+
+/// an array shape of up to [`MAX_NDARRAY_DIMS`] dimensions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Shape {
+    dims: [u32; MAX_NDARRAY_DIMS],
+    ndim: usize,
+}
+
+impl Shape {
+    pub(super) fn new(dims: &[u32]) -> Self {
+        let mut shape = Shape {
+            dims: [0; MAX_NDARRAY_DIMS],
+            ndim: dims.len(),
+        };
+        shape.dims[..dims.len()].copy_from_slice(dims);
+        shape
+    }
+
+    /// the length of every dimension, outermost first.
+    pub(super) fn dims(&self) -> &[u32] {
+        &self.dims[..self.ndim]
+    }
+
+    /// draw a shape of up to `max_dims` dimensions of up to `max_len`
+    /// elements, shortening dimensions that would take it past
+    /// `max_elements`. empty dimensions count as one, so the strides of a
+    /// contiguous array stay under `max_elements` too.
+    pub(super) fn sample(
+        max_dims: usize,
+        max_len: usize,
+        max_elements: usize,
+        source: &mut GenerationSource,
+    ) -> Self {
+        let ndim = source.choose_index(max_dims + 1);
+        let mut dims = [0; MAX_NDARRAY_DIMS];
+        let mut span = 1;
+        for len in &mut dims[..ndim] {
+            let room = max_elements / span;
+            *len = source.choose_index(max_len.min(room) + 1) as u32;
+            span *= (*len as usize).max(1);
+        }
+        Shape::new(&dims[..ndim])
+    }
+
+    /// the number of elements; 1 for a zero-dimensional array.
+    pub(super) fn elements(&self) -> usize {
+        self.dims().iter().map(|&len| len as usize).product()
+    }
+}
+
+/// which arrays the ndarray pattern builds.
+///
+/// parsed from `DTYPES`, `DTYPES:MAX_DIMS`, or `DTYPES:MAX_DIMS:MAX_LEN`,
+/// where `DTYPES` is `all` or a comma-separated list of type codes (`b1`,
+/// `i1`-`i8`, `u1`-`u8`, `f2`-`f8`, `c8`, `c16`). arrays have up to
+/// `MAX_DIMS` (default 3) dimensions of up to `MAX_LEN` (default 8) elements.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NdarraySpec {
+    /// dtypes to pick from, never empty
+    pub dtypes: Vec<Dtype>,
+    /// most dimensions, up to [`MAX_NDARRAY_DIMS`]
+    pub max_dims: usize,
+    /// longest dimension
+    pub max_len: usize,
+}
+
+impl Default for NdarraySpec {
+    fn default() -> Self {
+        Self {
+            dtypes: Dtype::ALL.to_vec(),
+            max_dims: 3,
+            max_len: 8,
+        }
+    }
+}
+
+impl NdarraySpec {
+    /// draw a dtype and a shape of at most [`MAX_NDARRAY_ELEMENTS`] elements.
+    pub(super) fn sample(&self, source: &mut GenerationSource) -> (Dtype, Shape) {
+        let dtype = self.dtypes[source.choose_index(self.dtypes.len())];
+        let shape = Shape::sample(self.max_dims, self.max_len, MAX_NDARRAY_ELEMENTS, source);
+        (dtype, shape)
+    }
+}
+
+impl FromStr for NdarraySpec {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.trim().splitn(3, ':');
+        let dtypes = match parts.next().unwrap_or_default().trim() {
+            "all" => Dtype::ALL.to_vec(),
+            "" => return Err(eyre!("expected DTYPES[:MAX_DIMS[:MAX_LEN]], got {:?}", s)),
+            list => list
+                .split(',')
+                .map(str::parse)
+                .collect::<Result<Vec<Dtype>, _>>()?,
+        };
+        let mut spec = NdarraySpec {
+            dtypes,
+            ..NdarraySpec::default()
+        };
+        if let Some(max_dims) = parts.next() {
+            spec.max_dims = max_dims
+                .trim()
+                .parse::<usize>()
+                .ok()
+                .filter(|&dims| dims <= MAX_NDARRAY_DIMS)
+                .ok_or_else(|| {
+                    eyre!(
+                        "invalid ndarray dimension count {:?} (at most {})",
+                        max_dims,
+                        MAX_NDARRAY_DIMS
+                    )
+                })?;
+        }
+        if let Some(max_len) = parts.next() {
+            spec.max_len = max_len
+                .trim()
+                .parse::<usize>()
+                .ok()
+                .filter(|&len| len <= MAX_NDARRAY_ELEMENTS)
+                .ok_or_else(|| {
+                    eyre!(
+                        "invalid ndarray dimension length {:?} (at most {})",
+                        max_len,
+                        MAX_NDARRAY_ELEMENTS
+                    )
+                })?;
+        }
+        Ok(spec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    #[test]
+    fn parses_dtypes_and_shape_limits() {
+        assert_eq!(
+            "all".parse::<NdarraySpec>().unwrap(),
+            NdarraySpec::default()
+        );
+        assert_eq!(
+            "f4, f8:2".parse::<NdarraySpec>().unwrap(),
+            NdarraySpec {
+                dtypes: vec![Dtype::Float32, Dtype::Float64],
+                max_dims: 2,
+                max_len: 8,
+            }
+        );
+        assert_eq!(
+            "c16:0:1000".parse::<NdarraySpec>().unwrap(),
+            NdarraySpec {
+                dtypes: vec![Dtype::Complex128],
+                max_dims: 0,
+                max_len: 1000,
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_specs() {
+        for text in [
+            "",
+            ":3",
+            "f9",
+            "f4,",
+            "float32",
+            "f4:x",
+            "f4:33",
+            "f4:2:-1",
+            "f4:2:1048577",
+        ] {
+            assert!(text.parse::<NdarraySpec>().is_err(), "{text:?}");
+        }
+    }
+
+    #[test]
+    fn samples_stay_within_the_spec() {
+        let mut rng = ChaCha8Rng::seed_from_u64(8);
+        let mut source = GenerationSource::Rand(&mut rng);
+        let spec = NdarraySpec {
+            dtypes: vec![Dtype::Int16, Dtype::Bool],
+            max_dims: 4,
+            max_len: 5,
+        };
+        for _ in 0..200 {
+            let (dtype, shape) = spec.sample(&mut source);
+            assert!(spec.dtypes.contains(&dtype));
+            assert!(shape.dims().len() <= 4);
+            assert!(shape.dims().iter().all(|&len| len <= 5));
+        }
+
+        let huge = NdarraySpec {
+            max_dims: MAX_NDARRAY_DIMS,
+            max_len: MAX_NDARRAY_ELEMENTS,
+            ..NdarraySpec::default()
+        };
+        for _ in 0..200 {
+            let (_, shape) = huge.sample(&mut source);
+            assert!(shape.elements() <= MAX_NDARRAY_ELEMENTS, "{shape:?}");
+        }
+    }
+}
@@ -0,0 +1,2984 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! multi-opcode emission patterns.
+//!
+//! the generation loop picks one opcode at a time, which almost never lines up
+//! the several opcodes a real-world idiom needs. the routines here emit such an
+//! idiom as a unit: they plan the whole sequence first, so the loop can check
+//! it against the opcode budget, then emit every opcode through the normal
+//! stack simulation. pattern opcodes bypass mutators, and every pattern leaves
+//! exactly one new object on the stack.
+//!
+//! # Patterns
+//!
+//! - **indirect STACK_GLOBAL** (protocol 4+, `with_indirect_stack_globals`):
+//!   the module and name strings reach STACK_GLOBAL through a memo GET, a
+//!   `\uXXXX`-escaped UNICODE, or a DUP/POP pair instead of a literal directly
+//!   in front of it, which is all several scanners look for.
+//! - **global call** (`with_interesting_patterns`): GLOBAL, EMPTY_TUPLE,
+//!   REDUCE, i.e. calling a stdlib global with no arguments.
+//! - **appends batch** (protocol 1+, `with_interesting_patterns`): EMPTY_LIST,
+//!   MARK, a few scalars, APPENDS, the way picklers write lists.
+//! - **dict of reduces** (`with_interesting_patterns`): EMPTY_DICT, MARK, then
+//!   keys whose values are global calls, SETITEMS.
+//! - **setstate chain** (`with_interesting_patterns`): a global call followed
+//!   by one or more dict states, each applied with BUILD.
+//! - **shared object** (`with_interesting_patterns`): a list holding a small
+//!   memoized list, then one-item tuples that hold it again through memo GETs,
+//!   the way a pickler writes an object it meets more than once. unpickled,
+//!   every appearance is the same object, not a copy.
+//! - **recursive container** (`with_interesting_patterns`): a memoized list
+//!   or dict that a memo GET puts into itself among its other items, the way
+//!   a pickler writes `a.append(a)`: the memo entry is read back while the
+//!   container is still being filled.
+//! - **sized container** (`with_container_sizes`): a list, dict, or (protocol
+//!   4+) set whose size is drawn from the configured distribution, filled in
+//!   APPENDS, SETITEMS, or ADDITEMS batches of up to 1000 items the way
+//!   CPython's C pickler does: APPEND or SETITEM only for a one-item list or
+//!   dict, and an empty batch after a full last dict or set batch. with
+//!   `with_oversized_batches`, containers over 1000 items get larger batches.
+//! - **ndarray** (`with_ndarrays`): a numpy array the way numpy's
+//!   `ndarray.__reduce__` pickles it, `_reconstruct` from
+//!   `numpy.core.multiarray` or `numpy._core.multiarray` called with
+//!   `(numpy.ndarray, (0,), b'b')`, then a BUILD of `(1, shape, dtype,
+//!   is_fortran, data)`, where the dtype is a `numpy.dtype` call with its own
+//!   BUILD and the data is raw bytes of the drawn shape. this is the pattern
+//!   ML model scanners meet most, and an arbitrary GLOBAL never spells it.
+//!   bytes arguments go through `_codecs.encode` below protocol 3, as CPython
+//!   writes them.
+//! - **torch tensor** (protocol 1+, `with_torch_tensors` and
+//!   `with_persistent_id_opcodes`): a tensor the way
+//!   `torch.save` pickles one, `torch._utils._rebuild_tensor_v2` called with
+//!   a BINPERSID storage, the storage offset, size and stride tuples,
+//!   `requires_grad`, and an empty `OrderedDict` of backward hooks. the
+//!   persistent id is `('storage', storage_type, key, location, numel)`, with
+//!   a legacy storage class like `torch.FloatStorage`; the tensor data lives
+//!   outside the pickle, so none is written.
+//! - **persistent id** (protocol 1+, `with_persistent_id_payloads` and
+//!   `with_persistent_id_opcodes`): a str, int, bytes (protocol 3+), or
+//!   tuple of alternating strs and ints, then BINPERSID, so
+//!   `persistent_load` gets an id of a shape it might expect.
+//! - **sklearn estimator** (`with_sklearn_estimators`): a scikit-learn
+//!   estimator the way `joblib.dump` pickles it, the class from its private
+//!   `sklearn.*` module created with NEWOBJ (or, below protocol 2,
+//!   `copy_reg._reconstructor`), then a BUILD of its `__dict__`: the default
+//!   hyperparameters, and for a fitted estimator `n_features_in_` and a
+//!   `joblib.numpy_pickle.NumpyArrayWrapper` with its own BUILD per array
+//!   attribute, then `_sklearn_version`. joblib writes each array's bytes
+//!   into the file right after its wrapper, outside any opcode; they are left
+//!   out, so the output stays a pickle.
+//! - **constructor call** (`with_loadable`): a builtin list, dict, set,
+//!   frozenset, complex, or bytearray called with no arguments or with one
+//!   built to fit it, `(a, b)`, `{a: b}`, an int, or bytes: through REDUCE,
+//!   through NEWOBJ (protocol 2+), or as `copyreg._reconstructor(cls, cls,
+//!   arg)`. unlike the global call, it constructs successfully when loaded.
+//!   bytearray arguments need protocol 3+.
+//! - **legacy instance** (protocol 2 and below, `with_legacy_instances`): an
+//!   old-style class instance the way Python 2's `save_inst` pickles it,
+//!   MARK, the `__getinitargs__` arguments if any, and INST on protocol 0 or
+//!   the memoized class and OBJ above it, then the memoized instance's
+//!   `__dict__` with memoized str keys, applied with BUILD.
+//! - **tangled marks** (protocol 1+, `with_unsafe_marks`): two of a list,
+//!   dict, or (protocol 4+) set, the second nested in the first's batch, each
+//!   with its MARK and items, then the outer batch opcode before the inner
+//!   one, so each closes the other's MARK.
+//! - **mark stress** (protocol 1+, `with_mark_stress`): a dense run of MARK,
+//!   POP, POP_MARK, DUP, and scalars that closes every MARK it opens (see
+//!   the `marks` module), duplicating MARKs too with `with_unsafe_marks`.
+//!
+//! protocol 0 has no EMPTY_TUPLE, EMPTY_LIST, EMPTY_DICT, or SETITEMS, so there
+//! the empty tuple is MARK TUPLE, lists are MARK ... LIST, and dicts are
+//! MARK ... DICT. below protocol 2 the one-item tuples are MARK ... TUPLE.
+//! protocol 0 fills sized lists and dicts one APPEND or SETITEM at a time.
+
+use color_eyre::Result;
+
+use super::canonical::BATCH_SIZE;
+use super::loadable::{LOADABLE_TYPES, RECONSTRUCTOR};
+use super::marks::{StressBurst, StressOp};
+use super::ndarray::{Dtype, Shape};
+use super::source::{EntropySource, GenerationSource};
+use super::strict::encode_arg;
+use super::Generator;
+use super::Version;
+use crate::opcodes::{raw_unicode_escape, OpcodeKind, PICKLE_OPCODES};
+
+/// one in this many loop iterations tries a pattern when one is enabled.
+const PATTERN_ODDS: usize = 16;
+
+/// most scalars in an appends batch.
+const MAX_BATCH_ITEMS: usize = 8;
+
+/// most key/global call pairs in a dict of reduces.
+const MAX_DICT_PAIRS: usize = 4;
+
+/// most BUILDs in a setstate chain.
+const MAX_SETSTATES: usize = 3;
+
+/// most later appearances of the object in a shared object pattern.
+const MAX_SHARED_COPIES: usize = 3;
+
+/// most dimensions of a torch tensor.
+const MAX_TENSOR_DIMS: usize = 4;
+
+/// longest dimension of a torch tensor.
+const MAX_TENSOR_LEN: usize = 4096;
+
+/// most elements of a torch tensor, so its storage size fits a BININT.
+const MAX_TENSOR_ELEMENTS: usize = 1 << 30;
+
+/// storage keys are drawn below this, so some tensors share a storage the
+/// way tied weights do.
+const MAX_STORAGE_KEYS: usize = 8;
+
+/// torch's legacy typed storage classes, which checkpoints still name in
+/// their persistent ids.
+const TORCH_STORAGES: [&str; 12] = [
+    "FloatStorage",
+    "DoubleStorage",
+    "HalfStorage",
+    "BFloat16Storage",
+    "LongStorage",
+    "IntStorage",
+    "ShortStorage",
+    "CharStorage",
+    "ByteStorage",
+    "BoolStorage",
+    "ComplexFloatStorage",
+    "ComplexDoubleStorage",
+];
+
+/// most features of a fitted estimator, the length of its array attributes.
+const MAX_ESTIMATOR_FEATURES: usize = 16;
+
+/// the scikit-learn releases an estimator claims to be pickled by.
+const SKLEARN_VERSIONS: [&str; 4] = ["1.2.2", "1.3.2", "1.4.2", "1.5.1"];
+
+/// dtypes of the arrays of a fitted estimator.
+const ESTIMATOR_DTYPES: [Dtype; 4] = [Dtype::Float64, Dtype::Float32, Dtype::Int64, Dtype::Int32];
+
+/// most items of a persistent id tuple, as many as torch's storage ids have.
+const MAX_PID_ITEMS: usize = 5;
+
+/// longest bytes persistent id.
+const MAX_PID_BYTES: usize = 16;
+
+/// most arguments a legacy class's `__getinitargs__` returns.
+const MAX_INIT_ARGS: usize = 3;
+
+/// attribute names a legacy instance's `__dict__` draws its keys from.
+const LEGACY_ATTRS: [&str; 8] = [
+    "name", "value", "data", "count", "enabled", "timeout", "parent", "_cache",
+];
+
+/// a hyperparameter's default value.
+#[derive(Debug, Clone, Copy)]
+enum Param {
+    Bool(bool),
+    Int(i32),
+    Float(f64),
+    Text(&'static str),
+    None,
+}
+
+/// a scikit-learn estimator class, its hyperparameters in `__init__` order,
+/// and the array attributes `fit` sets, with how many dimensions each has.
+struct Estimator {
+    module: &'static str,
+    name: &'static str,
+    params: &'static [(&'static str, Param)],
+    arrays: &'static [(&'static str, usize)],
+}
+
+/// estimators model hubs are full of, under the private modules that
+/// pickles name.
+const SKLEARN_ESTIMATORS: [Estimator; 5] = [
+    Estimator {
+        module: "sklearn.linear_model._base",
+        name: "LinearRegression",
+        params: &[
+            ("fit_intercept", Param::Bool(true)),
+            ("copy_X", Param::Bool(true)),
+            ("n_jobs", Param::None),
+            ("positive", Param::Bool(false)),
+        ],
+        arrays: &[("coef_", 1), ("singular_", 1)],
+    },
+    Estimator {
+        module: "sklearn.linear_model._logistic",
+        name: "LogisticRegression",
+        params: &[
+            ("penalty", Param::Text("l2")),
+            ("dual", Param::Bool(false)),
+            ("tol", Param::Float(0.0001)),
+            ("C", Param::Float(1.0)),
+            ("fit_intercept", Param::Bool(true)),
+            ("intercept_scaling", Param::Int(1)),
+            ("class_weight", Param::None),
+            ("random_state", Param::None),
+            ("solver", Param::Text("lbfgs")),
+            ("max_iter", Param::Int(100)),
+            ("verbose", Param::Int(0)),
+            ("warm_start", Param::Bool(false)),
+            ("n_jobs", Param::None),
+            ("l1_ratio", Param::None),
+        ],
+        arrays: &[("classes_", 1), ("coef_", 2), ("intercept_", 1)],
+    },
+    Estimator {
+        module: "sklearn.preprocessing._data",
+        name: "StandardScaler",
+        params: &[
+            ("with_mean", Param::Bool(true)),
+            ("with_std", Param::Bool(true)),
+            ("copy", Param::Bool(true)),
+        ],
+        arrays: &[("mean_", 1), ("var_", 1), ("scale_", 1)],
+    },
+    Estimator {
+        module: "sklearn.decomposition._pca",
+        name: "PCA",
+        params: &[
+            ("n_components", Param::None),
+            ("copy", Param::Bool(true)),
+            ("whiten", Param::Bool(false)),
+            ("svd_solver", Param::Text("auto")),
+            ("tol", Param::Float(0.0)),
+            ("iterated_power", Param::Text("auto")),
+            ("n_oversamples", Param::Int(10)),
+            ("power_iteration_normalizer", Param::Text("auto")),
+            ("random_state", Param::None),
+        ],
+        arrays: &[("components_", 2), ("explained_variance_", 1), ("mean_", 1)],
+    },
+    Estimator {
+        module: "sklearn.cluster._kmeans",
+        name: "KMeans",
+        params: &[
+            ("n_clusters", Param::Int(8)),
+            ("init", Param::Text("k-means++")),
+            ("max_iter", Param::Int(300)),
+            ("tol", Param::Float(0.0001)),
+            ("n_init", Param::Text("auto")),
+            ("verbose", Param::Int(0)),
+            ("random_state", Param::None),
+            ("copy_x", Param::Bool(true)),
+            ("algorithm", Param::Text("lloyd")),
+        ],
+        arrays: &[("cluster_centers_", 2), ("labels_", 1)],
+    },
+];
+
+/// opcodes pushed as list items and dict values.
+const SCALAR_OPCODES: &[OpcodeKind] = &[
+    OpcodeKind::Int,
+    OpcodeKind::Float,
+    OpcodeKind::BinFloat,
+    OpcodeKind::String,
+    OpcodeKind::Unicode,
+    OpcodeKind::BinUnicode,
+    OpcodeKind::ShortBinUnicode,
+    OpcodeKind::ShortBinBytes,
+    OpcodeKind::None,
+    OpcodeKind::NewTrue,
+    OpcodeKind::NewFalse,
+];
+
+/// opcodes pushed as dict keys, all hashable.
+const KEY_OPCODES: &[OpcodeKind] = &[
+    OpcodeKind::Int,
+    OpcodeKind::String,
+    OpcodeKind::Unicode,
+    OpcodeKind::BinUnicode,
+    OpcodeKind::ShortBinUnicode,
+];
+
+/// how a module or name string reaches STACK_GLOBAL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NameSource {
+    /// pushed, memoized, and popped up front, then fetched with a GET
+    Memo,
+    /// a protocol 0 UNICODE with every character spelled as `\uXXXX`
+    Escaped,
+    /// a literal, DUP, then POP of the duplicate
+    Doubled,
+}
+
+impl NameSource {
+    const ALL: [NameSource; 3] = [NameSource::Memo, NameSource::Escaped, NameSource::Doubled];
+
+    /// opcodes this source emits, the GET's setup included.
+    fn opcode_count(self) -> usize {
+        match self {
+            NameSource::Memo => 4,
+            NameSource::Escaped => 1,
+            NameSource::Doubled => 3,
+        }
+    }
+}
+
+/// the container a sized container pattern builds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SizedKind {
+    List,
+    Dict,
+    /// protocol 4+ only
+    Set,
+}
+
+impl SizedKind {
+    /// the opcodes that create the container, close a batch of its items,
+    /// and add a lone item.
+    fn opcodes(self) -> (OpcodeKind, OpcodeKind, OpcodeKind) {
+        match self {
+            SizedKind::List => (
+                OpcodeKind::EmptyList,
+                OpcodeKind::Appends,
+                OpcodeKind::Append,
+            ),
+            SizedKind::Dict => (
+                OpcodeKind::EmptyDict,
+                OpcodeKind::SetItems,
+                OpcodeKind::SetItem,
+            ),
+            SizedKind::Set => (
+                OpcodeKind::EmptySet,
+                OpcodeKind::AddItems,
+                OpcodeKind::AddItems,
+            ),
+        }
+    }
+
+    /// stack items one of its entries takes, two for a dict's key and value.
+    fn item_len(self) -> usize {
+        if self == SizedKind::Dict {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// whether CPython puts a lone item under APPEND or SETITEM instead of a
+    /// batch; sets have no single-item opcode.
+    fn has_single(self) -> bool {
+        self != SizedKind::Set
+    }
+
+    /// whether the C pickler starts another, empty batch after a full last
+    /// one, as its dict and set loops do and its list loop does not.
+    fn trailing_batch(self, len: usize, batch: usize) -> bool {
+        self != SizedKind::List && len > 0 && len.is_multiple_of(batch)
+    }
+}
+
+/// opcodes that close the items of a `len` item container the way the C
+/// pickler does: APPEND or SETITEM for a lone item, else MARK and the batch
+/// opcode for every batch of up to `batch` items.
+fn batch_opcode_count(kind: SizedKind, len: usize, batch: usize) -> usize {
+    if len == 1 && kind.has_single() {
+        return 1;
+    }
+    2 * (len.div_ceil(batch) + usize::from(kind.trailing_batch(len, batch)))
+}
+
+/// whether a `len` item tuple has its own opcode (EMPTY_TUPLE, TUPLE1-3)
+/// under `version`, instead of MARK ... TUPLE.
+fn has_short_tuple(version: Version, len: usize) -> bool {
+    match len {
+        0 => version >= Version::V1,
+        1..=3 => version >= Version::V2,
+        _ => false,
+    }
+}
+
+/// opcodes that build a tuple around `len` items already counted.
+fn tuple_opcode_count(version: Version, len: usize) -> usize {
+    if has_short_tuple(version, len) {
+        1
+    } else {
+        2
+    }
+}
+
+/// opcodes a named global takes: GLOBAL, or two strings and STACK_GLOBAL on
+/// protocol 4+.
+fn global_opcode_count(version: Version) -> usize {
+    if version >= Version::V4 {
+        3
+    } else {
+        1
+    }
+}
+
+/// opcodes a `len` byte bytes object takes: one BINBYTES on protocol 3+, else
+/// the `_codecs.encode(text, 'latin1')` call (or, empty, `bytes()`) CPython
+/// reduces bytes to.
+fn bytes_opcode_count(version: Version, len: usize) -> usize {
+    match (version >= Version::V3, len) {
+        (true, _) => 1,
+        (false, 0) => global_opcode_count(version) + tuple_opcode_count(version, 0) + 1,
+        (false, _) => global_opcode_count(version) + 2 + tuple_opcode_count(version, 2) + 1,
+    }
+}
+
+/// opcodes that create an instance of a named class without calling
+/// `__init__`: the class, EMPTY_TUPLE, and NEWOBJ on protocol 2+, else a
+/// `copy_reg._reconstructor(cls, object, None)` call.
+fn instance_opcode_count(version: Version) -> usize {
+    let global = global_opcode_count(version);
+    if version >= Version::V2 {
+        global + 2
+    } else {
+        3 * global + 1 + tuple_opcode_count(version, 3) + 1
+    }
+}
+
+/// opcodes of a `pairs` entry dict of text keys, its values not counted:
+/// MARK ... DICT below protocol 1, else EMPTY_DICT, MARK ... SETITEMS.
+fn dict_opcode_count(version: Version, pairs: usize) -> usize {
+    let container = if version < Version::V1 { 2 } else { 3 };
+    container + pairs
+}
+
+/// opcodes of a `numpy.dtype(code, False, True)` call and the BUILD of its
+/// 8-item state.
+fn dtype_opcode_count(version: Version) -> usize {
+    global_opcode_count(version)
+        + 3
+        + tuple_opcode_count(version, 3)
+        + 1
+        + 8
+        + tuple_opcode_count(version, 8)
+        + 1
+}
+
+/// opcodes of a `NumpyArrayWrapper` for an `ndim` array: the instance, then
+/// the BUILD of its subclass, shape, order, dtype, allow_mmap, and alignment.
+fn array_wrapper_opcode_count(version: Version, ndim: usize) -> usize {
+    let values = global_opcode_count(version)
+        + ndim
+        + tuple_opcode_count(version, ndim)
+        + 1
+        + dtype_opcode_count(version)
+        + 2;
+    instance_opcode_count(version) + dict_opcode_count(version, 6) + values + 1
+}
+
+/// how a constructor call pattern calls its type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Construction {
+    /// `cls()`
+    Empty,
+    /// `cls(arg)`
+    WithArg,
+    /// `cls.__new__(cls, arg)`, with NEWOBJ
+    NewObj,
+    /// `copyreg._reconstructor(cls, cls, arg)`
+    Reconstructor,
+}
+
+/// opcodes of the argument a constructor call passes `ty`.
+fn constructor_arg_opcode_count(version: Version, ty: &str) -> usize {
+    match ty {
+        // MARK a b DICT, or EMPTY_DICT a b SETITEM
+        "dict" => 4,
+        "complex" | "bytearray" => 1,
+        _ => 2 + tuple_opcode_count(version, 2),
+    }
+}
+
+/// most items the argument of a `ty` constructor call has on the stack.
+fn constructor_arg_peak(version: Version, ty: &str) -> usize {
+    match ty {
+        "dict" => 3,
+        "complex" | "bytearray" => 1,
+        _ => 2 + usize::from(!has_short_tuple(version, 2)),
+    }
+}
+
+/// the id a persistent id pattern hands BINPERSID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PidPayload {
+    Text,
+    Int,
+    /// protocol 3+
+    Bytes,
+    /// strs at even and ints at odd positions, like `('storage', ..., 4)`
+    Tuple {
+        len: usize,
+    },
+}
+
+/// a planned pattern, with every random size already chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pattern {
+    IndirectStackGlobal {
+        module: NameSource,
+        name: NameSource,
+    },
+    GlobalCall,
+    AppendsBatch {
+        items: usize,
+    },
+    DictOfReduces {
+        pairs: usize,
+    },
+    SetstateChain {
+        states: usize,
+    },
+    SharedObject {
+        copies: usize,
+    },
+    RecursiveContainer {
+        /// a list or a dict
+        kind: SizedKind,
+        items: usize,
+        /// the item (or value) that is the container itself
+        at: usize,
+    },
+    SizedContainer {
+        kind: SizedKind,
+        len: usize,
+        /// most items per batch, [`BATCH_SIZE`] unless oversized
+        batch: usize,
+    },
+    Ndarray {
+        dtype: Dtype,
+        shape: Shape,
+    },
+    TorchTensor {
+        /// index into [`TORCH_STORAGES`]
+        storage: usize,
+        shape: Shape,
+    },
+    PersistentId {
+        payload: PidPayload,
+    },
+    SklearnEstimator {
+        /// index into [`SKLEARN_ESTIMATORS`]
+        estimator: usize,
+        /// whether the state holds `fit`'s attributes
+        fitted: bool,
+    },
+    ConstructorCall {
+        /// index into [`LOADABLE_TYPES`]
+        ty: usize,
+        construction: Construction,
+    },
+    LegacyInstance {
+        /// how many `__getinitargs__` arguments the class is called with
+        args: usize,
+        /// how many entries its `__dict__` has, at most [`LEGACY_ATTRS`]
+        attrs: usize,
+    },
+    TangledMarks {
+        outer: SizedKind,
+        /// never the same kind as `outer`
+        inner: SizedKind,
+        /// entries in each batch
+        items: usize,
+    },
+    MarkStress {
+        burst: StressBurst,
+    },
+}
+
+impl Pattern {
+    /// whether the pattern calls a global, which forbidding reduce rules out.
+    /// what annotations call the pattern.
+    fn name(self) -> &'static str {
+        match self {
+            Pattern::IndirectStackGlobal { .. } => "indirect stack global",
+            Pattern::GlobalCall => "global call",
+            Pattern::AppendsBatch { .. } => "appends batch",
+            Pattern::DictOfReduces { .. } => "dict of reduces",
+            Pattern::SetstateChain { .. } => "setstate chain",
+            Pattern::SharedObject { .. } => "shared object",
+            Pattern::RecursiveContainer { .. } => "recursive container",
+            Pattern::SizedContainer { .. } => "sized container",
+            Pattern::Ndarray { .. } => "ndarray",
+            Pattern::TorchTensor { .. } => "torch tensor",
+            Pattern::PersistentId { .. } => "persistent id",
+            Pattern::SklearnEstimator { .. } => "sklearn estimator",
+            Pattern::ConstructorCall { .. } => "constructor call",
+            Pattern::LegacyInstance { .. } => "legacy instance",
+            Pattern::TangledMarks { .. } => "tangled marks",
+            Pattern::MarkStress { .. } => "mark stress",
+        }
+    }
+
+    fn calls(self) -> bool {
+        matches!(
+            self,
+            Pattern::GlobalCall
+                | Pattern::DictOfReduces { .. }
+                | Pattern::SetstateChain { .. }
+                | Pattern::Ndarray { .. }
+                | Pattern::TorchTensor { .. }
+                | Pattern::SklearnEstimator { .. }
+                | Pattern::ConstructorCall { .. }
+                | Pattern::LegacyInstance { .. }
+        )
+    }
+
+    /// opcodes the pattern emits under `version`.
+    fn opcode_count(self, version: Version) -> usize {
+        // MARK TUPLE and MARK ... DICT stand in for EMPTY_TUPLE and EMPTY_DICT
+        // ... SETITEM(S) below protocol 1
+        let empty_tuple = if version < Version::V1 { 2 } else { 1 };
+        let global_call = 2 + empty_tuple;
+        match self {
+            Pattern::IndirectStackGlobal { module, name } => {
+                module.opcode_count() + name.opcode_count() + 1
+            }
+            Pattern::GlobalCall => global_call,
+            Pattern::AppendsBatch { items } => 3 + items,
+            Pattern::DictOfReduces { pairs } => {
+                let container = if version < Version::V1 { 2 } else { 3 };
+                container + pairs * (1 + global_call)
+            }
+            // each state is a one-entry dict (4 opcodes either way) and BUILD
+            Pattern::SetstateChain { states } => global_call + states * 5,
+            // the outer list (MARK ... LIST below protocol 1), the memoized
+            // list (MARK LIST, PUT, scalar, APPEND there), and a GET and
+            // TUPLE1 (MARK GET TUPLE below protocol 2) per later appearance
+            Pattern::SharedObject { copies } => {
+                let copy = if version < Version::V2 { 3 } else { 2 };
+                7 + copies * copy
+            }
+            // MARK LIST or MARK DICT, PUT, and the items each with their
+            // APPEND or SETITEM below protocol 1
+            Pattern::RecursiveContainer { kind, items, .. } if version < Version::V1 => {
+                3 + items * (kind.item_len() + 1)
+            }
+            // the empty container, PUT, and the items batched like CPython
+            Pattern::RecursiveContainer { kind, items, .. } => {
+                2 + items * kind.item_len() + batch_opcode_count(kind, items, BATCH_SIZE)
+            }
+            // MARK LIST or MARK DICT, then one APPEND or SETITEM per item
+            // below protocol 1
+            Pattern::SizedContainer { kind, len, .. } if version < Version::V1 => match kind {
+                SizedKind::List => 2 + 2 * len,
+                SizedKind::Dict => 2 + 3 * len,
+                SizedKind::Set => unreachable!("sets need protocol 4"),
+            },
+            Pattern::SizedContainer { kind, len, batch } => {
+                let item = if kind == SizedKind::Dict { 2 } else { 1 };
+                1 + item * len + batch_opcode_count(kind, len, batch)
+            }
+            Pattern::Ndarray { dtype, shape } => {
+                let global = global_opcode_count(version);
+                let ndim = shape.dims().len();
+                let data_len = shape.elements() * dtype.itemsize();
+                // ndarray, 0, (0,), b'b'
+                let args = global
+                    + 1
+                    + tuple_opcode_count(version, 1)
+                    + bytes_opcode_count(version, 1)
+                    + tuple_opcode_count(version, 3);
+                // 1, shape, dtype, is_fortran, data
+                let state = 1
+                    + ndim
+                    + tuple_opcode_count(version, ndim)
+                    + dtype_opcode_count(version)
+                    + 1
+                    + bytes_opcode_count(version, data_len)
+                    + tuple_opcode_count(version, 5);
+                // the REDUCE and BUILD of the array itself
+                global + args + 1 + state + 1
+            }
+            Pattern::TorchTensor { shape, .. } => {
+                let global = global_opcode_count(version);
+                let ndim = shape.dims().len();
+                // the persistent id tuple and BINPERSID
+                let storage = 4 + global + tuple_opcode_count(version, 5) + 1;
+                // an OrderedDict() of backward hooks
+                let hooks = global + tuple_opcode_count(version, 0) + 1;
+                // storage, offset, size, stride, requires_grad, hooks, then
+                // the REDUCE of _rebuild_tensor_v2
+                let args = storage
+                    + 1
+                    + 2 * (ndim + tuple_opcode_count(version, ndim))
+                    + 1
+                    + hooks
+                    + tuple_opcode_count(version, 6);
+                global + args + 1
+            }
+            Pattern::SklearnEstimator { estimator, fitted } => {
+                let estimator = &SKLEARN_ESTIMATORS[estimator];
+                // every hyperparameter and `_sklearn_version` is one opcode
+                let mut pairs = estimator.params.len() + 1;
+                let mut values = pairs;
+                if fitted {
+                    pairs += 1 + estimator.arrays.len();
+                    values += 1;
+                    values += estimator
+                        .arrays
+                        .iter()
+                        .map(|&(_, ndim)| array_wrapper_opcode_count(version, ndim))
+                        .sum::<usize>();
+                }
+                instance_opcode_count(version) + dict_opcode_count(version, pairs) + values + 1
+            }
+            Pattern::ConstructorCall { ty, construction } => {
+                let global = global_opcode_count(version);
+                let arg = constructor_arg_opcode_count(version, LOADABLE_TYPES[ty]);
+                match construction {
+                    Construction::Empty => global + tuple_opcode_count(version, 0) + 1,
+                    Construction::WithArg | Construction::NewObj => {
+                        global + arg + tuple_opcode_count(version, 1) + 1
+                    }
+                    Construction::Reconstructor => {
+                        3 * global + arg + tuple_opcode_count(version, 3) + 1
+                    }
+                }
+            }
+            // MARK, the arguments, INST, PUT, the dict's MARK DICT PUT, then a
+            // key, its PUT, a value, and SETITEM per attribute, and BUILD
+            Pattern::LegacyInstance { args, attrs } if version < Version::V1 => {
+                7 + args + 4 * attrs
+            }
+            // MARK, GLOBAL and BINPUT, the arguments, OBJ and BINPUT, the
+            // dict's EMPTY_DICT and BINPUT, a key, its BINPUT, and a value per
+            // attribute closed by SETITEM or MARK ... SETITEMS, and BUILD
+            Pattern::LegacyInstance { args, attrs } => {
+                let batch = match attrs {
+                    0 => 0,
+                    1 => 1,
+                    _ => 2,
+                };
+                8 + args + 3 * attrs + batch
+            }
+            // per container its empty opcode, MARK, and items, then the two
+            // batch opcodes
+            Pattern::TangledMarks {
+                outer,
+                inner,
+                items,
+            } => 6 + items * (outer.item_len() + inner.item_len()),
+            Pattern::MarkStress { burst } => burst.ops().len(),
+            // the id, then BINPERSID
+            Pattern::PersistentId { payload } => match payload {
+                PidPayload::Tuple { len } => len + tuple_opcode_count(version, len) + 1,
+                PidPayload::Text | PidPayload::Int | PidPayload::Bytes => 2,
+            },
+        }
+    }
+
+    /// most items the pattern has on the stack at once, above what was there.
+    fn peak_stack_growth(self, version: Version) -> usize {
+        match self {
+            // module, name, and the name's duplicate
+            Pattern::IndirectStackGlobal { .. } => 3,
+            // the global and its (MARK or) empty tuple
+            Pattern::GlobalCall => 2,
+            Pattern::AppendsBatch { items } => 2 + items,
+            // finished pairs, then a key, a global, and its empty tuple
+            Pattern::DictOfReduces { pairs } => {
+                let container = if version < Version::V1 { 1 } else { 2 };
+                container + 2 * (pairs - 1) + 3
+            }
+            // the instance, then the state's dict (or MARK), key and value
+            Pattern::SetstateChain { .. } => 4,
+            // the outer list (or MARK), its MARK, the shared list, finished
+            // tuples, then the last tuple's (MARK and) GET
+            Pattern::SharedObject { copies } => {
+                let container = if version < Version::V1 { 1 } else { 2 };
+                let copy = if version < Version::V2 { 2 } else { 1 };
+                container + 1 + (copies - 1) + copy
+            }
+            // the container and one item, or the container, MARK, and all
+            // items of the batch
+            Pattern::RecursiveContainer { kind, items, .. } => {
+                if version < Version::V1 || items == 1 {
+                    1 + kind.item_len()
+                } else {
+                    2 + items * kind.item_len()
+                }
+            }
+            // the container and the items of one APPEND or SETITEM
+            Pattern::SizedContainer { kind, len, .. } if version < Version::V1 => {
+                let item = if kind == SizedKind::Dict { 2 } else { 1 };
+                1 + if len > 0 { item } else { 0 }
+            }
+            // the container, then MARK and the first (largest) batch, or the
+            // lone item
+            Pattern::SizedContainer { kind, len, batch } => {
+                let item = if kind == SizedKind::Dict { 2 } else { 1 };
+                if len == 0 || (len == 1 && kind.has_single()) {
+                    1 + len * item
+                } else {
+                    2 + len.min(batch) * item
+                }
+            }
+            // the array, the state's MARK, 1, the shape, the dtype, and the
+            // dtype state's MARK and 8 items, unless the shape's (MARK and)
+            // items outgrow them
+            Pattern::Ndarray { shape, .. } => {
+                let ndim = shape.dims().len();
+                let mark = usize::from(!has_short_tuple(version, ndim));
+                14.max(3 + (ndim + mark).max(1))
+            }
+            // the tensor function, the arguments' MARK, storage, offset, size,
+            // requires_grad, and the hooks' global (or its two strings) and
+            // empty tuple, unless the stride's (MARK and) items outgrow them
+            Pattern::TorchTensor { shape, .. } => {
+                let ndim = shape.dims().len();
+                let mark = usize::from(!has_short_tuple(version, ndim));
+                9.max(5 + (ndim + mark).max(1))
+            }
+            // the instance, its dict (or MARK) and MARK, finished pairs, then
+            // the last pair, or the last array's key and wrapper: the wrapper,
+            // its dict (or MARK) and MARK, three pairs, the dtype key, and the
+            // dtype with its state's MARK and 8 items
+            Pattern::SklearnEstimator { estimator, fitted } => {
+                let estimator = &SKLEARN_ESTIMATORS[estimator];
+                let dict = if version < Version::V1 { 2 } else { 3 };
+                if fitted {
+                    let wrapper = dict + 6 + 1 + 10;
+                    dict + 2 * (estimator.params.len() + estimator.arrays.len()) + 1 + wrapper
+                } else {
+                    dict + 2 * (estimator.params.len() + 1)
+                }
+            }
+            // the callable and the arguments' (MARK and) empty tuple, or the
+            // callable, the arguments' MARK, the two classes, and the
+            // argument's items
+            Pattern::ConstructorCall { ty, construction } => {
+                let arg = constructor_arg_peak(version, LOADABLE_TYPES[ty]);
+                match construction {
+                    Construction::Empty => 2,
+                    Construction::WithArg | Construction::NewObj => {
+                        1 + usize::from(!has_short_tuple(version, 1)) + arg
+                    }
+                    Construction::Reconstructor => {
+                        3 + usize::from(!has_short_tuple(version, 3)) + arg
+                    }
+                }
+            }
+            // the MARK and arguments, then the instance, its dict (or MARK),
+            // and one key and value, each SETITEM'd on its own
+            Pattern::LegacyInstance { args, attrs } if version < Version::V1 => {
+                (1 + args).max(if attrs > 0 { 4 } else { 2 })
+            }
+            // the MARK, class, and arguments, then the instance, its dict, and
+            // the MARK and pairs of a SETITEMS or the lone pair of a SETITEM
+            Pattern::LegacyInstance { args, attrs } => {
+                let state = match attrs {
+                    0 => 2,
+                    1 => 4,
+                    _ => 3 + 2 * attrs,
+                };
+                (2 + args).max(state)
+            }
+            // both containers with their MARKs and items
+            Pattern::TangledMarks {
+                outer,
+                inner,
+                items,
+            } => 4 + items * (outer.item_len() + inner.item_len()),
+            Pattern::MarkStress { burst } => burst.peak_stack_growth(),
+            // the tuple's items and MARK
+            Pattern::PersistentId { payload } => match payload {
+                PidPayload::Tuple { len } => len + usize::from(!has_short_tuple(version, len)),
+                PidPayload::Text | PidPayload::Int | PidPayload::Bytes => 1,
+            },
+        }
+    }
+}
+
+/// spell every character of `text` as a raw-unicode-escape `\uXXXX` or
+/// `\UXXXXXXXX` escape.
+pub(super) fn escape_all(text: &str) -> String {
+    text.chars()
+        .map(|c| match u32::from(c) {
+            code @ 0..=0xffff => format!("\\u{code:04x}"),
+            code => format!("\\U{code:08x}"),
+        })
+        .collect()
+}
+
+impl Generator {
+    /// occasionally emit one of the enabled multi-opcode patterns.
+    ///
+    /// the pattern is only emitted if its opcodes and the cleanup it leaves
+    /// behind fit `remaining_budget` and its peak fits `max_stack_depth`, and
+    /// it is rolled back if it overruns the byte limit. with no pattern enabled
+    /// this draws no entropy, so the output is unchanged.
+    ///
+    /// # Returns
+    /// the number of opcodes emitted, or `None` if no pattern was emitted.
+    pub(super) fn emit_interesting_pattern(
+        &mut self,
+        remaining_budget: usize,
+        source: &mut GenerationSource,
+    ) -> Result<Option<usize>> {
+        let version = self.state.version;
+        let indirect = self.indirect_stack_globals && version >= Version::V4;
+        let enabled = indirect
+            || self.interesting_patterns
+            || self.container_sizes.is_some()
+            || self.ndarrays.is_some()
+            || self.torch_tensors_enabled()
+            || self.sklearn_estimators
+            || self.legacy_instances
+            || self.loadable
+            || self.unsafe_marks
+            || self.mark_stress.is_some()
+            || self.persistent_id_payloads_enabled();
+        if !enabled || source.choose_index(PATTERN_ODDS) != 0 {
+            return Ok(None);
+        }
+
+        let Some(pattern) = self.plan_pattern(indirect, source) else {
+            return Ok(None);
+        };
+        let stack_len = self.state.stack.len();
+        if self
+            .max_stack_depth
+            .is_some_and(|max| stack_len + pattern.peak_stack_growth(version) > max)
+        {
+            return Ok(None);
+        }
+        let opcodes = pattern.opcode_count(version);
+        let marks = self.state.stack.mark_positions().len();
+        let cleanup_after = self.cleanup_opcode_count_for(stack_len + 1, marks, None);
+        if opcodes + cleanup_after > remaining_budget {
+            return Ok(None);
+        }
+
+        let rollback = self
+            .bufsize
+            .map(|_| (self.state.clone(), self.output.len()));
+        let mutators = std::mem::take(&mut self.mutators);
+        self.begin_step(pattern.name());
+        let emitted = self.emit_pattern(pattern, source);
+        self.mutators = mutators;
+        emitted?;
+        if !self.fits_byte_limit(self.current_cleanup_opcode_count()) {
+            if let Some((state, output_len)) = rollback {
+                self.state = state;
+                self.output.truncate(output_len);
+            }
+            return Ok(None);
+        }
+        Ok(Some(opcodes))
+    }
+
+    /// whether torch tensors can be emitted: they need BINPERSID, so protocol
+    /// 1+ and persistent-id opcodes.
+    fn torch_tensors_enabled(&self) -> bool {
+        self.torch_tensors && self.allow_persistent_id_opcodes && self.state.version >= Version::V1
+    }
+
+    /// whether persistent id payloads can be emitted: like torch tensors they
+    /// need BINPERSID.
+    fn persistent_id_payloads_enabled(&self) -> bool {
+        self.persistent_id_payloads
+            && self.allow_persistent_id_opcodes
+            && self.state.version >= Version::V1
+    }
+
+    /// pick one of the enabled patterns for the current protocol and size it,
+    /// if forbidding reduce leaves one.
+    fn plan_pattern(&self, indirect: bool, source: &mut GenerationSource) -> Option<Pattern> {
+        let mut candidates = Vec::with_capacity(10);
+        if indirect {
+            candidates.push(Pattern::IndirectStackGlobal {
+                module: NameSource::Memo,
+                name: NameSource::Memo,
+            });
+        }
+        if self.interesting_patterns {
+            candidates.push(Pattern::GlobalCall);
+            if self.state.version >= Version::V1 {
+                candidates.push(Pattern::AppendsBatch { items: 0 });
+            }
+            candidates.push(Pattern::DictOfReduces { pairs: 0 });
+            candidates.push(Pattern::SetstateChain { states: 0 });
+            candidates.push(Pattern::SharedObject { copies: 0 });
+            candidates.push(Pattern::RecursiveContainer {
+                kind: SizedKind::List,
+                items: 0,
+                at: 0,
+            });
+        }
+        if self.container_sizes.is_some() {
+            candidates.push(Pattern::SizedContainer {
+                kind: SizedKind::List,
+                len: 0,
+                batch: BATCH_SIZE,
+            });
+        }
+        if self.ndarrays.is_some() {
+            candidates.push(Pattern::Ndarray {
+                dtype: Dtype::Bool,
+                shape: Shape::new(&[]),
+            });
+        }
+        if self.torch_tensors_enabled() {
+            candidates.push(Pattern::TorchTensor {
+                storage: 0,
+                shape: Shape::new(&[]),
+            });
+        }
+        if self.persistent_id_payloads_enabled() {
+            candidates.push(Pattern::PersistentId {
+                payload: PidPayload::Text,
+            });
+        }
+        if self.sklearn_estimators {
+            candidates.push(Pattern::SklearnEstimator {
+                estimator: 0,
+                fitted: false,
+            });
+        }
+        // Python 2 pickled up to protocol 2
+        if self.legacy_instances && self.state.version <= Version::V2 {
+            candidates.push(Pattern::LegacyInstance { args: 0, attrs: 0 });
+        }
+        if self.unsafe_marks && self.state.version >= Version::V1 {
+            candidates.push(Pattern::TangledMarks {
+                outer: SizedKind::List,
+                inner: SizedKind::Dict,
+                items: 0,
+            });
+        }
+        // POP_MARK is protocol 1+
+        if self.mark_stress.is_some() && self.state.version >= Version::V1 {
+            candidates.push(Pattern::MarkStress {
+                burst: StressBurst::EMPTY,
+            });
+        }
+
+        if self.loadable {
+            // the other calls have random callables and arguments
+            candidates.retain(|pattern| !pattern.calls());
+            if LOADABLE_TYPES
+                .iter()
+                .any(|ty| self.allowlisted("builtins", ty))
+            {
+                candidates.push(Pattern::ConstructorCall {
+                    ty: 0,
+                    construction: Construction::Empty,
+                });
+            }
+        }
+        if self.forbid_reduce {
+            candidates.retain(|pattern| !pattern.calls());
+        }
+        if candidates.is_empty() {
+            return None;
+        }
+
+        Some(match candidates[source.choose_index(candidates.len())] {
+            Pattern::IndirectStackGlobal { .. } => Pattern::IndirectStackGlobal {
+                module: NameSource::ALL[source.choose_index(NameSource::ALL.len())],
+                name: NameSource::ALL[source.choose_index(NameSource::ALL.len())],
+            },
+            Pattern::GlobalCall => Pattern::GlobalCall,
+            Pattern::AppendsBatch { .. } => Pattern::AppendsBatch {
+                items: 1 + source.choose_index(MAX_BATCH_ITEMS),
+            },
+            Pattern::DictOfReduces { .. } => Pattern::DictOfReduces {
+                pairs: 1 + source.choose_index(MAX_DICT_PAIRS),
+            },
+            Pattern::SetstateChain { .. } => Pattern::SetstateChain {
+                states: 1 + source.choose_index(MAX_SETSTATES),
+            },
+            Pattern::SharedObject { .. } => Pattern::SharedObject {
+                copies: 1 + source.choose_index(MAX_SHARED_COPIES),
+            },
+            Pattern::RecursiveContainer { .. } => {
+                let kind = if source.gen_bool() {
+                    SizedKind::List
+                } else {
+                    SizedKind::Dict
+                };
+                let items = 1 + source.choose_index(MAX_BATCH_ITEMS);
+                Pattern::RecursiveContainer {
+                    kind,
+                    items,
+                    at: source.choose_index(items),
+                }
+            }
+            Pattern::SizedContainer { .. } => {
+                let kinds: &[SizedKind] = if self.state.version >= Version::V4 {
+                    &[SizedKind::List, SizedKind::Dict, SizedKind::Set]
+                } else {
+                    &[SizedKind::List, SizedKind::Dict]
+                };
+                let kind = kinds[source.choose_index(kinds.len())];
+                let sizes = self.container_sizes.expect("sized containers are enabled");
+                let len = sizes.sample(source);
+                let batch = if self.oversized_batches && len > BATCH_SIZE {
+                    BATCH_SIZE + 1 + source.choose_index(len - BATCH_SIZE)
+                } else {
+                    BATCH_SIZE
+                };
+                Pattern::SizedContainer { kind, len, batch }
+            }
+            Pattern::Ndarray { .. } => {
+                let spec = self.ndarrays.as_ref().expect("ndarrays are enabled");
+                let (dtype, shape) = spec.sample(source);
+                Pattern::Ndarray { dtype, shape }
+            }
+            Pattern::TorchTensor { .. } => Pattern::TorchTensor {
+                storage: source.choose_index(TORCH_STORAGES.len()),
+                shape: Shape::sample(MAX_TENSOR_DIMS, MAX_TENSOR_LEN, MAX_TENSOR_ELEMENTS, source),
+            },
+            Pattern::PersistentId { .. } => {
+                let mut payloads = vec![
+                    PidPayload::Text,
+                    PidPayload::Int,
+                    PidPayload::Tuple {
+                        len: 1 + source.choose_index(MAX_PID_ITEMS),
+                    },
+                ];
+                if self.state.version >= Version::V3 {
+                    payloads.push(PidPayload::Bytes);
+                }
+                Pattern::PersistentId {
+                    payload: payloads[source.choose_index(payloads.len())],
+                }
+            }
+            // most shipped models are fitted
+            Pattern::SklearnEstimator { .. } => Pattern::SklearnEstimator {
+                estimator: source.choose_index(SKLEARN_ESTIMATORS.len()),
+                fitted: source.choose_index(4) != 0,
+            },
+            Pattern::ConstructorCall { .. } => {
+                let version = self.state.version;
+                let reconstructor = self.allowlisted(RECONSTRUCTOR.0, RECONSTRUCTOR.1);
+                let mut calls = Vec::new();
+                for (ty, &name) in LOADABLE_TYPES.iter().enumerate() {
+                    if !self.allowlisted("builtins", name) {
+                        continue;
+                    }
+                    calls.push((ty, Construction::Empty));
+                    if name == "bytearray" && version < Version::V3 {
+                        continue;
+                    }
+                    calls.push((ty, Construction::WithArg));
+                    if version >= Version::V2 {
+                        calls.push((ty, Construction::NewObj));
+                    }
+                    if reconstructor {
+                        calls.push((ty, Construction::Reconstructor));
+                    }
+                }
+                let (ty, construction) = calls[source.choose_index(calls.len())];
+                Pattern::ConstructorCall { ty, construction }
+            }
+            // most classes define no __getinitargs__
+            Pattern::LegacyInstance { .. } => Pattern::LegacyInstance {
+                args: if source.choose_index(4) == 0 {
+                    1 + source.choose_index(MAX_INIT_ARGS)
+                } else {
+                    0
+                },
+                attrs: source.choose_index(LEGACY_ATTRS.len() / 2 + 1),
+            },
+            Pattern::TangledMarks { .. } => {
+                let mut kinds = vec![SizedKind::List, SizedKind::Dict];
+                if self.state.version >= Version::V4 {
+                    kinds.push(SizedKind::Set);
+                }
+                let outer = kinds.remove(source.choose_index(kinds.len()));
+                Pattern::TangledMarks {
+                    outer,
+                    inner: kinds[source.choose_index(kinds.len())],
+                    items: 1 + source.choose_index(MAX_BATCH_ITEMS),
+                }
+            }
+            Pattern::MarkStress { .. } => {
+                let intensity = self.mark_stress.expect("mark stress is enabled");
+                Pattern::MarkStress {
+                    burst: StressBurst::plan(
+                        1 + source.choose_index(intensity),
+                        self.unsafe_marks,
+                        source,
+                    ),
+                }
+            }
+        })
+    }
+
+    /// emit every opcode of `pattern`.
+    fn emit_pattern(&mut self, pattern: Pattern, source: &mut GenerationSource) -> Result<()> {
+        let v0 = self.state.version < Version::V1;
+        match pattern {
+            Pattern::IndirectStackGlobal { module, name } => {
+                self.emit_indirect_stack_global(module, name, source)?;
+            }
+            Pattern::GlobalCall => self.emit_global_call(source)?,
+            Pattern::AppendsBatch { items } => {
+                self.emit_opcode(OpcodeKind::EmptyList);
+                self.emit_opcode(OpcodeKind::Mark);
+                for _ in 0..items {
+                    self.emit_one_of(SCALAR_OPCODES, source)?;
+                }
+                self.emit_opcode(OpcodeKind::Appends);
+            }
+            Pattern::DictOfReduces { pairs } => {
+                if !v0 {
+                    self.emit_opcode(OpcodeKind::EmptyDict);
+                }
+                self.emit_opcode(OpcodeKind::Mark);
+                for _ in 0..pairs {
+                    self.emit_one_of(KEY_OPCODES, source)?;
+                    self.emit_global_call(source)?;
+                }
+                self.emit_opcode(if v0 {
+                    OpcodeKind::Dict
+                } else {
+                    OpcodeKind::SetItems
+                });
+            }
+            Pattern::SetstateChain { states } => {
+                self.emit_global_call(source)?;
+                for _ in 0..states {
+                    if v0 {
+                        self.emit_opcode(OpcodeKind::Mark);
+                    } else {
+                        self.emit_opcode(OpcodeKind::EmptyDict);
+                    }
+                    self.emit_one_of(KEY_OPCODES, source)?;
+                    self.emit_one_of(SCALAR_OPCODES, source)?;
+                    self.emit_opcode(if v0 {
+                        OpcodeKind::Dict
+                    } else {
+                        OpcodeKind::SetItem
+                    });
+                    self.emit_opcode(OpcodeKind::Build);
+                }
+            }
+            Pattern::SharedObject { copies } => self.emit_shared_object(copies, source)?,
+            Pattern::RecursiveContainer { kind, items, at } => {
+                self.emit_recursive_container(kind, items, at, source)?;
+            }
+            Pattern::SizedContainer { kind, len, batch } => {
+                self.emit_sized_container(kind, len, batch, source)?;
+            }
+            Pattern::Ndarray { dtype, shape } => self.emit_ndarray(dtype, shape, source),
+            Pattern::TorchTensor { storage, shape } => {
+                self.emit_torch_tensor(TORCH_STORAGES[storage], shape, source);
+            }
+            Pattern::PersistentId { payload } => self.emit_persistent_id(payload, source),
+            Pattern::SklearnEstimator { estimator, fitted } => {
+                self.emit_sklearn_estimator(&SKLEARN_ESTIMATORS[estimator], fitted, source);
+            }
+            Pattern::ConstructorCall { ty, construction } => {
+                self.emit_constructor_call(LOADABLE_TYPES[ty], construction, source);
+            }
+            Pattern::LegacyInstance { args, attrs } => {
+                self.emit_legacy_instance(args, attrs, source)?;
+            }
+            Pattern::TangledMarks {
+                outer,
+                inner,
+                items,
+            } => {
+                for kind in [outer, inner] {
+                    self.emit_opcode(kind.opcodes().0);
+                    self.emit_opcode(OpcodeKind::Mark);
+                    for _ in 0..items {
+                        self.emit_sized_item(kind, source)?;
+                    }
+                }
+                // outer first: each batch opcode closes the other's MARK
+                self.emit_opcode(outer.opcodes().1);
+                self.emit_opcode(inner.opcodes().1);
+            }
+            Pattern::MarkStress { burst } => {
+                for &op in burst.ops() {
+                    match op {
+                        StressOp::Mark => self.emit_opcode(OpcodeKind::Mark),
+                        StressOp::Push => self.emit_one_of(SCALAR_OPCODES, source)?,
+                        StressOp::Dup => self.emit_opcode(OpcodeKind::Dup),
+                        StressOp::Pop => self.emit_opcode(OpcodeKind::Pop),
+                        StressOp::PopMark => self.emit_opcode(OpcodeKind::PopMark),
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// emit an instance of a random class the way Python 2's `save_inst`
+    /// pickles an old-style class instance: created from `args` init
+    /// arguments with INST or OBJ, then given a `__dict__` of `attrs`
+    /// entries with BUILD. the class, instance, dict, and keys are memoized,
+    /// as Python 2 memoizes every object it saves.
+    fn emit_legacy_instance(
+        &mut self,
+        args: usize,
+        attrs: usize,
+        source: &mut GenerationSource,
+    ) -> Result<()> {
+        let v0 = self.state.version < Version::V1;
+        let class = self.get_random_module(source)?;
+        self.emit_opcode(OpcodeKind::Mark);
+        if !v0 {
+            self.emit_arg(OpcodeKind::Global, class.as_bytes());
+            self.emit_memo_put(source);
+        }
+        for _ in 0..args {
+            let value = self.legacy_value(source);
+            self.emit_param(value);
+        }
+        if v0 {
+            self.emit_arg(OpcodeKind::Inst, class.as_bytes());
+        } else {
+            self.emit_opcode(OpcodeKind::Obj);
+        }
+        self.emit_memo_put(source);
+
+        if v0 {
+            self.emit_opcode(OpcodeKind::Mark);
+            self.emit_opcode(OpcodeKind::Dict);
+        } else {
+            self.emit_opcode(OpcodeKind::EmptyDict);
+        }
+        self.emit_memo_put(source);
+        let batched = !v0 && attrs > 1;
+        if batched {
+            self.emit_opcode(OpcodeKind::Mark);
+        }
+        // consecutive names, so the keys are distinct
+        let first = source.with_values(|source| source.choose_index(LEGACY_ATTRS.len()));
+        for i in 0..attrs {
+            let key = LEGACY_ATTRS[(first + i) % LEGACY_ATTRS.len()];
+            if v0 {
+                self.emit_arg(OpcodeKind::String, format!("'{key}'\n").as_bytes());
+            } else {
+                self.emit_arg(OpcodeKind::ShortBinString, key.as_bytes());
+            }
+            self.emit_memo_put(source);
+            let value = self.legacy_value(source);
+            self.emit_param(value);
+            if !batched {
+                self.emit_opcode(OpcodeKind::SetItem);
+            }
+        }
+        if batched {
+            self.emit_opcode(OpcodeKind::SetItems);
+        }
+        self.emit_opcode(OpcodeKind::Build);
+        Ok(())
+    }
+
+    /// draw an init argument or attribute value of a legacy instance: an int,
+    /// float, bool, or None.
+    fn legacy_value(&self, source: &mut GenerationSource) -> Param {
+        source.with_values(|source| match source.choose_index(4) {
+            0 => Param::Int(source.choose_index(1 << 16) as i32),
+            1 => Param::Float(source.gen_f64()),
+            2 => Param::Bool(source.gen_bool()),
+            _ => Param::None,
+        })
+    }
+
+    /// call the builtin type `ty` the way `construction` says.
+    fn emit_constructor_call(
+        &mut self,
+        ty: &str,
+        construction: Construction,
+        source: &mut GenerationSource,
+    ) {
+        let len = match construction {
+            Construction::Empty => 0,
+            Construction::WithArg | Construction::NewObj => 1,
+            Construction::Reconstructor => 3,
+        };
+        if construction == Construction::Reconstructor {
+            self.emit_named_global(RECONSTRUCTOR.0, RECONSTRUCTOR.1);
+            self.open_tuple(len);
+            self.emit_named_global("builtins", ty);
+            self.emit_named_global("builtins", ty);
+        } else {
+            self.emit_named_global("builtins", ty);
+            self.open_tuple(len);
+        }
+        if construction != Construction::Empty {
+            self.emit_constructor_arg(ty, source);
+        }
+        self.close_tuple(len);
+        self.emit_opcode(if construction == Construction::NewObj {
+            OpcodeKind::NewObj
+        } else {
+            OpcodeKind::Reduce
+        });
+    }
+
+    /// push an argument the builtin type `ty` is constructed from.
+    fn emit_constructor_arg(&mut self, ty: &str, source: &mut GenerationSource) {
+        let small_int = |source: &mut GenerationSource| source.choose_index(256) as i32;
+        match ty {
+            "dict" => {
+                let v0 = self.state.version < Version::V1;
+                self.emit_opcode(if v0 {
+                    OpcodeKind::Mark
+                } else {
+                    OpcodeKind::EmptyDict
+                });
+                self.emit_small_int(small_int(source));
+                self.emit_small_int(small_int(source));
+                self.emit_opcode(if v0 {
+                    OpcodeKind::Dict
+                } else {
+                    OpcodeKind::SetItem
+                });
+            }
+            "complex" => self.emit_small_int(small_int(source)),
+            "bytearray" => {
+                let bytes: Vec<u8> = (0..source.choose_index(8))
+                    .map(|_| source.gen_u8())
+                    .collect();
+                self.emit_bytes_literal(&bytes);
+            }
+            _ => {
+                self.open_tuple(2);
+                self.emit_small_int(small_int(source));
+                self.emit_small_int(small_int(source));
+                self.close_tuple(2);
+            }
+        }
+    }
+
+    /// emit a `kind` container of `len` items the way CPython's C pickler
+    /// batches them, in batches of up to `batch` items.
+    fn emit_sized_container(
+        &mut self,
+        kind: SizedKind,
+        len: usize,
+        batch: usize,
+        source: &mut GenerationSource,
+    ) -> Result<()> {
+        let (empty, batch_opcode, single) = kind.opcodes();
+        let v0 = self.state.version < Version::V1;
+        if v0 {
+            self.emit_opcode(OpcodeKind::Mark);
+            self.emit_opcode(if kind == SizedKind::Dict {
+                OpcodeKind::Dict
+            } else {
+                OpcodeKind::List
+            });
+        } else {
+            self.emit_opcode(empty);
+        }
+
+        if v0 || (len == 1 && kind.has_single()) {
+            for _ in 0..len {
+                self.emit_sized_item(kind, source)?;
+                self.emit_opcode(single);
+            }
+            return Ok(());
+        }
+        let mut left = len;
+        while left > 0 {
+            let items = left.min(batch);
+            self.emit_opcode(OpcodeKind::Mark);
+            for _ in 0..items {
+                self.emit_sized_item(kind, source)?;
+            }
+            self.emit_opcode(batch_opcode);
+            left -= items;
+        }
+        if kind.trailing_batch(len, batch) {
+            self.emit_opcode(OpcodeKind::Mark);
+            self.emit_opcode(batch_opcode);
+        }
+        Ok(())
+    }
+
+    /// emit one item (or key and value) of a `kind` container.
+    fn emit_sized_item(&mut self, kind: SizedKind, source: &mut GenerationSource) -> Result<()> {
+        match kind {
+            SizedKind::List => self.emit_one_of(SCALAR_OPCODES, source),
+            SizedKind::Dict => {
+                self.emit_one_of(KEY_OPCODES, source)?;
+                self.emit_one_of(SCALAR_OPCODES, source)
+            }
+            SizedKind::Set => self.emit_one_of(KEY_OPCODES, source),
+        }
+    }
+
+    /// emit a list holding a memoized one-item list, followed by `copies`
+    /// one-item tuples that fetch the same list from the memo.
+    fn emit_shared_object(&mut self, copies: usize, source: &mut GenerationSource) -> Result<()> {
+        let v0 = self.state.version < Version::V1;
+        if !v0 {
+            self.emit_opcode(OpcodeKind::EmptyList);
+        }
+        self.emit_opcode(OpcodeKind::Mark);
+
+        // first appearance, written in full and memoized right after creation
+        if v0 {
+            self.emit_opcode(OpcodeKind::Mark);
+            self.emit_opcode(OpcodeKind::List);
+        } else {
+            self.emit_opcode(OpcodeKind::EmptyList);
+        }
+        let index = self.emit_memo_put(source);
+        self.emit_one_of(SCALAR_OPCODES, source)?;
+        self.emit_opcode(OpcodeKind::Append);
+
+        for _ in 0..copies {
+            if self.state.version < Version::V2 {
+                self.emit_opcode(OpcodeKind::Mark);
+                self.emit_memo_get(index);
+                self.emit_opcode(OpcodeKind::Tuple);
+            } else {
+                self.emit_memo_get(index);
+                self.emit_opcode(OpcodeKind::Tuple1);
+            }
+        }
+        self.emit_opcode(if v0 {
+            OpcodeKind::List
+        } else {
+            OpcodeKind::Appends
+        });
+        Ok(())
+    }
+
+    /// emit a memoized list or dict of `items` items whose item (or value)
+    /// `at` is a memo GET of the container itself, filled the way CPython
+    /// fills it: one APPEND or SETITEM for a lone item (and below protocol 1
+    /// for every item), else a MARK ... APPENDS or SETITEMS batch.
+    fn emit_recursive_container(
+        &mut self,
+        kind: SizedKind,
+        items: usize,
+        at: usize,
+        source: &mut GenerationSource,
+    ) -> Result<()> {
+        let v0 = self.state.version < Version::V1;
+        let (empty, batch_opcode, single) = kind.opcodes();
+        if v0 {
+            self.emit_opcode(OpcodeKind::Mark);
+            self.emit_opcode(if kind == SizedKind::Dict {
+                OpcodeKind::Dict
+            } else {
+                OpcodeKind::List
+            });
+        } else {
+            self.emit_opcode(empty);
+        }
+        let index = self.emit_memo_put(source);
+
+        let batch = !v0 && items > 1;
+        if batch {
+            self.emit_opcode(OpcodeKind::Mark);
+        }
+        for item in 0..items {
+            if kind == SizedKind::Dict {
+                self.emit_one_of(KEY_OPCODES, source)?;
+            }
+            if item == at {
+                self.emit_memo_get(index);
+            } else {
+                self.emit_one_of(SCALAR_OPCODES, source)?;
+            }
+            if !batch {
+                self.emit_opcode(single);
+            }
+        }
+        if batch {
+            self.emit_opcode(batch_opcode);
+        }
+        Ok(())
+    }
+
+    /// emit a `shape` array of `dtype` elements the way numpy's
+    /// `ndarray.__reduce__` pickles it.
+    fn emit_ndarray(&mut self, dtype: Dtype, shape: Shape, source: &mut GenerationSource) {
+        // numpy 2 moved the module, and writes the new name
+        let module = if source.gen_bool() {
+            "numpy.core.multiarray"
+        } else {
+            "numpy._core.multiarray"
+        };
+        self.emit_named_global(module, "_reconstruct");
+        self.open_tuple(3);
+        self.emit_named_global("numpy", "ndarray");
+        self.open_tuple(1);
+        self.emit_small_int(0);
+        self.close_tuple(1);
+        self.emit_bytes_literal(b"b");
+        self.close_tuple(3);
+        self.emit_opcode(OpcodeKind::Reduce);
+
+        let ndim = shape.dims().len();
+        self.open_tuple(5);
+        self.emit_small_int(1);
+        self.open_tuple(ndim);
+        for &len in shape.dims() {
+            self.emit_small_int(len as i32);
+        }
+        self.close_tuple(ndim);
+        self.emit_dtype(dtype, source);
+        // only arrays of two or more dimensions differ in Fortran order
+        self.emit_bool(ndim > 1 && source.with_values(|source| source.choose_index(4)) == 0);
+        let mut data = source.gen_bytes(shape.elements() * dtype.itemsize());
+        if dtype == Dtype::Bool {
+            data.iter_mut().for_each(|byte| *byte &= 1);
+        }
+        self.emit_bytes_literal(&data);
+        self.close_tuple(5);
+        self.emit_opcode(OpcodeKind::Build);
+    }
+
+    /// emit `payload` as a persistent id for BINPERSID, then BINPERSID.
+    fn emit_persistent_id(&mut self, payload: PidPayload, source: &mut GenerationSource) {
+        let emit_int = |generator: &mut Generator, source: &mut GenerationSource| {
+            let value = source.with_values(|source| source.choose_index(1 << 16));
+            generator.emit_small_int(value as i32);
+        };
+        let text = |source: &mut GenerationSource| {
+            format!("pid_{}", source.with_values(|source| source.gen_u32()))
+        };
+        match payload {
+            PidPayload::Text => self.emit_text(&text(source)),
+            PidPayload::Int => emit_int(self, source),
+            PidPayload::Bytes => {
+                let bytes = source.with_values(|source| {
+                    let len = source.choose_index(MAX_PID_BYTES + 1);
+                    source.gen_bytes(len)
+                });
+                self.emit_bytes_literal(&bytes);
+            }
+            PidPayload::Tuple { len } => {
+                self.open_tuple(len);
+                for item in 0..len {
+                    if item % 2 == 0 {
+                        self.emit_text(&text(source));
+                    } else {
+                        emit_int(self, source);
+                    }
+                }
+                self.close_tuple(len);
+            }
+        }
+        self.emit_opcode(OpcodeKind::BinPersID);
+    }
+
+    /// emit a `shape` tensor the way `torch.save` pickles one: a call of
+    /// `_rebuild_tensor_v2` on the persistent id of a `storage` typed storage.
+    fn emit_torch_tensor(&mut self, storage: &str, shape: Shape, source: &mut GenerationSource) {
+        self.emit_named_global("torch._utils", "_rebuild_tensor_v2");
+        self.open_tuple(6);
+
+        // views start past the head of a storage they share
+        let elements = shape.elements();
+        // the offset decides how wide an int pushes it, so it is structure
+        let offset = if source.choose_index(4) == 0 {
+            source.choose_index(elements + 1)
+        } else {
+            0
+        };
+        self.open_tuple(5);
+        self.emit_text("storage");
+        self.emit_named_global("torch", storage);
+        let key = source.with_values(|source| source.choose_index(MAX_STORAGE_KEYS));
+        self.emit_text(&key.to_string());
+        self.emit_text(
+            if source.with_values(|source| source.choose_index(4)) == 0 {
+                "cuda:0"
+            } else {
+                "cpu"
+            },
+        );
+        self.emit_small_int((offset + elements) as i32);
+        self.close_tuple(5);
+        self.emit_opcode(OpcodeKind::BinPersID);
+        self.emit_small_int(offset as i32);
+
+        let dims = shape.dims();
+        self.open_tuple(dims.len());
+        for &len in dims {
+            self.emit_small_int(len as i32);
+        }
+        self.close_tuple(dims.len());
+        // contiguous strides: each dimension steps over all the later ones
+        let mut strides = vec![1; dims.len()];
+        for i in (1..dims.len()).rev() {
+            strides[i - 1] = strides[i] * dims[i].max(1);
+        }
+        self.open_tuple(dims.len());
+        for stride in strides {
+            self.emit_small_int(stride as i32);
+        }
+        self.close_tuple(dims.len());
+
+        // state dicts hold detached tensors
+        self.emit_bool(source.with_values(|source| source.choose_index(8)) == 0);
+        self.emit_named_global("collections", "OrderedDict");
+        self.open_tuple(0);
+        self.close_tuple(0);
+        self.emit_opcode(OpcodeKind::Reduce);
+        self.close_tuple(6);
+        self.emit_opcode(OpcodeKind::Reduce);
+    }
+
+    /// emit `estimator` the way `joblib.dump` pickles it: an instance built
+    /// without `__init__`, then the BUILD of its `__dict__`, with array
+    /// attributes when `fitted`.
+    fn emit_sklearn_estimator(
+        &mut self,
+        estimator: &Estimator,
+        fitted: bool,
+        source: &mut GenerationSource,
+    ) {
+        self.emit_instance(estimator.module, estimator.name);
+        self.open_dict();
+        for &(key, param) in estimator.params {
+            self.emit_text(key);
+            self.emit_param(param);
+        }
+        if fitted {
+            let features =
+                1 + source.with_values(|source| source.choose_index(MAX_ESTIMATOR_FEATURES));
+            self.emit_text("n_features_in_");
+            self.emit_small_int(features as i32);
+            for &(key, ndim) in estimator.arrays {
+                // 1-d attributes run over the features, 2-d ones have a row
+                // per class, target, or component
+                let rows =
+                    1 + source.with_values(|source| source.choose_index(MAX_ESTIMATOR_FEATURES));
+                let shape = match ndim {
+                    1 => Shape::new(&[features as u32]),
+                    _ => Shape::new(&[rows as u32, features as u32]),
+                };
+                self.emit_text(key);
+                self.emit_array_wrapper(shape, source);
+            }
+        }
+        self.emit_text("_sklearn_version");
+        let version = source.with_values(|source| source.choose_index(SKLEARN_VERSIONS.len()));
+        self.emit_text(SKLEARN_VERSIONS[version]);
+        self.close_dict();
+        self.emit_opcode(OpcodeKind::Build);
+    }
+
+    /// emit the `NumpyArrayWrapper` joblib pickles in place of a `shape`
+    /// array, whose bytes it writes after the pickled wrapper.
+    fn emit_array_wrapper(&mut self, shape: Shape, source: &mut GenerationSource) {
+        self.emit_instance("joblib.numpy_pickle", "NumpyArrayWrapper");
+        self.open_dict();
+        self.emit_text("subclass");
+        self.emit_named_global("numpy", "ndarray");
+        self.emit_text("shape");
+        let ndim = shape.dims().len();
+        self.open_tuple(ndim);
+        for &len in shape.dims() {
+            self.emit_small_int(len as i32);
+        }
+        self.close_tuple(ndim);
+        self.emit_text("order");
+        self.emit_text(
+            if ndim > 1 && source.with_values(|source| source.choose_index(4)) == 0 {
+                "F"
+            } else {
+                "C"
+            },
+        );
+        self.emit_text("dtype");
+        let dtype = source.with_values(|source| source.choose_index(ESTIMATOR_DTYPES.len()));
+        self.emit_dtype(ESTIMATOR_DTYPES[dtype], source);
+        self.emit_text("allow_mmap");
+        self.emit_bool(true);
+        self.emit_text("numpy_array_alignment_bytes");
+        self.emit_small_int(16);
+        self.close_dict();
+        self.emit_opcode(OpcodeKind::Build);
+    }
+
+    /// push an instance of `module.name` without calling `__init__`, as
+    /// `object.__reduce_ex__` does: NEWOBJ on an empty tuple on protocol 2+,
+    /// else `copy_reg._reconstructor(cls, object, None)`.
+    fn emit_instance(&mut self, module: &str, name: &str) {
+        if self.state.version >= Version::V2 {
+            self.emit_named_global(module, name);
+            self.open_tuple(0);
+            self.close_tuple(0);
+            self.emit_opcode(OpcodeKind::NewObj);
+            return;
+        }
+        self.emit_named_global("copy_reg", "_reconstructor");
+        self.open_tuple(3);
+        self.emit_named_global(module, name);
+        self.emit_named_global("__builtin__", "object");
+        self.emit_opcode(OpcodeKind::None);
+        self.close_tuple(3);
+        self.emit_opcode(OpcodeKind::Reduce);
+    }
+
+    /// start a dict of key/value pairs: MARK below protocol 1, else
+    /// EMPTY_DICT and MARK.
+    fn open_dict(&mut self) {
+        if self.state.version >= Version::V1 {
+            self.emit_opcode(OpcodeKind::EmptyDict);
+        }
+        self.emit_opcode(OpcodeKind::Mark);
+    }
+
+    /// finish a dict after `open_dict` with DICT or SETITEMS.
+    fn close_dict(&mut self) {
+        self.emit_opcode(if self.state.version >= Version::V1 {
+            OpcodeKind::SetItems
+        } else {
+            OpcodeKind::Dict
+        });
+    }
+
+    /// push a hyperparameter's default value.
+    fn emit_param(&mut self, param: Param) {
+        match param {
+            Param::Bool(flag) => self.emit_bool(flag),
+            Param::Int(value) => self.emit_small_int(value),
+            Param::Float(value) => self.emit_float(value),
+            Param::Text(text) => self.emit_text(text),
+            Param::None => self.emit_opcode(OpcodeKind::None),
+        }
+    }
+
+    /// push `value` with FLOAT below protocol 1, otherwise BINFLOAT.
+    fn emit_float(&mut self, value: f64) {
+        if self.state.version < Version::V1 {
+            self.emit_arg(OpcodeKind::Float, format!("{value:?}\n").as_bytes());
+        } else {
+            self.emit_arg(OpcodeKind::BinFloat, &value.to_be_bytes());
+        }
+    }
+
+    /// emit `numpy.dtype(code, False, True)` and the BUILD of its state,
+    /// `(3, byteorder, None, None, None, -1, -1, 0)`.
+    fn emit_dtype(&mut self, dtype: Dtype, source: &mut GenerationSource) {
+        self.emit_named_global("numpy", "dtype");
+        self.open_tuple(3);
+        self.emit_text(dtype.code());
+        self.emit_bool(false);
+        self.emit_bool(true);
+        self.close_tuple(3);
+        self.emit_opcode(OpcodeKind::Reduce);
+
+        // one-byte types have no byte order; the rest are mostly little-endian
+        let byteorder = match dtype.itemsize() {
+            1 => "|",
+            _ if source.with_values(|source| source.choose_index(4)) == 0 => ">",
+            _ => "<",
+        };
+        self.open_tuple(8);
+        self.emit_small_int(3);
+        self.emit_text(byteorder);
+        for _ in 0..3 {
+            self.emit_opcode(OpcodeKind::None);
+        }
+        self.emit_small_int(-1);
+        self.emit_small_int(-1);
+        self.emit_small_int(0);
+        self.close_tuple(8);
+        self.emit_opcode(OpcodeKind::Build);
+    }
+
+    /// push `module.name` with GLOBAL, or with two strings and STACK_GLOBAL on
+    /// protocol 4+.
+    fn emit_named_global(&mut self, module: &str, name: &str) {
+        if self.state.version >= Version::V4 {
+            self.emit_text(module);
+            self.emit_text(name);
+            self.emit_opcode(OpcodeKind::StackGlobal);
+        } else {
+            self.emit_arg(OpcodeKind::Global, format!("{module}\n{name}\n").as_bytes());
+        }
+    }
+
+    /// the MARK in front of a `len` item tuple, if the protocol needs one.
+    fn open_tuple(&mut self, len: usize) {
+        if !has_short_tuple(self.state.version, len) {
+            self.emit_opcode(OpcodeKind::Mark);
+        }
+    }
+
+    /// build a tuple of the top `len` items, after `open_tuple`.
+    fn close_tuple(&mut self, len: usize) {
+        let opcode = match len {
+            _ if !has_short_tuple(self.state.version, len) => OpcodeKind::Tuple,
+            0 => OpcodeKind::EmptyTuple,
+            1 => OpcodeKind::Tuple1,
+            2 => OpcodeKind::Tuple2,
+            _ => OpcodeKind::Tuple3,
+        };
+        self.emit_opcode(opcode);
+    }
+
+    /// push `value` with INT below protocol 1, otherwise the shortest of
+    /// BININT1, BININT2, and BININT.
+    fn emit_small_int(&mut self, value: i32) {
+        if self.state.version < Version::V1 {
+            self.emit_arg(OpcodeKind::Int, format!("{value}\n").as_bytes());
+            return;
+        }
+        match value {
+            0..=0xff => self.emit_arg(OpcodeKind::BinInt1, &[value as u8]),
+            0x100..=0xffff => self.emit_arg(OpcodeKind::BinInt2, &(value as u16).to_le_bytes()),
+            _ => self.emit_arg(OpcodeKind::BinInt, &value.to_le_bytes()),
+        }
+    }
+
+    /// push `flag` with NEWTRUE or NEWFALSE, or as `INT 01`/`INT 00` below
+    /// protocol 2.
+    fn emit_bool(&mut self, flag: bool) {
+        match (self.state.version >= Version::V2, flag) {
+            (true, true) => self.emit_opcode(OpcodeKind::NewTrue),
+            (true, false) => self.emit_opcode(OpcodeKind::NewFalse),
+            (false, true) => self.emit_arg(OpcodeKind::Int, b"01\n"),
+            (false, false) => self.emit_arg(OpcodeKind::Int, b"00\n"),
+        }
+    }
+
+    /// push `text` with the opcode CPython would: UNICODE below protocol 1,
+    /// SHORT_BINUNICODE on protocol 4+ when it fits, otherwise BINUNICODE.
+    fn emit_text(&mut self, text: &str) {
+        if self.state.version < Version::V1 {
+            self.emit_arg(OpcodeKind::Unicode, &raw_unicode_escape(text));
+        } else if self.state.version >= Version::V4 && text.len() <= u8::MAX as usize {
+            self.emit_arg(OpcodeKind::ShortBinUnicode, text.as_bytes());
+        } else {
+            self.emit_arg(OpcodeKind::BinUnicode, text.as_bytes());
+        }
+    }
+
+    /// push `bytes` with SHORT_BINBYTES or BINBYTES on protocol 3+, and below
+    /// it with the `_codecs.encode(text, 'latin1')` or `bytes()` call CPython
+    /// reduces bytes to.
+    fn emit_bytes_literal(&mut self, bytes: &[u8]) {
+        if self.state.version >= Version::V3 {
+            let opcode = if bytes.len() <= u8::MAX as usize {
+                OpcodeKind::ShortBinBytes
+            } else {
+                OpcodeKind::BinBytes
+            };
+            self.emit_arg(opcode, bytes);
+            return;
+        }
+        if bytes.is_empty() {
+            self.emit_named_global("__builtin__", "bytes");
+            self.open_tuple(0);
+            self.close_tuple(0);
+        } else {
+            self.emit_named_global("_codecs", "encode");
+            self.open_tuple(2);
+            let text: String = bytes.iter().map(|&byte| char::from(byte)).collect();
+            self.emit_text(&text);
+            self.emit_text("latin1");
+            self.close_tuple(2);
+        }
+        self.emit_opcode(OpcodeKind::Reduce);
+    }
+
+    /// write `opcode` with `arg` encoded after it, and simulate it.
+    fn emit_arg(&mut self, opcode: OpcodeKind, arg: &[u8]) {
+        let encoded = encode_arg(opcode, arg).expect("pattern arguments fit their opcode");
+        self.output.push(opcode.as_u8());
+        self.output.extend_from_slice(&encoded);
+        self.process_stack_ops(opcode, Some(arg));
+    }
+
+    /// call a random stdlib global with no arguments.
+    fn emit_global_call(&mut self, source: &mut GenerationSource) -> Result<()> {
+        self.emit_global(source)?;
+        if self.state.version < Version::V1 {
+            self.emit_opcode(OpcodeKind::Mark);
+            self.emit_opcode(OpcodeKind::Tuple);
+        } else {
+            self.emit_opcode(OpcodeKind::EmptyTuple);
+        }
+        self.emit_opcode(OpcodeKind::Reduce);
+        Ok(())
+    }
+
+    /// emit a random value with one of the `choices` the protocol has.
+    fn emit_one_of(&mut self, choices: &[OpcodeKind], source: &mut GenerationSource) -> Result<()> {
+        let protocol_opcodes = PICKLE_OPCODES[&(self.state.version as u8)];
+        let available: Vec<OpcodeKind> = choices
+            .iter()
+            .copied()
+            .filter(|opcode| protocol_opcodes.contains(opcode))
+            .collect();
+        let opcode = available[source.choose_index(available.len())];
+        self.emit_and_process(opcode, source)?;
+        Ok(())
+    }
+
+    /// emit STACK_GLOBAL for a random stdlib global, its module and name
+    /// pushed the way `module_source` and `name_source` say.
+    fn emit_indirect_stack_global(
+        &mut self,
+        module_source: NameSource,
+        name_source: NameSource,
+        source: &mut GenerationSource,
+    ) -> Result<()> {
+        let global = self.get_random_module(source)?;
+        let (module, name) = global
+            .trim_end()
+            .split_once('\n')
+            .expect("get_random_module returns two lines");
+
+        // memo setups go first, so the GETs end up right before STACK_GLOBAL
+        let module_index =
+            (module_source == NameSource::Memo).then(|| self.emit_memoized_string(module, source));
+        let name_index =
+            (name_source == NameSource::Memo).then(|| self.emit_memoized_string(name, source));
+
+        self.emit_name(module_source, module, module_index, source);
+        self.emit_name(name_source, name, name_index, source);
+        self.emit_opcode(OpcodeKind::StackGlobal);
+        Ok(())
+    }
+
+    /// push `text` the way `name_source` says; `memo_index` is where a
+    /// `NameSource::Memo` string was stored.
+    fn emit_name(
+        &mut self,
+        name_source: NameSource,
+        text: &str,
+        memo_index: Option<usize>,
+        source: &mut GenerationSource,
+    ) {
+        match name_source {
+            NameSource::Memo => {
+                let index = memo_index.expect("memo strings are stored first");
+                self.emit_memo_get(index);
+            }
+            NameSource::Escaped => {
+                let mut arg_bytes = escape_all(text).into_bytes();
+                arg_bytes.push(b'\n');
+                self.output.push(OpcodeKind::Unicode.as_u8());
+                self.output.extend_from_slice(&arg_bytes);
+                self.process_stack_ops(OpcodeKind::Unicode, Some(&arg_bytes));
+            }
+            NameSource::Doubled => {
+                self.emit_unicode_literal(text, source);
+                self.emit_opcode(OpcodeKind::Dup);
+                self.emit_opcode(OpcodeKind::Pop);
+            }
+        }
+    }
+
+    /// push `text`, store it in a free memo slot, and pop it again.
+    ///
+    /// # Returns
+    /// the memo index `text` was stored at.
+    fn emit_memoized_string(&mut self, text: &str, source: &mut GenerationSource) -> usize {
+        self.emit_unicode_literal(text, source);
+        let index = self.emit_memo_put(source);
+        self.emit_opcode(OpcodeKind::Pop);
+        index
+    }
+
+    /// store the top of the stack in a free memo slot with the protocol's
+    /// PUT or, on protocol 4+, sometimes MEMOIZE.
+    ///
+    /// # Returns
+    /// the memo index the object was stored at.
+    fn emit_memo_put(&mut self, source: &mut GenerationSource) -> usize {
+        // MEMOIZE stores at len(memo), which earlier sparse PUTs may have taken
+        let next = self.state.memo.len();
+        if self.state.version >= Version::V4
+            && !self.state.memo.contains_key(&next)
+            && source.gen_bool()
+        {
+            self.emit_opcode(OpcodeKind::Memoize);
+            return next;
+        }
+
+        let index = (next..)
+            .find(|index| !self.state.memo.contains_key(index))
+            .expect("the memo has a free slot");
+        if self.state.version < Version::V1 {
+            let arg_bytes = format!("{index}\n").into_bytes();
+            self.output.push(OpcodeKind::Put.as_u8());
+            self.output.extend_from_slice(&arg_bytes);
+            self.process_stack_ops(OpcodeKind::Put, Some(&arg_bytes));
+            return index;
+        }
+        match u8::try_from(index) {
+            Ok(byte) => {
+                self.output
+                    .extend_from_slice(&[OpcodeKind::BinPut.as_u8(), byte]);
+                self.process_stack_ops(OpcodeKind::BinPut, Some(&[byte]));
+            }
+            Err(_) => {
+                let arg_bytes = (index as u32).to_le_bytes();
+                self.output.push(OpcodeKind::LongBinPut.as_u8());
+                self.output.extend_from_slice(&arg_bytes);
+                self.process_stack_ops(OpcodeKind::LongBinPut, Some(&arg_bytes));
+            }
+        }
+        index
+    }
+
+    /// push the memo entry at `index` with GET below protocol 1, otherwise
+    /// BINGET or LONG_BINGET.
+    fn emit_memo_get(&mut self, index: usize) {
+        if self.state.version < Version::V1 {
+            let arg_bytes = format!("{index}\n").into_bytes();
+            self.output.push(OpcodeKind::Get.as_u8());
+            self.output.extend_from_slice(&arg_bytes);
+            self.process_stack_ops(OpcodeKind::Get, Some(&arg_bytes));
+            return;
+        }
+        match u8::try_from(index) {
+            Ok(byte) => {
+                self.output
+                    .extend_from_slice(&[OpcodeKind::BinGet.as_u8(), byte]);
+                self.process_stack_ops(OpcodeKind::BinGet, Some(&[byte]));
+            }
+            Err(_) => {
+                let arg_bytes = (index as u32).to_le_bytes();
+                self.output.push(OpcodeKind::LongBinGet.as_u8());
+                self.output.extend_from_slice(&arg_bytes);
+                self.process_stack_ops(OpcodeKind::LongBinGet, Some(&arg_bytes));
+            }
+        }
+    }
+
+    /// push `text` with a random protocol 4 unicode opcode.
+    fn emit_unicode_literal(&mut self, text: &str, source: &mut GenerationSource) {
+        let bytes = text.as_bytes();
+        let opcode = match source.choose_index(3) {
+            0 if bytes.len() <= u8::MAX as usize => OpcodeKind::ShortBinUnicode,
+            1 => OpcodeKind::BinUnicode8,
+            _ => OpcodeKind::BinUnicode,
+        };
+        self.output.push(opcode.as_u8());
+        match opcode {
+            OpcodeKind::ShortBinUnicode => self.output.push(bytes.len() as u8),
+            OpcodeKind::BinUnicode8 => self
+                .output
+                .extend_from_slice(&(bytes.len() as u64).to_le_bytes()),
+            _ => self
+                .output
+                .extend_from_slice(&(bytes.len() as u32).to_le_bytes()),
+        }
+        self.output.extend_from_slice(bytes);
+        self.process_stack_ops(opcode, Some(bytes));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disasm::{disassemble, validate, Argument};
+    use crate::generator::{SizeDistribution, MAX_STRESS_OPS};
+    use crate::stack::StackObject;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use std::rc::Rc;
+
+    #[test]
+    fn escape_all_spells_every_character() {
+        assert_eq!(escape_all("os"), "\\u006f\\u0073");
+        assert_eq!(escape_all("\u{1f600}"), "\\U0001f600");
+    }
+
+    /// run `emit_pattern` on a fresh, strictly checked generator and return
+    /// its disassembled output.
+    fn emit_alone(version: Version, pattern: Pattern) -> Vec<crate::disasm::Instruction> {
+        let mut generator = Generator::new(version)
+            .with_persistent_id_opcodes(matches!(
+                pattern,
+                Pattern::TorchTensor { .. } | Pattern::PersistentId { .. }
+            ))
+            .with_strict_checks(true);
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        let mut source = GenerationSource::Rand(&mut rng);
+
+        generator.emit_proto(&mut source);
+        generator.emit_pattern(pattern, &mut source).unwrap();
+        generator.take_strict_violation().unwrap();
+        assert_eq!(generator.state.stack.len(), 1, "{pattern:?}");
+
+        generator.emit_opcode(OpcodeKind::Stop);
+        validate(&generator.output).unwrap();
+        let instructions = disassemble(&generator.output).unwrap();
+        let proto = usize::from(version >= Version::V2);
+        assert_eq!(
+            instructions.len(),
+            proto + pattern.opcode_count(version) + 1,
+            "{version:?} {pattern:?}"
+        );
+        instructions
+    }
+
+    #[test]
+    fn every_name_source_builds_the_global() {
+        for module in NameSource::ALL {
+            for name in NameSource::ALL {
+                let pattern = Pattern::IndirectStackGlobal { module, name };
+                let instructions = emit_alone(Version::V4, pattern);
+
+                // the opcode right before STACK_GLOBAL is never a binary unicode literal
+                let before = &instructions[instructions.len() - 3];
+                assert!(
+                    !matches!(before.arg, Argument::Str(_)) || before.name == "UNICODE",
+                    "{pattern:?}: {}",
+                    before.name
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn interesting_patterns_are_valid_for_every_protocol() {
+        let patterns = [
+            Pattern::GlobalCall,
+            Pattern::AppendsBatch { items: 1 },
+            Pattern::AppendsBatch {
+                items: MAX_BATCH_ITEMS,
+            },
+            Pattern::DictOfReduces { pairs: 1 },
+            Pattern::DictOfReduces {
+                pairs: MAX_DICT_PAIRS,
+            },
+            Pattern::SetstateChain {
+                states: MAX_SETSTATES,
+            },
+            Pattern::SharedObject {
+                copies: MAX_SHARED_COPIES,
+            },
+            Pattern::RecursiveContainer {
+                kind: SizedKind::List,
+                items: 1,
+                at: 0,
+            },
+            Pattern::RecursiveContainer {
+                kind: SizedKind::Dict,
+                items: MAX_BATCH_ITEMS,
+                at: 2,
+            },
+        ];
+        for version in Version::all() {
+            for pattern in patterns {
+                if matches!(pattern, Pattern::AppendsBatch { .. }) && version < Version::V1 {
+                    continue;
+                }
+                let instructions = emit_alone(version, pattern);
+                let names: Vec<&str> = instructions.iter().map(|i| i.name).collect();
+                let last = names[names.len() - 2];
+                match pattern {
+                    Pattern::GlobalCall => assert_eq!(last, "REDUCE"),
+                    Pattern::AppendsBatch { .. } => assert_eq!(last, "APPENDS"),
+                    Pattern::DictOfReduces { .. } if version < Version::V1 => {
+                        assert_eq!(last, "DICT")
+                    }
+                    Pattern::DictOfReduces { pairs } => {
+                        assert_eq!(last, "SETITEMS");
+                        assert_eq!(names.iter().filter(|&&n| n == "REDUCE").count(), pairs);
+                    }
+                    Pattern::SetstateChain { states } => {
+                        assert_eq!(last, "BUILD");
+                        assert_eq!(names.iter().filter(|&&n| n == "BUILD").count(), states);
+                    }
+                    Pattern::SharedObject { .. } if version < Version::V1 => {
+                        assert_eq!(last, "LIST")
+                    }
+                    Pattern::SharedObject { copies } => {
+                        assert_eq!(last, "APPENDS");
+                        assert_eq!(names.iter().filter(|n| n.ends_with("GET")).count(), copies);
+                    }
+                    Pattern::RecursiveContainer { kind, items, .. } => {
+                        let (_, batch, single) = kind.opcodes();
+                        let closer = if version >= Version::V1 && items > 1 {
+                            batch
+                        } else {
+                            single
+                        };
+                        assert_eq!(last, closer.name());
+                        assert_eq!(names.iter().filter(|n| n.ends_with("GET")).count(), 1);
+                    }
+                    Pattern::IndirectStackGlobal { .. }
+                    | Pattern::SizedContainer { .. }
+                    | Pattern::Ndarray { .. }
+                    | Pattern::TorchTensor { .. }
+                    | Pattern::PersistentId { .. }
+                    | Pattern::SklearnEstimator { .. }
+                    | Pattern::ConstructorCall { .. }
+                    | Pattern::LegacyInstance { .. }
+                    | Pattern::TangledMarks { .. }
+                    | Pattern::MarkStress { .. } => unreachable!(),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn peak_stack_growth_bounds_every_pattern() {
+        for version in [Version::V0, Version::V1, Version::V4] {
+            let patterns = [
+                Pattern::GlobalCall,
+                Pattern::AppendsBatch { items: 3 },
+                Pattern::DictOfReduces { pairs: 3 },
+                Pattern::SetstateChain { states: 2 },
+                Pattern::SharedObject { copies: 1 },
+                Pattern::SharedObject { copies: 3 },
+                Pattern::RecursiveContainer {
+                    kind: SizedKind::List,
+                    items: 1,
+                    at: 0,
+                },
+                Pattern::RecursiveContainer {
+                    kind: SizedKind::Dict,
+                    items: 4,
+                    at: 3,
+                },
+            ];
+            for pattern in patterns {
+                if matches!(pattern, Pattern::AppendsBatch { .. }) && version < Version::V1 {
+                    continue;
+                }
+                let mut generator = Generator::new(version);
+                let mut rng = ChaCha8Rng::seed_from_u64(11);
+                let mut source = GenerationSource::Rand(&mut rng);
+                generator.emit_proto(&mut source);
+                generator.emit_pattern(pattern, &mut source).unwrap();
+                assert_eq!(
+                    generator.state.stack.peak_len(),
+                    pattern.peak_stack_growth(version),
+                    "{version:?} {pattern:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn constructor_calls_fit_their_counts() {
+        let constructions = [
+            Construction::Empty,
+            Construction::WithArg,
+            Construction::NewObj,
+            Construction::Reconstructor,
+        ];
+        for version in Version::all() {
+            for (ty, &name) in LOADABLE_TYPES.iter().enumerate() {
+                for construction in constructions {
+                    if (construction == Construction::NewObj && version < Version::V2)
+                        || (construction != Construction::Empty
+                            && name == "bytearray"
+                            && version < Version::V3)
+                    {
+                        continue;
+                    }
+                    let pattern = Pattern::ConstructorCall { ty, construction };
+                    let instructions = emit_alone(version, pattern);
+                    let call = instructions[instructions.len() - 2].name;
+                    let expected = if construction == Construction::NewObj {
+                        "NEWOBJ"
+                    } else {
+                        "REDUCE"
+                    };
+                    assert_eq!(call, expected, "{version:?} {pattern:?}");
+
+                    let mut generator = Generator::new(version);
+                    let mut rng = ChaCha8Rng::seed_from_u64(2);
+                    let mut source = GenerationSource::Rand(&mut rng);
+                    generator.emit_proto(&mut source);
+                    generator.emit_pattern(pattern, &mut source).unwrap();
+                    assert_eq!(
+                        generator.state.stack.peak_len(),
+                        pattern.peak_stack_growth(version),
+                        "{version:?} {pattern:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn shared_object_appearances_are_one_object() {
+        for version in [Version::V0, Version::V1, Version::V2, Version::V4] {
+            let mut generator = Generator::new(version);
+            let mut rng = ChaCha8Rng::seed_from_u64(3);
+            let mut source = GenerationSource::Rand(&mut rng);
+            generator.emit_proto(&mut source);
+            generator
+                .emit_pattern(Pattern::SharedObject { copies: 2 }, &mut source)
+                .unwrap();
+
+            let top = generator.state.stack.peek().unwrap().clone();
+            let StackObject::List(items) = &*top.borrow() else {
+                panic!("{version:?}: expected the outer list");
+            };
+            assert_eq!(items.len(), 3, "{version:?}");
+            let shared = items[0].clone();
+            for tuple in &items[1..] {
+                let StackObject::Tuple(inner) = &*tuple.borrow() else {
+                    panic!("{version:?}: expected a one-item tuple");
+                };
+                assert!(Rc::ptr_eq(&inner[0].0, &shared.0), "{version:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn recursive_containers_hold_themselves() {
+        for version in [Version::V0, Version::V1, Version::V4] {
+            for kind in [SizedKind::List, SizedKind::Dict] {
+                let mut generator = Generator::new(version).with_strict_checks(true);
+                let mut rng = ChaCha8Rng::seed_from_u64(4);
+                let mut source = GenerationSource::Rand(&mut rng);
+                generator.emit_proto(&mut source);
+                let pattern = Pattern::RecursiveContainer {
+                    kind,
+                    items: 3,
+                    at: 1,
+                };
+                generator.emit_pattern(pattern, &mut source).unwrap();
+                generator.take_strict_violation().unwrap();
+
+                let top = generator.state.stack.peek().unwrap().clone();
+                let holds_itself = match &*top.borrow() {
+                    StackObject::List(items) => Rc::ptr_eq(&items[1].0, &top.0),
+                    StackObject::Dict(entries) => {
+                        entries.values().any(|value| Rc::ptr_eq(&value.0, &top.0))
+                    }
+                    _ => panic!("{version:?}: expected the container"),
+                };
+                assert!(holds_itself, "{version:?} {kind:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn sized_containers_are_batched_like_cpython() {
+        for version in Version::all() {
+            for kind in [SizedKind::List, SizedKind::Dict, SizedKind::Set] {
+                if kind == SizedKind::Set && version < Version::V4 {
+                    continue;
+                }
+                let sizes = [
+                    (0, BATCH_SIZE),
+                    (1, BATCH_SIZE),
+                    (2, BATCH_SIZE),
+                    (BATCH_SIZE, BATCH_SIZE),
+                    (BATCH_SIZE + 1, BATCH_SIZE),
+                    (2 * BATCH_SIZE + 5, BATCH_SIZE),
+                    (2 * BATCH_SIZE + 5, BATCH_SIZE + 500),
+                    (2 * BATCH_SIZE + 2, BATCH_SIZE + 1),
+                ];
+                for (len, batch) in sizes {
+                    let pattern = Pattern::SizedContainer { kind, len, batch };
+                    let instructions = emit_alone(version, pattern);
+                    let count = |name: &str| instructions.iter().filter(|i| i.name == name).count();
+                    // a lone list or dict item takes APPEND or SETITEM, and
+                    // a full last dict or set batch is followed by an empty one
+                    let batches = match (version < Version::V1, kind) {
+                        (true, _) => len,
+                        (false, SizedKind::List | SizedKind::Dict) if len == 1 => 0,
+                        (false, SizedKind::List) => len.div_ceil(batch),
+                        (false, _) => {
+                            len.div_ceil(batch) + usize::from(len > 0 && len % batch == 0)
+                        }
+                    };
+                    let closing = match kind {
+                        SizedKind::List if version < Version::V1 => "APPEND",
+                        SizedKind::Dict if version < Version::V1 => "SETITEM",
+                        SizedKind::List => "APPENDS",
+                        SizedKind::Dict => "SETITEMS",
+                        SizedKind::Set => "ADDITEMS",
+                    };
+                    assert_eq!(count(closing), batches, "{version:?} {pattern:?}");
+
+                    let mut generator = Generator::new(version);
+                    let mut rng = ChaCha8Rng::seed_from_u64(5);
+                    let mut source = GenerationSource::Rand(&mut rng);
+                    generator.emit_proto(&mut source);
+                    generator.emit_pattern(pattern, &mut source).unwrap();
+                    assert_eq!(
+                        generator.state.stack.peak_len(),
+                        pattern.peak_stack_growth(version),
+                        "{version:?} {pattern:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn oversized_batches_exceed_cpythons_batch_size() {
+        let len = BATCH_SIZE + 200;
+        for oversized in [false, true] {
+            let generator = Generator::new(Version::V4)
+                .with_container_sizes(SizeDistribution::Constant(len))
+                .with_oversized_batches(oversized);
+            let mut rng = ChaCha8Rng::seed_from_u64(2);
+            let mut source = GenerationSource::Rand(&mut rng);
+            for _ in 0..32 {
+                let Some(Pattern::SizedContainer { batch, .. }) =
+                    generator.plan_pattern(false, &mut source)
+                else {
+                    panic!("only sized containers are enabled");
+                };
+                if oversized {
+                    assert!((BATCH_SIZE + 1..=len).contains(&batch), "{batch}");
+                } else {
+                    assert_eq!(batch, BATCH_SIZE);
+                }
+            }
+        }
+
+        let mut generator = Generator::new(Version::V4)
+            .with_seed(4)
+            .with_opcode_range(6000, 6000)
+            .with_container_sizes(SizeDistribution::Constant(len))
+            .with_oversized_batches(true);
+        validate(&generator.generate().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn ndarrays_are_reconstructed_like_numpy() {
+        let shapes: [&[u32]; 6] = [&[], &[0], &[3], &[2, 5], &[2, 0, 3], &[1; 12]];
+        for version in Version::all() {
+            for dtype in [Dtype::Bool, Dtype::Int16, Dtype::Complex128] {
+                for dims in shapes {
+                    let shape = Shape::new(dims);
+                    let pattern = Pattern::Ndarray { dtype, shape };
+                    let instructions = emit_alone(version, pattern);
+                    let count = |name: &str| instructions.iter().filter(|i| i.name == name).count();
+                    // below protocol 3, b'b' and the data are `_codecs.encode`
+                    // or `bytes()` calls
+                    let reduces = if version < Version::V3 { 4 } else { 2 };
+                    assert_eq!(count("REDUCE"), reduces, "{version:?} {pattern:?}");
+                    assert_eq!(count("BUILD"), 2, "{version:?} {pattern:?}");
+
+                    let texts: Vec<String> = instructions
+                        .iter()
+                        .filter_map(|i| match &i.arg {
+                            Argument::Str(text) => Some(text.clone()),
+                            _ => None,
+                        })
+                        .collect();
+                    assert!(texts.contains(&dtype.code().to_string()), "{texts:?}");
+                    assert!(
+                        texts.iter().any(|text| text.ends_with("_reconstruct")),
+                        "{texts:?}"
+                    );
+                    if version >= Version::V3 {
+                        let Argument::Bytes(data) = &instructions[instructions.len() - 4].arg
+                        else {
+                            panic!("{version:?} {pattern:?}: expected the data bytes");
+                        };
+                        assert_eq!(data.len(), shape.elements() * dtype.itemsize());
+                    }
+
+                    let mut generator = Generator::new(version);
+                    let mut rng = ChaCha8Rng::seed_from_u64(6);
+                    let mut source = GenerationSource::Rand(&mut rng);
+                    generator.emit_proto(&mut source);
+                    generator.emit_pattern(pattern, &mut source).unwrap();
+                    assert_eq!(
+                        generator.state.stack.peak_len(),
+                        pattern.peak_stack_growth(version),
+                        "{version:?} {pattern:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn torch_tensors_are_rebuilt_from_persistent_storages() {
+        let shapes: [&[u32]; 5] = [&[], &[0, 7], &[768], &[3, 4, 5], &[2, 3, 4, 5]];
+        for version in Version::all().skip(1) {
+            for dims in shapes {
+                let shape = Shape::new(dims);
+                let pattern = Pattern::TorchTensor { storage: 0, shape };
+                let instructions = emit_alone(version, pattern);
+                let count = |name: &str| instructions.iter().filter(|i| i.name == name).count();
+                assert_eq!(count("BINPERSID"), 1, "{version:?} {pattern:?}");
+                assert_eq!(count("REDUCE"), 2, "{version:?} {pattern:?}");
+
+                let texts: Vec<String> = instructions
+                    .iter()
+                    .filter_map(|i| match &i.arg {
+                        Argument::Str(text) => Some(text.clone()),
+                        _ => None,
+                    })
+                    .collect();
+                let expected: &[&str] = if version >= Version::V4 {
+                    &[
+                        "torch._utils",
+                        "_rebuild_tensor_v2",
+                        "storage",
+                        "torch",
+                        "FloatStorage",
+                    ]
+                } else {
+                    &[
+                        "torch._utils _rebuild_tensor_v2",
+                        "storage",
+                        "torch FloatStorage",
+                    ]
+                };
+                assert_eq!(&texts[..expected.len()], expected, "{version:?}");
+
+                let mut generator = Generator::new(version);
+                let mut rng = ChaCha8Rng::seed_from_u64(12);
+                let mut source = GenerationSource::Rand(&mut rng);
+                generator.emit_proto(&mut source);
+                generator.emit_pattern(pattern, &mut source).unwrap();
+                assert_eq!(
+                    generator.state.stack.peak_len(),
+                    pattern.peak_stack_growth(version),
+                    "{version:?} {pattern:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn torch_tensors_need_binpersid() {
+        // without BINPERSID, on protocol 0 or with persistent ids off, the
+        // output is unchanged
+        for (version, persistent_ids) in [(Version::V0, true), (Version::V2, false)] {
+            let plain = Generator::new(version)
+                .with_seed(5)
+                .with_persistent_id_opcodes(persistent_ids)
+                .generate()
+                .unwrap();
+            let torch = Generator::new(version)
+                .with_seed(5)
+                .with_persistent_id_opcodes(persistent_ids)
+                .with_torch_tensors(true)
+                .generate()
+                .unwrap();
+            assert_eq!(plain, torch, "{version:?}");
+        }
+
+        let mut generator = Generator::new(Version::V2)
+            .with_seed(5)
+            .with_opcode_range(400, 400)
+            .with_persistent_id_opcodes(true)
+            .with_torch_tensors(true);
+        let output = generator.generate().unwrap();
+        let instructions = disassemble(&output).unwrap();
+        assert!(instructions.iter().any(|i| i.name == "BINPERSID"));
+        validate(&output).unwrap();
+    }
+
+    #[test]
+    fn persistent_ids_hand_binpersid_their_payload() {
+        for version in Version::all().filter(|&v| v >= Version::V1) {
+            let mut payloads = vec![PidPayload::Text, PidPayload::Int];
+            payloads.extend((1..=MAX_PID_ITEMS).map(|len| PidPayload::Tuple { len }));
+            if version >= Version::V3 {
+                payloads.push(PidPayload::Bytes);
+            }
+            for payload in payloads {
+                let instructions = emit_alone(version, Pattern::PersistentId { payload });
+                let last = instructions.len() - 2;
+                assert_eq!(instructions[last].name, "BINPERSID", "{payload:?}");
+                let id = instructions[last - 1].name;
+                match payload {
+                    PidPayload::Tuple { .. } => assert!(id.contains("TUPLE"), "{id}"),
+                    PidPayload::Text => assert!(id.contains("UNICODE"), "{id}"),
+                    PidPayload::Int => assert!(id.contains("INT"), "{id}"),
+                    PidPayload::Bytes => assert!(id.contains("BYTES"), "{id}"),
+                }
+            }
+        }
+
+        // persistent id payloads need BINPERSID
+        let plain = Generator::new(Version::V2).with_seed(6).generate().unwrap();
+        let payloads = Generator::new(Version::V2)
+            .with_seed(6)
+            .with_persistent_id_payloads(true)
+            .generate()
+            .unwrap();
+        assert_eq!(plain, payloads);
+    }
+
+    #[test]
+    fn sklearn_estimators_are_pickled_like_joblib() {
+        for version in Version::all() {
+            for (index, estimator) in SKLEARN_ESTIMATORS.iter().enumerate() {
+                for fitted in [false, true] {
+                    let pattern = Pattern::SklearnEstimator {
+                        estimator: index,
+                        fitted,
+                    };
+                    let instructions = emit_alone(version, pattern);
+                    let count = |name: &str| instructions.iter().filter(|i| i.name == name).count();
+                    // the estimator, and per array its wrapper and dtype
+                    let arrays = if fitted { estimator.arrays.len() } else { 0 };
+                    assert_eq!(count("BUILD"), 1 + 2 * arrays, "{version:?} {pattern:?}");
+                    // below protocol 2, instances are REDUCEs like the dtypes
+                    if version >= Version::V2 {
+                        assert_eq!(count("NEWOBJ"), 1 + arrays, "{version:?} {pattern:?}");
+                    } else {
+                        assert_eq!(count("REDUCE"), 1 + 2 * arrays, "{version:?} {pattern:?}");
+                    }
+
+                    let texts: Vec<String> = instructions
+                        .iter()
+                        .filter_map(|i| match &i.arg {
+                            Argument::Str(text) => Some(text.clone()),
+                            _ => None,
+                        })
+                        .collect();
+                    assert!(texts.iter().any(|text| text == "_sklearn_version"));
+                    assert!(texts.iter().any(|text| text.contains(estimator.name)));
+                    let wrappers = texts
+                        .iter()
+                        .filter(|text| text.contains("NumpyArrayWrapper"))
+                        .count();
+                    assert_eq!(wrappers, arrays, "{version:?} {pattern:?}");
+
+                    let mut generator = Generator::new(version);
+                    let mut rng = ChaCha8Rng::seed_from_u64(13);
+                    let mut source = GenerationSource::Rand(&mut rng);
+                    generator.emit_proto(&mut source);
+                    generator.emit_pattern(pattern, &mut source).unwrap();
+                    assert_eq!(
+                        generator.state.stack.peak_len(),
+                        pattern.peak_stack_growth(version),
+                        "{version:?} {pattern:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sklearn_estimators_alone_enable_the_estimator_pattern() {
+        let plain = Generator::new(Version::V3).with_seed(4).generate().unwrap();
+        let sklearn = Generator::new(Version::V3)
+            .with_seed(4)
+            .with_sklearn_estimators(false)
+            .generate()
+            .unwrap();
+        assert_eq!(plain, sklearn);
+
+        let mut generator = Generator::new(Version::V3)
+            .with_seed(4)
+            .with_opcode_range(400, 400)
+            .with_sklearn_estimators(true);
+        let output = generator.generate().unwrap();
+        let instructions = disassemble(&output).unwrap();
+        assert!(instructions
+            .iter()
+            .any(|i| matches!(&i.arg, Argument::Str(text) if text.starts_with("sklearn."))));
+        validate(&output).unwrap();
+    }
+
+    #[test]
+    fn legacy_instances_are_pickled_like_python_2() {
+        for version in [Version::V0, Version::V1, Version::V2] {
+            for args in 0..=MAX_INIT_ARGS {
+                for attrs in 0..=LEGACY_ATTRS.len() / 2 {
+                    let pattern = Pattern::LegacyInstance { args, attrs };
+                    let instructions = emit_alone(version, pattern);
+                    let count = |name: &str| instructions.iter().filter(|i| i.name == name).count();
+                    let (create, keys) = if version < Version::V1 {
+                        ("INST", "STRING")
+                    } else {
+                        ("OBJ", "SHORT_BINSTRING")
+                    };
+                    assert_eq!(count(create), 1, "{version:?} {pattern:?}");
+                    assert_eq!(count(keys), attrs, "{version:?} {pattern:?}");
+                    assert_eq!(count("BUILD"), 1, "{version:?} {pattern:?}");
+                    // the class on protocol 1+, the instance, the dict, and
+                    // every key
+                    let memoized = usize::from(version >= Version::V1) + 2 + attrs;
+                    let puts = count("PUT") + count("BINPUT");
+                    assert_eq!(puts, memoized, "{version:?} {pattern:?}");
+
+                    let mut generator = Generator::new(version);
+                    let mut rng = ChaCha8Rng::seed_from_u64(13);
+                    let mut source = GenerationSource::Rand(&mut rng);
+                    generator.emit_proto(&mut source);
+                    generator.emit_pattern(pattern, &mut source).unwrap();
+                    assert_eq!(
+                        generator.state.stack.peak_len(),
+                        pattern.peak_stack_growth(version),
+                        "{version:?} {pattern:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn legacy_instances_stay_below_protocol_3() {
+        let plain = Generator::new(Version::V1).with_seed(2).generate().unwrap();
+        let legacy = Generator::new(Version::V1)
+            .with_seed(2)
+            .with_legacy_instances(false)
+            .generate()
+            .unwrap();
+        assert_eq!(plain, legacy);
+
+        for (version, expected) in [(Version::V0, "INST"), (Version::V2, "OBJ")] {
+            let output = Generator::new(version)
+                .with_seed(5)
+                .with_opcode_range(400, 400)
+                .with_legacy_instances(true)
+                .generate()
+                .unwrap();
+            validate(&output).unwrap();
+            let instructions = disassemble(&output).unwrap();
+            assert!(
+                instructions.iter().any(|i| i.name == expected),
+                "{version:?}"
+            );
+        }
+
+        let mut generator = Generator::new(Version::V3)
+            .with_seed(5)
+            .with_legacy_instances(true);
+        let mut rng = ChaCha8Rng::seed_from_u64(5);
+        let mut source = GenerationSource::Rand(&mut rng);
+        generator.emit_proto(&mut source);
+        assert_eq!(generator.plan_pattern(false, &mut source), None);
+    }
+
+    #[test]
+    fn tangled_marks_close_each_others_marks() {
+        let kinds = [SizedKind::List, SizedKind::Dict, SizedKind::Set];
+        for version in [Version::V1, Version::V4] {
+            for outer in kinds {
+                for inner in kinds {
+                    let sets = outer == SizedKind::Set || inner == SizedKind::Set;
+                    if outer == inner || (sets && version < Version::V4) {
+                        continue;
+                    }
+                    let pattern = Pattern::TangledMarks {
+                        outer,
+                        inner,
+                        items: 3,
+                    };
+                    let mut generator = Generator::new(version)
+                        .with_unsafe_marks(true)
+                        .with_strict_checks(true);
+                    let mut rng = ChaCha8Rng::seed_from_u64(3);
+                    let mut source = GenerationSource::Rand(&mut rng);
+                    generator.emit_proto(&mut source);
+                    generator.emit_pattern(pattern, &mut source).unwrap();
+                    generator.take_strict_violation().unwrap();
+                    assert_eq!(generator.state.stack.len(), 1, "{pattern:?}");
+                    assert_eq!(
+                        generator.state.stack.peak_len(),
+                        pattern.peak_stack_growth(version),
+                        "{version:?} {pattern:?}"
+                    );
+
+                    generator.emit_opcode(OpcodeKind::Stop);
+                    validate(&generator.output).unwrap();
+                    let names: Vec<&str> = disassemble(&generator.output)
+                        .unwrap()
+                        .iter()
+                        .map(|i| i.name)
+                        .collect();
+                    let proto = usize::from(version >= Version::V2);
+                    assert_eq!(names.len(), proto + pattern.opcode_count(version) + 1);
+                    let closers = &names[names.len() - 3..names.len() - 1];
+                    let expected = [outer.opcodes().1.name(), inner.opcodes().1.name()];
+                    assert_eq!(closers, expected, "{pattern:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn mark_stress_bursts_close_their_marks() {
+        let mut duplicated = false;
+        for unsafe_marks in [false, true] {
+            for seed in 0..40 {
+                let version = Version::ALL[1 + seed as usize % 5];
+                let mut generator = Generator::new(version)
+                    .with_unsafe_marks(unsafe_marks)
+                    .with_strict_checks(true);
+                let mut rng = ChaCha8Rng::seed_from_u64(seed);
+                let mut source = GenerationSource::Rand(&mut rng);
+                generator.emit_proto(&mut source);
+                let burst = StressBurst::plan(MAX_STRESS_OPS, unsafe_marks, &mut source);
+                let pattern = Pattern::MarkStress { burst };
+                generator.emit_pattern(pattern, &mut source).unwrap();
+                generator.take_strict_violation().unwrap();
+                assert_eq!(generator.state.stack.len(), 1, "{:?}", burst.ops());
+                assert_eq!(
+                    generator.state.stack.peak_len(),
+                    pattern.peak_stack_growth(version),
+                    "{:?}",
+                    burst.ops()
+                );
+
+                generator.emit_opcode(OpcodeKind::Stop);
+                let names: Vec<&str> = disassemble(&generator.output)
+                    .unwrap()
+                    .iter()
+                    .map(|i| i.name)
+                    .collect();
+                let proto = usize::from(version >= Version::V2);
+                assert_eq!(names.len(), proto + pattern.opcode_count(version) + 1);
+                let copies = names
+                    .windows(2)
+                    .any(|pair| pair[0] == "MARK" && pair[1] == "DUP");
+                duplicated |= copies;
+                if !unsafe_marks {
+                    assert!(!copies);
+                    validate(&generator.output).unwrap();
+                }
+            }
+        }
+        assert!(duplicated);
+    }
+
+    #[test]
+    fn ndarrays_alone_enable_the_ndarray_pattern() {
+        let mut generator = Generator::new(Version::V2)
+            .with_seed(3)
+            .with_opcode_range(400, 400)
+            .with_ndarrays("f4,f8:2:4".parse().unwrap());
+        let output = generator.generate().unwrap();
+        let instructions = disassemble(&output).unwrap();
+        assert!(instructions
+            .iter()
+            .any(|i| matches!(&i.arg, Argument::Str(text) if text.ends_with("_reconstruct"))));
+        validate(&output).unwrap();
+    }
+
+    #[test]
+    fn container_sizes_alone_enable_sized_containers() {
+        let mut generator = Generator::new(Version::V4)
+            .with_seed(9)
+            .with_opcode_range(400, 400)
+            .with_container_sizes(SizeDistribution::Constant(40));
+        let output = generator.generate().unwrap();
+        let names: Vec<&str> = disassemble(&output)
+            .unwrap()
+            .iter()
+            .map(|i| i.name)
+            .collect();
+        assert!(names
+            .iter()
+            .any(|name| name.ends_with("ITEMS") || *name == "APPENDS"));
+        validate(&output).unwrap();
+    }
+}
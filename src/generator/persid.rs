@@ -0,0 +1,127 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! persistent ids shaped for the application code that resolves them.
+//!
+//! the unpickler hands every PERSID and BINPERSID id to `persistent_load`,
+//! which the application writes: `torch.load` expects a tuple, ZODB a
+//! `(oid, class)` pair or bytes, others an int or a str. left to the
+//! generation loop, BINPERSID pops whatever happens to be on the stack and
+//! PERSID gets a `pid_N` line. the persistent id pattern builds the id
+//! BINPERSID pops as a str, int, bytes, or a tuple of strs and ints instead.
+//!
+//! PERSID's id is a newline-terminated ASCII line, which Python 3 decodes
+//! as ASCII and Python 2 took as bytes. unsafe persistent ids sometimes
+//! break that: a CRLF ending, a non-ASCII UTF-8 character, or a newline
+//! inside the id, after which every reader parses the rest of the id as
+//! opcodes while the simulation goes on as if it were one line.
+
+use super::source::{EntropySource, GenerationSource};
+use super::Generator;
+
+/// ids unsafe persistent ids break, with a stand-in for the id's number.
+const HOSTILE_IDS: [&str; 4] = ["pid_{}\r", "pid_\u{e9}{}", "oid_\u{1f4be}{}", "pid_{}\nN"];
+
+impl Generator {
+    /// sometimes build the id BINPERSID pops as a str, int, bytes (protocol
+    /// 3+), or tuple of strs and ints, like the ids `persistent_load`
+    /// implementations expect (protocol 1+, off by default).
+    ///
+    /// like the other patterns, about one in sixteen steps of the generation
+    /// loop emits one. it needs `with_persistent_id_opcodes`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pickle_fuzzer::{Generator, Version};
+    ///
+    /// let mut gen = Generator::new(Version::V3)
+    ///     .with_seed(3)
+    ///     .with_persistent_id_opcodes(true)
+    ///     .with_persistent_id_payloads(true);
+    /// let pickle = gen.generate().unwrap();
+    /// assert_eq!(pickle.last(), Some(&b'.'));
+    /// ```
+    pub fn with_persistent_id_payloads(mut self, enabled: bool) -> Self {
+        self.persistent_id_payloads = enabled;
+        self
+    }
+
+    /// give half of the PERSID ids a CRLF ending, a non-ASCII character, or
+    /// a newline in the middle (off by default).
+    ///
+    /// Python 3 rejects non-ASCII ids, and an id with a newline inside ends
+    /// the line early, so the rest of it is read as opcodes. like unsafe
+    /// mutations this is for negative tests of `persistent_load` handling. it
+    /// needs `with_persistent_id_opcodes`.
+    pub fn with_unsafe_persistent_ids(mut self, enabled: bool) -> Self {
+        self.unsafe_persistent_ids = enabled;
+        self
+    }
+
+    /// the line PERSID writes, newline included.
+    pub(super) fn persistent_id_line(&self, source: &mut GenerationSource) -> Vec<u8> {
+        let number = source.gen_u32();
+        if self.unsafe_persistent_ids && source.gen_bool() {
+            let id = HOSTILE_IDS[source.choose_index(HOSTILE_IDS.len())];
+            return format!("{}\n", id.replace("{}", &number.to_string())).into_bytes();
+        }
+        format!("pid_{number}\n").into_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::disasm::{disassemble, validate};
+    use crate::{Generator, Version};
+
+    #[test]
+    fn unsafe_persistent_ids_break_the_persid_line() {
+        let mut hostile = 0;
+        for seed in 0..32 {
+            let pickle = Generator::new(Version::V0)
+                .with_seed(seed)
+                .with_opcode_range(40, 80)
+                .with_persistent_id_opcodes(true)
+                .with_unsafe_persistent_ids(true)
+                .with_strict_checks(true)
+                .generate()
+                .unwrap();
+            if validate(&pickle).is_err() {
+                hostile += 1;
+                continue;
+            }
+            let non_ascii = disassemble(&pickle).unwrap().iter().any(|i| {
+                i.name == "PERSID" && format!("{:?}", i.arg).chars().any(|c| !c.is_ascii())
+            });
+            hostile += usize::from(non_ascii);
+        }
+        assert!(hostile > 0);
+
+        let plain = Generator::new(Version::V0)
+            .with_seed(4)
+            .with_persistent_id_opcodes(true)
+            .generate()
+            .unwrap();
+        let safe = Generator::new(Version::V0)
+            .with_seed(4)
+            .with_persistent_id_opcodes(true)
+            .with_unsafe_persistent_ids(false)
+            .generate()
+            .unwrap();
+        assert_eq!(plain, safe);
+    }
+}
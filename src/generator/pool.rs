@@ -0,0 +1,361 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! per-thread generators for multithreaded embedders.
+//!
+//! a `Generator` keeps its simulated stack in `Rc`s and its mutators in
+//! boxes, so it can't be shared between threads or cloned into them. a
+//! [`GeneratorPool`] owns what builds one instead, a [`GeneratorConfig`] or
+//! any closure, and can be shared freely. each thread checks generators out
+//! of it; a returned generator waits in a cache of the thread that built it,
+//! so a worker builds its generators, mutators included, once and not once
+//! per sample.
+//!
+//! [`sample`](GeneratorPool::sample) also derives a sample's seeds and
+//! protocol from its index the way batch mode does, so sample `i` is the same
+//! pickle whichever thread generates it.
+
+use std::cell::RefCell;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Weak};
+
+use color_eyre::Result;
+
+use super::Generator;
+use crate::{GeneratorConfig, ProtocolMix, Version};
+
+type BuildFn = dyn Fn() -> Generator + Send + Sync;
+
+/// how a pool picks the protocol of a sample.
+#[derive(Debug, Clone)]
+enum Protocols {
+    /// keep the version the generators are built with
+    Built,
+    /// pick one from the sample's seed, uniformly
+    Uniform,
+    /// pick one from the sample's seed, by weight
+    Mix(ProtocolMix),
+}
+
+struct PoolInner {
+    build: Box<BuildFn>,
+    seed: Option<u64>,
+    value_seed: Option<u64>,
+    protocols: Protocols,
+}
+
+impl std::fmt::Debug for PoolInner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("PoolInner")
+    }
+}
+
+thread_local! {
+    /// generators returned on this thread, by the pool that built them
+    static IDLE: RefCell<Vec<(Weak<PoolInner>, Vec<Generator>)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// builds identically configured generators for any number of threads.
+///
+/// cloning a pool is cheap and shares its generators. generators are checked
+/// out with [`generator`](Self::generator) or, seeded for one sample,
+/// [`sample`](Self::sample), and go back to the pool when dropped.
+///
+/// # Examples
+///
+/// ```
+/// use pickle_fuzzer::{GeneratorConfig, GeneratorPool};
+///
+/// let config = GeneratorConfig::from_json(br#"{"seed": 7, "mutators": ["bitflip"]}"#).unwrap();
+/// let pool = GeneratorPool::from_config(&config).unwrap();
+/// let pickles: Vec<Vec<u8>> = std::thread::scope(|scope| {
+///     let workers: Vec<_> = (0..4)
+///         .map(|worker| {
+///             let pool = &pool;
+///             scope.spawn(move || pool.sample(worker).generate().unwrap())
+///         })
+///         .collect();
+///     workers.into_iter().map(|w| w.join().unwrap()).collect()
+/// });
+/// // sample 2 is the same pickle on any thread
+/// assert_eq!(pool.sample(2).generate().unwrap(), pickles[2]);
+/// ```
+#[derive(Clone)]
+pub struct GeneratorPool {
+    inner: Arc<PoolInner>,
+}
+
+impl std::fmt::Debug for GeneratorPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GeneratorPool")
+            .field("seed", &self.inner.seed)
+            .field("value_seed", &self.inner.value_seed)
+            .field("protocols", &self.inner.protocols)
+            .finish_non_exhaustive()
+    }
+}
+
+impl GeneratorPool {
+    /// a pool of the generators `build` returns.
+    ///
+    /// `build` runs once for every generator the pool needs, on the thread
+    /// that will use it, so it is where per-thread state such as mutators is
+    /// created. samples keep the version `build` gives its generators until
+    /// [`with_protocol_mix`](Self::with_protocol_mix) says otherwise.
+    pub fn new(build: impl Fn() -> Generator + Send + Sync + 'static) -> Self {
+        Self {
+            inner: Arc::new(PoolInner {
+                build: Box::new(build),
+                seed: None,
+                value_seed: None,
+                protocols: Protocols::Built,
+            }),
+        }
+    }
+
+    /// a pool of the generators `config` describes, or why it is invalid.
+    ///
+    /// `config.seed` becomes the pool's base seed. without a `protocol`, each
+    /// sample's protocol is picked from its seed like batch mode picks it.
+    pub fn from_config(config: &GeneratorConfig) -> Result<Self, String> {
+        config.build()?;
+        let protocols = match config.protocol {
+            Some(_) => Protocols::Built,
+            None => Protocols::Uniform,
+        };
+        let seed = config.seed;
+        let config = config.clone();
+        let mut pool = Self::new(move || config.build().expect("the configuration was checked"));
+        let inner = Arc::get_mut(&mut pool.inner).expect("the pool is not shared yet");
+        inner.seed = seed;
+        inner.protocols = protocols;
+        Ok(pool)
+    }
+
+    /// derive sample `i`'s seed as `seed + i`, as batch mode does.
+    pub fn with_seed(self, seed: u64) -> Self {
+        self.map_inner(|inner| inner.seed = Some(seed))
+    }
+
+    /// derive sample `i`'s value seed as `value_seed + i`, as batch mode
+    /// does with `--value-seed`.
+    pub fn with_value_seed(self, value_seed: u64) -> Self {
+        self.map_inner(|inner| inner.value_seed = Some(value_seed))
+    }
+
+    /// pick each sample's protocol from `mix`, by its seed.
+    pub fn with_protocol_mix(self, mix: ProtocolMix) -> Self {
+        self.map_inner(|inner| inner.protocols = Protocols::Mix(mix))
+    }
+
+    fn map_inner(mut self, change: impl FnOnce(&mut PoolInner)) -> Self {
+        match Arc::get_mut(&mut self.inner) {
+            Some(inner) => change(inner),
+            None => panic!("a GeneratorPool can only be configured before it is cloned"),
+        }
+        self
+    }
+
+    /// the seed of sample `index`, if the pool has a base seed.
+    pub fn sample_seed(&self, index: u64) -> Option<u64> {
+        self.inner.seed.map(|seed| seed.wrapping_add(index))
+    }
+
+    /// check out a generator, built on this thread now or earlier.
+    ///
+    /// a reused generator keeps the seed and version its last user left it
+    /// with; [`sample`](Self::sample) sets both.
+    pub fn generator(&self) -> PooledGenerator {
+        let pool = Arc::downgrade(&self.inner);
+        let idle = IDLE.with(|idle| {
+            let mut idle = idle.borrow_mut();
+            idle.retain(|(pool, _)| pool.strong_count() > 0);
+            idle.iter_mut()
+                .find(|(owner, _)| owner.ptr_eq(&pool))
+                .and_then(|(_, generators)| generators.pop())
+        });
+        PooledGenerator {
+            generator: Some(idle.unwrap_or_else(|| (self.inner.build)())),
+            pool,
+        }
+    }
+
+    /// check out a generator set up for sample `index`: its seeds derived
+    /// from the pool's, and its protocol picked from its seed if the pool
+    /// picks protocols.
+    pub fn sample(&self, index: u64) -> PooledGenerator {
+        let mut generator = self.generator();
+        let seed = self.sample_seed(index);
+        if let Some(version) = self.version(seed) {
+            generator.set_version(version);
+        }
+        generator.set_seed(seed);
+        generator.set_value_seed(self.inner.value_seed.map(|seed| seed.wrapping_add(index)));
+        generator
+    }
+
+    /// generate sample `index`; see [`sample`](Self::sample).
+    pub fn generate(&self, index: u64) -> Result<Vec<u8>> {
+        self.sample(index).generate()
+    }
+
+    fn version(&self, seed: Option<u64>) -> Option<Version> {
+        if let Protocols::Built = self.inner.protocols {
+            return None;
+        }
+        // random rolls need no hashing, so select_for_seed works for both
+        let seed = match seed {
+            Some(seed) => seed,
+            #[cfg(feature = "os-rng")]
+            None => rand::random(),
+            #[cfg(not(feature = "os-rng"))]
+            None => return None,
+        };
+        match &self.inner.protocols {
+            Protocols::Built => None,
+            Protocols::Uniform => Some(Version::select_for_seed(seed)),
+            Protocols::Mix(mix) => Some(mix.select_for_seed(seed)),
+        }
+    }
+}
+
+/// a generator checked out of a [`GeneratorPool`], returned to it on drop.
+///
+/// it dereferences to the [`Generator`]. like the generator, it stays on the
+/// thread that checked it out.
+#[derive(Debug)]
+pub struct PooledGenerator {
+    generator: Option<Generator>,
+    pool: Weak<PoolInner>,
+}
+
+impl Deref for PooledGenerator {
+    type Target = Generator;
+
+    fn deref(&self) -> &Generator {
+        self.generator.as_ref().expect("only taken on drop")
+    }
+}
+
+impl DerefMut for PooledGenerator {
+    fn deref_mut(&mut self) -> &mut Generator {
+        self.generator.as_mut().expect("only taken on drop")
+    }
+}
+
+impl Drop for PooledGenerator {
+    fn drop(&mut self) {
+        let Some(generator) = self.generator.take() else {
+            return;
+        };
+        if self.pool.strong_count() == 0 {
+            return;
+        }
+        // the cache is gone once the thread is shutting down; the generator
+        // is then just dropped
+        let _ = IDLE.try_with(|idle| {
+            let mut idle = idle.borrow_mut();
+            match idle.iter_mut().find(|(owner, _)| owner.ptr_eq(&self.pool)) {
+                Some((_, generators)) => generators.push(generator),
+                None => idle.push((self.pool.clone(), vec![generator])),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::mutators::BitFlipMutator;
+
+    #[test]
+    fn generators_are_built_once_per_thread() {
+        let built = Arc::new(AtomicUsize::new(0));
+        let pool = {
+            let built = Arc::clone(&built);
+            GeneratorPool::new(move || {
+                built.fetch_add(1, Ordering::Relaxed);
+                Generator::new(Version::V3).with_mutator(Box::new(BitFlipMutator))
+            })
+            .with_seed(40)
+        };
+
+        let here: Vec<Vec<u8>> = (0..8).map(|i| pool.generate(i).unwrap()).collect();
+        assert_eq!(built.load(Ordering::Relaxed), 1);
+
+        let there: Vec<Vec<u8>> = std::thread::scope(|scope| {
+            (0..2)
+                .map(|half| {
+                    let pool = pool.clone();
+                    scope.spawn(move || {
+                        (half * 4..half * 4 + 4)
+                            .map(|i| pool.generate(i).unwrap())
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect::<Vec<_>>()
+                .into_iter()
+                .flat_map(|worker| worker.join().unwrap())
+                .collect()
+        });
+        assert_eq!(here, there);
+        assert_eq!(built.load(Ordering::Relaxed), 3);
+
+        // two at once on one thread need two generators
+        let first = pool.generator();
+        let second = pool.generator();
+        assert_eq!(built.load(Ordering::Relaxed), 4);
+        drop((first, second));
+    }
+
+    #[test]
+    fn samples_match_their_configuration_with_the_derived_seed() {
+        let config = GeneratorConfig {
+            seed: Some(100),
+            mutators: vec!["bitflip".into()],
+            ..GeneratorConfig::default()
+        };
+        let pool = GeneratorPool::from_config(&config).unwrap();
+        for index in 0..6 {
+            let seeded = GeneratorConfig {
+                seed: Some(100 + index),
+                ..config.clone()
+            };
+            let expected = seeded.build().unwrap().generate().unwrap();
+            assert_eq!(pool.generate(index).unwrap(), expected);
+        }
+
+        let invalid = GeneratorConfig {
+            protocol: Some(9),
+            ..GeneratorConfig::default()
+        };
+        assert!(GeneratorPool::from_config(&invalid).is_err());
+    }
+
+    #[test]
+    fn protocol_mixes_pick_each_samples_protocol() {
+        let mix: ProtocolMix = "1:1,4:1".parse().unwrap();
+        let pool = GeneratorPool::new(|| Generator::new(Version::V0))
+            .with_seed(0)
+            .with_protocol_mix(mix.clone());
+        for index in 0..8 {
+            let sample = pool.sample(index);
+            assert_eq!(sample.state.version, mix.select_for_seed(index));
+        }
+    }
+}
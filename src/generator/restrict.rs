@@ -0,0 +1,425 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! generation for hardened unpicklers.
+//!
+//! a global allowlist stands in for the `find_class` of a restricted
+//! unpickler: GLOBAL and INST draw their module and name from it instead of
+//! the stdlib list, STACK_GLOBAL is only picked when the two strings on top
+//! of the stack name an allowlisted global, and EXT opcodes, whose registry
+//! lookups could import anything, are never picked. forbidding reduce
+//! removes the opcodes that call what was imported: REDUCE, NEWOBJ,
+//! NEWOBJ_EX, OBJ, and INST.
+//!
+//! both apply to the opcodes generation picks one at a time and to the
+//! patterns that draw their globals at random. emissions a mutator changed
+//! are rolled back when they import or call what the single opcodes may not;
+//! unmutated ones already can't.
+
+use color_eyre::eyre::eyre;
+use color_eyre::Result;
+
+use super::loadable::{loadable_global, LOADABLE_TYPES, RECONSTRUCTOR};
+use super::Generator;
+use crate::disasm::read_opcode;
+use crate::opcodes::{Opcode, OpcodeKind};
+use crate::stack::StackObject;
+use crate::state::State;
+
+/// whether `opcode` calls a global, which forbidding reduce rules out.
+pub(super) fn calls_global(opcode: OpcodeKind) -> bool {
+    use OpcodeKind as Op;
+    matches!(
+        opcode,
+        Op::Reduce | Op::NewObj | Op::NewObjEx | Op::Obj | Op::Inst
+    )
+}
+
+impl Generator {
+    /// only import the globals in `globals`, as module and name.
+    ///
+    /// GLOBAL and INST import a random allowlisted global, STACK_GLOBAL is
+    /// picked only when the strings on top of the stack name one, and EXT
+    /// opcodes are never picked. with an empty allowlist nothing is imported.
+    /// canonical and diverse-encoding output, the indirect STACK_GLOBAL
+    /// pattern, and the global call patterns draw from the allowlist too.
+    ///
+    /// globals a pattern or pickle encoding names itself are written as they
+    /// are: the numpy, torch, and sklearn reconstructors, `copy_reg` and
+    /// `__builtin__` helpers, and globals like `builtins.set` or
+    /// `_codecs.encode` the object picklers need for older protocols.
+    /// allowlist those as well to unpickle such output under a loader that
+    /// permits only the allowlist.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pickle_fuzzer::{disasm, Generator, Version};
+    ///
+    /// let mut gen = Generator::new(Version::V2)
+    ///     .with_seed(3)
+    ///     .with_global_allowlist([("collections", "OrderedDict"), ("datetime", "date")]);
+    /// let pickle = gen.generate().unwrap();
+    /// for op in disasm::disassemble(&pickle).unwrap() {
+    ///     if op.name == "GLOBAL" || op.name == "INST" {
+    ///         let global = op.arg.to_string();
+    ///         assert!(global.contains("OrderedDict") || global.contains("date"));
+    ///     }
+    /// }
+    /// ```
+    pub fn with_global_allowlist<M: Into<String>, N: Into<String>>(
+        mut self,
+        globals: impl IntoIterator<Item = (M, N)>,
+    ) -> Self {
+        self.global_allowlist = Some(
+            globals
+                .into_iter()
+                .map(|(module, name)| (module.into(), name.into()))
+                .collect(),
+        );
+        self
+    }
+
+    /// never emit the opcodes that call a global: REDUCE, NEWOBJ, NEWOBJ_EX,
+    /// OBJ, and INST.
+    ///
+    /// patterns built around a call (the global call, dict of reduces, and
+    /// setstate chain idioms, ndarrays, torch tensors, and sklearn
+    /// estimators) are skipped too. together with a global allowlist the
+    /// output only imports allowlisted globals and never calls them, which a
+    /// hardened loader should accept. canonical and diverse-encoding output
+    /// pickle objects the way CPython does, calls included, and ignore this.
+    pub fn with_forbid_reduce(mut self, enabled: bool) -> Self {
+        self.forbid_reduce = enabled;
+        self
+    }
+
+    /// parse a global allowlist: one global per line, as `module name` or
+    /// `module.name` (split at the last dot), with blank lines and `#`
+    /// comments ignored.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pickle_fuzzer::Generator;
+    ///
+    /// let globals = Generator::parse_global_allowlist(
+    ///     "# what the loader permits\nbuiltins set\ncollections.OrderedDict\n",
+    /// )
+    /// .unwrap();
+    /// assert_eq!(
+    ///     globals,
+    ///     [
+    ///         ("builtins".to_string(), "set".to_string()),
+    ///         ("collections".to_string(), "OrderedDict".to_string()),
+    ///     ]
+    /// );
+    /// ```
+    pub fn parse_global_allowlist(text: &str) -> Result<Vec<(String, String)>> {
+        let mut globals = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split_once('#').map_or(line, |(line, _)| line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let global = match line.split_once(char::is_whitespace) {
+                Some((module, name)) => Some((module, name.trim())),
+                None => line.rsplit_once('.'),
+            };
+            match global {
+                Some((module, name))
+                    if !module.is_empty() && !name.is_empty() && !name.contains(' ') =>
+                {
+                    globals.push((module.to_string(), name.to_string()));
+                }
+                _ => {
+                    return Err(eyre!(
+                        "line {}: expected `module name` or `module.name`, got {line:?}",
+                        number + 1
+                    ))
+                }
+            }
+        }
+        Ok(globals)
+    }
+
+    /// whether the allowlist and forbidding reduce let generation pick
+    /// `opcode`, on top of what `can_emit` checks.
+    pub(super) fn restrictions_allow(&self, opcode: OpcodeKind) -> bool {
+        use OpcodeKind as Op;
+
+        if self.forbid_reduce && calls_global(opcode) {
+            return false;
+        }
+        if self.loadable && !self.loadable_allows(opcode) {
+            return false;
+        }
+        if self.global_allowlist.is_none() && !self.loadable {
+            return true;
+        }
+        match opcode {
+            Op::Global | Op::Inst => !self.importable_globals().is_empty(),
+            Op::StackGlobal => {
+                let string_at = |depth| {
+                    self.peek_at(depth).and_then(|obj| match &*obj.borrow() {
+                        StackObject::String(text) => Some(text.clone()),
+                        _ => None,
+                    })
+                };
+                match (string_at(1), string_at(0)) {
+                    (Some(module), Some(name)) => self.allowlisted(&module, &name),
+                    _ => false,
+                }
+            }
+            Op::Ext1 | Op::Ext2 | Op::Ext4 => false,
+            _ => true,
+        }
+    }
+
+    /// whether `module.name` may be imported.
+    pub(super) fn allowlisted(&self, module: &str, name: &str) -> bool {
+        (!self.loadable || loadable_global(module, name))
+            && self.global_allowlist.as_ref().is_none_or(|allowlist| {
+                allowlist.iter().any(|(allowed_module, allowed_name)| {
+                    allowed_module == module && allowed_name == name
+                })
+            })
+    }
+
+    /// the globals GLOBAL and INST draw from when the allowlist or loadable
+    /// mode restricts them.
+    pub(super) fn importable_globals(&self) -> Vec<(&str, &str)> {
+        let loadable = LOADABLE_TYPES
+            .iter()
+            .map(|&name| ("builtins", name))
+            .chain([RECONSTRUCTOR]);
+        match &self.global_allowlist {
+            Some(allowlist) if !self.loadable => allowlist
+                .iter()
+                .map(|(module, name)| (module.as_str(), name.as_str()))
+                .collect(),
+            _ => loadable
+                .filter(|&(module, name)| self.allowlisted(module, name))
+                .collect(),
+        }
+    }
+
+    /// roll a mutated emission back to `pre_emission_state` if it imports or
+    /// calls what the restrictions rule out.
+    ///
+    /// STACK_GLOBAL and EXT count as imports of unknown globals, as do bytes
+    /// that no longer decode.
+    ///
+    /// # Returns
+    /// `true` if the emission was kept.
+    pub(super) fn enforce_restrictions(
+        &mut self,
+        output_len: usize,
+        pre_emission_state: &State,
+    ) -> bool {
+        if self.global_allowlist.is_none() && !self.forbid_reduce && !self.loadable {
+            return true;
+        }
+        let mut rest = &self.output[output_len..];
+        let mut allowed = true;
+        while allowed && !rest.is_empty() {
+            let Ok((opcode, len)) = read_opcode(rest) else {
+                allowed = false;
+                break;
+            };
+            rest = &rest[len..];
+            allowed = !(self.forbid_reduce && calls_global(opcode.kind()))
+                && self.loadable_keeps(&opcode)
+                && match &opcode {
+                    Opcode::Global(module, name) | Opcode::Inst(module, name) => {
+                        self.allowlisted(module, name)
+                    }
+                    Opcode::StackGlobal | Opcode::Ext1(_) | Opcode::Ext2(_) | Opcode::Ext4(_) => {
+                        self.global_allowlist.is_none() && !self.loadable
+                    }
+                    _ => true,
+                };
+        }
+        if !allowed {
+            self.state = pre_emission_state.clone();
+            self.output.truncate(output_len);
+        }
+        allowed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disasm::{disassemble, validate};
+    use crate::mutators::MutatorKind;
+    use crate::Version;
+
+    const ALLOWLIST: [(&str, &str); 3] = [
+        ("builtins", "set"),
+        ("collections", "OrderedDict"),
+        ("datetime", "timedelta"),
+    ];
+
+    /// the globals `pickle` imports, as `module name`.
+    fn imports(pickle: &[u8]) -> Vec<String> {
+        let mut rest = pickle;
+        let mut imports = Vec::new();
+        while let Ok((opcode, len)) = read_opcode(rest) {
+            rest = &rest[len..];
+            if let Opcode::Global(module, name) | Opcode::Inst(module, name) = opcode {
+                imports.push(format!("{module} {name}"));
+            }
+        }
+        imports
+    }
+
+    #[test]
+    fn allowlisted_generation_only_imports_allowlisted_globals() {
+        let allowed: Vec<String> = ALLOWLIST
+            .iter()
+            .map(|(module, name)| format!("{module} {name}"))
+            .collect();
+        for version in Version::all() {
+            let mut imported = 0;
+            for seed in 0..20 {
+                let pickle = Generator::new(version)
+                    .with_seed(seed)
+                    .with_global_allowlist(ALLOWLIST)
+                    .with_interesting_patterns(true)
+                    .with_indirect_stack_globals(true)
+                    .generate()
+                    .unwrap();
+                validate(&pickle).unwrap_or_else(|e| panic!("protocol {version}: {e}"));
+                for global in imports(&pickle) {
+                    assert!(allowed.contains(&global), "protocol {version}: {global}");
+                    imported += 1;
+                }
+            }
+            assert!(imported > 0, "protocol {version}");
+        }
+    }
+
+    #[test]
+    fn stack_global_needs_an_allowlisted_name_on_the_stack() {
+        let mut generator = Generator::new(Version::V4).with_global_allowlist(ALLOWLIST);
+        generator.push(StackObject::String("collections".into()));
+        generator.push(StackObject::String("OrderedDict".into()));
+        assert!(generator.restrictions_allow(OpcodeKind::StackGlobal));
+        generator.pop();
+        generator.push(StackObject::String("deque".into()));
+        assert!(!generator.restrictions_allow(OpcodeKind::StackGlobal));
+        assert!(!generator.restrictions_allow(OpcodeKind::Ext1));
+    }
+
+    #[test]
+    fn an_empty_allowlist_imports_nothing() {
+        for version in Version::all() {
+            for seed in 0..10 {
+                let pickle = Generator::new(version)
+                    .with_seed(seed)
+                    .with_global_allowlist(Vec::<(String, String)>::new())
+                    .generate()
+                    .unwrap();
+                let opcodes = disassemble(&pickle).unwrap();
+                assert!(
+                    opcodes.iter().all(|op| !matches!(
+                        op.name,
+                        "GLOBAL" | "INST" | "STACK_GLOBAL" | "EXT1" | "EXT2" | "EXT4"
+                    )),
+                    "protocol {version}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn forbidding_reduce_leaves_no_calls() {
+        for version in Version::all() {
+            for seed in 0..20 {
+                let pickle = Generator::new(version)
+                    .with_seed(seed)
+                    .with_forbid_reduce(true)
+                    .with_interesting_patterns(true)
+                    .with_sklearn_estimators(true)
+                    .generate()
+                    .unwrap();
+                validate(&pickle).unwrap_or_else(|e| panic!("protocol {version}: {e}"));
+                let opcodes = disassemble(&pickle).unwrap();
+                assert!(
+                    opcodes.iter().all(|op| !matches!(
+                        op.name,
+                        "REDUCE" | "NEWOBJ" | "NEWOBJ_EX" | "OBJ" | "INST"
+                    )),
+                    "protocol {version}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn restricted_pickles_at_most_import() {
+        use crate::risk::{classify, Risk};
+
+        for version in Version::all() {
+            for seed in 0..20 {
+                let pickle = Generator::new(version)
+                    .with_seed(seed)
+                    .with_global_allowlist(ALLOWLIST)
+                    .with_forbid_reduce(true)
+                    .with_interesting_patterns(true)
+                    .generate()
+                    .unwrap();
+                assert!(classify(&pickle) <= Risk::Import, "protocol {version}");
+            }
+        }
+    }
+
+    #[test]
+    fn mutated_emissions_keep_to_the_restrictions() {
+        let allowed: Vec<String> = ALLOWLIST
+            .iter()
+            .map(|(module, name)| format!("{module} {name}"))
+            .collect();
+        for seed in 0..20 {
+            let pickle = Generator::new(Version::V2)
+                .with_seed(seed)
+                .with_mutators(vec![MutatorKind::Typeconfusion.create(true)])
+                .with_mutation_rate(1.0)
+                .with_global_allowlist(ALLOWLIST)
+                .with_forbid_reduce(true)
+                .generate()
+                .unwrap();
+            for global in imports(&pickle) {
+                assert!(allowed.contains(&global), "{global}");
+            }
+            let opcodes = disassemble(&pickle).unwrap();
+            assert!(opcodes
+                .iter()
+                .all(|op| !calls_global(OpcodeKind::from_u8(op.code).unwrap())));
+        }
+    }
+
+    #[test]
+    fn allowlists_reject_malformed_lines() {
+        assert!(Generator::parse_global_allowlist("builtins\n").is_err());
+        assert!(Generator::parse_global_allowlist("os path join\n").is_err());
+        assert_eq!(
+            Generator::parse_global_allowlist("  os.path.join  # ok\n\n").unwrap(),
+            [("os.path".to_string(), "join".to_string())]
+        );
+    }
+}
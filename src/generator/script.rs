@@ -0,0 +1,255 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! scripted generation: hand-picked opcodes mixed with random ones.
+//!
+//! [`Generator::begin`] starts a pickle, [`Generator::emit`] appends one
+//! [`Opcode`] exactly as given, [`Generator::fill`] appends random opcodes the
+//! way [`Generator::generate`] picks them, and [`Generator::finish`] cleans up
+//! the stack and writes STOP. every step updates the simulated stack and memo,
+//! so random opcodes after a scripted one build on what it pushed.
+
+use color_eyre::eyre::eyre;
+use color_eyre::Result;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+use super::source::GenerationSource;
+use super::Generator;
+use crate::opcodes::{ArgLayout, Opcode, OpcodeKind, PICKLE_OPCODES};
+
+impl Generator {
+    /// start a scripted pickle: reset the generator and write PROTO.
+    ///
+    /// [`fill`](Self::fill) draws from a PRNG seeded here with the generator's
+    /// seed, so a seeded script builds the same pickle every time.
+    ///
+    /// fails if no seed is set and OS entropy is unavailable.
+    pub fn begin(&mut self) -> Result<()> {
+        self.reset();
+        let mut rng = match self.seed {
+            Some(seed) => ChaCha8Rng::seed_from_u64(seed),
+            #[cfg(feature = "os-rng")]
+            None => ChaCha8Rng::from_os_rng(),
+            #[cfg(not(feature = "os-rng"))]
+            None => {
+                return Err(eyre!(
+                    "no seed set and OS entropy is unavailable (built without the os-rng feature)"
+                ))
+            }
+        };
+        self.emit_proto(&mut GenerationSource::Rand(&mut rng));
+        self.emitted_opcodes = usize::from(self.state.proto_emitted);
+        self.script_rng = Some(rng);
+        Ok(())
+    }
+
+    /// append `opcode` to the scripted pickle, argument and all.
+    ///
+    /// the opcode is written as [`Opcode::encode`] writes it, without
+    /// mutations, and simulated like a random one. it has to be one random
+    /// generation could emit here: part of the protocol, with what it pops on
+    /// the stack, and a GET has to read a stored memo index (unless unsafe
+    /// mutations are on). PROTO, FRAME, and STOP are written by
+    /// [`begin`](Self::begin) and [`finish`](Self::finish), so they are
+    /// rejected; so are EXT, buffer, and persistent-id opcodes unless enabled.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pickle_fuzzer::{disasm, Generator, Opcode, Version};
+    ///
+    /// let mut gen = Generator::new(Version::V2).with_seed(1);
+    /// gen.begin().unwrap();
+    /// gen.fill(4).unwrap();
+    /// gen.emit(Opcode::Global("os".into(), "system".into())).unwrap();
+    /// gen.fill(4).unwrap();
+    /// let pickle = gen.finish().unwrap();
+    /// assert!(disasm::validate(&pickle).is_ok());
+    /// ```
+    pub fn emit(&mut self, opcode: Opcode) -> Result<()> {
+        use OpcodeKind as Op;
+
+        if self.script_rng.is_none() {
+            return Err(eyre!("emit needs a pickle started with begin()"));
+        }
+        let kind = opcode.kind();
+        if matches!(kind, Op::Proto | Op::Frame | Op::Stop) {
+            return Err(eyre!("{} is written by begin() and finish()", kind.name()));
+        }
+        let protocol = &PICKLE_OPCODES[&self.state.version.as_u8()];
+        if !protocol.contains(&kind) {
+            return Err(eyre!(
+                "{} is not part of protocol {}",
+                kind.name(),
+                self.state.version
+            ));
+        }
+        let index = match opcode {
+            Opcode::Get(index) | Opcode::LongBinGet(index) => Some(index as usize),
+            Opcode::BinGet(index) => Some(index as usize),
+            _ => None,
+        };
+        let readable =
+            index.is_none_or(|index| self.unsafe_mutations || self.state.memo.contains_key(&index));
+        if !self.can_emit(kind) || !readable {
+            return Err(eyre!(
+                "{} can't be emitted with the stack and memo as they are",
+                kind.name()
+            ));
+        }
+
+        let encoded = opcode.encode();
+        if !self.fits_byte_limit(encoded.len() + self.current_cleanup_opcode_count()) {
+            return Err(eyre!("{} doesn't fit the byte limit", kind.name()));
+        }
+        // process_stack_ops takes counted payloads without their length prefix,
+        // and LONG1/LONG4 with it
+        let arg = match kind.info().arg.layout() {
+            ArgLayout::None => None,
+            ArgLayout::Prefixed(width) if !matches!(kind, Op::Long1 | Op::Long4) => {
+                Some(&encoded[1 + width..])
+            }
+            _ => Some(&encoded[1..]),
+        };
+        self.output.extend_from_slice(&encoded);
+        self.process_stack_ops(kind, arg);
+        self.take_strict_violation()?;
+        self.emitted_opcodes += 1;
+        Ok(())
+    }
+
+    /// append up to `opcodes` random opcodes to the scripted pickle.
+    ///
+    /// picks and mutates them like [`generate`](Self::generate) does, minus
+    /// the multi-opcode patterns. stops early when no opcode can follow or the
+    /// byte limit leaves no room for one.
+    pub fn fill(&mut self, opcodes: usize) -> Result<()> {
+        let Some(mut rng) = self.script_rng.take() else {
+            return Err(eyre!("fill needs a pickle started with begin()"));
+        };
+        let result = self.fill_from(&mut GenerationSource::Rand(&mut rng), opcodes);
+        self.script_rng = Some(rng);
+        result
+    }
+
+    fn fill_from(&mut self, source: &mut GenerationSource, opcodes: usize) -> Result<()> {
+        for _ in 0..opcodes {
+            if !self.fits_byte_limit(self.current_cleanup_opcode_count() + 1) {
+                break;
+            }
+            let mut valid_ops = self.get_valid_opcodes();
+            valid_ops
+                .retain(|opcode| self.fits_byte_limit(self.cleanup_opcode_count_after(opcode) + 1));
+            if valid_ops.is_empty() {
+                break;
+            }
+            let chosen = self.weighted_choice(valid_ops, source);
+
+            let rollback = self.bufsize.map(|_| self.state.clone());
+            let output_len = self.emit_and_process(chosen, source)?;
+            self.take_strict_violation()?;
+            if !self.fits_byte_limit(self.current_cleanup_opcode_count()) {
+                if let Some(state) = rollback {
+                    self.state = state;
+                    self.output.truncate(output_len);
+                }
+                break;
+            }
+            if self.output.len() != output_len {
+                self.emitted_opcodes += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// clean the stack up to one object, write STOP, and return the pickle.
+    ///
+    /// ends the script: another one starts with [`begin`](Self::begin).
+    pub fn finish(&mut self) -> Result<Vec<u8>> {
+        if self.script_rng.take().is_none() {
+            return Err(eyre!("finish needs a pickle started with begin()"));
+        }
+        self.emitted_opcodes += self.current_cleanup_opcode_count() + 1;
+        self.cleanup_for_stop();
+        self.emit_opcode(OpcodeKind::Stop);
+        self.take_strict_violation()?;
+        Ok(self.output.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::disasm::{disassemble, validate};
+    use crate::{Generator, Opcode, Version};
+
+    #[test]
+    fn scripted_opcodes_land_where_they_were_emitted() {
+        for version in Version::all() {
+            let mut generator = Generator::new(version).with_seed(5);
+            generator.begin().unwrap();
+            generator.fill(6).unwrap();
+            let before = generator.output.len();
+            generator
+                .emit(Opcode::Global("os".into(), "system".into()))
+                .unwrap();
+            generator.emit(Opcode::Int(7)).unwrap();
+            generator.fill(6).unwrap();
+            let pickle = generator.finish().unwrap();
+
+            assert_eq!(&pickle[before..before + 14], b"cos\nsystem\nI7\n");
+            validate(&pickle).unwrap_or_else(|e| panic!("protocol {version}: {e}"));
+            assert_eq!(
+                generator.stats().opcodes,
+                disassemble(&pickle).unwrap().len(),
+                "protocol {version}"
+            );
+        }
+    }
+
+    #[test]
+    fn seeded_scripts_are_deterministic() {
+        let script = |generator: &mut Generator| {
+            generator.begin().unwrap();
+            generator.fill(10).unwrap();
+            generator.emit(Opcode::EmptyList).unwrap();
+            generator.fill(10).unwrap();
+            generator.finish().unwrap()
+        };
+        let mut generator = Generator::new(Version::V4).with_seed(9);
+        assert_eq!(script(&mut generator), script(&mut generator));
+    }
+
+    #[test]
+    fn emit_rejects_opcodes_the_state_cannot_take() {
+        let mut generator = Generator::new(Version::V1).with_seed(1);
+        assert!(generator.emit(Opcode::None).is_err());
+
+        generator.begin().unwrap();
+        assert!(generator.emit(Opcode::Pop).is_err());
+        assert!(generator.emit(Opcode::BinGet(0)).is_err());
+        assert!(generator.emit(Opcode::NewTrue).is_err());
+        assert!(generator.emit(Opcode::Stop).is_err());
+        assert!(generator.emit(Opcode::Ext1(1)).is_err());
+
+        generator.emit(Opcode::None).unwrap();
+        generator.emit(Opcode::BinPut(3)).unwrap();
+        generator.emit(Opcode::BinGet(3)).unwrap();
+        generator.emit(Opcode::Pop).unwrap();
+        assert_eq!(generator.finish().unwrap(), b"N\x71\x03\x68\x030.");
+        assert!(generator.fill(1).is_err());
+    }
+}
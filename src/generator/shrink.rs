@@ -0,0 +1,367 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! decision-level shrinking of recorded traces (shrink).
+//!
+//! a byte-level minimizer cuts a crashing pickle apart and mostly ends up with
+//! inputs the unpickler rejects early. shrinking the [`EntropyTrace`] behind a
+//! generated pickle instead replays every candidate through the generator, so
+//! each one is a valid pickle, and keeps a candidate only while the caller's
+//! predicate still holds.
+//!
+//! candidates get strictly smaller: fewer decisions, or the same number with a
+//! value closer to zero (or to the bottom of its range). every pass only ever
+//! moves toward smaller traces, so shrinking always terminates.
+
+use color_eyre::eyre::eyre;
+use color_eyre::Result;
+
+use super::trace::{Decision, EntropyTrace};
+use super::Generator;
+
+/// largest block of consecutive decisions one deletion removes.
+const MAX_DELETION_BLOCK: usize = 8;
+
+/// the result of [`Generator::shrink`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Shrunk {
+    /// the smallest trace found whose pickle still satisfies the predicate
+    pub trace: EntropyTrace,
+    /// the pickle `trace` replays to
+    pub pickle: Vec<u8>,
+    /// how many times the predicate was evaluated
+    pub runs: usize,
+}
+
+impl Generator {
+    /// shrink `trace` while `interesting` keeps holding for its pickle.
+    ///
+    /// replays candidates with this generator's configuration: traces with
+    /// decisions cut off the end, with blocks of decisions deleted, and with
+    /// single decisions made simpler (0, `false`, the bottom of a range, or
+    /// half the value). a candidate whose pickle `interesting` accepts becomes
+    /// the new trace, trimmed to the decisions its replay used. passes repeat
+    /// until none makes progress or `interesting` has run `max_runs` times.
+    ///
+    /// the trace should replay with this configuration, so record it with
+    /// [`generate_recorded`](Self::generate_recorded) on the same generator.
+    /// with [`ExhaustionPolicy::Stop`](super::ExhaustionPolicy::Stop) a cut
+    /// trace ends the pickle where the decisions end, so the pickle shrinks
+    /// with the trace instead of being padded to the minimum opcode count.
+    /// `replay(&shrunk.trace)` gives back `shrunk.pickle`.
+    ///
+    /// fails if `trace`'s own pickle doesn't satisfy `interesting`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pickle_fuzzer::{ExhaustionPolicy, Generator, Version};
+    ///
+    /// let mut gen = Generator::new(Version::V2)
+    ///     .with_seed(3)
+    ///     .with_exhaustion_policy(ExhaustionPolicy::Stop);
+    /// let (pickle, trace) = gen.generate_recorded().unwrap();
+    /// let has_tuple3 = |pickle: &[u8]| pickle.contains(&0x87);
+    /// # if !has_tuple3(&pickle) { return; }
+    /// let shrunk = gen.shrink(&trace, 1000, has_tuple3).unwrap();
+    /// assert!(shrunk.trace.len() <= trace.len());
+    /// assert!(has_tuple3(&shrunk.pickle));
+    /// ```
+    pub fn shrink(
+        &mut self,
+        trace: &EntropyTrace,
+        max_runs: usize,
+        mut interesting: impl FnMut(&[u8]) -> bool,
+    ) -> Result<Shrunk> {
+        let consumed = self.run_replay(&trace.decisions)?;
+        let pickle = self.output.clone();
+        if !interesting(&pickle) {
+            return Err(eyre!(
+                "the pickle of the trace does not satisfy the predicate"
+            ));
+        }
+
+        let mut shrinker = Shrinker {
+            generator: self,
+            interesting,
+            max_runs,
+            best: Shrunk {
+                trace: EntropyTrace {
+                    decisions: trace.decisions[..consumed].to_vec(),
+                },
+                pickle,
+                runs: 1,
+            },
+        };
+        loop {
+            let before = shrinker.best.trace.len();
+            let mut progress = shrinker.cut_tail();
+            progress |= shrinker.delete_blocks();
+            progress |= shrinker.simplify_values();
+            if !progress || shrinker.out_of_runs() {
+                break;
+            }
+            debug_assert!(shrinker.best.trace.len() <= before);
+        }
+        Ok(shrinker.best)
+    }
+}
+
+struct Shrinker<'g, F> {
+    generator: &'g mut Generator,
+    interesting: F,
+    max_runs: usize,
+    best: Shrunk,
+}
+
+impl<F: FnMut(&[u8]) -> bool> Shrinker<'_, F> {
+    fn out_of_runs(&self) -> bool {
+        self.best.runs >= self.max_runs
+    }
+
+    /// replay `decisions` and keep them if the pickle is still interesting.
+    fn try_candidate(&mut self, mut decisions: Vec<Decision>) -> bool {
+        if self.out_of_runs() {
+            return false;
+        }
+        // a configuration that fails to generate is not interesting
+        let Ok(consumed) = self.generator.run_replay(&decisions) else {
+            return false;
+        };
+        self.best.runs += 1;
+        if !(self.interesting)(&self.generator.output) {
+            return false;
+        }
+        decisions.truncate(consumed);
+        self.best.trace.decisions = decisions;
+        self.best.pickle = self.generator.output.clone();
+        true
+    }
+
+    /// cut decisions off the end, halving the cut each time it fails.
+    fn cut_tail(&mut self) -> bool {
+        let mut progress = false;
+        let mut cut = self.best.trace.len() / 2;
+        while cut > 0 {
+            let len = self.best.trace.len();
+            if cut <= len && self.try_candidate(self.best.trace.decisions[..len - cut].to_vec()) {
+                progress = true;
+            } else {
+                cut /= 2;
+            }
+        }
+        progress
+    }
+
+    /// delete every block of up to [`MAX_DELETION_BLOCK`] consecutive decisions.
+    fn delete_blocks(&mut self) -> bool {
+        let mut progress = false;
+        let mut block = MAX_DELETION_BLOCK;
+        while block > 0 {
+            let mut start = 0;
+            while start + block <= self.best.trace.len() {
+                let mut decisions = self.best.trace.decisions.clone();
+                decisions.drain(start..start + block);
+                if self.try_candidate(decisions) {
+                    progress = true;
+                } else {
+                    start += 1;
+                }
+            }
+            block /= 2;
+        }
+        progress
+    }
+
+    /// replace each decision with simpler ones, for as long as one is kept.
+    fn simplify_values(&mut self) -> bool {
+        let mut progress = false;
+        let mut index = 0;
+        while index < self.best.trace.len() {
+            let simpler = simpler_decisions(&self.best.trace.decisions[index]);
+            let kept = simpler.into_iter().any(|decision| {
+                let mut decisions = self.best.trace.decisions.clone();
+                decisions[index] = decision;
+                self.try_candidate(decisions)
+            });
+            if kept {
+                progress = true;
+            } else {
+                index += 1;
+            }
+        }
+        progress
+    }
+}
+
+/// simpler stand-ins for `decision`, simplest first; empty when it can't get
+/// any simpler.
+fn simpler_decisions(decision: &Decision) -> Vec<Decision> {
+    // the simplest value, then half way there
+    fn toward<T: PartialEq + Copy>(
+        value: T,
+        zero: T,
+        half: T,
+        wrap: fn(T) -> Decision,
+    ) -> Vec<Decision> {
+        let mut simpler = Vec::new();
+        if value != zero {
+            simpler.push(wrap(zero));
+            if half != zero && half != value {
+                simpler.push(wrap(half));
+            }
+        }
+        simpler
+    }
+
+    match *decision {
+        Decision::Bool(value) => toward(value, false, false, Decision::Bool),
+        Decision::U8(value) => toward(value, 0, value / 2, Decision::U8),
+        Decision::U16(value) => toward(value, 0, value / 2, Decision::U16),
+        Decision::U32(value) => toward(value, 0, value / 2, Decision::U32),
+        Decision::I32(value) => toward(value, 0, value / 2, Decision::I32),
+        Decision::I64(value) => toward(value, 0, value / 2, Decision::I64),
+        Decision::F64(value) => {
+            if value == 0.0 {
+                Vec::new()
+            } else {
+                vec![Decision::F64(0.0)]
+            }
+        }
+        Decision::Range { min, max, value } => {
+            let wrap = |value| Decision::Range { min, max, value };
+            let mut simpler = Vec::new();
+            if value > min {
+                simpler.push(wrap(min));
+                let half = min + (value - min) / 2;
+                if half != min {
+                    simpler.push(wrap(half));
+                }
+            }
+            simpler
+        }
+        Decision::Bytes(ref bytes) => {
+            if bytes.iter().all(|&byte| byte == 0) {
+                Vec::new()
+            } else {
+                vec![Decision::Bytes(vec![0; bytes.len()])]
+            }
+        }
+        Decision::Char(c) => {
+            if c == 'a' {
+                Vec::new()
+            } else {
+                vec![Decision::Char('a')]
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disasm::{disassemble, validate};
+    use crate::{ExhaustionPolicy, Version};
+
+    fn has_global(pickle: &[u8]) -> bool {
+        disassemble(pickle)
+            .map(|instructions| {
+                instructions
+                    .iter()
+                    .any(|i| matches!(i.name, "GLOBAL" | "STACK_GLOBAL"))
+            })
+            .unwrap_or(false)
+    }
+
+    #[test]
+    fn shrinks_to_a_smaller_trace_that_keeps_the_property() {
+        let mut shrunk_any = false;
+        for version in [Version::V0, Version::V2, Version::V4] {
+            for seed in 0..8 {
+                let mut gen = Generator::new(version)
+                    .with_seed(seed)
+                    .with_opcode_range(40, 120)
+                    .with_exhaustion_policy(ExhaustionPolicy::Stop);
+                let (pickle, trace) = gen.generate_recorded().unwrap();
+                if !has_global(&pickle) {
+                    continue;
+                }
+
+                let shrunk = gen.shrink(&trace, 2000, has_global).unwrap();
+                assert!(has_global(&shrunk.pickle));
+                validate(&shrunk.pickle).unwrap();
+                assert!(shrunk.trace.len() < trace.len(), "{version:?} seed {seed}");
+                assert!(
+                    shrunk.pickle.len() < pickle.len(),
+                    "{version:?} seed {seed}"
+                );
+                assert!(shrunk.runs <= 2000);
+                assert_eq!(gen.replay(&shrunk.trace).unwrap(), shrunk.pickle);
+                shrunk_any = true;
+            }
+        }
+        assert!(shrunk_any);
+    }
+
+    #[test]
+    fn stops_after_max_runs() {
+        let mut gen = Generator::new(Version::V3).with_seed(1);
+        let (_, trace) = gen.generate_recorded().unwrap();
+        let mut runs = 0;
+        let shrunk = gen
+            .shrink(&trace, 5, |_| {
+                // only the original pickle is interesting, so no pass runs out early
+                runs += 1;
+                runs == 1
+            })
+            .unwrap();
+        assert_eq!(shrunk.runs, 5);
+        assert_eq!(runs, 5);
+    }
+
+    #[test]
+    fn rejects_traces_that_do_not_satisfy_the_predicate() {
+        let mut gen = Generator::new(Version::V3).with_seed(1);
+        let (_, trace) = gen.generate_recorded().unwrap();
+        assert!(gen.shrink(&trace, 100, |_| false).is_err());
+    }
+
+    #[test]
+    fn simpler_decisions_get_strictly_smaller() {
+        assert_eq!(
+            simpler_decisions(&Decision::I64(-9)),
+            vec![Decision::I64(0), Decision::I64(-4)]
+        );
+        assert_eq!(
+            simpler_decisions(&Decision::Range {
+                min: 2,
+                max: 9,
+                value: 3
+            }),
+            vec![Decision::Range {
+                min: 2,
+                max: 9,
+                value: 2
+            }]
+        );
+        assert!(simpler_decisions(&Decision::Bool(false)).is_empty());
+        assert!(simpler_decisions(&Decision::Bytes(vec![0, 0])).is_empty());
+        assert_eq!(
+            simpler_decisions(&Decision::Char('z')),
+            vec![Decision::Char('a')]
+        );
+    }
+}
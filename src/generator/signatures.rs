@@ -0,0 +1,662 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! argument shapes of well-known callables.
+//!
+//! REDUCE, NEWOBJ, NEWOBJ_EX, OBJ, and INST call whatever the stack holds
+//! with whatever arguments are above it, so most generated calls would raise
+//! a `TypeError` in a real unpickler. the table here lists, for a few
+//! builtin and stdlib callables, the argument lists calling them and their
+//! `__new__` accept, and what kind of object they return. generation
+//! consults it before one of those opcodes calls a listed callable, so the
+//! calls it does emit construct something, and the results feed later
+//! checks: a `frozenset(...)` is hashable, a `list(...)` is iterable.
+//! callables the table doesn't list are called as before.
+//!
+//! the shapes describe the simulated stack, which knows types but not every
+//! value: ints that LONG clamped can't be told from small ones, so no shape
+//! here passes an int where a float overflow or a range check would reject
+//! a large one.
+
+use super::Generator;
+use crate::opcodes::OpcodeKind;
+use crate::stack::{ContainerKind, InstanceObject, StackObject, StackObjectRef};
+
+/// how deep checks look into nested tuples before giving up.
+const MAX_DEPTH: usize = 16;
+
+/// what kind of object a simulated value unpickles to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Kind {
+    Int,
+    Bool,
+    Float,
+    Complex,
+    Str,
+    Bytes,
+    ByteArray,
+    NoneValue,
+    Tuple,
+    List,
+    Dict,
+    Set,
+    FrozenSet,
+    /// an imported global, i.e. a class or function
+    Global,
+    /// something else, like a range or Decimal
+    Other {
+        hashable: bool,
+    },
+    /// a call result or placeholder nothing is known about
+    Unknown,
+}
+
+/// one positional argument a callable accepts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Arg {
+    /// any object
+    Any,
+    /// an int or bool
+    Int,
+    /// a float or bool
+    Real,
+    /// a float, bool, or complex
+    Number,
+    /// bytes or a bytearray
+    Bytes,
+    /// any iterable
+    Iterable,
+    /// an iterable of hashable items
+    HashableIterable,
+    /// a dict
+    Mapping,
+}
+
+/// the argument lists a call accepts.
+#[derive(Debug, Clone, Copy)]
+pub(super) enum Params {
+    /// any arguments at all
+    Any,
+    /// exactly one of these argument lists
+    Lists(&'static [&'static [Arg]]),
+    /// `copyreg._reconstructor(cls, base, state)`: `cls` and `base` the same
+    /// listed type, whose `__new__` and `__init__` both take `state`
+    Reconstructor,
+}
+
+/// what generation knows about one callable.
+#[derive(Debug)]
+pub(super) struct Signature {
+    pub module: &'static str,
+    pub name: &'static str,
+    /// what calling it (REDUCE, OBJ, INST) accepts
+    pub call: Params,
+    /// what `cls.__new__(cls, ...)` (NEWOBJ, NEWOBJ_EX) accepts, for types
+    pub new: Option<Params>,
+    /// what it returns, or `None` when that depends on the arguments
+    pub returns: Option<Kind>,
+}
+
+const EMPTY_OR_ITERABLE: &[&[Arg]] = &[&[], &[Arg::Iterable]];
+const EMPTY_OR_HASHABLE_ITERABLE: &[&[Arg]] = &[&[], &[Arg::HashableIterable]];
+const EMPTY_OR_MAPPING: &[&[Arg]] = &[&[], &[Arg::Mapping]];
+const EMPTY_OR_BYTES: &[&[Arg]] = &[&[], &[Arg::Bytes]];
+const EMPTY_OR_INT: &[&[Arg]] = &[&[], &[Arg::Int]];
+const EMPTY_OR_REAL: &[&[Arg]] = &[&[], &[Arg::Real]];
+const EMPTY_OR_ANY: &[&[Arg]] = &[&[], &[Arg::Any]];
+const COMPLEX: &[&[Arg]] = &[&[], &[Arg::Number], &[Arg::Number, Arg::Number]];
+
+/// the callables generation knows the arguments of.
+pub(super) const SIGNATURES: &[Signature] = &[
+    Signature {
+        module: "builtins",
+        name: "list",
+        call: Params::Lists(EMPTY_OR_ITERABLE),
+        // the mutable builtins' __new__ ignores its arguments
+        new: Some(Params::Any),
+        returns: Some(Kind::List),
+    },
+    Signature {
+        module: "builtins",
+        name: "dict",
+        call: Params::Lists(EMPTY_OR_MAPPING),
+        new: Some(Params::Any),
+        returns: Some(Kind::Dict),
+    },
+    Signature {
+        module: "builtins",
+        name: "set",
+        call: Params::Lists(EMPTY_OR_HASHABLE_ITERABLE),
+        new: Some(Params::Any),
+        returns: Some(Kind::Set),
+    },
+    Signature {
+        module: "builtins",
+        name: "frozenset",
+        call: Params::Lists(EMPTY_OR_HASHABLE_ITERABLE),
+        new: Some(Params::Lists(EMPTY_OR_HASHABLE_ITERABLE)),
+        returns: Some(Kind::FrozenSet),
+    },
+    Signature {
+        module: "builtins",
+        name: "complex",
+        call: Params::Lists(COMPLEX),
+        new: Some(Params::Lists(COMPLEX)),
+        returns: Some(Kind::Complex),
+    },
+    Signature {
+        module: "builtins",
+        name: "bytearray",
+        call: Params::Lists(EMPTY_OR_BYTES),
+        new: Some(Params::Any),
+        returns: Some(Kind::ByteArray),
+    },
+    Signature {
+        module: "builtins",
+        name: "tuple",
+        call: Params::Lists(EMPTY_OR_ITERABLE),
+        new: Some(Params::Lists(EMPTY_OR_ITERABLE)),
+        returns: Some(Kind::Tuple),
+    },
+    Signature {
+        module: "builtins",
+        name: "bytes",
+        call: Params::Lists(EMPTY_OR_BYTES),
+        new: Some(Params::Lists(EMPTY_OR_BYTES)),
+        returns: Some(Kind::Bytes),
+    },
+    Signature {
+        module: "builtins",
+        name: "str",
+        call: Params::Lists(EMPTY_OR_ANY),
+        new: Some(Params::Lists(EMPTY_OR_ANY)),
+        returns: Some(Kind::Str),
+    },
+    Signature {
+        module: "builtins",
+        name: "bool",
+        call: Params::Lists(EMPTY_OR_ANY),
+        new: Some(Params::Lists(EMPTY_OR_ANY)),
+        returns: Some(Kind::Bool),
+    },
+    Signature {
+        module: "builtins",
+        name: "int",
+        call: Params::Lists(EMPTY_OR_INT),
+        new: Some(Params::Lists(EMPTY_OR_INT)),
+        returns: Some(Kind::Int),
+    },
+    Signature {
+        module: "builtins",
+        name: "float",
+        call: Params::Lists(EMPTY_OR_REAL),
+        new: Some(Params::Lists(EMPTY_OR_REAL)),
+        returns: Some(Kind::Float),
+    },
+    Signature {
+        module: "builtins",
+        name: "range",
+        call: Params::Lists(&[&[Arg::Int], &[Arg::Int, Arg::Int]]),
+        new: None,
+        returns: Some(Kind::Other { hashable: true }),
+    },
+    Signature {
+        module: "builtins",
+        name: "slice",
+        call: Params::Lists(&[
+            &[Arg::Any],
+            &[Arg::Any, Arg::Any],
+            &[Arg::Any, Arg::Any, Arg::Any],
+        ]),
+        new: None,
+        returns: Some(Kind::Other { hashable: false }),
+    },
+    Signature {
+        module: "builtins",
+        name: "object",
+        call: Params::Lists(&[&[]]),
+        new: Some(Params::Lists(&[&[]])),
+        returns: Some(Kind::Other { hashable: true }),
+    },
+    Signature {
+        module: "collections",
+        name: "OrderedDict",
+        call: Params::Lists(EMPTY_OR_MAPPING),
+        new: Some(Params::Any),
+        returns: Some(Kind::Dict),
+    },
+    Signature {
+        module: "collections",
+        name: "Counter",
+        call: Params::Lists(&[&[], &[Arg::Mapping], &[Arg::HashableIterable]]),
+        new: Some(Params::Any),
+        returns: Some(Kind::Dict),
+    },
+    Signature {
+        module: "collections",
+        name: "deque",
+        call: Params::Lists(EMPTY_OR_ITERABLE),
+        new: Some(Params::Any),
+        returns: Some(Kind::List),
+    },
+    Signature {
+        module: "decimal",
+        name: "Decimal",
+        call: Params::Lists(EMPTY_OR_INT),
+        new: Some(Params::Lists(EMPTY_OR_INT)),
+        returns: Some(Kind::Other { hashable: true }),
+    },
+    Signature {
+        module: "fractions",
+        name: "Fraction",
+        call: Params::Lists(EMPTY_OR_INT),
+        new: Some(Params::Lists(EMPTY_OR_INT)),
+        returns: Some(Kind::Other { hashable: true }),
+    },
+    Signature {
+        module: "copyreg",
+        name: "_reconstructor",
+        call: Params::Reconstructor,
+        new: None,
+        returns: None,
+    },
+    Signature {
+        module: "copy_reg",
+        name: "_reconstructor",
+        call: Params::Reconstructor,
+        new: None,
+        returns: None,
+    },
+];
+
+/// the signature of `module.name`, if it is listed.
+pub(super) fn signature(module: &str, name: &str) -> Option<&'static Signature> {
+    SIGNATURES
+        .iter()
+        .find(|signature| signature.module == module && signature.name == name)
+}
+
+/// the signature of a global on the stack, plain or wrapped as a callable.
+fn signature_of(obj: &StackObjectRef) -> Option<&'static Signature> {
+    match &*obj.borrow() {
+        StackObject::Global { module, name } => signature(module, name),
+        StackObject::Callable(inner) => signature_of(inner),
+        _ => None,
+    }
+}
+
+/// what a call recorded as `instance` returned: the signature's result, or
+/// for `_reconstructor(cls, ...)` the result of `cls`.
+fn returned_kind(instance: &InstanceObject) -> Kind {
+    let Some(signature) = signature_of(&instance.callable) else {
+        return Kind::Unknown;
+    };
+    if let Some(kind) = signature.returns {
+        return kind;
+    }
+    match &*instance.args.borrow() {
+        StackObject::Tuple(items) => items
+            .first()
+            .and_then(signature_of)
+            .and_then(|cls| cls.returns)
+            .unwrap_or(Kind::Unknown),
+        _ => Kind::Unknown,
+    }
+}
+
+/// what `obj` unpickles to.
+pub(super) fn kind_of(obj: &StackObjectRef) -> Kind {
+    match &*obj.borrow() {
+        StackObject::Int(_) | StackObject::BigInt(_) => Kind::Int,
+        StackObject::Bool(_) => Kind::Bool,
+        StackObject::Float(_) => Kind::Float,
+        StackObject::String(_) => Kind::Str,
+        StackObject::Bytes(_) => Kind::Bytes,
+        StackObject::ByteArray(_) => Kind::ByteArray,
+        StackObject::None => Kind::NoneValue,
+        StackObject::Global { .. } | StackObject::Callable(_) => Kind::Global,
+        StackObject::Instance(instance) => returned_kind(instance),
+        other => match other.container_kind() {
+            Some(ContainerKind::List) => Kind::List,
+            Some(ContainerKind::Tuple) => Kind::Tuple,
+            Some(ContainerKind::Dict) => Kind::Dict,
+            Some(ContainerKind::Set) => Kind::Set,
+            Some(ContainerKind::FrozenSet) => Kind::FrozenSet,
+            None => Kind::Unknown,
+        },
+    }
+}
+
+/// whether `obj` unpickles to something hashable, so it can be a dict key
+/// or set item.
+pub(super) fn is_hashable(obj: &StackObjectRef) -> bool {
+    hashable_within(obj, 0)
+}
+
+fn hashable_within(obj: &StackObjectRef, depth: usize) -> bool {
+    if let StackObject::Tuple(items) = &*obj.borrow() {
+        return depth < MAX_DEPTH && items.iter().all(|item| hashable_within(item, depth + 1));
+    }
+    match kind_of(obj) {
+        Kind::Int
+        | Kind::Bool
+        | Kind::Float
+        | Kind::Complex
+        | Kind::Str
+        | Kind::Bytes
+        | Kind::NoneValue
+        | Kind::FrozenSet
+        | Kind::Global => true,
+        Kind::Other { hashable } => hashable,
+        // a summarized or constructed tuple's items are unknown
+        _ => false,
+    }
+}
+
+/// whether `obj` unpickles to an iterable, of hashable items if
+/// `hashable_items`.
+fn is_iterable(obj: &StackObjectRef, hashable_items: bool) -> bool {
+    if let StackObject::List(items) | StackObject::Tuple(items) = &*obj.borrow() {
+        return !hashable_items || items.iter().all(is_hashable);
+    }
+    match kind_of(obj) {
+        // bytes iterate as ints, str as str, and sets and dicts as their
+        // already hashed items and keys
+        Kind::Str | Kind::Bytes | Kind::ByteArray | Kind::Dict | Kind::Set | Kind::FrozenSet => {
+            true
+        }
+        Kind::List | Kind::Tuple => !hashable_items,
+        _ => false,
+    }
+}
+
+impl Arg {
+    /// whether `obj` can be passed as this argument.
+    fn accepts(self, obj: &StackObjectRef) -> bool {
+        let kind = kind_of(obj);
+        match self {
+            Arg::Any => true,
+            Arg::Int => matches!(kind, Kind::Int | Kind::Bool),
+            Arg::Real => matches!(kind, Kind::Float | Kind::Bool),
+            Arg::Number => matches!(kind, Kind::Float | Kind::Bool | Kind::Complex),
+            Arg::Bytes => matches!(kind, Kind::Bytes | Kind::ByteArray),
+            Arg::Iterable => is_iterable(obj, false),
+            Arg::HashableIterable => is_iterable(obj, true),
+            Arg::Mapping => kind == Kind::Dict,
+        }
+    }
+}
+
+impl Params {
+    /// whether a call with `args` fits.
+    pub(super) fn accept(self, args: &[StackObjectRef]) -> bool {
+        match self {
+            Params::Any => true,
+            Params::Lists(lists) => lists.iter().any(|list| {
+                list.len() == args.len() && list.iter().zip(args).all(|(arg, obj)| arg.accepts(obj))
+            }),
+            Params::Reconstructor => {
+                let [cls, base, state] = args else {
+                    return false;
+                };
+                // _reconstructor(cls, cls, state) is cls.__new__(cls, state)
+                // followed by cls.__init__(obj, state)
+                let state = std::slice::from_ref(state);
+                match (signature_of(cls), signature_of(base)) {
+                    (Some(cls), Some(base)) if std::ptr::eq(cls, base) => {
+                        cls.new.is_some_and(|new| new.accept(state)) && cls.call.accept(state)
+                    }
+                    _ => false,
+                }
+            }
+        }
+    }
+}
+
+impl Generator {
+    /// check callables against their signatures before calling them (on by
+    /// default).
+    ///
+    /// REDUCE, NEWOBJ, NEWOBJ_EX, and OBJ are only picked when the callable
+    /// they would call accepts the arguments on the stack, and INST redraws
+    /// a listed class whose signature rejects the arguments above its MARK.
+    /// the table covers builtin types like `list`, `set`, `complex`, and
+    /// `bytes`, a few `collections`, `decimal`, and `fractions` types, and
+    /// `copyreg._reconstructor`; the many callables it doesn't list are
+    /// called with any arguments, as they are with this off. turning it off
+    /// brings back `frozenset(1)` and friends.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pickle_fuzzer::{Generator, Version};
+    ///
+    /// let checked = Generator::new(Version::V2).with_seed(4).generate().unwrap();
+    /// let unchecked = Generator::new(Version::V2)
+    ///     .with_seed(4)
+    ///     .with_signatures(false)
+    ///     .generate()
+    ///     .unwrap();
+    /// assert!(pickle_fuzzer::disasm::validate(&checked).is_ok());
+    /// assert!(pickle_fuzzer::disasm::validate(&unchecked).is_ok());
+    /// ```
+    pub fn with_signatures(mut self, enabled: bool) -> Self {
+        self.signatures = enabled;
+        self
+    }
+
+    /// whether the callable `opcode` would call accepts the arguments on the
+    /// stack, or `None` if the opcode calls nothing from the stack or the
+    /// callable isn't listed.
+    pub(super) fn signature_verdict(&self, opcode: OpcodeKind) -> Option<bool> {
+        use OpcodeKind as Op;
+
+        let tuple_items = |obj: &StackObjectRef| match &*obj.borrow() {
+            StackObject::Tuple(items) => Some(items.to_vec()),
+            _ => None,
+        };
+        let (callable, args, new) = match opcode {
+            Op::Reduce | Op::NewObj => (
+                self.peek_at(1)?,
+                tuple_items(self.peek_at(0)?),
+                opcode == Op::NewObj,
+            ),
+            Op::NewObjEx => {
+                let no_kwargs = matches!(&*self.peek_at(0)?.borrow(), StackObject::Dict(kwargs) if kwargs.is_empty());
+                let args = tuple_items(self.peek_at(1)?).filter(|_| no_kwargs);
+                (self.peek_at(2)?, args, true)
+            }
+            Op::Obj => {
+                let mark = self.state.stack.top_mark()?;
+                let items = self.state.stack.items().get(mark + 1..)?;
+                let (callable, args) = items.split_first()?;
+                (callable, Some(args.to_vec()), false)
+            }
+            _ => return None,
+        };
+        let signature = signature_of(callable)?;
+        let params = if new {
+            signature.new
+        } else {
+            Some(signature.call)
+        };
+        Some(match (params, args) {
+            (Some(params), Some(args)) => params.accept(&args),
+            // not a type, or arguments that aren't a plain tuple
+            _ => false,
+        })
+    }
+
+    /// whether signature checks let generation pick `opcode`.
+    pub(super) fn signatures_allow(&self, opcode: OpcodeKind) -> bool {
+        !self.signatures || self.signature_verdict(opcode).unwrap_or(true)
+    }
+
+    /// whether INST may instantiate `module.name` with the items above the
+    /// topmost MARK.
+    pub(super) fn inst_fits(&self, module: &str, name: &str) -> bool {
+        let Some(signature) = signature(module, name).filter(|_| self.signatures) else {
+            return true;
+        };
+        let Some(mark) = self.state.stack.top_mark() else {
+            return false;
+        };
+        signature.call.accept(&self.state.stack.items()[mark + 1..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disasm::validate;
+    use crate::opcodes::Opcode;
+    use crate::Version;
+
+    /// a generator on `version` with `opcodes` emitted after PROTO.
+    fn with_stack(version: Version, opcodes: Vec<Opcode>) -> Generator {
+        let mut generator = Generator::new(version).with_seed(1);
+        generator.begin().unwrap();
+        for opcode in opcodes {
+            generator.emit(opcode).unwrap();
+        }
+        generator
+    }
+
+    fn global(module: &str, name: &str) -> Opcode {
+        Opcode::Global(module.into(), name.into())
+    }
+
+    #[test]
+    fn listed_calls_need_fitting_arguments() {
+        let cases = [
+            (global("builtins", "frozenset"), Opcode::BinInt1(1), false),
+            (
+                global("builtins", "frozenset"),
+                Opcode::BinUnicode("ab".into()),
+                true,
+            ),
+            (global("builtins", "complex"), Opcode::BinFloat(1.5), true),
+            (global("builtins", "complex"), Opcode::EmptyList, false),
+            (global("builtins", "range"), Opcode::BinInt1(3), true),
+            (global("decimal", "Decimal"), Opcode::None, false),
+        ];
+        for (callable, arg, fits) in cases {
+            let generator = with_stack(Version::V2, vec![callable.clone(), arg, Opcode::Tuple1]);
+            assert_eq!(
+                generator.signature_verdict(OpcodeKind::Reduce),
+                Some(fits),
+                "{callable:?}"
+            );
+            assert_eq!(generator.signatures_allow(OpcodeKind::Reduce), fits);
+        }
+
+        // range is not something NEWOBJ can create, and unlisted callables
+        // take anything
+        let generator = with_stack(
+            Version::V2,
+            vec![
+                global("builtins", "range"),
+                Opcode::BinInt1(3),
+                Opcode::Tuple1,
+            ],
+        );
+        assert_eq!(generator.signature_verdict(OpcodeKind::NewObj), Some(false));
+        let generator = with_stack(
+            Version::V2,
+            vec![global("os", "getcwd"), Opcode::BinInt1(3), Opcode::Tuple1],
+        );
+        assert_eq!(generator.signature_verdict(OpcodeKind::Reduce), None);
+        assert!(generator.signatures_allow(OpcodeKind::Reduce));
+
+        let generator = with_stack(
+            Version::V2,
+            vec![
+                global("builtins", "frozenset"),
+                Opcode::BinInt1(1),
+                Opcode::Tuple1,
+            ],
+        )
+        .with_signatures(false);
+        assert!(generator.signatures_allow(OpcodeKind::Reduce));
+    }
+
+    #[test]
+    fn obj_and_newobj_ex_read_their_own_layouts() {
+        let generator = with_stack(
+            Version::V4,
+            vec![
+                Opcode::Mark,
+                global("builtins", "complex"),
+                Opcode::BinFloat(1.0),
+                Opcode::BinFloat(2.0),
+            ],
+        );
+        assert_eq!(generator.signature_verdict(OpcodeKind::Obj), Some(true));
+
+        let generator = with_stack(
+            Version::V4,
+            vec![
+                global("builtins", "set"),
+                Opcode::EmptyTuple,
+                Opcode::EmptyDict,
+                Opcode::BinInt1(1),
+                Opcode::BinInt1(2),
+                Opcode::SetItem,
+            ],
+        );
+        // set.__new__ takes no keyword arguments here
+        assert_eq!(
+            generator.signature_verdict(OpcodeKind::NewObjEx),
+            Some(false)
+        );
+    }
+
+    #[test]
+    fn call_results_have_the_signatures_kind() {
+        let generator = with_stack(
+            Version::V2,
+            vec![
+                global("builtins", "frozenset"),
+                Opcode::EmptyTuple,
+                Opcode::Reduce,
+                global("copyreg", "_reconstructor"),
+                global("builtins", "list"),
+                global("builtins", "list"),
+                Opcode::EmptyTuple,
+                Opcode::Tuple3,
+                Opcode::Reduce,
+            ],
+        );
+        assert_eq!(kind_of(generator.peek_at(0).unwrap()), Kind::List);
+        assert_eq!(kind_of(generator.peek_at(1).unwrap()), Kind::FrozenSet);
+        assert!(is_hashable(generator.peek_at(1).unwrap()));
+        assert!(!is_hashable(generator.peek_at(0).unwrap()));
+    }
+
+    #[test]
+    fn checked_generation_stays_valid() {
+        for version in Version::all() {
+            for seed in 0..10 {
+                let pickle = Generator::new(version)
+                    .with_seed(seed)
+                    .with_interesting_patterns(true)
+                    .generate()
+                    .unwrap();
+                validate(&pickle).unwrap_or_else(|e| panic!("protocol {version}: {e}"));
+            }
+        }
+    }
+}
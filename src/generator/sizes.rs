@@ -0,0 +1,207 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! container size distributions (with_container_sizes).
+//!
+//! without one, container sizes fall out of random opcode choice. a
+//! [`SizeDistribution`] picks the size of a container up front instead, so a
+//! corpus can lean toward many small containers or a few huge ones on purpose.
+
+use std::str::FromStr;
+
+use color_eyre::eyre::{eyre, Error};
+
+use super::source::{EntropySource, GenerationSource};
+
+/// largest size a distribution may produce; no pickle has room for more
+/// elements than opcodes.
+const MAX_CONTAINER_SIZE: usize = super::MAX_OPCODE_RANGE_BOUND;
+
+/// how the sizes of deliberately sized containers are distributed.
+///
+/// parsed from `constant:N`, `uniform:MIN-MAX`, `zipf:MAX`, or
+/// `zipf:MAX:EXPONENT` (the exponent defaults to 1).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SizeDistribution {
+    /// every container has exactly this many elements
+    Constant(usize),
+    /// every size from `min` to `max` is equally likely
+    Uniform { min: usize, max: usize },
+    /// sizes from 0 to `max`, size `k` about `(k + 1)^-exponent` as likely as
+    /// size 0, so most containers are small and a few are huge
+    Zipf { max: usize, exponent: f64 },
+}
+
+impl SizeDistribution {
+    /// draw a container size.
+    pub fn sample(&self, source: &mut GenerationSource) -> usize {
+        match *self {
+            SizeDistribution::Constant(len) => len,
+            SizeDistribution::Uniform { min, max } => source.gen_range(min, max + 1),
+            SizeDistribution::Zipf { max, exponent } => {
+                // invert the CDF of the continuous power law on [1, max + 2),
+                // which needs one draw however large `max` is
+                let unit = f64::from(source.gen_u32()) / 2f64.powi(32);
+                let end = (max + 2) as f64;
+                let rank = if (exponent - 1.0).abs() < f64::EPSILON {
+                    end.powf(unit)
+                } else {
+                    let power = 1.0 - exponent;
+                    (unit * (end.powf(power) - 1.0) + 1.0).powf(1.0 / power)
+                };
+                (rank as usize).saturating_sub(1).min(max)
+            }
+        }
+    }
+}
+
+fn parse_size(text: &str) -> Result<usize, Error> {
+    let size = text
+        .trim()
+        .parse::<usize>()
+        .map_err(|_| eyre!("invalid container size: {:?}", text))?;
+    if size > MAX_CONTAINER_SIZE {
+        return Err(eyre!(
+            "container size {} is larger than {}",
+            size,
+            MAX_CONTAINER_SIZE
+        ));
+    }
+    Ok(size)
+}
+
+impl FromStr for SizeDistribution {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, params) = s
+            .trim()
+            .split_once(':')
+            .ok_or_else(|| eyre!("expected KIND:PARAMS, got {:?}", s))?;
+        match kind.trim() {
+            "constant" => Ok(SizeDistribution::Constant(parse_size(params)?)),
+            "uniform" => {
+                let (min, max) = params
+                    .split_once('-')
+                    .ok_or_else(|| eyre!("expected uniform:MIN-MAX, got {:?}", s))?;
+                let (min, max) = (parse_size(min)?, parse_size(max)?);
+                if min > max {
+                    return Err(eyre!(
+                        "uniform minimum {} is above its maximum {}",
+                        min,
+                        max
+                    ));
+                }
+                Ok(SizeDistribution::Uniform { min, max })
+            }
+            "zipf" => {
+                let (max, exponent) = match params.split_once(':') {
+                    Some((max, exponent)) => {
+                        let exponent = exponent
+                            .trim()
+                            .parse::<f64>()
+                            .ok()
+                            .filter(|exponent| exponent.is_finite() && *exponent > 0.0)
+                            .ok_or_else(|| eyre!("invalid zipf exponent: {:?}", exponent))?;
+                        (max, exponent)
+                    }
+                    None => (params, 1.0),
+                };
+                Ok(SizeDistribution::Zipf {
+                    max: parse_size(max)?,
+                    exponent,
+                })
+            }
+            other => Err(eyre!(
+                "unknown size distribution {:?} (expected constant, uniform, or zipf)",
+                other
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    #[test]
+    fn parses_every_distribution() {
+        assert_eq!(
+            "constant:500".parse::<SizeDistribution>().unwrap(),
+            SizeDistribution::Constant(500)
+        );
+        assert_eq!(
+            "uniform:2-9".parse::<SizeDistribution>().unwrap(),
+            SizeDistribution::Uniform { min: 2, max: 9 }
+        );
+        assert_eq!(
+            "zipf:1000".parse::<SizeDistribution>().unwrap(),
+            SizeDistribution::Zipf {
+                max: 1000,
+                exponent: 1.0
+            }
+        );
+        assert_eq!(
+            "zipf:50:1.5".parse::<SizeDistribution>().unwrap(),
+            SizeDistribution::Zipf {
+                max: 50,
+                exponent: 1.5
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_distributions() {
+        for text in [
+            "",
+            "constant",
+            "constant:x",
+            "uniform:5",
+            "uniform:9-2",
+            "zipf:10:0",
+            "zipf:10:nan",
+            "normal:5",
+            "constant:50001",
+            "uniform:0-99999999999999999999",
+        ] {
+            assert!(text.parse::<SizeDistribution>().is_err(), "{text:?}");
+        }
+    }
+
+    #[test]
+    fn samples_stay_in_range() {
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let mut source = GenerationSource::Rand(&mut rng);
+        let uniform = SizeDistribution::Uniform { min: 3, max: 5 };
+        let zipf = SizeDistribution::Zipf {
+            max: 1000,
+            exponent: 1.0,
+        };
+
+        let mut small = 0;
+        for _ in 0..1000 {
+            assert!((3..=5).contains(&uniform.sample(&mut source)));
+            let len = zipf.sample(&mut source);
+            assert!(len <= 1000);
+            small += usize::from(len < 10);
+        }
+        // P(k < 10) is ln(11) / ln(1002), about a third
+        assert!((250..450).contains(&small), "{small}");
+        assert_eq!(SizeDistribution::Constant(7).sample(&mut source), 7);
+    }
+}
@@ -22,9 +22,17 @@
 //!
 //! # Architecture
 //!
-//! the `GenerationSource` enum wraps two different entropy sources:
+//! the `GenerationSource` enum wraps these entropy sources:
 //! - **`Rand`**: uses ChaCha8Rng for deterministic, seeded generation in CLI mode
 //! - **`Arbitrary`**: consumes fuzzer-provided bytes for coverage-guided exploration
+//! - **`ArbitraryThenRand`**: fuzzer-provided bytes, then a ChaCha8Rng once
+//!   they run out (see [`ExhaustionPolicy`])
+//! - **`Split`**: two ChaCha8Rng streams, one for structure and one for leaf
+//!   values, so [`Generator::variants`](super::Generator::variants) and
+//!   [`Generator::with_value_seed`](super::Generator::with_value_seed) can fix
+//!   either one and vary the other
+//! - **`Recording`** and **`Replay`**: log every answer of another source to
+//!   an [`EntropyTrace`](super::EntropyTrace), and answer from one later
 //!
 //! the `EntropySource` trait provides a common interface with methods for generating
 //! various primitive types (bool, integers, floats, bytes, strings). all methods
@@ -40,16 +48,45 @@
 //!
 //! # Determinism
 //!
-//! both entropy sources are deterministic:
+//! every entropy source is deterministic:
 //! - `Rand` mode: same seed produces identical pickles
-//! - `Arbitrary` mode: same input bytes produce identical pickles
+//! - `Arbitrary` mode: same input bytes produce identical pickles, and so does
+//!   `ArbitraryThenRand`, whose PRNG is seeded from the input
 //!
 //! this is critical for reproducibility in testing and debugging.
+//!
+//! # Structure and Values
+//!
+//! a `Split` source draws indices, ranges, and booleans (`choose_index`,
+//! `gen_range`, `gen_bool`), which pick opcodes, sizes, memo slots, and
+//! globals, from its structure stream, and the raw values (`gen_u8` through
+//! `gen_f64`, `gen_bytes`, `gen_ascii_char`) that become integers, floats,
+//! strings, and bytes from its value stream. mutators draw everything from the
+//! value stream (see [`GenerationSource::with_values`]), so whether one fires
+//! never shifts the structure stream.
+//!
+//! generation code that draws a leaf value with an index, range, or boolean
+//! (a boundary constant, a flag, a date field) wraps the draw in
+//! `with_values` too, so it lands on the value stream. a draw that decides
+//! which opcodes follow stays on the structure stream even when it looks like
+//! a value, such as an int whose size picks BININT1 or BININT2. for `Rand`
+//! and `Arbitrary` sources `with_values` changes nothing, so the split never
+//! alters their output.
+//!
+//! # Portability
+//!
+//! every draw is made with a fixed-width integer type, never `usize`, so the bytes
+//! consumed from the PRNG or the fuzzer input do not depend on the target's pointer
+//! width. together with the portable ChaCha8 stream this makes a seed + config
+//! produce identical bytes on every OS and architecture. changes that alter the
+//! order or width of draws must bump `GENERATOR_FORMAT_VERSION`.
 
 use arbitrary::Unstructured;
 use rand::{Rng, TryRngCore};
 use rand_chacha::ChaCha8Rng;
 
+use super::trace::{next_decision, Decision};
+
 /// source of entropy for pickle generation.
 ///
 /// this enum abstracts over two different entropy sources:
@@ -72,6 +109,119 @@ pub enum GenerationSource<'a> {
     /// consumes bytes from the fuzzer's input, enabling coverage-guided exploration.
     /// when bytes are exhausted, `Unstructured` provides deterministic fallback values.
     Arbitrary(&'a mut Unstructured<'a>),
+
+    /// fuzzer-provided bytes, then a random number generator once they run out.
+    ///
+    /// used by [`ExhaustionPolicy::SwitchToPrng`]: a short input still decides
+    /// the start of the pickle, and the rest is random rather than the long
+    /// run of zeros and `false`s an exhausted `Unstructured` gives.
+    ArbitraryThenRand {
+        /// the fuzzer's bytes, drawn from while any are left
+        input: &'a mut Unstructured<'a>,
+        /// takes over every draw once `input` is empty
+        rng: &'a mut ChaCha8Rng,
+    },
+
+    /// separate random number generators for structure and leaf values.
+    ///
+    /// used by variant generation, where siblings share the structure stream
+    /// and each gets its own value stream, and by generators with a value
+    /// seed.
+    Split {
+        /// picks opcodes, sizes, memo indices, and globals
+        structure: &'a mut ChaCha8Rng,
+        /// draws integers, floats, strings, bytes, and mutations
+        values: &'a mut ChaCha8Rng,
+    },
+
+    /// another source whose every answer is logged.
+    ///
+    /// used by [`Generator::generate_recorded`](super::Generator::generate_recorded)
+    /// to capture an [`EntropyTrace`](super::EntropyTrace).
+    Recording {
+        /// answers the draws
+        inner: &'a mut GenerationSource<'a>,
+        /// gets one decision per draw, in order
+        trace: &'a mut Vec<Decision>,
+    },
+
+    /// answers from recorded decisions instead of drawing.
+    ///
+    /// used by [`Generator::replay`](super::Generator::replay). a draw takes
+    /// the next decision; one of a different kind, or a draw past the end,
+    /// gets the fallback value an exhausted `Arbitrary` source gives, and a
+    /// range decision is clamped into the range the draw asks for, so an
+    /// edited trace always replays.
+    Replay {
+        /// the recorded decisions
+        decisions: &'a [Decision],
+        /// index of the next decision to answer with
+        position: usize,
+    },
+}
+
+/// what generation from fuzzer bytes does once the bytes run out.
+///
+/// an exhausted `Unstructured` answers every draw with 0 or `false`, so a short
+/// input produces a long, degenerate tail of identical opcodes that costs
+/// fuzzing time without reaching new code.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ExhaustionPolicy {
+    /// keep going on fallback values (0, `false`, the lowest index), as every
+    /// earlier release did
+    #[default]
+    Fallback,
+    /// stop emitting new opcodes and finish the pickle right away, even short
+    /// of the minimum opcode count
+    Stop,
+    /// draw the rest from a ChaCha8 stream seeded with a hash of the input, so
+    /// the tail stays deterministic per input but is no longer degenerate
+    SwitchToPrng,
+}
+
+impl GenerationSource<'_> {
+    /// whether this source is fuzzer bytes that have all been consumed, or a
+    /// replay past its last decision.
+    ///
+    /// always `false` for PRNG-backed sources, and for `ArbitraryThenRand`,
+    /// which never runs dry.
+    pub fn is_exhausted(&self) -> bool {
+        match self {
+            GenerationSource::Arbitrary(u) => u.is_empty(),
+            GenerationSource::Recording { inner, .. } => inner.is_exhausted(),
+            GenerationSource::Replay {
+                decisions,
+                position,
+            } => *position >= decisions.len(),
+            _ => false,
+        }
+    }
+
+    /// run `f` with the source leaf values and mutations are drawn from: the
+    /// value stream of a `Split` source, otherwise this source.
+    pub fn with_values<R>(&mut self, f: impl FnOnce(&mut GenerationSource<'_>) -> R) -> R {
+        match self {
+            GenerationSource::Split { values, .. } => f(&mut GenerationSource::Rand(values)),
+            GenerationSource::Recording {
+                inner: GenerationSource::Split { values, .. },
+                trace,
+            } => f(&mut GenerationSource::Recording {
+                inner: &mut GenerationSource::Rand(values),
+                trace,
+            }),
+            _ => f(self),
+        }
+    }
+}
+
+/// draw from `[min, max)` the way every PRNG-backed range draw does.
+fn rng_range(rng: &mut ChaCha8Rng, min: u64, max: u64) -> u64 {
+    // the PRNG narrows to u32 when the range fits, which keeps the common case
+    // to a single 32-bit draw
+    match (u32::try_from(min), u32::try_from(max)) {
+        (Ok(min), Ok(max)) => u64::from(rng.random_range(min..max)),
+        _ => rng.random_range(min..max),
+    }
 }
 
 /// trait for abstracting entropy sources used in pickle generation.
@@ -101,7 +251,6 @@ pub trait EntropySource {
     fn gen_i32(&mut self) -> i32;
 
     /// generate a random i64 value.
-    #[allow(dead_code)]
     fn gen_i64(&mut self) -> i64;
 
     /// generate a random f64 value.
@@ -124,6 +273,79 @@ pub trait EntropySource {
 /// used by `gen_ascii_char()` to generate valid string content for pickle values.
 const ASCII_CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789 !\"#$%&'()*+,-./:;<=>?@[\\]^_`{|}~";
 
+/// where one draw comes from.
+enum Draw<'s, 'a> {
+    Rng(&'s mut ChaCha8Rng),
+    Input(&'s mut Unstructured<'a>),
+}
+
+impl<'a> GenerationSource<'a> {
+    /// where structural draws (indices, ranges, booleans) come from.
+    fn structure(&mut self) -> Draw<'_, 'a> {
+        match self {
+            GenerationSource::Rand(rng) | GenerationSource::Split { structure: rng, .. } => {
+                Draw::Rng(rng)
+            }
+            GenerationSource::Arbitrary(u) => Draw::Input(u),
+            GenerationSource::ArbitraryThenRand { input, rng } => {
+                if input.is_empty() {
+                    Draw::Rng(rng)
+                } else {
+                    Draw::Input(input)
+                }
+            }
+            GenerationSource::Recording { .. } | GenerationSource::Replay { .. } => {
+                unreachable!("recording and replaying sources answer before drawing")
+            }
+        }
+    }
+
+    /// where value draws (integers, floats, bytes, characters) come from.
+    fn value(&mut self) -> Draw<'_, 'a> {
+        match self {
+            GenerationSource::Split { values, .. } => Draw::Rng(values),
+            _ => self.structure(),
+        }
+    }
+
+    /// a structural `[min, max)` draw.
+    fn draw_range(&mut self, min: u64, max: u64) -> u64 {
+        match self.structure() {
+            Draw::Rng(rng) => rng_range(rng, min, max),
+            // convert exclusive range to inclusive for arbitrary, fallback to min
+            Draw::Input(u) => u.int_in_range(min..=max - 1).unwrap_or(min),
+        }
+    }
+
+    /// `len` value bytes.
+    fn draw_bytes(&mut self, len: usize) -> Vec<u8> {
+        match self.value() {
+            Draw::Rng(rng) => {
+                let mut bytes = vec![0u8; len];
+                // fill with random bytes, ignore errors (keeps zeros on failure)
+                rng.try_fill_bytes(&mut bytes).unwrap_or(());
+                bytes
+            }
+            Draw::Input(u) => {
+                // try to get bytes from fuzzer input, fallback to zeros if exhausted
+                u.bytes(len).unwrap_or(&vec![0u8; len]).to_vec()
+            }
+        }
+    }
+
+    /// a printable ASCII character from the values.
+    fn draw_ascii_char(&mut self) -> char {
+        // choose random index into ASCII_CHARS, convert byte to char; a
+        // character is a value, so a split source draws it from its value stream
+        let max = ASCII_CHARS.len() as u64;
+        let idx = match self.value() {
+            Draw::Rng(rng) => rng_range(rng, 0, max),
+            Draw::Input(u) => u.int_in_range(0..=max - 1).unwrap_or(0),
+        };
+        ASCII_CHARS[idx as usize] as char
+    }
+}
+
 /// implementation of `EntropySource` for `GenerationSource`.
 ///
 /// each method dispatches to either the PRNG or the `Unstructured` fuzzer bytes,
@@ -134,63 +356,155 @@ impl<'a> EntropySource for GenerationSource<'a> {
         if max == 0 {
             return 0;
         }
-        match self {
-            GenerationSource::Rand(rng) => rng.random_range(0..max),
-            // fallback to 0 if fuzzer bytes exhausted
-            GenerationSource::Arbitrary(u) => u.choose_index(max).unwrap_or(0),
-        }
+        self.gen_range(0, max)
     }
 
     fn gen_bool(&mut self) -> bool {
         match self {
-            GenerationSource::Rand(rng) => rng.random(),
-            // fallback to false if fuzzer bytes exhausted
-            GenerationSource::Arbitrary(u) => u.arbitrary().unwrap_or(false),
+            GenerationSource::Recording { inner, trace } => {
+                let value = inner.gen_bool();
+                trace.push(Decision::Bool(value));
+                value
+            }
+            GenerationSource::Replay {
+                decisions,
+                position,
+            } => match next_decision(decisions, position) {
+                Some(Decision::Bool(value)) => *value,
+                _ => false,
+            },
+            _ => match self.structure() {
+                Draw::Rng(rng) => rng.random(),
+                // fallback to false if fuzzer bytes exhausted
+                Draw::Input(u) => u.arbitrary().unwrap_or(false),
+            },
         }
     }
 
     fn gen_u8(&mut self) -> u8 {
         match self {
-            GenerationSource::Rand(rng) => rng.random(),
-            GenerationSource::Arbitrary(u) => u.arbitrary().unwrap_or(0),
+            GenerationSource::Recording { inner, trace } => {
+                let value = inner.gen_u8();
+                trace.push(Decision::U8(value));
+                value
+            }
+            GenerationSource::Replay {
+                decisions,
+                position,
+            } => match next_decision(decisions, position) {
+                Some(Decision::U8(value)) => *value,
+                _ => 0,
+            },
+            _ => match self.value() {
+                Draw::Rng(rng) => rng.random(),
+                Draw::Input(u) => u.arbitrary().unwrap_or(0),
+            },
         }
     }
 
     fn gen_u16(&mut self) -> u16 {
         match self {
-            GenerationSource::Rand(rng) => rng.random(),
-            GenerationSource::Arbitrary(u) => u.arbitrary().unwrap_or(0),
+            GenerationSource::Recording { inner, trace } => {
+                let value = inner.gen_u16();
+                trace.push(Decision::U16(value));
+                value
+            }
+            GenerationSource::Replay {
+                decisions,
+                position,
+            } => match next_decision(decisions, position) {
+                Some(Decision::U16(value)) => *value,
+                _ => 0,
+            },
+            _ => match self.value() {
+                Draw::Rng(rng) => rng.random(),
+                Draw::Input(u) => u.arbitrary().unwrap_or(0),
+            },
         }
     }
 
     fn gen_u32(&mut self) -> u32 {
         match self {
-            GenerationSource::Rand(rng) => rng.random(),
-            GenerationSource::Arbitrary(u) => u.arbitrary().unwrap_or(0),
+            GenerationSource::Recording { inner, trace } => {
+                let value = inner.gen_u32();
+                trace.push(Decision::U32(value));
+                value
+            }
+            GenerationSource::Replay {
+                decisions,
+                position,
+            } => match next_decision(decisions, position) {
+                Some(Decision::U32(value)) => *value,
+                _ => 0,
+            },
+            _ => match self.value() {
+                Draw::Rng(rng) => rng.random(),
+                Draw::Input(u) => u.arbitrary().unwrap_or(0),
+            },
         }
     }
 
     fn gen_i32(&mut self) -> i32 {
         match self {
-            GenerationSource::Rand(rng) => rng.random(),
-            GenerationSource::Arbitrary(u) => u.arbitrary().unwrap_or(0),
+            GenerationSource::Recording { inner, trace } => {
+                let value = inner.gen_i32();
+                trace.push(Decision::I32(value));
+                value
+            }
+            GenerationSource::Replay {
+                decisions,
+                position,
+            } => match next_decision(decisions, position) {
+                Some(Decision::I32(value)) => *value,
+                _ => 0,
+            },
+            _ => match self.value() {
+                Draw::Rng(rng) => rng.random(),
+                Draw::Input(u) => u.arbitrary().unwrap_or(0),
+            },
         }
     }
 
     fn gen_i64(&mut self) -> i64 {
         match self {
-            GenerationSource::Rand(rng) => rng.random(),
-            GenerationSource::Arbitrary(u) => u.arbitrary().unwrap_or(0),
+            GenerationSource::Recording { inner, trace } => {
+                let value = inner.gen_i64();
+                trace.push(Decision::I64(value));
+                value
+            }
+            GenerationSource::Replay {
+                decisions,
+                position,
+            } => match next_decision(decisions, position) {
+                Some(Decision::I64(value)) => *value,
+                _ => 0,
+            },
+            _ => match self.value() {
+                Draw::Rng(rng) => rng.random(),
+                Draw::Input(u) => u.arbitrary().unwrap_or(0),
+            },
         }
     }
 
     fn gen_f64(&mut self) -> f64 {
         match self {
-            GenerationSource::Rand(rng) => rng.random(),
-            GenerationSource::Arbitrary(u) => {
-                // arbitrary crate doesn't have float64(), use arbitrary() instead
-                u.arbitrary().unwrap_or(0.0)
+            GenerationSource::Recording { inner, trace } => {
+                let value = inner.gen_f64();
+                trace.push(Decision::F64(value));
+                value
             }
+            GenerationSource::Replay {
+                decisions,
+                position,
+            } => match next_decision(decisions, position) {
+                Some(Decision::F64(value)) => *value,
+                _ => 0.0,
+            },
+            _ => match self.value() {
+                Draw::Rng(rng) => rng.random(),
+                // arbitrary crate doesn't have float64(), use arbitrary() instead
+                Draw::Input(u) => u.arbitrary().unwrap_or(0.0),
+            },
         }
     }
 
@@ -198,33 +512,65 @@ impl<'a> EntropySource for GenerationSource<'a> {
         if min >= max {
             return min;
         }
-        match self {
-            GenerationSource::Rand(rng) => rng.random_range(min..max),
-            // convert exclusive range to inclusive for arbitrary, fallback to min
-            GenerationSource::Arbitrary(u) => {
-                u.int_in_range(min..=max.saturating_sub(1)).unwrap_or(min)
+        // widen to u64 before drawing so the consumed entropy is pointer-width
+        // independent
+        let (min, max) = (min as u64, max as u64);
+        let value = match self {
+            GenerationSource::Recording { inner, trace } => {
+                let value = inner.gen_range(min as usize, max as usize) as u64;
+                trace.push(Decision::Range { min, max, value });
+                value
             }
-        }
+            GenerationSource::Replay {
+                decisions,
+                position,
+            } => match next_decision(decisions, position) {
+                Some(Decision::Range { value, .. }) => (*value).clamp(min, max - 1),
+                _ => min,
+            },
+            _ => self.draw_range(min, max),
+        };
+        value as usize
     }
 
     fn gen_bytes(&mut self, len: usize) -> Vec<u8> {
         match self {
-            GenerationSource::Rand(rng) => {
-                let mut bytes = vec![0u8; len];
-                // fill with random bytes, ignore errors (keeps zeros on failure)
-                rng.try_fill_bytes(&mut bytes).unwrap_or(());
+            GenerationSource::Recording { inner, trace } => {
+                let bytes = inner.gen_bytes(len);
+                trace.push(Decision::Bytes(bytes.clone()));
                 bytes
             }
-            GenerationSource::Arbitrary(u) => {
-                // try to get bytes from fuzzer input, fallback to zeros if exhausted
-                u.bytes(len).unwrap_or(&vec![0u8; len]).to_vec()
-            }
+            GenerationSource::Replay {
+                decisions,
+                position,
+            } => match next_decision(decisions, position) {
+                // an edited trace may hold a different length; pad or cut it
+                Some(Decision::Bytes(bytes)) => {
+                    let mut bytes = bytes.clone();
+                    bytes.resize(len, 0);
+                    bytes
+                }
+                _ => vec![0u8; len],
+            },
+            _ => self.draw_bytes(len),
         }
     }
 
     fn gen_ascii_char(&mut self) -> char {
-        // choose random index into ASCII_CHARS, convert byte to char
-        let idx = self.choose_index(ASCII_CHARS.len());
-        ASCII_CHARS[idx] as char
+        match self {
+            GenerationSource::Recording { inner, trace } => {
+                let c = inner.gen_ascii_char();
+                trace.push(Decision::Char(c));
+                c
+            }
+            GenerationSource::Replay {
+                decisions,
+                position,
+            } => match next_decision(decisions, position) {
+                Some(Decision::Char(c)) => *c,
+                _ => ASCII_CHARS[0] as char,
+            },
+            _ => self.draw_ascii_char(),
+        }
     }
 }
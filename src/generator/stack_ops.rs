@@ -31,62 +31,112 @@
 //! of the PVM state, which is critical for validating whether subsequent opcodes
 //! can be safely emitted.
 
+use clap::ValueEnum;
+
 use super::Generator;
+use crate::disasm::{self, Argument};
 use crate::opcodes::OpcodeKind;
 use crate::protocol::Version;
-use crate::stack::{InstanceObject, StackObject, StackObjectRef};
+use crate::stack::{ContainerKind, InstanceObject, Items, StackObject, StackObjectRef};
+use smallvec::smallvec;
 use std::collections::{HashMap, HashSet};
 
-fn decode_signed_le_i64(int_bytes: &[u8]) -> i64 {
-    let size = int_bytes.len().min(8);
-    let mut value: i64 = 0;
-
-    for (index, byte) in int_bytes.iter().take(size).enumerate() {
-        value |= (*byte as i64) << (index * 8);
+/// the stack object for a decoded integer argument: `Int` when it fits in an
+/// i64, `BigInt` otherwise.
+fn int_object(argument: Argument) -> StackObject {
+    match argument {
+        Argument::Int(value) => match i64::try_from(value) {
+            Ok(value) => StackObject::Int(value),
+            Err(_) => StackObject::BigInt(value.to_le_bytes().to_vec()),
+        },
+        Argument::BigInt(bytes) => StackObject::BigInt(bytes),
+        _ => StackObject::Int(0),
     }
+}
+
+/// whether `callable` is `collections.OrderedDict`.
+fn is_ordered_dict(callable: &StackObject) -> bool {
+    matches!(callable, StackObject::Global { module, name }
+        if module == "collections" && name == "OrderedDict")
+}
 
-    if size > 0 && size < 8 && int_bytes[size - 1] & 0x80 != 0 {
-        value |= !0i64 << (size * 8);
+/// parse a decimal text argument (INT, LONG, GET, PUT) the way python's
+/// `int()` does: surrounding whitespace and `_` digit separators are allowed.
+pub(crate) fn parse_text_number<T: std::str::FromStr>(text: &str) -> Option<T> {
+    let text = text.trim();
+    if text.contains('_') {
+        text.replace('_', "").parse().ok()
+    } else {
+        text.parse().ok()
     }
+}
 
-    value
+/// how `cleanup_for_stop` reduces the stack to the single object STOP returns.
+///
+/// open MARKs are closed into the container below them either way; the policy
+/// decides what happens to everything else.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CleanupPolicy {
+    /// fold leftover items into tuples (TUPLE3/TUPLE2, or TUPLE for a MARK),
+    /// so the root is a tuple of everything still on the stack. protocol 0/1
+    /// can't use the tuple shortcuts and pop instead.
+    #[default]
+    Tuple,
+    /// POP leftover items (POP_MARK for a MARK) until only the bottom object
+    /// remains, so the root is the first object generation built, like the
+    /// top-level object of a real pickler's output.
+    KeepRoot,
 }
 
 impl Generator {
     /// clean up the stack to prepare for the STOP opcode.
     ///
     /// the STOP opcode requires exactly one item on the stack. this method
-    /// intelligently combines or removes stack items until only one remains,
-    /// using the following strategies:
+    /// closes every MARK and then reduces the remaining items to one:
     ///
-    /// - if stack is empty: push a None value
-    /// - if stack has one MARK: pop it and push None
-    /// - if stack has multiple items with MARK: combine items above MARK into tuple
-    /// - if stack has multiple items without MARK: use protocol-appropriate
-    ///   cleanup to reduce the stack to one item
+    /// - each MARK, topmost first, is closed with [`mark_closing_opcode`]:
+    ///   its items are folded into the list/dict/set below it where possible
+    /// - leftover items are tupled or popped according to the [`CleanupPolicy`]
+    ///   (protocol 0/1 always pop)
+    /// - if nothing is left, a None value is pushed
+    ///
+    /// [`mark_closing_opcode`]: Self::mark_closing_opcode
     ///
     /// this method is called at the end of generation to ensure the pickle is
     /// valid before emitting the final STOP opcode.
     pub(super) fn cleanup_for_stop(&mut self) {
         use OpcodeKind::*;
 
-        // remove any MARKs by using TUPLE
-        // TUPLE pops items until it finds a MARK, pops the MARK, and pushes a tuple
+        // close every MARK, topmost first, with the opcode that fits what's below
+        // it: fold the items into an open list/dict/set, drop an empty MARK, or
+        // fall back to what the cleanup policy uses. each MARK costs exactly one
+        // opcode.
         // note: DUP no longer duplicates MARKs, so each MARK corresponds to a real
         // MARK byte in the pickle
-        while self.has_mark() {
-            self.emit_opcode(Tuple);
+        while let Some(mark_idx) = self.state.stack.top_mark() {
+            let below = mark_idx
+                .checked_sub(1)
+                .and_then(|idx| self.state.stack.items()[idx].borrow().container_kind());
+            let items = self.state.stack.len() - mark_idx - 1;
+            let mut opcode = self.mark_closing_opcode(below, items);
+            if self.loadable && !self.loadable_allows(opcode) {
+                // unhashable keys or set items; dropping them costs the same
+                // one opcode and leaves nothing behind either
+                opcode = PopMark;
+            }
+            self.emit_opcode(opcode);
         }
 
         // at this point, stack has no MARKs, just regular items.
         // protocol 2+ can use TUPLE2/TUPLE3, but protocol 0/1 must not emit
         // those shortcut opcodes during cleanup.
+        let pop_extras = self.pops_extra_items();
         let mut safety_counter = 0;
         while self.state.stack.len() > 1 && safety_counter < 10000 {
             safety_counter += 1;
 
             let stack_len = self.state.stack.len();
-            if self.state.version < Version::V2 {
+            if pop_extras {
                 self.emit_opcode(Pop);
             } else if stack_len >= 3 {
                 self.emit_opcode(Tuple3);
@@ -118,6 +168,41 @@ impl Generator {
         }
     }
 
+    /// opcode `cleanup_for_stop` uses to close a MARK with `items` items above it
+    /// and a container of kind `below` directly beneath it.
+    ///
+    /// - list below: APPENDS (protocol 1+)
+    /// - dict below and an even number of items: SETITEMS (protocol 1+)
+    /// - set below: ADDITEMS (protocol 4+)
+    /// - nothing above the MARK: POP_MARK (protocol 1+)
+    /// - otherwise: POP_MARK under [`CleanupPolicy::KeepRoot`] (protocol 1+),
+    ///   TUPLE under [`CleanupPolicy::Tuple`]
+    ///
+    /// the choices mirror the `can_emit` preconditions of those opcodes, so the
+    /// closing opcode is always valid where cleanup emits it.
+    pub(super) fn mark_closing_opcode(
+        &self,
+        below: Option<ContainerKind>,
+        items: usize,
+    ) -> OpcodeKind {
+        let version = self.state.version;
+        match below {
+            _ if version < Version::V1 => OpcodeKind::Tuple,
+            _ if items == 0 => OpcodeKind::PopMark,
+            Some(ContainerKind::List) => OpcodeKind::Appends,
+            Some(ContainerKind::Dict) if items.is_multiple_of(2) => OpcodeKind::SetItems,
+            Some(ContainerKind::Set) if version >= Version::V4 => OpcodeKind::AddItems,
+            _ if self.cleanup_policy == CleanupPolicy::KeepRoot => OpcodeKind::PopMark,
+            _ => OpcodeKind::Tuple,
+        }
+    }
+
+    /// whether cleanup pops the items left after closing MARKs instead of
+    /// tupling them.
+    pub(super) fn pops_extra_items(&self) -> bool {
+        self.state.version < Version::V2 || self.cleanup_policy == CleanupPolicy::KeepRoot
+    }
+
     /// process the stack effects of an emitted opcode.
     ///
     /// this is the core method that simulates the pickle virtual machine's stack
@@ -146,6 +231,10 @@ impl Generator {
     pub(super) fn process_stack_ops(&mut self, opcode: OpcodeKind, arg_bytes: Option<&[u8]>) {
         use OpcodeKind::*;
 
+        if self.strict_checks {
+            self.record_strict_violation(opcode, arg_bytes);
+        }
+
         match opcode {
             Pop => {
                 self.pop();
@@ -155,9 +244,11 @@ impl Generator {
                     // IMPORTANT: don't duplicate a MARK!
                     // duplicating MARKs creates invalid pickle state that causes
                     // TUPLE to fail (it tries to pop until MARK, but if stack is
-                    // all MARKs, it crashes with "list index out of range")
-                    if !matches!(*top.borrow(), StackObject::Mark) {
-                        self.state.stack.inner.push(top.clone());
+                    // all MARKs, it crashes with "list index out of range").
+                    // unsafe marks do it anyway, and the copy is a second MARK
+                    // as it was for Python 2's unpickler
+                    if self.unsafe_marks || !matches!(*top.borrow(), StackObject::Mark) {
+                        self.state.stack.push_ref(top.clone());
                     }
                 }
             }
@@ -171,7 +262,7 @@ impl Generator {
                     }
                 }
             }
-            EmptyList => self.push(StackObject::List(Vec::new())),
+            EmptyList => self.push(StackObject::List(Items::new())),
             Append => {
                 if self.state.stack.len() < 2 {
                     return;
@@ -179,11 +270,13 @@ impl Generator {
                 if let Some(item) = self.pop() {
                     if let Some(cell) = self.peek() {
                         // check if it's a list first without holding the borrow
-                        let is_list = matches!(*cell.borrow(), StackObject::List(_));
+                        let is_list = cell.borrow().is_container(ContainerKind::List);
                         if is_list {
                             // now mutably borrow to append
-                            if let StackObject::List(ref mut list) = *cell.borrow_mut() {
-                                list.push(item);
+                            match *cell.borrow_mut() {
+                                StackObject::List(ref mut list) => list.push(item),
+                                StackObject::Summarized { ref mut len, .. } => *len += 1,
+                                _ => {}
                             }
                         }
                     }
@@ -200,13 +293,20 @@ impl Generator {
                 items_to_append.reverse();
 
                 if let Some(list_obj) = self.peek() {
-                    if let StackObject::List(ref mut list) = *list_obj.borrow_mut() {
-                        list.extend(items_to_append);
+                    let is_list = list_obj.borrow().is_container(ContainerKind::List);
+                    if is_list {
+                        match *list_obj.borrow_mut() {
+                            StackObject::List(ref mut list) => list.extend(items_to_append),
+                            StackObject::Summarized { ref mut len, .. } => {
+                                *len += items_to_append.len()
+                            }
+                            _ => {}
+                        }
                     }
                 }
             }
             List => {
-                let mut accumulated = Vec::new();
+                let mut accumulated = Items::new();
                 while let Some(item) = self.pop() {
                     match *item.borrow() {
                         StackObject::Mark => break,
@@ -217,10 +317,10 @@ impl Generator {
                 self.push(StackObject::List(accumulated));
             }
             EmptyTuple => {
-                self.push(StackObject::Tuple(Vec::new()));
+                self.push(StackObject::Tuple(Items::new()));
             }
             Tuple => {
-                let mut accumulated = Vec::new();
+                let mut accumulated = Items::new();
                 while let Some(item) = self.pop() {
                     match *item.borrow() {
                         StackObject::Mark => break,
@@ -232,7 +332,7 @@ impl Generator {
             }
             Tuple1 => {
                 if let Some(item) = self.pop() {
-                    self.push(StackObject::Tuple(vec![item]));
+                    self.push(StackObject::Tuple(smallvec![item]));
                 }
             }
             Tuple2 => {
@@ -240,7 +340,7 @@ impl Generator {
                     // unwraps are guarded with the length check, fine to leave as-is
                     let second = self.pop().unwrap();
                     let first = self.pop().unwrap();
-                    self.push(StackObject::Tuple(vec![first, second]));
+                    self.push(StackObject::Tuple(smallvec![first, second]));
                 }
             }
             Tuple3 => {
@@ -248,7 +348,7 @@ impl Generator {
                     let third = self.pop().unwrap();
                     let second = self.pop().unwrap();
                     let first = self.pop().unwrap();
-                    self.push(StackObject::Tuple(vec![first, second, third]));
+                    self.push(StackObject::Tuple(smallvec![first, second, third]));
                 }
             }
             EmptyDict => {
@@ -258,19 +358,22 @@ impl Generator {
                 // allow interior mutability in hash keys because StackObjectRef
                 // hashes and compares by Rc identity, not the borrowed value
                 #[allow(clippy::mutable_key_type)]
-                let mut accumulated = HashMap::new();
+                let mut dict = HashMap::new();
 
+                let mut accumulated = Vec::new();
                 while let Some(value) = self.pop() {
                     match *value.borrow() {
                         StackObject::Mark => break,
                         _ => {
                             if let Some(key) = self.pop() {
-                                accumulated.insert(key, value.clone());
+                                accumulated.push((key, value.clone()));
                             }
                         }
                     }
                 }
-                self.push(StackObject::Dict(accumulated));
+                // insert in stack order, so a repeated key keeps its last value
+                dict.extend(accumulated.into_iter().rev());
+                self.push(StackObject::Dict(dict));
             }
             SetItem => {
                 if self.state.stack.len() < 3 {
@@ -281,11 +384,15 @@ impl Generator {
                     if let Some(key) = self.pop() {
                         if let Some(cell) = self.peek() {
                             // Check if it's a dict first without holding the borrow
-                            let is_dict = matches!(*cell.borrow(), StackObject::Dict(_));
+                            let is_dict = cell.borrow().is_container(ContainerKind::Dict);
                             if is_dict {
                                 // now mutably borrow to insert
-                                if let StackObject::Dict(ref mut dict) = *cell.borrow_mut() {
-                                    dict.insert(key, value);
+                                match *cell.borrow_mut() {
+                                    StackObject::Dict(ref mut dict) => {
+                                        dict.insert(key, value);
+                                    }
+                                    StackObject::Summarized { ref mut len, .. } => *len += 1,
+                                    _ => {}
                                 }
                             }
                         }
@@ -299,19 +406,32 @@ impl Generator {
                     if is_mark {
                         break;
                     }
-                    if let Some(key) = self.pop() {
-                        accumulated.push((key, value));
+                    // an odd item count leaves the value keyless; its MARK
+                    // still ends the batch
+                    match self.pop() {
+                        Some(key) if !matches!(*key.borrow(), StackObject::Mark) => {
+                            accumulated.push((key, value));
+                        }
+                        _ => break,
                     }
                 }
                 if let Some(cell) = self.peek() {
                     // check if it's a dict first without holding the borrow
-                    let is_dict = matches!(*cell.borrow(), StackObject::Dict(_));
+                    let is_dict = cell.borrow().is_container(ContainerKind::Dict);
                     if is_dict {
                         // now mutably borrow to insert all items
-                        if let StackObject::Dict(ref mut dict) = *cell.borrow_mut() {
-                            for (key, value) in accumulated {
-                                dict.insert(key, value);
+                        match *cell.borrow_mut() {
+                            StackObject::Dict(ref mut dict) => {
+                                // in stack order, so a repeated key keeps its
+                                // last value
+                                for (key, value) in accumulated.into_iter().rev() {
+                                    dict.insert(key, value);
+                                }
+                            }
+                            StackObject::Summarized { ref mut len, .. } => {
+                                *len += accumulated.len()
                             }
+                            _ => {}
                         }
                     }
                 }
@@ -330,13 +450,19 @@ impl Generator {
                 if let Some(cell) = self.peek() {
                     accumulated.reverse();
                     // check if it's a set first without holding the borrow
-                    let is_set = matches!(*cell.borrow(), StackObject::Set(_));
+                    let is_set = cell.borrow().is_container(ContainerKind::Set);
                     if is_set {
                         // now mutably borrow to insert all items
-                        if let StackObject::Set(ref mut set) = *cell.borrow_mut() {
-                            for item in accumulated {
-                                set.insert(item);
+                        match *cell.borrow_mut() {
+                            StackObject::Set(ref mut set) => {
+                                for item in accumulated {
+                                    set.insert(item);
+                                }
+                            }
+                            StackObject::Summarized { ref mut len, .. } => {
+                                *len += accumulated.len()
                             }
+                            _ => {}
                         }
                     }
                 }
@@ -360,22 +486,16 @@ impl Generator {
             }
             Int => {
                 // always push, even if parsing fails
-                let value = if let Some(arg_bytes) = arg_bytes {
-                    if let Ok(value_str) = std::str::from_utf8(arg_bytes) {
-                        value_str.trim().parse::<i64>().unwrap_or(0)
-                    } else {
-                        0
-                    }
-                } else {
-                    0
-                };
+                let text = arg_bytes.unwrap_or_default();
+                let text = text.strip_suffix(b"\n").unwrap_or(text);
                 // in protocol 0-1, INT opcode with 00/01 represents booleans
                 // protocol 2+ has dedicated NEWTRUE/NEWFALSE opcodes
-                let is_bool_literal = matches!(arg_bytes, Some(b"00\n" | b"01\n"));
+                let is_bool_literal = matches!(text, b"00" | b"01");
                 if matches!(self.state.version, Version::V0 | Version::V1) && is_bool_literal {
-                    self.push(StackObject::Bool(value != 0));
+                    self.push(StackObject::Bool(text == b"01"));
                 } else {
-                    self.push(StackObject::Int(value));
+                    let value = disasm::parse_int(text).unwrap_or(Argument::Int(0));
+                    self.push(int_object(value));
                 }
             }
             BinInt => {
@@ -412,18 +532,11 @@ impl Generator {
             }
             Long => {
                 // always push, even if parsing fails
-                let value = if let Some(arg_bytes) = arg_bytes {
-                    if let Ok(value_str) = std::str::from_utf8(arg_bytes) {
-                        // strip trailing 'L\n'
-                        let value_str = value_str.trim_end_matches('\n').trim_end_matches('L');
-                        value_str.trim().parse::<i64>().unwrap_or(0)
-                    } else {
-                        0
-                    }
-                } else {
-                    0
-                };
-                self.push(StackObject::Int(value));
+                let text = arg_bytes.unwrap_or_default();
+                let text = text.strip_suffix(b"\n").unwrap_or(text);
+                let text = text.strip_suffix(b"L").unwrap_or(text);
+                let value = disasm::parse_int(text).unwrap_or(Argument::Int(0));
+                self.push(int_object(value));
             }
             Long1 => {
                 if let Some(arg_bytes) = arg_bytes {
@@ -433,7 +546,7 @@ impl Generator {
                     let size = arg_bytes[0] as usize;
                     if arg_bytes.len() > size {
                         let int_bytes = &arg_bytes[1..1 + size];
-                        self.push(StackObject::Int(decode_signed_le_i64(int_bytes)));
+                        self.push(int_object(disasm::int_from_le_bytes(int_bytes)));
                     }
                 }
             }
@@ -448,21 +561,44 @@ impl Generator {
                         ]) as usize;
                         if arg_bytes.len() >= 4 + size {
                             let int_bytes = &arg_bytes[4..4 + size];
-                            self.push(StackObject::Int(decode_signed_le_i64(int_bytes)));
+                            self.push(int_object(disasm::int_from_le_bytes(int_bytes)));
                         }
                     }
                 }
             }
-            String | ShortBinUnicode | Unicode | BinUnicode | BinUnicode8 => {
-                // always push a string, even if arg_bytes is None
-                let value = if let Some(arg_bytes) = arg_bytes {
-                    std::string::String::from_utf8_lossy(arg_bytes).into_owned()
-                } else {
-                    std::string::String::new()
-                };
+            Unicode => {
+                // raw-unicode-escape text up to the newline
+                let text = arg_bytes.unwrap_or_default();
+                let text = text.strip_suffix(b"\n").unwrap_or(text);
+                let value = crate::disasm::raw_unicode_escape(text)
+                    .unwrap_or_else(|_| std::string::String::from_utf8_lossy(text).into_owned());
+                self.push(StackObject::String(value));
+            }
+            String => {
+                // a quoted bytes literal, which python 3 decodes as ASCII
+                let line = arg_bytes.unwrap_or_default();
+                let line = line.strip_suffix(b"\n").unwrap_or(line);
+                let value = disasm::strip_quotes(line)
+                    .and_then(disasm::escape_decode)
+                    .unwrap_or_else(|_| line.to_vec());
+                self.push(StackObject::String(
+                    std::string::String::from_utf8_lossy(&value).into_owned(),
+                ));
+            }
+            ShortBinUnicode | BinUnicode | BinUnicode8 => {
+                // always push a string, even if arg_bytes is None; lone
+                // surrogates become U+FFFD
+                let text = arg_bytes.unwrap_or_default();
+                let value = disasm::utf8_surrogatepass(text)
+                    .unwrap_or_else(|_| std::string::String::from_utf8_lossy(text).into_owned());
                 self.push(StackObject::String(value));
             }
-            BinString | ShortBinString | BinBytes | ShortBinBytes | BinBytes8 => {
+            BinString | ShortBinString => {
+                // python 3 decodes these as ASCII strings by default
+                let value = std::string::String::from_utf8_lossy(arg_bytes.unwrap_or_default());
+                self.push(StackObject::String(value.into_owned()));
+            }
+            BinBytes | ShortBinBytes | BinBytes8 => {
                 // always push bytes, even if arg_bytes is None
                 let bytes = if let Some(arg_bytes) = arg_bytes {
                     arg_bytes.to_vec()
@@ -570,10 +706,18 @@ impl Generator {
                         callable.clone()
                     };
 
-                    self.push(StackObject::Instance(InstanceObject {
-                        callable: inner_callable,
-                        args,
-                    }));
+                    // OrderedDict() is a dict that SETITEM(S) fill next, the way
+                    // CPython pickles one
+                    let ordered_dict = is_ordered_dict(&inner_callable.borrow())
+                        && matches!(&*args.borrow(), StackObject::Tuple(items) if items.is_empty());
+                    if ordered_dict {
+                        self.push(StackObject::Dict(HashMap::new()));
+                    } else {
+                        self.push(StackObject::Instance(InstanceObject {
+                            callable: inner_callable,
+                            args,
+                        }));
+                    }
                 }
             }
             Build => {
@@ -608,7 +752,7 @@ impl Generator {
                             name: class.clone(),
                         });
 
-                        let mut accumulated = Vec::new();
+                        let mut accumulated = Items::new();
 
                         while let Some(item) = self.pop() {
                             match *item.borrow() {
@@ -632,7 +776,7 @@ impl Generator {
                 // build a class instance (protocol 1)
                 // pops items from TOS back to MARK: first item after MARK is class, rest are args
                 // stack before: MARK class arg1 arg2 ... (arg2 on TOS)
-                let mut accumulated = Vec::new();
+                let mut accumulated = Items::new();
 
                 while let Some(item) = self.pop() {
                     match *item.borrow() {
@@ -714,7 +858,7 @@ impl Generator {
             Get => {
                 if let Some(arg_bytes) = arg_bytes {
                     if let Ok(index_str) = std::str::from_utf8(arg_bytes) {
-                        if let Ok(index) = index_str.trim().parse() {
+                        if let Some(index) = parse_text_number(index_str) {
                             if let Some(obj) = self.get(index) {
                                 self.push_ref(obj);
                             }
@@ -747,7 +891,7 @@ impl Generator {
                 // PUT doesn't pop - it just peeks at TOS and stores in memo
                 if let Some(arg_bytes) = arg_bytes {
                     if let Ok(index_str) = std::str::from_utf8(arg_bytes) {
-                        if let Ok(index) = index_str.trim().parse() {
+                        if let Some(index) = parse_text_number(index_str) {
                             if let Some(top) = self.peek() {
                                 if !matches!(*top.borrow(), StackObject::Mark) {
                                     self.put(index, top.clone());
@@ -805,13 +949,19 @@ impl Generator {
                 self.push(StackObject::Bytes(Vec::new())); // Use empty bytes as placeholder
             }
             ReadOnlyBuffer => {
-                if let Some(buffer) = self.pop() {
-                    let readonly_buffer = match &*buffer.borrow() {
-                        StackObject::Bytes(bytes) => StackObject::Bytes(bytes.clone()),
-                        StackObject::ByteArray(bytes) => StackObject::Bytes(bytes.clone()),
-                        other => other.clone(),
-                    };
-                    self.push(readonly_buffer);
+                // an already-readonly buffer is left in place, exactly like the
+                // unpickler does, so memo aliases of it keep pointing at TOS
+                let is_bytearray = self
+                    .peek()
+                    .is_some_and(|top| matches!(*top.borrow(), StackObject::ByteArray(_)));
+                if is_bytearray {
+                    if let Some(buffer) = self.pop() {
+                        let readonly_buffer = match &*buffer.borrow() {
+                            StackObject::ByteArray(bytes) => StackObject::Bytes(bytes.clone()),
+                            other => other.clone(),
+                        };
+                        self.push(readonly_buffer);
+                    }
                 }
             }
             Proto | Stop | Frame => {
@@ -820,6 +970,17 @@ impl Generator {
             }
         }
 
+        // once a container outgrows the simulation limit only its type and size
+        // are kept, so its elements stop costing memory and traversal time
+        if matches!(
+            opcode,
+            Append | Appends | List | Tuple | Dict | SetItem | SetItems | AddItems | FrozenSet
+        ) {
+            if let Some(top) = self.peek() {
+                top.summarize_if_larger_than(self.container_size_limit);
+            }
+        }
+
         // uncomment for debugging:
         // let after = self.state.stack.len();
         // let delta = after as i32 - before as i32;
@@ -907,7 +1068,7 @@ mod tests {
     #[test]
     fn get_put_and_memoize_preserve_aliasing() {
         let mut generator = Generator::new(Version::V4);
-        let shared = StackObjectRef::new(StackObject::List(Vec::new()));
+        let shared = StackObjectRef::new(StackObject::List(Items::new()));
         generator.push_ref(shared.clone());
 
         generator.process_stack_ops(OpcodeKind::BinPut, Some(&[0]));
@@ -937,7 +1098,7 @@ mod tests {
             module: "builtins".to_string(),
             name: "object".to_string(),
         });
-        let initial_args = StackObjectRef::new(StackObject::Tuple(Vec::new()));
+        let initial_args = StackObjectRef::new(StackObject::Tuple(Items::new()));
         let instance = StackObjectRef::new(StackObject::Instance(InstanceObject {
             callable,
             args: initial_args,
@@ -959,6 +1120,174 @@ mod tests {
         assert!(Rc::ptr_eq(&instance.args.0, &state.0));
     }
 
+    #[test]
+    fn reduce_of_ordered_dict_builds_a_dict() {
+        let mut generator = Generator::new(Version::V0);
+        generator.process_stack_ops(OpcodeKind::Global, Some(b"collections\nOrderedDict\n"));
+        generator.process_stack_ops(OpcodeKind::Mark, None);
+        generator.process_stack_ops(OpcodeKind::Tuple, None);
+        generator.process_stack_ops(OpcodeKind::Reduce, None);
+        assert!(generator
+            .peek()
+            .unwrap()
+            .borrow()
+            .is_container(ContainerKind::Dict));
+
+        // with arguments it is whatever the call returns
+        generator.process_stack_ops(OpcodeKind::Global, Some(b"collections\nOrderedDict\n"));
+        generator.process_stack_ops(OpcodeKind::Mark, None);
+        generator.process_stack_ops(OpcodeKind::Int, Some(b"1\n"));
+        generator.process_stack_ops(OpcodeKind::Tuple, None);
+        generator.process_stack_ops(OpcodeKind::Reduce, None);
+        assert!(matches!(
+            *generator.peek().unwrap().borrow(),
+            StackObject::Instance(_)
+        ));
+    }
+
+    #[test]
+    fn unicode_pushes_the_decoded_string() {
+        let mut generator = Generator::new(Version::V0);
+        generator.process_stack_ops(OpcodeKind::Unicode, Some(b"\\u0063ollections\n"));
+        let top = generator.peek().unwrap().borrow();
+        assert!(matches!(&*top, StackObject::String(text) if text == "collections"));
+    }
+
+    #[test]
+    fn python_2_strings_push_the_str_python_3_loads() {
+        let mut generator = Generator::new(Version::V0);
+        generator.process_stack_ops(OpcodeKind::String, Some(b"'it\\'s\\x41'\n"));
+        let top = generator.peek().unwrap().borrow();
+        assert!(matches!(&*top, StackObject::String(text) if text == "it's\x41"));
+        drop(top);
+
+        generator.process_stack_ops(OpcodeKind::ShortBinString, Some(b"abc"));
+        let top = generator.peek().unwrap().borrow();
+        assert!(matches!(&*top, StackObject::String(text) if text == "abc"));
+    }
+
+    #[test]
+    fn integers_past_64_bits_keep_their_value() {
+        let mut generator = Generator::new(Version::V0);
+        generator.process_stack_ops(OpcodeKind::Long, Some(b"-9223372036854775809L\n"));
+        let top = generator.peek().unwrap().borrow();
+        assert!(matches!(&*top, StackObject::BigInt(bytes)
+            if i128::from_le_bytes(bytes[..16].try_into().unwrap()) == i128::from(i64::MIN) - 1));
+        drop(top);
+
+        generator.process_stack_ops(OpcodeKind::Int, Some(b"18446744073709551616\n"));
+        let top = generator.peek().unwrap().borrow();
+        assert!(matches!(&*top, StackObject::BigInt(bytes)
+            if i128::from_le_bytes(bytes[..16].try_into().unwrap()) == 1 << 64));
+        drop(top);
+
+        let mut long1 = vec![17];
+        long1.extend([0; 16]);
+        long1.push(1);
+        generator.process_stack_ops(OpcodeKind::Long1, Some(&long1));
+        let top = generator.peek().unwrap().borrow();
+        assert!(matches!(&*top, StackObject::BigInt(bytes) if bytes.len() == 17));
+    }
+
+    #[test]
+    fn repeated_dict_keys_keep_their_last_value() {
+        let value_of = |generator: &Generator, key: &StackObjectRef| match &*generator
+            .peek()
+            .unwrap()
+            .borrow()
+        {
+            StackObject::Dict(dict) => match &*dict[key].borrow() {
+                StackObject::Int(value) => *value,
+                other => panic!("{other:?}"),
+            },
+            other => panic!("{other:?}"),
+        };
+        let mut generator = Generator::new(Version::V2);
+        let key = StackObjectRef::new(StackObject::Int(1));
+
+        generator.process_stack_ops(OpcodeKind::Mark, None);
+        for value in [2, 3] {
+            generator.push_ref(key.clone());
+            generator.push(StackObject::Int(value));
+        }
+        generator.process_stack_ops(OpcodeKind::Dict, None);
+        assert_eq!(value_of(&generator, &key), 3);
+
+        generator.process_stack_ops(OpcodeKind::Mark, None);
+        for value in [4, 5] {
+            generator.push_ref(key.clone());
+            generator.push(StackObject::Int(value));
+        }
+        generator.process_stack_ops(OpcodeKind::SetItems, None);
+        assert_eq!(value_of(&generator, &key), 5);
+    }
+
+    #[test]
+    fn readonly_buffer_keeps_bytes_identity() {
+        let mut generator = Generator::new(Version::V5);
+        let buffer = StackObjectRef::new(StackObject::Bytes(vec![1, 2, 3]));
+        generator.push_ref(buffer.clone());
+
+        generator.process_stack_ops(OpcodeKind::ReadOnlyBuffer, None);
+
+        assert!(Rc::ptr_eq(&generator.peek().unwrap().0, &buffer.0));
+    }
+
+    #[test]
+    fn memo_get_can_build_self_referencing_list() {
+        let mut generator = Generator::new(Version::V4);
+        generator.process_stack_ops(OpcodeKind::EmptyList, None);
+        generator.process_stack_ops(OpcodeKind::BinPut, Some(&[0]));
+        generator.process_stack_ops(OpcodeKind::BinGet, Some(&[0]));
+        generator.process_stack_ops(OpcodeKind::Append, None);
+
+        // l = []; l.append(l)
+        let list = generator.peek().unwrap().clone();
+        assert_eq!(generator.state.stack.len(), 1);
+        {
+            let borrowed = list.borrow();
+            let StackObject::List(items) = &*borrowed else {
+                panic!("expected list on stack after APPEND");
+            };
+            assert_eq!(items.len(), 1);
+            assert!(Rc::ptr_eq(&items[0].0, &list.0));
+        }
+
+        // the cycle is broken once nothing outside it holds a reference
+        let weak = Rc::downgrade(&list.0);
+        drop(list);
+        generator.reset();
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn oversized_containers_are_summarized_but_stay_usable() {
+        let mut generator = Generator::new(Version::V4).with_container_size_limit(2);
+        generator.process_stack_ops(OpcodeKind::EmptyList, None);
+        generator.process_stack_ops(OpcodeKind::BinPut, Some(&[0]));
+        generator.process_stack_ops(OpcodeKind::Mark, None);
+        for value in 0..3 {
+            generator.push(StackObject::Int(value));
+        }
+        generator.process_stack_ops(OpcodeKind::Appends, None);
+
+        // the memo alias sees the summary too
+        let memoized = generator.get(0).unwrap();
+        assert!(matches!(
+            *memoized.borrow(),
+            StackObject::Summarized {
+                kind: ContainerKind::List,
+                len: 3
+            }
+        ));
+
+        generator.push(StackObject::None);
+        assert!(generator.can_emit(OpcodeKind::Append));
+        generator.process_stack_ops(OpcodeKind::Append, None);
+        assert_eq!(memoized.borrow().container_len(), Some(4));
+        assert!(!generator.can_emit(OpcodeKind::SetItem));
+    }
+
     #[test]
     fn readonly_buffer_turns_bytearray_into_bytes() {
         let mut generator = Generator::new(Version::V5);
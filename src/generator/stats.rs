@@ -0,0 +1,69 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! per-run generation statistics.
+
+use super::Generator;
+
+/// statistics about the most recent generation run.
+///
+/// returned by [`Generator::stats`]. the values describe the simulated pickle
+/// machine, so they hold for the emitted pickle as the unpickler will see it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct GenerationStats {
+    /// total opcodes emitted, including PROTO, FRAME, cleanup, and STOP
+    pub opcodes: usize,
+    /// largest number of items on the stack at any point, MARKs included
+    pub peak_stack_depth: usize,
+    /// randomly chosen emissions a mutator changed that made it into the
+    /// pickle (not rolled back by safe mode or an emit hook)
+    pub mutated_emissions: usize,
+}
+
+impl Generator {
+    /// statistics for the most recent generation run.
+    ///
+    /// cleared by `reset()`, which every generation call runs first. a failed
+    /// run reports no opcodes.
+    pub fn stats(&self) -> GenerationStats {
+        GenerationStats {
+            opcodes: self.emitted_opcodes,
+            peak_stack_depth: self.state.stack.peak_len(),
+            mutated_emissions: self.mutated_emissions,
+        }
+    }
+
+    /// how many times each mutator fired in the most recent run, by name.
+    ///
+    /// unlike `mutated_emissions`, this counts every change a mutator made,
+    /// including those safe mode or an emit hook rolled back.
+    pub fn mutator_applications(&self) -> Vec<(String, usize)> {
+        self.mutator_applications
+            .borrow()
+            .iter()
+            .filter(|(_, count)| *count > 0)
+            .cloned()
+            .collect()
+    }
+
+    pub(super) fn count_mutation(&self, mutator: &str) {
+        let mut applications = self.mutator_applications.borrow_mut();
+        match applications.iter_mut().find(|(name, _)| name == mutator) {
+            Some((_, count)) => *count += 1,
+            None => applications.push((mutator.to_string(), 1)),
+        }
+    }
+}
@@ -0,0 +1,370 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! runtime invariant checks enabled by `with_strict_checks`.
+//!
+//! every emission funnels through `process_stack_ops`, which calls
+//! `check_emission` first while the simulated stack still reflects the state
+//! the opcode was emitted against. the checks cover:
+//!
+//! - the opcode belongs to the generator's protocol
+//! - the opcode does not pop more than the stack holds (or cross a MARK)
+//! - `can_emit` preconditions still hold at emission time (skipped with
+//!   unsafe mutations, which break them on purpose)
+//! - the argument bytes match the opcode's encoding, and the output ends with
+//!   exactly that encoding
+//!
+//! the first violation is recorded, and generation returns it as an error
+//! instead of emitting the rest of a questionable pickle.
+
+use color_eyre::eyre::eyre;
+use color_eyre::Result;
+
+use super::Generator;
+use crate::opcodes::{OpcodeKind, PICKLE_OPCODES};
+
+/// how an opcode's argument is laid out after the opcode byte.
+enum ArgFormat {
+    /// no argument
+    Empty,
+    /// exactly this many bytes
+    Fixed(usize),
+    /// a little-endian length prefix of this width, then `arg_bytes`
+    Counted(usize),
+    /// this many newline-terminated lines
+    Lines(usize),
+    /// LONG1/LONG4: `arg_bytes` carries its own length prefix of this width
+    SizedLong(usize),
+}
+
+fn arg_format(opcode: OpcodeKind) -> ArgFormat {
+    use OpcodeKind::*;
+
+    match opcode {
+        BinInt1 | BinPut | BinGet | Ext1 | Proto => ArgFormat::Fixed(1),
+        BinInt2 | Ext2 => ArgFormat::Fixed(2),
+        BinInt | LongBinPut | LongBinGet | Ext4 => ArgFormat::Fixed(4),
+        BinFloat | Frame => ArgFormat::Fixed(8),
+        Long1 => ArgFormat::SizedLong(1),
+        Long4 => ArgFormat::SizedLong(4),
+        ShortBinString | ShortBinBytes | ShortBinUnicode => ArgFormat::Counted(1),
+        BinString | BinBytes | BinUnicode => ArgFormat::Counted(4),
+        BinBytes8 | BinUnicode8 | ByteArray8 => ArgFormat::Counted(8),
+        Int | Long | Float | String | Unicode | Put | Get | PersID => ArgFormat::Lines(1),
+        Global | Inst => ArgFormat::Lines(2),
+        _ => ArgFormat::Empty,
+    }
+}
+
+/// the bytes that should follow the opcode byte for `arg`, or why `arg` can't
+/// be encoded for `opcode`.
+pub(super) fn encode_arg(
+    opcode: OpcodeKind,
+    arg: &[u8],
+) -> std::result::Result<Vec<u8>, std::string::String> {
+    match arg_format(opcode) {
+        ArgFormat::Empty => Err("takes no argument".into()),
+        ArgFormat::Fixed(len) => {
+            if arg.len() != len {
+                return Err(format!("needs {len} argument bytes, got {}", arg.len()));
+            }
+            Ok(arg.to_vec())
+        }
+        ArgFormat::Counted(width) => {
+            let len = arg.len() as u64;
+            // BINSTRING's length is signed
+            let max = match (opcode, width) {
+                (OpcodeKind::BinString, _) => i32::MAX as u64,
+                (_, 1) => u8::MAX as u64,
+                (_, 4) => u32::MAX as u64,
+                _ => u64::MAX,
+            };
+            if len > max {
+                return Err(format!(
+                    "payload of {len} bytes overflows its length prefix"
+                ));
+            }
+            let mut encoded = len.to_le_bytes()[..width].to_vec();
+            encoded.extend_from_slice(arg);
+            Ok(encoded)
+        }
+        ArgFormat::Lines(lines) => {
+            let newlines = arg.iter().filter(|&&b| b == b'\n').count();
+            if newlines != lines || arg.last() != Some(&b'\n') {
+                return Err(format!(
+                    "needs {lines} newline-terminated line(s), got {newlines} newline(s)"
+                ));
+            }
+            Ok(arg.to_vec())
+        }
+        ArgFormat::SizedLong(width) => {
+            let Some(prefix) = arg.get(..width) else {
+                return Err(format!("missing its {width}-byte size prefix"));
+            };
+            let mut size = [0u8; 8];
+            size[..width].copy_from_slice(prefix);
+            let size = u64::from_le_bytes(size);
+            if arg.len() as u64 != width as u64 + size {
+                return Err(format!(
+                    "declares {size} value bytes but carries {}",
+                    arg.len() - width
+                ));
+            }
+            Ok(arg.to_vec())
+        }
+    }
+}
+
+impl Generator {
+    /// enable internal invariant checks during generation.
+    ///
+    /// with strict checks on, every emitted opcode is verified against the
+    /// simulated stack (no underflow, no MARK consumed by a fixed-arity opcode,
+    /// `can_emit` preconditions hold, bar the empty MARK batches canonical mode
+    /// writes) and its argument bytes against the opcode's encoding. the first violation aborts generation with a descriptive error
+    /// instead of producing a questionable pickle.
+    ///
+    /// the checks cost time on every emission, so they're meant for debugging the
+    /// generator and for test suites rather than fuzzing campaigns.
+    pub fn with_strict_checks(mut self, strict: bool) -> Self {
+        self.strict_checks = strict;
+        self
+    }
+
+    /// record the first strict-check violation for the opcode about to be simulated.
+    pub(super) fn record_strict_violation(&mut self, opcode: OpcodeKind, arg_bytes: Option<&[u8]>) {
+        if self.strict_violation.is_some() {
+            return;
+        }
+        if let Err(reason) = self.check_emission(opcode, arg_bytes) {
+            tracing::debug!(
+                ?opcode,
+                offset = self.output_len(),
+                reason,
+                "strict check failed"
+            );
+            self.strict_violation = Some(format!(
+                "{opcode:?} at output offset {}: {reason}",
+                self.output_len()
+            ));
+        }
+    }
+
+    /// surface a recorded strict-check violation as an error.
+    pub(super) fn take_strict_violation(&mut self) -> Result<()> {
+        match self.strict_violation.take() {
+            Some(violation) => Err(eyre!("strict check failed: {violation}")),
+            Option::None => Ok(()),
+        }
+    }
+
+    fn check_emission(
+        &self,
+        opcode: OpcodeKind,
+        arg_bytes: Option<&[u8]>,
+    ) -> std::result::Result<(), std::string::String> {
+        use OpcodeKind::*;
+
+        let version = self.state.version as u8;
+        let in_protocol = PICKLE_OPCODES
+            .get(&version)
+            .is_some_and(|opcodes| opcodes.contains(&opcode));
+        // unsafe frames put FRAME in protocols before 4 on purpose
+        let misplaced_frame = opcode == Frame && self.unsafe_frames;
+        if !(in_protocol || misplaced_frame) {
+            return Err(format!("not part of protocol {version}"));
+        }
+
+        self.check_stack_depth(opcode)?;
+
+        if opcode == Stop {
+            if self.state.stack.len() != 1 || self.has_mark() {
+                return Err(format!(
+                    "STOP needs exactly one object on the stack, found {} slot(s)",
+                    self.state.stack.len()
+                ));
+            }
+        } else if !self.unsafe_mutations {
+            if !self.can_emit(opcode)
+                && !self.closes_empty_batch(opcode)
+                && !self.closes_any_mark(opcode)
+            {
+                return Err("can_emit preconditions do not hold".into());
+            }
+            self.check_memo_reference(opcode, arg_bytes)?;
+        }
+
+        // the opcode and its argument are the last thing written to the output
+        let mut expected = vec![opcode.as_u8()];
+        match (arg_format(opcode), arg_bytes) {
+            (ArgFormat::Empty, Option::None) => {}
+            (_, Option::None) => return Err("emitted without its argument".into()),
+            // unsafe persistent ids put a newline inside PERSID's line on purpose
+            (ArgFormat::Lines(_), Some(arg)) if opcode == PersID && self.unsafe_persistent_ids => {
+                expected.extend_from_slice(arg)
+            }
+            (_, Some(arg)) => expected.extend(encode_arg(opcode, arg)?),
+        }
+        if !self.output.ends_with(&expected) {
+            return Err("output does not end with the simulated opcode and argument".into());
+        }
+
+        Ok(())
+    }
+
+    /// whether `opcode` closes a MARK with nothing above it into a dict, or into
+    /// the list, dict, or set below it. CPython writes these (`MARK DICT` for an
+    /// empty dict in protocol 0, an empty SETITEMS after a full batch, and
+    /// Python 2 `MARK INST` for an instance without init arguments) and they
+    /// load fine, but `can_emit` keeps them out of generation.
+    fn closes_empty_batch(&self, opcode: OpcodeKind) -> bool {
+        use OpcodeKind::*;
+
+        let closes_batch = match opcode {
+            Dict | Inst => true,
+            Appends => self.is_list_at_mark(),
+            SetItems => self.is_dict_at_mark(),
+            AddItems => self.is_set_at_mark(),
+            _ => false,
+        };
+        closes_batch && self.count_items_to_mark() == Some(0)
+    }
+
+    /// check that a fixed-arity opcode has enough non-MARK items to pop, and
+    /// that a MARK-consuming opcode has a MARK to pop through.
+    fn check_stack_depth(
+        &self,
+        opcode: OpcodeKind,
+    ) -> std::result::Result<(), std::string::String> {
+        use OpcodeKind::*;
+
+        let pops = match opcode {
+            Appends | SetItems | AddItems | PopMark | Tuple | List | FrozenSet | Dict | Inst
+            | Obj => {
+                return if self.has_mark() {
+                    Ok(())
+                } else {
+                    Err("pops to a MARK, but the stack has none".into())
+                };
+            }
+            // POP is the one fixed-arity opcode allowed to discard a MARK
+            Pop => {
+                return if self.state.stack.len() >= 1 {
+                    Ok(())
+                } else {
+                    Err("stack underflow: POP on an empty stack".into())
+                };
+            }
+            // unsafe marks copy a MARK on purpose
+            Dup if self.unsafe_marks && self.dup_allowed() => return Ok(()),
+            Dup | Tuple1 | Memoize | BinPersID | Put | BinPut | LongBinPut | ReadOnlyBuffer
+            | Stop => 1,
+            Append | Tuple2 | Reduce | NewObj | Build | StackGlobal => 2,
+            SetItem | Tuple3 | NewObjEx => 3,
+            _ => 0,
+        };
+
+        if self.state.stack.len() < pops {
+            return Err(format!(
+                "stack underflow: pops {pops} item(s) from a stack of {}",
+                self.state.stack.len()
+            ));
+        }
+        if self.has_mark_in_top(pops) {
+            return Err(format!("would consume a MARK among its top {pops} item(s)"));
+        }
+        Ok(())
+    }
+
+    /// check that a memo GET reads an index that was previously PUT.
+    fn check_memo_reference(
+        &self,
+        opcode: OpcodeKind,
+        arg_bytes: Option<&[u8]>,
+    ) -> std::result::Result<(), std::string::String> {
+        let arg = arg_bytes.unwrap_or_default();
+        let index = match opcode {
+            OpcodeKind::Get => std::str::from_utf8(arg)
+                .ok()
+                .and_then(super::stack_ops::parse_text_number::<usize>),
+            OpcodeKind::BinGet => arg.first().map(|&b| b as usize),
+            OpcodeKind::LongBinGet => arg
+                .try_into()
+                .ok()
+                .map(|b: [u8; 4]| u32::from_le_bytes(b) as usize),
+            _ => return Ok(()),
+        };
+
+        match index {
+            Some(index) if self.state.memo.contains_key(&index) => Ok(()),
+            Some(index) => Err(format!("reads memo index {index}, which was never PUT")),
+            Option::None => Err("memo index argument is malformed".into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::Version;
+    use crate::stack::StackObject;
+
+    #[test]
+    fn encode_arg_matches_opcode_formats() {
+        assert_eq!(encode_arg(OpcodeKind::BinInt1, &[7]), Ok(vec![7]));
+        assert!(encode_arg(OpcodeKind::BinInt, &[1, 2]).is_err());
+        assert_eq!(
+            encode_arg(OpcodeKind::ShortBinBytes, b"ab"),
+            Ok(vec![2, b'a', b'b'])
+        );
+        assert!(encode_arg(OpcodeKind::ShortBinBytes, &[0; 256]).is_err());
+        assert!(encode_arg(OpcodeKind::Int, b"12").is_err());
+        assert!(encode_arg(OpcodeKind::Global, b"os\nsystem\n").is_ok());
+        assert!(encode_arg(OpcodeKind::Long1, &[4, 1, 2, 3]).is_err());
+        assert!(encode_arg(OpcodeKind::Pop, &[]).is_err());
+    }
+
+    #[test]
+    fn strict_checks_report_stack_and_argument_violations() {
+        let mut generator = Generator::new(Version::V2).with_strict_checks(true);
+
+        // SETITEM with a MARK among the key/value slots
+        generator.push(StackObject::Dict(Default::default()));
+        generator.push(StackObject::Int(1));
+        generator.push(StackObject::Mark);
+        generator.output.push(OpcodeKind::SetItem.as_u8());
+        generator.record_strict_violation(OpcodeKind::SetItem, None);
+        let err = generator.take_strict_violation().unwrap_err().to_string();
+        assert!(err.contains("MARK"), "{err}");
+
+        // BININT with a truncated argument
+        generator.reset();
+        generator
+            .output
+            .extend_from_slice(&[OpcodeKind::BinInt.as_u8(), 1, 2]);
+        generator.record_strict_violation(OpcodeKind::BinInt, Some(&[1, 2]));
+        let err = generator.take_strict_violation().unwrap_err().to_string();
+        assert!(err.contains("needs 4 argument bytes"), "{err}");
+
+        // a well-formed emission passes
+        generator.reset();
+        generator
+            .output
+            .extend_from_slice(&[OpcodeKind::BinInt1.as_u8(), 9]);
+        generator.record_strict_violation(OpcodeKind::BinInt1, Some(&[9]));
+        assert!(generator.take_strict_violation().is_ok());
+    }
+}
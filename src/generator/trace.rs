@@ -0,0 +1,131 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! recorded entropy decisions (generate_recorded, replay).
+//!
+//! an [`EntropyTrace`] lists every answer a generation run got from its
+//! entropy source, in order. replaying it rebuilds the same pickle without the
+//! PRNG, and replaying an edited copy rebuilds the pickle with one decision
+//! changed and everything before it kept, which makes a trace a
+//! PRNG-independent input for debugging and shrinking.
+
+use serde::{Deserialize, Serialize};
+
+/// one answer from an entropy source.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Decision {
+    /// a `gen_bool`
+    Bool(bool),
+    /// a `gen_u8`
+    U8(u8),
+    /// a `gen_u16`
+    U16(u16),
+    /// a `gen_u32`
+    U32(u32),
+    /// a `gen_i32`
+    I32(i32),
+    /// a `gen_i64`
+    I64(i64),
+    /// a `gen_f64`
+    F64(f64),
+    /// a `gen_range` (or `choose_index`) of `min..max` that gave `value`
+    Range { min: u64, max: u64, value: u64 },
+    /// a `gen_bytes`
+    Bytes(Vec<u8>),
+    /// a `gen_ascii_char`
+    Char(char),
+}
+
+/// the entropy decisions of one generation run, in the order they were made.
+///
+/// serializes to JSON as `{"decisions": [...]}`, with one externally tagged
+/// [`Decision`] per draw (`{"bool": true}`, `{"range": {"min": 0, "max": 4,
+/// "value": 2}}`), so a trace can be saved, edited by hand, and replayed.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EntropyTrace {
+    /// every decision, first draw first
+    pub decisions: Vec<Decision>,
+}
+
+impl EntropyTrace {
+    /// the number of recorded decisions.
+    pub fn len(&self) -> usize {
+        self.decisions.len()
+    }
+
+    /// whether the run made no decisions at all.
+    pub fn is_empty(&self) -> bool {
+        self.decisions.is_empty()
+    }
+}
+
+/// take the decision at `position` and move past it.
+pub(super) fn next_decision<'t>(
+    decisions: &'t [Decision],
+    position: &mut usize,
+) -> Option<&'t Decision> {
+    let decision = decisions.get(*position);
+    *position += 1;
+    decision
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn traces_round_trip_through_json() {
+        let trace = EntropyTrace {
+            decisions: vec![
+                Decision::Bool(true),
+                Decision::Range {
+                    min: 0,
+                    max: 4,
+                    value: 2,
+                },
+                Decision::Bytes(vec![1, 2]),
+                Decision::Char('x'),
+                Decision::F64(0.5),
+            ],
+        };
+        let json = serde_json::to_string(&trace).unwrap();
+        assert!(json
+            .starts_with(r#"{"decisions":[{"bool":true},{"range":{"min":0,"max":4,"value":2}}"#));
+        assert_eq!(serde_json::from_str::<EntropyTrace>(&json).unwrap(), trace);
+    }
+
+    #[test]
+    fn f64_decisions_keep_every_bit() {
+        // the shortest decimal spelling of a draw must parse back to the same
+        // bits, or a replayed FLOAT differs from the recorded one
+        let trace = EntropyTrace {
+            decisions: vec![
+                Decision::F64(0.447_067_615_733_246_05),
+                Decision::F64(0.1 + 0.2),
+                Decision::F64(f64::MIN_POSITIVE),
+            ],
+        };
+        let json = serde_json::to_string(&trace).unwrap();
+        let replayed = serde_json::from_str::<EntropyTrace>(&json).unwrap();
+        for (recorded, replayed) in trace.decisions.iter().zip(&replayed.decisions) {
+            let (Decision::F64(a), Decision::F64(b)) = (recorded, replayed) else {
+                unreachable!();
+            };
+            assert_eq!(a.to_bits(), b.to_bits());
+        }
+    }
+}
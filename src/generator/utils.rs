@@ -22,7 +22,7 @@
 //! manipulate the simulated PVM state.
 
 use super::Generator;
-use crate::stack::{StackObject, StackObjectRef};
+use crate::stack::{ContainerKind, StackObject, StackObjectRef};
 
 impl Generator {
     /// peek at the top of the stack without removing it.
@@ -46,7 +46,7 @@ impl Generator {
     /// this preserves aliasing for opcodes like GET/BINGET that must re-use
     /// the memoized object instead of creating a fresh copy.
     pub(super) fn push_ref(&mut self, value: StackObjectRef) {
-        self.state.stack.inner.push(value);
+        self.state.stack.push_ref(value);
     }
 
     /// pop a value from the stack.
@@ -78,11 +78,7 @@ impl Generator {
     /// returns `true` if at least one MARK is present on the stack. MARKs are
     /// used to delimit groups of items for operations like building lists or tuples.
     pub(super) fn has_mark(&self) -> bool {
-        self.state
-            .stack
-            .inner
-            .iter()
-            .any(|obj| matches!(*obj.borrow(), StackObject::Mark))
+        self.state.stack.has_mark()
     }
 
     /// peek at a stack object at a specific depth from the top.
@@ -90,21 +86,16 @@ impl Generator {
     /// depth 0 is the top of the stack, depth 1 is one below the top, etc.
     /// returns `None` if the depth exceeds the stack size.
     pub(super) fn peek_at(&self, depth: usize) -> Option<&StackObjectRef> {
-        let len = self.state.stack.len();
-        if depth < len {
-            self.state.stack.inner.get(len - 1 - depth)
-        } else {
-            None
-        }
+        self.state.stack.peek_at(depth)
     }
 
     /// check if the object at a given depth is a list.
     ///
     /// returns `true` if the object at the specified depth from the top is a
-    /// `StackObject::List`, `false` otherwise or if the depth is invalid.
+    /// `StackObject::List` (or a summarized one), `false` otherwise or if the depth is invalid.
     pub(super) fn is_list_at(&self, depth: usize) -> bool {
         if let Some(obj_ref) = self.peek_at(depth) {
-            matches!(*obj_ref.borrow(), StackObject::List(_))
+            obj_ref.borrow().is_container(ContainerKind::List)
         } else {
             false
         }
@@ -113,10 +104,10 @@ impl Generator {
     /// check if the object at a given depth is a dict.
     ///
     /// returns `true` if the object at the specified depth from the top is a
-    /// `StackObject::Dict`, `false` otherwise or if the depth is invalid.
+    /// `StackObject::Dict` (or a summarized one), `false` otherwise or if the depth is invalid.
     pub(super) fn is_dict_at(&self, depth: usize) -> bool {
         if let Some(obj_ref) = self.peek_at(depth) {
-            matches!(*obj_ref.borrow(), StackObject::Dict(_))
+            obj_ref.borrow().is_container(ContainerKind::Dict)
         } else {
             false
         }
@@ -140,10 +131,10 @@ impl Generator {
     /// check if the object at a given depth is a tuple.
     ///
     /// returns `true` if the object at the specified depth from the top is a
-    /// `StackObject::Tuple`, `false` otherwise or if the depth is invalid.
+    /// `StackObject::Tuple` (or a summarized one), `false` otherwise or if the depth is invalid.
     pub(super) fn is_tuple_at(&self, depth: usize) -> bool {
         if let Some(obj_ref) = self.peek_at(depth) {
-            matches!(*obj_ref.borrow(), StackObject::Tuple(_))
+            obj_ref.borrow().is_container(ContainerKind::Tuple)
         } else {
             false
         }
@@ -163,44 +154,22 @@ impl Generator {
 
     /// check if there's a list immediately below the topmost MARK.
     ///
-    /// searches from the top of the stack for the first MARK, then checks if the
+    /// looks up the topmost MARK in the stack's mark index, then checks if the
     /// object immediately below it is a list. returns `false` if no MARK is found,
     /// if the MARK is at the bottom, or if the object below isn't a list.
     pub(super) fn is_list_at_mark(&self) -> bool {
-        // check if there's a list below the topmost MARK
-        for (idx, obj_ref) in self.state.stack.inner.iter().enumerate().rev() {
-            if matches!(*obj_ref.borrow(), StackObject::Mark) {
-                // found the mark, check if item below it is a list
-                if idx > 0 {
-                    if let Some(below_mark) = self.state.stack.inner.get(idx - 1) {
-                        return matches!(*below_mark.borrow(), StackObject::List(_));
-                    }
-                }
-                return false;
-            }
-        }
-        false
+        self.below_top_mark()
+            .is_some_and(|obj| obj.borrow().is_container(ContainerKind::List))
     }
 
     /// check if there's a dict immediately below the topmost MARK.
     ///
-    /// searches from the top of the stack for the first MARK, then checks if the
+    /// looks up the topmost MARK in the stack's mark index, then checks if the
     /// object immediately below it is a dict. returns `false` if no MARK is found,
     /// if the MARK is at the bottom, or if the object below isn't a dict.
     pub(super) fn is_dict_at_mark(&self) -> bool {
-        // check if there's a dict below the topmost MARK
-        for (idx, obj_ref) in self.state.stack.inner.iter().enumerate().rev() {
-            if matches!(*obj_ref.borrow(), StackObject::Mark) {
-                // found the mark, check if item below it is a dict
-                if idx > 0 {
-                    if let Some(below_mark) = self.state.stack.inner.get(idx - 1) {
-                        return matches!(*below_mark.borrow(), StackObject::Dict(_));
-                    }
-                }
-                return false;
-            }
-        }
-        false
+        self.below_top_mark()
+            .is_some_and(|obj| obj.borrow().is_container(ContainerKind::Dict))
     }
 
     /// count the number of items from the top of the stack to the topmost MARK.
@@ -209,13 +178,7 @@ impl Generator {
     /// when searching from the top. returns `None` if no MARK is found on the stack.
     /// this is used to determine how many items will be consumed by MARK-based opcodes.
     pub(super) fn count_items_to_mark(&self) -> Option<usize> {
-        // count items from TOS back to (but not including) the topmost MARK
-        for (count, obj_ref) in self.state.stack.inner.iter().rev().enumerate() {
-            if matches!(*obj_ref.borrow(), StackObject::Mark) {
-                return Some(count);
-            }
-        }
-        None // no MARK found
+        self.state.stack.items_above_mark()
     }
 
     /// check whether any of the top `count` stack slots contain a MARK.
@@ -223,13 +186,7 @@ impl Generator {
     /// returns `true` if a MARK appears within the slice of the stack that a
     /// fixed-arity opcode would pop.
     pub(super) fn has_mark_in_top(&self, count: usize) -> bool {
-        self.state
-            .stack
-            .inner
-            .iter()
-            .rev()
-            .take(count)
-            .any(|obj_ref| matches!(*obj_ref.borrow(), StackObject::Mark))
+        self.state.stack.has_mark_in_top(count)
     }
 
     /// check if the object at a given depth is a string.
@@ -246,47 +203,39 @@ impl Generator {
 
     /// check if there's a set immediately below the topmost MARK.
     ///
-    /// searches from the top of the stack for the first MARK, then checks if the
+    /// looks up the topmost MARK in the stack's mark index, then checks if the
     /// object immediately below it is a set. returns `false` if no MARK is found,
     /// if the MARK is at the bottom, or if the object below isn't a set.
     pub(super) fn is_set_at_mark(&self) -> bool {
-        // check if there's a set below the topmost MARK
-        for (idx, obj_ref) in self.state.stack.inner.iter().enumerate().rev() {
-            if matches!(*obj_ref.borrow(), StackObject::Mark) {
-                // found the mark, check if item below it is a set
-                if idx > 0 {
-                    if let Some(below_mark) = self.state.stack.inner.get(idx - 1) {
-                        return matches!(*below_mark.borrow(), StackObject::Set(_));
-                    }
-                }
-                return false;
-            }
-        }
-        false
+        self.below_top_mark()
+            .is_some_and(|obj| obj.borrow().is_container(ContainerKind::Set))
     }
 
     /// check if there's a callable immediately above the topmost MARK.
     ///
-    /// searches from the top of the stack for the first MARK, then checks if the
+    /// looks up the topmost MARK in the stack's mark index, then checks if the
     /// object immediately above it (closer to TOS) is callable. returns `false` if
     /// no MARK is found, if the MARK is at the top, or if the object above isn't callable.
     /// this is used to validate BUILD/INST opcode preconditions.
     pub(super) fn is_callable_above_mark(&self) -> bool {
-        // check if there's a callable immediately above the topmost MARK
-        for (idx, obj_ref) in self.state.stack.inner.iter().enumerate().rev() {
-            if matches!(*obj_ref.borrow(), StackObject::Mark) {
-                let above_idx = idx + 1;
-                if above_idx < self.state.stack.inner.len() {
-                    if let Some(above_mark) = self.state.stack.inner.get(above_idx) {
-                        return matches!(
-                            *above_mark.borrow(),
-                            StackObject::Callable(_) | StackObject::Global { .. }
-                        );
-                    }
-                }
-                return false;
-            }
-        }
-        false
+        let Some(mark_idx) = self.state.stack.top_mark() else {
+            return false;
+        };
+        self.state
+            .stack
+            .items()
+            .get(mark_idx + 1)
+            .is_some_and(|obj| {
+                matches!(
+                    *obj.borrow(),
+                    StackObject::Callable(_) | StackObject::Global { .. }
+                )
+            })
+    }
+
+    /// the object immediately below the topmost MARK, if there is one.
+    fn below_top_mark(&self) -> Option<&StackObjectRef> {
+        let mark_idx = self.state.stack.top_mark()?;
+        self.state.stack.items().get(mark_idx.checked_sub(1)?)
     }
 }
@@ -30,6 +30,112 @@ use super::Generator;
 use crate::opcodes::{OpcodeKind, PICKLE_OPCODES};
 use crate::stack::StackObject;
 
+/// set of opcodes that passed validation, stored as a bitmask over the current
+/// protocol's opcode table.
+///
+/// bit `i` stands for `table[i]`, so iterating set bits visits opcodes in table
+/// order. selection by index therefore picks exactly the opcode a filtered `Vec`
+/// would have, without allocating on every emission.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct ValidOpcodes {
+    table: &'static [OpcodeKind],
+    bits: u128,
+}
+
+impl ValidOpcodes {
+    /// largest opcode table the bitmask can represent.
+    pub(super) const CAPACITY: usize = u128::BITS as usize;
+
+    fn empty() -> Self {
+        Self {
+            table: &[],
+            bits: 0,
+        }
+    }
+
+    /// number of opcodes in the set.
+    pub(super) fn len(&self) -> usize {
+        self.bits.count_ones() as usize
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.bits == 0
+    }
+
+    /// keep only the opcodes for which `keep` returns true.
+    pub(super) fn retain(&mut self, mut keep: impl FnMut(OpcodeKind) -> bool) {
+        let mut remaining = self.bits;
+        while remaining != 0 {
+            let idx = remaining.trailing_zeros();
+            remaining &= remaining - 1;
+            if !keep(self.table[idx as usize]) {
+                self.bits &= !(1 << idx);
+            }
+        }
+    }
+
+    /// the `n`th opcode in table order, if the set has that many.
+    pub(super) fn nth(&self, n: usize) -> Option<OpcodeKind> {
+        let mut remaining = self.bits;
+        for _ in 0..n {
+            if remaining == 0 {
+                return None;
+            }
+            remaining &= remaining - 1;
+        }
+        (remaining != 0).then(|| self.table[remaining.trailing_zeros() as usize])
+    }
+}
+
+/// whether `opcode` leaves the stack one item deeper than it found it.
+///
+/// every other opcode either keeps the depth (TUPLE1, MEMOIZE, BINPERSID, ...)
+/// or pops more than it pushes.
+fn grows_stack(opcode: OpcodeKind) -> bool {
+    use OpcodeKind::*;
+
+    matches!(
+        opcode,
+        Dup | Mark
+            | None
+            | NewTrue
+            | NewFalse
+            | Int
+            | Long
+            | Long1
+            | Long4
+            | BinInt
+            | BinInt1
+            | BinInt2
+            | Float
+            | BinFloat
+            | String
+            | BinString
+            | ShortBinString
+            | Unicode
+            | ShortBinUnicode
+            | BinUnicode
+            | BinUnicode8
+            | ShortBinBytes
+            | BinBytes
+            | BinBytes8
+            | ByteArray8
+            | EmptyList
+            | EmptyDict
+            | EmptyTuple
+            | EmptySet
+            | Global
+            | Get
+            | BinGet
+            | LongBinGet
+            | PersID
+            | Ext1
+            | Ext2
+            | Ext4
+            | NextBuffer
+    )
+}
+
 impl Generator {
     /// get all opcodes that are valid for the current protocol version and state.
     ///
@@ -37,27 +143,40 @@ impl Generator {
     /// to only those that can be safely emitted given the current stack and memo state.
     /// this is the primary entry point for opcode selection during generation.
     ///
-    /// returns a vector of `OpcodeKind` values that pass the `can_emit()` validation.
-    pub(super) fn get_valid_opcodes(&self) -> Vec<OpcodeKind> {
+    /// returns the opcodes that pass the `can_emit()` validation and the caller's
+    /// opcode filters (see `with_opcode_filter`) as a [`ValidOpcodes`]
+    /// bitmask, so the hot loop doesn't allocate.
+    pub(super) fn get_valid_opcodes(&self) -> ValidOpcodes {
         let version = self.state.version as u8;
         let Some(all_opcodes) = PICKLE_OPCODES.get(&version) else {
-            return vec![];
+            return ValidOpcodes::empty();
         };
+        debug_assert!(all_opcodes.len() <= ValidOpcodes::CAPACITY);
+
+        let mut bits = 0u128;
+        for (idx, &op) in all_opcodes.iter().enumerate() {
+            if self.can_emit(op)
+                && self.restrictions_allow(op)
+                && self.signatures_allow(op)
+                && self.passes_opcode_filters(op)
+            {
+                bits |= 1 << idx;
+            }
+        }
 
-        all_opcodes
-            .iter()
-            .filter(|&&op| self.can_emit(op))
-            .copied()
-            .collect()
+        ValidOpcodes {
+            table: all_opcodes,
+            bits,
+        }
     }
 
-    /// select an opcode from a list using weighted random selection.
+    /// select an opcode from a set using weighted random selection.
     ///
     /// currently implements uniform random selection from the provided opcodes.
-    /// returns `OpcodeKind::None` as a safe fallback if the input list is empty.
+    /// returns `OpcodeKind::None` as a safe fallback if the set is empty.
     ///
     /// # Parameters
-    /// - `opcodes`: list of valid opcodes to choose from
+    /// - `opcodes`: set of valid opcodes to choose from
     /// - `source`: entropy source for random selection
     ///
     /// # Future Work
@@ -65,7 +184,7 @@ impl Generator {
     /// current state (e.g., favor value-producing opcodes when stack is empty).
     pub(super) fn weighted_choice(
         &self,
-        opcodes: Vec<OpcodeKind>,
+        opcodes: ValidOpcodes,
         source: &mut GenerationSource,
     ) -> OpcodeKind {
         if opcodes.is_empty() {
@@ -75,7 +194,7 @@ impl Generator {
 
         // uniform random selection
         let idx = source.choose_index(opcodes.len());
-        opcodes[idx]
+        opcodes.nth(idx).unwrap_or(OpcodeKind::None)
     }
 
     /// check if a specific opcode can be safely emitted in the current state.
@@ -95,23 +214,22 @@ impl Generator {
     pub(super) fn can_emit(&self, opcode: OpcodeKind) -> bool {
         use OpcodeKind::*;
 
+        // a full stack only accepts opcodes that don't grow it
+        if self
+            .max_stack_depth
+            .is_some_and(|limit| self.state.stack.len() >= limit)
+            && grows_stack(opcode)
+        {
+            return false;
+        }
+
         match opcode {
             // stack manipulation - need items on stack
             Pop => self.state.stack.len() >= 1,
-            Dup => {
-                // DUP requires at least 1 item, and TOS can't be a MARK
-                // duplicating MARKs creates invalid pickle state that causes
-                // various MARK-reliant opcodes to crash
-                if self.state.stack.len() < 1 {
-                    return false;
-                }
-
-                if let Some(top) = self.peek() {
-                    !matches!(*top.borrow(), StackObject::Mark)
-                } else {
-                    false
-                }
-            }
+            // DUP requires at least 1 item, and TOS can't be a MARK unless
+            // marks are unsafe: duplicating MARKs creates pickle state that
+            // Python 3 and pickletools reject
+            Dup => self.dup_allowed(),
 
             // list operations
             Append => {
@@ -128,7 +246,10 @@ impl Generator {
             }
 
             // dict operations
-            SetItem => self.state.stack.len() >= 3 && self.is_dict_at(2),
+            // key and value must not be MARKs; the unpickler can't pop across one
+            SetItem => {
+                self.state.stack.len() >= 3 && self.is_dict_at(2) && !self.has_mark_in_top(2)
+            }
             SetItems => {
                 // need: MARK, dict below mark, and even number of items (key-value pairs)
                 self.has_mark()
@@ -197,17 +318,21 @@ impl Generator {
             }
 
             // STACK_GLOBAL needs 2 strings on stack (module at depth 1, name at depth 0)
-            // in unsafe mode, allow any 2 values (type confusion will replace them)
+            // in unsafe mode, allow any 2 non-MARK values (type confusion will replace them)
             StackGlobal => {
                 if self.unsafe_mutations {
-                    self.state.stack.len() >= 2
+                    self.state.stack.len() >= 2 && !self.has_mark_in_top(2)
                 } else {
                     self.state.stack.len() >= 2 && self.is_string_at(0) && self.is_string_at(1)
                 }
             }
 
             // persistent-id opcodes require unpickler-side persistent_load support
-            BinPersID => self.allow_persistent_id_opcodes && self.state.stack.len() >= 1,
+            BinPersID => {
+                self.allow_persistent_id_opcodes
+                    && self.state.stack.len() >= 1
+                    && !self.has_mark_in_top(1)
+            }
 
             // proto should only be emitted once at the start
             Proto => !self.state.proto_emitted,
@@ -240,22 +365,62 @@ impl Generator {
                     })
             }
 
-            // frame: handled specially after generation is complete
-            Frame => false, // don't emit during generation, will be inserted at the end if needed
+            // frame: inserted around the whole pickle after generation is
+            // complete, and only emitted mid-pickle with unsafe frames
+            Frame => self.unsafe_frames,
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::opcodes::OpcodeKind;
-    use crate::stack::StackObject;
+    use super::ValidOpcodes;
+    use crate::opcodes::{OpcodeKind, PICKLE_OPCODES};
+    use crate::stack::{Items, StackObject};
     use crate::{Generator, Version};
 
+    #[test]
+    fn opcode_tables_fit_in_bitmask() {
+        for (version, table) in PICKLE_OPCODES.entries() {
+            assert!(
+                table.len() <= ValidOpcodes::CAPACITY,
+                "protocol {version} has {} opcodes",
+                table.len()
+            );
+        }
+    }
+
+    #[test]
+    fn valid_opcodes_match_can_emit_in_table_order() {
+        let mut generator = Generator::new(Version::V5);
+        generator.push(StackObject::List(Items::new()));
+        generator.push(StackObject::Mark);
+        generator.push(StackObject::None);
+
+        let table = PICKLE_OPCODES.get(&5).unwrap();
+        let expected: Vec<OpcodeKind> = table
+            .iter()
+            .copied()
+            .filter(|&op| generator.can_emit(op))
+            .collect();
+
+        let valid = generator.get_valid_opcodes();
+        assert_eq!(valid.len(), expected.len());
+        for (n, op) in expected.iter().enumerate() {
+            assert_eq!(valid.nth(n), Some(*op));
+        }
+        assert_eq!(valid.nth(expected.len()), None);
+
+        let mut filtered = valid;
+        filtered.retain(|op| op != OpcodeKind::Appends);
+        assert_eq!(filtered.len(), expected.len() - 1);
+        assert!((0..filtered.len()).all(|n| filtered.nth(n) != Some(OpcodeKind::Appends)));
+    }
+
     #[test]
     fn append_rejects_mark_item() {
         let mut generator = Generator::new(Version::V4);
-        generator.push(StackObject::List(Vec::new()));
+        generator.push(StackObject::List(Items::new()));
         generator.push(StackObject::Mark);
 
         assert!(!generator.can_emit(OpcodeKind::Append));
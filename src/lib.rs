@@ -32,17 +32,53 @@
 //! std::fs::write("output.pkl", pickle_bytes).unwrap();
 //! ```
 
+#[cfg(feature = "capi")]
+pub mod capi;
 mod cli;
+mod config;
+pub mod disasm;
+pub mod fuzz_harness;
 mod generator;
+pub mod loadcheck;
+pub mod metrics;
+pub mod monitor;
 pub mod mutators;
 mod opcodes;
+pub mod output;
 mod protocol;
 #[cfg(feature = "python-bindings")]
 mod python;
+pub mod risk;
+#[cfg(feature = "serve")]
+pub mod serve;
 mod stack;
 mod state;
+#[cfg(feature = "wasm")]
+mod wasm;
 
-pub use cli::Cli;
-pub use generator::Generator;
-pub use mutators::{EmissionSnapshot, Mutator, MutatorKind, PostProcessEmission};
-pub use protocol::Version;
+#[cfg(feature = "serve")]
+pub use cli::ServeArgs;
+pub use cli::{
+    AnalyzeArgs, Cli, Command, DatasetArgs, DisArgs, DistillArgs, GenerateArgs, GeneratorOptions,
+    GrepArgs, LogFormat, MinimizeArgs, MutateArgs, ValidateArgs,
+};
+pub use config::GeneratorConfig;
+pub use fuzz_harness::FuzzConfig;
+pub use generator::{
+    Annotation, CleanupPolicy, Decision, Dtype, EmitVerdict, EntropySource, EntropyTrace,
+    ExhaustionPolicy, GenerationSource, GenerationStats, Generator, GeneratorPool, MutationPolicy,
+    MutationScope, MutationTarget, NdarraySpec, PooledGenerator, ProtoHeader, Shrunk,
+    SizeDistribution, TimeBudgetExceeded, DEFAULT_CONTAINER_SIZE_LIMIT, GENERATOR_FORMAT_VERSION,
+    MAX_STRESS_OPS,
+};
+pub use mutators::{
+    register_mutator, register_unsafe_mutator, registered_mutators, EmissionSnapshot, Mutator,
+    MutatorChoice, MutatorConfig, MutatorKind, PostProcessEmission,
+};
+pub use opcodes::{
+    ArgFormat, ArgLayout, Opcode, OpcodeInfo, OpcodeKind, StackEffect, OPCODE_TABLE,
+};
+pub use output::{Compression, NameTemplate};
+pub use protocol::{ProtocolMix, Version};
+pub use stack::{Stack, StackObject, StackObjectRef};
+pub use state::State;
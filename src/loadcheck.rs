@@ -0,0 +1,590 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! checking the simulated root object against the one `pickle.loads` builds.
+//!
+//! the generator simulates the unpickler's stack to decide what it may emit
+//! next. [`crate::disasm::validate`] and `pickletools` only check the
+//! opcodes' stack effects, so a simulation that gets a value wrong (a string
+//! that keeps its quotes, an integer cut to 64 bits, a dict with two equal
+//! keys) goes unnoticed until a later choice depends on it. this module
+//! writes the simulated root and the loaded object in one canonical form and
+//! compares them, for pickles generated in loadable mode.
+//!
+//! the canonical form is JSON. every value is an array starting with its
+//! type: `["none"]`, `["bool", true]`, `["int", "7"]`, `["float", "0.5"]`
+//! (Python's `repr`), `["str", "a"]`, `["bytes", "6162"]` and
+//! `["bytearray", "6162"]` (hex), `["list", [...]]`, `["tuple", [...]]`,
+//! `["set", [...]]`, `["frozenset", [...]]`, `["dict", [[key, value], ...]]`,
+//! and `["cycle"]` for a container reached again from inside itself. the
+//! simulation writes `["?"]` for what it doesn't model (instances, globals,
+//! summarized containers) and for entries it can't pin down, and `["?"]`
+//! matches anything. sets and dicts match in any order.
+//!
+//! a [`PythonPool`] loads the pickles in long-lived `python3` workers that
+//! only import the globals loadable mode uses, so a run pays for one
+//! interpreter start per thread rather than one per pickle.
+
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::rc::Rc;
+use std::sync::Mutex;
+
+use color_eyre::eyre::{bail, eyre};
+use color_eyre::Result;
+use serde_json::{json, Value};
+
+use crate::disasm::big_int_to_decimal;
+use crate::opcodes::python_float_repr;
+use crate::{Generator, StackObject, StackObjectRef};
+
+/// the canonical form of a value the simulation doesn't pin down.
+const WILDCARD: &str = "?";
+
+/// containers nested deeper than this are written as wildcards on both
+/// sides, which keeps the worker's replies within `serde_json`'s nesting
+/// limit. [`LOADER_SOURCE`] spells the same number.
+const MAX_DEPTH: usize = 24;
+
+/// the worker `python3` runs: it reads pickles framed by an 8-byte
+/// little-endian length from stdin and answers each with one JSON line,
+/// `{"root": form}` or `{"error": message}`.
+pub const LOADER_SOURCE: &str = r#"import io
+import json
+import pickle
+import struct
+import sys
+
+TYPES = ("list", "dict", "set", "frozenset", "complex", "bytearray")
+ALLOWED = {(module, name) for module in ("builtins", "__builtin__") for name in TYPES}
+ALLOWED |= {("copyreg", "_reconstructor"), ("copy_reg", "_reconstructor")}
+
+
+class Unpickler(pickle.Unpickler):
+    def find_class(self, module, name):
+        if (module, name) not in ALLOWED:
+            raise pickle.UnpicklingError(f"{module}.{name} is not a loadable-mode global")
+        return super().find_class(module, name)
+
+
+def text(value):
+    # lone surrogates can't cross JSON; the simulation holds U+FFFD instead
+    return "".join("\ufffd" if 0xD800 <= ord(c) < 0xE000 else c for c in value)
+
+
+def form(obj, ancestors):
+    if len(ancestors) >= 24:
+        return ["?"]
+    kind = type(obj)
+    if obj is None:
+        return ["none"]
+    if kind is bool:
+        return ["bool", obj]
+    if kind is int:
+        return ["int", str(obj)]
+    if kind is float:
+        return ["float", repr(obj)]
+    if kind is str:
+        return ["str", text(obj)]
+    if kind in (bytes, bytearray):
+        return [kind.__name__, obj.hex()]
+    if kind in (list, tuple, set, frozenset, dict):
+        if id(obj) in ancestors:
+            return ["cycle"]
+        ancestors.add(id(obj))
+        if kind is dict:
+            items = [[form(k, ancestors), form(v, ancestors)] for k, v in obj.items()]
+        else:
+            items = [form(item, ancestors) for item in obj]
+        ancestors.discard(id(obj))
+        return [kind.__name__, items]
+    return ["other", f"{kind.__module__}.{kind.__qualname__}"]
+
+
+while True:
+    header = sys.stdin.buffer.read(8)
+    if len(header) < 8:
+        break
+    data = sys.stdin.buffer.read(struct.unpack("<Q", header)[0])
+    try:
+        reply = {"root": form(Unpickler(io.BytesIO(data)).load(), set())}
+    except BaseException as e:
+        reply = {"error": f"{type(e).__name__}: {e}"}
+    sys.stdout.write(json.dumps(reply) + "\n")
+    sys.stdout.flush()
+"#;
+
+/// the canonical form of `object`; see the module docs.
+pub fn canonical_form(object: &StackObjectRef) -> Value {
+    form(object, &mut Vec::new())
+}
+
+fn wildcard() -> Value {
+    json!([WILDCARD])
+}
+
+fn form(object: &StackObjectRef, ancestors: &mut Vec<*const ()>) -> Value {
+    if ancestors.len() >= MAX_DEPTH {
+        return wildcard();
+    }
+    let address = Rc::as_ptr(object.as_rc()) as *const ();
+    if ancestors.contains(&address) {
+        return json!(["cycle"]);
+    }
+    ancestors.push(address);
+    let mut forms = |items: &mut dyn Iterator<Item = &StackObjectRef>| -> Vec<Value> {
+        items.map(|item| form(item, ancestors)).collect()
+    };
+    let value = match &*object.borrow() {
+        StackObject::None => json!(["none"]),
+        StackObject::Bool(value) => json!(["bool", value]),
+        StackObject::Int(value) => json!(["int", value.to_string()]),
+        StackObject::BigInt(bytes) => json!(["int", big_int_to_decimal(bytes)]),
+        StackObject::Float(value) => json!(["float", python_float_repr(*value)]),
+        StackObject::String(text) => json!(["str", text]),
+        StackObject::Bytes(bytes) => json!(["bytes", hex(bytes)]),
+        StackObject::ByteArray(bytes) => json!(["bytearray", hex(bytes)]),
+        StackObject::List(items) => json!(["list", forms(&mut items.iter())]),
+        StackObject::Tuple(items) => json!(["tuple", forms(&mut items.iter())]),
+        StackObject::Set(items) => distinct("set", forms(&mut items.iter())),
+        StackObject::FrozenSet(items) => distinct("frozenset", forms(&mut items.iter())),
+        StackObject::Dict(entries) => {
+            let keys = forms(&mut entries.keys());
+            let values = forms(&mut entries.values());
+            dict(keys.into_iter().zip(values).collect())
+        }
+        _ => wildcard(),
+    };
+    ancestors.pop();
+    value
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// the set `kind` of `items`, which the simulation keeps by identity:
+/// Python keeps one of the items that compare equal.
+fn distinct(kind: &str, items: Vec<Value>) -> Value {
+    let mut groups: Vec<(String, Vec<Value>)> = Vec::new();
+    for item in items {
+        let Some(key) = python_key(&item) else {
+            return wildcard();
+        };
+        match groups.iter_mut().find(|(other, _)| *other == key) {
+            Some((_, group)) => group.push(item),
+            None => groups.push((key, vec![item])),
+        }
+    }
+    let items: Vec<Value> = groups
+        .into_iter()
+        .map(|(_, group)| same_or_wildcard(group))
+        .collect();
+    json!([kind, items])
+}
+
+/// a dict of `entries`, which the simulation keys by identity: Python keeps
+/// the first of the keys that compare equal and the value set last, in an
+/// order the simulation doesn't record.
+fn dict(entries: Vec<(Value, Value)>) -> Value {
+    let mut groups: HashMap<String, (Vec<Value>, Vec<Value>)> = HashMap::new();
+    for (key, value) in entries {
+        let Some(python) = python_key(&key) else {
+            return wildcard();
+        };
+        let (keys, values) = groups.entry(python).or_default();
+        keys.push(key);
+        values.push(value);
+    }
+    let entries: Vec<Value> = groups
+        .into_values()
+        .map(|(keys, values)| json!([same_or_wildcard(keys), same_or_wildcard(values)]))
+        .collect();
+    json!(["dict", entries])
+}
+
+fn same_or_wildcard(mut forms: Vec<Value>) -> Value {
+    if forms.windows(2).all(|pair| pair[0] == pair[1]) {
+        forms.swap_remove(0)
+    } else {
+        wildcard()
+    }
+}
+
+/// a string equal for exactly the hashable forms Python considers equal
+/// (`1`, `1.0`, and `True` are one key), or `None` when that depends on
+/// something the form leaves open: a wildcard, a NaN, or a cycle.
+fn python_key(form: &Value) -> Option<String> {
+    let [kind, rest @ ..] = form.as_array()?.as_slice() else {
+        return None;
+    };
+    Some(match (kind.as_str()?, rest) {
+        ("none", []) => "n".to_string(),
+        ("bool", [Value::Bool(value)]) => format!("#{}", u8::from(*value)),
+        ("int", [Value::String(value)]) => format!("#{value}"),
+        ("float", [Value::String(repr)]) => {
+            let value: f64 = repr.parse().ok()?;
+            if value.is_nan() {
+                return None;
+            }
+            if value.fract() == 0.0 && value.is_finite() {
+                // integral floats equal the int of the same value
+                format!("#{:.0}", value + 0.0)
+            } else {
+                format!("f{repr}")
+            }
+        }
+        ("str", [value]) => format!("s{value}"),
+        ("bytes", [Value::String(hex)]) => format!("b{hex}"),
+        ("tuple", [Value::Array(items)]) => {
+            let keys: Option<Vec<String>> = items.iter().map(python_key).collect();
+            format!("({})", keys?.join(","))
+        }
+        ("frozenset", [Value::Array(items)]) => {
+            let mut keys: Vec<String> = items.iter().map(python_key).collect::<Option<_>>()?;
+            keys.sort();
+            format!("{{{}}}", keys.join(","))
+        }
+        _ => return None,
+    })
+}
+
+/// whether the simulated form `simulated` describes the loaded form
+/// `loaded`, with `["?"]` on either side matching anything and sets and
+/// dicts in any order.
+pub fn forms_match(simulated: &Value, loaded: &Value) -> bool {
+    let (Some(simulated_parts), Some(loaded_parts)) = (simulated.as_array(), loaded.as_array())
+    else {
+        return simulated == loaded;
+    };
+    let is_wildcard = |parts: &[Value]| parts.first().and_then(Value::as_str) == Some(WILDCARD);
+    if is_wildcard(simulated_parts) || is_wildcard(loaded_parts) {
+        return true;
+    }
+    match (simulated_parts.as_slice(), loaded_parts.as_slice()) {
+        (
+            [Value::String(kind), Value::Array(items)],
+            [Value::String(loaded_kind), Value::Array(loaded_items)],
+        ) if kind == loaded_kind => match kind.as_str() {
+            "list" | "tuple" => {
+                items.len() == loaded_items.len()
+                    && items
+                        .iter()
+                        .zip(loaded_items)
+                        .all(|(item, loaded)| forms_match(item, loaded))
+            }
+            "set" | "frozenset" => match_unordered(items, loaded_items, forms_match),
+            "dict" => match_unordered(items, loaded_items, |entry, loaded| {
+                match (entry.as_array(), loaded.as_array()) {
+                    (Some(entry), Some(loaded)) if entry.len() == 2 && loaded.len() == 2 => {
+                        forms_match(&entry[0], &loaded[0]) && forms_match(&entry[1], &loaded[1])
+                    }
+                    _ => false,
+                }
+            }),
+            _ => simulated == loaded,
+        },
+        _ => simulated == loaded,
+    }
+}
+
+/// whether `simulated` and `loaded` pair up one to one under `matches`, by
+/// bipartite matching since wildcards can match several items.
+fn match_unordered(
+    simulated: &[Value],
+    loaded: &[Value],
+    matches: impl Fn(&Value, &Value) -> bool,
+) -> bool {
+    if simulated.len() != loaded.len() {
+        return false;
+    }
+    let fits: Vec<Vec<usize>> = simulated
+        .iter()
+        .map(|item| {
+            (0..loaded.len())
+                .filter(|&index| matches(item, &loaded[index]))
+                .collect()
+        })
+        .collect();
+    let mut owners = vec![None; loaded.len()];
+    (0..simulated.len())
+        .all(|item| augment(item, &fits, &mut owners, &mut vec![false; loaded.len()]))
+}
+
+/// find `item` a partner, moving earlier items to other partners if needed.
+fn augment(
+    item: usize,
+    fits: &[Vec<usize>],
+    owners: &mut [Option<usize>],
+    seen: &mut [bool],
+) -> bool {
+    for &partner in &fits[item] {
+        if std::mem::replace(&mut seen[partner], true) {
+            continue;
+        }
+        let free = match owners[partner] {
+            Some(owner) => augment(owner, fits, owners, seen),
+            None => true,
+        };
+        if free {
+            owners[partner] = Some(item);
+            return true;
+        }
+    }
+    false
+}
+
+/// one `python3` process running [`LOADER_SOURCE`].
+#[derive(Debug)]
+pub struct PythonWorker {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl PythonWorker {
+    /// start a worker with the interpreter `python`.
+    pub fn spawn(python: &OsStr) -> io::Result<Self> {
+        let mut child = Command::new(python)
+            .arg("-c")
+            .arg(LOADER_SOURCE)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+        Ok(Self {
+            child,
+            stdin,
+            stdout,
+        })
+    }
+
+    /// load `pickle` and return the canonical form of the object, or the
+    /// error `pickle.loads` raised.
+    ///
+    /// fails if the worker can't be talked to, after which it is unusable.
+    pub fn load(&mut self, pickle: &[u8]) -> io::Result<std::result::Result<Value, String>> {
+        self.stdin.write_all(&(pickle.len() as u64).to_le_bytes())?;
+        self.stdin.write_all(pickle)?;
+        self.stdin.flush()?;
+
+        let mut line = String::new();
+        if self.stdout.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "python worker exited",
+            ));
+        }
+        let mut reply: Value = serde_json::from_str(&line)?;
+        match (reply["root"].take(), reply["error"].take()) {
+            (Value::Null, Value::String(error)) => Ok(Err(error)),
+            (root @ Value::Array(_), _) => Ok(Ok(root)),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unexpected reply from python worker: {line}"),
+            )),
+        }
+    }
+}
+
+impl Drop for PythonWorker {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// [`PythonWorker`]s shared between threads, started on first use and kept
+/// for the next pickle.
+#[derive(Debug)]
+pub struct PythonPool {
+    python: OsString,
+    idle: Mutex<Vec<PythonWorker>>,
+}
+
+impl PythonPool {
+    /// a pool of workers running the interpreter `python`, e.g. `python3`.
+    pub fn new(python: impl Into<OsString>) -> Self {
+        Self {
+            python: python.into(),
+            idle: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// load `pickle` in an idle worker, or a new one if all are busy.
+    pub fn load(&self, pickle: &[u8]) -> io::Result<std::result::Result<Value, String>> {
+        let idle = self.idle.lock().unwrap_or_else(|e| e.into_inner()).pop();
+        let mut worker = match idle {
+            Some(worker) => worker,
+            None => PythonWorker::spawn(&self.python)?,
+        };
+        // a worker that fails is dropped rather than handed out again
+        let loaded = worker.load(pickle)?;
+        self.idle
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(worker);
+        Ok(loaded)
+    }
+}
+
+/// load `pickle`, which `generator` just generated, in `pool` and check that
+/// the result matches the generator's simulated root object.
+///
+/// fails if the pickle doesn't load, or with both canonical forms if they
+/// differ.
+pub fn check_loads(generator: &Generator, pickle: &[u8], pool: &PythonPool) -> Result<()> {
+    let root = generator
+        .simulated_root()
+        .ok_or_else(|| eyre!("the simulation didn't end with one root object"))?;
+    let simulated = canonical_form(root);
+    let loaded = pool
+        .load(pickle)
+        .map_err(|e| eyre!("python worker: {e}"))?
+        .map_err(|e| eyre!("pickle.loads failed: {e}"))?;
+    if !forms_match(&simulated, &loaded) {
+        bail!("simulated root {simulated} but pickle.loads built {loaded}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Version;
+
+    fn object(value: StackObject) -> StackObjectRef {
+        StackObjectRef::new(value)
+    }
+
+    // StackObjectRef hashes by identity, so its interior mutability is fine
+    #[allow(clippy::mutable_key_type)]
+    #[test]
+    fn canonical_forms_merge_keys_python_considers_equal() {
+        // 1, 1.0, and True are one key; the dict keeps the first key and the
+        // last value, but the simulation doesn't know which that is
+        let mut dict = HashMap::new();
+        dict.insert(object(StackObject::Int(1)), object(StackObject::None));
+        dict.insert(object(StackObject::Float(1.0)), object(StackObject::None));
+        dict.insert(
+            object(StackObject::String("a".into())),
+            object(StackObject::None),
+        );
+        let form = canonical_form(&object(StackObject::Dict(dict)));
+        let entries = form[1].as_array().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert!(entries.contains(&json!([["?"], ["none"]])));
+
+        let set = [StackObject::Bool(true), StackObject::Int(1)]
+            .map(object)
+            .into_iter()
+            .collect();
+        let form = canonical_form(&object(StackObject::Set(set)));
+        assert_eq!(form, json!(["set", [["?"]]]));
+
+        let nan_key = [StackObject::Float(f64::NAN)]
+            .map(object)
+            .into_iter()
+            .collect();
+        assert_eq!(
+            canonical_form(&object(StackObject::FrozenSet(nan_key))),
+            wildcard()
+        );
+    }
+
+    #[test]
+    fn canonical_forms_spell_values_like_python() {
+        let list = object(StackObject::List(Default::default()));
+        if let StackObject::List(items) = &mut *list.borrow_mut() {
+            items.push(object(StackObject::BigInt(vec![0, 0, 0, 0, 0, 0, 0, 0, 1])));
+            items.push(object(StackObject::Float(1e-5)));
+            items.push(object(StackObject::Bytes(b"\x00a".to_vec())));
+            items.push(object(StackObject::Any));
+            items.push(list.clone());
+        }
+        assert_eq!(
+            canonical_form(&list),
+            json!([
+                "list",
+                [
+                    ["int", "18446744073709551616"],
+                    ["float", "1e-05"],
+                    ["bytes", "0061"],
+                    ["?"],
+                    ["cycle"]
+                ]
+            ])
+        );
+        // break the cycle so the list is freed
+        if let StackObject::List(items) = &mut *list.borrow_mut() {
+            items.clear();
+        };
+    }
+
+    #[test]
+    fn forms_match_up_to_wildcards_and_order() {
+        let simulated = json!(["set", [["?"], ["int", "1"]]]);
+        assert!(forms_match(
+            &simulated,
+            &json!(["set", [["int", "1"], ["str", "a"]]])
+        ));
+        assert!(!forms_match(
+            &simulated,
+            &json!(["set", [["int", "2"], ["str", "a"]]])
+        ));
+        assert!(!forms_match(&simulated, &json!(["set", [["int", "1"]]])));
+
+        let simulated = json!(["dict", [[["str", "a"], ["?"]], [["?"], ["none"]]]]);
+        let loaded = json!([
+            "dict",
+            [[["int", "2"], ["none"]], [["str", "a"], ["int", "1"]]]
+        ]);
+        assert!(forms_match(&simulated, &loaded));
+        assert!(!forms_match(
+            &json!(["tuple", [["none"]]]),
+            &json!(["list", [["none"]]])
+        ));
+    }
+
+    #[test]
+    fn simulated_roots_match_pickle_loads() {
+        let pool = PythonPool::new("python3");
+        if pool.load(b"N.").is_err() {
+            eprintln!("skipping: python3 is not available");
+            return;
+        }
+        for version in Version::all() {
+            for seed in 0..16 {
+                let mut generator = Generator::new(version)
+                    .with_seed(seed)
+                    .with_loadable(true)
+                    .with_interesting_patterns(true)
+                    .with_integer_boundaries(true);
+                let pickle = generator.generate().unwrap();
+                check_loads(&generator, &pickle, &pool)
+                    .unwrap_or_else(|e| panic!("{version:?} seed {seed}: {e}"));
+            }
+        }
+
+        let generator = Generator::new(Version::V2);
+        let error = check_loads(&generator, b"N.", &pool).unwrap_err();
+        assert!(error.to_string().contains("one root object"), "{error}");
+    }
+}
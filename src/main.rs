@@ -14,180 +14,1835 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use clap::ValueEnum;
-use color_eyre::{eyre::bail, Result};
-use pickle_fuzzer::{Cli, Generator, Version};
+use color_eyre::{
+    eyre::{bail, eyre, WrapErr},
+    Result,
+};
+use pickle_fuzzer::loadcheck::{self, PythonPool};
+use pickle_fuzzer::metrics::Metrics;
+use pickle_fuzzer::monitor::{Alert, Bounds, Monitor, SampleStats};
+use pickle_fuzzer::output::Archive;
+use pickle_fuzzer::risk::Risk;
+use pickle_fuzzer::{
+    disasm, output, risk, AnalyzeArgs, Annotation, Cli, Command, DatasetArgs, DisArgs, DistillArgs,
+    EntropyTrace, ExhaustionPolicy, GenerateArgs, Generator, GeneratorOptions, GeneratorPool,
+    GrepArgs, LogFormat, MinimizeArgs, MutateArgs, NameTemplate, OpcodeKind, PooledGenerator,
+    ProtocolMix, TimeBudgetExceeded, ValidateArgs, Version, GENERATOR_FORMAT_VERSION, OPCODE_TABLE,
+};
 use rand::Rng;
 use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::OsString;
+use std::fs::File;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use indicatif::{ProgressBar, ProgressStyle};
+use tracing::{debug, error, info, info_span, warn};
+use tracing_subscriber::filter::{EnvFilter, LevelFilter};
+
+/// The progress bar batch mode is drawing, which log lines are printed above.
+static PROGRESS: Mutex<Option<ProgressBar>> = Mutex::new(None);
+
+/// Writes log lines to stderr, above the progress bar if one is drawn.
+struct LogWriter;
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let progress = PROGRESS.lock().unwrap_or_else(|e| e.into_inner());
+        match progress.as_ref() {
+            Some(bar) => bar.suspend(|| std::io::stderr().write(buf)),
+            None => std::io::stderr().write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        std::io::stderr().flush()
+    }
+}
+
+/// Log to stderr in `format`, keeping the events `RUST_LOG` selects (info
+/// and up when it is unset).
+fn init_logging(format: LogFormat) {
+    let filter = EnvFilter::builder()
+        .with_default_directive(LevelFilter::INFO.into())
+        .from_env_lossy();
+    let logs = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(|| LogWriter);
+    match format {
+        LogFormat::Text => logs
+            .without_time()
+            .with_target(false)
+            .with_ansi(std::io::IsTerminal::is_terminal(&std::io::stderr()))
+            .init(),
+        LogFormat::Json => logs.json().with_current_span(false).init(),
+    }
+}
+
+/// Number of samples generated per parallel chunk in batch mode.
+const BATCH_CHUNK_SIZE: usize = 1024;
+
+/// Number of per-sample errors printed before further ones are only counted.
+const MAX_REPORTED_ERRORS: usize = 10;
+
+/// Number of times a batch sample that hits `--sample-timeout` is regenerated
+/// with fresh seeds before it counts as an error.
+const MAX_SAMPLE_TIMEOUTS: usize = 3;
 
 fn batch_sample_seed(seed: u64, idx: usize) -> u64 {
     seed.wrapping_add(idx as u64)
 }
 
-fn main() -> Result<()> {
-    color_eyre::install()?;
+/// Number of times `--dedupe` regenerates a sample that repeats an earlier
+/// pickle before it skips the sample.
+const MAX_DEDUPE_RETRIES: usize = 8;
+
+/// The seed of `--dedupe`'s `retry`th regeneration of a sample seeded `seed`.
+///
+/// Derived rather than random so a seeded, deduplicated run stays reproducible.
+fn dedupe_retry_seed(seed: u64, retry: usize) -> u64 {
+    seed ^ (retry as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+}
+
+/// Pick the protocol version for one sample.
+///
+/// An explicit `--protocol` wins, then `--protocol-mix`, then the uniform default.
+/// When a seed is available the choice is derived from it so seeded runs stay
+/// reproducible without having to seed `rand::rng`.
+fn select_version(
+    protocol: Option<Version>,
+    mix: Option<&ProtocolMix>,
+    seed: Option<u64>,
+) -> Version {
+    if let Some(protocol) = protocol {
+        return protocol;
+    }
 
-    let args = Cli::parse_args();
+    match (mix, seed) {
+        (Some(mix), Some(seed)) => mix.select_for_seed(seed),
+        (Some(mix), None) => mix.select(rand::rng().random_range(0..mix.total_weight())),
+        (None, Some(seed)) => Version::select_for_seed(seed),
+        (None, None) => Version::select(rand::rng().random()),
+    }
+}
 
-    if !args.unsafe_mutations {
-        if let Some(kind) = args
-            .mutators
+/// One line of the batch manifest.
+#[derive(Serialize)]
+struct ManifestEntry {
+    index: usize,
+    file: String,
+    protocol: u8,
+    seed: Option<u64>,
+    /// seed of the leaf values under `--value-seed`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value_seed: Option<u64>,
+    size: usize,
+    peak_stack_depth: usize,
+    format_version: u32,
+    /// sibling number under `--variants`, whose siblings share `seed`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    variant: Option<usize>,
+    /// tries `--sample-timeout` cut short before this one, whose seeds replaced
+    /// the derived ones
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timeouts: Option<usize>,
+    /// regenerations `--dedupe` needed before this one, whose seeds replaced
+    /// the derived ones
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duplicates: Option<usize>,
+}
+
+/// One line of `pickle-fuzzer dataset`'s labels.
+#[derive(Serialize)]
+struct DatasetLabel {
+    /// path below the corpus directory
+    file: String,
+    size: usize,
+    /// the protocol the pickle declares or needs, if it disassembles
+    protocol: Option<u8>,
+    valid: bool,
+    /// why `validate` rejected it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    /// `risk::classify` label
+    risk: String,
+    /// `module.name` globals imported, in order
+    globals: Vec<String>,
+    /// opcode-sequence fingerprint, shared by pickles that differ only in
+    /// their literal values
+    #[serde(skip_serializing_if = "Option::is_none")]
+    structure: Option<String>,
+}
+
+/// Content hashes of the pickles `--dedupe` has kept, and what it did about
+/// repeats.
+#[derive(Default)]
+struct Dedupe {
+    /// compare opcode sequences instead of bytes (`--dedupe-structural`)
+    structural: bool,
+    seen: HashSet<u64>,
+    /// samples whose first try repeated an earlier pickle
+    collisions: usize,
+    /// colliding samples a retry made unique
+    regenerated: usize,
+    /// colliding samples that were never unique and weren't written
+    skipped: usize,
+}
+
+impl Dedupe {
+    /// Whether every pickle of a sample is new, remembering them if so.
+    fn admit(&mut self, samples: &[BatchSample]) -> bool {
+        let hashes: Vec<u64> = samples
             .iter()
-            .find(|kind| kind.requires_unsafe_mutations())
-        {
-            let name = kind
-                .to_possible_value()
-                .map(|value| value.get_name().to_string())
-                .unwrap_or_else(|| "unknown".to_string());
-            bail!("--mutators {name} requires --unsafe-mutations");
+            .map(|sample| {
+                let pickle = sample.pickle.as_deref().unwrap_or_default();
+                let fingerprint = self
+                    .structural
+                    .then(|| disasm::structural_fingerprint(pickle).ok())
+                    .flatten();
+                // a pickle a mutation broke has no opcode sequence to compare
+                fingerprint.unwrap_or_else(|| {
+                    let mut hasher = DefaultHasher::new();
+                    pickle.hash(&mut hasher);
+                    hasher.finish()
+                })
+            })
+            .collect();
+        let unique = hashes
+            .iter()
+            .enumerate()
+            .all(|(i, hash)| !self.seen.contains(hash) && !hashes[..i].contains(hash));
+        if unique {
+            self.seen.extend(hashes);
         }
+        unique
     }
 
-    // Expand "all" meta-option and create mutators
-    let mutator_kinds: Vec<pickle_fuzzer::MutatorKind> =
-        if args.mutators.contains(&pickle_fuzzer::MutatorKind::All) {
-            // if "all" is specified, use all mutators allowed by the current safety mode
-            pickle_fuzzer::MutatorKind::all_mutators(args.unsafe_mutations)
+    fn print(&self, samples: usize) {
+        println!(
+            "Dedupe: {} of {} samples ({:.2}%) repeated an earlier {}; {} regenerated, {} skipped",
+            self.collisions,
+            samples,
+            100.0 * self.collisions as f64 / samples.max(1) as f64,
+            if self.structural {
+                "opcode sequence"
+            } else {
+                "pickle"
+            },
+            self.regenerated,
+            self.skipped
+        );
+    }
+}
+
+/// Why one try at a batch sample failed.
+enum SampleError {
+    /// `--sample-timeout` cut generation short
+    TimedOut,
+    Failed(String),
+}
+
+impl SampleError {
+    fn generation(error: color_eyre::Report) -> Self {
+        if error.downcast_ref::<TimeBudgetExceeded>().is_some() {
+            Self::TimedOut
         } else {
-            // otherwise use the specified mutators
-            args.mutators.clone()
-        };
+            Self::Failed(format!("generation error: {}", error))
+        }
+    }
+}
+
+impl From<String> for SampleError {
+    fn from(error: String) -> Self {
+        Self::Failed(error)
+    }
+}
 
-    let mutators: Vec<Box<dyn pickle_fuzzer::Mutator>> = mutator_kinds
+/// One generated batch pickle, as the batch loop hands it to the main thread.
+struct BatchSample {
+    entry: ManifestEntry,
+    mutated_emissions: usize,
+    /// opcode names of the pickle under `--dry-run`, `None` if it doesn't
+    /// disassemble
+    opcodes: Option<Vec<&'static str>>,
+    /// the pickle under `--dedupe`, which writes it once it is known to be new,
+    /// and `--archive`, which appends it to the archive in index order
+    pickle: Option<Vec<u8>>,
+    /// what the sample adds to the `--min-validity` and related checks, and
+    /// to `--metrics-file`
+    stats: Option<SampleStats>,
+    /// how often each mutator fired, under `--metrics-file`
+    mutators: Vec<(String, usize)>,
+}
+
+/// The opcode names of `pickle`, or `None` if a mutation broke its encoding.
+fn opcode_names(pickle: &[u8]) -> Option<Vec<&'static str>> {
+    let instructions = disasm::disassemble(pickle).ok()?;
+    Some(instructions.iter().map(|op| op.name).collect())
+}
+
+/// The protocol a pickle needs: its PROTO argument, or the newest protocol
+/// among its opcodes if that is higher, as `pickletools` works it out.
+fn pickle_protocol(instructions: &[disasm::Instruction]) -> u8 {
+    instructions
         .iter()
-        .map(|kind| kind.create(args.unsafe_mutations))
-        .collect();
+        .map(|instruction| match (instruction.name, &instruction.arg) {
+            ("PROTO", disasm::Argument::Int(version)) => u8::try_from(*version).unwrap_or(u8::MAX),
+            _ => OpcodeKind::from_u8(instruction.code).map_or(0, |kind| kind.info().proto.as_u8()),
+        })
+        .max()
+        .unwrap_or(0)
+}
 
-    if let Some(file) = args.file {
-        // single file mode - generate one pickle
-        let version = if let Some(protocol) = args.protocol {
-            // if --protocol was used, prefer that
-            Version::try_from(protocol).unwrap_or(Version::V3)
-        } else if let Some(seed) = args.seed {
-            // otoh, if --seed need to use that to deterministically select protocol version
-            // so we don't need to dork with seeding the rand::rng
-            Version::try_from((seed % 6) as usize).unwrap_or(Version::V3)
+/// The `module.name` globals a pickle imports, in order.
+///
+/// STACK_GLOBAL's operands are the strings pushed right before it, memo stores
+/// aside; a STACK_GLOBAL whose strings were built some other way, like fetched
+/// from the memo, counts as `<computed>`.
+fn globals_used(instructions: &[disasm::Instruction]) -> Vec<String> {
+    let mut globals = Vec::new();
+    let mut strings: Vec<&str> = Vec::new();
+    for instruction in instructions {
+        match (instruction.name, &instruction.arg) {
+            ("GLOBAL" | "INST", disasm::Argument::Str(text)) => {
+                globals.push(text.replacen(' ', ".", 1));
+                strings.clear();
+            }
+            ("STACK_GLOBAL", _) => {
+                globals.push(match strings[..] {
+                    [.., module, name] => format!("{module}.{name}"),
+                    _ => "<computed>".into(),
+                });
+                strings.clear();
+            }
+            ("PUT" | "BINPUT" | "LONG_BINPUT" | "MEMOIZE", _) => {}
+            (_, disasm::Argument::Str(text)) => strings.push(text),
+            _ => strings.clear(),
+        }
+    }
+    globals
+}
+
+/// Aggregate statistics over a set of pickles: what `--dry-run` prints instead
+/// of writing samples, and what `analyze` prints about a corpus.
+#[derive(Default)]
+struct CorpusReport {
+    pickles: usize,
+    total_bytes: usize,
+    min_size: usize,
+    max_size: usize,
+    protocols: BTreeMap<u8, usize>,
+    opcodes: BTreeMap<&'static str, usize>,
+    /// pickles the opcode histogram leaves out because they don't disassemble
+    undecodable: usize,
+    mutated_emissions: usize,
+    /// imports by `module.name`, only filled in by `analyze`
+    globals: BTreeMap<String, usize>,
+    /// pickles by `risk::classify` label, only filled in by `analyze`
+    risks: BTreeMap<Risk, usize>,
+}
+
+impl CorpusReport {
+    fn record(
+        &mut self,
+        protocol: u8,
+        size: usize,
+        mutated_emissions: usize,
+        opcodes: Option<&[&'static str]>,
+    ) {
+        self.min_size = if self.pickles == 0 {
+            size
         } else {
-            Version::try_from(rand::rng().random_range(0..=5)).unwrap_or(Version::V3)
+            self.min_size.min(size)
         };
+        self.max_size = self.max_size.max(size);
+        self.pickles += 1;
+        self.total_bytes += size;
+        *self.protocols.entry(protocol).or_default() += 1;
+        self.mutated_emissions += mutated_emissions;
+        match opcodes {
+            Some(opcodes) => {
+                for name in opcodes {
+                    *self.opcodes.entry(name).or_default() += 1;
+                }
+            }
+            None => self.undecodable += 1,
+        }
+    }
 
-        let mut generator =
-            Generator::new(version).with_opcode_range(args.min_opcodes, args.max_opcodes);
+    fn print_dry_run(&self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64().max(f64::EPSILON);
+
+        println!("Dry run: generated {} pickles, wrote nothing", self.pickles);
+        self.print_sizes_and_protocols();
+        println!(
+            "Mutated emissions: {} ({:.2} per pickle)",
+            self.mutated_emissions,
+            self.mutated_emissions as f64 / self.pickles.max(1) as f64
+        );
+        println!(
+            "Throughput: {:.1} pickles/s, {:.2} MB/s over {:.2}s",
+            self.pickles as f64 / seconds,
+            self.total_bytes as f64 / 1e6 / seconds,
+            elapsed.as_secs_f64()
+        );
+        self.print_opcodes();
+    }
+
+    fn print_sizes_and_protocols(&self) {
+        println!(
+            "Sizes: min {} B, mean {:.1} B, max {} B, total {} B",
+            self.min_size,
+            self.total_bytes as f64 / self.pickles.max(1) as f64,
+            self.max_size,
+            self.total_bytes
+        );
+        let protocols: Vec<String> = self
+            .protocols
+            .iter()
+            .map(|(protocol, &count)| {
+                format!("{protocol}: {count} ({:.1}%)", percent(count, self.pickles))
+            })
+            .collect();
+        println!("Protocols: {}", protocols.join(", "));
+    }
 
-        if let Some(seed) = args.seed {
-            generator = generator.with_seed(seed);
+    fn print_opcodes(&self) {
+        let total_opcodes = self.opcodes.values().sum();
+        print!("Opcodes: {total_opcodes}");
+        if self.undecodable > 0 {
+            print!(" ({} pickles don't disassemble)", self.undecodable);
         }
+        println!();
+        print_histogram(
+            self.opcodes.iter().map(|(name, &count)| (*name, count)),
+            total_opcodes,
+            usize::MAX,
+        );
+    }
 
-        if !mutators.is_empty() {
-            generator = generator
-                .with_mutators(mutators)
-                .with_mutation_rate(args.mutation_rate)
-                .with_unsafe_mutations(args.unsafe_mutations);
+    fn print_risks(&self) {
+        let risks: Vec<String> = Risk::all()
+            .iter()
+            .map(|risk| {
+                let count = self.risks.get(risk).copied().unwrap_or(0);
+                format!("{risk}: {count} ({:.1}%)", percent(count, self.pickles))
+            })
+            .collect();
+        println!("Risks: {}", risks.join(", "));
+    }
+
+    /// print the globals, only the `top` most common unless it is 0.
+    fn print_globals(&self, top: usize) {
+        let total_globals = self.globals.values().sum();
+        println!(
+            "Globals: {total_globals} imports of {} distinct globals",
+            self.globals.len()
+        );
+        let limit = if top == 0 { usize::MAX } else { top };
+        print_histogram(
+            self.globals
+                .iter()
+                .map(|(name, &count)| (name.as_str(), count)),
+            total_globals,
+            limit,
+        );
+        if self.globals.len() > limit {
+            println!("  ... {} more", self.globals.len() - limit);
         }
+    }
+}
 
-        // apply EXT and buffer opcode flags
-        generator = generator
-            .with_ext_opcodes(args.allow_ext)
-            .with_buffer_opcodes(args.allow_buffer)
-            .with_persistent_id_opcodes(args.allow_persistent_ids);
-
-        let bytecode = generator.generate()?;
-        std::fs::write(&file, &bytecode)?;
-        println!("Generated {} bytes to {:?}", bytecode.len(), file);
-    } else if let Some(dir) = args.dir {
-        if !dir.exists() {
-            std::fs::create_dir(&dir)?;
-        }
-
-        // Collect errors from parallel generation
-        let seed = args.seed;
-        let protocol = args.protocol;
-        let min_opcodes = args.min_opcodes;
-        let max_opcodes = args.max_opcodes;
-        let mutation_rate = args.mutation_rate;
-        let unsafe_mutations = args.unsafe_mutations;
-        let allow_ext_opcodes = args.allow_ext;
-        let allow_buffer_opcodes = args.allow_buffer;
-        let allow_persistent_id_opcodes = args.allow_persistent_ids;
-        let mutator_kinds_for_batch = mutator_kinds.clone();
-
-        let errors: Vec<_> = (0..args.samples)
-            .into_par_iter()
-            .filter_map(|idx| {
-                let sample_seed = seed.map(|seed| batch_sample_seed(seed, idx));
-                let version = if let Some(proto) = protocol {
-                    // same version selection logic as what's used above
-                    Version::try_from(proto).unwrap_or(Version::V3)
-                } else if let Some(sample_seed) = sample_seed {
-                    Version::try_from((sample_seed % 6) as usize).unwrap_or(Version::V3)
-                } else {
-                    Version::try_from(rand::rng().random_range(0..=5)).unwrap_or(Version::V3)
-                };
+fn percent(count: usize, total: usize) -> f64 {
+    100.0 * count as f64 / total.max(1) as f64
+}
+
+/// Print the `limit` most frequent `(name, count)` pairs, as shares of `total`.
+fn print_histogram<'a>(counts: impl Iterator<Item = (&'a str, usize)>, total: usize, limit: usize) {
+    let mut histogram: Vec<_> = counts.collect();
+    histogram.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    histogram.truncate(limit);
+    let width = histogram
+        .iter()
+        .map(|(name, _)| name.chars().count())
+        .fold(16, usize::max);
+    for (name, count) in histogram {
+        println!(
+            "  {name:<width$} {count:>10} ({:.1}%)",
+            percent(count, total)
+        );
+    }
+}
+
+/// creates a mutator with the configured settings, giving the dictionary
+/// mutator the user-supplied tokens.
+fn create_mutator(
+    choice: &pickle_fuzzer::MutatorChoice,
+    unsafe_mutations: bool,
+    dictionary: &[Vec<u8>],
+    config: &pickle_fuzzer::MutatorConfig,
+) -> Box<dyn pickle_fuzzer::Mutator> {
+    match choice {
+        pickle_fuzzer::MutatorChoice::Builtin(pickle_fuzzer::MutatorKind::Dictionary) => Box::new(
+            pickle_fuzzer::mutators::DictionaryMutator::with_tokens(dictionary.to_vec()),
+        ),
+        _ => choice.create_with(unsafe_mutations, config),
+    }
+}
 
-                let mut generator =
-                    Generator::new(version).with_opcode_range(min_opcodes, max_opcodes);
+/// What a run's options ask for that is checked or read from files up front:
+/// the configured mutators (dictionary tokens included) and mutation scope,
+/// and the global allowlist.
+#[derive(Clone)]
+struct GeneratorSetup {
+    /// one configured mutator per choice, copied into every generator
+    mutators: Arc<[Box<dyn pickle_fuzzer::Mutator>]>,
+    choices: Vec<pickle_fuzzer::MutatorChoice>,
+    dictionary: Vec<Vec<u8>>,
+    config: pickle_fuzzer::MutatorConfig,
+    scope: pickle_fuzzer::MutationScope,
+    global_allowlist: Option<Vec<(String, String)>>,
+}
 
-                if let Some(sample_seed) = sample_seed {
-                    generator = generator.with_seed(sample_seed);
+impl GeneratorSetup {
+    fn new(options: &GeneratorOptions) -> Result<Self> {
+        if !options.unsafe_mutations {
+            if let Some(choice) = options
+                .mutators
+                .iter()
+                .find(|choice| choice.requires_unsafe_mutations())
+            {
+                bail!("--mutators {choice} requires --unsafe-mutations");
+            }
+        }
+
+        // Expand "all" meta-option (builtin and registered mutators allowed by the
+        // current safety mode)
+        let choices =
+            pickle_fuzzer::MutatorChoice::expand(&options.mutators, options.unsafe_mutations);
+
+        let dictionary = match &options.dictionary {
+            Some(path) => {
+                let dictionary_mutator =
+                    pickle_fuzzer::MutatorChoice::Builtin(pickle_fuzzer::MutatorKind::Dictionary);
+                if !choices.contains(&dictionary_mutator) {
+                    bail!("--dictionary requires --mutators dictionary (or all)");
                 }
+                let text = std::fs::read_to_string(path)
+                    .map_err(|e| eyre!("failed to read {path:?}: {e}"))?;
+                pickle_fuzzer::mutators::DictionaryMutator::parse_afl_dictionary(&text)?
+            }
+            None => Vec::new(),
+        };
 
-                // Create mutators for this thread
-                if !mutator_kinds_for_batch.is_empty() {
-                    let thread_mutators: Vec<Box<dyn pickle_fuzzer::Mutator>> =
-                        mutator_kinds_for_batch
-                            .iter()
-                            .map(|kind| kind.create(unsafe_mutations))
-                            .collect();
-                    generator = generator
-                        .with_mutators(thread_mutators)
-                        .with_mutation_rate(mutation_rate)
-                        .with_unsafe_mutations(unsafe_mutations);
+        let scope = if options.mutation_scope.is_empty() {
+            pickle_fuzzer::MutationScope::ALL
+        } else {
+            pickle_fuzzer::MutationScope::only(&options.mutation_scope)
+        };
+
+        let global_allowlist = match &options.global_allowlist {
+            Some(path) => {
+                let text = std::fs::read_to_string(path)
+                    .map_err(|e| eyre!("failed to read {path:?}: {e}"))?;
+                let globals = Generator::parse_global_allowlist(&text)
+                    .wrap_err_with(|| format!("invalid global allowlist {path:?}"))?;
+                if globals.is_empty() {
+                    bail!("global allowlist {path:?} lists no globals");
                 }
+                Some(globals)
+            }
+            None => None,
+        };
+
+        let selected = |kind| choices.contains(&pickle_fuzzer::MutatorChoice::Builtin(kind));
+        let mut config = pickle_fuzzer::MutatorConfig::default();
+        if options.stringlen_max_extend.is_some() || options.stringlen_no_empty {
+            if !selected(pickle_fuzzer::MutatorKind::Stringlen) {
+                bail!("--stringlen-max-extend and --stringlen-no-empty require --mutators stringlen (or all)");
+            }
+            let mut stringlen = config
+                .stringlen
+                .with_allow_empty(!options.stringlen_no_empty);
+            if let Some(max_extend) = options.stringlen_max_extend {
+                stringlen = stringlen.with_max_extend(max_extend);
+            }
+            config = config.with_stringlen(stringlen);
+        }
+        if let Some(max_index) = options.memoindex_max {
+            if !selected(pickle_fuzzer::MutatorKind::Memoindex) {
+                bail!("--memoindex-max requires --mutators memoindex (or all)");
+            }
+            config = config.with_memoindex(config.memoindex.with_max_index(max_index));
+        }
+        if let Some(charset) = options.character_set {
+            if !selected(pickle_fuzzer::MutatorKind::Character) {
+                bail!("--character-set requires --mutators character (or all)");
+            }
+            config = config.with_character(config.character.with_charset(charset));
+        }
+        if let Some(max_len) = options.lengthboundary_max {
+            if !selected(pickle_fuzzer::MutatorKind::Lengthboundary) {
+                bail!("--lengthboundary-max requires --mutators lengthboundary (or all)");
+            }
+            config = config.with_lengthboundary(config.lengthboundary.with_max_len(max_len));
+        }
+
+        let mutators = choices
+            .iter()
+            .map(|choice| create_mutator(choice, options.unsafe_mutations, &dictionary, &config))
+            .collect();
+
+        Ok(Self {
+            mutators,
+            choices,
+            dictionary,
+            config,
+            scope,
+            global_allowlist,
+        })
+    }
+
+    /// Copies of the configured mutators for one generator; a mutator that
+    /// can't be copied is built again from its choice.
+    fn mutators(&self, unsafe_mutations: bool) -> Vec<Box<dyn pickle_fuzzer::Mutator>> {
+        self.mutators
+            .iter()
+            .zip(&self.choices)
+            .map(|(mutator, choice)| {
+                mutator.boxed_clone().unwrap_or_else(|| {
+                    create_mutator(choice, unsafe_mutations, &self.dictionary, &self.config)
+                })
+            })
+            .collect()
+    }
+}
+
+/// Build an unseeded generator for `version` with every option applied and
+/// copies of the configured mutators.
+fn configured_generator(
+    version: Version,
+    options: &GeneratorOptions,
+    setup: &GeneratorSetup,
+) -> Generator {
+    let mut generator =
+        Generator::new(version).with_opcode_range(options.min_opcodes, options.max_opcodes);
+
+    if !setup.mutators.is_empty() {
+        generator = generator
+            .with_mutators(setup.mutators(options.unsafe_mutations))
+            .with_mutation_rate(options.mutation_rate)
+            .with_mutation_policy(options.mutation_policy)
+            .with_mutation_scope(setup.scope)
+            .with_unsafe_mutations(options.unsafe_mutations);
+    }
+
+    // apply EXT and buffer opcode flags
+    generator = generator
+        .with_ext_opcodes(options.allow_ext)
+        .with_buffer_opcodes(options.allow_buffer)
+        .with_persistent_id_opcodes(options.allow_persistent_ids)
+        .with_cleanup_policy(options.cleanup_policy)
+        .with_proto_header(options.proto_header)
+        .with_integer_boundaries(options.integer_boundaries)
+        .with_interesting_patterns(options.interesting_patterns)
+        .with_indirect_stack_globals(options.indirect_stack_globals)
+        .with_canonical(options.canonical)
+        .with_diverse_encodings(options.diverse_encodings)
+        .with_oversized_batches(options.oversized_batches)
+        .with_torch_tensors(options.torch_tensors)
+        .with_sklearn_estimators(options.sklearn_estimators)
+        .with_legacy_instances(options.legacy_instances)
+        .with_forbid_reduce(options.forbid_reduce)
+        .with_loadable(options.loadable)
+        .with_signatures(!options.ignore_signatures)
+        .with_unsafe_marks(options.unsafe_marks)
+        .with_unsafe_frames(options.unsafe_frames)
+        .with_persistent_id_payloads(options.persistent_id_payloads)
+        .with_unsafe_persistent_ids(options.unsafe_persistent_ids);
+    if let Some(globals) = &setup.global_allowlist {
+        generator = generator.with_global_allowlist(globals.iter().cloned());
+    }
+    if let Some(depth) = options.max_stack_depth {
+        generator = generator.with_max_stack_depth(depth);
+    }
+    if let Some(sizes) = options.container_sizes {
+        generator = generator.with_container_sizes(sizes);
+    }
+    if let Some(spec) = &options.ndarrays {
+        generator = generator.with_ndarrays(spec.clone());
+    }
+    if let Some(intensity) = options.mark_stress {
+        generator = generator.with_mark_stress(intensity);
+    }
+    if let Some(timeout) = options.sample_timeout {
+        generator = generator.with_time_budget(Duration::from_millis(timeout));
+    }
+    generator
+}
+
+/// The generator of a single-pickle run: the protocol the options pick, seeded
+/// with their seeds.
+fn single_generator(options: &GeneratorOptions, setup: &GeneratorSetup) -> Generator {
+    let version = select_version(
+        options.protocol,
+        options.protocol_mix.as_ref(),
+        options.seed,
+    );
+    let mut generator = configured_generator(version, options, setup);
+    if let Some(seed) = options.seed {
+        generator = generator.with_seed(seed);
+    }
+    if let Some(value_seed) = options.value_seed {
+        generator = generator.with_value_seed(value_seed);
+    }
+    generator
+}
+
+/// The FILE argument that stands for stdin or stdout.
+const STDIO_PATH: &str = "-";
+
+/// Read `path`, or stdin when it is `-`.
+fn read_input(path: &Path) -> Result<Vec<u8>> {
+    if path == Path::new(STDIO_PATH) {
+        let mut data = Vec::new();
+        std::io::stdin().lock().read_to_end(&mut data)?;
+        return Ok(data);
+    }
+    std::fs::read(path).map_err(|e| eyre!("failed to read {path:?}: {e}"))
+}
+
+/// Write `bytes` to `path`, or to stdout when it is `-`.
+fn write_output(path: &Path, bytes: &[u8]) -> Result<()> {
+    if path == Path::new(STDIO_PATH) {
+        let mut out = std::io::stdout().lock();
+        out.write_all(bytes)?;
+        out.flush()?;
+        return Ok(());
+    }
+    Ok(std::fs::write(path, bytes)?)
+}
+
+/// Replace `--metrics-file` with the counters of the run so far.
+fn write_metrics(metrics: Option<&Metrics>, path: Option<&Path>) -> Result<()> {
+    if let (Some(metrics), Some(path)) = (metrics, path) {
+        metrics
+            .write_file(path)
+            .map_err(|e| eyre!("failed to write {path:?}: {e}"))?;
+    }
+    Ok(())
+}
+
+/// Report that `what` went to `path`: on stdout, or on stderr when stdout
+/// carries the pickle itself.
+fn report_written(what: &str, path: &Path) {
+    if path == Path::new(STDIO_PATH) {
+        eprintln!("{what} to stdout");
+    } else {
+        println!("{what} to {path:?}");
+    }
+}
+
+/// Read an entropy trace written by `--record-trace`.
+fn read_trace(path: &Path) -> Result<EntropyTrace> {
+    let text = std::fs::read_to_string(path).map_err(|e| eyre!("failed to read {path:?}: {e}"))?;
+    serde_json::from_str(&text).map_err(|e| eyre!("invalid trace {path:?}: {e}"))
+}
+
+/// Read the sample lines of a batch manifest, keyed by the file they describe.
+///
+/// A missing manifest has no lines; the `{"config": ...}` line is skipped.
+fn read_manifest_lines(path: &Path) -> Result<HashMap<String, String>> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => bail!("failed to read {path:?}: {e}"),
+    };
+    let mut lines = HashMap::new();
+    for line in text.lines() {
+        // an interrupted run can leave its last line half-written
+        let Ok(entry) = serde_json::from_str::<serde_json::Value>(line) else {
+            continue;
+        };
+        if let Some(file) = entry["file"].as_str() {
+            lines.insert(file.to_owned(), line.to_owned());
+        }
+    }
+    Ok(lines)
+}
+
+/// Print every opcode's metadata, one opcode per line, in opcode byte order.
+fn print_opcode_table() -> Result<()> {
+    let mut table: Vec<_> = OPCODE_TABLE.iter().collect();
+    table.sort_by_key(|info| info.code);
+    let mut out = std::io::stdout().lock();
+    writeln!(
+        out,
+        "{:<6}{:<18}{:<24}{:<7}STACK",
+        "CODE", "NAME", "ARGUMENT", "PROTO"
+    )?;
+    for info in table {
+        writeln!(
+            out,
+            "{:<6}{:<18}{:<24}{:<7}{}",
+            format!("{:#04x}", info.code),
+            info.name,
+            info.arg.name(),
+            info.proto.to_string(),
+            info.stack
+        )?;
+    }
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    color_eyre::install()?;
+
+    let cli = Cli::parse_args();
+    init_logging(cli.log_format);
+    match cli.into_command() {
+        Command::Generate(args) => generate(*args),
+        Command::Mutate(args) => mutate(&args),
+        Command::Validate(args) => validate(&args),
+        Command::Analyze(args) => analyze(&args),
+        Command::Distill(args) => distill(&args),
+        Command::Grep(args) => grep(&args),
+        Command::Dis(args) => dis(&args),
+        Command::Minimize(args) => minimize(&args),
+        Command::Dataset(args) => dataset(&args),
+        #[cfg(feature = "serve")]
+        Command::Serve(serve) => pickle_fuzzer::serve::serve(serve.bind.as_str()),
+    }
+}
+
+/// `pickle-fuzzer generate`: write one pickle or a directory of them.
+fn generate(args: GenerateArgs) -> Result<()> {
+    if args.list_opcodes {
+        return print_opcode_table();
+    }
+
+    let options = &args.options;
+    let setup = GeneratorSetup::new(options)?;
+
+    if let Some(file) = &args.file {
+        // single file mode - generate one pickle
+        if args.variants.is_some() {
+            bail!("--variants requires --dir");
+        }
+        if args.resume {
+            bail!("--resume requires --dir");
+        }
+        if args.min_validity.is_some()
+            || args.min_average_size.is_some()
+            || args.max_average_size.is_some()
+            || args.min_opcode_entropy.is_some()
+        {
+            bail!("--min-validity and the other anomaly bounds require --dir");
+        }
+        if args.metrics_file.is_some() {
+            bail!("--metrics-file requires --dir");
+        }
+        let mut generator =
+            single_generator(options, &setup).with_annotations(args.annotations.is_some());
+        let started = Instant::now();
 
-                // apply EXT and buffer opcode flags
-                generator = generator
-                    .with_ext_opcodes(allow_ext_opcodes)
-                    .with_buffer_opcodes(allow_buffer_opcodes)
-                    .with_persistent_id_opcodes(allow_persistent_id_opcodes);
+        let bytecode = match (&args.replay_trace, &args.record_trace) {
+            (Some(path), _) => generator.replay(&read_trace(path)?)?,
+            (None, Some(path)) => {
+                let (bytecode, trace) = generator.generate_recorded()?;
+                std::fs::write(path, serde_json::to_string(&trace)?)?;
+                bytecode
+            }
+            (None, None) => generator.generate()?,
+        };
+        if args.check_loads {
+            loadcheck::check_loads(&generator, &bytecode, &PythonPool::new("python3"))?;
+        }
+        if args.dry_run {
+            let mut report = CorpusReport::default();
+            report.record(
+                generator.state.version.as_u8(),
+                bytecode.len(),
+                generator.stats().mutated_emissions,
+                opcode_names(&bytecode).as_deref(),
+            );
+            report.print_dry_run(started.elapsed());
+            return Ok(());
+        }
+        write_output(file, &bytecode)?;
+        if let Some(path) = &args.annotations {
+            std::fs::write(path, serde_json::to_string(generator.annotations())?)?;
+        }
+        report_written(&format!("Generated {} bytes", bytecode.len()), file);
+    } else if let Some(dir) = &args.dir {
+        if args.record_trace.is_some() || args.replay_trace.is_some() {
+            bail!("--record-trace and --replay-trace need a single output file");
+        }
+        if args.annotations.is_some() {
+            bail!("--annotations needs a single output file");
+        }
 
-                let bytecode = match generator.generate() {
-                    Ok(b) => b,
-                    Err(e) => return Some((idx, format!("generation error: {}", e))),
-                };
+        let seed = options.seed;
+        let value_seed = options.value_seed;
+        let variants = args.variants;
+        let compression = args.compress;
+        let shards = args.shard_dirs;
+        let dry_run = args.dry_run;
+        let dedupe = args.dedupe || args.dedupe_structural;
+        // --dedupe and --archive write samples from the main thread, in index
+        // order, so the workers hand their pickles back instead of writing them
+        let kept = dedupe || args.archive;
+        let bounds = Bounds {
+            min_validity: args.min_validity,
+            min_average_size: args.min_average_size,
+            max_average_size: args.max_average_size,
+            min_opcode_entropy: args.min_opcode_entropy,
+        };
+        let monitored = !bounds.is_empty();
+        let metrics = args.metrics_file.as_ref().map(|_| Metrics::new());
+        let counted = metrics.is_some();
+        let template = args
+            .name_template
+            .clone()
+            .unwrap_or_else(|| NameTemplate::batch_default(variants.is_some()));
+        if template.uses_variant() != variants.is_some() {
+            bail!("--name-template needs {{variant}} exactly when --variants is given");
+        }
+        // siblings always share a base seed, other samples only have one with --seed
+        if template.uses_seed() && seed.is_none() && variants.is_none() {
+            bail!("--name-template {{seed}} needs --seed");
+        }
 
-                let mut file_path = dir.clone();
-                file_path.push(format!("{idx}.pkl"));
+        if !dry_run && !dir.exists() {
+            std::fs::create_dir(dir)?;
+        }
+        if let Some(shards) = shards.filter(|_| !dry_run && !args.archive) {
+            for shard in 0..shards {
+                std::fs::create_dir_all(dir.join(output::shard_dir(shard, shards)))?;
+            }
+        }
 
-                if let Err(e) = std::fs::write(&file_path, &bytecode) {
-                    return Some((idx, format!("write error: {}", e)));
+        let file_name = |idx: usize, protocol: u8, seed: Option<u64>, variant: Option<usize>| {
+            let mut name = template.render(idx, protocol, seed, variant);
+            if let Some(compression) = compression {
+                name.push('.');
+                name.push_str(compression.extension());
+            }
+            match shards {
+                Some(shards) => format!("{}/{name}", output::shard_dir(idx, shards)),
+                None => name,
+            }
+        };
+        // every file of a sample, named the way generate_sample names them
+        let sample_files = |idx: usize| -> Vec<String> {
+            let sample_seed = seed.map(|seed| batch_sample_seed(seed, idx));
+            let version =
+                select_version(options.protocol, options.protocol_mix.as_ref(), sample_seed);
+            match variants {
+                Some(count) => (0..count)
+                    .map(|variant| file_name(idx, version.as_u8(), sample_seed, Some(variant)))
+                    .collect(),
+                None => vec![file_name(idx, version.as_u8(), sample_seed, None)],
+            }
+        };
+        // samples are written under a temporary name and renamed into place, so
+        // an interrupted run never leaves a truncated sample for --resume to keep
+        let write_sample = |file_name: &str, bytecode: &[u8]| {
+            if dry_run {
+                return Ok(());
+            }
+            let path = dir.join(file_name);
+            let partial = dir.join(format!("{file_name}.partial"));
+            let result = match compression {
+                Some(compression) => std::fs::write(&partial, compression.compress(bytecode)),
+                None => std::fs::write(&partial, bytecode),
+            };
+            result
+                .and_then(|()| std::fs::rename(&partial, &path))
+                .map_err(|e| format!("write error: {}", e))
+        };
+
+        // shared by the rayon workers, each using one idle python3 at a time
+        let python = args.check_loads.then(|| PythonPool::new("python3"));
+        let check_loads = |generator: &Generator, bytecode: &[u8]| match &python {
+            Some(python) => loadcheck::check_loads(generator, bytecode, python)
+                .map_err(|e| SampleError::Failed(format!("load check failed: {e}"))),
+            None => Ok(()),
+        };
+
+        // each rayon thread builds its generators once and reuses them across
+        // chunks; map_init pairs one with an output buffer per work split so the
+        // hot loop doesn't allocate
+        let generators = {
+            let (options, setup) = (options.clone(), setup.clone());
+            GeneratorPool::new(move || configured_generator(Version::default(), &options, &setup))
+        };
+        let new_worker = || (generators.generator(), Vec::new());
+
+        // one try at sample `idx` with the given seeds; a timed-out try is
+        // retried below with fresh ones
+        let generate_attempt = |(generator, bytecode): &mut (PooledGenerator, Vec<u8>),
+                                idx: usize,
+                                version: Version,
+                                sample_seed: Option<u64>,
+                                sample_value_seed: Option<u64>|
+         -> Result<Vec<BatchSample>, SampleError> {
+            if let Some(count) = variants {
+                // the siblings need one base seed to share, if only a random one
+                let base_seed = sample_seed.unwrap_or_else(|| rand::rng().random());
+                return (0..count)
+                    .map(|variant| {
+                        let bytecode = generator
+                            .generate_variant(base_seed, variant)
+                            .map_err(SampleError::generation)?;
+                        check_loads(generator, &bytecode)?;
+                        let file_name =
+                            file_name(idx, version.as_u8(), Some(base_seed), Some(variant));
+                        if !kept {
+                            write_sample(&file_name, &bytecode)?;
+                        }
+                        Ok(BatchSample {
+                            entry: ManifestEntry {
+                                index: idx,
+                                file: file_name,
+                                protocol: version.as_u8(),
+                                seed: Some(base_seed),
+                                value_seed: None,
+                                size: bytecode.len(),
+                                peak_stack_depth: generator.stats().peak_stack_depth,
+                                format_version: GENERATOR_FORMAT_VERSION,
+                                variant: Some(variant),
+                                timeouts: None,
+                                duplicates: None,
+                            },
+                            mutated_emissions: generator.stats().mutated_emissions,
+                            opcodes: dry_run.then(|| opcode_names(&bytecode)).flatten(),
+                            stats: (monitored || counted).then(|| SampleStats::of(&bytecode)),
+                            mutators: if counted {
+                                generator.mutator_applications()
+                            } else {
+                                Vec::new()
+                            },
+                            pickle: kept.then_some(bytecode),
+                        })
+                    })
+                    .collect();
+            }
+
+            generator.set_seed(sample_seed);
+            generator.set_value_seed(sample_value_seed);
+            generator
+                .generate_into(bytecode)
+                .map_err(SampleError::generation)?;
+            check_loads(generator, bytecode)?;
+
+            let file_name = file_name(idx, version.as_u8(), sample_seed, None);
+            if !kept {
+                write_sample(&file_name, bytecode)?;
+            }
+
+            Ok(vec![BatchSample {
+                entry: ManifestEntry {
+                    index: idx,
+                    file: file_name,
+                    protocol: version.as_u8(),
+                    seed: sample_seed,
+                    value_seed: sample_value_seed,
+                    size: bytecode.len(),
+                    peak_stack_depth: generator.stats().peak_stack_depth,
+                    format_version: GENERATOR_FORMAT_VERSION,
+                    variant: None,
+                    timeouts: None,
+                    duplicates: None,
+                },
+                mutated_emissions: generator.stats().mutated_emissions,
+                opcodes: dry_run.then(|| opcode_names(bytecode)).flatten(),
+                stats: (monitored || counted).then(|| SampleStats::of(bytecode)),
+                mutators: if counted {
+                    generator.mutator_applications()
+                } else {
+                    Vec::new()
+                },
+                pickle: kept.then(|| bytecode.clone()),
+            }])
+        };
+
+        let generate_sample = |worker: &mut (PooledGenerator, Vec<u8>),
+                               idx: usize|
+         -> Result<Vec<BatchSample>, String> {
+            let _sample = info_span!("sample", idx).entered();
+            let mut sample_seed = seed.map(|seed| batch_sample_seed(seed, idx));
+            let mut sample_value_seed = value_seed.map(|seed| batch_sample_seed(seed, idx));
+            // same version selection logic as what's used above
+            let version =
+                select_version(options.protocol, options.protocol_mix.as_ref(), sample_seed);
+            worker.0.set_version(version);
+
+            let mut timeouts = 0;
+            loop {
+                match generate_attempt(worker, idx, version, sample_seed, sample_value_seed) {
+                    Ok(mut samples) => {
+                        for sample in &mut samples {
+                            sample.entry.timeouts = (timeouts > 0).then_some(timeouts);
+                        }
+                        return Ok(samples);
+                    }
+                    Err(SampleError::TimedOut) if timeouts < MAX_SAMPLE_TIMEOUTS => {
+                        // the protocol stays the index's; the seeds that hung don't
+                        timeouts += 1;
+                        debug!(timeouts, "timed out, regenerating with fresh seeds");
+                        sample_seed = sample_seed.map(|_| rand::rng().random());
+                        sample_value_seed = sample_value_seed.map(|_| rand::rng().random());
+                    }
+                    Err(SampleError::TimedOut) => {
+                        return Err(format!(
+                            "timed out {} times in a row",
+                            MAX_SAMPLE_TIMEOUTS + 1
+                        ))
+                    }
+                    Err(SampleError::Failed(error)) => return Err(error),
                 }
+            }
+        };
 
-                None
-            })
-            .collect();
+        // --dedupe's `retry`th regeneration of sample `idx`, made on the main
+        // thread in index order so which of two equal samples survives doesn't
+        // depend on scheduling
+        let regenerate = |worker: &mut (PooledGenerator, Vec<u8>), idx: usize, retry: usize| {
+            let sample_seed = seed.map(|seed| batch_sample_seed(seed, idx));
+            let version =
+                select_version(options.protocol, options.protocol_mix.as_ref(), sample_seed);
+            worker.0.set_version(version);
+            let derive = |seed: u64| dedupe_retry_seed(batch_sample_seed(seed, idx), retry);
+            generate_attempt(
+                worker,
+                idx,
+                version,
+                seed.map(derive),
+                value_seed.map(derive),
+            )
+        };
+
+        // a dedicated pool so --jobs doesn't leak into rayon's global pool; 0 keeps
+        // rayon's default of one thread per logical CPU
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(args.jobs)
+            .build()?;
 
-        if !errors.is_empty() {
-            eprintln!("Encountered {} errors during generation:", errors.len());
-            for (idx, error) in errors.iter().take(10) {
-                eprintln!("  Sample {}: {}", idx, error);
+        // what the interrupted run finished: a sample is kept when all of its files
+        // exist and, with --manifest, the manifest describes them
+        let previous_manifest = match &args.manifest {
+            Some(path) if args.resume => read_manifest_lines(path)?,
+            _ => HashMap::new(),
+        };
+        let finished_lines = |idx: usize| -> Option<Vec<String>> {
+            if !args.resume {
+                return None;
             }
-            if errors.len() > 10 {
-                eprintln!("  ... and {} more errors", errors.len() - 10);
+            sample_files(idx)
+                .into_iter()
+                .map(|file| {
+                    if !dir.join(&file).is_file() {
+                        return None;
+                    }
+                    match args.manifest {
+                        Some(_) => previous_manifest.get(&file).cloned(),
+                        None => Some(String::new()),
+                    }
+                })
+                .collect()
+        };
+
+        let mut manifest = match &args.manifest {
+            Some(path) => Some(BufWriter::new(File::create(path)?)),
+            None => None,
+        };
+        // a run configured from a file records what it ran with ahead of the samples
+        if let (Some(manifest), Some(config)) = (manifest.as_mut(), &args.effective_config) {
+            serde_json::to_writer(&mut *manifest, &serde_json::json!({ "config": config }))?;
+            manifest.write_all(b"\n")?;
+        }
+
+        // like a sample, the archive is written under a temporary name and
+        // renamed into place once it is complete
+        let archive_path = dir.join(output::ARCHIVE_NAME);
+        let mut archive = match args.archive {
+            true => Some(Archive::new(BufWriter::new(File::create(partial_path(
+                &archive_path,
+            ))?))?),
+            false => None,
+        };
+
+        let progress = ProgressBar::new(args.samples as u64);
+        progress.set_style(
+            ProgressStyle::with_template(
+                "{elapsed_precise} [{wide_bar}] {pos}/{len} ({per_sec}, eta {eta})",
+            )?
+            .progress_chars("=> "),
+        );
+        *PROGRESS.lock().unwrap_or_else(|e| e.into_inner()) = Some(progress.clone());
+
+        // samples are generated one chunk at a time so memory stays bounded by the
+        // chunk size no matter how large --samples is
+        let mut error_count = 0usize;
+        let mut resumed = 0usize;
+        let mut timeouts = 0usize;
+        let mut deduped = dedupe.then(|| {
+            let dedupe = Dedupe {
+                structural: args.dedupe_structural,
+                ..Dedupe::default()
+            };
+            (dedupe, new_worker())
+        });
+        let mut report = CorpusReport::default();
+        let mut monitor = monitored.then(|| Monitor::new(bounds, args.monitor_window));
+        let started = Instant::now();
+        for chunk_start in (0..args.samples).step_by(BATCH_CHUNK_SIZE) {
+            let chunk_end = (chunk_start + BATCH_CHUNK_SIZE).min(args.samples);
+            let finished: Vec<Option<Vec<String>>> =
+                (chunk_start..chunk_end).map(finished_lines).collect();
+            let missing: Vec<usize> = (chunk_start..chunk_end)
+                .zip(&finished)
+                .filter(|(_, lines)| lines.is_none())
+                .map(|(idx, _)| idx)
+                .collect();
+            let mut results = pool
+                .install(|| {
+                    missing
+                        .into_par_iter()
+                        .map_init(new_worker, generate_sample)
+                        .collect::<Vec<Result<Vec<BatchSample>, String>>>()
+                })
+                .into_iter();
+
+            for (idx, finished) in (chunk_start..chunk_end).zip(finished) {
+                if let Some(lines) = finished {
+                    // kept from the interrupted run, manifest line and all
+                    resumed += 1;
+                    if let Some(manifest) = manifest.as_mut() {
+                        for line in lines {
+                            manifest.write_all(line.as_bytes())?;
+                            manifest.write_all(b"\n")?;
+                        }
+                    }
+                    continue;
+                }
+                let mut result = results.next().expect("one result per missing sample");
+                if let (Some((dedupe, worker)), Ok(samples)) = (deduped.as_mut(), &result) {
+                    if !dedupe.admit(samples) {
+                        dedupe.collisions += 1;
+                        let mut unique = None;
+                        for retry in 1..=MAX_DEDUPE_RETRIES {
+                            // a timed-out or failed retry just uses up a retry
+                            let Ok(mut samples) = regenerate(worker, idx, retry) else {
+                                continue;
+                            };
+                            if dedupe.admit(&samples) {
+                                for sample in &mut samples {
+                                    sample.entry.duplicates = Some(retry);
+                                }
+                                unique = Some(samples);
+                                break;
+                            }
+                        }
+                        match unique {
+                            Some(samples) => {
+                                dedupe.regenerated += 1;
+                                result = Ok(samples);
+                            }
+                            None => {
+                                dedupe.skipped += 1;
+                                continue;
+                            }
+                        }
+                    }
+                }
+                if let (true, Ok(samples)) = (kept, &result) {
+                    for sample in samples {
+                        let pickle = sample.pickle.as_deref().unwrap_or_default();
+                        let written = match archive.as_mut() {
+                            Some(archive) => archive
+                                .append(&sample.entry.file, pickle)
+                                .map_err(|e| format!("write error: {e}")),
+                            None => write_sample(&sample.entry.file, pickle),
+                        };
+                        if let Err(error) = written {
+                            result = Err(error);
+                            break;
+                        }
+                    }
+                }
+                match result {
+                    Ok(samples) => {
+                        // siblings share their tries, so count them once
+                        timeouts += samples
+                            .first()
+                            .and_then(|sample| sample.entry.timeouts)
+                            .unwrap_or(0);
+                        for sample in samples {
+                            if dry_run {
+                                report.record(
+                                    sample.entry.protocol,
+                                    sample.entry.size,
+                                    sample.mutated_emissions,
+                                    sample.opcodes.as_deref(),
+                                );
+                            }
+                            // the manifest only lists samples that were actually written
+                            if let Some(manifest) = manifest.as_mut() {
+                                serde_json::to_writer(&mut *manifest, &sample.entry)?;
+                                manifest.write_all(b"\n")?;
+                            }
+                            if let (Some(metrics), Some(stats)) = (&metrics, &sample.stats) {
+                                metrics.record_sample(stats.size, stats.valid, &sample.mutators);
+                            }
+                            let Some((monitor, stats)) = monitor.as_mut().zip(sample.stats) else {
+                                continue;
+                            };
+                            for alert in monitor.record(stats) {
+                                if let Alert::Recovered { .. } = alert {
+                                    info!("{alert}");
+                                    continue;
+                                }
+                                if args.abort_on_anomaly {
+                                    PROGRESS.lock().unwrap_or_else(|e| e.into_inner()).take();
+                                    progress.abandon();
+                                    if let Some(manifest) = manifest.as_mut() {
+                                        manifest.flush()?;
+                                    }
+                                    if let Some(archive) = archive.take() {
+                                        finish_archive(archive, &archive_path)?;
+                                    }
+                                    write_metrics(metrics.as_ref(), args.metrics_file.as_deref())?;
+                                    bail!("stopping at sample {idx} (--abort-on-anomaly): {alert}");
+                                }
+                                warn!("{alert}");
+                            }
+                        }
+                    }
+                    Err(error) => {
+                        error_count += 1;
+                        if let Some(metrics) = &metrics {
+                            metrics.record_failure();
+                        }
+                        if error_count <= MAX_REPORTED_ERRORS {
+                            info_span!("sample", idx).in_scope(|| error!("{error}"));
+                        } else if error_count == MAX_REPORTED_ERRORS + 1 {
+                            warn!("suppressing further sample errors");
+                        }
+                    }
+                }
             }
+
+            progress.inc((chunk_end - chunk_start) as u64);
+            write_metrics(metrics.as_ref(), args.metrics_file.as_deref())?;
+        }
+        PROGRESS.lock().unwrap_or_else(|e| e.into_inner()).take();
+        progress.finish_and_clear();
+        let elapsed = started.elapsed();
+
+        if timeouts > 0 {
+            warn!("{timeouts} tries hit --sample-timeout and were regenerated with fresh seeds");
+        }
+
+        if let Some(mut manifest) = manifest {
+            manifest.flush()?;
+        }
+        if let Some(archive) = archive {
+            finish_archive(archive, &archive_path)?;
+        }
+
+        // the statistics of the samples that did generate help tune a failing
+        // configuration too
+        if dry_run {
+            report.print_dry_run(elapsed);
+        }
+
+        if error_count > 0 {
+            error!("encountered {error_count} errors during generation");
             return Err(color_eyre::eyre::eyre!(
                 "Failed to generate {} out of {} samples",
-                errors.len(),
+                error_count,
                 args.samples
             ));
         }
 
-        println!(
-            "Successfully generated {} pickle files to {:?}",
-            args.samples, dir
-        );
+        let skipped = deduped.as_ref().map_or(0, |(dedupe, _)| dedupe.skipped);
+        let destination = if args.archive { &archive_path } else { dir };
+        if resumed > 0 {
+            println!(
+                "Successfully generated {} pickle files to {:?} ({} kept from the interrupted run)",
+                args.samples - resumed - skipped,
+                dir,
+                resumed
+            );
+        } else if !dry_run {
+            println!(
+                "Successfully generated {} pickle files to {:?}",
+                args.samples - skipped,
+                destination
+            );
+        }
+        if let Some((dedupe, _)) = &deduped {
+            dedupe.print(args.samples);
+        }
     } else {
         unreachable!("clap should ensure either file or dir is provided");
     }
 
     Ok(())
 }
+
+/// Where a batch file is written until it is complete.
+fn partial_path(path: &Path) -> PathBuf {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    PathBuf::from(partial)
+}
+
+/// Finish a `--archive` archive and move it into place at `path`.
+fn finish_archive(archive: Archive<BufWriter<File>>, path: &Path) -> Result<()> {
+    archive.finish()?.into_inner().map_err(|e| e.into_error())?;
+    std::fs::rename(partial_path(path), path)?;
+    Ok(())
+}
+
+/// `pickle-fuzzer mutate`: write a structure-aware mutant of a pickle.
+fn mutate(args: &MutateArgs) -> Result<()> {
+    let data = read_input(&args.input)?;
+    let seed = args.seed.unwrap_or_else(|| rand::rng().random());
+    let mutant = pickle_fuzzer::fuzz_harness::mutate_pickle(&data, args.max_size, seed);
+    write_output(&args.output, &mutant)?;
+    report_written(
+        &format!("Wrote a {}-byte mutant", mutant.len()),
+        &args.output,
+    );
+    Ok(())
+}
+
+/// `pickle-fuzzer validate`: check every pickle in a directory, or one file.
+/// `path` if it is a file, or every file below it, sorted.
+///
+/// Subdirectories are included so sharded corpora (`--shard-dirs`) are read
+/// whole.
+fn corpus_files(path: &Path) -> Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut files = Vec::new();
+    let mut dirs = vec![path.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(&dir).map_err(|e| eyre!("failed to read {dir:?}: {e}"))? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.is_file() {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn validate(args: &ValidateArgs) -> Result<()> {
+    let files = corpus_files(&args.path)?;
+
+    let mut invalid = 0usize;
+    for path in &files {
+        let data = std::fs::read(path).map_err(|e| eyre!("failed to read {path:?}: {e}"))?;
+        if let Err(error) = disasm::validate(&data) {
+            invalid += 1;
+            println!("{}: {}", path.display(), error);
+        }
+    }
+
+    if invalid > 0 {
+        bail!("{} of {} pickles are invalid", invalid, files.len());
+    }
+    println!("All {} pickles are valid", files.len());
+    Ok(())
+}
+
+/// `pickle-fuzzer analyze`: aggregate statistics about a corpus of pickles.
+fn analyze(args: &AnalyzeArgs) -> Result<()> {
+    let files = corpus_files(&args.path)?;
+
+    let mut report = CorpusReport::default();
+    let mut invalid = Vec::new();
+    for path in &files {
+        let data = std::fs::read(path).map_err(|e| eyre!("failed to read {path:?}: {e}"))?;
+        if let Err(error) = disasm::validate(&data) {
+            invalid.push((path, error));
+        }
+        // a pickle that decodes but fails the stack checks still has opcodes
+        // and globals to count
+        let Ok(instructions) = disasm::disassemble(&data) else {
+            continue;
+        };
+        let names: Vec<&'static str> = instructions.iter().map(|op| op.name).collect();
+        report.record(pickle_protocol(&instructions), data.len(), 0, Some(&names));
+        for global in globals_used(&instructions) {
+            *report.globals.entry(global).or_default() += 1;
+        }
+        *report.risks.entry(risk::classify(&data)).or_default() += 1;
+    }
+
+    println!(
+        "Analyzed {} files in {:?}: {} decode, {} invalid",
+        files.len(),
+        args.path,
+        report.pickles,
+        invalid.len()
+    );
+    if report.pickles > 0 {
+        report.print_sizes_and_protocols();
+        report.print_risks();
+        report.print_opcodes();
+        report.print_globals(args.top);
+    }
+    if !invalid.is_empty() {
+        println!("Invalid files:");
+        for (path, error) in invalid.iter().take(MAX_REPORTED_ERRORS) {
+            println!("  {}: {}", path.display(), error);
+        }
+        if invalid.len() > MAX_REPORTED_ERRORS {
+            println!("  ... {} more", invalid.len() - MAX_REPORTED_ERRORS);
+        }
+    }
+    Ok(())
+}
+
+/// What `distill` keeps covered: something a corpus pickle exercises.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Feature {
+    /// two opcodes in a row
+    Bigram(&'static str, &'static str),
+    Global(String),
+    Protocol(u8),
+}
+
+/// `pickle-fuzzer distill`: copy a smallest subset of a corpus with the same
+/// coverage.
+///
+/// Finding the smallest subset is set cover, so this picks greedily: the
+/// pickle covering the most features not yet covered comes next, the smaller
+/// one on ties. Files that don't disassemble cover nothing and are dropped.
+fn distill(args: &DistillArgs) -> Result<()> {
+    use std::cmp::Reverse;
+    use std::collections::BinaryHeap;
+
+    if !args.input.is_dir() {
+        bail!("{:?} is not a directory", args.input);
+    }
+    let files = corpus_files(&args.input)?;
+
+    let mut candidates = Vec::new();
+    for path in files.iter() {
+        let data = std::fs::read(path).map_err(|e| eyre!("failed to read {path:?}: {e}"))?;
+        let Ok(instructions) = disasm::disassemble(&data) else {
+            continue;
+        };
+        let mut features: HashSet<Feature> = instructions
+            .windows(2)
+            .map(|pair| Feature::Bigram(pair[0].name, pair[1].name))
+            .collect();
+        features.extend(globals_used(&instructions).into_iter().map(Feature::Global));
+        features.insert(Feature::Protocol(pickle_protocol(&instructions)));
+        candidates.push((path, data.len(), features));
+    }
+
+    // lazy greedy: a candidate's gain only shrinks as others are picked, so a
+    // popped candidate whose recomputed gain still tops the heap is the best
+    let mut covered: HashSet<Feature> = HashSet::new();
+    let mut heap: BinaryHeap<_> = candidates
+        .iter()
+        .enumerate()
+        .map(|(index, (_, size, features))| (features.len(), Reverse(*size), Reverse(index)))
+        .collect();
+    let mut selected = Vec::new();
+    while let Some((gain, size, Reverse(index))) = heap.pop() {
+        let features = &candidates[index].2;
+        let fresh = features.difference(&covered).count();
+        if fresh == 0 {
+            continue;
+        }
+        if fresh < gain {
+            heap.push((fresh, size, Reverse(index)));
+            continue;
+        }
+        covered.extend(features.iter().cloned());
+        selected.push(index);
+    }
+    selected.sort_unstable();
+
+    let mut bytes = 0;
+    for &index in &selected {
+        let (path, size, _) = &candidates[index];
+        let relative = path.strip_prefix(&args.input).unwrap_or(path);
+        let target = args.output.join(relative);
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::copy(path, &target).map_err(|e| eyre!("failed to copy {path:?}: {e}"))?;
+        bytes += size;
+    }
+
+    let count = |kind: fn(&Feature) -> bool| covered.iter().filter(|f| kind(f)).count();
+    println!(
+        "Distilled {} of {} files ({} bytes) into {:?}, covering {} opcode bigrams, {} globals, and {} protocols",
+        selected.len(),
+        files.len(),
+        bytes,
+        args.output,
+        count(|f| matches!(f, Feature::Bigram(..))),
+        count(|f| matches!(f, Feature::Global(_))),
+        count(|f| matches!(f, Feature::Protocol(_))),
+    );
+    Ok(())
+}
+
+/// `pickle-fuzzer grep`: list the pickles of a corpus that match the given
+/// globals and opcodes.
+///
+/// Pickles are disassembled, not searched as bytes, so a global only matches
+/// where it is imported and an opcode only where it is an opcode. Matching
+/// paths go to stdout one per line, for piping into other tools; the count
+/// goes to stderr. A reader that closes the pipe early, like `head`, ends the
+/// search.
+fn grep(args: &GrepArgs) -> Result<()> {
+    let files = corpus_files(&args.path)?;
+    let global_matches = |pattern: &str, global: &str| match pattern.strip_suffix('*') {
+        Some(module) if module.ends_with('.') => global.starts_with(module),
+        _ => pattern == global,
+    };
+
+    let mut stdout = BufWriter::new(std::io::stdout().lock());
+    let (mut matched, mut undecodable) = (0usize, 0usize);
+    for path in &files {
+        let data = std::fs::read(path).map_err(|e| eyre!("failed to read {path:?}: {e}"))?;
+        let Ok(instructions) = disasm::disassemble(&data) else {
+            undecodable += 1;
+            continue;
+        };
+        let globals = globals_used(&instructions);
+        let mut hits = args
+            .globals
+            .iter()
+            .map(|pattern| globals.iter().any(|global| global_matches(pattern, global)))
+            .chain(
+                args.opcodes
+                    .iter()
+                    .map(|name| instructions.iter().any(|op| op.name == *name)),
+            );
+        let hit = if args.any {
+            hits.any(|hit| hit)
+        } else {
+            hits.all(|hit| hit)
+        };
+        if hit {
+            matched += 1;
+            match writeln!(stdout, "{}", path.display()) {
+                Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => return Ok(()),
+                result => result?,
+            }
+        }
+    }
+    match stdout.flush() {
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => return Ok(()),
+        result => result?,
+    }
+
+    eprintln!(
+        "{} of {} files match{}",
+        matched,
+        files.len(),
+        match undecodable {
+            0 => String::new(),
+            n => format!(" ({n} don't disassemble and were skipped)"),
+        }
+    );
+    Ok(())
+}
+
+/// `pickle-fuzzer dataset`: write a label line for every pickle of a corpus.
+fn dataset(args: &DatasetArgs) -> Result<()> {
+    let files = corpus_files(&args.path)?;
+    let mut out: BufWriter<Box<dyn Write>> =
+        BufWriter::new(if args.output == Path::new(STDIO_PATH) {
+            Box::new(std::io::stdout().lock())
+        } else {
+            Box::new(
+                File::create(&args.output)
+                    .map_err(|e| eyre!("failed to create {:?}: {e}", args.output))?,
+            )
+        });
+
+    let mut risks: BTreeMap<Risk, usize> = BTreeMap::new();
+    let mut valid = 0;
+    for path in &files {
+        let data = std::fs::read(path).map_err(|e| eyre!("failed to read {path:?}: {e}"))?;
+        // a single file is named by its file name
+        let relative = match path.strip_prefix(&args.path) {
+            Ok(relative) if !relative.as_os_str().is_empty() => relative,
+            _ => path.file_name().map_or(path.as_path(), Path::new),
+        };
+        let instructions = disasm::disassemble(&data).ok();
+        let error = disasm::validate(&data).err().map(|e| e.to_string());
+        let risk = risk::classify(&data);
+        *risks.entry(risk).or_default() += 1;
+        valid += usize::from(error.is_none());
+
+        let label = DatasetLabel {
+            file: relative.display().to_string(),
+            size: data.len(),
+            protocol: instructions.as_deref().map(pickle_protocol),
+            valid: error.is_none(),
+            error,
+            risk: risk.to_string(),
+            globals: instructions
+                .as_deref()
+                .map(globals_used)
+                .unwrap_or_default(),
+            structure: disasm::structural_fingerprint(&data)
+                .ok()
+                .map(|fingerprint| format!("{fingerprint:016x}")),
+        };
+        serde_json::to_writer(&mut out, &label)?;
+        out.write_all(b"\n")?;
+    }
+    out.flush()?;
+
+    let risks: Vec<String> = Risk::all()
+        .iter()
+        .map(|risk| format!("{risk}: {}", risks.get(risk).copied().unwrap_or(0)))
+        .collect();
+    report_written(
+        &format!(
+            "Labeled {} pickles ({valid} valid; {})",
+            files.len(),
+            risks.join(", ")
+        ),
+        &args.output,
+    );
+    Ok(())
+}
+
+/// `pickle-fuzzer dis`: print a pickle's opcodes the way `pickletools.dis`
+/// lays them out, then check it like `validate` does.
+fn dis(args: &DisArgs) -> Result<()> {
+    let data = read_input(&args.file)?;
+    let instructions = disasm::disassemble(&data)?;
+    let annotations: HashMap<usize, Annotation> = match &args.annotations {
+        Some(path) => serde_json::from_slice::<Vec<Annotation>>(&std::fs::read(path)?)
+            .wrap_err_with(|| format!("invalid annotations in {}", path.display()))?
+            .into_iter()
+            .map(|annotation| (annotation.offset, annotation))
+            .collect(),
+        None => HashMap::new(),
+    };
+    let mut out = std::io::stdout().lock();
+    for instruction in &instructions {
+        let code = [instruction.code].escape_ascii().to_string();
+        let mut line = match &instruction.arg {
+            disasm::Argument::None => {
+                format!("{:>5}: {:<4} {}", instruction.pos, code, instruction.name)
+            }
+            arg => format!(
+                "{:>5}: {:<4} {:<16} {}",
+                instruction.pos, code, instruction.name, arg
+            ),
+        };
+        if let Some(annotation) = annotations.get(&instruction.pos) {
+            line = format!(
+                "{line:<40} # {}, depth {}",
+                annotation.origin, annotation.depth
+            );
+            if !annotation.mutations.is_empty() {
+                line.push_str(&format!(" [{}]", annotation.mutations.join(", ")));
+            }
+        }
+        match writeln!(out, "{line}") {
+            Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => return Ok(()),
+            result => result?,
+        }
+    }
+    match out.flush() {
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => return Ok(()),
+        result => result?,
+    }
+    disasm::validate(&data)
+}
+
+/// `pickle-fuzzer minimize`: shrink a recorded trace while `args.command`
+/// keeps failing on its pickle.
+fn minimize(args: &MinimizeArgs) -> Result<()> {
+    let trace = read_trace(&args.trace)?;
+    let setup = GeneratorSetup::new(&args.options)?;
+    // a cut trace ends the pickle where its decisions end
+    let mut generator =
+        single_generator(&args.options, &setup).with_exhaustion_policy(ExhaustionPolicy::Stop);
+
+    let candidate =
+        std::env::temp_dir().join(format!("pickle-fuzzer-minimize-{}.pkl", std::process::id()));
+    let shrunk = generator.shrink(&trace, args.max_runs, |pickle| {
+        command_fails(&args.command, &candidate, pickle)
+    });
+    let _ = std::fs::remove_file(&candidate);
+    let shrunk = shrunk.wrap_err("the command has to fail on the pickle of the trace")?;
+
+    std::fs::write(&args.output, &shrunk.pickle)?;
+    if let Some(path) = &args.output_trace {
+        std::fs::write(path, serde_json::to_string(&shrunk.trace)?)?;
+    }
+    println!(
+        "Shrunk {} decisions to {} in {} runs; wrote {} bytes to {:?}",
+        trace.len(),
+        shrunk.trace.len(),
+        shrunk.runs,
+        shrunk.pickle.len(),
+        args.output
+    );
+    Ok(())
+}
+
+/// Whether `command` exits unsuccessfully on `pickle`, written to `path` for
+/// it. `{}` in an argument stands for `path`, which is appended when no
+/// argument has one.
+fn command_fails(command: &[OsString], path: &Path, pickle: &[u8]) -> bool {
+    let Some((program, args)) = command.split_first() else {
+        return false;
+    };
+    if std::fs::write(path, pickle).is_err() {
+        return false;
+    }
+
+    let mut substituted = false;
+    let mut args: Vec<OsString> = args
+        .iter()
+        .map(|arg| match arg.to_str() {
+            Some(text) if text.contains("{}") => {
+                substituted = true;
+                OsString::from(text.replace("{}", &path.to_string_lossy()))
+            }
+            _ => arg.clone(),
+        })
+        .collect();
+    if !substituted {
+        args.push(path.as_os_str().to_owned());
+    }
+
+    std::process::Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| !status.success())
+}
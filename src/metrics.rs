@@ -0,0 +1,187 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! counters for long-running generation, in the Prometheus text format.
+//!
+//! a [`Metrics`] counts the samples a service or batch job generates, their
+//! bytes, the ones that fail or don't validate, and how often each mutator
+//! fired. [`render`](Metrics::render) writes them in the text exposition
+//! format Prometheus scrapes, which `pickle-fuzzer serve` answers on
+//! `GET /metrics`, and [`write_file`](Metrics::write_file) replaces a file
+//! with them, for the node exporter's textfile collector to pick up from a
+//! batch run (`--metrics-file`).
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// counters shared by the threads of a run.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    samples: AtomicU64,
+    bytes: AtomicU64,
+    invalid: AtomicU64,
+    failed: AtomicU64,
+    mutators: Mutex<BTreeMap<String, u64>>,
+}
+
+impl Metrics {
+    /// counters at zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// count a generated sample of `size` bytes, whether
+    /// [`disasm::validate`](crate::disasm::validate) accepts it, and the
+    /// mutators that fired while generating it, as
+    /// [`Generator::mutator_applications`](crate::Generator::mutator_applications)
+    /// lists them.
+    pub fn record_sample(&self, size: usize, valid: bool, mutators: &[(String, usize)]) {
+        self.samples.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(size as u64, Ordering::Relaxed);
+        if !valid {
+            self.invalid.fetch_add(1, Ordering::Relaxed);
+        }
+        if mutators.is_empty() {
+            return;
+        }
+        let mut counts = self.mutators.lock().unwrap_or_else(|e| e.into_inner());
+        for (name, count) in mutators {
+            *counts.entry(name.clone()).or_default() += *count as u64;
+        }
+    }
+
+    /// count a sample that failed to generate or failed a check.
+    pub fn record_failure(&self) {
+        self.failed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// the counters in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let counters = [
+            (
+                "pickle_fuzzer_samples_total",
+                "Pickles generated.",
+                &self.samples,
+            ),
+            (
+                "pickle_fuzzer_bytes_written_total",
+                "Bytes of the generated pickles, before compression.",
+                &self.bytes,
+            ),
+            (
+                "pickle_fuzzer_invalid_samples_total",
+                "Generated pickles that fail validation, e.g. after unsafe mutations.",
+                &self.invalid,
+            ),
+            (
+                "pickle_fuzzer_failed_samples_total",
+                "Samples that failed to generate or failed a check.",
+                &self.failed,
+            ),
+        ];
+        for (name, help, counter) in counters {
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} counter");
+            let _ = writeln!(out, "{name} {}", counter.load(Ordering::Relaxed));
+        }
+
+        let name = "pickle_fuzzer_mutator_applications_total";
+        let _ = writeln!(
+            out,
+            "# HELP {name} Times each mutator changed a value or emission."
+        );
+        let _ = writeln!(out, "# TYPE {name} counter");
+        let counts = self.mutators.lock().unwrap_or_else(|e| e.into_inner());
+        for (mutator, count) in counts.iter() {
+            let _ = writeln!(
+                out,
+                "{name}{{mutator=\"{}\"}} {count}",
+                escape_label(mutator)
+            );
+        }
+        out
+    }
+
+    /// replace the file at `path` with [`render`](Self::render)'s output.
+    ///
+    /// the counters go to `PATH.partial` first and are renamed into place,
+    /// so a collector never reads half a file.
+    pub fn write_file(&self, path: &Path) -> io::Result<()> {
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".partial");
+        std::fs::write(&partial, self.render())?;
+        std::fs::rename(&partial, path)
+    }
+}
+
+/// `value` as a label value: backslashes, quotes, and newlines escaped.
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', r#"\""#)
+        .replace('\n', r"\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_lists_every_counter() {
+        let metrics = Metrics::new();
+        metrics.record_sample(100, true, &[("bitflip".into(), 2)]);
+        metrics.record_sample(
+            20,
+            false,
+            &[("bitflip".into(), 1), ("say \"hi\"\n".into(), 1)],
+        );
+        metrics.record_failure();
+
+        let text = metrics.render();
+        for line in [
+            "# TYPE pickle_fuzzer_samples_total counter",
+            "pickle_fuzzer_samples_total 2",
+            "pickle_fuzzer_bytes_written_total 120",
+            "pickle_fuzzer_invalid_samples_total 1",
+            "pickle_fuzzer_failed_samples_total 1",
+            "pickle_fuzzer_mutator_applications_total{mutator=\"bitflip\"} 3",
+            r#"pickle_fuzzer_mutator_applications_total{mutator="say \"hi\"\n"} 1"#,
+        ] {
+            assert!(
+                text.lines().any(|l| l == line),
+                "{line} missing from\n{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn write_file_replaces_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pickle_fuzzer.prom");
+        std::fs::write(&path, "stale").unwrap();
+
+        let metrics = Metrics::new();
+        metrics.record_sample(7, true, &[]);
+        metrics.write_file(&path).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), metrics.render());
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
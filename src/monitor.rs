@@ -0,0 +1,325 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! rolling statistics of a long batch run, and alerts when they drift.
+//!
+//! a [`Monitor`] keeps the last few thousand samples of a run: how many of
+//! them validate, their average size, and the entropy of their combined
+//! opcode histogram. once its window is full it checks them against
+//! [`Bounds`] after every sample and raises an [`Alert`] when one leaves its
+//! bounds and another when it comes back, so a week-long corpus job whose
+//! unsafe mutators suddenly make everything invalid, or whose samples
+//! collapse to a handful of opcodes, can stop in its first minutes.
+
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::disasm;
+
+/// what one sample contributes to a [`Monitor`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SampleStats {
+    /// whether [`disasm::validate`] accepts the pickle
+    pub valid: bool,
+    /// the pickle's length in bytes
+    pub size: usize,
+    /// how often each opcode occurs, up to the first byte that doesn't decode
+    opcodes: Vec<(u8, u32)>,
+}
+
+impl SampleStats {
+    /// the statistics of `pickle`.
+    pub fn of(pickle: &[u8]) -> Self {
+        let mut counts = [0u32; 256];
+        let mut pos = 0;
+        while let Ok((_, len)) = disasm::read_opcode(&pickle[pos..]) {
+            counts[usize::from(pickle[pos])] += 1;
+            pos += len;
+        }
+        Self {
+            valid: disasm::validate(pickle).is_ok(),
+            size: pickle.len(),
+            opcodes: (0u8..=255)
+                .zip(counts)
+                .filter(|&(_, count)| count > 0)
+                .collect(),
+        }
+    }
+}
+
+/// a statistic a [`Monitor`] watches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    /// the fraction of samples that validate
+    Validity,
+    /// the mean sample size in bytes
+    AverageSize,
+    /// the Shannon entropy, in bits, of the samples' combined opcode
+    /// histogram
+    OpcodeEntropy,
+}
+
+impl Metric {
+    const ALL: [Metric; 3] = [Metric::Validity, Metric::AverageSize, Metric::OpcodeEntropy];
+
+    /// the metric's name in alerts.
+    pub fn name(self) -> &'static str {
+        match self {
+            Metric::Validity => "validity rate",
+            Metric::AverageSize => "average size",
+            Metric::OpcodeEntropy => "opcode entropy",
+        }
+    }
+}
+
+/// the range each metric should stay in; `None` leaves that side open.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Bounds {
+    /// lowest fraction of samples that validate, 0-1
+    pub min_validity: Option<f64>,
+    /// smallest mean sample size in bytes
+    pub min_average_size: Option<f64>,
+    /// largest mean sample size in bytes
+    pub max_average_size: Option<f64>,
+    /// lowest entropy of the combined opcode histogram, in bits
+    pub min_opcode_entropy: Option<f64>,
+}
+
+impl Bounds {
+    /// whether no metric has a bound, so there is nothing to watch.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    fn of(&self, metric: Metric) -> (Option<f64>, Option<f64>) {
+        match metric {
+            Metric::Validity => (self.min_validity, None),
+            Metric::AverageSize => (self.min_average_size, self.max_average_size),
+            Metric::OpcodeEntropy => (self.min_opcode_entropy, None),
+        }
+    }
+}
+
+/// a metric leaving its bounds or coming back.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Alert {
+    /// `metric` is `value` over the last `window` samples, past `bound`
+    Drifted {
+        metric: Metric,
+        value: f64,
+        bound: f64,
+        window: usize,
+    },
+    /// `metric` is back within its bounds at `value`
+    Recovered { metric: Metric, value: f64 },
+}
+
+impl fmt::Display for Alert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Alert::Drifted {
+                metric,
+                value,
+                bound,
+                window,
+            } => {
+                let side = if value < bound { "below" } else { "above" };
+                write!(
+                    f,
+                    "{} {value:.3} over the last {window} samples is {side} its bound of {bound}",
+                    metric.name()
+                )
+            }
+            Alert::Recovered { metric, value } => {
+                write!(f, "{} is back within bounds at {value:.3}", metric.name())
+            }
+        }
+    }
+}
+
+/// rolling statistics over the last `window` samples of a run.
+#[derive(Debug)]
+pub struct Monitor {
+    bounds: Bounds,
+    window: usize,
+    samples: VecDeque<SampleStats>,
+    valid: usize,
+    bytes: usize,
+    opcodes: Vec<u64>,
+    /// the metrics currently out of bounds, in [`Metric::ALL`] order
+    drifted: [bool; 3],
+}
+
+impl Monitor {
+    /// a monitor checking the last `window` samples (at least 1) against
+    /// `bounds`.
+    pub fn new(bounds: Bounds, window: usize) -> Self {
+        Self {
+            bounds,
+            window: window.max(1),
+            samples: VecDeque::new(),
+            valid: 0,
+            bytes: 0,
+            opcodes: vec![0; 256],
+            drifted: [false; 3],
+        }
+    }
+
+    /// add `sample`, dropping the oldest one from a full window, and return
+    /// the metrics that left or came back within their bounds.
+    ///
+    /// nothing is checked until the window has filled once.
+    pub fn record(&mut self, sample: SampleStats) -> Vec<Alert> {
+        self.add(&sample, 1);
+        self.samples.push_back(sample);
+        if self.samples.len() > self.window {
+            let oldest = self.samples.pop_front().expect("the window is not empty");
+            self.add(&oldest, -1);
+        }
+        if self.samples.len() < self.window {
+            return Vec::new();
+        }
+
+        let mut alerts = Vec::new();
+        for (metric, drifted) in Metric::ALL.into_iter().zip(&mut self.drifted) {
+            let value = match metric {
+                Metric::Validity => self.valid as f64 / self.samples.len() as f64,
+                Metric::AverageSize => self.bytes as f64 / self.samples.len() as f64,
+                Metric::OpcodeEntropy => entropy(&self.opcodes),
+            };
+            let (min, max) = self.bounds.of(metric);
+            let bound = min
+                .filter(|&min| value < min)
+                .or(max.filter(|&max| value > max));
+            match (bound, *drifted) {
+                (Some(bound), false) => alerts.push(Alert::Drifted {
+                    metric,
+                    value,
+                    bound,
+                    window: self.window,
+                }),
+                (None, true) => alerts.push(Alert::Recovered { metric, value }),
+                _ => {}
+            }
+            *drifted = bound.is_some();
+        }
+        alerts
+    }
+
+    /// count `sample` in (`sign` 1) or out (`sign` -1) of the totals.
+    fn add(&mut self, sample: &SampleStats, sign: i64) {
+        let apply = |total: &mut usize, amount: usize| {
+            *total = total.wrapping_add_signed(sign as isize * amount as isize);
+        };
+        apply(&mut self.valid, usize::from(sample.valid));
+        apply(&mut self.bytes, sample.size);
+        for &(code, count) in &sample.opcodes {
+            let total = &mut self.opcodes[usize::from(code)];
+            *total = total.wrapping_add_signed(sign * i64::from(count));
+        }
+    }
+}
+
+/// the Shannon entropy of a histogram, in bits.
+fn entropy(counts: &[u64]) -> f64 {
+    let total: u64 = counts.iter().sum();
+    if total == 0 {
+        return 0.0;
+    }
+    counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / total as f64;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Generator, Version};
+
+    #[test]
+    fn sample_stats_count_opcodes_up_to_broken_bytes() {
+        let stats = SampleStats::of(b"\x80\x02K\x01K\x02\x86.");
+        assert!(stats.valid);
+        assert_eq!(stats.size, 8);
+        assert_eq!(stats.opcodes, [(b'.', 1), (b'K', 2), (0x80, 1), (0x86, 1)]);
+
+        let stats = SampleStats::of(b"K\x01J\x01");
+        assert!(!stats.valid);
+        assert_eq!(stats.opcodes, [(b'K', 1)]);
+    }
+
+    #[test]
+    fn alerts_fire_once_when_a_metric_drifts_and_again_when_it_recovers() {
+        let bounds = Bounds {
+            min_validity: Some(0.5),
+            ..Bounds::default()
+        };
+        let mut monitor = Monitor::new(bounds, 4);
+        let good = SampleStats::of(&Generator::new(Version::V2).with_seed(1).generate().unwrap());
+        let bad = SampleStats::of(b"K");
+
+        // nothing is checked before the window fills
+        for _ in 0..3 {
+            assert!(monitor.record(bad.clone()).is_empty());
+        }
+        let alerts = monitor.record(bad.clone());
+        assert!(matches!(
+            alerts[..],
+            [Alert::Drifted { metric: Metric::Validity, value, .. }] if value == 0.0
+        ));
+        assert!(monitor.record(good.clone()).is_empty());
+
+        let alerts = monitor.record(good.clone());
+        assert_eq!(
+            alerts,
+            [Alert::Recovered {
+                metric: Metric::Validity,
+                value: 0.5
+            }]
+        );
+        assert_eq!(
+            alerts[0].to_string(),
+            "validity rate is back within bounds at 0.500"
+        );
+        assert!(monitor.record(good).is_empty());
+    }
+
+    #[test]
+    fn size_and_entropy_bounds_watch_the_whole_window() {
+        let bounds = Bounds {
+            max_average_size: Some(4.0),
+            min_opcode_entropy: Some(1.0),
+            ..Bounds::default()
+        };
+        let mut monitor = Monitor::new(bounds, 2);
+        monitor.record(SampleStats::of(b"N."));
+        let alerts = monitor.record(SampleStats::of(b"N."));
+        // two opcodes, each half the histogram, are exactly one bit
+        assert!(alerts.is_empty(), "{alerts:?}");
+
+        let alerts = monitor.record(SampleStats::of(b"NNNNNNNN0000000."));
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(
+            alerts[0].to_string(),
+            "average size 9.000 over the last 2 samples is above its bound of 4"
+        );
+    }
+}
@@ -18,7 +18,7 @@ use super::Mutator;
 use crate::generator::{EntropySource, GenerationSource};
 
 /// Applies bit flips to integer arguments.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct BitFlipMutator;
 
 impl Mutator for BitFlipMutator {
@@ -26,6 +26,10 @@ impl Mutator for BitFlipMutator {
         "bitflip"
     }
 
+    fn boxed_clone(&self) -> Option<Box<dyn Mutator>> {
+        Some(Box::new(self.clone()))
+    }
+
     fn mutate_int(&self, value: i32, source: &mut GenerationSource, rate: f64) -> Option<i32> {
         if source.gen_f64() > rate {
             return None;
@@ -18,7 +18,7 @@ use super::Mutator;
 use crate::generator::{EntropySource, GenerationSource};
 
 /// Applies boundary value mutations (0, -1, MAX, MIN).
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct BoundaryMutator;
 
 impl Mutator for BoundaryMutator {
@@ -26,6 +26,10 @@ impl Mutator for BoundaryMutator {
         "boundary"
     }
 
+    fn boxed_clone(&self) -> Option<Box<dyn Mutator>> {
+        Some(Box::new(self.clone()))
+    }
+
     fn mutate_int(&self, _value: i32, source: &mut GenerationSource, rate: f64) -> Option<i32> {
         if source.gen_f64() > rate {
             return None;
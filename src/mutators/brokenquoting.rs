@@ -0,0 +1,212 @@
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{EmissionSnapshot, Mutator, PostProcessEmission};
+use crate::generator::{EntropySource, GenerationSource};
+use crate::opcodes::OpcodeKind;
+
+/// Broken quoting mutator: rewrites STRING literals without escaping.
+///
+/// The generator emits protocol 0 STRING arguments as properly escaped,
+/// `repr()`-style literals. This mutator undoes the escaping and wraps the raw
+/// value in single quotes, so quotes, backslashes, and newlines inside the
+/// value end up unescaped. Parsers then see unterminated or mismatched
+/// literals and dangling escapes, which is why the mutator is unsafe-only.
+#[derive(Debug, Clone)]
+pub struct BrokenQuotingMutator;
+
+impl BrokenQuotingMutator {
+    pub fn new(unsafe_mode: bool) -> Self {
+        assert!(
+            unsafe_mode,
+            "BrokenQuotingMutator requires unsafe_mode=true"
+        );
+        Self
+    }
+
+    /// Decode a quoted STRING literal (without its trailing newline) back to
+    /// the raw bytes it stands for.
+    ///
+    /// Handles the escapes the generator produces: `\\`, `\'`, `\"`, `\t`,
+    /// `\n`, `\r`, and `\xNN`.
+    fn unquote(literal: &[u8]) -> Option<Vec<u8>> {
+        let (&quote, rest) = literal.split_first()?;
+        let (&closing, body) = rest.split_last()?;
+        if !matches!(quote, b'\'' | b'"') || closing != quote {
+            return None;
+        }
+
+        let mut raw = Vec::with_capacity(body.len());
+        let mut bytes = body.iter();
+        while let Some(&byte) = bytes.next() {
+            if byte != b'\\' {
+                raw.push(byte);
+                continue;
+            }
+            match *bytes.next()? {
+                b't' => raw.push(b'\t'),
+                b'n' => raw.push(b'\n'),
+                b'r' => raw.push(b'\r'),
+                b'x' => {
+                    let hex = [*bytes.next()?, *bytes.next()?];
+                    let hex = std::str::from_utf8(&hex).ok()?;
+                    raw.push(u8::from_str_radix(hex, 16).ok()?);
+                }
+                escaped => raw.push(escaped),
+            }
+        }
+        Some(raw)
+    }
+}
+
+impl Mutator for BrokenQuotingMutator {
+    fn name(&self) -> &str {
+        "brokenquoting"
+    }
+
+    fn boxed_clone(&self) -> Option<Box<dyn Mutator>> {
+        Some(Box::new(self.clone()))
+    }
+
+    fn is_unsafe(&self) -> bool {
+        true
+    }
+
+    fn post_process(
+        &self,
+        snapshot: &EmissionSnapshot,
+        output: &mut Vec<u8>,
+        source: &mut GenerationSource,
+        rate: f64,
+    ) -> bool {
+        let Some((&opcode, argument)) = snapshot.output_delta.split_first() else {
+            return false;
+        };
+        if opcode != OpcodeKind::String.as_u8() || source.gen_f64() > rate {
+            return false;
+        }
+
+        let Some(literal) = argument.strip_suffix(b"\n") else {
+            return false;
+        };
+        let Some(raw) = Self::unquote(literal) else {
+            return false;
+        };
+
+        let mut rewritten = Vec::with_capacity(raw.len() + 4);
+        rewritten.push(opcode);
+        rewritten.push(b'\'');
+        rewritten.extend_from_slice(&raw);
+        rewritten.extend_from_slice(b"'\n");
+        if rewritten == snapshot.output_delta {
+            return false;
+        }
+
+        output.truncate(snapshot.output_len);
+        output.extend_from_slice(&rewritten);
+        true
+    }
+
+    fn describe_post_process(
+        &self,
+        _snapshot: &EmissionSnapshot,
+        output: &[u8],
+    ) -> Option<PostProcessEmission> {
+        let (&opcode, argument) = output.split_first()?;
+        (opcode == OpcodeKind::String.as_u8()).then(|| PostProcessEmission {
+            opcode: OpcodeKind::String,
+            arg_bytes: Some(argument.to_vec()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::GenerationSource;
+    use crate::mutators::testing::snapshot;
+    use crate::Version;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    #[test]
+    fn test_brokenquoting_is_always_unsafe() {
+        let mutator = BrokenQuotingMutator::new(true);
+        assert_eq!(mutator.name(), "brokenquoting");
+        assert!(mutator.is_unsafe());
+    }
+
+    #[test]
+    #[should_panic(expected = "BrokenQuotingMutator requires unsafe_mode=true")]
+    fn test_brokenquoting_requires_unsafe_mode() {
+        let _ = BrokenQuotingMutator::new(false);
+    }
+
+    #[test]
+    fn test_brokenquoting_unquote_reverses_escaping() {
+        assert_eq!(
+            BrokenQuotingMutator::unquote(b"\"it's\""),
+            Some(b"it's".to_vec())
+        );
+        assert_eq!(
+            BrokenQuotingMutator::unquote(b"'a\\\\b\\'\\n\\xc3\\xa9'"),
+            Some(b"a\\b'\n\xc3\xa9".to_vec())
+        );
+        assert_eq!(BrokenQuotingMutator::unquote(b"'abc"), None);
+        assert_eq!(BrokenQuotingMutator::unquote(b"'\\x4'"), None);
+    }
+
+    #[test]
+    fn test_brokenquoting_rewrites_string_without_escaping() {
+        let mutator = BrokenQuotingMutator::new(true);
+        let mut rng = ChaCha8Rng::seed_from_u64(42);
+        let mut source = GenerationSource::Rand(&mut rng);
+
+        let emitted = b"S'don\\'t\\n'\n";
+        let mut output = emitted.to_vec();
+        assert!(mutator.post_process(
+            &snapshot(Version::V0, emitted),
+            &mut output,
+            &mut source,
+            1.0
+        ));
+        assert_eq!(output, b"S'don't\n'\n");
+
+        let emission = mutator
+            .describe_post_process(&snapshot(Version::V0, emitted), &output)
+            .unwrap();
+        assert_eq!(emission.opcode, OpcodeKind::String);
+        assert_eq!(emission.arg_bytes.as_deref(), Some(&b"'don't\n'\n"[..]));
+    }
+
+    #[test]
+    fn test_brokenquoting_leaves_plain_strings_and_other_opcodes() {
+        let mutator = BrokenQuotingMutator::new(true);
+        let mut rng = ChaCha8Rng::seed_from_u64(42);
+        let mut source = GenerationSource::Rand(&mut rng);
+
+        for emitted in [&b"S'plain'\n"[..], b"Vabc\n", b"N"] {
+            let mut output = emitted.to_vec();
+            assert!(!mutator.post_process(
+                &snapshot(Version::V0, emitted),
+                &mut output,
+                &mut source,
+                1.0
+            ));
+            assert_eq!(output, emitted);
+        }
+    }
+}
@@ -16,16 +16,71 @@
 
 use super::Mutator;
 use crate::generator::{EntropySource, GenerationSource};
+use clap::ValueEnum;
+
+/// Characters [`CharacterMutator`] writes into strings.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum CharacterSet {
+    /// Printable ASCII, `!` through `~`
+    #[default]
+    Printable,
+    /// Any ASCII character, control characters included
+    Ascii,
+    /// Any Unicode scalar value
+    Unicode,
+}
+
+impl CharacterSet {
+    fn draw(self, source: &mut GenerationSource) -> char {
+        match self {
+            CharacterSet::Printable => (source.gen_u8() % 94 + 33) as char,
+            CharacterSet::Ascii => (source.gen_u8() % 128) as char,
+            // surrogates aren't scalar values
+            CharacterSet::Unicode => {
+                char::from_u32(source.gen_u32() % 0x11_0000).unwrap_or('\u{fffd}')
+            }
+        }
+    }
+}
+
+/// Which replacements [`CharacterMutator`] makes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CharacterConfig {
+    /// Characters written into strings; bytes values take any byte
+    pub charset: CharacterSet,
+}
+
+impl CharacterConfig {
+    /// Write characters from `charset` into strings.
+    pub fn with_charset(mut self, charset: CharacterSet) -> Self {
+        self.charset = charset;
+        self
+    }
+}
 
 /// Mutates individual characters/bytes in strings.
-#[derive(Debug)]
-pub struct CharacterMutator;
+#[derive(Debug, Clone, Default)]
+pub struct CharacterMutator {
+    config: CharacterConfig,
+}
+
+impl CharacterMutator {
+    /// Use `config` instead of printable ASCII replacements.
+    pub fn with_config(mut self, config: CharacterConfig) -> Self {
+        self.config = config;
+        self
+    }
+}
 
 impl Mutator for CharacterMutator {
     fn name(&self) -> &str {
         "character"
     }
 
+    fn boxed_clone(&self) -> Option<Box<dyn Mutator>> {
+        Some(Box::new(self.clone()))
+    }
+
     fn mutate_string(
         &self,
         value: String,
@@ -38,8 +93,7 @@ impl Mutator for CharacterMutator {
 
         let mut chars: Vec<char> = value.chars().collect();
         let idx = source.gen_range(0, chars.len());
-        // replace with random printable ASCII
-        chars[idx] = (source.gen_u8() % 94 + 33) as char;
+        chars[idx] = self.config.charset.draw(source);
 
         Some(chars.into_iter().collect())
     }
@@ -71,13 +125,13 @@ mod tests {
 
     #[test]
     fn test_character_name() {
-        let mutator = CharacterMutator;
+        let mutator = CharacterMutator::default();
         assert_eq!(mutator.name(), "character");
     }
 
     #[test]
     fn test_character_mutate_string() {
-        let mutator = CharacterMutator;
+        let mutator = CharacterMutator::default();
         let mut rng = ChaCha8Rng::seed_from_u64(42);
         let mut source = GenerationSource::Rand(&mut rng);
 
@@ -92,7 +146,7 @@ mod tests {
 
     #[test]
     fn test_character_mutate_string_empty() {
-        let mutator = CharacterMutator;
+        let mutator = CharacterMutator::default();
         let mut rng = ChaCha8Rng::seed_from_u64(42);
         let mut source = GenerationSource::Rand(&mut rng);
 
@@ -102,7 +156,7 @@ mod tests {
 
     #[test]
     fn test_character_mutate_bytes() {
-        let mutator = CharacterMutator;
+        let mutator = CharacterMutator::default();
         let mut rng = ChaCha8Rng::seed_from_u64(42);
         let mut source = GenerationSource::Rand(&mut rng);
 
@@ -117,7 +171,7 @@ mod tests {
 
     #[test]
     fn test_character_mutate_bytes_empty() {
-        let mutator = CharacterMutator;
+        let mutator = CharacterMutator::default();
         let mut rng = ChaCha8Rng::seed_from_u64(42);
         let mut source = GenerationSource::Rand(&mut rng);
 
@@ -127,7 +181,7 @@ mod tests {
 
     #[test]
     fn test_character_never_mutates_at_rate_0() {
-        let mutator = CharacterMutator;
+        let mutator = CharacterMutator::default();
         let mut rng = ChaCha8Rng::seed_from_u64(42);
         let mut source = GenerationSource::Rand(&mut rng);
 
@@ -138,4 +192,31 @@ mod tests {
             .mutate_bytes(vec![1, 2, 3], &mut source, 0.0)
             .is_none());
     }
+
+    #[test]
+    fn test_character_sets() {
+        let mut rng = ChaCha8Rng::seed_from_u64(42);
+        let mut source = GenerationSource::Rand(&mut rng);
+        let mutated = |charset, source: &mut GenerationSource| {
+            let mutator = CharacterMutator::default()
+                .with_config(CharacterConfig::default().with_charset(charset));
+            (0..200)
+                .flat_map(|_| {
+                    mutator
+                        .mutate_string("a".into(), source, 1.0)
+                        .unwrap()
+                        .chars()
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<char>>()
+        };
+
+        let printable = mutated(CharacterSet::Printable, &mut source);
+        assert!(printable.iter().all(|c| c.is_ascii_graphic()));
+        let ascii = mutated(CharacterSet::Ascii, &mut source);
+        assert!(ascii.iter().all(char::is_ascii));
+        assert!(ascii.iter().any(|c| c.is_ascii_control()));
+        let unicode = mutated(CharacterSet::Unicode, &mut source);
+        assert!(unicode.iter().any(|c| !c.is_ascii()));
+    }
 }
@@ -0,0 +1,399 @@
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use color_eyre::eyre::eyre;
+use color_eyre::Result;
+
+use super::Mutator;
+use crate::generator::{EntropySource, GenerationSource};
+
+/// Longest token a dictionary may hold, the same limit AFL applies.
+pub const MAX_TOKEN_LEN: usize = 128;
+
+/// Tokens that tend to matter to code handling unpickled data: pickle hooks
+/// and dunder lookups, modules and callables used by gadget chains, path
+/// traversal, format strings, and SQL/NoSQL injection fragments.
+const BUILTIN_TOKENS: &[&str] = &[
+    // pickle protocol hooks and attribute lookups
+    "__reduce__",
+    "__reduce_ex__",
+    "__setstate__",
+    "__getstate__",
+    "__getnewargs__",
+    "__getnewargs_ex__",
+    "__getattr__",
+    "__getattribute__",
+    "__class__",
+    "__dict__",
+    "__init__",
+    "__new__",
+    "__call__",
+    "__import__",
+    "__builtins__",
+    "__globals__",
+    "__code__",
+    "__subclasses__",
+    "__mro__",
+    "__base__",
+    "copyreg",
+    "_reconstructor",
+    "__newobj__",
+    // modules and callables
+    "builtins",
+    "os",
+    "posix",
+    "nt",
+    "system",
+    "popen",
+    "subprocess",
+    "Popen",
+    "check_output",
+    "eval",
+    "exec",
+    "compile",
+    "getattr",
+    "setattr",
+    "open",
+    "importlib",
+    "import_module",
+    "pickle",
+    "loads",
+    "codecs",
+    "decode",
+    "socket",
+    "shutil",
+    "rmtree",
+    "webbrowser",
+    // path traversal
+    "../",
+    "..\\",
+    "../../../../../../etc/passwd",
+    "..\\..\\..\\..\\windows\\win.ini",
+    "/etc/shadow",
+    "file:///etc/passwd",
+    "/proc/self/environ",
+    "\u{0}",
+    // format strings
+    "%s",
+    "%n",
+    "%x",
+    "%(x)s",
+    "{}",
+    "{0}",
+    "{0.__class__}",
+    "{0.__init__.__globals__}",
+    "${jndi:ldap://localhost/a}",
+    "{{7*7}}",
+    // SQL and NoSQL injection
+    "' OR '1'='1",
+    "\" OR \"\"=\"",
+    "'; DROP TABLE t; --",
+    "1; --",
+    "' UNION SELECT NULL --",
+    "{\"$gt\": \"\"}",
+    "{\"$where\": \"1 == 1\"}",
+    "$ne",
+    "' || '1'=='1",
+    // unusual text
+    "\u{feff}",
+    "\u{202e}",
+    "\u{ffff}",
+    "\u{10ffff}",
+];
+
+/// Dictionary mutator: splices interesting tokens into strings and bytes.
+///
+/// Like AFL's dictionaries, it replaces a generated value with a token,
+/// inserts a token at a random position, or appends one. The built-in tokens
+/// can be extended with user-supplied ones, e.g. from an AFL-style dictionary
+/// file via [`DictionaryMutator::parse_afl_dictionary`]. Strings only draw on
+/// tokens that are valid UTF-8. Every result is still a well-formed argument,
+/// so the mutator is safe.
+#[derive(Debug, Clone)]
+pub struct DictionaryMutator {
+    tokens: Vec<Vec<u8>>,
+    text_tokens: Vec<String>,
+}
+
+impl Default for DictionaryMutator {
+    fn default() -> Self {
+        Self::with_tokens(Vec::new())
+    }
+}
+
+impl DictionaryMutator {
+    /// Builtin tokens followed by `extra`.
+    ///
+    /// Tokens longer than [`MAX_TOKEN_LEN`] bytes are ignored, like empty ones
+    /// and duplicates.
+    pub fn with_tokens(extra: Vec<Vec<u8>>) -> Self {
+        let mut tokens: Vec<Vec<u8>> = Vec::new();
+        let builtin = BUILTIN_TOKENS.iter().map(|token| token.as_bytes().to_vec());
+        for token in builtin.chain(extra) {
+            if !token.is_empty() && token.len() <= MAX_TOKEN_LEN && !tokens.contains(&token) {
+                tokens.push(token);
+            }
+        }
+        let text_tokens = tokens
+            .iter()
+            .filter_map(|token| std::str::from_utf8(token).ok().map(str::to_string))
+            .collect();
+        Self {
+            tokens,
+            text_tokens,
+        }
+    }
+
+    /// Returns every token, builtin ones first.
+    pub fn tokens(&self) -> &[Vec<u8>] {
+        &self.tokens
+    }
+
+    /// Parse an AFL-style dictionary.
+    ///
+    /// Each non-empty line that isn't a `#` comment holds one token in double
+    /// quotes, optionally preceded by `name=` (and a `@level` suffix on the
+    /// name, which is ignored). Inside the quotes, `\\`, `\"`, and `\xNN`
+    /// escapes are decoded; other bytes are taken as they are.
+    pub fn parse_afl_dictionary(text: &str) -> Result<Vec<Vec<u8>>> {
+        let mut tokens = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let token =
+                parse_afl_line(line).map_err(|e| eyre!("dictionary line {}: {e}", number + 1))?;
+            if token.len() > MAX_TOKEN_LEN {
+                return Err(eyre!(
+                    "dictionary line {}: token is longer than {MAX_TOKEN_LEN} bytes",
+                    number + 1
+                ));
+            }
+            tokens.push(token);
+        }
+        Ok(tokens)
+    }
+}
+
+/// Decode the quoted token of one AFL dictionary line.
+fn parse_afl_line(line: &str) -> Result<Vec<u8>> {
+    let quoted = match line.find('"') {
+        Some(0) => line,
+        Some(start) => {
+            let name = line[..start].trim_end();
+            let name = name
+                .strip_suffix('=')
+                .ok_or_else(|| eyre!("expected name=\"token\""))?
+                .trim_end();
+            let name = name.split_once('@').map_or(name, |(name, _level)| name);
+            if !name
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+            {
+                return Err(eyre!("invalid token name {name:?}"));
+            }
+            &line[start..]
+        }
+        None => return Err(eyre!("token must be in double quotes")),
+    };
+    let body = quoted[1..]
+        .strip_suffix('"')
+        .ok_or_else(|| eyre!("unterminated token"))?;
+
+    let mut token = Vec::with_capacity(body.len());
+    let mut bytes = body.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte != b'\\' {
+            token.push(byte);
+            continue;
+        }
+        match bytes.next() {
+            Some(b'\\') => token.push(b'\\'),
+            Some(b'"') => token.push(b'"'),
+            Some(b'x') => {
+                let digits = [bytes.next(), bytes.next()];
+                let value = match digits {
+                    [Some(high), Some(low)] => std::str::from_utf8(&[high, low])
+                        .ok()
+                        .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+                    _ => None,
+                };
+                token.push(value.ok_or_else(|| eyre!("\\x needs two hex digits"))?);
+            }
+            _ => return Err(eyre!("unknown escape sequence")),
+        }
+    }
+    Ok(token)
+}
+
+impl Mutator for DictionaryMutator {
+    fn name(&self) -> &str {
+        "dictionary"
+    }
+
+    fn boxed_clone(&self) -> Option<Box<dyn Mutator>> {
+        Some(Box::new(self.clone()))
+    }
+
+    fn mutate_string(
+        &self,
+        value: String,
+        source: &mut GenerationSource,
+        rate: f64,
+    ) -> Option<String> {
+        if source.gen_f64() > rate || self.text_tokens.is_empty() {
+            return None;
+        }
+
+        let token = &self.text_tokens[source.choose_index(self.text_tokens.len())];
+        match source.gen_range(0, 3) {
+            0 => Some(token.clone()),
+            1 => {
+                // insert on a character boundary
+                let boundaries: Vec<usize> = value
+                    .char_indices()
+                    .map(|(idx, _)| idx)
+                    .chain(std::iter::once(value.len()))
+                    .collect();
+                let at = boundaries[source.choose_index(boundaries.len())];
+                let mut result = value;
+                result.insert_str(at, token);
+                Some(result)
+            }
+            _ => Some(value + token),
+        }
+    }
+
+    fn mutate_bytes(
+        &self,
+        value: Vec<u8>,
+        source: &mut GenerationSource,
+        rate: f64,
+    ) -> Option<Vec<u8>> {
+        if source.gen_f64() > rate || self.tokens.is_empty() {
+            return None;
+        }
+
+        let token = &self.tokens[source.choose_index(self.tokens.len())];
+        match source.gen_range(0, 3) {
+            0 => Some(token.clone()),
+            1 => {
+                let at = source.choose_index(value.len() + 1);
+                let mut result = value;
+                result.splice(at..at, token.iter().copied());
+                Some(result)
+            }
+            _ => {
+                let mut result = value;
+                result.extend_from_slice(token);
+                Some(result)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    #[test]
+    fn test_dictionary_name() {
+        assert_eq!(DictionaryMutator::default().name(), "dictionary");
+    }
+
+    #[test]
+    fn test_dictionary_mutations_contain_a_token() {
+        let mutator = DictionaryMutator::with_tokens(vec![b"\xff\xfe".to_vec()]);
+        let mut rng = ChaCha8Rng::seed_from_u64(42);
+        let mut source = GenerationSource::Rand(&mut rng);
+
+        for _ in 0..100 {
+            let mutated = mutator
+                .mutate_string("héllo".to_string(), &mut source, 1.0)
+                .unwrap();
+            assert!(mutator
+                .text_tokens
+                .iter()
+                .any(|t| mutated.contains(t.as_str())));
+
+            let mutated = mutator
+                .mutate_bytes(vec![1, 2, 3], &mut source, 1.0)
+                .unwrap();
+            assert!(mutator
+                .tokens()
+                .iter()
+                .any(|t| mutated.windows(t.len()).any(|w| w == t.as_slice())));
+        }
+        // non-UTF-8 tokens are only used for bytes
+        assert!(mutator.tokens().contains(&b"\xff\xfe".to_vec()));
+        assert_eq!(mutator.text_tokens.len() + 1, mutator.tokens().len());
+    }
+
+    #[test]
+    fn test_dictionary_never_mutates_at_rate_0() {
+        let mutator = DictionaryMutator::default();
+        let mut rng = ChaCha8Rng::seed_from_u64(42);
+        let mut source = GenerationSource::Rand(&mut rng);
+
+        assert!(mutator
+            .mutate_string("test".to_string(), &mut source, 0.0)
+            .is_none());
+        assert!(mutator.mutate_bytes(vec![1], &mut source, 0.0).is_none());
+    }
+
+    #[test]
+    fn test_with_tokens_skips_empty_long_and_duplicate_tokens() {
+        let builtin = DictionaryMutator::default().tokens().len();
+        let mutator = DictionaryMutator::with_tokens(vec![
+            Vec::new(),
+            vec![b'a'; MAX_TOKEN_LEN + 1],
+            b"os".to_vec(),
+            b"custom".to_vec(),
+        ]);
+        assert_eq!(mutator.tokens().len(), builtin + 1);
+    }
+
+    #[test]
+    fn test_parse_afl_dictionary() {
+        let text = "# comment\n\n\"plain\"\nkw_eval=\"eval\"\nkw_hdr@2 = \"\\x00\\xffA\\\\\\\"\"\n";
+        let tokens = DictionaryMutator::parse_afl_dictionary(text).unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                b"plain".to_vec(),
+                b"eval".to_vec(),
+                b"\x00\xffA\\\"".to_vec()
+            ]
+        );
+
+        for bad in [
+            "plain",
+            "\"open",
+            "name \"x\"",
+            "bad name=\"x\"",
+            "\"\\q\"",
+            "\"\\x4\"",
+        ] {
+            let error = DictionaryMutator::parse_afl_dictionary(bad).unwrap_err();
+            assert!(error.to_string().starts_with("dictionary line 1:"), "{bad}");
+        }
+        let long = format!("\"{}\"", "a".repeat(MAX_TOKEN_LEN + 1));
+        assert!(DictionaryMutator::parse_afl_dictionary(&long).is_err());
+    }
+}
@@ -0,0 +1,272 @@
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{EmissionSnapshot, Mutator, PostProcessEmission};
+use crate::disasm::utf8_surrogatepass;
+use crate::generator::{EntropySource, GenerationSource};
+use crate::opcodes::{OpcodeKind, PICKLE_OPCODES};
+use crate::Version;
+
+/// What Python 3 builds from a string-carrying opcode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Family {
+    /// `str` decoded as UTF-8 with `surrogatepass`
+    Unicode,
+    /// Python 2 `str`: decoded with the unpickler's `encoding=`, or `bytes`
+    /// with `encoding="bytes"`
+    ByteString,
+    /// `bytes`
+    Bytes,
+}
+
+/// Length-prefixed string opcodes: family and length prefix width.
+const STRING_OPCODES: [(OpcodeKind, Family, usize); 8] = [
+    (OpcodeKind::ShortBinUnicode, Family::Unicode, 1),
+    (OpcodeKind::BinUnicode, Family::Unicode, 4),
+    (OpcodeKind::BinUnicode8, Family::Unicode, 8),
+    (OpcodeKind::ShortBinString, Family::ByteString, 1),
+    (OpcodeKind::BinString, Family::ByteString, 4),
+    (OpcodeKind::ShortBinBytes, Family::Bytes, 1),
+    (OpcodeKind::BinBytes, Family::Bytes, 4),
+    (OpcodeKind::BinBytes8, Family::Bytes, 8),
+];
+
+/// Encoding confusion mutator: moves string payloads between unicode,
+/// byte-string, and bytes opcodes.
+///
+/// It rewrites e.g. `SHORT_BINUNICODE` as `SHORT_BINSTRING` or `BINBYTES` as
+/// `BINUNICODE`, keeping the payload bytes, so Python 3 unpicklers see
+/// Python 2 `str`s whose decoding depends on `encoding=` (`ASCII`, `latin1`,
+/// `bytes`) and `bytes` where `str` was intended, or the reverse. Only
+/// opcodes of the pickle's protocol are used. In safe mode a payload only
+/// moves into a unicode opcode if it decodes as UTF-8 with `surrogatepass`;
+/// unsafe mode also moves invalid UTF-8 there.
+#[derive(Debug, Clone)]
+pub struct EncodingConfusionMutator {
+    unsafe_mode: bool,
+}
+
+impl EncodingConfusionMutator {
+    pub fn new(unsafe_mode: bool) -> Self {
+        Self { unsafe_mode }
+    }
+
+    /// Split a single string opcode emission into its opcode and payload.
+    fn decode(emission: &[u8]) -> Option<(OpcodeKind, Family, &[u8])> {
+        let (&code, rest) = emission.split_first()?;
+        let opcode = OpcodeKind::try_from(code).ok()?;
+        let &(_, family, width) = STRING_OPCODES
+            .iter()
+            .find(|(string, _, _)| *string == opcode)?;
+        if rest.len() < width {
+            return None;
+        }
+        let (prefix, payload) = rest.split_at(width);
+        let mut len = [0u8; 8];
+        len[..width].copy_from_slice(prefix);
+        let len = u64::from_le_bytes(len);
+        if opcode == OpcodeKind::BinString && len > i32::MAX as u64 {
+            return None;
+        }
+        (len == payload.len() as u64).then_some((opcode, family, payload))
+    }
+
+    /// Opcodes of another family that `version` has and that can carry a
+    /// payload of length `len`.
+    fn replacements(version: Version, family: Family, len: usize) -> Vec<(OpcodeKind, usize)> {
+        let protocol = &PICKLE_OPCODES[&(version as u8)];
+        STRING_OPCODES
+            .iter()
+            .filter(|(opcode, other, width)| {
+                *other != family
+                    && protocol.contains(opcode)
+                    && match (opcode, width) {
+                        (OpcodeKind::BinString, _) => i32::try_from(len).is_ok(),
+                        (_, 1) => len <= u8::MAX as usize,
+                        (_, 4) => u32::try_from(len).is_ok(),
+                        _ => true,
+                    }
+            })
+            .map(|&(opcode, _, width)| (opcode, width))
+            .collect()
+    }
+}
+
+impl Mutator for EncodingConfusionMutator {
+    fn name(&self) -> &str {
+        "encodingconfusion"
+    }
+
+    fn boxed_clone(&self) -> Option<Box<dyn Mutator>> {
+        Some(Box::new(self.clone()))
+    }
+
+    fn is_unsafe(&self) -> bool {
+        self.unsafe_mode
+    }
+
+    fn post_process(
+        &self,
+        snapshot: &EmissionSnapshot,
+        output: &mut Vec<u8>,
+        source: &mut GenerationSource,
+        rate: f64,
+    ) -> bool {
+        let Some(emission) = output.get(snapshot.output_len..) else {
+            return false;
+        };
+        let Some((_, family, payload)) = Self::decode(emission) else {
+            return false;
+        };
+        if source.gen_f64() > rate {
+            return false;
+        }
+
+        let mut replacements = Self::replacements(snapshot.version, family, payload.len());
+        if !self.unsafe_mode && utf8_surrogatepass(payload).is_err() {
+            replacements.retain(|(opcode, _)| {
+                STRING_OPCODES
+                    .iter()
+                    .any(|(other, family, _)| other == opcode && *family != Family::Unicode)
+            });
+        }
+        if replacements.is_empty() {
+            return false;
+        }
+
+        let (opcode, width) = replacements[source.choose_index(replacements.len())];
+        let mut rewritten = Vec::with_capacity(1 + width + payload.len());
+        rewritten.push(opcode.as_u8());
+        rewritten.extend_from_slice(&(payload.len() as u64).to_le_bytes()[..width]);
+        rewritten.extend_from_slice(payload);
+
+        output.truncate(snapshot.output_len);
+        output.extend_from_slice(&rewritten);
+        true
+    }
+
+    fn describe_post_process(
+        &self,
+        _snapshot: &EmissionSnapshot,
+        output: &[u8],
+    ) -> Option<PostProcessEmission> {
+        let (opcode, _, payload) = Self::decode(output)?;
+        Some(PostProcessEmission {
+            opcode,
+            arg_bytes: Some(payload.to_vec()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mutators::testing::{assert_safety_follows_mode, snapshot};
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    /// Every opcode the mutator rewrites `emitted` into, with the payloads.
+    fn rewrites(
+        mutator: &EncodingConfusionMutator,
+        version: Version,
+        emitted: &[u8],
+    ) -> Vec<(OpcodeKind, Vec<u8>)> {
+        let mut rng = ChaCha8Rng::seed_from_u64(42);
+        let mut source = GenerationSource::Rand(&mut rng);
+        let mut seen = Vec::new();
+        for _ in 0..200 {
+            let mut output = emitted.to_vec();
+            if mutator.post_process(&snapshot(version, emitted), &mut output, &mut source, 1.0) {
+                let emission = mutator
+                    .describe_post_process(&snapshot(version, emitted), &output)
+                    .unwrap();
+                let rewrite = (emission.opcode, emission.arg_bytes.unwrap());
+                if !seen.contains(&rewrite) {
+                    seen.push(rewrite);
+                }
+            }
+        }
+        seen
+    }
+
+    #[test]
+    fn test_encodingconfusion_safety_follows_mode() {
+        assert_safety_follows_mode("encodingconfusion", EncodingConfusionMutator::new);
+    }
+
+    #[test]
+    fn test_encodingconfusion_swaps_families_within_the_protocol() {
+        let mutator = EncodingConfusionMutator::new(false);
+
+        // protocol 2 only has BINSTRING/SHORT_BINSTRING next to BINUNICODE
+        let mut opcodes: Vec<_> = rewrites(&mutator, Version::V2, b"X\x02\x00\x00\x00hi")
+            .into_iter()
+            .map(|(opcode, payload)| {
+                assert_eq!(payload, b"hi");
+                opcode
+            })
+            .collect();
+        opcodes.sort_by_key(|opcode| opcode.as_u8());
+        assert_eq!(opcodes, [OpcodeKind::BinString, OpcodeKind::ShortBinString]);
+
+        let opcodes: Vec<_> = rewrites(&mutator, Version::V4, b"\x8c\x02hi")
+            .into_iter()
+            .map(|(opcode, _)| opcode)
+            .collect();
+        assert!(opcodes.contains(&OpcodeKind::ShortBinBytes));
+        assert!(opcodes.contains(&OpcodeKind::BinBytes8));
+        assert!(!opcodes.contains(&OpcodeKind::BinUnicode));
+    }
+
+    #[test]
+    fn test_encodingconfusion_keeps_invalid_utf8_out_of_unicode_in_safe_mode() {
+        let emitted = b"U\x02\xff\xfe";
+        let safe = rewrites(&EncodingConfusionMutator::new(false), Version::V4, emitted);
+        assert!(!safe.is_empty());
+        assert!(safe.iter().all(|(opcode, _)| {
+            !matches!(
+                opcode,
+                OpcodeKind::ShortBinUnicode | OpcodeKind::BinUnicode | OpcodeKind::BinUnicode8
+            )
+        }));
+
+        let unsafe_rewrites = rewrites(&EncodingConfusionMutator::new(true), Version::V4, emitted);
+        assert!(unsafe_rewrites.contains(&(OpcodeKind::ShortBinUnicode, b"\xff\xfe".to_vec())));
+
+        // a UTF-8 encoded surrogate is fine for surrogatepass
+        let surrogate = rewrites(
+            &EncodingConfusionMutator::new(false),
+            Version::V2,
+            b"U\x03\xed\xa0\x80",
+        );
+        assert!(surrogate.contains(&(OpcodeKind::BinUnicode, b"\xed\xa0\x80".to_vec())));
+    }
+
+    #[test]
+    fn test_encodingconfusion_leaves_other_emissions() {
+        let mutator = EncodingConfusionMutator::new(true);
+        assert!(rewrites(&mutator, Version::V4, b"K\x01").is_empty());
+        // length prefix that doesn't match the payload
+        assert!(rewrites(&mutator, Version::V4, b"\x8c\x05hi").is_empty());
+        // protocol 1 has no bytes opcodes, so BINUNICODE can only become a
+        // byte string
+        let v1 = rewrites(&mutator, Version::V1, b"X\x00\x00\x00\x00");
+        assert!(v1.iter().all(|(opcode, _)| matches!(
+            opcode,
+            OpcodeKind::BinString | OpcodeKind::ShortBinString
+        )));
+    }
+}
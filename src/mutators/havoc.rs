@@ -0,0 +1,255 @@
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{EmissionSnapshot, Mutator, PostProcessEmission};
+use crate::disasm::disassemble_fragment;
+use crate::generator::{EntropySource, GenerationSource};
+use crate::opcodes::OpcodeKind;
+
+/// Most edits a single havoc round makes, reached at mutation rate 1.0.
+const MAX_EDITS: usize = 8;
+
+/// How many bytes a havoc round may add to an emission.
+const MAX_GROWTH: usize = 64;
+
+/// Longest block a duplicate edit copies.
+const MAX_DUPLICATE_LEN: usize = 8;
+
+/// Havoc mutator: stacks several random byte edits on the last emission.
+///
+/// Like AFL's havoc stage, each round picks a number of edits that grows
+/// with the mutation rate and applies bit flips, byte inserts and deletes,
+/// block duplication, and byte swaps anywhere in the emission's bytes
+/// (opcode included), never touching earlier output. The result usually no
+/// longer decodes, which is why the mutator is unsafe-only; it trades the
+/// precise mutators' targeting for broad exploration of parser robustness.
+#[derive(Debug, Clone)]
+pub struct HavocMutator;
+
+/// One havoc edit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edit {
+    Flip,
+    Insert,
+    Delete,
+    Duplicate,
+    Swap,
+}
+
+impl Edit {
+    const ALL: [Edit; 5] = [
+        Edit::Flip,
+        Edit::Insert,
+        Edit::Delete,
+        Edit::Duplicate,
+        Edit::Swap,
+    ];
+}
+
+impl HavocMutator {
+    pub fn new(unsafe_mode: bool) -> Self {
+        assert!(unsafe_mode, "HavocMutator requires unsafe_mode=true");
+        Self
+    }
+
+    /// Apply one edit to `bytes`, keeping at least one byte and at most
+    /// `max_len` bytes.
+    fn apply(edit: Edit, bytes: &mut Vec<u8>, max_len: usize, source: &mut GenerationSource) {
+        let len = bytes.len();
+        match edit {
+            Edit::Flip => {
+                let at = source.choose_index(len);
+                bytes[at] ^= 1 << source.gen_range(0, 8);
+            }
+            Edit::Insert if len < max_len => {
+                let at = source.choose_index(len + 1);
+                bytes.insert(at, source.gen_u8());
+            }
+            Edit::Delete if len > 1 => {
+                bytes.remove(source.choose_index(len));
+            }
+            Edit::Duplicate if len < max_len => {
+                let block = source.gen_range(1, MAX_DUPLICATE_LEN.min(len).min(max_len - len) + 1);
+                let start = source.choose_index(len - block + 1);
+                let at = source.choose_index(len + 1);
+                let copy = bytes[start..start + block].to_vec();
+                bytes.splice(at..at, copy);
+            }
+            Edit::Swap if len > 1 => {
+                let (a, b) = (source.choose_index(len), source.choose_index(len));
+                bytes.swap(a, b);
+            }
+            _ => {}
+        }
+    }
+
+    /// Decode `emission` as exactly one opcode and its raw argument bytes.
+    fn single_opcode(emission: &[u8]) -> Option<PostProcessEmission> {
+        match disassemble_fragment(emission).ok()?.as_slice() {
+            [instruction] => {
+                OpcodeKind::try_from(instruction.code)
+                    .ok()
+                    .map(|opcode| PostProcessEmission {
+                        opcode,
+                        arg_bytes: Some(emission[1..].to_vec()),
+                    })
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Mutator for HavocMutator {
+    fn name(&self) -> &str {
+        "havoc"
+    }
+
+    fn boxed_clone(&self) -> Option<Box<dyn Mutator>> {
+        Some(Box::new(self.clone()))
+    }
+
+    fn is_unsafe(&self) -> bool {
+        true
+    }
+
+    fn post_process(
+        &self,
+        snapshot: &EmissionSnapshot,
+        output: &mut Vec<u8>,
+        source: &mut GenerationSource,
+        rate: f64,
+    ) -> bool {
+        if output.len() <= snapshot.output_len || source.gen_f64() > rate {
+            return false;
+        }
+
+        let mut emission = output[snapshot.output_len..].to_vec();
+        let max_len = emission.len() + MAX_GROWTH;
+        let max_edits = 1 + (rate.clamp(0.0, 1.0) * (MAX_EDITS - 1) as f64).round() as usize;
+        for _ in 0..source.gen_range(1, max_edits + 1) {
+            let edit = Edit::ALL[source.choose_index(Edit::ALL.len())];
+            Self::apply(edit, &mut emission, max_len, source);
+        }
+        if emission == output[snapshot.output_len..] {
+            return false;
+        }
+
+        output.truncate(snapshot.output_len);
+        output.extend_from_slice(&emission);
+        true
+    }
+
+    /// Replays the mangled bytes when they still decode as one opcode;
+    /// otherwise keeps simulating the original emission, so later opcodes are
+    /// chosen as if the mangled bytes had their intended effect.
+    fn describe_post_process(
+        &self,
+        snapshot: &EmissionSnapshot,
+        output: &[u8],
+    ) -> Option<PostProcessEmission> {
+        Self::single_opcode(output).or_else(|| Self::single_opcode(&snapshot.output_delta))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mutators::testing::snapshot;
+    use crate::Version;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    #[test]
+    fn test_havoc_is_always_unsafe() {
+        let mutator = HavocMutator::new(true);
+        assert_eq!(mutator.name(), "havoc");
+        assert!(mutator.is_unsafe());
+    }
+
+    #[test]
+    #[should_panic(expected = "HavocMutator requires unsafe_mode=true")]
+    fn test_havoc_requires_unsafe_mode() {
+        let _ = HavocMutator::new(false);
+    }
+
+    #[test]
+    fn test_havoc_edits_stay_within_the_emission() {
+        let mutator = HavocMutator::new(true);
+        let mut rng = ChaCha8Rng::seed_from_u64(42);
+        let mut source = GenerationSource::Rand(&mut rng);
+
+        let prefix = b"\x80\x04N";
+        let emitted = b"\x8c\x05hello";
+        let mut changed = 0;
+        for _ in 0..200 {
+            let mut output = [&prefix[..], &emitted[..]].concat();
+            if mutator.post_process(
+                &EmissionSnapshot {
+                    output_len: prefix.len(),
+                    ..snapshot(Version::V4, emitted)
+                },
+                &mut output,
+                &mut source,
+                1.0,
+            ) {
+                changed += 1;
+                assert_ne!(&output[prefix.len()..], emitted);
+            }
+            assert!(output.starts_with(prefix));
+            assert!(output.len() > prefix.len());
+            assert!(output.len() <= prefix.len() + emitted.len() + MAX_GROWTH);
+        }
+        assert!(
+            changed > 150,
+            "only {changed} of 200 rounds changed the emission"
+        );
+    }
+
+    #[test]
+    fn test_havoc_never_mutates_at_rate_0() {
+        let mutator = HavocMutator::new(true);
+        let mut rng = ChaCha8Rng::seed_from_u64(42);
+        let mut source = GenerationSource::Rand(&mut rng);
+
+        let mut output = b"K\x07".to_vec();
+        assert!(!mutator.post_process(
+            &snapshot(Version::V4, b"K\x07"),
+            &mut output,
+            &mut source,
+            0.0
+        ));
+        assert_eq!(output, b"K\x07");
+    }
+
+    #[test]
+    fn test_havoc_describes_decodable_or_original_emission() {
+        let mutator = HavocMutator::new(true);
+        let original = snapshot(Version::V4, b"K\x07");
+
+        let emission = mutator
+            .describe_post_process(&original, b"J\x01\x00\x00\x00")
+            .unwrap();
+        assert_eq!(emission.opcode, OpcodeKind::BinInt);
+        assert_eq!(
+            emission.arg_bytes.as_deref(),
+            Some(&b"\x01\x00\x00\x00"[..])
+        );
+
+        let emission = mutator.describe_post_process(&original, b"K\x07K").unwrap();
+        assert_eq!(emission.opcode, OpcodeKind::BinInt1);
+        assert_eq!(emission.arg_bytes.as_deref(), Some(&b"\x07"[..]));
+    }
+}
@@ -0,0 +1,474 @@
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{operand_texts, stack_global_operands, EmissionSnapshot, Mutator, PostProcessEmission};
+use crate::generator::{EntropySource, GenerationSource};
+use crate::opcodes::OpcodeKind;
+
+/// Non-ASCII characters that render like ASCII ones.
+const HOMOGLYPHS: [(char, char); 16] = [
+    ('a', '\u{0430}'), // cyrillic a
+    ('a', '\u{03b1}'), // greek alpha
+    ('c', '\u{0441}'), // cyrillic es
+    ('d', '\u{0501}'), // cyrillic komi de
+    ('e', '\u{0435}'), // cyrillic ie
+    ('h', '\u{04bb}'), // cyrillic shha
+    ('i', '\u{0456}'), // cyrillic byelorussian-ukrainian i
+    ('i', '\u{03b9}'), // greek iota
+    ('j', '\u{0458}'), // cyrillic je
+    ('o', '\u{043e}'), // cyrillic o
+    ('o', '\u{03bf}'), // greek omicron
+    ('p', '\u{0440}'), // cyrillic er
+    ('s', '\u{0455}'), // cyrillic dze
+    ('x', '\u{0445}'), // cyrillic ha
+    ('y', '\u{0443}'), // cyrillic u
+    ('_', '\u{02cd}'), // modifier letter low macron
+];
+
+/// Ligatures that NFKC expands back into their ASCII letters.
+const LIGATURES: [([char; 2], char); 3] = [
+    (['f', 'f'], '\u{fb00}'),
+    (['f', 'i'], '\u{fb01}'),
+    (['f', 'l'], '\u{fb02}'),
+];
+
+/// Characters that render as nothing.
+const ZERO_WIDTH: [char; 5] = [
+    '\u{200b}', // zero width space
+    '\u{200c}', // zero width non-joiner
+    '\u{200d}', // zero width joiner
+    '\u{2060}', // word joiner
+    '\u{feff}', // zero width no-break space
+];
+
+/// Homoglyph mutator: respells the module and class names of GLOBAL and INST,
+/// and the module and name pushes directly before a STACK_GLOBAL.
+///
+/// In safe mode it only changes the case of the names (`OS`, `oS.path`), which
+/// keeps them ASCII as `pickletools` requires. In unsafe mode it also swaps in
+/// Cyrillic and Greek look-alike letters, fullwidth, mathematical bold, and
+/// ligature forms that NFKC normalizes back to the name, and zero-width
+/// characters. The unpickler decodes these names as UTF-8 and imports them as
+/// they are, so they catch scanners that compare names after lowercasing or
+/// normalizing them, or that render them for a human to read.
+#[derive(Debug, Clone)]
+pub struct HomoglyphMutator {
+    unsafe_mode: bool,
+}
+
+/// A respelling of one name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Rewrite {
+    Case,
+    // unsafe-only
+    Homoglyph,
+    Compatibility,
+    ZeroWidth,
+}
+
+impl HomoglyphMutator {
+    pub fn new(unsafe_mode: bool) -> Self {
+        Self { unsafe_mode }
+    }
+
+    /// The name-carrying opcode (`GLOBAL` or `INST`) `code` stands for.
+    fn names_opcode(code: u8) -> Option<OpcodeKind> {
+        OpcodeKind::from_u8(code)
+            .filter(|opcode| matches!(opcode, OpcodeKind::Global | OpcodeKind::Inst))
+    }
+
+    /// Respell `name` according to `rewrite`, if it has characters the
+    /// rewrite applies to.
+    fn respell(rewrite: Rewrite, name: &str, source: &mut GenerationSource) -> Option<String> {
+        let chars: Vec<char> = name.chars().collect();
+        let replace = |at: usize, len: usize, with: &str| -> String {
+            let mut respelled: String = chars[..at].iter().collect();
+            respelled.push_str(with);
+            respelled.extend(&chars[at + len..]);
+            respelled
+        };
+
+        match rewrite {
+            Rewrite::Case => {
+                let letters: Vec<usize> = (0..chars.len())
+                    .filter(|&at| chars[at].is_ascii_alphabetic())
+                    .collect();
+                if letters.is_empty() {
+                    return None;
+                }
+                let swap = |c: char| {
+                    if c.is_ascii_lowercase() {
+                        c.to_ascii_uppercase()
+                    } else {
+                        c.to_ascii_lowercase()
+                    }
+                };
+                if source.gen_bool() {
+                    return Some(chars.into_iter().map(swap).collect());
+                }
+                let at = letters[source.choose_index(letters.len())];
+                Some(replace(at, 1, &swap(chars[at]).to_string()))
+            }
+            Rewrite::Homoglyph => {
+                let candidates: Vec<(usize, char)> = chars
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(at, &c)| {
+                        HOMOGLYPHS
+                            .iter()
+                            .filter(move |&&(ascii, _)| ascii == c)
+                            .map(move |&(_, glyph)| (at, glyph))
+                    })
+                    .collect();
+                if candidates.is_empty() {
+                    return None;
+                }
+                let (at, glyph) = candidates[source.choose_index(candidates.len())];
+                Some(replace(at, 1, &glyph.to_string()))
+            }
+            Rewrite::Compatibility => {
+                // (position, characters replaced, replacement)
+                let mut candidates: Vec<(usize, usize, char)> = Vec::new();
+                for (at, &c) in chars.iter().enumerate() {
+                    if c.is_ascii_graphic() {
+                        let fullwidth = char::from_u32(u32::from(c) - 0x21 + 0xff01);
+                        candidates.extend(fullwidth.map(|wide| (at, 1, wide)));
+                    }
+                    let bold = match c {
+                        'A'..='Z' => char::from_u32(u32::from(c) - 'A' as u32 + 0x1d400),
+                        'a'..='z' => char::from_u32(u32::from(c) - 'a' as u32 + 0x1d41a),
+                        _ => None,
+                    };
+                    candidates.extend(bold.map(|bold| (at, 1, bold)));
+                    for &(letters, ligature) in &LIGATURES {
+                        if chars[at..].starts_with(&letters) {
+                            candidates.push((at, letters.len(), ligature));
+                        }
+                    }
+                }
+                if candidates.is_empty() {
+                    return None;
+                }
+                let (at, len, with) = candidates[source.choose_index(candidates.len())];
+                Some(replace(at, len, &with.to_string()))
+            }
+            Rewrite::ZeroWidth => {
+                let at = source.gen_range(0, chars.len() + 1);
+                let with = ZERO_WIDTH[source.choose_index(ZERO_WIDTH.len())];
+                Some(replace(at, 0, &with.to_string()))
+            }
+        }
+    }
+
+    /// Respell either `module` or `name`, with a rewrite the mode allows.
+    fn respell_global(
+        &self,
+        module: &str,
+        name: &str,
+        source: &mut GenerationSource,
+    ) -> Option<(String, String)> {
+        let rewrites: &[Rewrite] = if self.unsafe_mode {
+            &[
+                Rewrite::Case,
+                Rewrite::Homoglyph,
+                Rewrite::Compatibility,
+                Rewrite::ZeroWidth,
+            ]
+        } else {
+            &[Rewrite::Case]
+        };
+        let rewrite = rewrites[source.choose_index(rewrites.len())];
+        if source.gen_bool() {
+            Some((Self::respell(rewrite, module, source)?, name.to_string()))
+        } else {
+            Some((module.to_string(), Self::respell(rewrite, name, source)?))
+        }
+    }
+
+    /// `text` pushed with the text opcode `code`, unless it outgrows the
+    /// opcode's length prefix.
+    fn text_push(code: u8, text: &str) -> Option<Vec<u8>> {
+        let len = text.len();
+        let mut push = vec![code];
+        match OpcodeKind::from_u8(code)? {
+            OpcodeKind::ShortBinUnicode => push.push(u8::try_from(len).ok()?),
+            OpcodeKind::BinUnicode => push.extend(u32::try_from(len).ok()?.to_le_bytes()),
+            OpcodeKind::BinUnicode8 => push.extend((len as u64).to_le_bytes()),
+            _ => return None,
+        }
+        push.extend_from_slice(text.as_bytes());
+        Some(push)
+    }
+
+    /// Respell the module or name pushed before the STACK_GLOBAL `snapshot`
+    /// emitted, moving the STACK_GLOBAL after the new pushes.
+    fn post_process_operands(
+        &self,
+        snapshot: &EmissionSnapshot,
+        operands_len: usize,
+        output: &mut Vec<u8>,
+        source: &mut GenerationSource,
+        rate: f64,
+    ) -> bool {
+        let pushes = &output[operands_len..snapshot.output_len];
+        let Some(operands) = stack_global_operands(pushes) else {
+            return false;
+        };
+        // only rewrite plain ASCII names, as for GLOBAL
+        let texts = operand_texts(&operands);
+        let [module, name] = texts[..] else {
+            return false;
+        };
+        if [module, name]
+            .iter()
+            .any(|text| text.is_empty() || !text.is_ascii())
+        {
+            return false;
+        }
+        if source.gen_f64() > rate {
+            return false;
+        }
+
+        let Some((new_module, new_name)) = self.respell_global(module, name, source) else {
+            return false;
+        };
+        // the first push is the module, the second the name
+        let mut respelled = [new_module, new_name].into_iter();
+        let mut rewritten = Vec::with_capacity(pushes.len() + snapshot.output_delta.len());
+        for (at, instruction) in operands.iter().enumerate() {
+            let end = operands.get(at + 1).map_or(pushes.len(), |next| next.pos);
+            if operand_texts(std::slice::from_ref(instruction)).is_empty() {
+                rewritten.extend_from_slice(&pushes[instruction.pos..end]);
+                continue;
+            }
+            let text = respelled.next().unwrap_or_default();
+            let Some(push) = Self::text_push(instruction.code, &text) else {
+                return false;
+            };
+            rewritten.extend_from_slice(&push);
+        }
+        rewritten.extend_from_slice(&output[snapshot.output_len..]);
+
+        output.truncate(operands_len);
+        output.extend_from_slice(&rewritten);
+        true
+    }
+}
+
+impl Mutator for HomoglyphMutator {
+    fn name(&self) -> &str {
+        "homoglyph"
+    }
+
+    fn boxed_clone(&self) -> Option<Box<dyn Mutator>> {
+        Some(Box::new(self.clone()))
+    }
+
+    fn is_unsafe(&self) -> bool {
+        self.unsafe_mode
+    }
+
+    fn post_process(
+        &self,
+        snapshot: &EmissionSnapshot,
+        output: &mut Vec<u8>,
+        source: &mut GenerationSource,
+        rate: f64,
+    ) -> bool {
+        if let Some(operands_len) = snapshot.operands_len {
+            return self.post_process_operands(snapshot, operands_len, output, source, rate);
+        }
+        let Some((&opcode_byte, argument)) = snapshot.output_delta.split_first() else {
+            return false;
+        };
+        if Self::names_opcode(opcode_byte).is_none() {
+            return false;
+        }
+
+        // only rewrite the plain ASCII "module\nname\n" the generator emits
+        let Some((module, name)) = std::str::from_utf8(argument)
+            .ok()
+            .filter(|text| text.is_ascii())
+            .and_then(|text| text.strip_suffix('\n'))
+            .and_then(|text| text.split_once('\n'))
+            .filter(|(module, name)| {
+                !module.is_empty() && !name.is_empty() && !name.contains('\n')
+            })
+        else {
+            return false;
+        };
+        if source.gen_f64() > rate {
+            return false;
+        }
+
+        let Some((module, name)) = self.respell_global(module, name, source) else {
+            return false;
+        };
+
+        output.truncate(snapshot.output_len);
+        output.push(opcode_byte);
+        output.extend_from_slice(format!("{module}\n{name}\n").as_bytes());
+        true
+    }
+
+    fn describe_post_process(
+        &self,
+        _snapshot: &EmissionSnapshot,
+        output: &[u8],
+    ) -> Option<PostProcessEmission> {
+        let (&opcode_byte, argument) = output.split_first()?;
+        let opcode = Self::names_opcode(opcode_byte)?;
+        Some(PostProcessEmission {
+            opcode,
+            arg_bytes: Some(argument.to_vec()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::GenerationSource;
+    use crate::mutators::testing::{assert_safety_follows_mode, rewritten_arguments, snapshot};
+    use crate::Version;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    /// Rewrite `emitted` many times, returning the distinct `(module, name)`
+    /// pairs seen.
+    fn respelled_names(mutator: &HomoglyphMutator, emitted: &[u8]) -> Vec<(String, String)> {
+        rewritten_arguments(mutator, emitted)
+            .into_iter()
+            .map(|argument| {
+                let text = String::from_utf8(argument).unwrap();
+                let (module, name) = text.strip_suffix('\n').unwrap().split_once('\n').unwrap();
+                (module.to_string(), name.to_string())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_homoglyph_safety_follows_mode() {
+        assert_safety_follows_mode("homoglyph", HomoglyphMutator::new);
+    }
+
+    #[test]
+    fn test_homoglyph_safe_mode_only_changes_case() {
+        let mutator = HomoglyphMutator::new(false);
+        let seen = respelled_names(&mutator, b"cos.path\njoin\n");
+        assert!(seen.len() > 1);
+        for (module, name) in &seen {
+            assert!(module.is_ascii() && name.is_ascii(), "{module:?} {name:?}");
+            assert_eq!(format!("{module}.{name}").to_lowercase(), "os.path.join");
+            assert_ne!((module.as_str(), name.as_str()), ("os.path", "join"));
+        }
+        assert!(seen.contains(&("OS.PATH".into(), "join".into())));
+    }
+
+    #[test]
+    fn test_homoglyph_unsafe_mode_adds_lookalikes() {
+        let mutator = HomoglyphMutator::new(true);
+        let seen = respelled_names(&mutator, b"iposix\nsystem\n");
+        let has = |text: &str| {
+            seen.iter()
+                .any(|(module, name)| module.contains(text) || name.contains(text))
+        };
+        assert!(has("\u{043e}") || has("\u{03bf}"), "no homoglyph o");
+        assert!(has("\u{ff53}"), "no fullwidth s");
+        assert!(has("\u{1d42c}"), "no bold s");
+        assert!(
+            ZERO_WIDTH.iter().any(|&c| has(&c.to_string())),
+            "no zero-width"
+        );
+        assert!(has("POSIX"));
+
+        let seen = respelled_names(&mutator, b"cbuiltins\nfilter\n");
+        assert!(seen.iter().any(|(_, name)| name == "\u{fb01}lter"));
+    }
+
+    #[test]
+    fn test_homoglyph_respells_stack_global_operands() {
+        // protocol 4: "os" and "system", each memoized, then STACK_GLOBAL
+        let pushes = b"\x8c\x02os\x94\x8c\x06system\x94";
+        let emitted = [&pushes[..], b"\x93"].concat();
+        let snapshot = EmissionSnapshot {
+            output_len: pushes.len(),
+            operands_len: Some(0),
+            ..snapshot(Version::V4, b"\x93")
+        };
+        let mut rng = ChaCha8Rng::seed_from_u64(42);
+        let mut source = GenerationSource::Rand(&mut rng);
+
+        for unsafe_mode in [false, true] {
+            let mutator = HomoglyphMutator::new(unsafe_mode);
+            let mut seen = Vec::new();
+            for _ in 0..300 {
+                let mut output = emitted.clone();
+                if !mutator.post_process(&snapshot, &mut output, &mut source, 1.0) {
+                    assert_eq!(output, emitted);
+                    continue;
+                }
+                let (&opcode, pushes) = output.split_last().unwrap();
+                assert_eq!(opcode, OpcodeKind::StackGlobal.as_u8());
+                let operands = stack_global_operands(pushes).expect("operand pushes");
+                let codes: Vec<u8> = operands.iter().map(|operand| operand.code).collect();
+                assert_eq!(codes, b"\x8c\x94\x8c\x94");
+                let texts = operand_texts(&operands);
+                assert_ne!(texts, ["os", "system"]);
+                seen.push((texts[0].to_string(), texts[1].to_string()));
+            }
+            assert!(seen.contains(&("OS".into(), "system".into())));
+            if unsafe_mode {
+                assert!(seen
+                    .iter()
+                    .any(|(module, name)| !module.is_ascii() || !name.is_ascii()));
+            } else {
+                for (module, name) in &seen {
+                    assert_eq!(format!("{module}.{name}").to_lowercase(), "os.system");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_homoglyph_skips_other_opcodes_and_rewritten_names() {
+        let mutator = HomoglyphMutator::new(true);
+        let mut rng = ChaCha8Rng::seed_from_u64(42);
+        let mut source = GenerationSource::Rand(&mut rng);
+
+        for emitted in [
+            &b"S'os'\n"[..],
+            b"\x93",
+            "c\u{043e}s\nsystem\n".as_bytes(),
+            b"cos\n",
+        ] {
+            let mut output = emitted.to_vec();
+            assert!(!mutator.post_process(
+                &snapshot(Version::V0, emitted),
+                &mut output,
+                &mut source,
+                1.0
+            ));
+            assert_eq!(output, emitted);
+        }
+
+        let described =
+            mutator.describe_post_process(&snapshot(Version::V0, b""), b"cOS\nsystem\n");
+        let described = described.unwrap();
+        assert_eq!(described.opcode, OpcodeKind::Global);
+        assert_eq!(described.arg_bytes.as_deref(), Some(&b"OS\nsystem\n"[..]));
+    }
+}
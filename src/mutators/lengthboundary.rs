@@ -0,0 +1,374 @@
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{EmissionSnapshot, Mutator, PostProcessEmission};
+use crate::generator::{EntropySource, GenerationSource};
+use crate::opcodes::OpcodeKind;
+
+/// Lengths on either side of the 1- and 2-byte length prefix limits.
+const BOUNDARY_LENGTHS: [usize; 6] = [0, 1, 255, 256, 65535, 65536];
+
+/// Length-prefixed string and bytes opcodes and their prefix widths.
+const LENGTH_PREFIXED: [(OpcodeKind, usize); 9] = [
+    (OpcodeKind::ShortBinUnicode, 1),
+    (OpcodeKind::ShortBinString, 1),
+    (OpcodeKind::ShortBinBytes, 1),
+    (OpcodeKind::BinUnicode, 4),
+    (OpcodeKind::BinString, 4),
+    (OpcodeKind::BinBytes, 4),
+    (OpcodeKind::BinUnicode8, 8),
+    (OpcodeKind::BinBytes8, 8),
+    (OpcodeKind::ByteArray8, 8),
+];
+
+/// How long [`LengthBoundaryMutator`] makes values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LengthBoundaryConfig {
+    /// Longest value written, in bytes; boundary lengths past it are skipped
+    pub max_len: usize,
+}
+
+impl Default for LengthBoundaryConfig {
+    fn default() -> Self {
+        Self { max_len: 65536 }
+    }
+}
+
+impl LengthBoundaryConfig {
+    /// Write values of at most `max_len` bytes.
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self
+    }
+}
+
+/// Length boundary mutator: resizes strings and bytes to the lengths where
+/// length prefixes change width.
+///
+/// Values become 0, 1, 255, 256, 65535, or 65536 bytes long (UTF-8 bytes for
+/// strings), truncated or padded with one repeated character, so the data
+/// always matches the length the opcode declares. A value too long for its
+/// opcode, such as 256 bytes for `SHORT_BINBYTES`, is dropped with the
+/// emission. In unsafe mode it also rewrites the length prefix of
+/// `SHORT_BINUNICODE`, `BINBYTES`, `BINUNICODE8`, and the other
+/// length-prefixed opcodes to claim more data than follows, including the
+/// lengths around 2^31 and 2^32 that can't be written out.
+#[derive(Debug, Clone)]
+pub struct LengthBoundaryMutator {
+    unsafe_mode: bool,
+    config: LengthBoundaryConfig,
+}
+
+impl LengthBoundaryMutator {
+    pub fn new(unsafe_mode: bool) -> Self {
+        Self {
+            unsafe_mode,
+            config: LengthBoundaryConfig::default(),
+        }
+    }
+
+    /// Use `config` instead of the default length cap.
+    pub fn with_config(mut self, config: LengthBoundaryConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// A boundary length no longer than the cap.
+    fn pick_len(&self, source: &mut GenerationSource) -> usize {
+        let lengths: Vec<usize> = BOUNDARY_LENGTHS
+            .into_iter()
+            .filter(|&len| len <= self.config.max_len)
+            .collect();
+        lengths[source.gen_range(0, lengths.len())]
+    }
+
+    /// The prefix width of the length-prefixed string or bytes opcode `code`.
+    fn prefix_width(code: u8) -> Option<(OpcodeKind, usize)> {
+        let opcode = OpcodeKind::from_u8(code)?;
+        LENGTH_PREFIXED
+            .into_iter()
+            .find(|&(prefixed, _)| prefixed == opcode)
+    }
+
+    /// Lengths a `width`-byte prefix can claim beyond `len`, as little-endian
+    /// bytes.
+    fn lies(width: usize, len: usize) -> Vec<Vec<u8>> {
+        let len = len as u64;
+        let max = match width {
+            1 => u64::from(u8::MAX),
+            4 => u64::from(u32::MAX),
+            _ => u64::MAX,
+        };
+        let claims: Vec<u64> = match width {
+            1 => vec![len + 1, 255],
+            4 => vec![len + 1, (1 << 31) - 1, 1 << 31, u64::from(u32::MAX)],
+            _ => vec![len + 1, (1 << 31) - 1, 1 << 31, 1 << 32, 1 << 63, u64::MAX],
+        };
+        claims
+            .into_iter()
+            .filter(|&claim| claim > len && claim <= max)
+            .map(|claim| claim.to_le_bytes()[..width].to_vec())
+            .collect()
+    }
+}
+
+impl Mutator for LengthBoundaryMutator {
+    fn name(&self) -> &str {
+        "lengthboundary"
+    }
+
+    fn boxed_clone(&self) -> Option<Box<dyn Mutator>> {
+        Some(Box::new(self.clone()))
+    }
+
+    fn mutate_string(
+        &self,
+        value: String,
+        source: &mut GenerationSource,
+        rate: f64,
+    ) -> Option<String> {
+        if source.gen_f64() > rate {
+            return None;
+        }
+        let len = self.pick_len(source);
+        let fill = source.gen_ascii_char();
+
+        let mut cut = len.min(value.len());
+        while !value.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        let mut result = value[..cut].to_string();
+        result.extend(std::iter::repeat_n(fill, len - cut));
+        Some(result)
+    }
+
+    fn mutate_bytes(
+        &self,
+        value: Vec<u8>,
+        source: &mut GenerationSource,
+        rate: f64,
+    ) -> Option<Vec<u8>> {
+        if source.gen_f64() > rate {
+            return None;
+        }
+        let len = self.pick_len(source);
+        let fill = source.gen_u8();
+
+        let mut result = value;
+        result.resize(len, fill);
+        Some(result)
+    }
+
+    fn is_unsafe(&self) -> bool {
+        self.unsafe_mode
+    }
+
+    fn post_process(
+        &self,
+        snapshot: &EmissionSnapshot,
+        output: &mut Vec<u8>,
+        source: &mut GenerationSource,
+        rate: f64,
+    ) -> bool {
+        if !self.unsafe_mode {
+            return false;
+        }
+        // an earlier mutator may have rewritten the emission, so read it back
+        let Some(emission) = output.get(snapshot.output_len..) else {
+            return false;
+        };
+        let Some((_, width)) = emission.first().and_then(|&code| Self::prefix_width(code)) else {
+            return false;
+        };
+        let Some(prefix) = emission.get(1..1 + width) else {
+            return false;
+        };
+        let mut declared = [0u8; 8];
+        declared[..width].copy_from_slice(prefix);
+        let len = emission.len() - 1 - width;
+        // only the generator's own, truthful encoding
+        if u64::from_le_bytes(declared) != len as u64 {
+            return false;
+        }
+        if source.gen_f64() > rate {
+            return false;
+        }
+
+        let lies = Self::lies(width, len);
+        if lies.is_empty() {
+            return false;
+        }
+        let lie = &lies[source.gen_range(0, lies.len())];
+        let start = snapshot.output_len + 1;
+        let Some(prefix) = output.get_mut(start..start + width) else {
+            return false;
+        };
+        prefix.copy_from_slice(lie);
+        true
+    }
+
+    fn describe_post_process(
+        &self,
+        _snapshot: &EmissionSnapshot,
+        output: &[u8],
+    ) -> Option<PostProcessEmission> {
+        let (&code, rest) = output.split_first()?;
+        let (opcode, width) = Self::prefix_width(code)?;
+        // the unpickler reads past the data, but it is what was written
+        Some(PostProcessEmission {
+            opcode,
+            arg_bytes: Some(rest.get(width..)?.to_vec()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::GenerationSource;
+    use crate::mutators::testing::snapshot;
+    use crate::mutators::HavocMutator;
+    use crate::Version;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    #[test]
+    fn test_lengthboundary_name() {
+        assert_eq!(LengthBoundaryMutator::new(false).name(), "lengthboundary");
+    }
+
+    #[test]
+    fn test_lengthboundary_values_take_boundary_lengths() {
+        let mutator = LengthBoundaryMutator::new(false);
+        let mut rng = ChaCha8Rng::seed_from_u64(42);
+        let mut source = GenerationSource::Rand(&mut rng);
+
+        let mut seen = Vec::new();
+        for _ in 0..100 {
+            let bytes = mutator
+                .mutate_bytes(vec![1, 2, 3], &mut source, 1.0)
+                .unwrap();
+            assert!(BOUNDARY_LENGTHS.contains(&bytes.len()));
+            let text = mutator
+                .mutate_string("héllo".into(), &mut source, 1.0)
+                .unwrap();
+            assert!(BOUNDARY_LENGTHS.contains(&text.len()));
+            seen.push(text.len());
+        }
+        assert!(seen.contains(&256) && seen.contains(&65535));
+
+        assert!(mutator.mutate_bytes(vec![1], &mut source, 0.0).is_none());
+    }
+
+    #[test]
+    fn test_lengthboundary_config_caps_lengths() {
+        let config = LengthBoundaryConfig::default().with_max_len(300);
+        let mutator = LengthBoundaryMutator::new(false).with_config(config);
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let mut source = GenerationSource::Rand(&mut rng);
+
+        for _ in 0..50 {
+            let bytes = mutator.mutate_bytes(Vec::new(), &mut source, 1.0).unwrap();
+            assert!(bytes.len() <= 256);
+        }
+    }
+
+    #[test]
+    fn test_lengthboundary_lies_about_prefixes_only_when_unsafe() {
+        let mut rng = ChaCha8Rng::seed_from_u64(3);
+        let mut source = GenerationSource::Rand(&mut rng);
+        // BINBYTES of 3 bytes
+        let emission = b"B\x03\x00\x00\x00abc";
+
+        let mut output = emission.to_vec();
+        let safe = LengthBoundaryMutator::new(false);
+        assert!(!safe.post_process(
+            &snapshot(Version::V4, emission),
+            &mut output,
+            &mut source,
+            1.0
+        ));
+        assert_eq!(output, emission);
+
+        let mutator = LengthBoundaryMutator::new(true);
+        let mut claims = Vec::new();
+        for _ in 0..40 {
+            let mut output = emission.to_vec();
+            assert!(mutator.post_process(
+                &snapshot(Version::V4, emission),
+                &mut output,
+                &mut source,
+                1.0
+            ));
+            assert_eq!(&output[5..], b"abc");
+            claims.push(u32::from_le_bytes(output[1..5].try_into().unwrap()));
+
+            let described =
+                mutator.describe_post_process(&snapshot(Version::V4, emission), &output);
+            let described = described.unwrap();
+            assert_eq!(described.opcode, OpcodeKind::BinBytes);
+            assert_eq!(described.arg_bytes.as_deref(), Some(&b"abc"[..]));
+        }
+        for claim in [4, (1 << 31) - 1, 1 << 31, u32::MAX] {
+            assert!(claims.contains(&claim), "{claim} never claimed");
+        }
+
+        // a SHORT_BINBYTES prefix can only grow to 255
+        let emission = b"C\x02hi";
+        let mut output = emission.to_vec();
+        assert!(mutator.post_process(
+            &snapshot(Version::V4, emission),
+            &mut output,
+            &mut source,
+            1.0
+        ));
+        assert!(matches!(output[1], 3 | 255));
+    }
+
+    #[test]
+    fn test_lengthboundary_reads_the_emission_havoc_left() {
+        let havoc = HavocMutator::new(true);
+        let mutator = LengthBoundaryMutator::new(true);
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        let mut source = GenerationSource::Rand(&mut rng);
+        // a protocol header, then an empty SHORT_BINUNICODE
+        let header = b"\x80\x04";
+        let emission = b"\x8c\x00";
+
+        let mut lied = 0;
+        for _ in 0..500 {
+            let mut output = [&header[..], &emission[..]].concat();
+            let snapshot = EmissionSnapshot {
+                output_len: header.len(),
+                ..snapshot(Version::V4, emission)
+            };
+            havoc.post_process(&snapshot, &mut output, &mut source, 1.0);
+            let before = output.clone();
+            if mutator.post_process(&snapshot, &mut output, &mut source, 1.0) {
+                // only the prefix of a truthful encoding havoc left is rewritten
+                let (_, width) = LengthBoundaryMutator::prefix_width(before[2]).unwrap();
+                assert_eq!(usize::from(before[3]), before.len() - 3 - width);
+                assert_eq!(output.len(), before.len());
+                assert_eq!(output[..3], before[..3]);
+                assert_eq!(output[4..], before[4..]);
+                lied += 1;
+            } else {
+                assert_eq!(output, before);
+            }
+        }
+        assert!(lied > 0);
+    }
+}
@@ -17,15 +17,47 @@
 use super::Mutator;
 use crate::generator::{EntropySource, GenerationSource};
 
+/// Which memo indices [`MemoIndexMutator`] picks in unsafe mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoIndexConfig {
+    /// Largest index drawn in unsafe mode; indices are uniform in
+    /// `0..=max_index`
+    pub max_index: usize,
+}
+
+impl Default for MemoIndexConfig {
+    fn default() -> Self {
+        Self { max_index: 999 }
+    }
+}
+
+impl MemoIndexConfig {
+    /// Draw unsafe indices from `0..=max_index`.
+    pub fn with_max_index(mut self, max_index: usize) -> Self {
+        self.max_index = max_index;
+        self
+    }
+}
+
 /// Mutates memo indices to reference different slots (potentially invalid).
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct MemoIndexMutator {
     unsafe_mode: bool,
+    config: MemoIndexConfig,
 }
 
 impl MemoIndexMutator {
     pub fn new(unsafe_mode: bool) -> Self {
-        Self { unsafe_mode }
+        Self {
+            unsafe_mode,
+            config: MemoIndexConfig::default(),
+        }
+    }
+
+    /// Use `config` instead of the default index range.
+    pub fn with_config(mut self, config: MemoIndexConfig) -> Self {
+        self.config = config;
+        self
     }
 }
 
@@ -34,6 +66,10 @@ impl Mutator for MemoIndexMutator {
         "memoindex"
     }
 
+    fn boxed_clone(&self) -> Option<Box<dyn Mutator>> {
+        Some(Box::new(self.clone()))
+    }
+
     fn mutate_memo_index(
         &self,
         index: usize,
@@ -46,7 +82,7 @@ impl Mutator for MemoIndexMutator {
 
         if self.unsafe_mode {
             // unsafe: any random index
-            Some(source.gen_range(0, 1000))
+            Some(source.gen_range(0, self.config.max_index.saturating_add(1)))
         } else {
             // safe: small perturbations
             match source.gen_range(0, 3) {
@@ -134,4 +170,18 @@ mod tests {
         assert!(safe_mutator.is_unsafe());
         assert!(unsafe_mutator.is_unsafe());
     }
+
+    #[test]
+    fn test_memoindex_config_widens_unsafe_range() {
+        let config = MemoIndexConfig::default().with_max_index(1 << 20);
+        let mutator = MemoIndexMutator::new(true).with_config(config);
+        let mut rng = ChaCha8Rng::seed_from_u64(42);
+        let mut source = GenerationSource::Rand(&mut rng);
+
+        let results: Vec<usize> = (0..50)
+            .filter_map(|_| mutator.mutate_memo_index(0, &mut source, 1.0))
+            .collect();
+        assert!(results.iter().all(|&index| index <= 1 << 20));
+        assert!(results.iter().any(|&index| index >= 1000));
+    }
 }
@@ -0,0 +1,257 @@
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{EmissionSnapshot, Mutator, PostProcessEmission};
+use crate::disasm::disassemble_fragment;
+use crate::generator::{EntropySource, GenerationSource};
+use crate::opcodes::{Opcode, OpcodeKind, PICKLE_OPCODES};
+use crate::Version;
+
+/// How far past the current memo size a forward reference reaches.
+const FORWARD_REACH: usize = 4;
+
+/// Smallest index of a reference that is never PUT. The generator fills
+/// memo slots in order, so it never gets this far.
+const NEVER_INDEX_MIN: usize = 1 << 24;
+
+/// Opcodes that only push one value, with the width of their length prefix
+/// (0 for opcodes whose argument isn't length-prefixed).
+const PURE_PUSHES: [(OpcodeKind, usize); 25] = [
+    (OpcodeKind::Int, 0),
+    (OpcodeKind::BinInt, 0),
+    (OpcodeKind::BinInt1, 0),
+    (OpcodeKind::BinInt2, 0),
+    (OpcodeKind::Long, 0),
+    (OpcodeKind::Long1, 0),
+    (OpcodeKind::Long4, 0),
+    (OpcodeKind::Float, 0),
+    (OpcodeKind::BinFloat, 0),
+    (OpcodeKind::String, 0),
+    (OpcodeKind::Unicode, 0),
+    (OpcodeKind::ShortBinUnicode, 1),
+    (OpcodeKind::BinUnicode, 4),
+    (OpcodeKind::BinUnicode8, 8),
+    (OpcodeKind::ShortBinString, 1),
+    (OpcodeKind::BinString, 4),
+    (OpcodeKind::ShortBinBytes, 1),
+    (OpcodeKind::BinBytes, 4),
+    (OpcodeKind::BinBytes8, 8),
+    (OpcodeKind::None, 0),
+    (OpcodeKind::NewTrue, 0),
+    (OpcodeKind::NewFalse, 0),
+    (OpcodeKind::EmptyList, 0),
+    (OpcodeKind::EmptyTuple, 0),
+    (OpcodeKind::EmptyDict, 0),
+];
+
+/// Memo order mutator: replaces pushed values with GETs of memo slots that
+/// aren't filled yet.
+///
+/// [`MemoIndexMutator`](super::MemoIndexMutator) only perturbs GETs the
+/// generator already emits, which always start from a resolving index. This
+/// mutator rewrites a pure push (an int, float, string, bytes, `None`, bool, or
+/// empty list, tuple, or dict) into a GET, BINGET, or LONG_BINGET of an index that is
+/// either PUT a few opcodes later (a forward reference) or never. Unpicklers
+/// fail on the memo miss, which exercises their error handling; the generator
+/// keeps simulating the original value so the rest of the pickle is built as
+/// usual. Every rewrite is invalid, so the mutator is unsafe-only.
+#[derive(Debug, Clone)]
+pub struct MemoOrderMutator;
+
+impl MemoOrderMutator {
+    pub fn new(unsafe_mode: bool) -> Self {
+        assert!(unsafe_mode, "MemoOrderMutator requires unsafe_mode=true");
+        Self
+    }
+
+    /// The pure push `emission` holds, with its argument in the form the
+    /// stack simulation takes.
+    fn pure_push(emission: &[u8]) -> Option<PostProcessEmission> {
+        let (&code, argument) = emission.split_first()?;
+        let opcode = OpcodeKind::try_from(code).ok()?;
+        let &(_, width) = PURE_PUSHES.iter().find(|(pure, _)| *pure == opcode)?;
+        if disassemble_fragment(emission).ok()?.len() != 1 {
+            return None;
+        }
+        Some(PostProcessEmission {
+            opcode,
+            arg_bytes: (!argument.is_empty()).then(|| argument[width..].to_vec()),
+        })
+    }
+
+    /// Encode a GET of `index` with the narrowest GET opcode of `version`.
+    fn encode_get(version: Version, index: usize) -> Option<Vec<u8>> {
+        let protocol = &PICKLE_OPCODES[&(version as u8)];
+        let get = if !protocol.contains(&OpcodeKind::BinGet) {
+            Opcode::Get(u32::try_from(index).ok()?)
+        } else if let Ok(index) = u8::try_from(index) {
+            Opcode::BinGet(index)
+        } else {
+            Opcode::LongBinGet(u32::try_from(index).ok()?)
+        };
+        Some(get.encode())
+    }
+
+    /// Decode a GET, BINGET, or LONG_BINGET emission into its memo index.
+    fn get_index(emission: &[u8]) -> Option<usize> {
+        match Opcode::decode(emission).ok()? {
+            (Opcode::Get(index) | Opcode::LongBinGet(index), len) if len == emission.len() => {
+                Some(index as usize)
+            }
+            (Opcode::BinGet(index), 2) if emission.len() == 2 => Some(index as usize),
+            _ => None,
+        }
+    }
+}
+
+impl Mutator for MemoOrderMutator {
+    fn name(&self) -> &str {
+        "memoorder"
+    }
+
+    fn boxed_clone(&self) -> Option<Box<dyn Mutator>> {
+        Some(Box::new(self.clone()))
+    }
+
+    fn is_unsafe(&self) -> bool {
+        true
+    }
+
+    fn post_process(
+        &self,
+        snapshot: &EmissionSnapshot,
+        output: &mut Vec<u8>,
+        source: &mut GenerationSource,
+        rate: f64,
+    ) -> bool {
+        let Some(emission) = output.get(snapshot.output_len..) else {
+            return false;
+        };
+        if Self::pure_push(emission).is_none() || source.gen_f64() > rate {
+            return false;
+        }
+
+        // the generator PUTs into slot `memo_size` next, then the ones after
+        let index = if source.gen_bool() {
+            snapshot.memo_size + source.choose_index(FORWARD_REACH)
+        } else {
+            NEVER_INDEX_MIN + source.choose_index(NEVER_INDEX_MIN)
+        };
+        let Some(get) = Self::encode_get(snapshot.version, index) else {
+            return false;
+        };
+
+        output.truncate(snapshot.output_len);
+        output.extend_from_slice(&get);
+        true
+    }
+
+    fn describe_post_process(
+        &self,
+        snapshot: &EmissionSnapshot,
+        output: &[u8],
+    ) -> Option<PostProcessEmission> {
+        Self::get_index(output)?;
+        Self::pure_push(&snapshot.output_delta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mutators::testing::snapshot;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    #[test]
+    fn test_memoorder_is_always_unsafe() {
+        let mutator = MemoOrderMutator::new(true);
+        assert_eq!(mutator.name(), "memoorder");
+        assert!(mutator.is_unsafe());
+    }
+
+    #[test]
+    #[should_panic(expected = "MemoOrderMutator requires unsafe_mode=true")]
+    fn test_memoorder_requires_unsafe_mode() {
+        let _ = MemoOrderMutator::new(false);
+    }
+
+    #[test]
+    fn test_memoorder_references_unfilled_slots() {
+        let mutator = MemoOrderMutator::new(true);
+        let mut rng = ChaCha8Rng::seed_from_u64(42);
+        let mut source = GenerationSource::Rand(&mut rng);
+
+        let (mut forward, mut never) = (false, false);
+        for (version, original, emitted) in [
+            (Version::V0, OpcodeKind::Int, &b"I5\n"[..]),
+            (Version::V4, OpcodeKind::BinInt1, &b"K\x05"[..]),
+        ] {
+            for _ in 0..50 {
+                let snapshot = EmissionSnapshot {
+                    memo_size: 3,
+                    ..snapshot(version, emitted)
+                };
+                let mut output = emitted.to_vec();
+                assert!(mutator.post_process(&snapshot, &mut output, &mut source, 1.0));
+
+                let index = MemoOrderMutator::get_index(&output).unwrap();
+                assert!(index >= 3, "index {index} is already in the memo");
+                forward |= index < 3 + FORWARD_REACH;
+                never |= index >= NEVER_INDEX_MIN;
+                if version == Version::V0 {
+                    assert_eq!(output[0], OpcodeKind::Get.as_u8());
+                }
+
+                // the simulation keeps the original value
+                let emission = mutator.describe_post_process(&snapshot, &output).unwrap();
+                assert_eq!(emission.opcode, original);
+            }
+        }
+        assert!(forward && never);
+    }
+
+    #[test]
+    fn test_memoorder_describes_original_push_argument() {
+        let mutator = MemoOrderMutator::new(true);
+        let emitted = b"\x8c\x02hi";
+        let emission = mutator
+            .describe_post_process(&snapshot(Version::V4, emitted), b"h\x00")
+            .unwrap();
+        assert_eq!(emission.opcode, OpcodeKind::ShortBinUnicode);
+        assert_eq!(emission.arg_bytes.as_deref(), Some(&b"hi"[..]));
+
+        let emission = mutator
+            .describe_post_process(&snapshot(Version::V4, b"N"), b"j\x00\x00\x00\x01")
+            .unwrap();
+        assert_eq!(emission.opcode, OpcodeKind::None);
+        assert_eq!(emission.arg_bytes, None);
+    }
+
+    #[test]
+    fn test_memoorder_leaves_other_opcodes() {
+        let mutator = MemoOrderMutator::new(true);
+        let mut rng = ChaCha8Rng::seed_from_u64(42);
+        let mut source = GenerationSource::Rand(&mut rng);
+
+        for emitted in [&b"0"[..], b"t", b"q\x00", b"K"] {
+            let mut output = emitted.to_vec();
+            let snapshot = snapshot(Version::V4, emitted);
+            assert!(!mutator.post_process(&snapshot, &mut output, &mut source, 1.0));
+            assert_eq!(output, emitted);
+        }
+    }
+}
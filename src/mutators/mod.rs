@@ -19,6 +19,7 @@
 //! Mutators allow injecting controlled variations during pickle generation
 //! to create more diverse test cases for fuzzing and validation.
 
+use crate::disasm::{self, Argument, Instruction};
 use crate::generator::GenerationSource;
 use crate::opcodes::OpcodeKind;
 use clap::ValueEnum;
@@ -27,19 +28,39 @@ use crate::stack::StackObjectRef;
 
 mod bitflip;
 mod boundary;
+mod brokenquoting;
 mod character;
+mod dictionary;
+mod encodingconfusion;
+mod havoc;
+mod homoglyph;
+mod lengthboundary;
 mod memoindex;
+mod memoorder;
 mod offbyone;
+mod registry;
 mod stringlen;
+mod textnumber;
 mod typeconfusion;
+mod whitespace;
 
 pub use bitflip::BitFlipMutator;
 pub use boundary::BoundaryMutator;
-pub use character::CharacterMutator;
-pub use memoindex::MemoIndexMutator;
+pub use brokenquoting::BrokenQuotingMutator;
+pub use character::{CharacterConfig, CharacterMutator, CharacterSet};
+pub use dictionary::{DictionaryMutator, MAX_TOKEN_LEN};
+pub use encodingconfusion::EncodingConfusionMutator;
+pub use havoc::HavocMutator;
+pub use homoglyph::HomoglyphMutator;
+pub use lengthboundary::{LengthBoundaryConfig, LengthBoundaryMutator};
+pub use memoindex::{MemoIndexConfig, MemoIndexMutator};
+pub use memoorder::MemoOrderMutator;
 pub use offbyone::OffByOneMutator;
-pub use stringlen::StringLengthMutator;
+pub use registry::{register_mutator, register_unsafe_mutator, registered_mutators, MutatorChoice};
+pub use stringlen::{StringLengthConfig, StringLengthMutator};
+pub use textnumber::TextNumberMutator;
 pub use typeconfusion::TypeConfusionMutator;
+pub use whitespace::WhitespaceMutator;
 
 /// Snapshot of generator state before an opcode emission.
 ///
@@ -67,6 +88,57 @@ pub struct EmissionSnapshot {
 
     /// Memo indices added by this emission
     pub memo_delta: Vec<usize>,
+
+    /// Output buffer length before the opcodes that pushed this emission's
+    /// operands, when they directly precede it: the module and name pushes
+    /// of a STACK_GLOBAL (see [`stack_global_operands`]). Mutators may rewrite
+    /// those pushes too, as long as they leave the emission after them as it
+    /// is.
+    pub operands_len: Option<usize>,
+}
+
+/// Decode `pushes`, the output between [`EmissionSnapshot::operands_len`] and
+/// the STACK_GLOBAL after it, into its instructions: a SHORT_BINUNICODE,
+/// BINUNICODE, or BINUNICODE8 push of the module, then one of the name, each
+/// optionally followed by a MEMOIZE, BINPUT, or LONG_BINPUT.
+pub(crate) fn stack_global_operands(pushes: &[u8]) -> Option<Vec<Instruction>> {
+    use OpcodeKind as Op;
+
+    let instructions = disasm::disassemble_fragment(pushes).ok()?;
+    let kind = |instruction: &Instruction| OpcodeKind::from_u8(instruction.code);
+    let mut rest = instructions.as_slice();
+    for _ in 0..2 {
+        let [push, tail @ ..] = rest else {
+            return None;
+        };
+        if !matches!(
+            kind(push),
+            Some(Op::ShortBinUnicode | Op::BinUnicode | Op::BinUnicode8)
+        ) {
+            return None;
+        }
+        rest = match tail {
+            [memo, tail @ ..]
+                if matches!(kind(memo), Some(Op::Memoize | Op::BinPut | Op::LongBinPut)) =>
+            {
+                tail
+            }
+            _ => tail,
+        };
+    }
+    rest.is_empty().then_some(instructions)
+}
+
+/// The module and name texts of `operands`, as [`stack_global_operands`]
+/// decodes them.
+pub(crate) fn operand_texts(operands: &[Instruction]) -> Vec<&str> {
+    operands
+        .iter()
+        .filter_map(|instruction| match &instruction.arg {
+            Argument::Str(text) => Some(text.as_str()),
+            _ => None,
+        })
+        .collect()
 }
 
 /// A rewritten emission that can be replayed through normal stack simulation.
@@ -79,6 +151,47 @@ pub struct PostProcessEmission {
     pub arg_bytes: Option<Vec<u8>>,
 }
 
+/// Settings of the configurable builtin mutators.
+///
+/// The defaults are the settings [`MutatorKind::create`] uses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MutatorConfig {
+    /// Settings of the `stringlen` mutator
+    pub stringlen: StringLengthConfig,
+    /// Settings of the `memoindex` mutator
+    pub memoindex: MemoIndexConfig,
+    /// Settings of the `character` mutator
+    pub character: CharacterConfig,
+    /// Settings of the `lengthboundary` mutator
+    pub lengthboundary: LengthBoundaryConfig,
+}
+
+impl MutatorConfig {
+    /// Configure the `stringlen` mutator.
+    pub fn with_stringlen(mut self, config: StringLengthConfig) -> Self {
+        self.stringlen = config;
+        self
+    }
+
+    /// Configure the `memoindex` mutator.
+    pub fn with_memoindex(mut self, config: MemoIndexConfig) -> Self {
+        self.memoindex = config;
+        self
+    }
+
+    /// Configure the `character` mutator.
+    pub fn with_character(mut self, config: CharacterConfig) -> Self {
+        self.character = config;
+        self
+    }
+
+    /// Configure the `lengthboundary` mutator.
+    pub fn with_lengthboundary(mut self, config: LengthBoundaryConfig) -> Self {
+        self.lengthboundary = config;
+        self
+    }
+}
+
 /// Available mutator types.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
 pub enum MutatorKind {
@@ -98,6 +211,24 @@ pub enum MutatorKind {
     Memoindex,
     /// Type confusion: inject non-string values before opcodes expecting strings
     Typeconfusion,
+    /// Rewrite STRING literals without escaping quotes, backslashes, or newlines
+    Brokenquoting,
+    /// Respell INT/GET/PUT text arguments (whitespace, signs, zeros, large indices)
+    Textnumber,
+    /// Splice interesting tokens (dunder names, callables, injection strings) into strings/bytes
+    Dictionary,
+    /// Stack random byte edits (flip, insert, delete, duplicate, swap) on an emission
+    Havoc,
+    /// Move string payloads between unicode, byte-string, and bytes opcodes
+    Encodingconfusion,
+    /// Replace pushed values with GETs of memo slots that are PUT later or never
+    Memoorder,
+    /// Resize strings/bytes to length prefix boundaries (0, 1, 255, 256, 65535, 65536)
+    Lengthboundary,
+    /// Respell GLOBAL/INST module and class names (case; homoglyphs, NFKC forms, zero-width characters when unsafe)
+    Homoglyph,
+    /// Pad text-argument lines with spaces and \r\n terminators (drop terminators, split STRING quotes when unsafe)
+    Whitespace,
 }
 
 impl MutatorKind {
@@ -112,12 +243,21 @@ impl MutatorKind {
             MutatorKind::Offbyone,
             MutatorKind::Stringlen,
             MutatorKind::Character,
+            MutatorKind::Textnumber,
+            MutatorKind::Dictionary,
+            MutatorKind::Encodingconfusion,
+            MutatorKind::Lengthboundary,
+            MutatorKind::Homoglyph,
+            MutatorKind::Whitespace,
         ];
 
         // only include unsafe-only mutators when explicitly enabled
         if unsafe_mutations {
             mutators.push(MutatorKind::Memoindex);
             mutators.push(MutatorKind::Typeconfusion);
+            mutators.push(MutatorKind::Brokenquoting);
+            mutators.push(MutatorKind::Havoc);
+            mutators.push(MutatorKind::Memoorder);
         }
 
         mutators
@@ -125,11 +265,24 @@ impl MutatorKind {
 
     /// Returns whether this mutator requires `--unsafe-mutations`.
     pub fn requires_unsafe_mutations(&self) -> bool {
-        matches!(self, MutatorKind::Memoindex | MutatorKind::Typeconfusion)
+        matches!(
+            self,
+            MutatorKind::Memoindex
+                | MutatorKind::Typeconfusion
+                | MutatorKind::Brokenquoting
+                | MutatorKind::Havoc
+                | MutatorKind::Memoorder
+        )
     }
 
     /// Create a boxed mutator instance from this kind.
     pub fn create(&self, unsafe_mode: bool) -> Box<dyn Mutator> {
+        self.create_with(unsafe_mode, &MutatorConfig::default())
+    }
+
+    /// Create a boxed mutator instance from this kind, with the settings
+    /// `config` gives it.
+    pub fn create_with(&self, unsafe_mode: bool, config: &MutatorConfig) -> Box<dyn Mutator> {
         match self {
             MutatorKind::All => {
                 panic!("MutatorKind::All should be expanded before calling create()")
@@ -137,10 +290,27 @@ impl MutatorKind {
             MutatorKind::Bitflip => Box::new(BitFlipMutator),
             MutatorKind::Boundary => Box::new(BoundaryMutator),
             MutatorKind::Offbyone => Box::new(OffByOneMutator),
-            MutatorKind::Stringlen => Box::new(StringLengthMutator),
-            MutatorKind::Character => Box::new(CharacterMutator),
-            MutatorKind::Memoindex => Box::new(MemoIndexMutator::new(unsafe_mode)),
+            MutatorKind::Stringlen => {
+                Box::new(StringLengthMutator::default().with_config(config.stringlen))
+            }
+            MutatorKind::Character => {
+                Box::new(CharacterMutator::default().with_config(config.character))
+            }
+            MutatorKind::Memoindex => {
+                Box::new(MemoIndexMutator::new(unsafe_mode).with_config(config.memoindex))
+            }
             MutatorKind::Typeconfusion => Box::new(TypeConfusionMutator::new(unsafe_mode)),
+            MutatorKind::Brokenquoting => Box::new(BrokenQuotingMutator::new(unsafe_mode)),
+            MutatorKind::Textnumber => Box::new(TextNumberMutator::new(unsafe_mode)),
+            MutatorKind::Dictionary => Box::new(DictionaryMutator::default()),
+            MutatorKind::Havoc => Box::new(HavocMutator::new(unsafe_mode)),
+            MutatorKind::Encodingconfusion => Box::new(EncodingConfusionMutator::new(unsafe_mode)),
+            MutatorKind::Memoorder => Box::new(MemoOrderMutator::new(unsafe_mode)),
+            MutatorKind::Lengthboundary => {
+                Box::new(LengthBoundaryMutator::new(unsafe_mode).with_config(config.lengthboundary))
+            }
+            MutatorKind::Homoglyph => Box::new(HomoglyphMutator::new(unsafe_mode)),
+            MutatorKind::Whitespace => Box::new(WhitespaceMutator::new(unsafe_mode)),
         }
     }
 }
@@ -150,10 +320,23 @@ impl MutatorKind {
 /// Mutators can modify opcode arguments during generation to create
 /// variations in the output. Each mutator implements a specific
 /// mutation strategy (e.g., bit flips, boundary values, etc.).
+///
+/// Mutators that can be copied opt in with [`Mutator::boxed_clone`], so
+/// generators can share a configured set.
 pub trait Mutator: Send + Sync + std::fmt::Debug {
     /// Returns the name of this mutator.
     fn name(&self) -> &str;
 
+    /// Returns a copy of this mutator, configuration included, in a new box.
+    ///
+    /// `None`, the default, means the mutator can't be copied, and callers
+    /// sharing a mutator set rebuild it from its [`MutatorKind`] or
+    /// registered name instead. A `Clone` mutator implements this as
+    /// `Some(Box::new(self.clone()))`.
+    fn boxed_clone(&self) -> Option<Box<dyn Mutator>> {
+        None
+    }
+
     /// Attempts to mutate an integer argument.
     ///
     /// Returns `Some(mutated_value)` if mutation was applied,
@@ -242,9 +425,63 @@ pub trait Mutator: Send + Sync + std::fmt::Debug {
     }
 }
 
+/// Helpers shared by the mutators' tests.
+#[cfg(test)]
+mod testing {
+    use super::{EmissionSnapshot, Mutator};
+    use crate::generator::GenerationSource;
+    use crate::Version;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    /// A snapshot of an emission, `output_delta`, at the start of a
+    /// `version` pickle, with an empty stack and memo.
+    pub(super) fn snapshot(version: Version, output_delta: &[u8]) -> EmissionSnapshot {
+        EmissionSnapshot {
+            version,
+            stack_depth: 0,
+            output_len: 0,
+            memo_size: 0,
+            stack_delta: Vec::new(),
+            output_delta: output_delta.to_vec(),
+            memo_delta: Vec::new(),
+            operands_len: None,
+        }
+    }
+
+    /// Rewrite the protocol 0 emission `emitted` many times, returning the
+    /// distinct arguments seen; every round must rewrite it and keep its
+    /// opcode.
+    pub(super) fn rewritten_arguments(mutator: &dyn Mutator, emitted: &[u8]) -> Vec<Vec<u8>> {
+        let mut rng = ChaCha8Rng::seed_from_u64(42);
+        let mut source = GenerationSource::Rand(&mut rng);
+        let mut seen = Vec::new();
+        for _ in 0..300 {
+            let mut output = emitted.to_vec();
+            let snapshot = snapshot(Version::V0, emitted);
+            assert!(mutator.post_process(&snapshot, &mut output, &mut source, 1.0));
+            assert_eq!(output[0], emitted[0]);
+            let argument = output[1..].to_vec();
+            if !seen.contains(&argument) {
+                seen.push(argument);
+            }
+        }
+        seen
+    }
+
+    /// Check that `new(unsafe_mode)` builds the mutator `name`, unsafe
+    /// exactly in unsafe mode.
+    pub(super) fn assert_safety_follows_mode<M: Mutator>(name: &str, new: fn(bool) -> M) {
+        assert_eq!(new(false).name(), name);
+        assert!(!new(false).is_unsafe());
+        assert!(new(true).is_unsafe());
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::MutatorKind;
+    use super::{DictionaryMutator, Mutator, MutatorKind};
+    use crate::{Generator, Version};
 
     #[test]
     fn test_all_mutators_excludes_unsafe_only_mutators_without_flag() {
@@ -255,6 +492,11 @@ mod tests {
         let unsafe_set = MutatorKind::all_mutators(true);
         assert!(unsafe_set.contains(&MutatorKind::Memoindex));
         assert!(unsafe_set.contains(&MutatorKind::Typeconfusion));
+        assert!(unsafe_set.contains(&MutatorKind::Brokenquoting));
+        assert!(!safe.contains(&MutatorKind::Havoc));
+        assert!(unsafe_set.contains(&MutatorKind::Havoc));
+        assert!(!safe.contains(&MutatorKind::Memoorder));
+        assert!(unsafe_set.contains(&MutatorKind::Memoorder));
     }
 
     #[test]
@@ -263,4 +505,44 @@ mod tests {
         assert!(MutatorKind::Typeconfusion.requires_unsafe_mutations());
         assert!(!MutatorKind::Bitflip.requires_unsafe_mutations());
     }
+
+    #[test]
+    fn test_boxed_clone_keeps_configuration() {
+        let configured: Box<dyn Mutator> =
+            Box::new(DictionaryMutator::with_tokens(vec![b"zz_custom".to_vec()]));
+        let copy = configured.boxed_clone().unwrap();
+        assert_eq!(copy.name(), "dictionary");
+        assert_eq!(format!("{copy:?}"), format!("{configured:?}"));
+
+        let generator = |mutators| {
+            Generator::new(Version::V4)
+                .with_mutators(mutators)
+                .with_mutation_rate(1.0)
+                .with_seed(3)
+        };
+        let mut original = generator(vec![configured]);
+        let mut cloned = generator(vec![copy]);
+        assert_eq!(original.generate().unwrap(), cloned.generate().unwrap());
+    }
+
+    #[test]
+    fn test_boxed_clone_is_opt_in() {
+        #[derive(Debug)]
+        struct Uncopyable;
+
+        impl Mutator for Uncopyable {
+            fn name(&self) -> &str {
+                "uncopyable"
+            }
+        }
+
+        assert!(Uncopyable.boxed_clone().is_none());
+        for kind in MutatorKind::all_mutators(true) {
+            let mutator = kind.create(true);
+            let copy = mutator
+                .boxed_clone()
+                .expect("built-in mutators are copyable");
+            assert_eq!(copy.name(), mutator.name());
+        }
+    }
 }
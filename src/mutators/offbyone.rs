@@ -18,7 +18,7 @@ use super::Mutator;
 use crate::generator::{EntropySource, GenerationSource};
 
 /// Applies off-by-one mutations (value ± 1) to scalar values.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct OffByOneMutator;
 
 impl Mutator for OffByOneMutator {
@@ -26,6 +26,10 @@ impl Mutator for OffByOneMutator {
         "offbyone"
     }
 
+    fn boxed_clone(&self) -> Option<Box<dyn Mutator>> {
+        Some(Box::new(self.clone()))
+    }
+
     fn mutate_int(&self, value: i32, source: &mut GenerationSource, rate: f64) -> Option<i32> {
         if source.gen_f64() > rate {
             return None;
@@ -0,0 +1,260 @@
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Runtime registry for mutators defined outside this crate.
+//!
+//! [`MutatorKind`] only covers the builtin mutators. Downstream crates can
+//! register their own under a name with [`register_mutator`], after which the
+//! name is accepted everywhere a builtin one is: `--mutators`, JSON
+//! [`GeneratorConfig`](crate::GeneratorConfig)s, and [`FuzzConfig`](crate::FuzzConfig)
+//! inputs.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, PoisonError, RwLock};
+
+use clap::ValueEnum;
+
+use super::{Mutator, MutatorConfig, MutatorKind};
+
+/// Builds a mutator instance; the argument is the unsafe-mutations flag.
+type Factory = Arc<dyn Fn(bool) -> Box<dyn Mutator> + Send + Sync>;
+
+#[derive(Clone)]
+struct Registration {
+    factory: Factory,
+    requires_unsafe: bool,
+}
+
+static REGISTRY: RwLock<BTreeMap<&'static str, Registration>> = RwLock::new(BTreeMap::new());
+
+/// Register a mutator under `name`.
+///
+/// The factory is called with the unsafe-mutations flag each time a
+/// generator is configured with the mutator. Names must be non-empty,
+/// lowercase ASCII letters, digits, `-` or `_`, must not start with `-`, and
+/// must not clash with a builtin or already registered mutator.
+pub fn register_mutator<F>(name: &'static str, factory: F) -> Result<(), String>
+where
+    F: Fn(bool) -> Box<dyn Mutator> + Send + Sync + 'static,
+{
+    register(name, Arc::new(factory), false)
+}
+
+/// Register a mutator that, like `memoindex`, is only available with unsafe
+/// mutations enabled.
+pub fn register_unsafe_mutator<F>(name: &'static str, factory: F) -> Result<(), String>
+where
+    F: Fn(bool) -> Box<dyn Mutator> + Send + Sync + 'static,
+{
+    register(name, Arc::new(factory), true)
+}
+
+fn register(name: &'static str, factory: Factory, requires_unsafe: bool) -> Result<(), String> {
+    let valid_name = !name.is_empty()
+        && !name.starts_with('-')
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-' || b == b'_');
+    if !valid_name {
+        return Err(format!("invalid mutator name {name:?}"));
+    }
+    if MutatorKind::from_str(name, true).is_ok() {
+        return Err(format!(
+            "mutator name {name:?} is reserved by a builtin mutator"
+        ));
+    }
+
+    let mut registry = REGISTRY.write().unwrap_or_else(PoisonError::into_inner);
+    if registry.contains_key(name) {
+        return Err(format!("mutator {name:?} is already registered"));
+    }
+    registry.insert(
+        name,
+        Registration {
+            factory,
+            requires_unsafe,
+        },
+    );
+    Ok(())
+}
+
+fn registration(name: &str) -> Option<Registration> {
+    REGISTRY
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(name)
+        .cloned()
+}
+
+/// Returns the names of all registered mutators, sorted.
+///
+/// If `unsafe_mutations` is false, excludes mutators registered with
+/// [`register_unsafe_mutator`].
+pub fn registered_mutators(unsafe_mutations: bool) -> Vec<&'static str> {
+    REGISTRY
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter()
+        .filter(|(_, registration)| unsafe_mutations || !registration.requires_unsafe)
+        .map(|(name, _)| *name)
+        .collect()
+}
+
+/// A mutator selected by name: a builtin kind or a registered mutator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MutatorChoice {
+    /// One of the builtin mutators, or `all`
+    Builtin(MutatorKind),
+    /// A mutator added with [`register_mutator`] or [`register_unsafe_mutator`]
+    Registered(&'static str),
+}
+
+impl MutatorChoice {
+    /// Expand `all` into every builtin and registered mutator allowed by the
+    /// current safety mode, keeping the other choices as given.
+    pub fn expand(choices: &[MutatorChoice], unsafe_mutations: bool) -> Vec<MutatorChoice> {
+        if !choices.contains(&MutatorChoice::Builtin(MutatorKind::All)) {
+            return choices.to_vec();
+        }
+        MutatorKind::all_mutators(unsafe_mutations)
+            .into_iter()
+            .map(MutatorChoice::Builtin)
+            .chain(
+                registered_mutators(unsafe_mutations)
+                    .into_iter()
+                    .map(MutatorChoice::Registered),
+            )
+            .collect()
+    }
+
+    /// Returns whether this mutator requires unsafe mutations.
+    pub fn requires_unsafe_mutations(&self) -> bool {
+        match self {
+            MutatorChoice::Builtin(kind) => kind.requires_unsafe_mutations(),
+            MutatorChoice::Registered(name) => {
+                registration(name).is_some_and(|registration| registration.requires_unsafe)
+            }
+        }
+    }
+
+    /// Create a boxed mutator instance from this choice.
+    ///
+    /// Like [`MutatorKind::create`], `all` must be expanded first.
+    pub fn create(&self, unsafe_mode: bool) -> Box<dyn Mutator> {
+        self.create_with(unsafe_mode, &MutatorConfig::default())
+    }
+
+    /// Create a boxed mutator instance from this choice, configuring builtin
+    /// mutators from `config`.
+    ///
+    /// Registered mutators come from their factories as they are.
+    pub fn create_with(&self, unsafe_mode: bool, config: &MutatorConfig) -> Box<dyn Mutator> {
+        match self {
+            MutatorChoice::Builtin(kind) => kind.create_with(unsafe_mode, config),
+            MutatorChoice::Registered(name) => {
+                let registration = registration(name)
+                    .unwrap_or_else(|| panic!("mutator {name:?} is not registered"));
+                (registration.factory)(unsafe_mode)
+            }
+        }
+    }
+}
+
+impl FromStr for MutatorChoice {
+    type Err = String;
+
+    /// Builtin names match case-insensitively; registered names exactly.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        if let Ok(kind) = MutatorKind::from_str(name, true) {
+            return Ok(MutatorChoice::Builtin(kind));
+        }
+        let registry = REGISTRY.read().unwrap_or_else(PoisonError::into_inner);
+        if let Some((registered, _)) = registry.get_key_value(name) {
+            return Ok(MutatorChoice::Registered(registered));
+        }
+        Err(format!("unknown mutator {name:?}"))
+    }
+}
+
+impl fmt::Display for MutatorChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MutatorChoice::Builtin(kind) => match kind.to_possible_value() {
+                Some(value) => f.write_str(value.get_name()),
+                None => write!(f, "{kind:?}"),
+            },
+            MutatorChoice::Registered(name) => f.write_str(name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mutators::BitFlipMutator;
+
+    #[derive(Debug)]
+    struct Renamed(&'static str);
+
+    impl Mutator for Renamed {
+        fn name(&self) -> &str {
+            self.0
+        }
+    }
+
+    // safe registrations would show up in every `all` expansion made by the
+    // other unit tests, so they are covered by the integration tests instead
+    #[test]
+    fn test_unsafe_registrations_resolve_by_name() {
+        register_unsafe_mutator("registry-test-unsafe", |_| Box::new(Renamed("unsafe"))).unwrap();
+
+        let choice: MutatorChoice = "registry-test-unsafe".parse().unwrap();
+        assert_eq!(choice, MutatorChoice::Registered("registry-test-unsafe"));
+        assert!(choice.requires_unsafe_mutations());
+        assert_eq!(choice.create(true).name(), "unsafe");
+        assert_eq!(choice.to_string(), "registry-test-unsafe");
+
+        assert!(!registered_mutators(false).contains(&"registry-test-unsafe"));
+        assert!(registered_mutators(true).contains(&"registry-test-unsafe"));
+
+        let all = MutatorChoice::Builtin(MutatorKind::All);
+        assert!(!MutatorChoice::expand(&[all], false).contains(&choice));
+        assert!(MutatorChoice::expand(&[all], true).contains(&choice));
+    }
+
+    #[test]
+    fn test_builtin_names_parse_case_insensitively() {
+        let choice: MutatorChoice = "BitFlip".parse().unwrap();
+        assert_eq!(choice, MutatorChoice::Builtin(MutatorKind::Bitflip));
+        assert_eq!(choice.to_string(), "bitflip");
+        assert_eq!(choice.create(false).name(), BitFlipMutator.name());
+        assert!("registry-test-missing".parse::<MutatorChoice>().is_err());
+    }
+
+    #[test]
+    fn test_register_rejects_bad_and_duplicate_names() {
+        let factory = |_: bool| -> Box<dyn Mutator> { Box::new(BitFlipMutator) };
+        assert!(register_mutator("bitflip", factory).is_err());
+        assert!(register_mutator("all", factory).is_err());
+        assert!(register_mutator("", factory).is_err());
+        assert!(register_mutator("-dash", factory).is_err());
+        assert!(register_mutator("Upper", factory).is_err());
+        register_unsafe_mutator("registry-test-dup", factory).unwrap();
+        assert!(register_mutator("registry-test-dup", factory).is_err());
+    }
+}
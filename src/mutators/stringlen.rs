@@ -17,15 +17,76 @@
 use super::Mutator;
 use crate::generator::{EntropySource, GenerationSource};
 
+/// How far [`StringLengthMutator`] changes a length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StringLengthConfig {
+    /// Most characters or bytes appended when extending a value (at least 1)
+    pub max_extend: usize,
+    /// Whether truncation may leave a value empty
+    pub allow_empty: bool,
+}
+
+impl Default for StringLengthConfig {
+    fn default() -> Self {
+        Self {
+            max_extend: 9,
+            allow_empty: true,
+        }
+    }
+}
+
+impl StringLengthConfig {
+    /// Append at most `max_extend` characters or bytes (raised to 1).
+    pub fn with_max_extend(mut self, max_extend: usize) -> Self {
+        self.max_extend = max_extend.max(1);
+        self
+    }
+
+    /// Let truncation leave a value empty, or keep at least one element.
+    pub fn with_allow_empty(mut self, allow_empty: bool) -> Self {
+        self.allow_empty = allow_empty;
+        self
+    }
+
+    /// The length a value of `len` elements is truncated to, or `None` when
+    /// it is too short to truncate.
+    fn truncated_len(&self, len: usize, source: &mut GenerationSource) -> Option<usize> {
+        let min = usize::from(!self.allow_empty);
+        if len <= min {
+            return None;
+        }
+        Some(source.gen_range(min, len))
+    }
+
+    /// How many elements to append.
+    fn extra_len(&self, source: &mut GenerationSource) -> usize {
+        source.gen_range(1, self.max_extend.max(1) + 1)
+    }
+}
+
 /// Mutates string lengths (truncate or extend).
-#[derive(Debug)]
-pub struct StringLengthMutator;
+#[derive(Debug, Clone, Default)]
+pub struct StringLengthMutator {
+    config: StringLengthConfig,
+}
+
+impl StringLengthMutator {
+    /// Use `config` instead of the default lengths.
+    pub fn with_config(mut self, config: StringLengthConfig) -> Self {
+        self.config = config;
+        self
+    }
+}
 
 impl Mutator for StringLengthMutator {
     fn name(&self) -> &str {
         "stringlen"
     }
 
+    fn boxed_clone(&self) -> Option<Box<dyn Mutator>> {
+        Some(Box::new(self.clone()))
+    }
+
     fn mutate_string(
         &self,
         value: String,
@@ -39,15 +100,14 @@ impl Mutator for StringLengthMutator {
         match source.gen_range(0, 3) {
             0 => {
                 // truncate
-                if value.is_empty() {
-                    return Some(value);
+                match self.config.truncated_len(value.len(), source) {
+                    Some(new_len) => Some(value.chars().take(new_len).collect()),
+                    None => Some(value),
                 }
-                let new_len = source.gen_range(0, value.len());
-                Some(value.chars().take(new_len).collect())
             }
             1 => {
                 // extend with random chars
-                let extra_len = source.gen_range(1, 10);
+                let extra_len = self.config.extra_len(source);
                 let mut result = value.clone();
                 for _ in 0..extra_len {
                     result.push((source.gen_u8() % 26 + b'a') as char);
@@ -76,15 +136,14 @@ impl Mutator for StringLengthMutator {
         match source.gen_range(0, 3) {
             0 => {
                 // truncate
-                if value.is_empty() {
-                    return Some(value);
+                match self.config.truncated_len(value.len(), source) {
+                    Some(new_len) => Some(value[..new_len].to_vec()),
+                    None => Some(value),
                 }
-                let new_len = source.gen_range(0, value.len());
-                Some(value[..new_len].to_vec())
             }
             1 => {
                 // extend with random bytes
-                let extra_len = source.gen_range(1, 10);
+                let extra_len = self.config.extra_len(source);
                 let mut result = value.clone();
                 for _ in 0..extra_len {
                     result.push(source.gen_u8());
@@ -110,13 +169,13 @@ mod tests {
 
     #[test]
     fn test_stringlen_name() {
-        let mutator = StringLengthMutator;
+        let mutator = StringLengthMutator::default();
         assert_eq!(mutator.name(), "stringlen");
     }
 
     #[test]
     fn test_stringlen_mutate_string_changes_length() {
-        let mutator = StringLengthMutator;
+        let mutator = StringLengthMutator::default();
         let mut rng = ChaCha8Rng::seed_from_u64(42);
         let mut source = GenerationSource::Rand(&mut rng);
 
@@ -140,7 +199,7 @@ mod tests {
 
     #[test]
     fn test_stringlen_mutate_string_empty() {
-        let mutator = StringLengthMutator;
+        let mutator = StringLengthMutator::default();
         let mut rng = ChaCha8Rng::seed_from_u64(42);
         let mut source = GenerationSource::Rand(&mut rng);
 
@@ -155,7 +214,7 @@ mod tests {
 
     #[test]
     fn test_stringlen_mutate_bytes_changes_length() {
-        let mutator = StringLengthMutator;
+        let mutator = StringLengthMutator::default();
         let mut rng = ChaCha8Rng::seed_from_u64(42);
         let mut source = GenerationSource::Rand(&mut rng);
 
@@ -179,7 +238,7 @@ mod tests {
 
     #[test]
     fn test_stringlen_mutate_bytes_empty() {
-        let mutator = StringLengthMutator;
+        let mutator = StringLengthMutator::default();
         let mut rng = ChaCha8Rng::seed_from_u64(42);
         let mut source = GenerationSource::Rand(&mut rng);
 
@@ -193,7 +252,7 @@ mod tests {
 
     #[test]
     fn test_stringlen_never_mutates_at_rate_0() {
-        let mutator = StringLengthMutator;
+        let mutator = StringLengthMutator::default();
         let mut rng = ChaCha8Rng::seed_from_u64(42);
         let mut source = GenerationSource::Rand(&mut rng);
 
@@ -204,4 +263,29 @@ mod tests {
             .mutate_bytes(vec![1, 2, 3], &mut source, 0.0)
             .is_none());
     }
+
+    #[test]
+    fn test_stringlen_config_bounds_lengths() {
+        let config = StringLengthConfig::default()
+            .with_max_extend(40)
+            .with_allow_empty(false);
+        let mutator = StringLengthMutator::default().with_config(config);
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        let mut source = GenerationSource::Rand(&mut rng);
+
+        let mut longest = 0;
+        for _ in 0..200 {
+            let result = mutator.mutate_bytes(vec![1, 2], &mut source, 1.0).unwrap();
+            assert!(!result.is_empty());
+            assert!(result.len() <= 2 + 40);
+            longest = longest.max(result.len());
+        }
+        assert!(longest > 2 + 9, "longer extensions than the default");
+
+        // a single element can't be truncated without emptying the value
+        for _ in 0..20 {
+            let result = mutator.mutate_string("x".into(), &mut source, 1.0).unwrap();
+            assert!(!result.is_empty());
+        }
+    }
 }
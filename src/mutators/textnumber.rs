@@ -0,0 +1,290 @@
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{EmissionSnapshot, Mutator, PostProcessEmission};
+use crate::generator::{EntropySource, GenerationSource};
+use crate::opcodes::OpcodeKind;
+
+/// Smallest memo index the large-PUT rewrite picks.
+const LARGE_INDEX_MIN: usize = 1 << 16;
+
+/// Bound on the large-PUT rewrite, kept low enough that CPython's C unpickler,
+/// which sizes its memo array by the largest index, still loads the pickle.
+const LARGE_INDEX_MAX: usize = 1 << 20;
+
+/// Text number mutator: rewrites the decimal arguments of the protocol 0/1
+/// text opcodes INT, GET, and PUT.
+///
+/// In safe mode it only uses spellings that `pickle.py`, the C unpickler, and
+/// `pickletools` read as the same number: surrounding whitespace, a `+` sign,
+/// `_` digit separators, and leading zeros (for memo indices). It also moves
+/// PUTs to large memo indices that later GETs can reference. In unsafe mode it
+/// adds negative and overflowing memo indices, plus `0x`/`0o`/`0b` and
+/// leading-zero INTs, on which those parsers disagree.
+#[derive(Debug, Clone)]
+pub struct TextNumberMutator {
+    unsafe_mode: bool,
+}
+
+/// A rewrite of one text number argument.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Rewrite {
+    LeadingZeros,
+    Whitespace,
+    PlusSign,
+    Underscore,
+    LargeIndex,
+    // unsafe-only
+    Negative,
+    Overflow,
+    RadixPrefix,
+    OctalLookalike,
+}
+
+impl TextNumberMutator {
+    pub fn new(unsafe_mode: bool) -> Self {
+        Self { unsafe_mode }
+    }
+
+    /// The text-argument opcode (`INT`, `GET`, or `PUT`) `code` stands for.
+    fn text_number_opcode(code: u8) -> Option<OpcodeKind> {
+        OpcodeKind::from_u8(code)
+            .filter(|opcode| matches!(opcode, OpcodeKind::Int | OpcodeKind::Get | OpcodeKind::Put))
+    }
+
+    /// Rewrites that apply to `opcode` with the (non-negative for memo
+    /// opcodes) value `value`.
+    fn rewrites(&self, opcode: OpcodeKind, value: i64) -> Vec<Rewrite> {
+        use Rewrite::*;
+
+        let digits = value.unsigned_abs().to_string().len();
+        let mut rewrites = vec![Whitespace];
+        if value >= 0 {
+            rewrites.push(PlusSign);
+        }
+        if digits >= 2 {
+            rewrites.push(Underscore);
+        }
+
+        match opcode {
+            OpcodeKind::Get | OpcodeKind::Put => {
+                rewrites.push(LeadingZeros);
+                if opcode == OpcodeKind::Put {
+                    rewrites.push(LargeIndex);
+                }
+                if self.unsafe_mode {
+                    rewrites.extend([Negative, Overflow]);
+                }
+            }
+            _ => {
+                if self.unsafe_mode {
+                    rewrites.extend([RadixPrefix, OctalLookalike]);
+                }
+            }
+        }
+        rewrites
+    }
+
+    /// Spell `value` according to `rewrite`.
+    fn spell(rewrite: Rewrite, value: i64, source: &mut GenerationSource) -> String {
+        let sign = if value < 0 { "-" } else { "" };
+        let magnitude = value.unsigned_abs();
+        let digits = magnitude.to_string();
+
+        match rewrite {
+            Rewrite::LeadingZeros | Rewrite::OctalLookalike => {
+                let zeros = "0".repeat(source.gen_range(1, 4));
+                format!("{sign}{zeros}{digits}")
+            }
+            Rewrite::Whitespace => {
+                let mut pad = |min| -> String {
+                    (0..source.gen_range(min, 3))
+                        .map(|_| if source.gen_bool() { ' ' } else { '\t' })
+                        .collect()
+                };
+                let before = pad(0);
+                let after = pad(usize::from(before.is_empty()));
+                format!("{before}{value}{after}")
+            }
+            Rewrite::PlusSign => format!("+{digits}"),
+            Rewrite::Underscore => {
+                let split = source.gen_range(1, digits.len());
+                format!("{sign}{}_{}", &digits[..split], &digits[split..])
+            }
+            Rewrite::LargeIndex => source
+                .gen_range(LARGE_INDEX_MIN, LARGE_INDEX_MAX)
+                .to_string(),
+            Rewrite::Negative => format!("-{}", magnitude + 1),
+            Rewrite::Overflow => (u128::from(magnitude) + (1u128 << 64)).to_string(),
+            Rewrite::RadixPrefix => match source.gen_range(0, 3) {
+                0 => format!("{sign}0x{magnitude:x}"),
+                1 => format!("{sign}0o{magnitude:o}"),
+                _ => format!("{sign}0b{magnitude:b}"),
+            },
+        }
+    }
+}
+
+impl Mutator for TextNumberMutator {
+    fn name(&self) -> &str {
+        "textnumber"
+    }
+
+    fn boxed_clone(&self) -> Option<Box<dyn Mutator>> {
+        Some(Box::new(self.clone()))
+    }
+
+    fn is_unsafe(&self) -> bool {
+        self.unsafe_mode
+    }
+
+    fn post_process(
+        &self,
+        snapshot: &EmissionSnapshot,
+        output: &mut Vec<u8>,
+        source: &mut GenerationSource,
+        rate: f64,
+    ) -> bool {
+        let Some((&opcode_byte, argument)) = snapshot.output_delta.split_first() else {
+            return false;
+        };
+        let Some(opcode) = Self::text_number_opcode(opcode_byte) else {
+            return false;
+        };
+
+        // only rewrite the canonical spelling the generator emits
+        let Some(value) = argument
+            .strip_suffix(b"\n")
+            .and_then(|text| std::str::from_utf8(text).ok())
+            .filter(|text| !text.starts_with('+') && !text.starts_with(char::is_whitespace))
+            .and_then(|text| text.parse::<i64>().ok())
+        else {
+            return false;
+        };
+        // "00"/"01" are bools, and the C unpickler also reads any other
+        // three-byte INT line holding 0 or 1 (" 1\n", "+0\n") as one
+        if opcode == OpcodeKind::Int && matches!(value, 0 | 1) {
+            return false;
+        }
+        if source.gen_f64() > rate {
+            return false;
+        }
+
+        let rewrites = self.rewrites(opcode, value);
+        let rewrite = rewrites[source.choose_index(rewrites.len())];
+        let text = Self::spell(rewrite, value, source);
+
+        output.truncate(snapshot.output_len);
+        output.push(opcode_byte);
+        output.extend_from_slice(text.as_bytes());
+        output.push(b'\n');
+        true
+    }
+
+    fn describe_post_process(
+        &self,
+        _snapshot: &EmissionSnapshot,
+        output: &[u8],
+    ) -> Option<PostProcessEmission> {
+        let (&opcode_byte, argument) = output.split_first()?;
+        let opcode = Self::text_number_opcode(opcode_byte)?;
+        Some(PostProcessEmission {
+            opcode,
+            arg_bytes: Some(argument.to_vec()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::GenerationSource;
+    use crate::mutators::testing::{assert_safety_follows_mode, rewritten_arguments, snapshot};
+    use crate::Version;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    /// Rewrite `emitted` many times, returning the distinct numbers seen.
+    fn rewritten_numbers(mutator: &TextNumberMutator, emitted: &[u8]) -> Vec<String> {
+        rewritten_arguments(mutator, emitted)
+            .into_iter()
+            .map(|argument| {
+                String::from_utf8(argument.strip_suffix(b"\n").unwrap().to_vec()).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_textnumber_safety_follows_mode() {
+        assert_safety_follows_mode("textnumber", TextNumberMutator::new);
+    }
+
+    #[test]
+    fn test_textnumber_safe_memo_rewrites_keep_the_index() {
+        let mutator = TextNumberMutator::new(false);
+        for text in rewritten_numbers(&mutator, b"g42\n") {
+            // python's int() semantics: surrounding whitespace, sign, separators
+            let normalized = text.trim().replace('_', "");
+            assert_eq!(normalized.parse::<usize>(), Ok(42), "{text:?}");
+        }
+
+        let large = rewritten_numbers(&mutator, b"p3\n")
+            .iter()
+            .filter_map(|text| text.parse::<usize>().ok())
+            .any(|index| (LARGE_INDEX_MIN..LARGE_INDEX_MAX).contains(&index));
+        assert!(large, "PUT should sometimes move to a large index");
+    }
+
+    #[test]
+    fn test_textnumber_safe_int_rewrites_keep_the_value() {
+        let mutator = TextNumberMutator::new(false);
+        for text in rewritten_numbers(&mutator, b"I-1234\n") {
+            let normalized = text.trim().replace('_', "");
+            assert_eq!(normalized.parse::<i64>(), Ok(-1234), "{text:?}");
+            assert!(!normalized.starts_with("-0"), "{text:?}");
+        }
+    }
+
+    #[test]
+    fn test_textnumber_unsafe_adds_ambiguous_spellings() {
+        let mutator = TextNumberMutator::new(true);
+        let ints = rewritten_numbers(&mutator, b"I255\n");
+        assert!(ints.iter().any(|text| text.starts_with("0x")));
+        assert!(ints.iter().any(|text| text.starts_with("00")));
+
+        let gets = rewritten_numbers(&mutator, b"g7\n");
+        assert!(gets.contains(&"-8".to_string()));
+        assert!(gets.contains(&"18446744073709551623".to_string()));
+    }
+
+    #[test]
+    fn test_textnumber_skips_bools_and_other_opcodes() {
+        let mutator = TextNumberMutator::new(true);
+        let mut rng = ChaCha8Rng::seed_from_u64(42);
+        let mut source = GenerationSource::Rand(&mut rng);
+
+        for emitted in [&b"I01\n"[..], b"I1\n", b"I0\n", b"J\x05\x00\x00\x00", b"N"] {
+            let mut output = emitted.to_vec();
+            assert!(!mutator.post_process(
+                &snapshot(Version::V0, emitted),
+                &mut output,
+                &mut source,
+                1.0
+            ));
+            assert_eq!(output, emitted);
+        }
+    }
+}
@@ -24,7 +24,7 @@ use crate::Version;
 ///
 /// This mutator is unsafe by design because it intentionally breaks type
 /// expectations for later stack consumers such as `STACK_GLOBAL`.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TypeConfusionMutator;
 
 /// Types that pure push opcodes can place on the stack.
@@ -92,16 +92,26 @@ impl TypeConfusionMutator {
     /// Post-processing runs after stack simulation, so opcodes that consume
     /// existing stack state cannot be safely rewritten here.
     fn opcode_to_type(opcode_byte: u8) -> Option<StackType> {
-        match opcode_byte {
-            0x49 | 0x4a | 0x4b | 0x4d | 0x4c | 0x8a | 0x8b => Some(StackType::Int),
-            0x46 | 0x47 => Some(StackType::Float),
-            0x53 | 0x56 | 0x8c | 0x58 | 0x8d => Some(StackType::String),
-            0x42 | 0x43 | 0x8e | 0x54 | 0x55 => Some(StackType::Bytes),
-            0x5d => Some(StackType::List),
-            0x7d => Some(StackType::Dict),
-            0x29 => Some(StackType::Tuple),
-            0x4e => Some(StackType::None),
-            0x88 | 0x89 => Some(StackType::Bool),
+        use OpcodeKind as Op;
+
+        match OpcodeKind::try_from(opcode_byte).ok()? {
+            Op::Int | Op::BinInt | Op::BinInt1 | Op::BinInt2 | Op::Long | Op::Long1 | Op::Long4 => {
+                Some(StackType::Int)
+            }
+            Op::Float | Op::BinFloat => Some(StackType::Float),
+            Op::String | Op::Unicode | Op::ShortBinUnicode | Op::BinUnicode | Op::BinUnicode8 => {
+                Some(StackType::String)
+            }
+            Op::BinBytes
+            | Op::ShortBinBytes
+            | Op::BinBytes8
+            | Op::BinString
+            | Op::ShortBinString => Some(StackType::Bytes),
+            Op::EmptyList => Some(StackType::List),
+            Op::EmptyDict => Some(StackType::Dict),
+            Op::EmptyTuple => Some(StackType::Tuple),
+            Op::None => Some(StackType::None),
+            Op::NewTrue | Op::NewFalse => Some(StackType::Bool),
             _ => None,
         }
     }
@@ -198,68 +208,32 @@ impl TypeConfusionMutator {
     }
 
     fn describe_replacement(output: &[u8]) -> Option<PostProcessEmission> {
-        let opcode = *output.first()?;
+        use OpcodeKind as Op;
 
+        let (&code, argument) = output.split_first()?;
+        let opcode = OpcodeKind::try_from(code).ok()?;
         match opcode {
-            value if value == OpcodeKind::Int.as_u8() => Some(PostProcessEmission {
-                opcode: OpcodeKind::Int,
-                arg_bytes: Some(output[1..].to_vec()),
-            }),
-            value if value == OpcodeKind::Float.as_u8() => Some(PostProcessEmission {
-                opcode: OpcodeKind::Float,
-                arg_bytes: Some(output[1..].to_vec()),
+            Op::Int | Op::Float | Op::Unicode => Some(PostProcessEmission {
+                opcode,
+                arg_bytes: Some(argument.to_vec()),
             }),
-            value if value == OpcodeKind::Unicode.as_u8() => Some(PostProcessEmission {
-                opcode: OpcodeKind::Unicode,
-                arg_bytes: Some(output[1..].to_vec()),
-            }),
-            value if value == OpcodeKind::ShortBinString.as_u8() => {
-                let len = *output.get(1)? as usize;
-                (output.len() == 2 + len).then(|| PostProcessEmission {
-                    opcode: OpcodeKind::ShortBinString,
-                    arg_bytes: Some(output[2..].to_vec()),
-                })
-            }
-            value if value == OpcodeKind::ShortBinBytes.as_u8() => {
-                let len = *output.get(1)? as usize;
-                (output.len() == 2 + len).then(|| PostProcessEmission {
-                    opcode: OpcodeKind::ShortBinBytes,
-                    arg_bytes: Some(output[2..].to_vec()),
-                })
-            }
-            value if value == OpcodeKind::EmptyList.as_u8() && output.len() == 1 => {
-                Some(PostProcessEmission {
-                    opcode: OpcodeKind::EmptyList,
-                    arg_bytes: None,
-                })
-            }
-            value if value == OpcodeKind::EmptyDict.as_u8() && output.len() == 1 => {
-                Some(PostProcessEmission {
-                    opcode: OpcodeKind::EmptyDict,
-                    arg_bytes: None,
+            Op::ShortBinString | Op::ShortBinBytes => {
+                let (&len, payload) = argument.split_first()?;
+                (payload.len() == len as usize).then(|| PostProcessEmission {
+                    opcode,
+                    arg_bytes: Some(payload.to_vec()),
                 })
             }
-            value if value == OpcodeKind::EmptyTuple.as_u8() && output.len() == 1 => {
+            Op::EmptyList
+            | Op::EmptyDict
+            | Op::EmptyTuple
+            | Op::None
+            | Op::NewTrue
+            | Op::NewFalse
+                if argument.is_empty() =>
+            {
                 Some(PostProcessEmission {
-                    opcode: OpcodeKind::EmptyTuple,
-                    arg_bytes: None,
-                })
-            }
-            value if value == OpcodeKind::None.as_u8() && output.len() == 1 => {
-                Some(PostProcessEmission {
-                    opcode: OpcodeKind::None,
-                    arg_bytes: None,
-                })
-            }
-            value if value == OpcodeKind::NewTrue.as_u8() && output.len() == 1 => {
-                Some(PostProcessEmission {
-                    opcode: OpcodeKind::NewTrue,
-                    arg_bytes: None,
-                })
-            }
-            value if value == OpcodeKind::NewFalse.as_u8() && output.len() == 1 => {
-                Some(PostProcessEmission {
-                    opcode: OpcodeKind::NewFalse,
+                    opcode,
                     arg_bytes: None,
                 })
             }
@@ -273,6 +247,10 @@ impl Mutator for TypeConfusionMutator {
         "typeconfusion"
     }
 
+    fn boxed_clone(&self) -> Option<Box<dyn Mutator>> {
+        Some(Box::new(self.clone()))
+    }
+
     fn is_unsafe(&self) -> bool {
         true
     }
@@ -456,9 +434,8 @@ mod tests {
         )
         .expect("protocol 4 should use NEWTRUE/NEWFALSE");
         assert!(matches!(
-            bool_v4[0],
-            value if value == OpcodeKind::NewTrue.as_u8()
-                || value == OpcodeKind::NewFalse.as_u8()
+            OpcodeKind::try_from(bool_v4[0]),
+            Ok(OpcodeKind::NewTrue | OpcodeKind::NewFalse)
         ));
     }
 
@@ -491,6 +468,7 @@ mod tests {
             stack_delta: vec![],
             output_delta: vec![OpcodeKind::BinInt.as_u8(), 1, 0, 0, 0],
             memo_delta: vec![],
+            operands_len: None,
         };
 
         let output = vec![OpcodeKind::BinInt.as_u8(), 1, 0, 0, 0];
@@ -526,6 +504,7 @@ mod tests {
             stack_delta: vec![],
             output_delta: vec![OpcodeKind::List.as_u8()],
             memo_delta: vec![],
+            operands_len: None,
         };
 
         let mut output = vec![OpcodeKind::List.as_u8()];
@@ -0,0 +1,289 @@
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{EmissionSnapshot, Mutator, PostProcessEmission};
+use crate::generator::{EntropySource, GenerationSource};
+use crate::opcodes::OpcodeKind;
+
+/// Whitespace mutator: pads and re-terminates the newline-terminated
+/// arguments of the protocol 0 text opcodes INT, LONG, FLOAT, STRING, GLOBAL,
+/// PERSID, GET, and PUT.
+///
+/// In safe mode it only adds leading or trailing spaces and tabs and `\r\n`
+/// terminators where `pickle.py`, the C unpickler, and `pickletools` read the
+/// same argument: around INT, LONG, GET, and PUT numbers (dropping LONG's `L`
+/// suffix, which may not be followed by whitespace) and inside GLOBAL and
+/// PERSID lines, where they become part of the name or id. In unsafe mode it
+/// also pads FLOAT, which only the C unpickler rejects, STRING, whose quotes
+/// must be the first and last bytes of its line, and INT lines the C
+/// unpickler reads as bools; embeds newlines in STRING quotes; and drops line
+/// terminators, so the argument runs into the next opcode.
+#[derive(Debug, Clone)]
+pub struct WhitespaceMutator {
+    unsafe_mode: bool,
+}
+
+/// A rewrite of one argument line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Rewrite {
+    Leading,
+    Trailing,
+    CrLf,
+    // unsafe-only
+    Unterminated,
+    EmbeddedNewline,
+}
+
+impl WhitespaceMutator {
+    pub fn new(unsafe_mode: bool) -> Self {
+        Self { unsafe_mode }
+    }
+
+    /// The text-argument opcode `code` stands for.
+    fn text_opcode(code: u8) -> Option<OpcodeKind> {
+        use OpcodeKind::*;
+
+        OpcodeKind::from_u8(code).filter(|opcode| {
+            matches!(
+                opcode,
+                Int | Long | Float | String | Global | PersID | Get | Put
+            )
+        })
+    }
+
+    /// Rewrites that apply to `line`, an argument line of `opcode`.
+    fn rewrites(&self, opcode: OpcodeKind, line: &[u8]) -> Vec<Rewrite> {
+        use Rewrite::*;
+
+        // the C unpickler reads a three-byte INT line holding 0 or 1 as a bool
+        let bool_like = opcode == OpcodeKind::Int && matches!(line, b"0" | b"1" | b"00" | b"01");
+        let safe = match opcode {
+            OpcodeKind::Float | OpcodeKind::String => false,
+            _ => !bool_like,
+        };
+
+        let mut rewrites = Vec::new();
+        if safe || self.unsafe_mode {
+            rewrites.extend([Leading, Trailing, CrLf]);
+        }
+        if self.unsafe_mode {
+            rewrites.push(Unterminated);
+            if opcode == OpcodeKind::String && line.len() > 2 {
+                rewrites.push(EmbeddedNewline);
+            }
+        }
+        rewrites
+    }
+
+    /// Spaces and tabs to pad a line with.
+    fn padding(source: &mut GenerationSource) -> Vec<u8> {
+        (0..source.gen_range(1, 3))
+            .map(|_| if source.gen_bool() { b' ' } else { b'\t' })
+            .collect()
+    }
+
+    /// `line`, an argument line of `opcode`, rewritten according to
+    /// `rewrite`, with its terminator.
+    fn spell(
+        &self,
+        rewrite: Rewrite,
+        opcode: OpcodeKind,
+        line: &[u8],
+        source: &mut GenerationSource,
+    ) -> Vec<u8> {
+        let mut line = line;
+        // nothing may follow LONG's L, so safe mode drops it
+        if opcode == OpcodeKind::Long
+            && matches!(rewrite, Rewrite::Trailing | Rewrite::CrLf)
+            && (!self.unsafe_mode || source.gen_bool())
+        {
+            line = line.strip_suffix(b"L").unwrap_or(line);
+        }
+
+        match rewrite {
+            Rewrite::Leading => [&Self::padding(source), line, b"\n"].concat(),
+            Rewrite::Trailing => [line, &Self::padding(source), b"\n"].concat(),
+            Rewrite::CrLf => [line, b"\r\n"].concat(),
+            Rewrite::Unterminated => line.to_vec(),
+            Rewrite::EmbeddedNewline => {
+                let at = source.gen_range(1, line.len() - 1);
+                [&line[..at], b"\n", &line[at..], b"\n"].concat()
+            }
+        }
+    }
+}
+
+impl Mutator for WhitespaceMutator {
+    fn name(&self) -> &str {
+        "whitespace"
+    }
+
+    fn boxed_clone(&self) -> Option<Box<dyn Mutator>> {
+        Some(Box::new(self.clone()))
+    }
+
+    fn is_unsafe(&self) -> bool {
+        self.unsafe_mode
+    }
+
+    fn post_process(
+        &self,
+        snapshot: &EmissionSnapshot,
+        output: &mut Vec<u8>,
+        source: &mut GenerationSource,
+        rate: f64,
+    ) -> bool {
+        let Some((&opcode_byte, argument)) = snapshot.output_delta.split_first() else {
+            return false;
+        };
+        let Some(opcode) = Self::text_opcode(opcode_byte) else {
+            return false;
+        };
+
+        // only rewrite the lines the generator emits: newline-terminated,
+        // without surrounding whitespace
+        let Some(text) = argument.strip_suffix(b"\n") else {
+            return false;
+        };
+        let lines: Vec<&[u8]> = text.split(|&b| b == b'\n').collect();
+        let expected = if opcode == OpcodeKind::Global { 2 } else { 1 };
+        let canonical = |line: &&[u8]| {
+            !line.contains(&b'\r')
+                && !line.first().is_some_and(u8::is_ascii_whitespace)
+                && !line.last().is_some_and(u8::is_ascii_whitespace)
+        };
+        if lines.len() != expected || !lines.iter().all(canonical) {
+            return false;
+        }
+        if source.gen_f64() > rate {
+            return false;
+        }
+
+        let at = source.choose_index(lines.len());
+        let rewrites = self.rewrites(opcode, lines[at]);
+        if rewrites.is_empty() {
+            return false;
+        }
+        let rewrite = rewrites[source.choose_index(rewrites.len())];
+
+        output.truncate(snapshot.output_len);
+        output.push(opcode_byte);
+        for (index, line) in lines.iter().enumerate() {
+            if index == at {
+                let spelled = self.spell(rewrite, opcode, line, source);
+                output.extend_from_slice(&spelled);
+            } else {
+                output.extend_from_slice(line);
+                output.push(b'\n');
+            }
+        }
+        true
+    }
+
+    fn describe_post_process(
+        &self,
+        _snapshot: &EmissionSnapshot,
+        output: &[u8],
+    ) -> Option<PostProcessEmission> {
+        let (&opcode_byte, argument) = output.split_first()?;
+        let opcode = Self::text_opcode(opcode_byte)?;
+        Some(PostProcessEmission {
+            opcode,
+            arg_bytes: Some(argument.to_vec()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::GenerationSource;
+    use crate::mutators::testing::{assert_safety_follows_mode, rewritten_arguments, snapshot};
+    use crate::Version;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    #[test]
+    fn test_whitespace_safety_follows_mode() {
+        assert_safety_follows_mode("whitespace", WhitespaceMutator::new);
+    }
+
+    #[test]
+    fn test_whitespace_safe_rewrites_keep_the_number() {
+        let mutator = WhitespaceMutator::new(false);
+        for emitted in [&b"I-1234\n"[..], b"g42\n", b"L99L\n"] {
+            let seen = rewritten_arguments(&mutator, emitted);
+            assert!(seen.iter().any(|argument| argument.ends_with(b"\r\n")));
+            for argument in seen {
+                let text = std::str::from_utf8(&argument).unwrap();
+                assert!(text.ends_with('\n'), "{text:?}");
+                // nothing may follow the L
+                let number = text.trim().strip_suffix('L').unwrap_or(text.trim());
+                assert!(number.parse::<i64>().is_ok(), "{text:?}");
+                assert!(!text.contains("L ") && !text.contains("L\t") && !text.contains("L\r"));
+            }
+        }
+    }
+
+    #[test]
+    fn test_whitespace_pads_one_global_line() {
+        let mutator = WhitespaceMutator::new(false);
+        for argument in rewritten_arguments(&mutator, b"cos\nsystem\n") {
+            let text = std::str::from_utf8(&argument).unwrap();
+            let lines: Vec<&str> = text.strip_suffix('\n').unwrap().split('\n').collect();
+            assert_eq!(lines.len(), 2, "{text:?}");
+            let changed = [lines[0] != "os", lines[1] != "system"];
+            assert_eq!(changed.iter().filter(|&&c| c).count(), 1, "{text:?}");
+            assert_eq!((lines[0].trim(), lines[1].trim()), ("os", "system"));
+        }
+    }
+
+    #[test]
+    fn test_whitespace_unsafe_breaks_floats_strings_and_terminators() {
+        let safe = WhitespaceMutator::new(false);
+        let mut rng = ChaCha8Rng::seed_from_u64(42);
+        let mut source = GenerationSource::Rand(&mut rng);
+        for emitted in [
+            &b"F1.5\n"[..],
+            b"S'abc'\n",
+            b"I1\n",
+            b"V\n",
+            b"J\x05\x00\x00\x00",
+        ] {
+            let mut output = emitted.to_vec();
+            assert!(!safe.post_process(
+                &snapshot(Version::V0, emitted),
+                &mut output,
+                &mut source,
+                1.0
+            ));
+            assert_eq!(output, emitted);
+        }
+
+        let mutator = WhitespaceMutator::new(true);
+        let floats = rewritten_arguments(&mutator, b"F1.5\n");
+        assert!(floats.contains(&b"1.5\r\n".to_vec()));
+        assert!(floats.contains(&b"1.5".to_vec()));
+
+        let strings = rewritten_arguments(&mutator, b"S'abc'\n");
+        assert!(strings.contains(&b"'a\nbc'\n".to_vec()));
+        assert!(strings.iter().any(|argument| argument.starts_with(b" ")));
+
+        let longs = rewritten_arguments(&mutator, b"L7L\n");
+        assert!(longs.contains(&b"7L\r\n".to_vec()));
+        assert!(longs.contains(&b"7\r\n".to_vec()));
+    }
+}
@@ -14,24 +14,39 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::fmt;
+
 use phf::phf_map;
 
-/// enumeration of all pickle opcodes we care about
+use crate::protocol::Version;
+
+/// one pickle opcode with its decoded argument, a typed IR for pickles.
+///
+/// every variant stands for one opcode of the stream, named like its
+/// [`OpcodeKind`]; `INT`'s `00`/`01` spellings of False and True get
+/// [`Opcode::IntBool`]. [`encode`](Opcode::encode) writes the form CPython's
+/// pickler writes and [`decode`](Opcode::decode) reads anything `pickletools`
+/// reads, so decoding and re-encoding a pickle normalizes its text arguments
+/// but keeps what the unpickler sees. `LONG1`/`LONG4` keep their exact bytes.
+///
+/// text arguments decode the way [`crate::disasm`] decodes them: lone
+/// surrogates become U+FFFD, and integers outside `i128` (or a memo index
+/// outside `u32`) don't decode.
 /// source: https://github.com/python/cpython/blob/main/Lib/pickletools.py
-#[allow(dead_code)]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Opcode {
     // Integer opcodes
-    Int(i32),       // 0x49
+    Int(i128),      // 0x49
+    IntBool(bool),  // 0x49 00/01
     BinInt(i32),    // 0x4a
     BinInt1(u8),    // 0x4b
     BinInt2(u16),   // 0x4d
-    Long(i64),      // 0x4c
-    Long1(Vec<u8>), // 0x8a
-    Long4(Vec<u8>), // 0x8b
+    Long(i128),     // 0x4c
+    Long1(Vec<u8>), // 0x8a, little-endian two's complement
+    Long4(Vec<u8>), // 0x8b, little-endian two's complement
 
     // String/bytes opcodes
-    String(String),          // 0x53
+    String(Vec<u8>),         // 0x53
     BinString(Vec<u8>),      // 0x54
     ShortBinString(Vec<u8>), // 0x55
     BinBytes(Vec<u8>),       // 0x42
@@ -57,22 +72,22 @@ pub enum Opcode {
     BinFloat(f64), // 0x47
 
     // List/tuple/dict/set
-    EmptyList,                                     // 0x5d
-    Append,                                        // 0x61
-    Appends,                                       // 0x65
-    List(Vec<Opcode>),                             // 0x6c
-    EmptyTuple,                                    // 0x29
-    Tuple(Vec<Opcode>),                            // 0x74
-    Tuple1(Box<Opcode>),                           // 0x85
-    Tuple2(Box<Opcode>, Box<Opcode>),              // 0x86
-    Tuple3(Box<Opcode>, Box<Opcode>, Box<Opcode>), // 0x87
-    EmptyDict,                                     // 0x7d
-    Dict(Vec<(Opcode, Opcode)>),                   // 0x64
-    SetItem,                                       // 0x73
-    SetItems,                                      // 0x75
-    EmptySet,                                      // 0x8f
-    AddItems,                                      // 0x90
-    FrozenSet(Vec<Opcode>),                        // 0x91
+    EmptyList,  // 0x5d
+    Append,     // 0x61
+    Appends,    // 0x65
+    List,       // 0x6c
+    EmptyTuple, // 0x29
+    Tuple,      // 0x74
+    Tuple1,     // 0x85
+    Tuple2,     // 0x86
+    Tuple3,     // 0x87
+    EmptyDict,  // 0x7d
+    Dict,       // 0x64
+    SetItem,    // 0x73
+    SetItems,   // 0x75
+    EmptySet,   // 0x8f
+    AddItems,   // 0x90
+    FrozenSet,  // 0x91
 
     // Stack/memo opcodes
     Pop,             // 0x30
@@ -88,24 +103,317 @@ pub enum Opcode {
     Memoize,         // 0x94
 
     // Extension/global
-    Ext1(u8),       // 0x82
-    Ext2(u16),      // 0x83
-    Ext4(u32),      // 0x84
-    Global(String), // 0x63
-    StackGlobal,    // 0x93
-    Reduce,         // 0x52
-    Build,          // 0x62
-    Inst,           // 0x69
-    Obj,            // 0x6f
-    NewObj,         // 0x81
-    NewObjEx,       // 0x92
-    Proto(u8),      // 0x80
-    Stop,           // 0x2e
-    Frame(u64),     // 0x95
-    PersID,         // 0x50
-    BinPersID,      // 0x51
+    Ext1(u8),               // 0x82
+    Ext2(u16),              // 0x83
+    Ext4(i32),              // 0x84
+    Global(String, String), // 0x63, module and name
+    StackGlobal,            // 0x93
+    Reduce,                 // 0x52
+    Build,                  // 0x62
+    Inst(String, String),   // 0x69, module and name
+    Obj,                    // 0x6f
+    NewObj,                 // 0x81
+    NewObjEx,               // 0x92
+    Proto(u8),              // 0x80
+    Stop,                   // 0x2e
+    Frame(u64),             // 0x95
+    PersID(String),         // 0x50
+    BinPersID,              // 0x51
+}
+
+impl Opcode {
+    /// the opcode's kind, without its argument.
+    pub fn kind(&self) -> OpcodeKind {
+        use OpcodeKind as Op;
+
+        match self {
+            Opcode::Int(_) | Opcode::IntBool(_) => Op::Int,
+            Opcode::BinInt(_) => Op::BinInt,
+            Opcode::BinInt1(_) => Op::BinInt1,
+            Opcode::BinInt2(_) => Op::BinInt2,
+            Opcode::Long(_) => Op::Long,
+            Opcode::Long1(_) => Op::Long1,
+            Opcode::Long4(_) => Op::Long4,
+            Opcode::String(_) => Op::String,
+            Opcode::BinString(_) => Op::BinString,
+            Opcode::ShortBinString(_) => Op::ShortBinString,
+            Opcode::BinBytes(_) => Op::BinBytes,
+            Opcode::ShortBinBytes(_) => Op::ShortBinBytes,
+            Opcode::BinBytes8(_) => Op::BinBytes8,
+            Opcode::ByteArray8(_) => Op::ByteArray8,
+            Opcode::NextBuffer => Op::NextBuffer,
+            Opcode::ReadOnlyBuffer => Op::ReadOnlyBuffer,
+            Opcode::None => Op::None,
+            Opcode::NewTrue => Op::NewTrue,
+            Opcode::NewFalse => Op::NewFalse,
+            Opcode::Unicode(_) => Op::Unicode,
+            Opcode::ShortBinUnicode(_) => Op::ShortBinUnicode,
+            Opcode::BinUnicode(_) => Op::BinUnicode,
+            Opcode::BinUnicode8(_) => Op::BinUnicode8,
+            Opcode::Float(_) => Op::Float,
+            Opcode::BinFloat(_) => Op::BinFloat,
+            Opcode::EmptyList => Op::EmptyList,
+            Opcode::Append => Op::Append,
+            Opcode::Appends => Op::Appends,
+            Opcode::List => Op::List,
+            Opcode::EmptyTuple => Op::EmptyTuple,
+            Opcode::Tuple => Op::Tuple,
+            Opcode::Tuple1 => Op::Tuple1,
+            Opcode::Tuple2 => Op::Tuple2,
+            Opcode::Tuple3 => Op::Tuple3,
+            Opcode::EmptyDict => Op::EmptyDict,
+            Opcode::Dict => Op::Dict,
+            Opcode::SetItem => Op::SetItem,
+            Opcode::SetItems => Op::SetItems,
+            Opcode::EmptySet => Op::EmptySet,
+            Opcode::AddItems => Op::AddItems,
+            Opcode::FrozenSet => Op::FrozenSet,
+            Opcode::Pop => Op::Pop,
+            Opcode::Dup => Op::Dup,
+            Opcode::Mark => Op::Mark,
+            Opcode::PopMark => Op::PopMark,
+            Opcode::Get(_) => Op::Get,
+            Opcode::BinGet(_) => Op::BinGet,
+            Opcode::LongBinGet(_) => Op::LongBinGet,
+            Opcode::Put(_) => Op::Put,
+            Opcode::BinPut(_) => Op::BinPut,
+            Opcode::LongBinPut(_) => Op::LongBinPut,
+            Opcode::Memoize => Op::Memoize,
+            Opcode::Ext1(_) => Op::Ext1,
+            Opcode::Ext2(_) => Op::Ext2,
+            Opcode::Ext4(_) => Op::Ext4,
+            Opcode::Global(..) => Op::Global,
+            Opcode::StackGlobal => Op::StackGlobal,
+            Opcode::Reduce => Op::Reduce,
+            Opcode::Build => Op::Build,
+            Opcode::Inst(..) => Op::Inst,
+            Opcode::Obj => Op::Obj,
+            Opcode::NewObj => Op::NewObj,
+            Opcode::NewObjEx => Op::NewObjEx,
+            Opcode::Proto(_) => Op::Proto,
+            Opcode::Stop => Op::Stop,
+            Opcode::Frame(_) => Op::Frame,
+            Opcode::PersID(_) => Op::PersID,
+            Opcode::BinPersID => Op::BinPersID,
+        }
+    }
+
+    /// the opcode byte followed by its encoded argument.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode_into(&mut out);
+        out
+    }
+
+    /// append the opcode byte and its encoded argument to `out`.
+    ///
+    /// sizes that don't fit the opcode's length prefix (a `SHORT_BINBYTES` of
+    /// more than 255 bytes) are truncated to the prefix width, like the
+    /// generator's own encoders do.
+    pub fn encode_into(&self, out: &mut Vec<u8>) {
+        out.push(self.kind().as_u8());
+        match self {
+            Opcode::Int(value) | Opcode::Long(value) => {
+                out.extend_from_slice(value.to_string().as_bytes());
+                if matches!(self, Opcode::Long(_)) {
+                    out.push(b'L');
+                }
+                out.push(b'\n');
+            }
+            Opcode::IntBool(value) => out.extend_from_slice(if *value { b"01\n" } else { b"00\n" }),
+            Opcode::BinInt(value) => out.extend_from_slice(&value.to_le_bytes()),
+            Opcode::Ext4(value) => out.extend_from_slice(&value.to_le_bytes()),
+            Opcode::BinInt1(value)
+            | Opcode::BinGet(value)
+            | Opcode::BinPut(value)
+            | Opcode::Ext1(value)
+            | Opcode::Proto(value) => out.push(*value),
+            Opcode::BinInt2(value) | Opcode::Ext2(value) => {
+                out.extend_from_slice(&value.to_le_bytes())
+            }
+            Opcode::LongBinGet(value) | Opcode::LongBinPut(value) => {
+                out.extend_from_slice(&value.to_le_bytes())
+            }
+            Opcode::Frame(value) => out.extend_from_slice(&value.to_le_bytes()),
+            Opcode::Long1(bytes) => counted(out, 1, bytes),
+            Opcode::Long4(bytes) | Opcode::BinString(bytes) | Opcode::BinBytes(bytes) => {
+                counted(out, 4, bytes)
+            }
+            Opcode::ShortBinString(bytes) | Opcode::ShortBinBytes(bytes) => counted(out, 1, bytes),
+            Opcode::BinBytes8(bytes) | Opcode::ByteArray8(bytes) => counted(out, 8, bytes),
+            Opcode::String(bytes) => {
+                out.extend_from_slice(&repr_string_literal(bytes));
+                out.push(b'\n');
+            }
+            Opcode::Unicode(text) => out.extend_from_slice(&raw_unicode_escape(text)),
+            Opcode::ShortBinUnicode(text) => counted(out, 1, text.as_bytes()),
+            Opcode::BinUnicode(text) => counted(out, 4, text.as_bytes()),
+            Opcode::BinUnicode8(text) => counted(out, 8, text.as_bytes()),
+            Opcode::Float(value) => {
+                out.extend_from_slice(python_float_repr(*value).as_bytes());
+                out.push(b'\n');
+            }
+            Opcode::BinFloat(value) => out.extend_from_slice(&value.to_be_bytes()),
+            Opcode::Get(index) | Opcode::Put(index) => {
+                out.extend_from_slice(format!("{index}\n").as_bytes())
+            }
+            Opcode::Global(module, name) | Opcode::Inst(module, name) => {
+                out.extend_from_slice(format!("{module}\n{name}\n").as_bytes())
+            }
+            Opcode::PersID(id) => out.extend_from_slice(format!("{id}\n").as_bytes()),
+            _ => {}
+        }
+    }
+
+    /// encode every opcode of `opcodes`, in order.
+    pub fn encode_all(opcodes: &[Opcode]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for opcode in opcodes {
+            opcode.encode_into(&mut out);
+        }
+        out
+    }
+
+    /// decode the opcode at the front of `data`, and how many bytes it took.
+    ///
+    /// fails on an unknown opcode, an argument that doesn't decode, or one the
+    /// IR can't hold.
+    pub fn decode(data: &[u8]) -> color_eyre::Result<(Opcode, usize)> {
+        crate::disasm::read_opcode(data)
+    }
+
+    /// decode `pickle` opcode by opcode through its first STOP, like
+    /// [`crate::disasm::disassemble`].
+    pub fn decode_all(pickle: &[u8]) -> color_eyre::Result<Vec<Opcode>> {
+        let mut opcodes = Vec::new();
+        let mut pos = 0;
+        loop {
+            if pos == pickle.len() {
+                return Err(color_eyre::eyre::eyre!(
+                    "pickle exhausted before seeing STOP"
+                ));
+            }
+            let (opcode, len) = Self::decode(&pickle[pos..])
+                .map_err(|e| color_eyre::eyre::eyre!("at position {pos}: {e}"))?;
+            pos += len;
+            let stop = opcode == Opcode::Stop;
+            opcodes.push(opcode);
+            if stop {
+                return Ok(opcodes);
+            }
+        }
+    }
+}
+
+/// append `bytes` after a little-endian length prefix `width` bytes wide.
+fn counted(out: &mut Vec<u8>, width: usize, bytes: &[u8]) {
+    out.extend_from_slice(&(bytes.len() as u64).to_le_bytes()[..width]);
+    out.extend_from_slice(bytes);
+}
+
+/// quote `bytes` the way python 2's `repr()` quotes a `str`, for the STRING
+/// opcode.
+///
+/// the unpickler strips matching outer quotes and runs the rest through
+/// `codecs.escape_decode`, so the literal must use single quotes unless the
+/// string contains a single quote and no double quote, escape backslashes and
+/// the chosen quote, and spell out every byte outside printable ASCII. the
+/// result always decodes back to `bytes`.
+pub(crate) fn repr_string_literal(bytes: &[u8]) -> Vec<u8> {
+    let quote = if bytes.contains(&b'\'') && !bytes.contains(&b'"') {
+        b'"'
+    } else {
+        b'\''
+    };
+
+    let mut literal = Vec::with_capacity(bytes.len() + 2);
+    literal.push(quote);
+    for &byte in bytes {
+        match byte {
+            b'\\' => literal.extend_from_slice(b"\\\\"),
+            b'\t' => literal.extend_from_slice(b"\\t"),
+            b'\n' => literal.extend_from_slice(b"\\n"),
+            b'\r' => literal.extend_from_slice(b"\\r"),
+            _ if byte == quote => literal.extend_from_slice(&[b'\\', quote]),
+            b' '..=b'~' => literal.push(byte),
+            _ => literal.extend_from_slice(format!("\\x{byte:02x}").as_bytes()),
+        }
+    }
+    literal.push(quote);
+    literal
+}
+
+/// `repr(value)` for a python float, which FLOAT carries below protocol 1.
+pub(crate) fn python_float_repr(value: f64) -> String {
+    if value.is_nan() {
+        return "nan".to_string();
+    }
+    if value.is_infinite() {
+        return if value > 0.0 { "inf" } else { "-inf" }.to_string();
+    }
+
+    // rust's shortest round-trip digits are the ones python's repr uses
+    let scientific = format!("{value:e}");
+    let (mantissa, exponent) = scientific
+        .split_once('e')
+        .expect("LowerExp output has an exponent");
+    let exponent: i32 = exponent.parse().expect("LowerExp exponent is an integer");
+    let (sign, mantissa) = match mantissa.strip_prefix('-') {
+        Some(mantissa) => ("-", mantissa),
+        None => ("", mantissa),
+    };
+    let digits: String = mantissa.chars().filter(|&c| c != '.').collect();
+
+    if (-4..16).contains(&exponent) {
+        let point = exponent + 1;
+        if point <= 0 {
+            format!(
+                "{sign}0.{}{digits}",
+                "0".repeat(point.unsigned_abs() as usize)
+            )
+        } else if point as usize >= digits.len() {
+            format!(
+                "{sign}{digits}{}.0",
+                "0".repeat(point as usize - digits.len())
+            )
+        } else {
+            let (whole, fraction) = digits.split_at(point as usize);
+            format!("{sign}{whole}.{fraction}")
+        }
+    } else {
+        let (first, rest) = digits.split_at(1);
+        let fraction = if rest.is_empty() {
+            String::new()
+        } else {
+            format!(".{rest}")
+        };
+        let exponent_sign = if exponent < 0 { '-' } else { '+' };
+        format!(
+            "{sign}{first}{fraction}e{exponent_sign}{:02}",
+            exponent.unsigned_abs()
+        )
+    }
 }
 
+/// the UNICODE argument CPython writes for `text`: raw-unicode-escape, plus
+/// `\u` escapes for the characters that would break the line format.
+pub(crate) fn raw_unicode_escape(text: &str) -> Vec<u8> {
+    let mut escaped = Vec::with_capacity(text.len() + 1);
+    for c in text.chars() {
+        let code = u32::from(c);
+        if code >= 0x10000 {
+            escaped.extend_from_slice(format!("\\U{code:08x}").as_bytes());
+        } else if code >= 0x100 || matches!(c, '\\' | '\0' | '\n' | '\r' | '\x1a') {
+            escaped.extend_from_slice(format!("\\u{code:04x}").as_bytes());
+        } else {
+            escaped.push(code as u8);
+        }
+    }
+    escaped.push(b'\n');
+    escaped
+}
+
+/// the kind of a pickle opcode, without its argument.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
 pub enum OpcodeKind {
     Int,             // 0x49
@@ -180,77 +488,349 @@ pub enum OpcodeKind {
 
 impl OpcodeKind {
     pub fn as_u8(self) -> u8 {
+        self.info().code
+    }
+
+    /// the opcode with byte `code`, if it is one.
+    pub fn from_u8(code: u8) -> Option<Self> {
+        OPCODES_BY_CODE[code as usize]
+    }
+
+    /// the opcode's static metadata.
+    pub fn info(self) -> &'static OpcodeInfo {
+        &OPCODE_TABLE[self as usize]
+    }
+
+    /// the opcode's name as `pickletools` spells it.
+    pub fn name(self) -> &'static str {
+        self.info().name
+    }
+}
+
+/// how an opcode's inline argument is encoded, after `pickletools`' argument
+/// descriptors of the same names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgFormat {
+    /// no inline argument
+    None,
+    /// `uint1`: one unsigned byte
+    Uint1,
+    /// `uint2`: a little-endian u16
+    Uint2,
+    /// `int4`: a little-endian i32
+    Int4,
+    /// `uint4`: a little-endian u32
+    Uint4,
+    /// `uint8`: a little-endian u64
+    Uint8,
+    /// `decimalnl_short`: a decimal line, `00`/`01` for False and True
+    DecimalNlShort,
+    /// `decimalnl_long`: a decimal line with an optional `L` suffix
+    DecimalNlLong,
+    /// `floatnl`: a `repr(float)` line
+    FloatNl,
+    /// `float8`: a big-endian f64
+    Float8,
+    /// `stringnl`: a quoted, escaped line
+    StringNl,
+    /// `stringnl_noescape`: an unquoted line
+    StringNlNoEscape,
+    /// `stringnl_noescape_pair`: two unquoted lines, module and name
+    StringNlNoEscapePair,
+    /// `unicodestringnl`: a raw-unicode-escape line
+    UnicodeStringNl,
+    /// `string1`: latin-1 text after a u8 length
+    String1,
+    /// `string4`: latin-1 text after an i32 length
+    String4,
+    /// `bytes1`: bytes after a u8 length
+    Bytes1,
+    /// `bytes4`: bytes after a u32 length
+    Bytes4,
+    /// `bytes8`: bytes after a u64 length
+    Bytes8,
+    /// `bytearray8`: bytes after a u64 length
+    ByteArray8,
+    /// `unicodestring1`: UTF-8 after a u8 length
+    UnicodeString1,
+    /// `unicodestring4`: UTF-8 after a u32 length
+    UnicodeString4,
+    /// `unicodestring8`: UTF-8 after a u64 length
+    UnicodeString8,
+    /// `long1`: two's complement bytes after a u8 length
+    Long1,
+    /// `long4`: two's complement bytes after an i32 length
+    Long4,
+}
+
+/// the shape of an inline argument, whatever its contents decode to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgLayout {
+    /// no argument bytes
+    None,
+    /// exactly this many bytes
+    Fixed(usize),
+    /// this many newline-terminated lines
+    Lines(usize),
+    /// a little-endian length prefix this many bytes wide, then that many bytes
+    Prefixed(usize),
+}
+
+impl ArgFormat {
+    /// the `pickletools` descriptor name (`"uint1"`, `"unicodestring1"`), or
+    /// `"none"`.
+    pub fn name(self) -> &'static str {
         match self {
-            OpcodeKind::Int => 0x49,
-            OpcodeKind::BinInt => 0x4a,
-            OpcodeKind::BinInt1 => 0x4b,
-            OpcodeKind::BinInt2 => 0x4d,
-            OpcodeKind::Long => 0x4c,
-            OpcodeKind::Long1 => 0x8a,
-            OpcodeKind::Long4 => 0x8b,
-            OpcodeKind::String => 0x53,
-            OpcodeKind::BinString => 0x54,
-            OpcodeKind::ShortBinString => 0x55,
-            OpcodeKind::BinBytes => 0x42,
-            OpcodeKind::ShortBinBytes => 0x43,
-            OpcodeKind::BinBytes8 => 0x8e,
-            OpcodeKind::ByteArray8 => 0x96,
-            OpcodeKind::NextBuffer => 0x97,
-            OpcodeKind::ReadOnlyBuffer => 0x98,
-            OpcodeKind::None => 0x4e,
-            OpcodeKind::NewTrue => 0x88,
-            OpcodeKind::NewFalse => 0x89,
-            OpcodeKind::Unicode => 0x56,
-            OpcodeKind::ShortBinUnicode => 0x8c,
-            OpcodeKind::BinUnicode => 0x58,
-            OpcodeKind::BinUnicode8 => 0x8d,
-            OpcodeKind::Float => 0x46,
-            OpcodeKind::BinFloat => 0x47,
-            OpcodeKind::EmptyList => 0x5d,
-            OpcodeKind::Append => 0x61,
-            OpcodeKind::Appends => 0x65,
-            OpcodeKind::List => 0x6c,
-            OpcodeKind::EmptyTuple => 0x29,
-            OpcodeKind::Tuple => 0x74,
-            OpcodeKind::Tuple1 => 0x85,
-            OpcodeKind::Tuple2 => 0x86,
-            OpcodeKind::Tuple3 => 0x87,
-            OpcodeKind::EmptyDict => 0x7d,
-            OpcodeKind::Dict => 0x64,
-            OpcodeKind::SetItem => 0x73,
-            OpcodeKind::SetItems => 0x75,
-            OpcodeKind::EmptySet => 0x8f,
-            OpcodeKind::AddItems => 0x90,
-            OpcodeKind::FrozenSet => 0x91,
-            OpcodeKind::Pop => 0x30,
-            OpcodeKind::Dup => 0x32,
-            OpcodeKind::Mark => 0x28,
-            OpcodeKind::PopMark => 0x31,
-            OpcodeKind::Get => 0x67,
-            OpcodeKind::BinGet => 0x68,
-            OpcodeKind::LongBinGet => 0x6a,
-            OpcodeKind::Put => 0x70,
-            OpcodeKind::BinPut => 0x71,
-            OpcodeKind::LongBinPut => 0x72,
-            OpcodeKind::Memoize => 0x94,
-            OpcodeKind::Ext1 => 0x82,
-            OpcodeKind::Ext2 => 0x83,
-            OpcodeKind::Ext4 => 0x84,
-            OpcodeKind::Global => 0x63,
-            OpcodeKind::StackGlobal => 0x93,
-            OpcodeKind::Reduce => 0x52,
-            OpcodeKind::Build => 0x62,
-            OpcodeKind::Inst => 0x69,
-            OpcodeKind::Obj => 0x6f,
-            OpcodeKind::NewObj => 0x81,
-            OpcodeKind::NewObjEx => 0x92,
-            OpcodeKind::Proto => 0x80,
-            OpcodeKind::Stop => 0x2e,
-            OpcodeKind::Frame => 0x95,
-            OpcodeKind::PersID => 0x50,
-            OpcodeKind::BinPersID => 0x51,
+            ArgFormat::None => "none",
+            ArgFormat::Uint1 => "uint1",
+            ArgFormat::Uint2 => "uint2",
+            ArgFormat::Int4 => "int4",
+            ArgFormat::Uint4 => "uint4",
+            ArgFormat::Uint8 => "uint8",
+            ArgFormat::DecimalNlShort => "decimalnl_short",
+            ArgFormat::DecimalNlLong => "decimalnl_long",
+            ArgFormat::FloatNl => "floatnl",
+            ArgFormat::Float8 => "float8",
+            ArgFormat::StringNl => "stringnl",
+            ArgFormat::StringNlNoEscape => "stringnl_noescape",
+            ArgFormat::StringNlNoEscapePair => "stringnl_noescape_pair",
+            ArgFormat::UnicodeStringNl => "unicodestringnl",
+            ArgFormat::String1 => "string1",
+            ArgFormat::String4 => "string4",
+            ArgFormat::Bytes1 => "bytes1",
+            ArgFormat::Bytes4 => "bytes4",
+            ArgFormat::Bytes8 => "bytes8",
+            ArgFormat::ByteArray8 => "bytearray8",
+            ArgFormat::UnicodeString1 => "unicodestring1",
+            ArgFormat::UnicodeString4 => "unicodestring4",
+            ArgFormat::UnicodeString8 => "unicodestring8",
+            ArgFormat::Long1 => "long1",
+            ArgFormat::Long4 => "long4",
         }
     }
+
+    /// how the argument's bytes are laid out.
+    pub fn layout(self) -> ArgLayout {
+        match self {
+            ArgFormat::None => ArgLayout::None,
+            ArgFormat::Uint1 => ArgLayout::Fixed(1),
+            ArgFormat::Uint2 => ArgLayout::Fixed(2),
+            ArgFormat::Int4 | ArgFormat::Uint4 => ArgLayout::Fixed(4),
+            ArgFormat::Uint8 | ArgFormat::Float8 => ArgLayout::Fixed(8),
+            ArgFormat::DecimalNlShort
+            | ArgFormat::DecimalNlLong
+            | ArgFormat::FloatNl
+            | ArgFormat::StringNl
+            | ArgFormat::StringNlNoEscape
+            | ArgFormat::UnicodeStringNl => ArgLayout::Lines(1),
+            ArgFormat::StringNlNoEscapePair => ArgLayout::Lines(2),
+            ArgFormat::String1
+            | ArgFormat::Bytes1
+            | ArgFormat::UnicodeString1
+            | ArgFormat::Long1 => ArgLayout::Prefixed(1),
+            ArgFormat::String4
+            | ArgFormat::Bytes4
+            | ArgFormat::UnicodeString4
+            | ArgFormat::Long4 => ArgLayout::Prefixed(4),
+            ArgFormat::Bytes8 | ArgFormat::ByteArray8 | ArgFormat::UnicodeString8 => {
+                ArgLayout::Prefixed(8)
+            }
+        }
+    }
+}
+
+/// the nominal stack effect of an opcode, as `pickletools.dis` simulates it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StackEffect {
+    /// for opcodes that pop to the topmost MARK, how many items below the MARK
+    /// they pop as well
+    pub below_mark: Option<usize>,
+    /// items popped, for opcodes that don't pop to a MARK
+    pub pops: usize,
+    /// items pushed
+    pub pushes: usize,
+    /// whether the opcode pushes a MARK (only `MARK` does)
+    pub pushes_mark: bool,
+}
+
+impl StackEffect {
+    const MARK: StackEffect = StackEffect {
+        below_mark: None,
+        pops: 0,
+        pushes: 0,
+        pushes_mark: true,
+    };
+
+    const fn items(pops: usize, pushes: usize) -> Self {
+        StackEffect {
+            below_mark: None,
+            pops,
+            pushes,
+            pushes_mark: false,
+        }
+    }
+
+    const fn to_mark(below_mark: usize, pushes: usize) -> Self {
+        StackEffect {
+            below_mark: Some(below_mark),
+            pops: 0,
+            pushes,
+            pushes_mark: false,
+        }
+    }
+}
+
+/// writes the stack before and after the opcode like `pickletools` lists
+/// them, e.g. `[any, any] -> [any]` for `TUPLE2` and
+/// `[any, mark, stackslice] -> [any]` for `APPENDS`.
+impl fmt::Display for StackEffect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut before: Vec<&str> = Vec::new();
+        match self.below_mark {
+            Some(below) => {
+                before.extend(std::iter::repeat_n("any", below));
+                before.extend(["mark", "stackslice"]);
+            }
+            None => before.extend(std::iter::repeat_n("any", self.pops)),
+        }
+        let mut after = vec!["any"; self.pushes];
+        if self.pushes_mark {
+            after.push("mark");
+        }
+        write!(f, "[{}] -> [{}]", before.join(", "), after.join(", "))
+    }
+}
+
+/// static metadata of one opcode: its byte, `pickletools` name, argument
+/// format, the protocol that introduced it, and its stack effect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpcodeInfo {
+    /// the opcode
+    pub kind: OpcodeKind,
+    /// opcode byte
+    pub code: u8,
+    /// name as `pickletools` spells it (e.g. `"SHORT_BINUNICODE"`)
+    pub name: &'static str,
+    /// how the inline argument is encoded
+    pub arg: ArgFormat,
+    /// the first protocol that has the opcode
+    pub proto: Version,
+    /// what the opcode does to the stack
+    pub stack: StackEffect,
+}
+
+impl OpcodeInfo {
+    /// the metadata of the opcode with byte `code`, if it is one.
+    pub fn by_code(code: u8) -> Option<&'static OpcodeInfo> {
+        OpcodeKind::from_u8(code).map(OpcodeKind::info)
+    }
+
+    /// the metadata of the opcode `pickletools` calls `name`, if it is one.
+    pub fn by_name(name: &str) -> Option<&'static OpcodeInfo> {
+        OPCODE_TABLE.iter().find(|info| info.name == name)
+    }
+}
+
+/// metadata of every opcode, in [`OpcodeKind`] declaration order.
+#[rustfmt::skip]
+pub static OPCODE_TABLE: [OpcodeInfo; 68] = {
+    use OpcodeKind as Op;
+    [
+        OpcodeInfo { kind: Op::Int, code: 0x49, name: "INT", arg: ArgFormat::DecimalNlShort, proto: Version::V0, stack: StackEffect::items(0, 1) },
+        OpcodeInfo { kind: Op::BinInt, code: 0x4a, name: "BININT", arg: ArgFormat::Int4, proto: Version::V1, stack: StackEffect::items(0, 1) },
+        OpcodeInfo { kind: Op::BinInt1, code: 0x4b, name: "BININT1", arg: ArgFormat::Uint1, proto: Version::V1, stack: StackEffect::items(0, 1) },
+        OpcodeInfo { kind: Op::BinInt2, code: 0x4d, name: "BININT2", arg: ArgFormat::Uint2, proto: Version::V1, stack: StackEffect::items(0, 1) },
+        OpcodeInfo { kind: Op::Long, code: 0x4c, name: "LONG", arg: ArgFormat::DecimalNlLong, proto: Version::V0, stack: StackEffect::items(0, 1) },
+        OpcodeInfo { kind: Op::Long1, code: 0x8a, name: "LONG1", arg: ArgFormat::Long1, proto: Version::V2, stack: StackEffect::items(0, 1) },
+        OpcodeInfo { kind: Op::Long4, code: 0x8b, name: "LONG4", arg: ArgFormat::Long4, proto: Version::V2, stack: StackEffect::items(0, 1) },
+        OpcodeInfo { kind: Op::String, code: 0x53, name: "STRING", arg: ArgFormat::StringNl, proto: Version::V0, stack: StackEffect::items(0, 1) },
+        OpcodeInfo { kind: Op::BinString, code: 0x54, name: "BINSTRING", arg: ArgFormat::String4, proto: Version::V1, stack: StackEffect::items(0, 1) },
+        OpcodeInfo { kind: Op::ShortBinString, code: 0x55, name: "SHORT_BINSTRING", arg: ArgFormat::String1, proto: Version::V1, stack: StackEffect::items(0, 1) },
+        OpcodeInfo { kind: Op::BinBytes, code: 0x42, name: "BINBYTES", arg: ArgFormat::Bytes4, proto: Version::V3, stack: StackEffect::items(0, 1) },
+        OpcodeInfo { kind: Op::ShortBinBytes, code: 0x43, name: "SHORT_BINBYTES", arg: ArgFormat::Bytes1, proto: Version::V3, stack: StackEffect::items(0, 1) },
+        OpcodeInfo { kind: Op::BinBytes8, code: 0x8e, name: "BINBYTES8", arg: ArgFormat::Bytes8, proto: Version::V4, stack: StackEffect::items(0, 1) },
+        OpcodeInfo { kind: Op::ByteArray8, code: 0x96, name: "BYTEARRAY8", arg: ArgFormat::ByteArray8, proto: Version::V5, stack: StackEffect::items(0, 1) },
+        OpcodeInfo { kind: Op::NextBuffer, code: 0x97, name: "NEXT_BUFFER", arg: ArgFormat::None, proto: Version::V5, stack: StackEffect::items(0, 1) },
+        OpcodeInfo { kind: Op::ReadOnlyBuffer, code: 0x98, name: "READONLY_BUFFER", arg: ArgFormat::None, proto: Version::V5, stack: StackEffect::items(1, 1) },
+        OpcodeInfo { kind: Op::None, code: 0x4e, name: "NONE", arg: ArgFormat::None, proto: Version::V0, stack: StackEffect::items(0, 1) },
+        OpcodeInfo { kind: Op::NewTrue, code: 0x88, name: "NEWTRUE", arg: ArgFormat::None, proto: Version::V2, stack: StackEffect::items(0, 1) },
+        OpcodeInfo { kind: Op::NewFalse, code: 0x89, name: "NEWFALSE", arg: ArgFormat::None, proto: Version::V2, stack: StackEffect::items(0, 1) },
+        OpcodeInfo { kind: Op::Unicode, code: 0x56, name: "UNICODE", arg: ArgFormat::UnicodeStringNl, proto: Version::V0, stack: StackEffect::items(0, 1) },
+        OpcodeInfo { kind: Op::ShortBinUnicode, code: 0x8c, name: "SHORT_BINUNICODE", arg: ArgFormat::UnicodeString1, proto: Version::V4, stack: StackEffect::items(0, 1) },
+        OpcodeInfo { kind: Op::BinUnicode, code: 0x58, name: "BINUNICODE", arg: ArgFormat::UnicodeString4, proto: Version::V1, stack: StackEffect::items(0, 1) },
+        OpcodeInfo { kind: Op::BinUnicode8, code: 0x8d, name: "BINUNICODE8", arg: ArgFormat::UnicodeString8, proto: Version::V4, stack: StackEffect::items(0, 1) },
+        OpcodeInfo { kind: Op::Float, code: 0x46, name: "FLOAT", arg: ArgFormat::FloatNl, proto: Version::V0, stack: StackEffect::items(0, 1) },
+        OpcodeInfo { kind: Op::BinFloat, code: 0x47, name: "BINFLOAT", arg: ArgFormat::Float8, proto: Version::V1, stack: StackEffect::items(0, 1) },
+        OpcodeInfo { kind: Op::EmptyList, code: 0x5d, name: "EMPTY_LIST", arg: ArgFormat::None, proto: Version::V1, stack: StackEffect::items(0, 1) },
+        OpcodeInfo { kind: Op::Append, code: 0x61, name: "APPEND", arg: ArgFormat::None, proto: Version::V0, stack: StackEffect::items(2, 1) },
+        OpcodeInfo { kind: Op::Appends, code: 0x65, name: "APPENDS", arg: ArgFormat::None, proto: Version::V1, stack: StackEffect::to_mark(1, 1) },
+        OpcodeInfo { kind: Op::List, code: 0x6c, name: "LIST", arg: ArgFormat::None, proto: Version::V0, stack: StackEffect::to_mark(0, 1) },
+        OpcodeInfo { kind: Op::EmptyTuple, code: 0x29, name: "EMPTY_TUPLE", arg: ArgFormat::None, proto: Version::V1, stack: StackEffect::items(0, 1) },
+        OpcodeInfo { kind: Op::Tuple, code: 0x74, name: "TUPLE", arg: ArgFormat::None, proto: Version::V0, stack: StackEffect::to_mark(0, 1) },
+        OpcodeInfo { kind: Op::Tuple1, code: 0x85, name: "TUPLE1", arg: ArgFormat::None, proto: Version::V2, stack: StackEffect::items(1, 1) },
+        OpcodeInfo { kind: Op::Tuple2, code: 0x86, name: "TUPLE2", arg: ArgFormat::None, proto: Version::V2, stack: StackEffect::items(2, 1) },
+        OpcodeInfo { kind: Op::Tuple3, code: 0x87, name: "TUPLE3", arg: ArgFormat::None, proto: Version::V2, stack: StackEffect::items(3, 1) },
+        OpcodeInfo { kind: Op::EmptyDict, code: 0x7d, name: "EMPTY_DICT", arg: ArgFormat::None, proto: Version::V1, stack: StackEffect::items(0, 1) },
+        OpcodeInfo { kind: Op::Dict, code: 0x64, name: "DICT", arg: ArgFormat::None, proto: Version::V0, stack: StackEffect::to_mark(0, 1) },
+        OpcodeInfo { kind: Op::SetItem, code: 0x73, name: "SETITEM", arg: ArgFormat::None, proto: Version::V0, stack: StackEffect::items(3, 1) },
+        OpcodeInfo { kind: Op::SetItems, code: 0x75, name: "SETITEMS", arg: ArgFormat::None, proto: Version::V1, stack: StackEffect::to_mark(1, 1) },
+        OpcodeInfo { kind: Op::EmptySet, code: 0x8f, name: "EMPTY_SET", arg: ArgFormat::None, proto: Version::V4, stack: StackEffect::items(0, 1) },
+        OpcodeInfo { kind: Op::AddItems, code: 0x90, name: "ADDITEMS", arg: ArgFormat::None, proto: Version::V4, stack: StackEffect::to_mark(1, 1) },
+        OpcodeInfo { kind: Op::FrozenSet, code: 0x91, name: "FROZENSET", arg: ArgFormat::None, proto: Version::V4, stack: StackEffect::to_mark(0, 1) },
+        OpcodeInfo { kind: Op::Pop, code: 0x30, name: "POP", arg: ArgFormat::None, proto: Version::V0, stack: StackEffect::items(1, 0) },
+        OpcodeInfo { kind: Op::Dup, code: 0x32, name: "DUP", arg: ArgFormat::None, proto: Version::V0, stack: StackEffect::items(1, 2) },
+        OpcodeInfo { kind: Op::Mark, code: 0x28, name: "MARK", arg: ArgFormat::None, proto: Version::V0, stack: StackEffect::MARK },
+        OpcodeInfo { kind: Op::PopMark, code: 0x31, name: "POP_MARK", arg: ArgFormat::None, proto: Version::V1, stack: StackEffect::to_mark(0, 0) },
+        OpcodeInfo { kind: Op::Get, code: 0x67, name: "GET", arg: ArgFormat::DecimalNlShort, proto: Version::V0, stack: StackEffect::items(0, 1) },
+        OpcodeInfo { kind: Op::BinGet, code: 0x68, name: "BINGET", arg: ArgFormat::Uint1, proto: Version::V1, stack: StackEffect::items(0, 1) },
+        OpcodeInfo { kind: Op::LongBinGet, code: 0x6a, name: "LONG_BINGET", arg: ArgFormat::Uint4, proto: Version::V1, stack: StackEffect::items(0, 1) },
+        OpcodeInfo { kind: Op::Put, code: 0x70, name: "PUT", arg: ArgFormat::DecimalNlShort, proto: Version::V0, stack: StackEffect::items(0, 0) },
+        OpcodeInfo { kind: Op::BinPut, code: 0x71, name: "BINPUT", arg: ArgFormat::Uint1, proto: Version::V1, stack: StackEffect::items(0, 0) },
+        OpcodeInfo { kind: Op::LongBinPut, code: 0x72, name: "LONG_BINPUT", arg: ArgFormat::Uint4, proto: Version::V1, stack: StackEffect::items(0, 0) },
+        OpcodeInfo { kind: Op::Memoize, code: 0x94, name: "MEMOIZE", arg: ArgFormat::None, proto: Version::V4, stack: StackEffect::items(1, 1) },
+        OpcodeInfo { kind: Op::Ext1, code: 0x82, name: "EXT1", arg: ArgFormat::Uint1, proto: Version::V2, stack: StackEffect::items(0, 1) },
+        OpcodeInfo { kind: Op::Ext2, code: 0x83, name: "EXT2", arg: ArgFormat::Uint2, proto: Version::V2, stack: StackEffect::items(0, 1) },
+        OpcodeInfo { kind: Op::Ext4, code: 0x84, name: "EXT4", arg: ArgFormat::Int4, proto: Version::V2, stack: StackEffect::items(0, 1) },
+        OpcodeInfo { kind: Op::Global, code: 0x63, name: "GLOBAL", arg: ArgFormat::StringNlNoEscapePair, proto: Version::V0, stack: StackEffect::items(0, 1) },
+        OpcodeInfo { kind: Op::StackGlobal, code: 0x93, name: "STACK_GLOBAL", arg: ArgFormat::None, proto: Version::V4, stack: StackEffect::items(2, 1) },
+        OpcodeInfo { kind: Op::Reduce, code: 0x52, name: "REDUCE", arg: ArgFormat::None, proto: Version::V0, stack: StackEffect::items(2, 1) },
+        OpcodeInfo { kind: Op::Build, code: 0x62, name: "BUILD", arg: ArgFormat::None, proto: Version::V0, stack: StackEffect::items(2, 1) },
+        OpcodeInfo { kind: Op::Inst, code: 0x69, name: "INST", arg: ArgFormat::StringNlNoEscapePair, proto: Version::V0, stack: StackEffect::to_mark(0, 1) },
+        OpcodeInfo { kind: Op::Obj, code: 0x6f, name: "OBJ", arg: ArgFormat::None, proto: Version::V1, stack: StackEffect::to_mark(0, 1) },
+        OpcodeInfo { kind: Op::NewObj, code: 0x81, name: "NEWOBJ", arg: ArgFormat::None, proto: Version::V2, stack: StackEffect::items(2, 1) },
+        OpcodeInfo { kind: Op::NewObjEx, code: 0x92, name: "NEWOBJ_EX", arg: ArgFormat::None, proto: Version::V4, stack: StackEffect::items(3, 1) },
+        OpcodeInfo { kind: Op::Proto, code: 0x80, name: "PROTO", arg: ArgFormat::Uint1, proto: Version::V2, stack: StackEffect::items(0, 0) },
+        OpcodeInfo { kind: Op::Stop, code: 0x2e, name: "STOP", arg: ArgFormat::None, proto: Version::V0, stack: StackEffect::items(1, 0) },
+        OpcodeInfo { kind: Op::Frame, code: 0x95, name: "FRAME", arg: ArgFormat::Uint8, proto: Version::V4, stack: StackEffect::items(0, 0) },
+        OpcodeInfo { kind: Op::PersID, code: 0x50, name: "PERSID", arg: ArgFormat::StringNlNoEscape, proto: Version::V0, stack: StackEffect::items(0, 1) },
+        OpcodeInfo { kind: Op::BinPersID, code: 0x51, name: "BINPERSID", arg: ArgFormat::None, proto: Version::V1, stack: StackEffect::items(1, 1) },
+    ]
+};
+
+/// the opcode of every byte, built from [`OPCODE_TABLE`].
+static OPCODES_BY_CODE: [Option<OpcodeKind>; 256] = {
+    let mut by_code = [None; 256];
+    let mut index = 0;
+    while index < OPCODE_TABLE.len() {
+        let info = &OPCODE_TABLE[index];
+        by_code[info.code as usize] = Some(info.kind);
+        index += 1;
+    }
+    by_code
+};
+
+impl TryFrom<u8> for OpcodeKind {
+    type Error = color_eyre::eyre::Error;
+
+    fn try_from(code: u8) -> Result<Self, Self::Error> {
+        Self::from_u8(code).ok_or_else(|| color_eyre::eyre::eyre!("unknown opcode {code:#04x}"))
+    }
+}
+
+impl From<OpcodeKind> for u8 {
+    fn from(opcode: OpcodeKind) -> Self {
+        opcode.as_u8()
+    }
 }
 
 pub static PICKLE_OPCODES: phf::Map<u8, &'static [OpcodeKind]> = phf_map! {
@@ -582,3 +1162,177 @@ pub static PICKLE_OPCODES: phf::Map<u8, &'static [OpcodeKind]> = phf_map! {
         OpcodeKind::ReadOnlyBuffer,
     ],
 };
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn float_repr_matches_python() {
+        for (value, repr) in [
+            (1e16, "1e+16"),
+            (1.5e-5, "1.5e-05"),
+            (0.1, "0.1"),
+            (-0.0, "-0.0"),
+            (1e22, "1e+22"),
+            (123456789.125, "123456789.125"),
+            (5e-324, "5e-324"),
+            (1e-4, "0.0001"),
+            (9999999999999998.0, "9999999999999998.0"),
+            (f64::MAX, "1.7976931348623157e+308"),
+            (f64::NEG_INFINITY, "-inf"),
+            (f64::NAN, "nan"),
+        ] {
+            assert_eq!(python_float_repr(value), repr);
+        }
+    }
+
+    #[test]
+    fn repr_string_literal_matches_python_2_repr() {
+        let cases: [(&str, &[u8]); 7] = [
+            ("", b"''"),
+            ("abc", b"'abc'"),
+            ("it's", b"\"it's\""),
+            ("'\"", b"'\\'\"'"),
+            ("a\\b\n", b"'a\\\\b\\n'"),
+            ("\t\r\x00\x7f", b"'\\t\\r\\x00\\x7f'"),
+            ("\u{e9}", b"'\\xc3\\xa9'"),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(
+                repr_string_literal(value.as_bytes()),
+                expected,
+                "{value:?} quoted as {:?}",
+                std::string::String::from_utf8_lossy(&repr_string_literal(value.as_bytes()))
+            );
+        }
+    }
+
+    #[test]
+    fn table_is_indexed_by_kind_and_unique() {
+        for (index, info) in OPCODE_TABLE.iter().enumerate() {
+            assert_eq!(info.kind as usize, index, "{}", info.name);
+            assert_eq!(OpcodeKind::from_u8(info.code), Some(info.kind));
+            assert_eq!(OpcodeInfo::by_name(info.name), Some(info));
+        }
+        assert_eq!(OpcodeKind::ShortBinUnicode.as_u8(), 0x8c);
+        assert_eq!(OpcodeKind::ShortBinUnicode.name(), "SHORT_BINUNICODE");
+        assert_eq!(OpcodeKind::from_u8(0xff), None);
+        assert!(OpcodeKind::try_from(0xff).is_err());
+        for code in 0..=u8::MAX {
+            match OpcodeKind::try_from(code) {
+                Ok(kind) => assert_eq!(u8::from(kind), code),
+                Err(_) => assert!(OPCODE_TABLE.iter().all(|info| info.code != code)),
+            }
+        }
+    }
+
+    #[test]
+    fn protocol_tables_list_the_opcodes_each_protocol_introduced() {
+        for version in Version::all() {
+            let mut listed: Vec<u8> = PICKLE_OPCODES[&version.as_u8()]
+                .iter()
+                .map(|kind| kind.as_u8())
+                .collect();
+            let mut expected: Vec<u8> = OPCODE_TABLE
+                .iter()
+                .filter(|info| info.proto <= version)
+                .map(|info| info.code)
+                .collect();
+            listed.sort_unstable();
+            expected.sort_unstable();
+            assert_eq!(listed, expected, "protocol {version}");
+        }
+    }
+
+    #[test]
+    fn metadata_matches_pickletools() {
+        let info = OpcodeKind::Appends.info();
+        assert_eq!(info.arg, ArgFormat::None);
+        assert_eq!(info.proto, Version::V1);
+        assert_eq!(info.stack.to_string(), "[any, mark, stackslice] -> [any]");
+        assert_eq!(OpcodeKind::Mark.info().stack.to_string(), "[] -> [mark]");
+        assert_eq!(
+            OpcodeKind::Tuple2.info().stack.to_string(),
+            "[any, any] -> [any]"
+        );
+
+        let info = OpcodeKind::BinUnicode8.info();
+        assert_eq!(info.arg.name(), "unicodestring8");
+        assert_eq!(info.arg.layout(), ArgLayout::Prefixed(8));
+        assert_eq!(info.proto, Version::V4);
+        assert_eq!(OpcodeKind::Global.info().arg.layout(), ArgLayout::Lines(2));
+        assert_eq!(
+            OpcodeKind::BinFloat.info().arg.layout(),
+            ArgLayout::Fixed(8)
+        );
+    }
+
+    #[test]
+    fn opcodes_encode_like_cpython() {
+        let cases: [(Opcode, &[u8]); 14] = [
+            (Opcode::Int(-7), b"I-7\n"),
+            (Opcode::IntBool(true), b"I01\n"),
+            (Opcode::Long(12), b"L12L\n"),
+            (Opcode::BinInt(-2), b"J\xfe\xff\xff\xff"),
+            (Opcode::BinInt2(0x1234), b"M\x34\x12"),
+            (Opcode::Long1(vec![0xff, 0x00]), b"\x8a\x02\xff\x00"),
+            (Opcode::String(b"it's".to_vec()), b"S\"it's\"\n"),
+            (Opcode::ShortBinBytes(b"ab".to_vec()), b"C\x02ab"),
+            (Opcode::Unicode("\u{e9}\n".into()), b"V\xe9\\u000a\n"),
+            (
+                Opcode::BinUnicode("\u{e9}".into()),
+                b"X\x02\x00\x00\x00\xc3\xa9",
+            ),
+            (Opcode::Float(0.5), b"F0.5\n"),
+            (Opcode::BinFloat(1.0), b"G\x3f\xf0\x00\x00\x00\x00\x00\x00"),
+            (
+                Opcode::Global("os".into(), "system".into()),
+                b"cos\nsystem\n",
+            ),
+            (Opcode::Frame(3), b"\x95\x03\x00\x00\x00\x00\x00\x00\x00"),
+        ];
+        for (opcode, expected) in cases {
+            assert_eq!(opcode.encode(), expected, "{opcode:?}");
+            assert_eq!(Opcode::decode(expected).unwrap(), (opcode, expected.len()));
+        }
+        assert_eq!(Opcode::Float(f64::NAN).encode(), b"Fnan\n");
+        assert_eq!(Opcode::Float(f64::NEG_INFINITY).encode(), b"F-inf\n");
+        assert_eq!(Opcode::Float(1e300).encode(), b"F1e+300\n");
+    }
+
+    #[test]
+    fn decode_normalizes_text_and_rejects_what_the_ir_cannot_hold() {
+        assert_eq!(Opcode::decode(b"I 00\n").unwrap().0, Opcode::Int(0));
+        assert_eq!(Opcode::decode(b"I00\n").unwrap().0, Opcode::IntBool(false));
+        assert_eq!(Opcode::decode(b"g01\n").unwrap().0, Opcode::Get(1));
+        assert_eq!(
+            Opcode::decode(b"S'a\\x62'\n").unwrap().0,
+            Opcode::String(b"ab".to_vec())
+        );
+        assert_eq!(
+            Opcode::decode(b"K\x05rest").unwrap(),
+            (Opcode::BinInt1(5), 2)
+        );
+
+        assert!(Opcode::decode(b"").is_err());
+        assert!(Opcode::decode(b"\xff").is_err());
+        assert!(Opcode::decode(b"J\x01\x02").is_err());
+        assert!(Opcode::decode(b"I1000000000000000000000000000000000000000\n").is_err());
+        assert!(Opcode::decode(b"g-1\n").is_err());
+        assert!(Opcode::decode_all(b"N").is_err());
+    }
+
+    #[test]
+    fn generated_pickles_re_encode_byte_for_byte() {
+        for version in Version::all() {
+            let mut generator = crate::Generator::new(version).with_seed(version.as_u8().into());
+            for _ in 0..50 {
+                let pickle = generator.generate().unwrap();
+                let opcodes = Opcode::decode_all(&pickle)
+                    .unwrap_or_else(|e| panic!("protocol {version}: {e}"));
+                assert_eq!(Opcode::encode_all(&opcodes), pickle, "protocol {version}");
+            }
+        }
+    }
+}
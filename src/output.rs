@@ -0,0 +1,371 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! batch output: sample file names, shard directories, and compression.
+//!
+//! a [`NameTemplate`] names each sample from its index, protocol, seed, and
+//! variant, [`shard_dir`] spreads samples over subdirectories, and samples are
+//! compressed one at a time, after generation, so the generator and the
+//! manifest only ever see the plain pickle. with the `zstd` feature, an
+//! [`Archive`] packs a whole batch into one zstd-compressed tar stream instead.
+
+use std::fmt::Write;
+#[cfg(feature = "zstd")]
+use std::io;
+use std::str::FromStr;
+
+use clap::ValueEnum;
+use color_eyre::eyre::{eyre, Error};
+
+/// one piece of a [`NameTemplate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum NamePart {
+    Literal(String),
+    Index,
+    Protocol,
+    Seed,
+    Variant,
+}
+
+/// how batch samples are named, parsed from text such as
+/// `proto{proto}_seed{seed}_{idx}.pkl`.
+///
+/// `{idx}` is the sample index, `{proto}` the protocol number, `{seed}` the
+/// sample's seed, and `{variant}` the sibling number under `--variants`.
+/// every template has `{idx}`, so names stay unique, and no path separators.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameTemplate {
+    parts: Vec<NamePart>,
+}
+
+impl NameTemplate {
+    /// the names batch mode uses without a template: `IDX.pkl`, or
+    /// `IDX-VARIANT.pkl` for siblings.
+    pub fn batch_default(variants: bool) -> Self {
+        let template = if variants {
+            "{idx}-{variant}.pkl"
+        } else {
+            "{idx}.pkl"
+        };
+        template.parse().expect("default templates are valid")
+    }
+
+    /// whether the template has `{seed}`.
+    pub fn uses_seed(&self) -> bool {
+        self.parts.contains(&NamePart::Seed)
+    }
+
+    /// whether the template has `{variant}`.
+    pub fn uses_variant(&self) -> bool {
+        self.parts.contains(&NamePart::Variant)
+    }
+
+    /// the file name of one sample; a missing seed or variant renders empty.
+    pub fn render(
+        &self,
+        idx: usize,
+        protocol: u8,
+        seed: Option<u64>,
+        variant: Option<usize>,
+    ) -> String {
+        let mut name = String::new();
+        for part in &self.parts {
+            // writing to a String can't fail
+            let _ = match part {
+                NamePart::Literal(text) => write!(name, "{text}"),
+                NamePart::Index => write!(name, "{idx}"),
+                NamePart::Protocol => write!(name, "{protocol}"),
+                NamePart::Seed => seed.map_or(Ok(()), |seed| write!(name, "{seed}")),
+                NamePart::Variant => variant.map_or(Ok(()), |variant| write!(name, "{variant}")),
+            };
+        }
+        name
+    }
+}
+
+impl FromStr for NameTemplate {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.contains(['/', '\\']) {
+            return Err(eyre!("name template {:?} has a path separator", s));
+        }
+        let mut parts = Vec::new();
+        let mut rest = s;
+        while let Some(open) = rest.find('{') {
+            if open > 0 {
+                parts.push(NamePart::Literal(rest[..open].to_string()));
+            }
+            let close = rest[open..]
+                .find('}')
+                .ok_or_else(|| eyre!("unclosed {{ in name template {:?}", s))?;
+            parts.push(match &rest[open + 1..open + close] {
+                "idx" => NamePart::Index,
+                "proto" => NamePart::Protocol,
+                "seed" => NamePart::Seed,
+                "variant" => NamePart::Variant,
+                other => {
+                    return Err(eyre!(
+                        "unknown placeholder {{{}}} (expected idx, proto, seed, or variant)",
+                        other
+                    ))
+                }
+            });
+            rest = &rest[open + close + 1..];
+        }
+        if rest.contains('}') {
+            return Err(eyre!("unmatched }} in name template {:?}", s));
+        }
+        if !rest.is_empty() {
+            parts.push(NamePart::Literal(rest.to_string()));
+        }
+        if !parts.contains(&NamePart::Index) {
+            return Err(eyre!("name template {:?} needs {{idx}}", s));
+        }
+        Ok(NameTemplate { parts })
+    }
+}
+
+/// the subdirectory of sample `idx` when samples are spread round-robin over
+/// `shards` directories, zero-padded so the directories sort in order.
+pub fn shard_dir(idx: usize, shards: usize) -> String {
+    let width = shards.saturating_sub(1).to_string().len();
+    format!("{:0width$}", idx % shards.max(1))
+}
+
+/// a compression format for batch samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Compression {
+    /// gzip (RFC 1952) at zlib's default level, written as `IDX.pkl.gz`
+    Gzip,
+    /// zstd at its default level, written as `IDX.pkl.zst` (`zstd` feature)
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+/// deflate level, the one `gzip` and zlib default to.
+const GZIP_LEVEL: u8 = 6;
+
+/// gzip member header: magic, deflate, no flags, no mtime, no extra flags,
+/// unknown OS.
+const GZIP_HEADER: [u8; 10] = [0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff];
+
+impl Compression {
+    /// the file extension the format adds after `.pkl`.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Compression::Gzip => "gz",
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => "zst",
+        }
+    }
+
+    /// compress `data` into one complete file of this format.
+    pub fn compress(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Compression::Gzip => {
+                let deflated = miniz_oxide::deflate::compress_to_vec(data, GZIP_LEVEL);
+                let mut out = Vec::with_capacity(GZIP_HEADER.len() + deflated.len() + 8);
+                out.extend_from_slice(&GZIP_HEADER);
+                out.extend_from_slice(&deflated);
+                out.extend_from_slice(&crc32(data).to_le_bytes());
+                // ISIZE is the input length modulo 2^32
+                out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+                out
+            }
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => zstd::bulk::compress(data, zstd::DEFAULT_COMPRESSION_LEVEL)
+                .expect("compressing into memory at a valid level can't fail"),
+        }
+    }
+}
+
+/// the name of the single archive `--archive` writes into the batch directory.
+#[cfg(feature = "zstd")]
+pub const ARCHIVE_NAME: &str = "corpus.tar.zst";
+
+/// a zstd-compressed tar stream of batch samples.
+///
+/// entries are compressed as they are appended, so memory stays bounded however
+/// many samples the archive holds, and their headers carry no owner or mtime,
+/// so the same samples appended in the same order make the same archive.
+#[cfg(feature = "zstd")]
+pub struct Archive<W: io::Write> {
+    builder: tar::Builder<zstd::Encoder<'static, W>>,
+}
+
+#[cfg(feature = "zstd")]
+impl<W: io::Write> Archive<W> {
+    /// start an archive written to `inner`.
+    pub fn new(inner: W) -> io::Result<Self> {
+        let encoder = zstd::Encoder::new(inner, zstd::DEFAULT_COMPRESSION_LEVEL)?;
+        Ok(Archive {
+            builder: tar::Builder::new(encoder),
+        })
+    }
+
+    /// append `data` as the file `name`, which may name a shard subdirectory.
+    pub fn append(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        let mut header = tar::Header::new_ustar();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(0);
+        self.builder.append_data(&mut header, name, data)
+    }
+
+    /// write the end-of-archive marker and the end of the zstd frame, handing
+    /// back the writer.
+    pub fn finish(self) -> io::Result<W> {
+        self.builder.into_inner()?.finish()
+    }
+}
+
+/// byte-at-a-time table for the reflected CRC-32 polynomial gzip uses.
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut byte = 0;
+    while byte < 256 {
+        let mut crc = byte as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[byte] = crc;
+        byte += 1;
+    }
+    table
+};
+
+/// the CRC-32 of `data`, as gzip stores it.
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| {
+        CRC32_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn name_templates_render_every_placeholder() {
+        let template: NameTemplate = "proto{proto}_seed{seed}_{idx}-{variant}.pkl"
+            .parse()
+            .unwrap();
+        assert!(template.uses_seed() && template.uses_variant());
+        assert_eq!(
+            template.render(7, 4, Some(99), Some(2)),
+            "proto4_seed99_7-2.pkl"
+        );
+        assert_eq!(
+            NameTemplate::batch_default(false).render(3, 0, None, None),
+            "3.pkl"
+        );
+        assert_eq!(
+            NameTemplate::batch_default(true).render(3, 0, Some(1), Some(0)),
+            "3-0.pkl"
+        );
+
+        for bad in ["{seed}.pkl", "{idx", "{idx}}", "{index}.pkl", "a/{idx}"] {
+            assert!(bad.parse::<NameTemplate>().is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn shard_dirs_are_round_robin_and_padded() {
+        assert_eq!(shard_dir(0, 1), "0");
+        assert_eq!(shard_dir(13, 10), "3");
+        assert_eq!(shard_dir(13, 16), "13");
+        assert_eq!(shard_dir(5, 16), "05");
+        assert_eq!(shard_dir(1005, 1000), "005");
+    }
+
+    #[test]
+    fn crc32_matches_the_check_value() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn gzip_round_trips() {
+        let pickle = crate::Generator::new(crate::Version::V4)
+            .with_seed(1)
+            .generate()
+            .unwrap();
+        let gz = Compression::Gzip.compress(&pickle);
+
+        assert_eq!(gz[..GZIP_HEADER.len()], GZIP_HEADER);
+        let (body, trailer) = gz[GZIP_HEADER.len()..].split_at(gz.len() - GZIP_HEADER.len() - 8);
+        assert_eq!(
+            miniz_oxide::inflate::decompress_to_vec(body).unwrap(),
+            pickle
+        );
+        assert_eq!(trailer[..4], crc32(&pickle).to_le_bytes());
+        assert_eq!(trailer[4..], (pickle.len() as u32).to_le_bytes());
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn zstd_round_trips() {
+        let pickle = crate::Generator::new(crate::Version::V4)
+            .with_seed(1)
+            .generate()
+            .unwrap();
+        let zst = Compression::Zstd.compress(&pickle);
+
+        assert_eq!(zst[..4], [0x28, 0xb5, 0x2f, 0xfd], "zstd frame magic");
+        assert_eq!(zstd::decode_all(&zst[..]).unwrap(), pickle);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn archives_hold_every_sample_in_order() {
+        let samples = [("0.pkl", &b"\x80\x04K\x01."[..]), ("1/1.pkl", b"N.")];
+        let write = || {
+            let mut archive = Archive::new(Vec::new()).unwrap();
+            for (name, data) in samples {
+                archive.append(name, data).unwrap();
+            }
+            archive.finish().unwrap()
+        };
+        let zst = write();
+        assert_eq!(zst, write(), "archives are reproducible");
+
+        let tar = zstd::decode_all(&zst[..]).unwrap();
+        let mut archive = tar::Archive::new(&tar[..]);
+        let entries: Vec<(String, Vec<u8>)> = archive
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                let mut entry = entry.unwrap();
+                let name = entry.path().unwrap().to_string_lossy().into_owned();
+                let mut data = Vec::new();
+                io::Read::read_to_end(&mut entry, &mut data).unwrap();
+                (name, data)
+            })
+            .collect();
+        let expected: Vec<(String, Vec<u8>)> = samples
+            .iter()
+            .map(|(name, data)| (name.to_string(), data.to_vec()))
+            .collect();
+        assert_eq!(entries, expected);
+    }
+}
@@ -14,6 +14,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use arbitrary::Arbitrary;
 use phf::PhfHash;
 
 /// Pickle protocol versions supported by pickle-fuzzer.
@@ -25,7 +26,7 @@ use phf::PhfHash;
 /// - V3: Bytes support (Python 3.0+)
 /// - V4: Large data support (Python 3.4+)
 /// - V5: Out-of-band data (Python 3.8+)
-#[derive(Debug, Default, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Default, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord, Arbitrary)]
 pub enum Version {
     /// Protocol 0: Original ASCII protocol
     V0,
@@ -42,19 +43,90 @@ pub enum Version {
     V5,
 }
 
+impl Version {
+    /// Every protocol version, oldest first.
+    pub const ALL: [Version; 6] = [
+        Version::V0,
+        Version::V1,
+        Version::V2,
+        Version::V3,
+        Version::V4,
+        Version::V5,
+    ];
+
+    /// The protocol number, as written after `PROTO`.
+    pub fn as_u8(self) -> u8 {
+        self as u8
+    }
+
+    /// Iterate over every protocol version, oldest first.
+    pub fn all() -> impl DoubleEndedIterator<Item = Version> + ExactSizeIterator + Clone {
+        Self::ALL.into_iter()
+    }
+
+    /// Select a version given a roll, uniformly over all six.
+    ///
+    /// The roll is reduced modulo the number of versions, so callers can pass a
+    /// raw seed or byte and get a deterministic choice.
+    pub fn select(roll: u64) -> Version {
+        Self::ALL[(roll % Self::ALL.len() as u64) as usize]
+    }
+
+    /// Select a version for the sample seeded `seed`, uniformly over all six.
+    ///
+    /// Unlike [`select`](Self::select), consecutive seeds don't cycle through
+    /// the versions in order; see [`ProtocolMix::select_for_seed`].
+    pub fn select_for_seed(seed: u64) -> Version {
+        Self::select(splitmix64(seed))
+    }
+}
+
+impl TryFrom<u8> for Version {
+    type Error = color_eyre::eyre::Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Self::ALL
+            .get(value as usize)
+            .copied()
+            .ok_or_else(|| color_eyre::eyre::eyre!("protocol version must be 0-5"))
+    }
+}
+
 impl TryFrom<usize> for Version {
     type Error = color_eyre::eyre::Error;
 
     fn try_from(value: usize) -> Result<Self, Self::Error> {
-        match value {
-            0 => Ok(Version::V0),
-            1 => Ok(Version::V1),
-            2 => Ok(Version::V2),
-            3 => Ok(Version::V3),
-            4 => Ok(Version::V4),
-            5 => Ok(Version::V5),
-            _ => Err(color_eyre::eyre::eyre!("protocol version must be 0-5")),
-        }
+        Self::ALL
+            .get(value)
+            .copied()
+            .ok_or_else(|| color_eyre::eyre::eyre!("protocol version must be 0-5"))
+    }
+}
+
+impl From<Version> for u8 {
+    fn from(version: Version) -> Self {
+        version.as_u8()
+    }
+}
+
+/// Parses a protocol number, optionally prefixed with `v` or `V` (`4`, `v4`).
+impl std::str::FromStr for Version {
+    type Err = color_eyre::eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let digits = s.strip_prefix(['v', 'V']).unwrap_or(s);
+        digits
+            .parse::<u8>()
+            .map_err(|_| color_eyre::eyre::eyre!("invalid protocol version: {:?}", s))
+            .and_then(Version::try_from)
+    }
+}
+
+/// Displays the protocol number (`4`), which [`FromStr`](std::str::FromStr)
+/// parses back.
+impl std::fmt::Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_u8())
     }
 }
 
@@ -63,3 +135,200 @@ impl PhfHash for Version {
         (*self as u8).phf_hash(state);
     }
 }
+
+/// splitmix64's finalizer: a bijective scramble of `seed`, so nearby seeds
+/// give unrelated rolls.
+fn splitmix64(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Weighted mixture of protocol versions for batch generation.
+///
+/// Parsed from a comma-separated list of `version:weight` pairs such as
+/// `0:10,2:20,4:40,5:30`. Versions that are not listed are never selected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtocolMix {
+    entries: Vec<(Version, u64)>,
+    total: u64,
+}
+
+impl ProtocolMix {
+    /// Sum of all weights in the mix.
+    pub fn total_weight(&self) -> u64 {
+        self.total
+    }
+
+    /// The configured `(version, weight)` pairs in declaration order.
+    pub fn entries(&self) -> &[(Version, u64)] {
+        &self.entries
+    }
+
+    /// Select a version given a roll in `0..total_weight()`.
+    ///
+    /// Rolls outside that range are reduced modulo the total weight so callers can
+    /// pass a raw seed and still get a deterministic, correctly weighted choice.
+    pub fn select(&self, roll: u64) -> Version {
+        let mut roll = roll % self.total;
+        for (version, weight) in &self.entries {
+            if roll < *weight {
+                return *version;
+            }
+            roll -= weight;
+        }
+
+        // unreachable as long as `total` is the sum of the weights
+        self.entries[self.entries.len() - 1].0
+    }
+
+    /// Select a version for the sample seeded `seed`.
+    ///
+    /// Batch samples are seeded `seed + idx`, and [`select`](Self::select)
+    /// would map such a run of seeds onto contiguous blocks of one version, so
+    /// the seed is hashed first to spread consecutive samples over the weights.
+    pub fn select_for_seed(&self, seed: u64) -> Version {
+        self.select(splitmix64(seed))
+    }
+}
+
+impl std::str::FromStr for ProtocolMix {
+    type Err = color_eyre::eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut entries: Vec<(Version, u64)> = Vec::new();
+
+        for part in s.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+            let (version, weight) = part.split_once(':').ok_or_else(|| {
+                color_eyre::eyre::eyre!("expected VERSION:WEIGHT, got {:?}", part)
+            })?;
+            let version = version.trim().parse::<Version>()?;
+            let weight = weight
+                .trim()
+                .parse::<u64>()
+                .map_err(|_| color_eyre::eyre::eyre!("invalid weight: {:?}", weight))?;
+
+            if entries.iter().any(|(existing, _)| *existing == version) {
+                return Err(color_eyre::eyre::eyre!(
+                    "protocol {} listed more than once",
+                    version
+                ));
+            }
+            if weight > 0 {
+                entries.push((version, weight));
+            }
+        }
+
+        let total = entries
+            .iter()
+            .try_fold(0u64, |acc, (_, weight)| acc.checked_add(*weight))
+            .ok_or_else(|| color_eyre::eyre::eyre!("protocol mix weights overflow"))?;
+        if total == 0 {
+            return Err(color_eyre::eyre::eyre!(
+                "protocol mix needs at least one version with a non-zero weight"
+            ));
+        }
+
+        Ok(Self { entries, total })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version_conversions_round_trip() {
+        for (number, version) in Version::all().enumerate() {
+            assert_eq!(version.as_u8() as usize, number);
+            assert_eq!(Version::try_from(version.as_u8()).unwrap(), version);
+            assert_eq!(version.to_string().parse::<Version>().unwrap(), version);
+            assert_eq!(format!("v{version}").parse::<Version>().unwrap(), version);
+        }
+        assert_eq!("V4".parse::<Version>().unwrap(), Version::V4);
+        assert!(Version::try_from(6u8).is_err());
+        for invalid in ["", "v", "6", "v6", "-1", "3.5", "four", " 4"] {
+            assert!(invalid.parse::<Version>().is_err(), "{invalid:?}");
+        }
+    }
+
+    #[test]
+    fn test_version_select_cycles_through_all_versions() {
+        assert_eq!(Version::all().len(), 6);
+        assert_eq!(Version::select(0), Version::V0);
+        assert_eq!(Version::select(5), Version::V5);
+        assert_eq!(Version::select(6), Version::V0);
+        assert_eq!(Version::select(u64::MAX), Version::V3);
+    }
+
+    #[test]
+    fn test_protocol_mix_parses_weights() {
+        let mix: ProtocolMix = "0:10,2:20,4:40,5:30".parse().unwrap();
+        assert_eq!(mix.total_weight(), 100);
+        assert_eq!(
+            mix.entries(),
+            &[
+                (Version::V0, 10),
+                (Version::V2, 20),
+                (Version::V4, 40),
+                (Version::V5, 30)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_protocol_mix_select_respects_weights() {
+        let mix: ProtocolMix = "0:10,2:20,4:40,5:30".parse().unwrap();
+        assert_eq!(mix.select(0), Version::V0);
+        assert_eq!(mix.select(9), Version::V0);
+        assert_eq!(mix.select(10), Version::V2);
+        assert_eq!(mix.select(29), Version::V2);
+        assert_eq!(mix.select(30), Version::V4);
+        assert_eq!(mix.select(69), Version::V4);
+        assert_eq!(mix.select(70), Version::V5);
+        assert_eq!(mix.select(99), Version::V5);
+        assert_eq!(mix.select(100), Version::V0);
+    }
+
+    #[test]
+    fn test_protocol_mix_spreads_consecutive_seeds_over_the_weights() {
+        let mix: ProtocolMix = "0:10,2:20,4:40,5:30".parse().unwrap();
+        let picks: Vec<Version> = (1000..3000).map(|seed| mix.select_for_seed(seed)).collect();
+
+        for &(version, weight) in mix.entries() {
+            let count = picks.iter().filter(|&&pick| pick == version).count();
+            let expected = picks.len() as u64 * weight / mix.total_weight();
+            assert!(
+                count.abs_diff(expected as usize) < expected as usize / 5,
+                "protocol {version}: {count} of {}, expected about {expected}",
+                picks.len()
+            );
+        }
+        // no long runs of one protocol, as raw consecutive rolls would give
+        let longest_run = picks
+            .chunk_by(|a, b| a == b)
+            .map(<[Version]>::len)
+            .max()
+            .unwrap();
+        assert!(longest_run < 12, "longest run {longest_run}");
+        assert_eq!(mix.select_for_seed(7), mix.select_for_seed(7));
+    }
+
+    #[test]
+    fn test_protocol_mix_skips_zero_weights() {
+        let mix: ProtocolMix = "1:0,3:5".parse().unwrap();
+        assert_eq!(mix.entries(), &[(Version::V3, 5)]);
+        assert!((0..20).all(|roll| mix.select(roll) == Version::V3));
+    }
+
+    #[test]
+    fn test_protocol_mix_rejects_invalid_input() {
+        assert!("".parse::<ProtocolMix>().is_err());
+        assert!("0:0".parse::<ProtocolMix>().is_err());
+        assert!("6:10".parse::<ProtocolMix>().is_err());
+        assert!("2".parse::<ProtocolMix>().is_err());
+        assert!("2:x".parse::<ProtocolMix>().is_err());
+        assert!("2:1,2:3".parse::<ProtocolMix>().is_err());
+    }
+}
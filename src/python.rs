@@ -20,13 +20,96 @@
 //! using PyO3. It allows Python code to generate pickle bytecode with the same
 //! capabilities as the Rust API.
 
-use crate::{Generator, Version};
+use crate::disasm::{self, Argument};
+use crate::mutators::MutatorKind;
+use crate::{Generator, GeneratorConfig, GeneratorPool, Version};
+use clap::ValueEnum;
+use pyo3::exceptions::{PyRuntimeError, PyTypeError, PyValueError};
 use pyo3::prelude::*;
-use pyo3::types::PyBytes;
+use pyo3::types::{PyByteArray, PyBytes, PyDict, PyInt};
+use rayon::prelude::*;
+use std::path::PathBuf;
 
 #[pyclass(name = "Generator", unsendable)]
 struct PyGenerator {
     inner: Generator,
+    /// Configured mutator kinds, kept so the mutators can be rebuilt when
+    /// unsafe mode changes.
+    mutator_kinds: Vec<MutatorKind>,
+}
+
+fn parse_version(protocol: usize) -> PyResult<Version> {
+    Version::try_from(protocol)
+        .map_err(|e| PyValueError::new_err(format!("Invalid protocol: {}", e)))
+}
+
+fn check_mutation_rate(rate: f64) -> PyResult<f64> {
+    if (0.0..=1.0).contains(&rate) {
+        Ok(rate)
+    } else {
+        Err(PyValueError::new_err(format!(
+            "mutation_rate must be between 0.0 and 1.0, got {}",
+            rate
+        )))
+    }
+}
+
+/// Resolves mutator names as accepted by `--mutators`, expanding `all`.
+fn parse_mutators(names: &[String], unsafe_mutations: bool) -> PyResult<Vec<MutatorKind>> {
+    let mut kinds = Vec::with_capacity(names.len());
+    for name in names {
+        let kind = MutatorKind::from_str(name, true)
+            .map_err(|_| PyValueError::new_err(format!("Unknown mutator: {:?}", name)))?;
+        if kind == MutatorKind::All {
+            return Ok(MutatorKind::all_mutators(unsafe_mutations));
+        }
+        kinds.push(kind);
+    }
+    Ok(kinds)
+}
+
+fn mutator_name(kind: &MutatorKind) -> String {
+    kind.to_possible_value()
+        .map(|value| value.get_name().to_string())
+        .unwrap_or_default()
+}
+
+/// Lazy stream of pickles returned by `Generator.samples()`.
+///
+/// Holds its own copy of the generator's configuration, so changing the
+/// `Generator` afterwards does not affect a stream in progress.
+#[pyclass(name = "SampleIterator", unsendable)]
+struct PySampleIterator {
+    pool: GeneratorPool,
+    index: usize,
+    count: Option<usize>,
+}
+
+#[pymethods]
+impl PySampleIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python) -> PyResult<Option<Py<PyBytes>>> {
+        if self.count.is_some_and(|count| self.index >= count) {
+            return Ok(None);
+        }
+        let bytes = self.pool.generate(self.index as u64).map_err(|e| {
+            PyRuntimeError::new_err(format!(
+                "Generation failed for sample {}: {}",
+                self.index, e
+            ))
+        })?;
+        self.index += 1;
+        Ok(Some(PyBytes::new(py, &bytes).into()))
+    }
+
+    fn __len__(&self) -> PyResult<usize> {
+        self.count
+            .map(|count| count - self.index.min(count))
+            .ok_or_else(|| PyTypeError::new_err("an unbounded sample stream has no len()"))
+    }
 }
 
 impl PyGenerator {
@@ -37,23 +120,109 @@ impl PyGenerator {
             (existing, None) => existing,
         }
     }
+
+    /// The generator's settings as a configuration, for building its copies
+    /// on other threads.
+    fn config(&self) -> GeneratorConfig {
+        let inner = &self.inner;
+        GeneratorConfig {
+            protocol: Some(inner.state.version as usize),
+            seed: inner.seed,
+            min_opcodes: Some(inner.min_opcodes),
+            max_opcodes: Some(inner.max_opcodes),
+            max_size: inner.bufsize,
+            mutators: self.mutator_kinds.iter().map(mutator_name).collect(),
+            mutation_rate: Some(inner.mutation_rate),
+            unsafe_mutations: inner.unsafe_mutations,
+            allow_ext: inner.allow_ext_opcodes,
+            allow_buffer: inner.allow_buffer_opcodes,
+            allow_persistent_ids: inner.allow_persistent_id_opcodes,
+            ..GeneratorConfig::default()
+        }
+    }
+
+    /// A pool of generators configured like this one; sample `i` is seeded
+    /// `seed + i`, as in the CLI's batch mode.
+    fn pool(&self) -> PyResult<GeneratorPool> {
+        GeneratorPool::from_config(&self.config())
+            .map_err(|e| PyRuntimeError::new_err(format!("Invalid configuration: {}", e)))
+    }
+
+    /// Installs `kinds` as the active mutators for the given unsafe mode.
+    ///
+    /// Fails without changing anything if an unsafe-only mutator is requested
+    /// while unsafe mode is off.
+    fn apply_mutators(&mut self, kinds: Vec<MutatorKind>, unsafe_mutations: bool) -> PyResult<()> {
+        if !unsafe_mutations {
+            if let Some(kind) = kinds.iter().find(|kind| kind.requires_unsafe_mutations()) {
+                return Err(PyValueError::new_err(format!(
+                    "Mutator {:?} requires unsafe_mutations=True",
+                    mutator_name(kind)
+                )));
+            }
+        }
+        self.inner.mutators = kinds
+            .iter()
+            .map(|kind| kind.create(unsafe_mutations))
+            .collect();
+        self.inner.unsafe_mutations = unsafe_mutations;
+        self.mutator_kinds = kinds;
+        Ok(())
+    }
 }
 
 #[pymethods]
 impl PyGenerator {
     #[new]
-    #[pyo3(signature = (protocol=3, seed=None, allow_persistent_ids=false))]
-    fn new(protocol: usize, seed: Option<u64>, allow_persistent_ids: bool) -> PyResult<Self> {
-        let version = Version::try_from(protocol).map_err(|e| {
-            PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Invalid protocol: {}", e))
-        })?;
-
-        let mut generator = Generator::new(version);
+    #[pyo3(signature = (
+        protocol=3,
+        seed=None,
+        allow_persistent_ids=false,
+        *,
+        min_opcodes=None,
+        max_opcodes=None,
+        mutators=None,
+        mutation_rate=None,
+        unsafe_mutations=false,
+        allow_ext=false,
+        allow_buffer=false,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        protocol: usize,
+        seed: Option<u64>,
+        allow_persistent_ids: bool,
+        min_opcodes: Option<usize>,
+        max_opcodes: Option<usize>,
+        mutators: Option<Vec<String>>,
+        mutation_rate: Option<f64>,
+        unsafe_mutations: bool,
+        allow_ext: bool,
+        allow_buffer: bool,
+    ) -> PyResult<Self> {
+        let mut generator = Generator::new(parse_version(protocol)?)
+            .with_persistent_id_opcodes(allow_persistent_ids)
+            .with_ext_opcodes(allow_ext)
+            .with_buffer_opcodes(allow_buffer);
         if let Some(s) = seed {
             generator = generator.with_seed(s);
         }
-        generator = generator.with_persistent_id_opcodes(allow_persistent_ids);
-        Ok(PyGenerator { inner: generator })
+        if min_opcodes.is_some() || max_opcodes.is_some() {
+            let min = min_opcodes.unwrap_or(generator.min_opcodes);
+            let max = max_opcodes.unwrap_or(generator.max_opcodes);
+            generator = generator.with_opcode_range(min, max);
+        }
+        if let Some(rate) = mutation_rate {
+            generator = generator.with_mutation_rate(check_mutation_rate(rate)?);
+        }
+
+        let mut py_generator = PyGenerator {
+            inner: generator,
+            mutator_kinds: Vec::new(),
+        };
+        let kinds = parse_mutators(&mutators.unwrap_or_default(), unsafe_mutations)?;
+        py_generator.apply_mutators(kinds, unsafe_mutations)?;
+        Ok(py_generator)
     }
 
     #[pyo3(signature = (max_size=None))]
@@ -87,17 +256,278 @@ impl PyGenerator {
         Ok(PyBytes::new(py, &bytes).into())
     }
 
+    /// Generates `n` pickles in parallel with the GIL released.
+    ///
+    /// Sample `i` uses seed `seed + i` when the generator is seeded (matching the
+    /// CLI's batch mode), and OS entropy otherwise. Without `dir` the pickles are
+    /// returned as a list of bytes; with `dir` they are written to `<dir>/<i>.pkl`
+    /// and the list of paths is returned. `jobs` sets the number of worker
+    /// threads (default: one per logical CPU).
+    #[pyo3(signature = (n, dir=None, jobs=None))]
+    fn generate_batch(
+        &self,
+        py: Python,
+        n: usize,
+        dir: Option<PathBuf>,
+        jobs: Option<usize>,
+    ) -> PyResult<Py<PyAny>> {
+        let generators = self.pool()?;
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs.unwrap_or(0))
+            .build()
+            .map_err(|e| PyRuntimeError::new_err(format!("Thread pool failed: {}", e)))?;
+
+        // each rayon thread builds its generator once and reuses it
+        let generate_sample = |idx: usize| {
+            generators
+                .generate(idx as u64)
+                .map_err(|e| format!("Generation failed for sample {}: {}", idx, e))
+        };
+
+        match dir {
+            None => {
+                let pickles: Vec<Vec<u8>> = py
+                    .detach(|| {
+                        pool.install(|| {
+                            (0..n)
+                                .into_par_iter()
+                                .map(generate_sample)
+                                .collect::<Result<_, _>>()
+                        })
+                    })
+                    .map_err(PyRuntimeError::new_err)?;
+                let pickles: Vec<Py<PyBytes>> = pickles
+                    .iter()
+                    .map(|bytes| PyBytes::new(py, bytes).into())
+                    .collect();
+                Ok(pickles.into_pyobject(py)?.into_any().unbind())
+            }
+            Some(dir) => {
+                let paths: Vec<PathBuf> = py
+                    .detach(|| {
+                        std::fs::create_dir_all(&dir)
+                            .map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+                        pool.install(|| {
+                            (0..n)
+                                .into_par_iter()
+                                .map(|idx| {
+                                    let bytes = generate_sample(idx)?;
+                                    let path = dir.join(format!("{idx}.pkl"));
+                                    std::fs::write(&path, bytes).map_err(|e| {
+                                        format!("Cannot write {}: {}", path.display(), e)
+                                    })?;
+                                    Ok(path)
+                                })
+                                .collect::<Result<_, String>>()
+                        })
+                    })
+                    .map_err(PyRuntimeError::new_err)?;
+                Ok(paths.into_pyobject(py)?.into_any().unbind())
+            }
+        }
+    }
+
+    /// Returns an iterator that generates pickles one at a time.
+    ///
+    /// Yields `count` pickles, or never stops when `count` is `None`. Sample `i`
+    /// uses seed `seed + i` when the generator is seeded, like `generate_batch`,
+    /// so `samples(n)` yields the same pickles as `generate_batch(n)`.
+    #[pyo3(signature = (count=None))]
+    fn samples(&self, count: Option<usize>) -> PyResult<PySampleIterator> {
+        Ok(PySampleIterator {
+            pool: self.pool()?,
+            index: 0,
+            count,
+        })
+    }
+
+    /// Iterating a generator is an unbounded `samples()` stream.
+    fn __iter__(&self) -> PyResult<PySampleIterator> {
+        self.samples(None)
+    }
+
+    /// Protocol version of generated pickles.
+    #[getter]
+    fn protocol(&self) -> usize {
+        self.inner.state.version as usize
+    }
+
+    fn set_protocol(&mut self, protocol: usize) -> PyResult<()> {
+        self.inner.set_version(parse_version(protocol)?);
+        Ok(())
+    }
+
+    /// Seed for reproducible output, or `None` for OS entropy.
+    #[getter]
+    fn seed(&self) -> Option<u64> {
+        self.inner.seed
+    }
+
+    #[pyo3(signature = (seed=None))]
+    fn set_seed(&mut self, seed: Option<u64>) {
+        self.inner.set_seed(seed);
+    }
+
+    #[getter]
+    fn min_opcodes(&self) -> usize {
+        self.inner.min_opcodes
+    }
+
+    #[getter]
+    fn max_opcodes(&self) -> usize {
+        self.inner.max_opcodes
+    }
+
     fn set_opcode_range(&mut self, min: usize, max: usize) {
         self.inner.set_opcode_range(min, max);
     }
 
+    /// Names of the active mutators.
+    #[getter]
+    fn mutators(&self) -> Vec<String> {
+        self.mutator_kinds.iter().map(mutator_name).collect()
+    }
+
+    /// Replaces the active mutators; `["all"]` selects every mutator allowed
+    /// in the current unsafe mode.
+    fn set_mutators(&mut self, names: Vec<String>) -> PyResult<()> {
+        let unsafe_mutations = self.inner.unsafe_mutations;
+        let kinds = parse_mutators(&names, unsafe_mutations)?;
+        self.apply_mutators(kinds, unsafe_mutations)
+    }
+
+    #[getter]
+    fn mutation_rate(&self) -> f64 {
+        self.inner.mutation_rate
+    }
+
+    fn set_mutation_rate(&mut self, rate: f64) -> PyResult<()> {
+        self.inner.mutation_rate = check_mutation_rate(rate)?;
+        Ok(())
+    }
+
+    #[getter]
+    fn unsafe_mutations(&self) -> bool {
+        self.inner.unsafe_mutations
+    }
+
+    /// Switches unsafe mode, rebuilding the active mutators for it.
+    ///
+    /// Turning it off fails while an unsafe-only mutator is configured.
+    fn set_unsafe_mutations(&mut self, unsafe_mutations: bool) -> PyResult<()> {
+        let kinds = self.mutator_kinds.clone();
+        self.apply_mutators(kinds, unsafe_mutations)
+    }
+
+    #[getter]
+    fn allow_ext(&self) -> bool {
+        self.inner.allow_ext_opcodes
+    }
+
+    fn set_allow_ext(&mut self, allow: bool) {
+        self.inner.allow_ext_opcodes = allow;
+    }
+
+    #[getter]
+    fn allow_buffer(&self) -> bool {
+        self.inner.allow_buffer_opcodes
+    }
+
+    fn set_allow_buffer(&mut self, allow: bool) {
+        self.inner.allow_buffer_opcodes = allow;
+    }
+
+    #[getter]
+    fn allow_persistent_ids(&self) -> bool {
+        self.inner.allow_persistent_id_opcodes
+    }
+
+    fn set_allow_persistent_ids(&mut self, allow: bool) {
+        self.inner.allow_persistent_id_opcodes = allow;
+    }
+
     fn reset(&mut self) {
         self.inner.reset();
     }
 }
 
+/// Atheris `custom_mutator` hook: mutates a corpus pickle into a new
+/// structurally valid pickle of at most `max_size` bytes.
+///
+/// See `fuzz_harness::mutate_pickle` for the mutation strategy. The same
+/// `data` and `seed` always give the same result.
+#[pyfunction]
+fn mutate(py: Python, data: &[u8], max_size: usize, seed: u64) -> Py<PyBytes> {
+    let mutant = py.detach(|| crate::fuzz_harness::mutate_pickle(data, max_size, seed));
+    PyBytes::new(py, &mutant).into()
+}
+
+/// Converts a decoded argument to the value `pickletools.genops` yields.
+fn argument_to_py(py: Python<'_>, arg: &Argument) -> PyResult<Py<PyAny>> {
+    Ok(match arg {
+        Argument::None => py.None(),
+        Argument::Bool(value) => value.into_pyobject(py)?.to_owned().into_any().unbind(),
+        Argument::Int(value) => value.into_pyobject(py)?.into_any().unbind(),
+        Argument::BigInt(bytes) => {
+            let kwargs = PyDict::new(py);
+            kwargs.set_item("signed", true)?;
+            py.get_type::<PyInt>()
+                .call_method(
+                    "from_bytes",
+                    (PyBytes::new(py, bytes), "little"),
+                    Some(&kwargs),
+                )?
+                .unbind()
+        }
+        Argument::Float(value) => value.into_pyobject(py)?.into_any().unbind(),
+        Argument::Bytes(bytes) => PyBytes::new(py, bytes).into_any().unbind(),
+        Argument::ByteArray(bytes) => PyByteArray::new(py, bytes).into_any().unbind(),
+        Argument::Str(text) => text.into_pyobject(py)?.into_any().unbind(),
+    })
+}
+
+/// Disassembles a pickle through its first STOP into `(name, arg, pos)`
+/// tuples, the values `pickletools.genops` yields (with opcode names in place
+/// of `OpcodeInfo` objects).
+///
+/// Raises `ValueError` on an unknown opcode, an undecodable argument, or
+/// input that ends before STOP.
+#[pyfunction]
+fn disassemble(py: Python<'_>, data: &[u8]) -> PyResult<Vec<(&'static str, Py<PyAny>, usize)>> {
+    let instructions =
+        disasm::disassemble(data).map_err(|e| PyValueError::new_err(e.to_string()))?;
+    instructions
+        .iter()
+        .map(|instruction| {
+            Ok((
+                instruction.name,
+                argument_to_py(py, &instruction.arg)?,
+                instruction.pos,
+            ))
+        })
+        .collect()
+}
+
+/// Checks that `data` is exactly one consistent pickle, with the checks of
+/// `pickletools.dis`. Returns `(True, "")` or `(False, reason)`.
+#[pyfunction]
+fn validate(py: Python<'_>, data: &[u8]) -> (bool, String) {
+    match py.detach(|| disasm::validate(data)) {
+        Ok(()) => (true, String::new()),
+        Err(e) => (false, e.to_string()),
+    }
+}
+
 #[pymodule]
 fn _native(parent_module: &Bound<'_, PyModule>) -> PyResult<()> {
     parent_module.add_class::<PyGenerator>()?;
+    parent_module.add_class::<PySampleIterator>()?;
+    parent_module.add_function(wrap_pyfunction!(mutate, parent_module)?)?;
+    parent_module.add_function(wrap_pyfunction!(disassemble, parent_module)?)?;
+    parent_module.add_function(wrap_pyfunction!(validate, parent_module)?)?;
+    parent_module.add(
+        "GENERATOR_FORMAT_VERSION",
+        crate::generator::GENERATOR_FORMAT_VERSION,
+    )?;
     Ok(())
 }
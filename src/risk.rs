@@ -0,0 +1,293 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! lightweight danger classification of pickles.
+//!
+//! [`classify`] labels a pickle with the worst [`Risk`] its opcodes carry:
+//! plain data, imports, calls of callables off [`SAFE_GLOBALS`], or calls with
+//! a code execution gadget from [`GADGETS`] imported. it looks at which
+//! globals are imported and whether anything is called, without simulating
+//! the stack, so a pickle that imports `os.system` and calls `builtins.set` is
+//! labelled as if it called `os.system`: the labels err towards danger.
+//!
+//! it is meant for checking that a way of generating pickles stays as
+//! harmless as intended, and for labelling datasets; `pickle-fuzzer analyze`
+//! prints how many pickles of a corpus get each label.
+
+use std::fmt;
+
+use crate::disasm::read_opcode;
+use crate::opcodes::Opcode;
+
+/// how dangerous unpickling a pickle is, from harmless to code execution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Risk {
+    /// imports nothing, so unpickling only builds containers and scalars
+    Data,
+    /// imports globals, but calls none, or only ones in [`SAFE_GLOBALS`]
+    Import,
+    /// calls with a global imported that isn't in [`SAFE_GLOBALS`]
+    Call,
+    /// calls with a global in [`GADGETS`] imported
+    Gadget,
+}
+
+impl Risk {
+    /// every risk, from harmless to code execution.
+    pub fn all() -> [Risk; 4] {
+        [Risk::Data, Risk::Import, Risk::Call, Risk::Gadget]
+    }
+}
+
+impl fmt::Display for Risk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Risk::Data => "data",
+            Risk::Import => "import",
+            Risk::Call => "call",
+            Risk::Gadget => "gadget",
+        })
+    }
+}
+
+/// globals that only build data when called, as module and name: the
+/// builtin types, the `copyreg` and `_codecs` helpers `pickle` itself emits,
+/// and stdlib and numpy value types.
+pub const SAFE_GLOBALS: &[(&str, &str)] = &[
+    ("builtins", "bool"),
+    ("builtins", "bytearray"),
+    ("builtins", "bytes"),
+    ("builtins", "complex"),
+    ("builtins", "dict"),
+    ("builtins", "float"),
+    ("builtins", "frozenset"),
+    ("builtins", "int"),
+    ("builtins", "list"),
+    ("builtins", "object"),
+    ("builtins", "range"),
+    ("builtins", "set"),
+    ("builtins", "slice"),
+    ("builtins", "str"),
+    ("builtins", "tuple"),
+    ("copyreg", "_reconstructor"),
+    ("copyreg", "__newobj__"),
+    ("copyreg", "__newobj_ex__"),
+    ("_codecs", "encode"),
+    ("collections", "OrderedDict"),
+    ("collections", "deque"),
+    ("datetime", "date"),
+    ("datetime", "datetime"),
+    ("datetime", "time"),
+    ("datetime", "timedelta"),
+    ("datetime", "timezone"),
+    ("decimal", "Decimal"),
+    ("fractions", "Fraction"),
+    ("numpy", "dtype"),
+    ("numpy", "ndarray"),
+    ("numpy.core.multiarray", "_reconstruct"),
+    ("numpy._core.multiarray", "_reconstruct"),
+];
+
+/// globals known to run code, touch files, or reach the network when called,
+/// as module and name; a name of `*` stands for the whole module.
+pub const GADGETS: &[(&str, &str)] = &[
+    ("os", "system"),
+    ("os", "popen"),
+    ("os", "execv"),
+    ("os", "execve"),
+    ("os", "execvp"),
+    ("os", "spawnv"),
+    ("os", "spawnlp"),
+    ("os", "remove"),
+    ("os", "unlink"),
+    ("posix", "system"),
+    ("posix", "popen"),
+    ("nt", "system"),
+    ("subprocess", "*"),
+    ("pty", "spawn"),
+    ("builtins", "eval"),
+    ("builtins", "exec"),
+    ("builtins", "compile"),
+    ("builtins", "__import__"),
+    ("builtins", "getattr"),
+    ("builtins", "setattr"),
+    ("builtins", "open"),
+    ("builtins", "breakpoint"),
+    ("importlib", "import_module"),
+    ("runpy", "*"),
+    ("code", "*"),
+    ("pickle", "loads"),
+    ("pickle", "load"),
+    ("_pickle", "loads"),
+    ("marshal", "loads"),
+    ("shutil", "rmtree"),
+    ("shutil", "move"),
+    ("socket", "*"),
+    ("webbrowser", "*"),
+];
+
+fn listed(list: &[(&str, &str)], module: &str, name: &str) -> bool {
+    list.iter()
+        .any(|&(m, n)| m == module && (n == "*" || n == name))
+}
+
+/// label `pickle` with the worst [`Risk`] its opcodes carry.
+///
+/// opcodes are read up to STOP or the first one that doesn't decode, since
+/// those are the ones an unpickler runs before it fails. REDUCE, NEWOBJ,
+/// NEWOBJ_EX, OBJ, and INST call; GLOBAL, INST, STACK_GLOBAL, and EXT import.
+/// an import whose name isn't known, like an EXT code or a STACK_GLOBAL of
+/// strings built some other way than pushed right before it, counts as off
+/// the allowlist.
+///
+/// # Examples
+///
+/// ```
+/// use pickle_fuzzer::risk::{classify, Risk};
+///
+/// assert_eq!(classify(b"\x80\x02]q\x00K\x01a."), Risk::Data);
+/// assert_eq!(classify(b"cos\nsystem\n."), Risk::Import);
+/// assert_eq!(classify(b"cbuiltins\nset\n)R."), Risk::Import);
+/// assert_eq!(classify(b"ctime\nsleep\n(K\x01tR."), Risk::Call);
+/// assert_eq!(classify(b"cos\nsystem\n(S'id'\ntR."), Risk::Gadget);
+/// ```
+pub fn classify(pickle: &[u8]) -> Risk {
+    // every import, with None for one whose name isn't known
+    let mut imports: Vec<Option<(String, String)>> = Vec::new();
+    // strings pushed since the last other opcode, for STACK_GLOBAL
+    let mut strings: Vec<String> = Vec::new();
+    let mut calls = false;
+
+    let mut rest = pickle;
+    while let Ok((opcode, len)) = read_opcode(rest) {
+        rest = &rest[len..];
+        match opcode {
+            Opcode::Unicode(text)
+            | Opcode::ShortBinUnicode(text)
+            | Opcode::BinUnicode(text)
+            | Opcode::BinUnicode8(text) => {
+                strings.push(text);
+                continue;
+            }
+            // memo stores leave the strings where they were
+            Opcode::Put(_) | Opcode::BinPut(_) | Opcode::LongBinPut(_) | Opcode::Memoize => {
+                continue;
+            }
+            Opcode::Global(module, name) => imports.push(Some((module, name))),
+            Opcode::Inst(module, name) => {
+                imports.push(Some((module, name)));
+                calls = true;
+            }
+            Opcode::StackGlobal => imports.push(match strings[..] {
+                [.., ref module, ref name] => Some((module.clone(), name.clone())),
+                _ => None,
+            }),
+            Opcode::Ext1(_) | Opcode::Ext2(_) | Opcode::Ext4(_) => imports.push(None),
+            Opcode::Reduce | Opcode::NewObj | Opcode::NewObjEx | Opcode::Obj => calls = true,
+            Opcode::Stop => break,
+            _ => {}
+        }
+        strings.clear();
+    }
+
+    let known = |list| {
+        move |import: &Option<(String, String)>| {
+            import
+                .as_ref()
+                .is_some_and(|(module, name)| listed(list, module, name))
+        }
+    };
+    if imports.is_empty() {
+        Risk::Data
+    } else if !calls {
+        Risk::Import
+    } else if imports.iter().any(known(GADGETS)) {
+        Risk::Gadget
+    } else if imports.iter().all(known(SAFE_GLOBALS)) {
+        Risk::Import
+    } else {
+        Risk::Call
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Generator, Version};
+
+    #[test]
+    fn stack_globals_are_named_after_the_pushed_strings() {
+        // STACK_GLOBAL of "os" "system", memoized, called
+        let pickle = b"\x80\x04\x8c\x02os\x94\x8c\x06system\x94\x93\x8c\x02id\x85R.";
+        assert_eq!(classify(pickle), Risk::Gadget);
+        let pickle = b"\x80\x04\x8c\x08builtins\x8c\x03set\x93)R.";
+        assert_eq!(classify(pickle), Risk::Import);
+        // strings fetched from the memo leave the callable unknown
+        let pickle = b"\x80\x04\x8c\x02os\x94h\x00\x8c\x06system\x93)R.";
+        assert_eq!(classify(pickle), Risk::Call);
+    }
+
+    #[test]
+    fn opcodes_after_a_broken_one_are_not_read() {
+        assert_eq!(classify(b"K\x01\xffcos\nsystem\n)R."), Risk::Data);
+        assert_eq!(classify(b"cos\nsystem\n)R"), Risk::Gadget);
+        assert_eq!(classify(b""), Risk::Data);
+    }
+
+    #[test]
+    fn pickles_without_import_opcodes_are_data() {
+        use crate::OpcodeKind as Op;
+
+        let imports = |_: &crate::State, opcode| {
+            matches!(
+                opcode,
+                Op::Global | Op::Inst | Op::StackGlobal | Op::Ext1 | Op::Ext2 | Op::Ext4
+            )
+        };
+        for version in Version::all() {
+            for seed in 0..20 {
+                let pickle = Generator::new(version)
+                    .with_seed(seed)
+                    .with_opcode_filter(move |state, opcode| !imports(state, opcode))
+                    .generate()
+                    .unwrap();
+                assert_eq!(
+                    classify(&pickle),
+                    Risk::Data,
+                    "protocol {version}: {pickle:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn random_pickles_get_labels_above_data() {
+        let risks: std::collections::BTreeSet<Risk> = (0..100)
+            .map(|seed| {
+                classify(
+                    &Generator::new(Version::V2)
+                        .with_seed(seed)
+                        .generate()
+                        .unwrap(),
+                )
+            })
+            .collect();
+        assert!(
+            risks.contains(&Risk::Import) && risks.contains(&Risk::Call),
+            "{risks:?}"
+        );
+    }
+}
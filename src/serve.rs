@@ -0,0 +1,504 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! HTTP generation service (`pickle-fuzzer serve`, behind the `serve` feature).
+//!
+//! lets fuzzing infrastructure that isn't written in Rust request samples on
+//! demand instead of spawning the CLI for every pickle. the server speaks just
+//! enough HTTP/1.1 for that, one request per connection, with no dependencies
+//! beyond the standard library:
+//!
+//! - `POST /generate` with an optional JSON [`GeneratorConfig`] body returns one
+//!   pickle as `application/octet-stream`. the `X-Pickle-Protocol` and
+//!   `X-Pickle-Format-Version` headers describe it.
+//! - `GET /health` returns `ok`.
+//! - `GET /metrics` returns the server's [`Metrics`] in the Prometheus text
+//!   format: pickles served, their bytes, the ones that fail validation or
+//!   fail to generate, and how often each mutator fired.
+//!
+//! invalid configurations get a `400` with the reason as plain text. the
+//! server handles at most [`MAX_CONNECTIONS`] connections at once and answers
+//! any more with a `503`.
+//!
+//! ```text
+//! $ pickle-fuzzer serve --bind 127.0.0.1:8000 &
+//! $ curl -s -X POST localhost:8000/generate -d '{"protocol": 4, "seed": 7}' -o sample.pkl
+//! ```
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use color_eyre::Result;
+use tracing::field::Empty;
+
+use crate::metrics::Metrics;
+use crate::{disasm, GeneratorConfig, GENERATOR_FORMAT_VERSION};
+
+/// largest request body the server reads.
+const MAX_BODY_SIZE: usize = 64 * 1024;
+
+/// largest request line or header line the server reads.
+const MAX_LINE_SIZE: usize = 8 * 1024;
+
+/// most header lines a request may send.
+const MAX_HEADERS: usize = 64;
+
+/// most connections the server handles at once.
+pub const MAX_CONNECTIONS: usize = 64;
+
+/// how long a connection may stall while sending its request.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// how long a refused connection may stall while reading its `503`.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// an HTTP response ready to be written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Response {
+    status: u16,
+    headers: Vec<(&'static str, String)>,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn text(status: u16, body: impl Into<String>) -> Self {
+        let mut body = body.into().into_bytes();
+        body.push(b'\n');
+        Self {
+            status,
+            headers: vec![("Content-Type", "text/plain; charset=utf-8".into())],
+            body,
+        }
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            413 => "Payload Too Large",
+            422 => "Unprocessable Entity",
+            431 => "Request Header Fields Too Large",
+            503 => "Service Unavailable",
+            _ => "Error",
+        }
+    }
+
+    fn write_to(&self, w: &mut impl Write) -> std::io::Result<()> {
+        write!(w, "HTTP/1.1 {} {}\r\n", self.status, self.reason())?;
+        for (name, value) in &self.headers {
+            write!(w, "{name}: {value}\r\n")?;
+        }
+        write!(
+            w,
+            "Content-Length: {}\r\nConnection: close\r\n\r\n",
+            self.body.len()
+        )?;
+        w.write_all(&self.body)?;
+        w.flush()
+    }
+}
+
+/// answer one request.
+fn route(method: &str, path: &str, body: &[u8], metrics: &Metrics) -> Response {
+    match (method, path) {
+        ("POST", "/generate") => generate(body, metrics),
+        ("GET", "/health") => Response::text(200, "ok"),
+        ("GET", "/metrics") => Response {
+            status: 200,
+            headers: vec![("Content-Type", "text/plain; version=0.0.4".into())],
+            body: metrics.render().into_bytes(),
+        },
+        (_, "/generate" | "/health" | "/metrics") => {
+            Response::text(405, format!("{method} not allowed"))
+        }
+        _ => Response::text(404, format!("no route for {path}")),
+    }
+}
+
+fn generate(body: &[u8], metrics: &Metrics) -> Response {
+    let config = if body.iter().all(u8::is_ascii_whitespace) {
+        Ok(GeneratorConfig::default())
+    } else {
+        GeneratorConfig::from_json(body)
+    };
+    let mut generator = match config.and_then(|config| config.build()) {
+        Ok(generator) => generator,
+        Err(e) => return Response::text(400, format!("invalid configuration: {e}")),
+    };
+
+    match generator.generate() {
+        Ok(pickle) => {
+            let valid = disasm::validate(&pickle).is_ok();
+            metrics.record_sample(pickle.len(), valid, &generator.mutator_applications());
+            Response {
+                status: 200,
+                headers: vec![
+                    ("Content-Type", "application/octet-stream".into()),
+                    ("X-Pickle-Protocol", generator.state.version.to_string()),
+                    (
+                        "X-Pickle-Format-Version",
+                        GENERATOR_FORMAT_VERSION.to_string(),
+                    ),
+                ],
+                body: pickle,
+            }
+        }
+        // e.g. a byte or opcode budget too small for the protocol
+        Err(e) => {
+            tracing::warn!(error = %e, "generation failed");
+            metrics.record_failure();
+            Response::text(422, format!("generation failed: {e}"))
+        }
+    }
+}
+
+/// read one line of at most `MAX_LINE_SIZE` bytes, without its line ending.
+fn read_line(reader: &mut impl BufRead) -> std::result::Result<String, Response> {
+    let mut line = Vec::new();
+    reader
+        .take(MAX_LINE_SIZE as u64 + 1)
+        .read_until(b'\n', &mut line)
+        .map_err(|e| Response::text(400, format!("failed to read request: {e}")))?;
+    if line.len() > MAX_LINE_SIZE {
+        return Err(Response::text(413, "request line or header too long"));
+    }
+    if line.last() != Some(&b'\n') {
+        return Err(Response::text(400, "incomplete request"));
+    }
+    let line = String::from_utf8(line).map_err(|_| Response::text(400, "request is not UTF-8"))?;
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// read a request from `reader` and produce its response.
+fn respond(reader: &mut impl BufRead, metrics: &Metrics) -> Response {
+    let parsed = (|| {
+        let request_line = read_line(reader)?;
+        let mut parts = request_line.split_whitespace();
+        let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+            return Err(Response::text(400, "malformed request line"));
+        };
+        let path = target.split('?').next().unwrap_or(target).to_string();
+        let method = method.to_string();
+
+        let mut content_length = 0;
+        for headers in 0.. {
+            let header = read_line(reader)?;
+            if header.is_empty() {
+                break;
+            }
+            if headers == MAX_HEADERS {
+                return Err(Response::text(431, "too many headers"));
+            }
+            let Some((name, value)) = header.split_once(':') else {
+                return Err(Response::text(400, "malformed header"));
+            };
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value
+                    .parse::<usize>()
+                    .map_err(|_| Response::text(400, "invalid Content-Length"))?;
+            } else if name.eq_ignore_ascii_case("transfer-encoding") {
+                return Err(Response::text(
+                    400,
+                    "chunked request bodies are not supported",
+                ));
+            }
+        }
+        if content_length > MAX_BODY_SIZE {
+            return Err(Response::text(413, "request body too large"));
+        }
+
+        let mut body = vec![0; content_length];
+        reader
+            .read_exact(&mut body)
+            .map_err(|_| Response::text(400, "request body shorter than Content-Length"))?;
+        Ok((method, path, body))
+    })();
+
+    match parsed {
+        Ok((method, path, body)) => {
+            tracing::Span::current()
+                .record("method", method.as_str())
+                .record("path", path.as_str());
+            route(&method, &path, &body, metrics)
+        }
+        Err(response) => response,
+    }
+}
+
+fn handle_connection(stream: TcpStream, metrics: &Metrics) -> std::io::Result<()> {
+    let peer = stream.peer_addr()?;
+    let _request = tracing::info_span!("request", %peer, method = Empty, path = Empty).entered();
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let response = respond(&mut reader, metrics);
+    tracing::info!(
+        status = response.status,
+        bytes = response.body.len(),
+        "responded"
+    );
+    response.write_to(&mut &stream)
+}
+
+/// a connection's place in the server's count of open connections, given
+/// back when the connection is done.
+struct ConnectionSlot(Arc<AtomicUsize>);
+
+impl ConnectionSlot {
+    /// take one of `limit` places in `open`, if one is free.
+    fn take(open: &Arc<AtomicUsize>, limit: usize) -> Option<Self> {
+        open.fetch_update(Ordering::AcqRel, Ordering::Acquire, |count| {
+            (count < limit).then_some(count + 1)
+        })
+        .ok()
+        .map(|_| Self(Arc::clone(open)))
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// serve requests from `listener`.
+///
+/// each connection is handled on its own thread, up to [`MAX_CONNECTIONS`] at
+/// once; connections past that get a `503`. a connection that fails to be
+/// accepted is logged and skipped.
+pub fn serve_listener(listener: TcpListener) -> Result<()> {
+    serve_connections(listener, MAX_CONNECTIONS)
+}
+
+fn serve_connections(listener: TcpListener, max_connections: usize) -> Result<()> {
+    let metrics = Arc::new(Metrics::new());
+    let open = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        // e.g. a peer that reset before it was accepted, or no file
+        // descriptors left; neither stops the server
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                tracing::warn!(error = %e, "failed to accept a connection");
+                continue;
+            }
+        };
+        let Some(slot) = ConnectionSlot::take(&open, max_connections) else {
+            tracing::warn!(max_connections, "too many connections");
+            let refused = stream
+                .set_write_timeout(Some(WRITE_TIMEOUT))
+                .and_then(|()| Response::text(503, "too many connections").write_to(&mut &stream));
+            if let Err(e) = refused {
+                tracing::warn!(error = %e, "connection failed");
+            }
+            continue;
+        };
+        let metrics = Arc::clone(&metrics);
+        std::thread::spawn(move || {
+            let _slot = slot;
+            if let Err(e) = handle_connection(stream, &metrics) {
+                tracing::warn!(error = %e, "connection failed");
+            }
+        });
+    }
+    Ok(())
+}
+
+/// bind `addr` and serve requests on it.
+pub fn serve(addr: impl ToSocketAddrs) -> Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    tracing::info!("serving pickles on http://{}", listener.local_addr()?);
+    serve_listener(listener)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Generator, Version};
+
+    fn request(raw: &[u8]) -> Response {
+        respond(&mut BufReader::new(raw), &Metrics::new())
+    }
+
+    #[test]
+    fn generate_returns_the_seeded_pickle() {
+        let body = br#"{"protocol": 4, "seed": 7, "max_opcodes": 80}"#;
+        let mut raw = format!(
+            "POST /generate HTTP/1.1\r\nHost: x\r\nContent-Length: {}\r\n\r\n",
+            body.len()
+        )
+        .into_bytes();
+        raw.extend_from_slice(body);
+
+        let response = request(&raw);
+        assert_eq!(response.status, 200);
+        assert!(response
+            .headers
+            .contains(&("X-Pickle-Protocol", "4".to_string())));
+
+        let expected = Generator::new(Version::V4)
+            .with_seed(7)
+            .with_opcode_range(60, 80)
+            .generate()
+            .unwrap();
+        assert_eq!(response.body, expected);
+    }
+
+    #[test]
+    fn empty_body_uses_defaults() {
+        let response = request(b"POST /generate HTTP/1.1\r\n\r\n");
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body.last(), Some(&b'.'));
+    }
+
+    #[test]
+    fn invalid_configurations_are_rejected() {
+        for body in [
+            r#"{"protocol": 9}"#,
+            r#"{"mutators": ["nope"]}"#,
+            r#"{"mutators": ["memoindex"]}"#,
+            r#"{"mutation_rate": 2.0}"#,
+            r#"{"cleanup_policy": "pop"}"#,
+            r#"{"colour": "blue"}"#,
+            "not json",
+        ] {
+            let raw = format!(
+                "POST /generate HTTP/1.1\r\nContent-Length: {}\r\n\r\n{body}",
+                body.len()
+            );
+            assert_eq!(request(raw.as_bytes()).status, 400, "{body}");
+        }
+
+        let raw = r#"{"protocol": 2, "max_size": 3}"#;
+        let raw = format!(
+            "POST /generate HTTP/1.1\r\nContent-Length: {}\r\n\r\n{raw}",
+            raw.len()
+        );
+        assert_eq!(request(raw.as_bytes()).status, 422);
+    }
+
+    #[test]
+    fn metrics_count_served_pickles() {
+        let metrics = Metrics::new();
+        let generate = |body: &str| {
+            let raw = format!(
+                "POST /generate HTTP/1.1\r\nContent-Length: {}\r\n\r\n{body}",
+                body.len()
+            );
+            respond(&mut BufReader::new(raw.as_bytes()), &metrics)
+        };
+        let served = generate(r#"{"seed": 1, "mutators": ["bitflip"], "mutation_rate": 0.5}"#);
+        assert_eq!(served.status, 200);
+        assert_eq!(generate(r#"{"protocol": 2, "max_size": 3}"#).status, 422);
+
+        let response = respond(
+            &mut BufReader::new(&b"GET /metrics HTTP/1.1\r\n\r\n"[..]),
+            &metrics,
+        );
+        assert_eq!(response.status, 200);
+        let text = String::from_utf8(response.body).unwrap();
+        assert!(text.contains("pickle_fuzzer_samples_total 1\n"), "{text}");
+        assert!(text.contains(&format!(
+            "pickle_fuzzer_bytes_written_total {}\n",
+            served.body.len()
+        )));
+        assert!(text.contains("pickle_fuzzer_failed_samples_total 1\n"));
+        assert!(text.contains("pickle_fuzzer_mutator_applications_total{mutator=\"bitflip\"}"));
+    }
+
+    #[test]
+    fn routing_and_framing_errors() {
+        assert_eq!(request(b"GET /health HTTP/1.1\r\n\r\n").body, b"ok\n");
+        assert_eq!(request(b"GET /generate HTTP/1.1\r\n\r\n").status, 405);
+        assert_eq!(request(b"GET /other HTTP/1.1\r\n\r\n").status, 404);
+        assert_eq!(request(b"garbage\r\n\r\n").status, 400);
+        assert_eq!(
+            request(b"POST /generate HTTP/1.1\r\nContent-Length: 10\r\n\r\n{}").status,
+            400
+        );
+        let too_large = format!(
+            "POST /generate HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY_SIZE + 1
+        );
+        assert_eq!(request(too_large.as_bytes()).status, 413);
+
+        let headers = |count: usize| {
+            let mut raw = "GET /health HTTP/1.1\r\n".to_string();
+            for i in 0..count {
+                raw.push_str(&format!("X-Padding-{i}: x\r\n"));
+            }
+            raw + "\r\n"
+        };
+        assert_eq!(request(headers(MAX_HEADERS).as_bytes()).status, 200);
+        assert_eq!(request(headers(MAX_HEADERS + 1).as_bytes()).status, 431);
+    }
+
+    #[test]
+    fn serves_over_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || serve_listener(listener));
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(b"POST /generate HTTP/1.1\r\nContent-Length: 12\r\n\r\n{\"seed\": 3}\n")
+            .unwrap();
+        let mut raw = Vec::new();
+        stream.read_to_end(&mut raw).unwrap();
+
+        let split = raw.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        let head = String::from_utf8_lossy(&raw[..split]);
+        assert!(head.starts_with("HTTP/1.1 200 OK"), "{head}");
+        assert!(head.contains("Content-Type: application/octet-stream"));
+        assert_eq!(raw.last(), Some(&b'.'));
+    }
+
+    #[test]
+    fn refuses_connections_past_the_limit() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || serve_connections(listener, 1));
+
+        // holds the only slot until it finishes its request
+        let mut idle = TcpStream::connect(addr).unwrap();
+        let mut refused = TcpStream::connect(addr).unwrap();
+        let mut raw = Vec::new();
+        refused.read_to_end(&mut raw).unwrap();
+        assert!(raw.starts_with(b"HTTP/1.1 503 Service Unavailable\r\n"));
+
+        idle.write_all(b"GET /health HTTP/1.1\r\n\r\n").unwrap();
+        idle.read_to_end(&mut Vec::new()).unwrap();
+        // the slot is given back once the thread is done with it
+        let response = loop {
+            // a refused connection may be reset before or after its 503
+            let mut stream = TcpStream::connect(addr).unwrap();
+            let mut raw = Vec::new();
+            let answered = stream
+                .write_all(b"GET /health HTTP/1.1\r\n\r\n")
+                .and_then(|()| stream.read_to_end(&mut raw));
+            if answered.is_ok() && !raw.starts_with(b"HTTP/1.1 503") {
+                break raw;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        };
+        assert!(response.ends_with(b"\r\n\r\nok\n"));
+    }
+}
@@ -19,6 +19,8 @@ use std::collections::{HashMap, HashSet};
 use std::hash::{Hash, Hasher};
 use std::rc::Rc;
 
+use smallvec::SmallVec;
+
 thread_local! {
     static DROP_CYCLE_CLEANUP_ACTIVE: Cell<bool> = const { Cell::new(false) };
 }
@@ -27,10 +29,18 @@ thread_local! {
 ///
 /// The stack holds objects during pickle generation, mirroring the behavior
 /// of Python's pickle unpickler.
+///
+/// Alongside the items it tracks the position of every MARK, updated on push
+/// and pop, so MARK-related queries used during opcode validation are O(1)
+/// instead of a scan of the whole stack.
 #[derive(Debug, Default, Clone)]
 pub struct Stack {
     /// Internal stack storage
-    pub inner: Vec<StackObjectRef>,
+    inner: Vec<StackObjectRef>,
+    /// Indices into `inner` of every MARK, bottom to top
+    marks: Vec<usize>,
+    /// Largest length the stack reached since the last reset
+    peak: usize,
 }
 
 impl Stack {
@@ -42,16 +52,31 @@ impl Stack {
     /// Clear all items from the stack.
     pub fn reset(&mut self) {
         self.inner.clear();
+        self.marks.clear();
+        self.peak = 0;
     }
 
     /// Push a value onto the stack.
     pub fn push(&mut self, value: StackObject) {
-        self.inner.push(StackObjectRef::new(value));
+        self.push_ref(StackObjectRef::new(value));
+    }
+
+    /// Push an existing shared object onto the stack.
+    pub fn push_ref(&mut self, value: StackObjectRef) {
+        if matches!(*value.borrow(), StackObject::Mark) {
+            self.marks.push(self.inner.len());
+        }
+        self.inner.push(value);
+        self.peak = self.peak.max(self.inner.len());
     }
 
     /// Pop a value from the stack.
     pub fn pop(&mut self) -> Option<StackObjectRef> {
-        self.inner.pop()
+        let value = self.inner.pop()?;
+        if self.marks.last() == Some(&self.inner.len()) {
+            self.marks.pop();
+        }
+        Some(value)
     }
 
     /// Peek at the top value without removing it.
@@ -59,10 +84,54 @@ impl Stack {
         self.inner.last()
     }
 
+    /// Peek at the value `depth` items below the top (0 is the top).
+    pub fn peek_at(&self, depth: usize) -> Option<&StackObjectRef> {
+        let idx = self.inner.len().checked_sub(depth + 1)?;
+        self.inner.get(idx)
+    }
+
     /// Get the current stack depth.
+    // validation reads better comparing depths (`len() >= 1` next to `len() >= 2`)
+    #[allow(clippy::len_without_is_empty)]
     pub fn len(&self) -> usize {
         self.inner.len()
     }
+
+    /// Largest stack depth reached since the stack was created or last reset.
+    pub fn peak_len(&self) -> usize {
+        self.peak
+    }
+
+    /// All items, bottom to top.
+    pub fn items(&self) -> &[StackObjectRef] {
+        &self.inner
+    }
+
+    /// Indices of every MARK on the stack, bottom to top.
+    pub fn mark_positions(&self) -> &[usize] {
+        &self.marks
+    }
+
+    /// Check if the stack contains any MARK.
+    pub fn has_mark(&self) -> bool {
+        !self.marks.is_empty()
+    }
+
+    /// Index of the topmost MARK, if any.
+    pub fn top_mark(&self) -> Option<usize> {
+        self.marks.last().copied()
+    }
+
+    /// Number of items above the topmost MARK, or `None` without a MARK.
+    pub fn items_above_mark(&self) -> Option<usize> {
+        self.top_mark().map(|idx| self.inner.len() - idx - 1)
+    }
+
+    /// Check whether any of the top `count` items is a MARK.
+    pub fn has_mark_in_top(&self, count: usize) -> bool {
+        self.top_mark()
+            .is_some_and(|idx| idx + count >= self.inner.len())
+    }
 }
 
 /// Reference-counted wrapper around a stack object with interior mutability.
@@ -142,6 +211,33 @@ impl StackObjectRef {
         }
     }
 
+    /// Replace a container holding more than `limit` elements with a
+    /// [`StackObject::Summarized`] placeholder.
+    ///
+    /// The replacement happens in place, so every stack slot and memo entry that
+    /// aliases this object sees the summary. Returns `true` if the object was
+    /// summarized.
+    pub fn summarize_if_larger_than(&self, limit: usize) -> bool {
+        let summary = {
+            let Ok(obj) = self.0.try_borrow() else {
+                return false;
+            };
+            if matches!(*obj, StackObject::Summarized { .. }) {
+                return false;
+            }
+            match (obj.container_kind(), obj.container_len()) {
+                (Some(kind), Some(len)) if len > limit => StackObject::Summarized { kind, len },
+                _ => return false,
+            }
+        };
+
+        // the dropped elements may still be referenced from elsewhere, so they are
+        // released only after the borrow is gone
+        let previous = std::mem::replace(&mut *self.0.borrow_mut(), summary);
+        drop(previous);
+        true
+    }
+
     fn ptr_addr(&self) -> usize {
         Rc::as_ptr(&self.0) as *const () as usize
     }
@@ -166,7 +262,7 @@ impl StackObjectRef {
         };
 
         match &*obj {
-            StackObject::List(items) | StackObject::Tuple(items) => items.clone(),
+            StackObject::List(items) | StackObject::Tuple(items) => items.to_vec(),
             StackObject::Dict(items) => {
                 let mut children = Vec::with_capacity(items.len() * 2);
                 for (key, value) in items {
@@ -285,6 +381,9 @@ pub enum StackObject {
     // Scalar types
     /// Integer value
     Int(i64),
+    /// Integer outside the range of `Int`, as little-endian two's complement
+    /// bytes (the `LONG1`/`LONG4` encoding)
+    BigInt(Vec<u8>),
     /// Floating point value
     Float(f64),
     /// Boolean value
@@ -302,9 +401,9 @@ pub enum StackObject {
 
     // Container types (can be recursive)
     /// List of objects
-    List(Vec<StackObjectRef>),
+    List(Items),
     /// Tuple of objects
-    Tuple(Vec<StackObjectRef>),
+    Tuple(Items),
     /// Dictionary mapping keys to values
     Dict(HashMap<StackObjectRef, StackObjectRef>),
     /// Set of unique objects
@@ -332,6 +431,67 @@ pub enum StackObject {
     /// Generic placeholder for unimplemented types
     #[allow(dead_code)]
     Any,
+
+    /// Container that grew past the generator's simulation size limit.
+    ///
+    /// Only its type and element count are tracked, so it satisfies the same
+    /// validation checks as the container it replaced without holding on to
+    /// every element. For dicts and sets the count is an upper bound, as
+    /// duplicate keys are no longer detected.
+    Summarized {
+        /// Container type the summary stands in for
+        kind: ContainerKind,
+        /// Number of elements (entries for dicts)
+        len: usize,
+    },
+}
+
+/// Element storage for simulated lists and tuples.
+///
+/// Most generated sequences are tiny (TUPLE1-3, short MARK groups), so a few
+/// elements are stored inline and only larger ones allocate. Four references
+/// fit in the space the largest `StackObject` variant already needs.
+pub type Items = SmallVec<[StackObjectRef; 4]>;
+
+/// Container types that can be tracked as a [`StackObject::Summarized`] placeholder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ContainerKind {
+    List,
+    Tuple,
+    Dict,
+    Set,
+    FrozenSet,
+}
+
+impl StackObject {
+    /// Container type of this object, treating summarized containers like the real thing.
+    pub fn container_kind(&self) -> Option<ContainerKind> {
+        match self {
+            StackObject::List(_) => Some(ContainerKind::List),
+            StackObject::Tuple(_) => Some(ContainerKind::Tuple),
+            StackObject::Dict(_) => Some(ContainerKind::Dict),
+            StackObject::Set(_) => Some(ContainerKind::Set),
+            StackObject::FrozenSet(_) => Some(ContainerKind::FrozenSet),
+            StackObject::Summarized { kind, .. } => Some(*kind),
+            _ => None,
+        }
+    }
+
+    /// Number of elements in a container (entries for dicts), or `None` for non-containers.
+    pub fn container_len(&self) -> Option<usize> {
+        match self {
+            StackObject::List(items) | StackObject::Tuple(items) => Some(items.len()),
+            StackObject::Dict(items) => Some(items.len()),
+            StackObject::Set(items) | StackObject::FrozenSet(items) => Some(items.len()),
+            StackObject::Summarized { len, .. } => Some(*len),
+            _ => None,
+        }
+    }
+
+    /// Check whether this object is a container of the given kind.
+    pub fn is_container(&self, kind: ContainerKind) -> bool {
+        self.container_kind() == Some(kind)
+    }
 }
 
 /// Represents a Python instance created via REDUCE/BUILD opcodes.
@@ -362,9 +522,78 @@ mod tests {
         assert_ne!(first, second);
     }
 
+    #[test]
+    fn inline_items_do_not_grow_stack_objects() {
+        assert!(
+            std::mem::size_of::<Items>()
+                <= std::mem::size_of::<HashMap<StackObjectRef, StackObjectRef>>()
+        );
+    }
+
+    #[test]
+    fn stack_tracks_mark_positions() {
+        let mut stack = Stack::new();
+        stack.push(StackObject::Int(1));
+        stack.push(StackObject::Mark);
+        stack.push(StackObject::Int(2));
+        stack.push_ref(StackObjectRef::new(StackObject::Mark));
+        stack.push(StackObject::Int(3));
+
+        assert_eq!(stack.mark_positions(), &[1, 3]);
+        assert_eq!(stack.top_mark(), Some(3));
+        assert_eq!(stack.peak_len(), 5);
+        assert_eq!(stack.items_above_mark(), Some(1));
+        assert!(stack.has_mark_in_top(2));
+        assert!(!stack.has_mark_in_top(1));
+
+        stack.pop();
+        stack.pop();
+        assert_eq!(stack.mark_positions(), &[1]);
+        assert_eq!(stack.peak_len(), 5);
+        assert_eq!(stack.items_above_mark(), Some(1));
+        assert!(matches!(
+            *stack.peek_at(1).unwrap().borrow(),
+            StackObject::Mark
+        ));
+
+        stack.reset();
+        assert!(!stack.has_mark());
+        assert_eq!(stack.peak_len(), 0);
+        assert_eq!(stack.items_above_mark(), None);
+        assert!(stack.peek_at(0).is_none());
+    }
+
+    #[test]
+    fn summarize_replaces_oversized_container_in_place() {
+        let list = StackObjectRef::new(StackObject::List(Items::new()));
+        let alias = list.clone();
+        if let StackObject::List(items) = &mut *list.borrow_mut() {
+            items.extend((0..5).map(|i| StackObjectRef::new(StackObject::Int(i))));
+        }
+
+        assert!(!list.summarize_if_larger_than(5));
+        assert!(list.summarize_if_larger_than(4));
+        assert!(matches!(
+            *alias.borrow(),
+            StackObject::Summarized {
+                kind: ContainerKind::List,
+                len: 5
+            }
+        ));
+        assert!(alias.borrow().is_container(ContainerKind::List));
+        assert!(!alias.summarize_if_larger_than(0));
+    }
+
+    #[test]
+    fn summarize_ignores_non_containers() {
+        let value = StackObjectRef::new(StackObject::String("abc".to_string()));
+        assert!(!value.summarize_if_larger_than(0));
+        assert_eq!(value.borrow().container_kind(), None);
+    }
+
     #[test]
     fn dropping_last_external_ref_breaks_self_cycle() {
-        let list = StackObjectRef::new(StackObject::List(Vec::new()));
+        let list = StackObjectRef::new(StackObject::List(Items::new()));
         let weak = Rc::downgrade(&list.0);
 
         if let StackObject::List(items) = &mut *list.borrow_mut() {
@@ -0,0 +1,93 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! WebAssembly bindings for pickle-fuzzer generator.
+//!
+//! This module exposes the generator to JavaScript through `wasm-bindgen`, for
+//! browser tooling and JS-based pipelines. Build it for the browser with
+//!
+//! ```text
+//! cargo build --release --lib --target wasm32-unknown-unknown \
+//!     --no-default-features --features wasm
+//! wasm-bindgen --target web target/wasm32-unknown-unknown/release/pickle_fuzzer.wasm --out-dir pkg
+//! ```
+//!
+//! `wasm32-unknown-unknown` has no OS entropy, so every call takes an explicit
+//! seed (JS can draw one from `crypto.getRandomValues`). Options use the JSON
+//! form of [`GeneratorConfig`].
+
+use wasm_bindgen::prelude::*;
+
+use crate::{GeneratorConfig, GENERATOR_FORMAT_VERSION};
+
+/// Generates the pickle for `seed` and `protocol`.
+///
+/// `options` is a JSON [`GeneratorConfig`] (an empty string for the defaults);
+/// `seed` and `protocol` override the fields of the same name in it.
+fn generate_pickle(seed: u64, protocol: u8, options: &str) -> Result<Vec<u8>, String> {
+    let mut config = if options.trim().is_empty() {
+        GeneratorConfig::default()
+    } else {
+        GeneratorConfig::from_json(options.as_bytes())?
+    };
+    config.seed = Some(seed);
+    config.protocol = Some(protocol.into());
+
+    config
+        .build()?
+        .generate()
+        .map_err(|e| format!("generation failed: {e}"))
+}
+
+/// Generates one pickle as a `Uint8Array`.
+///
+/// `seed` is a `BigInt`; `options` is a JSON configuration with the same fields
+/// as the CLI flags (`"{}"` or `""` for the defaults), for example
+/// `'{"max_opcodes": 100, "mutators": ["all"]}'`.
+#[wasm_bindgen]
+pub fn generate(seed: u64, protocol: u8, options: &str) -> Result<Vec<u8>, JsError> {
+    generate_pickle(seed, protocol, options).map_err(|e| JsError::new(&e))
+}
+
+/// Returns `GENERATOR_FORMAT_VERSION`.
+#[wasm_bindgen(js_name = formatVersion)]
+pub fn format_version() -> u32 {
+    GENERATOR_FORMAT_VERSION
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Generator, Version};
+
+    #[test]
+    fn generate_pickle_matches_the_builder() {
+        let pickle = generate_pickle(5, 3, r#"{"max_opcodes": 90, "protocol": 0}"#).unwrap();
+        let expected = Generator::new(Version::V3)
+            .with_seed(5)
+            .with_opcode_range(60, 90)
+            .generate()
+            .unwrap();
+        assert_eq!(pickle, expected);
+        assert_eq!(generate_pickle(5, 3, "").unwrap().last(), Some(&b'.'));
+    }
+
+    #[test]
+    fn generate_pickle_reports_invalid_options() {
+        assert!(generate_pickle(0, 6, "").unwrap_err().contains("protocol"));
+        assert!(generate_pickle(0, 2, "{").is_err());
+    }
+}
@@ -0,0 +1,181 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! golden corpus regression test.
+//!
+//! `tests/golden/corpus.jsonl` starts with the format version it was recorded
+//! under, then lists one case per line: a name, a `GeneratorConfig` as JSON,
+//! and the length and FNV-1a hash of the pickle that configuration generates.
+//! a case with an `arbitrary_len` is generated from that many bytes of a fixed
+//! pattern through `generate_from_arbitrary` instead. an accidental change to
+//! entropy consumption or an encoding fails here, naming every case it moved.
+//! this is the only place generator output is pinned.
+//!
+//! to change output on purpose, bump `GENERATOR_FORMAT_VERSION` and
+//! regenerate the expectations:
+//!
+//! ```text
+//! UPDATE_GOLDEN=1 cargo test --test golden_test
+//! ```
+//!
+//! regenerating also fills in cases added without a `len` and `fnv1a`, which
+//! is how new configurations join the corpus. it refuses to move an existing
+//! expectation while the format version is unchanged.
+
+use std::path::PathBuf;
+
+use pickle_fuzzer::{GeneratorConfig, GENERATOR_FORMAT_VERSION};
+use serde_json::{json, Value};
+
+/// 64-bit FNV-1a, small enough to keep the expectations readable.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn corpus_path() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden/corpus.jsonl")
+}
+
+/// one line of the corpus.
+struct Case {
+    name: String,
+    config: Value,
+    /// length of the fixed input fed to `generate_from_arbitrary`, if any
+    arbitrary_len: Option<usize>,
+    /// length and hash, unless the case is new
+    expected: Option<(usize, u64)>,
+}
+
+/// the recorded format version and the cases of the corpus.
+fn read_corpus() -> (u64, Vec<Case>) {
+    let text = std::fs::read_to_string(corpus_path()).expect("tests/golden/corpus.jsonl");
+    let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+    let header: Value = serde_json::from_str(lines.next().expect("a header line")).unwrap();
+    let version = header["format_version"]
+        .as_u64()
+        .expect("the header is {\"format_version\": N}");
+
+    let mut cases: Vec<Case> = Vec::new();
+    for line in lines {
+        let case: Value = serde_json::from_str(line).unwrap_or_else(|e| panic!("{line}: {e}"));
+        let name = case["name"]
+            .as_str()
+            .expect("every case has a name")
+            .to_string();
+        assert!(
+            cases.iter().all(|other| other.name != name),
+            "duplicate case {name}"
+        );
+        let expected = match (case["len"].as_u64(), case["fnv1a"].as_str()) {
+            (Some(len), Some(hash)) => {
+                let hash = u64::from_str_radix(hash.trim_start_matches("0x"), 16)
+                    .unwrap_or_else(|e| panic!("{name}: bad fnv1a {hash}: {e}"));
+                Some((len as usize, hash))
+            }
+            _ => None,
+        };
+        cases.push(Case {
+            name,
+            config: case["config"].clone(),
+            arbitrary_len: case["arbitrary_len"].as_u64().map(|len| len as usize),
+            expected,
+        });
+    }
+    (version, cases)
+}
+
+/// `len` bytes of a fixed, well-mixed pattern.
+fn arbitrary_input(len: usize) -> Vec<u8> {
+    (0..len as u32)
+        .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+        .collect()
+}
+
+fn generate(case: &Case) -> Vec<u8> {
+    let config = GeneratorConfig::from_json(case.config.to_string().as_bytes())
+        .unwrap_or_else(|e| panic!("{}: {e}", case.name));
+    let mut generator = config
+        .build()
+        .unwrap_or_else(|e| panic!("{}: {e}", case.name));
+    match case.arbitrary_len {
+        Some(len) => generator.generate_from_arbitrary(&arbitrary_input(len)),
+        None => generator.generate(),
+    }
+    .unwrap_or_else(|e| panic!("{}: {e}", case.name))
+}
+
+#[test]
+fn test_golden_corpus() {
+    let (recorded_version, cases) = read_corpus();
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+
+    let mut moved = Vec::new();
+    let mut missing = Vec::new();
+    let mut lines = vec![json!({ "format_version": GENERATOR_FORMAT_VERSION }).to_string()];
+    for case in &cases {
+        let pickle = generate(case);
+        let actual = (pickle.len(), fnv1a(&pickle));
+        match case.expected {
+            Some(expected) if expected != actual => moved.push(format!(
+                "  {}: expected {} bytes with hash {:#018x}, got {} bytes with hash {:#018x}",
+                case.name, expected.0, expected.1, actual.0, actual.1
+            )),
+            Some(_) => {}
+            None => missing.push(case.name.as_str()),
+        }
+        let mut line = json!({
+            "name": case.name,
+            "config": case.config,
+            "len": actual.0,
+            "fnv1a": format!("{:#018x}", actual.1),
+        });
+        if let Some(len) = case.arbitrary_len {
+            line["arbitrary_len"] = json!(len);
+        }
+        lines.push(line.to_string());
+    }
+
+    if update {
+        assert!(
+            moved.is_empty() || recorded_version != u64::from(GENERATOR_FORMAT_VERSION),
+            "output changed under format version {GENERATOR_FORMAT_VERSION}; bump \
+             GENERATOR_FORMAT_VERSION before regenerating:\n{}",
+            moved.join("\n")
+        );
+        std::fs::write(corpus_path(), lines.join("\n") + "\n").unwrap();
+        return;
+    }
+
+    assert_eq!(
+        recorded_version,
+        u64::from(GENERATOR_FORMAT_VERSION),
+        "the golden corpus was recorded under another format version; regenerate it with \
+         UPDATE_GOLDEN=1 cargo test --test golden_test"
+    );
+    assert!(
+        missing.is_empty(),
+        "cases without expectations, fill them in with UPDATE_GOLDEN=1 cargo test --test \
+         golden_test: {missing:?}"
+    );
+    assert!(
+        moved.is_empty(),
+        "generator output changed:\n{}\nif that is intended, bump GENERATOR_FORMAT_VERSION and \
+         regenerate with UPDATE_GOLDEN=1 cargo test --test golden_test",
+        moved.join("\n")
+    );
+}
@@ -19,31 +19,22 @@ use assert_cmd::cargo::cargo_bin_cmd;
 use tempfile::NamedTempFile;
 use tempfile::TempDir;
 
-use pickle_fuzzer::{Generator, Version};
+use pickle_fuzzer::{CleanupPolicy, ExhaustionPolicy, Generator, MutatorKind, Version};
 
 #[test]
 fn test_generate_all_protocol_versions() {
-    for version_num in 0..=5 {
-        let version = Version::try_from(version_num).unwrap();
+    for version in Version::all() {
         let mut gen = Generator::new(version);
         let result = gen.generate();
-        assert!(
-            result.is_ok(),
-            "Failed to generate for version {}",
-            version_num
-        );
+        assert!(result.is_ok(), "Failed to generate for version {}", version);
         let pickle = result.unwrap();
-        assert!(
-            !pickle.is_empty(),
-            "Empty pickle for version {}",
-            version_num
-        );
+        assert!(!pickle.is_empty(), "Empty pickle for version {}", version);
         // All pickles should end with STOP opcode (0x2e or '.')
         assert_eq!(
             pickle[pickle.len() - 1],
             b'.',
             "Missing STOP opcode for version {}",
-            version_num
+            version
         );
     }
 }
@@ -83,6 +74,132 @@ fn test_repeated_generate_from_arbitrary_is_deterministic() {
     assert_eq!(pickle1, pickle2);
 }
 
+#[test]
+fn test_exhaustion_policies_finish_short_inputs() {
+    use pickle_fuzzer::disasm::{disassemble, validate};
+
+    let input = b"short fuzzer input";
+    for version in Version::all() {
+        let generate = |policy: ExhaustionPolicy| {
+            let mut gen = Generator::new(version)
+                .with_opcode_range(200, 300)
+                .with_exhaustion_policy(policy);
+            let pickle = gen.generate_from_arbitrary(input).unwrap();
+            validate(&pickle).unwrap();
+            assert_eq!(pickle, gen.generate_from_arbitrary(input).unwrap());
+            disassemble(&pickle).unwrap()
+        };
+
+        let fallback = generate(ExhaustionPolicy::Fallback);
+        assert_eq!(
+            fallback,
+            disassemble(
+                &Generator::new(version)
+                    .with_opcode_range(200, 300)
+                    .generate_from_arbitrary(input)
+                    .unwrap()
+            )
+            .unwrap()
+        );
+        assert!(fallback.len() >= 200);
+
+        // the input decides a few opcodes, then the pickle is finished
+        let stop = generate(ExhaustionPolicy::Stop);
+        assert!(stop.len() < 100, "{version:?}: {} opcodes", stop.len());
+
+        // the tail keeps going on the PRNG instead of fallback values
+        let switched = generate(ExhaustionPolicy::SwitchToPrng);
+        assert!(switched.len() >= 200);
+        let distinct = |instructions: &[pickle_fuzzer::disasm::Instruction]| {
+            let tail = &instructions[instructions.len() / 2..];
+            tail.iter()
+                .map(|i| i.code)
+                .collect::<std::collections::HashSet<_>>()
+                .len()
+        };
+        assert!(
+            distinct(&switched) > distinct(&fallback),
+            "{version:?}: {} vs {}",
+            distinct(&switched),
+            distinct(&fallback)
+        );
+    }
+}
+
+#[test]
+fn test_canonical_generation_finishes_exhausted_inputs() {
+    use pickle_fuzzer::disasm::validate;
+
+    // once the input is empty every draw picks index 0, which used to repeat
+    // an earlier object forever
+    for version in Version::all() {
+        for input in [&b"short"[..], &[0xff; 24][..], &[0x5a; 64][..]] {
+            for diverse in [false, true] {
+                let pickle = Generator::new(version)
+                    .with_canonical(!diverse)
+                    .with_diverse_encodings(diverse)
+                    .generate_from_arbitrary(input)
+                    .unwrap();
+                validate(&pickle).unwrap();
+            }
+        }
+    }
+}
+
+#[test]
+fn test_recorded_traces_replay_to_the_same_pickle() {
+    use pickle_fuzzer::disasm::validate;
+    use pickle_fuzzer::{Decision, EntropyTrace};
+
+    for version in Version::all() {
+        for seed in 0..8 {
+            let configure = |gen: Generator| {
+                let gen = gen
+                    .with_seed(seed)
+                    .with_opcode_range(40, 200)
+                    .with_interesting_patterns(true)
+                    .with_integer_boundaries(true)
+                    .with_mutators(vec![MutatorKind::Bitflip.create(false)])
+                    .with_mutation_rate(0.3);
+                match seed % 3 {
+                    0 => gen.with_value_seed(seed + 100),
+                    1 => gen.with_canonical(true).with_mutators(Vec::new()),
+                    _ => gen,
+                }
+            };
+            let mut gen = configure(Generator::new(version));
+            let (pickle, trace) = gen.generate_recorded().unwrap();
+            assert!(!trace.is_empty());
+            // recording changes nothing about the pickle
+            assert_eq!(pickle, gen.generate().unwrap());
+            assert_eq!(
+                gen.replay(&trace).unwrap(),
+                pickle,
+                "{version:?} seed {seed}"
+            );
+
+            // an edited or cut trace still replays to a valid pickle
+            let mut edited = trace.clone();
+            for decision in &mut edited.decisions {
+                if let Decision::Range { min, max, value } = decision {
+                    *value = if *value + 1 < *max { *value + 1 } else { *min };
+                }
+            }
+            validate(&gen.replay(&edited).unwrap()).unwrap();
+            let mismatched = EntropyTrace {
+                decisions: vec![Decision::Bool(true); trace.len()],
+            };
+            validate(&gen.replay(&mismatched).unwrap()).unwrap();
+            let cut = EntropyTrace {
+                decisions: trace.decisions[..trace.len() / 2].to_vec(),
+            };
+            let mut stopping =
+                configure(Generator::new(version)).with_exhaustion_policy(ExhaustionPolicy::Stop);
+            validate(&stopping.replay(&cut).unwrap()).unwrap();
+        }
+    }
+}
+
 #[test]
 fn test_different_seeds_produce_different_pickles() {
     let mut gen1 = Generator::new(Version::V3).with_seed(1);
@@ -135,6 +252,465 @@ fn test_reset_clears_state() {
     assert_eq!(gen.output.len(), 0, "Reset should clear output");
 }
 
+#[test]
+fn test_reset_keeps_output_capacity() {
+    let mut gen = Generator::new(Version::V3).with_seed(5);
+    gen.generate().unwrap();
+    let capacity = gen.output.capacity();
+
+    gen.reset();
+    assert_eq!(gen.output.capacity(), capacity);
+}
+
+#[test]
+fn test_generate_into_matches_generate() {
+    let mut reused = Generator::new(Version::V0);
+    let mut buf = Vec::new();
+
+    for seed in 0..20u64 {
+        let version = Version::select(seed);
+        reused.set_version(version);
+        reused.set_seed(Some(seed));
+        reused.generate_into(&mut buf).unwrap();
+
+        let fresh = Generator::new(version).with_seed(seed).generate().unwrap();
+        assert_eq!(buf, fresh, "seed {seed} diverged on a reused generator");
+    }
+}
+
+/// a writer that records the size of every write it receives
+#[derive(Default)]
+struct ChunkRecorder {
+    bytes: Vec<u8>,
+    writes: Vec<usize>,
+}
+
+impl std::io::Write for ChunkRecorder {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.bytes.extend_from_slice(buf);
+        self.writes.push(buf.len());
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_generate_to_matches_generate() {
+    for seed in 0..24u64 {
+        let version = Version::select(seed);
+        let budget = if seed % 4 == 0 { Some(96) } else { None };
+        let configure = |gen: Generator| match budget {
+            Some(size) => gen.with_buffer_size(size),
+            None => gen,
+        };
+
+        let expected = configure(Generator::new(version).with_seed(seed))
+            .generate()
+            .unwrap();
+        let mut writer = ChunkRecorder::default();
+        configure(Generator::new(version).with_seed(seed))
+            .generate_to(&mut writer)
+            .unwrap();
+        assert_eq!(writer.bytes, expected, "seed {seed} diverged when streamed");
+    }
+}
+
+#[test]
+fn test_generate_to_streams_large_pickles_in_chunks() {
+    let mut gen = Generator::new(Version::V2)
+        .with_seed(9)
+        .with_opcode_range(20_000, 20_000)
+        .with_max_stack_depth(16);
+    let mut writer = ChunkRecorder::default();
+    gen.generate_to(&mut writer).unwrap();
+
+    assert!(writer.writes.len() > 1, "{:?}", writer.writes);
+    assert_eq!(writer.bytes.last(), Some(&b'.'));
+    // the buffer is handed over as it fills, so nothing is left behind
+    assert!(gen.output.is_empty());
+
+    let expected = Generator::new(Version::V2)
+        .with_seed(9)
+        .with_opcode_range(20_000, 20_000)
+        .with_max_stack_depth(16)
+        .generate()
+        .unwrap();
+    assert_eq!(writer.bytes, expected);
+}
+
+#[test]
+fn test_generate_from_arbitrary_into_matches_generate_from_arbitrary() {
+    let data: Vec<u8> = (0..512u32).map(|i| (i * 31 % 251) as u8).collect();
+    let mut gen = Generator::new(Version::V4);
+    let expected = gen.generate_from_arbitrary(&data).unwrap();
+
+    // stale contents must be replaced, not appended to
+    let mut buf = vec![0xff; 8];
+    gen.generate_from_arbitrary_into(&data, &mut buf).unwrap();
+    assert_eq!(buf, expected);
+}
+
+#[test]
+fn test_container_size_limit_does_not_change_output() {
+    for version in Version::all() {
+        for seed in [3u64, 99] {
+            let expected = Generator::new(version)
+                .with_seed(seed)
+                .with_opcode_range(400, 800)
+                .generate()
+                .unwrap();
+            let summarized = Generator::new(version)
+                .with_seed(seed)
+                .with_opcode_range(400, 800)
+                .with_container_size_limit(0)
+                .generate()
+                .unwrap();
+            assert_eq!(summarized, expected, "protocol {version} seed {seed}");
+        }
+    }
+}
+
+#[test]
+fn test_strict_checks_accept_generated_output_unchanged() {
+    for version in Version::all() {
+        for seed in [7u64, 2024] {
+            let expected = Generator::new(version)
+                .with_seed(seed)
+                .with_mutators(vec![MutatorKind::Bitflip.create(false)])
+                .generate()
+                .unwrap();
+            let strict = Generator::new(version)
+                .with_seed(seed)
+                .with_mutators(vec![MutatorKind::Bitflip.create(false)])
+                .with_strict_checks(true)
+                .generate()
+                .unwrap();
+            assert_eq!(strict, expected, "protocol {version} seed {seed}");
+        }
+    }
+}
+
+#[test]
+fn test_max_stack_depth_is_never_exceeded() {
+    for version in Version::all() {
+        for limit in [1, 2, 3, 8] {
+            for seed in 0..8 {
+                let mut gen = Generator::new(version)
+                    .with_seed(seed)
+                    .with_opcode_range(40, 120)
+                    .with_max_stack_depth(limit)
+                    .with_strict_checks(true);
+                gen.generate().unwrap();
+
+                let stats = gen.stats();
+                assert!(
+                    stats.peak_stack_depth <= limit,
+                    "protocol {version} seed {seed}: depth {} exceeds limit {limit}",
+                    stats.peak_stack_depth
+                );
+                assert!((40..=120).contains(&stats.opcodes), "{stats:?}");
+            }
+        }
+    }
+}
+
+#[test]
+fn test_integer_boundaries_hit_encoding_edges() {
+    let (mut binint1_max, mut binint2_min) = (false, false);
+    for version in Version::all().skip(1) {
+        for seed in 0..16 {
+            let mut gen = Generator::new(version)
+                .with_seed(seed)
+                .with_opcode_range(40, 120)
+                .with_integer_boundaries(true)
+                .with_strict_checks(true);
+            let pickle = gen.generate().unwrap();
+            assert_eq!(pickle.last(), Some(&b'.'));
+
+            // 255 is the last BININT1 value, 256 the first one needing BININT2
+            binint1_max |= pickle.windows(2).any(|w| w == b"K\xff");
+            binint2_min |= pickle.windows(3).any(|w| w == b"M\x00\x01");
+        }
+    }
+    assert!(binint1_max && binint2_min);
+}
+
+#[test]
+fn test_variants_share_their_opcode_sequence() {
+    use pickle_fuzzer::disasm::{disassemble, validate, Argument};
+
+    for version in Version::all() {
+        for base_seed in 0..16 {
+            let mut gen = Generator::new(version)
+                .with_opcode_range(40, 200)
+                .with_mutators(vec![MutatorKind::Bitflip.create(false)])
+                .with_mutation_rate(0.3);
+            let siblings = gen.variants(base_seed, 4).unwrap();
+            assert_eq!(siblings.len(), 4);
+            assert_eq!(siblings, gen.variants(base_seed, 4).unwrap());
+
+            // same opcodes and globals, different leaf values
+            let skeleton = |pickle: &[u8]| -> Vec<(u8, Option<String>)> {
+                validate(pickle).unwrap();
+                disassemble(pickle)
+                    .unwrap()
+                    .into_iter()
+                    .map(|i| match i.arg {
+                        Argument::Str(text) if i.name == "GLOBAL" => (i.code, Some(text)),
+                        _ => (i.code, None),
+                    })
+                    .collect()
+            };
+            let first = skeleton(&siblings[0]);
+            for sibling in &siblings[1..] {
+                assert_eq!(skeleton(sibling), first, "{version:?} seed {base_seed}");
+            }
+            assert!(
+                siblings[1..].iter().any(|sibling| *sibling != siblings[0]),
+                "{version:?} seed {base_seed}"
+            );
+        }
+    }
+}
+
+#[test]
+fn test_value_seed_splits_values_from_structure() {
+    use pickle_fuzzer::disasm::{disassemble, validate, Argument};
+
+    let skeleton = |pickle: &[u8]| -> Vec<(u8, Option<String>)> {
+        validate(pickle).unwrap();
+        disassemble(pickle)
+            .unwrap()
+            .into_iter()
+            .map(|i| match i.arg {
+                Argument::Str(text) if i.name == "GLOBAL" => (i.code, Some(text)),
+                _ => (i.code, None),
+            })
+            .collect()
+    };
+    for version in Version::all() {
+        for seed in 0..8 {
+            let generate = |value_seed: Option<u64>| {
+                let mut gen = Generator::new(version)
+                    .with_seed(seed)
+                    .with_opcode_range(40, 200)
+                    .with_integer_boundaries(true)
+                    .with_interesting_patterns(true)
+                    .with_torch_tensors(true)
+                    .with_sklearn_estimators(true)
+                    .with_mutators(vec![MutatorKind::Bitflip.create(false)])
+                    .with_mutation_rate(0.3);
+                if let Some(value_seed) = value_seed {
+                    gen = gen.with_value_seed(value_seed);
+                }
+                gen.generate().unwrap()
+            };
+
+            // a fixed seed keeps the skeleton whatever the value seed
+            let pickles: Vec<Vec<u8>> = (0..4)
+                .map(|value_seed| generate(Some(value_seed)))
+                .collect();
+            assert_eq!(pickles[0], generate(Some(0)));
+            for pickle in &pickles[1..] {
+                assert_eq!(
+                    skeleton(pickle),
+                    skeleton(&pickles[0]),
+                    "{version:?} seed {seed}"
+                );
+            }
+            assert!(pickles[1..].iter().any(|pickle| *pickle != pickles[0]));
+        }
+    }
+
+    // the value stream is the one sibling 0 draws from
+    let mut gen = Generator::new(Version::V4).with_seed(5).with_value_seed(5);
+    assert_eq!(
+        gen.generate().unwrap(),
+        Generator::new(Version::V4).generate_variant(5, 0).unwrap()
+    );
+}
+
+#[test]
+fn test_interesting_patterns_emit_valid_idioms() {
+    use pickle_fuzzer::disasm::{disassemble, validate};
+
+    // GLOBAL EMPTY_TUPLE REDUCE practically never comes out of uniform choice
+    let global_calls = |patterns: bool| {
+        let mut calls = 0;
+        for version in Version::all().skip(1) {
+            for seed in 0..16 {
+                let mut gen = Generator::new(version)
+                    .with_seed(seed)
+                    .with_opcode_range(80, 200)
+                    .with_interesting_patterns(patterns)
+                    .with_strict_checks(true);
+                let pickle = gen.generate().unwrap();
+                validate(&pickle).unwrap();
+
+                let instructions = disassemble(&pickle).unwrap();
+                let names: Vec<&str> = instructions.iter().map(|i| i.name).collect();
+                calls += names
+                    .windows(3)
+                    .filter(|w| *w == ["GLOBAL", "EMPTY_TUPLE", "REDUCE"])
+                    .count();
+            }
+        }
+        calls
+    };
+    assert!(global_calls(true) > global_calls(false) + 10);
+
+    for seed in 0..16 {
+        for version in Version::all() {
+            let mut gen = Generator::new(version)
+                .with_seed(seed)
+                .with_buffer_size(128)
+                .with_max_stack_depth(6)
+                .with_interesting_patterns(true)
+                .with_indirect_stack_globals(true)
+                .with_strict_checks(true);
+            let pickle = gen.generate().unwrap();
+            assert!(pickle.len() <= 128, "seed {seed}: {} bytes", pickle.len());
+            assert!(gen.stats().peak_stack_depth <= 6, "{:?}", gen.stats());
+            validate(&pickle).unwrap();
+        }
+    }
+}
+
+#[test]
+fn test_canonical_mode_writes_valid_pickles_within_limits() {
+    use pickle_fuzzer::disasm::{disassemble, validate};
+    use pickle_fuzzer::mutators::BitFlipMutator;
+
+    let mut memo_gets = 0;
+    for version in Version::all() {
+        for seed in 0..16 {
+            let mut gen = Generator::new(version)
+                .with_seed(seed)
+                .with_canonical(true)
+                .with_strict_checks(true);
+            let pickle = gen.generate().unwrap();
+            validate(&pickle).unwrap();
+
+            let instructions = disassemble(&pickle).unwrap();
+            assert_eq!(instructions.len(), gen.stats().opcodes);
+            assert!(instructions.len() <= gen.max_opcodes);
+            memo_gets += instructions
+                .iter()
+                .filter(|i| matches!(i.name, "GET" | "BINGET" | "LONG_BINGET"))
+                .count();
+
+            let mut gen = Generator::new(version)
+                .with_seed(seed)
+                .with_opcode_range(200, 1000)
+                .with_buffer_size(256)
+                .with_max_stack_depth(8)
+                .with_canonical(true)
+                .with_strict_checks(true);
+            let pickle = gen.generate().unwrap();
+            assert!(pickle.len() <= 256, "seed {seed}: {} bytes", pickle.len());
+            assert!(gen.stats().peak_stack_depth <= 8, "{:?}", gen.stats());
+            validate(&pickle).unwrap();
+        }
+    }
+    // shared objects come back as memo references
+    assert!(memo_gets > 0);
+
+    let mut gen = Generator::new(Version::V4)
+        .with_canonical(true)
+        .with_mutators(vec![Box::new(BitFlipMutator)]);
+    assert!(gen.generate().is_err());
+}
+
+#[test]
+fn test_diverse_encodings_stay_valid_within_limits() {
+    use pickle_fuzzer::disasm::{disassemble, validate};
+
+    for version in Version::all() {
+        let mut differs = false;
+        for seed in 0..16 {
+            let mut gen = Generator::new(version)
+                .with_seed(seed)
+                .with_diverse_encodings(true)
+                .with_strict_checks(true);
+            let pickle = gen.generate().unwrap();
+            validate(&pickle).unwrap();
+            assert_eq!(disassemble(&pickle).unwrap().len(), gen.stats().opcodes);
+
+            let canonical = Generator::new(version)
+                .with_seed(seed)
+                .with_canonical(true)
+                .generate()
+                .unwrap();
+            differs |= pickle != canonical;
+
+            let mut gen = Generator::new(version)
+                .with_seed(seed)
+                .with_opcode_range(200, 1000)
+                .with_buffer_size(256)
+                .with_max_stack_depth(8)
+                .with_diverse_encodings(true)
+                .with_strict_checks(true);
+            let pickle = gen.generate().unwrap();
+            assert!(pickle.len() <= 256, "seed {seed}: {} bytes", pickle.len());
+            assert!(gen.stats().peak_stack_depth <= 8, "{:?}", gen.stats());
+            validate(&pickle).unwrap();
+        }
+        assert!(differs, "protocol {version}");
+    }
+}
+
+#[test]
+fn test_indirect_stack_globals_hide_the_literal_names() {
+    use pickle_fuzzer::disasm::{disassemble, validate, Argument};
+
+    let mut indirect = 0;
+    for version in [Version::V4, Version::V5] {
+        for seed in 0..16 {
+            let mut gen = Generator::new(version)
+                .with_seed(seed)
+                .with_opcode_range(80, 200)
+                .with_indirect_stack_globals(true)
+                .with_strict_checks(true);
+            let pickle = gen.generate().unwrap();
+            validate(&pickle).unwrap();
+
+            let instructions = disassemble(&pickle).unwrap();
+            for pair in instructions.windows(2) {
+                let after_binary_literal =
+                    matches!(pair[0].arg, Argument::Str(_)) && pair[0].name != "UNICODE";
+                if pair[1].name == "STACK_GLOBAL" && !after_binary_literal {
+                    indirect += 1;
+                }
+            }
+        }
+    }
+    assert!(indirect > 0);
+
+    for seed in 0..32 {
+        let pickle = Generator::new(Version::V4)
+            .with_seed(seed)
+            .with_buffer_size(96)
+            .with_indirect_stack_globals(true)
+            .with_strict_checks(true)
+            .generate()
+            .unwrap();
+        assert!(pickle.len() <= 96, "seed {seed}: {} bytes", pickle.len());
+        validate(&pickle).unwrap();
+    }
+
+    // protocols without STACK_GLOBAL are unaffected
+    let plain = Generator::new(Version::V3).with_seed(3).generate().unwrap();
+    let enabled = Generator::new(Version::V3)
+        .with_seed(3)
+        .with_indirect_stack_globals(true)
+        .generate()
+        .unwrap();
+    assert_eq!(plain, enabled);
+}
+
 #[test]
 fn test_builder_pattern() {
     let mut gen = Generator::new(Version::V4)
@@ -157,6 +733,94 @@ fn test_generate_with_buffer_size_respects_limit() {
     assert_eq!(pickle[pickle.len() - 1], b'.');
 }
 
+#[test]
+fn test_buffer_size_is_never_exceeded() {
+    for version in Version::all() {
+        for limit in [4, 5, 8, 16, 13, 32, 100, 257, 1024] {
+            for seed in 0..8 {
+                let mutators = MutatorKind::all_mutators(false)
+                    .into_iter()
+                    .map(|kind| kind.create(false))
+                    .collect();
+                let pickle = Generator::new(version)
+                    .with_seed(seed)
+                    .with_buffer_size(limit)
+                    .with_mutators(mutators)
+                    .with_mutation_rate(0.5)
+                    .generate()
+                    .unwrap();
+
+                assert!(
+                    pickle.len() <= limit,
+                    "protocol {version} seed {seed}: {} bytes exceeds limit {limit}",
+                    pickle.len()
+                );
+                assert_eq!(pickle[pickle.len() - 1], b'.');
+            }
+        }
+    }
+}
+
+#[test]
+fn test_keep_root_cleanup_policy_respects_budgets() {
+    for version in Version::all() {
+        for limit in [8, 32, 257] {
+            for seed in 0..8 {
+                let pickle = Generator::new(version)
+                    .with_seed(seed)
+                    .with_buffer_size(limit)
+                    .with_cleanup_policy(CleanupPolicy::KeepRoot)
+                    .with_strict_checks(true)
+                    .generate()
+                    .unwrap();
+
+                assert!(
+                    pickle.len() <= limit,
+                    "protocol {version} seed {seed}: {} bytes exceeds limit {limit}",
+                    pickle.len()
+                );
+                assert_eq!(pickle[pickle.len() - 1], b'.');
+            }
+        }
+
+        // the default policy keeps the existing output
+        let default = Generator::new(version).with_seed(11).generate().unwrap();
+        let tuple = Generator::new(version)
+            .with_seed(11)
+            .with_cleanup_policy(CleanupPolicy::Tuple)
+            .generate()
+            .unwrap();
+        assert_eq!(default, tuple);
+    }
+}
+
+#[test]
+fn test_buffer_size_at_minimum_produces_smallest_pickle() {
+    let pickle = Generator::new(Version::V2)
+        .with_seed(5)
+        .with_buffer_size(4)
+        .generate()
+        .unwrap();
+    assert_eq!(pickle, b"\x80\x02N.");
+
+    let pickle = Generator::new(Version::V0)
+        .with_seed(5)
+        .with_buffer_size(2)
+        .generate()
+        .unwrap();
+    assert_eq!(pickle, b"N.");
+}
+
+#[test]
+fn test_buffer_size_below_minimum_errors() {
+    let err = Generator::new(Version::V2)
+        .with_buffer_size(3)
+        .generate()
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("buffer size 3 is too small for protocol 2"));
+}
+
 #[test]
 fn test_impossible_total_opcode_budget_errors() {
     let mut gen = Generator::new(Version::V2).with_opcode_range(0, 0);
@@ -180,8 +844,7 @@ fn test_protocol_v0_generates_ascii() {
 #[test]
 #[cfg_attr(tarpaulin, ignore)]
 fn test_protocol_v2_and_above_have_proto() {
-    for version_num in 2..=5 {
-        let version = Version::try_from(version_num).unwrap();
+    for version in Version::all().skip(2) {
         let mut gen = Generator::new(version).with_seed(123);
         let pickle = gen.generate().unwrap();
 
@@ -189,10 +852,11 @@ fn test_protocol_v2_and_above_have_proto() {
         assert_eq!(
             pickle[0], 0x80,
             "Protocol {} should start with PROTO opcode",
-            version_num
+            version
         );
         assert_eq!(
-            pickle[1], version_num as u8,
+            pickle[1],
+            version.as_u8(),
             "Protocol byte should match version"
         );
     }
@@ -215,6 +879,32 @@ fn test_cli_single_file_generation() {
     assert_eq!(contents[contents.len() - 1], b'.', "missing STOP opcode");
 }
 
+#[test]
+fn test_cli_lists_opcodes() {
+    let output = cargo_bin_cmd!("pickle-fuzzer")
+        .arg("--list-opcodes")
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let listing = String::from_utf8(output).unwrap();
+    let lines: Vec<&str> = listing.lines().collect();
+    assert!(lines[0].starts_with("CODE"));
+    assert_eq!(lines.len(), 1 + 68);
+    let appends = lines
+        .iter()
+        .find(|line| line.starts_with("0x65"))
+        .expect("APPENDS is listed");
+    let columns: Vec<&str> = appends.split_whitespace().collect();
+    assert_eq!(&columns[..4], ["0x65", "APPENDS", "none", "1"]);
+    assert!(appends.ends_with("[any, mark, stackslice] -> [any]"));
+    assert!(lines
+        .iter()
+        .any(|line| line.split_whitespace().collect::<Vec<_>>()[..4]
+            == ["0x8c", "SHORT_BINUNICODE", "unicodestring1", "4"]));
+}
+
 #[test]
 fn test_cli_with_protocol_flag() {
     let temp_file = NamedTempFile::new().expect("failed to create temp file");
@@ -308,6 +998,369 @@ fn test_cli_batch_mode_with_seed_is_distinct_and_reproducible() {
     assert_ne!(first_run[1], first_run[2]);
 }
 
+#[test]
+fn test_cli_batch_variants_share_one_base_seed() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let out_dir = temp_dir.path().join("samples");
+    let manifest = temp_dir.path().join("manifest.jsonl");
+
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args(["--dir", out_dir.to_str().unwrap()])
+        .args(["--samples", "2", "--seed", "9", "--variants", "3"])
+        .args(["--manifest", manifest.to_str().unwrap()])
+        .assert()
+        .success();
+
+    let manifest = fs::read_to_string(&manifest).expect("failed to read manifest");
+    let entries: Vec<serde_json::Value> = manifest
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("invalid manifest line"))
+        .collect();
+    assert_eq!(entries.len(), 6);
+    for (idx, entry) in entries.iter().enumerate() {
+        let (sample, variant) = (idx / 3, idx % 3);
+        assert_eq!(entry["file"], format!("{sample}-{variant}.pkl"));
+        assert_eq!(entry["variant"], variant);
+        assert_eq!(entry["seed"], entries[sample * 3]["seed"]);
+
+        let protocol = Version::try_from(entry["protocol"].as_u64().unwrap() as usize).unwrap();
+        let seed = entry["seed"].as_u64().unwrap();
+        let expected = Generator::new(protocol)
+            .generate_variant(seed, variant)
+            .unwrap();
+        assert_eq!(
+            fs::read(out_dir.join(format!("{sample}-{variant}.pkl"))).unwrap(),
+            expected
+        );
+    }
+
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args(["--variants", "3", temp_file.path().to_str().unwrap()])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_cli_batch_value_seed_follows_the_sample_index() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let manifest = temp_dir.path().join("manifest.jsonl");
+
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args(["--dir", temp_dir.path().join("samples").to_str().unwrap()])
+        .args(["--samples", "3", "--seed", "9", "--value-seed", "40"])
+        .args(["--manifest", manifest.to_str().unwrap()])
+        .assert()
+        .success();
+
+    let manifest = fs::read_to_string(&manifest).expect("failed to read manifest");
+    for (idx, line) in manifest.lines().enumerate() {
+        let entry: serde_json::Value = serde_json::from_str(line).expect("invalid manifest line");
+        assert_eq!(entry["seed"], 9 + idx as u64);
+        assert_eq!(entry["value_seed"], 40 + idx as u64);
+    }
+}
+
+#[test]
+fn test_cli_replays_a_recorded_trace() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let recorded = temp_dir.path().join("recorded.pkl");
+    let replayed = temp_dir.path().join("replayed.pkl");
+    let trace = temp_dir.path().join("trace.json");
+
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args(["--seed", "12", "--record-trace", trace.to_str().unwrap()])
+        .arg(&recorded)
+        .assert()
+        .success();
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args(["--seed", "12", "--replay-trace", trace.to_str().unwrap()])
+        .arg(&replayed)
+        .assert()
+        .success();
+    assert_eq!(fs::read(&recorded).unwrap(), fs::read(&replayed).unwrap());
+
+    fs::write(&trace, "not a trace").unwrap();
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args(["--replay-trace", trace.to_str().unwrap()])
+        .arg(&replayed)
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_cli_generate_subcommand_matches_the_default_mode() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let default = temp_dir.path().join("default.pkl");
+    let generate = temp_dir.path().join("generate.pkl");
+
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args(["--seed", "5"])
+        .arg(&default)
+        .assert()
+        .success();
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args(["generate", "--seed", "5"])
+        .arg(&generate)
+        .assert()
+        .success();
+    assert_eq!(fs::read(&default).unwrap(), fs::read(&generate).unwrap());
+
+    // subcommands don't take the generate options at the top level
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args(["--seed", "5", "dis"])
+        .arg(&default)
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_cli_dash_uses_stdin_and_stdout() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let file = temp_dir.path().join("file.pkl");
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args(["--seed", "6"])
+        .arg(&file)
+        .assert()
+        .success();
+    let pickle = fs::read(&file).unwrap();
+
+    let output = cargo_bin_cmd!("pickle-fuzzer")
+        .args(["--seed", "6", "-"])
+        .assert()
+        .success()
+        .stderr(format!("Generated {} bytes to stdout\n", pickle.len()))
+        .get_output()
+        .clone();
+    assert_eq!(output.stdout, pickle);
+
+    let mutant = temp_dir.path().join("mutant.pkl");
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args(["mutate", "-", "--seed", "4", "-o"])
+        .arg(&mutant)
+        .write_stdin(pickle.clone())
+        .assert()
+        .success();
+    let piped = cargo_bin_cmd!("pickle-fuzzer")
+        .args(["mutate", "-", "--seed", "4", "-o", "-"])
+        .write_stdin(pickle)
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    assert_eq!(piped, fs::read(&mutant).unwrap());
+}
+
+#[test]
+fn test_cli_validate_and_dis_subcommands() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let samples = temp_dir.path().join("samples");
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args([
+            "--dir",
+            samples.to_str().unwrap(),
+            "--samples",
+            "8",
+            "--seed",
+            "3",
+        ])
+        .assert()
+        .success();
+
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args(["validate", samples.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout("All 8 pickles are valid\n");
+
+    let listing = cargo_bin_cmd!("pickle-fuzzer")
+        .arg("dis")
+        .arg(samples.join("0.pkl"))
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let listing = String::from_utf8(listing).unwrap();
+    assert_eq!(
+        listing.lines().last().unwrap().split_whitespace().nth(2),
+        Some("STOP")
+    );
+
+    // a truncated pickle fails both
+    let pickle = fs::read(samples.join("1.pkl")).unwrap();
+    fs::write(samples.join("1.pkl"), &pickle[..pickle.len() - 1]).unwrap();
+    let report = cargo_bin_cmd!("pickle-fuzzer")
+        .args(["validate", samples.to_str().unwrap()])
+        .assert()
+        .failure()
+        .get_output()
+        .stdout
+        .clone();
+    let report = String::from_utf8(report).unwrap();
+    assert_eq!(report.lines().count(), 1);
+    assert!(report.contains("1.pkl: "), "{report}");
+    cargo_bin_cmd!("pickle-fuzzer")
+        .arg("dis")
+        .arg(samples.join("1.pkl"))
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_cli_dis_stops_quietly_when_stdout_closes() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    // far more listing than a pipe holds, so dis is still writing when the
+    // reader goes away
+    let pickle = temp_dir.path().join("long.pkl");
+    fs::write(&pickle, [b"N0".repeat(50_000), b".".to_vec()].concat()).unwrap();
+
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_pickle-fuzzer"))
+        .arg("dis")
+        .arg(&pickle)
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut first = [0u8; 1];
+    std::io::Read::read_exact(child.stdout.as_mut().unwrap(), &mut first).unwrap();
+    drop(child.stdout.take());
+
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert!(
+        output.stderr.is_empty(),
+        "{:?}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn test_cli_annotations_follow_the_pickle_into_dis() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let pickle = temp_dir.path().join("sample.pkl");
+    let annotations = temp_dir.path().join("sample.json");
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args([
+            "--seed",
+            "6",
+            "--protocol",
+            "2",
+            "--interesting-patterns",
+            "--annotations",
+        ])
+        .arg(&annotations)
+        .arg(&pickle)
+        .assert()
+        .success();
+
+    let map: Vec<serde_json::Value> =
+        serde_json::from_slice(&fs::read(&annotations).unwrap()).unwrap();
+    assert_eq!(map[0]["opcode"], "PROTO");
+    assert_eq!(map.last().unwrap()["origin"], "stop");
+
+    let listing = cargo_bin_cmd!("pickle-fuzzer")
+        .arg("dis")
+        .arg("--annotations")
+        .arg(&annotations)
+        .arg(&pickle)
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let listing = String::from_utf8(listing).unwrap();
+    assert_eq!(listing.lines().count(), map.len());
+    assert!(listing.lines().last().unwrap().ends_with("# stop, depth 0"));
+
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args(["--annotations", "a.json", "--dir"])
+        .arg(temp_dir.path().join("batch"))
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_cli_mutate_subcommand() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let input = temp_dir.path().join("input.pkl");
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args(["--seed", "8"])
+        .arg(&input)
+        .assert()
+        .success();
+
+    let mutant = |seed: &str, name: &str| {
+        let output = temp_dir.path().join(name);
+        cargo_bin_cmd!("pickle-fuzzer")
+            .arg("mutate")
+            .arg(&input)
+            .args(["--seed", seed, "--max-size", "512", "-o"])
+            .arg(&output)
+            .assert()
+            .success();
+        fs::read(output).unwrap()
+    };
+    let first = mutant("1", "a.pkl");
+    assert!(first.len() <= 512);
+    pickle_fuzzer::disasm::validate(&first).expect("mutants are well-formed");
+    assert_eq!(first, mutant("1", "b.pkl"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cli_minimize_subcommand() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let recorded = temp_dir.path().join("recorded.pkl");
+    let minimized = temp_dir.path().join("minimized.pkl");
+    let trace = temp_dir.path().join("trace.json");
+    let shrunk_trace = temp_dir.path().join("shrunk.json");
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args(["--seed", "12", "--record-trace", trace.to_str().unwrap()])
+        .arg(&recorded)
+        .assert()
+        .success();
+
+    // "fails" on every pickle of 24 bytes or more
+    cargo_bin_cmd!("pickle-fuzzer")
+        .arg("minimize")
+        .arg(&trace)
+        .args(["--seed", "12", "-o"])
+        .arg(&minimized)
+        .args(["--output-trace", shrunk_trace.to_str().unwrap()])
+        .args(["--", "sh", "-c", "test $(wc -c < \"$0\") -lt 24", "{}"])
+        .assert()
+        .success();
+    let recorded = fs::read(&recorded).unwrap();
+    let minimized = fs::read(&minimized).unwrap();
+    assert!(minimized.len() >= 24);
+    assert!(minimized.len() < recorded.len());
+
+    let replayed = temp_dir.path().join("replayed.pkl");
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args([
+            "--replay-trace",
+            shrunk_trace.to_str().unwrap(),
+            "--seed",
+            "12",
+        ])
+        .arg(&replayed)
+        .assert()
+        .success();
+    pickle_fuzzer::disasm::validate(&fs::read(&replayed).unwrap()).unwrap();
+
+    // a command that never fails leaves nothing to minimize
+    cargo_bin_cmd!("pickle-fuzzer")
+        .arg("minimize")
+        .arg(&trace)
+        .args(["--seed", "12", "-o"])
+        .arg(temp_dir.path().join("none.pkl"))
+        .args(["--", "true"])
+        .assert()
+        .failure();
+}
+
 #[test]
 fn test_cli_with_opcode_range() {
     let temp_file = NamedTempFile::new().expect("failed to create temp file");
@@ -382,3 +1435,1359 @@ fn test_cli_with_mutators() {
         .assert()
         .success();
 }
+
+#[test]
+fn test_cli_batch_protocol_mix_is_recorded_in_manifest() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let out_dir = temp_dir.path().join("samples");
+    let manifest = temp_dir.path().join("manifest.jsonl");
+
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args(["--dir", out_dir.to_str().unwrap()])
+        .args(["--samples", "40", "--seed", "7"])
+        .args(["--protocol-mix", "1:1,4:3"])
+        .args(["--manifest", manifest.to_str().unwrap()])
+        .assert()
+        .success();
+
+    let manifest = fs::read_to_string(&manifest).expect("failed to read manifest");
+    let lines: Vec<&str> = manifest.lines().collect();
+    assert_eq!(lines.len(), 40, "manifest should list every sample");
+
+    let mut seen = std::collections::HashSet::new();
+    for line in lines {
+        let entry: serde_json::Value = serde_json::from_str(line).expect("invalid manifest line");
+        let protocol = entry["protocol"].as_u64().unwrap();
+        assert!(
+            protocol == 1 || protocol == 4,
+            "unexpected protocol {protocol}"
+        );
+        seen.insert(protocol);
+
+        let contents = fs::read(out_dir.join(entry["file"].as_str().unwrap())).unwrap();
+        assert_eq!(contents.len() as u64, entry["size"].as_u64().unwrap());
+        if protocol == 4 {
+            assert_eq!(&contents[..2], &[0x80, 4]);
+        } else {
+            assert_ne!(contents[0], 0x80, "protocol 1 pickles have no PROTO header");
+        }
+    }
+    assert_eq!(seen.len(), 2, "both protocols should appear in the mix");
+}
+
+#[test]
+fn test_cli_config_file_is_recorded_in_manifest() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let out_dir = temp_dir.path().join("samples");
+    let manifest = temp_dir.path().join("manifest.jsonl");
+    let config = temp_dir.path().join("run.toml");
+    fs::write(
+        &config,
+        format!(
+            "dir = {:?}\nsamples = 40\nseed = 7\nprotocol-mix = \"1:1,4:3\"\n",
+            out_dir.to_str().unwrap()
+        ),
+    )
+    .unwrap();
+
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args(["--config", config.to_str().unwrap()])
+        .args(["--samples", "12"])
+        .args(["--manifest", manifest.to_str().unwrap()])
+        .assert()
+        .success();
+
+    let manifest = fs::read_to_string(&manifest).expect("failed to read manifest");
+    let lines: Vec<&str> = manifest.lines().collect();
+    assert_eq!(lines.len(), 13, "manifest should start with the config");
+    let header: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+    assert_eq!(header["config"]["samples"], 12);
+    assert_eq!(header["config"]["seed"], 7);
+    assert_eq!(header["config"]["protocol-mix"], "1:1,4:3");
+
+    // the same flags on the command line give the same samples
+    let flags_dir = TempDir::new().expect("failed to create temp dir");
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args(["--dir", flags_dir.path().to_str().unwrap()])
+        .args([
+            "--samples",
+            "12",
+            "--seed",
+            "7",
+            "--protocol-mix",
+            "1:1,4:3",
+        ])
+        .assert()
+        .success();
+    for idx in 0..12 {
+        let name = format!("{idx}.pkl");
+        assert_eq!(
+            fs::read(out_dir.join(&name)).unwrap(),
+            fs::read(flags_dir.path().join(&name)).unwrap(),
+            "sample {idx} differs from the flag-driven run"
+        );
+    }
+}
+
+#[test]
+fn test_cli_batch_compress_gzip() {
+    let plain = TempDir::new().expect("failed to create temp dir");
+    let compressed = TempDir::new().expect("failed to create temp dir");
+    let manifest = compressed.path().join("manifest.jsonl");
+    let out_dir = compressed.path().join("samples");
+
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args(["--dir", plain.path().to_str().unwrap()])
+        .args(["--samples", "6", "--seed", "21"])
+        .assert()
+        .success();
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args(["--dir", out_dir.to_str().unwrap()])
+        .args(["--samples", "6", "--seed", "21", "--compress", "gzip"])
+        .args(["--manifest", manifest.to_str().unwrap()])
+        .assert()
+        .success();
+
+    let manifest = fs::read_to_string(&manifest).expect("failed to read manifest");
+    for (idx, line) in manifest.lines().enumerate() {
+        let entry: serde_json::Value = serde_json::from_str(line).expect("invalid manifest line");
+        assert_eq!(entry["file"], format!("{idx}.pkl.gz"));
+
+        let gz = fs::read(out_dir.join(format!("{idx}.pkl.gz"))).unwrap();
+        assert_eq!(&gz[..3], &[0x1f, 0x8b, 8], "gzip magic and deflate method");
+        let pickle = miniz_oxide::inflate::decompress_to_vec(&gz[10..gz.len() - 8]).unwrap();
+        assert_eq!(
+            pickle,
+            fs::read(plain.path().join(format!("{idx}.pkl"))).unwrap()
+        );
+        assert_eq!(entry["size"].as_u64().unwrap(), pickle.len() as u64);
+    }
+}
+
+#[test]
+fn test_cli_batch_compress_zstd() {
+    let plain = TempDir::new().expect("failed to create temp dir");
+    let compressed = TempDir::new().expect("failed to create temp dir");
+
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args(["--dir", plain.path().to_str().unwrap()])
+        .args(["--samples", "6", "--seed", "21"])
+        .assert()
+        .success();
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args(["--dir", compressed.path().to_str().unwrap()])
+        .args(["--samples", "6", "--seed", "21", "--compress", "zstd"])
+        .assert()
+        .success();
+
+    for idx in 0..6 {
+        let zst = fs::read(compressed.path().join(format!("{idx}.pkl.zst"))).unwrap();
+        assert_eq!(
+            zstd::decode_all(&zst[..]).unwrap(),
+            fs::read(plain.path().join(format!("{idx}.pkl"))).unwrap()
+        );
+    }
+}
+
+#[test]
+fn test_cli_batch_archive() {
+    let plain = TempDir::new().expect("failed to create temp dir");
+    let archived = TempDir::new().expect("failed to create temp dir");
+    let manifest = archived.path().join("manifest.jsonl");
+    let out_dir = archived.path().join("samples");
+
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args(["--dir", plain.path().to_str().unwrap()])
+        .args(["--samples", "12", "--seed", "21", "--shard-dirs", "3"])
+        .assert()
+        .success();
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args(["--dir", out_dir.to_str().unwrap()])
+        .args(["--samples", "12", "--seed", "21", "--shard-dirs", "3"])
+        .args(["--archive", "--manifest", manifest.to_str().unwrap()])
+        .assert()
+        .success();
+
+    // the archive is all the run writes, its entries in index order
+    let names: Vec<_> = fs::read_dir(&out_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    assert_eq!(names, ["corpus.tar.zst"]);
+    let tar = zstd::decode_all(&fs::read(out_dir.join("corpus.tar.zst")).unwrap()[..]).unwrap();
+    let mut archive = tar::Archive::new(&tar[..]);
+    let entries: Vec<(String, Vec<u8>)> = archive
+        .entries()
+        .unwrap()
+        .map(|entry| {
+            let mut entry = entry.unwrap();
+            let name = entry.path().unwrap().to_string_lossy().into_owned();
+            let mut pickle = Vec::new();
+            std::io::Read::read_to_end(&mut entry, &mut pickle).unwrap();
+            (name, pickle)
+        })
+        .collect();
+
+    let manifest = fs::read_to_string(&manifest).expect("failed to read manifest");
+    assert_eq!(manifest.lines().count(), entries.len());
+    for (idx, (line, (name, pickle))) in manifest.lines().zip(&entries).enumerate() {
+        let entry: serde_json::Value = serde_json::from_str(line).expect("invalid manifest line");
+        assert_eq!(entry["file"], format!("{}/{idx}.pkl", idx % 3));
+        assert_eq!(entry["file"], name.as_str());
+        assert_eq!(pickle, &fs::read(plain.path().join(name)).unwrap());
+    }
+}
+
+#[test]
+fn test_cli_batch_name_template_and_shard_dirs() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let out_dir = temp_dir.path().join("samples");
+    let manifest = temp_dir.path().join("manifest.jsonl");
+
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args(["--dir", out_dir.to_str().unwrap()])
+        .args(["--samples", "10", "--seed", "30", "--shard-dirs", "3"])
+        .args(["--name-template", "proto{proto}_seed{seed}_{idx}.pkl"])
+        .args(["--manifest", manifest.to_str().unwrap()])
+        .assert()
+        .success();
+
+    let manifest = fs::read_to_string(&manifest).expect("failed to read manifest");
+    for (idx, line) in manifest.lines().enumerate() {
+        let entry: serde_json::Value = serde_json::from_str(line).expect("invalid manifest line");
+        let expected = format!(
+            "{}/proto{}_seed{}_{idx}.pkl",
+            idx % 3,
+            entry["protocol"],
+            30 + idx
+        );
+        assert_eq!(entry["file"], expected);
+        assert!(out_dir.join(&expected).is_file(), "{expected} missing");
+    }
+
+    for template in ["{seed}.pkl", "{idx}-{variant}.pkl", "sub/{idx}.pkl"] {
+        cargo_bin_cmd!("pickle-fuzzer")
+            .args(["--dir", out_dir.to_str().unwrap(), "--samples", "1"])
+            .args(["--name-template", template])
+            .assert()
+            .failure();
+    }
+    // unseeded runs have no {seed} to fill in
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args(["--dir", out_dir.to_str().unwrap(), "--samples", "1"])
+        .args(["--name-template", "{seed}_{idx}.pkl"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_cli_batch_jobs_flag_keeps_seeded_output_stable() {
+    let single = TempDir::new().expect("failed to create temp dir");
+    let multi = TempDir::new().expect("failed to create temp dir");
+
+    for (dir, jobs) in [(&single, "1"), (&multi, "4")] {
+        cargo_bin_cmd!("pickle-fuzzer")
+            .args(["--dir", dir.path().to_str().unwrap()])
+            .args(["--samples", "20", "--seed", "99", "--jobs", jobs])
+            .assert()
+            .success();
+    }
+
+    for idx in 0..20 {
+        let name = format!("{idx}.pkl");
+        assert_eq!(
+            fs::read(single.path().join(&name)).unwrap(),
+            fs::read(multi.path().join(&name)).unwrap(),
+            "sample {idx} differs between thread counts"
+        );
+    }
+}
+
+#[test]
+fn test_cli_batch_manifest_records_peak_stack_depth() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let out_dir = temp_dir.path().join("samples");
+    let manifest = temp_dir.path().join("manifest.jsonl");
+
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args(["--dir", out_dir.to_str().unwrap()])
+        .args(["--samples", "20", "--seed", "5", "--max-stack-depth", "3"])
+        .args(["--manifest", manifest.to_str().unwrap()])
+        .assert()
+        .success();
+
+    let manifest = fs::read_to_string(&manifest).expect("failed to read manifest");
+    for line in manifest.lines() {
+        let entry: serde_json::Value = serde_json::from_str(line).expect("invalid manifest line");
+        let depth = entry["peak_stack_depth"].as_u64().unwrap();
+        assert!((1..=3).contains(&depth), "unexpected depth {depth}");
+    }
+}
+
+#[derive(Debug)]
+struct NegatingMutator;
+
+impl pickle_fuzzer::Mutator for NegatingMutator {
+    fn name(&self) -> &str {
+        "negate"
+    }
+
+    fn mutate_int(
+        &self,
+        value: i32,
+        _source: &mut pickle_fuzzer::GenerationSource,
+        _rate: f64,
+    ) -> Option<i32> {
+        Some(value.wrapping_neg())
+    }
+}
+
+#[test]
+fn test_registered_mutator_is_selectable_by_name() {
+    use clap::Parser;
+    use pickle_fuzzer::fuzz_harness::FuzzMutators;
+    use pickle_fuzzer::{Cli, GeneratorConfig, MutatorChoice};
+
+    pickle_fuzzer::register_mutator("negate", |_| Box::new(NegatingMutator)).unwrap();
+    let names = |mutators: &[Box<dyn pickle_fuzzer::Mutator>]| -> Vec<String> {
+        mutators.iter().map(|m| m.name().to_string()).collect()
+    };
+
+    let cli = Cli::try_parse_from(["pickle-fuzzer", "--mutators", "negate", "out.pkl"]).unwrap();
+    assert_eq!(
+        cli.generate.options.mutators,
+        vec![MutatorChoice::Registered("negate")]
+    );
+    assert!(Cli::try_parse_from(["pickle-fuzzer", "--mutators", "nope", "out.pkl"]).is_err());
+
+    let config = GeneratorConfig::from_json(br#"{"mutators": ["negate"], "seed": 1}"#).unwrap();
+    assert_eq!(names(&config.build().unwrap().mutators), ["negate"]);
+
+    let all = GeneratorConfig::from_json(br#"{"mutators": ["all"]}"#).unwrap();
+    let all = names(&all.build().unwrap().mutators);
+    assert_eq!(all.len(), MutatorKind::all_mutators(false).len() + 1);
+    assert!(all.contains(&"negate".to_string()));
+
+    let fuzz = FuzzMutators {
+        registered: 1,
+        ..Default::default()
+    };
+    assert_eq!(names(&fuzz.build()), ["negate"]);
+}
+
+#[test]
+fn test_dictionary_mutator_splices_user_tokens_into_valid_pickles() {
+    use pickle_fuzzer::mutators::DictionaryMutator;
+
+    let tokens = DictionaryMutator::parse_afl_dictionary("marker=\"PF_DICT_TOKEN\"\n").unwrap();
+    let mut found = false;
+    for seed in 0..20 {
+        let mut generator = Generator::new(Version::V4)
+            .with_seed(seed)
+            .with_mutators(vec![Box::new(DictionaryMutator::with_tokens(
+                tokens.clone(),
+            ))])
+            .with_mutation_rate(1.0);
+        let bytecode = generator.generate().unwrap();
+        pickle_fuzzer::disasm::validate(&bytecode).unwrap();
+        found |= bytecode.windows(13).any(|w| w == b"PF_DICT_TOKEN");
+    }
+    assert!(found, "no sample contains the user-supplied token");
+}
+
+#[test]
+fn test_cli_dictionary_requires_dictionary_mutator() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let dictionary = temp_dir.path().join("tokens.dict");
+    fs::write(&dictionary, "# tokens\nkw=\"__reduce__\"\n\"\\x00\\xff\"\n").unwrap();
+    let dictionary = dictionary.to_str().unwrap();
+    let output = temp_dir.path().join("out.pkl");
+    let output = output.to_str().unwrap();
+
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args([
+            "--mutators",
+            "dictionary",
+            "--dictionary",
+            dictionary,
+            output,
+        ])
+        .assert()
+        .success();
+    assert!(fs::metadata(output).is_ok(), "output file not created");
+
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args(["--mutators", "bitflip", "--dictionary", dictionary, output])
+        .assert()
+        .failure();
+
+    fs::write(temp_dir.path().join("bad.dict"), "unquoted\n").unwrap();
+    let bad = temp_dir.path().join("bad.dict");
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args([
+            "--mutators",
+            "all",
+            "--dictionary",
+            bad.to_str().unwrap(),
+            output,
+        ])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_havoc_mutator_generates_for_every_protocol() {
+    use pickle_fuzzer::mutators::HavocMutator;
+
+    for version in [Version::V0, Version::V2, Version::V4, Version::V5] {
+        let mut differs = false;
+        for seed in 0..10 {
+            let clean = Generator::new(version).with_seed(seed).generate().unwrap();
+            let mut generator = Generator::new(version)
+                .with_seed(seed)
+                .with_mutators(vec![Box::new(HavocMutator::new(true))])
+                .with_mutation_rate(1.0)
+                .with_unsafe_mutations(true);
+            let bytecode = generator.generate().unwrap();
+            assert_eq!(bytecode.last(), Some(&b'.'));
+            differs |= bytecode != clean;
+        }
+        assert!(differs, "havoc never changed a {version:?} pickle");
+    }
+}
+
+#[test]
+fn test_encodingconfusion_mutator_keeps_safe_pickles_valid() {
+    use pickle_fuzzer::disasm::{disassemble, validate};
+    use pickle_fuzzer::mutators::EncodingConfusionMutator;
+
+    for version in [Version::V1, Version::V2, Version::V3, Version::V4] {
+        for seed in 0..20 {
+            let mut generator = Generator::new(version)
+                .with_seed(seed)
+                .with_mutators(vec![Box::new(EncodingConfusionMutator::new(false))])
+                .with_mutation_rate(1.0);
+            let bytecode = generator.generate().unwrap();
+            validate(&bytecode).unwrap();
+        }
+    }
+
+    // at rate 1.0 every ASCII BINUNICODE becomes a byte string; only bytes
+    // payloads that happen to be valid UTF-8 move the other way
+    let binunicode_count = |mutate: bool| -> usize {
+        (0..20)
+            .map(|seed| {
+                let mut generator = Generator::new(Version::V2).with_seed(seed);
+                if mutate {
+                    generator = generator
+                        .with_mutators(vec![Box::new(EncodingConfusionMutator::new(false))])
+                        .with_mutation_rate(1.0);
+                }
+                let bytecode = generator.generate().unwrap();
+                disassemble(&bytecode)
+                    .unwrap()
+                    .iter()
+                    .filter(|instruction| instruction.name == "BINUNICODE")
+                    .count()
+            })
+            .sum()
+    };
+    let (clean, mutated) = (binunicode_count(false), binunicode_count(true));
+    assert!(mutated * 2 < clean, "{mutated} of {clean} BINUNICODEs left");
+}
+
+#[test]
+fn test_lengthboundary_mutator_writes_matching_lengths() {
+    use pickle_fuzzer::disasm::{disassemble, validate, Argument};
+    use pickle_fuzzer::mutators::LengthBoundaryMutator;
+
+    let mut lengths = Vec::new();
+    for version in [
+        Version::V0,
+        Version::V2,
+        Version::V3,
+        Version::V4,
+        Version::V5,
+    ] {
+        for seed in 0..10 {
+            let mut generator = Generator::new(version)
+                .with_seed(seed)
+                .with_mutators(vec![Box::new(LengthBoundaryMutator::new(false))])
+                .with_mutation_rate(1.0);
+            let bytecode = generator.generate().unwrap();
+            validate(&bytecode).unwrap();
+            for instruction in disassemble(&bytecode).unwrap() {
+                match instruction.arg {
+                    Argument::Bytes(bytes) | Argument::ByteArray(bytes) => {
+                        lengths.push(bytes.len())
+                    }
+                    Argument::Str(text) if instruction.name.contains("BINUNICODE") => {
+                        lengths.push(text.len())
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    assert!(
+        lengths.contains(&255) && lengths.contains(&65536),
+        "{lengths:?}"
+    );
+
+    // unsafe mode also lies in the prefix, which the unpickler then reads past
+    let invalid = (0..10)
+        .filter(|&seed| {
+            let mut generator = Generator::new(Version::V3)
+                .with_seed(seed)
+                .with_mutators(vec![Box::new(LengthBoundaryMutator::new(true))])
+                .with_unsafe_mutations(true)
+                .with_mutation_rate(0.5);
+            validate(&generator.generate().unwrap()).is_err()
+        })
+        .count();
+    assert!(invalid > 0);
+}
+
+#[test]
+fn test_homoglyph_mutator_respells_global_names() {
+    use pickle_fuzzer::disasm::{disassemble, validate, Argument};
+    use pickle_fuzzer::mutators::HomoglyphMutator;
+
+    let mut respelled = 0;
+    for version in [Version::V0, Version::V1, Version::V2] {
+        for seed in 0..20 {
+            let mut generator = Generator::new(version)
+                .with_seed(seed)
+                .with_mutators(vec![Box::new(HomoglyphMutator::new(false))])
+                .with_mutation_rate(1.0);
+            let bytecode = generator.generate().unwrap();
+            validate(&bytecode).unwrap();
+            for instruction in disassemble(&bytecode).unwrap() {
+                match instruction.arg {
+                    Argument::Str(names) if instruction.name == "GLOBAL" => {
+                        assert!(names.is_ascii(), "{names:?}");
+                        // no stdlib module is spelled in capitals only
+                        let (module, _) = names.split_once(' ').unwrap();
+                        let capitals = module.chars().any(|c| c.is_ascii_uppercase())
+                            && !module.chars().any(|c| c.is_ascii_lowercase());
+                        respelled += usize::from(capitals);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    assert!(respelled > 0, "no GLOBAL name changed case");
+
+    // unsafe mode writes non-ASCII names, which pickletools rejects
+    let non_ascii = (0..20)
+        .filter(|&seed| {
+            let mut generator = Generator::new(Version::V2)
+                .with_seed(seed)
+                .with_mutators(vec![Box::new(HomoglyphMutator::new(true))])
+                .with_unsafe_mutations(true)
+                .with_mutation_rate(1.0);
+            validate(&generator.generate().unwrap()).is_err()
+        })
+        .count();
+    assert!(non_ascii > 0);
+}
+
+#[test]
+fn test_homoglyph_mutator_respells_stack_global_operands() {
+    use pickle_fuzzer::disasm::{disassemble, validate};
+    use pickle_fuzzer::mutators::HomoglyphMutator;
+    use pickle_fuzzer::OpcodeKind;
+
+    // protocol 4 text pushes and STACK_GLOBALs only, so every respelling is
+    // of STACK_GLOBAL operands
+    for unsafe_mode in [false, true] {
+        let mut respelled = 0;
+        for seed in 0..20 {
+            let mut generator = Generator::new(Version::V4)
+                .with_seed(seed)
+                .with_mutators(vec![Box::new(HomoglyphMutator::new(unsafe_mode))])
+                .with_unsafe_mutations(unsafe_mode)
+                .with_mutation_rate(1.0)
+                .with_opcode_filter(|_, opcode| {
+                    use OpcodeKind::*;
+                    matches!(opcode, ShortBinUnicode | Memoize | StackGlobal | Pop)
+                });
+            let bytecode = generator.generate().unwrap();
+            if unsafe_mode {
+                disassemble(&bytecode).unwrap();
+            } else {
+                validate(&bytecode).unwrap();
+            }
+            respelled += generator
+                .mutator_applications()
+                .iter()
+                .map(|(_, count)| count)
+                .sum::<usize>();
+        }
+        assert!(respelled > 0, "no STACK_GLOBAL operand respelled");
+    }
+}
+
+#[test]
+fn test_whitespace_mutator_keeps_safe_pickles_valid() {
+    use pickle_fuzzer::disasm::validate;
+    use pickle_fuzzer::mutators::WhitespaceMutator;
+
+    let mut crlf = 0;
+    for version in [Version::V0, Version::V1, Version::V2] {
+        for seed in 0..20 {
+            let mut generator = Generator::new(version)
+                .with_seed(seed)
+                .with_mutators(vec![Box::new(WhitespaceMutator::new(false))])
+                .with_mutation_rate(1.0);
+            let bytecode = generator.generate().unwrap();
+            validate(&bytecode).unwrap();
+            crlf += usize::from(bytecode.windows(2).any(|pair| pair == b"\r\n"));
+        }
+    }
+    assert!(crlf > 0, "no argument line ended in \\r\\n");
+
+    // unsafe mode also drops terminators and pads FLOAT and STRING lines
+    let invalid = (0..10)
+        .filter(|&seed| {
+            let mut generator = Generator::new(Version::V0)
+                .with_seed(seed)
+                .with_mutators(vec![Box::new(WhitespaceMutator::new(true))])
+                .with_unsafe_mutations(true)
+                .with_mutation_rate(0.5);
+            validate(&generator.generate().unwrap()).is_err()
+        })
+        .count();
+    assert!(invalid > 0);
+}
+
+#[test]
+fn test_memoorder_mutator_emits_forward_and_dangling_gets() {
+    use pickle_fuzzer::disasm::{disassemble, Argument};
+    use pickle_fuzzer::mutators::MemoOrderMutator;
+
+    let (mut forward, mut dangling) = (0, 0);
+    for version in [Version::V0, Version::V2, Version::V4] {
+        for seed in 0..20 {
+            let mut generator = Generator::new(version)
+                .with_seed(seed)
+                .with_mutators(vec![Box::new(MemoOrderMutator::new(true))])
+                .with_mutation_rate(0.2)
+                .with_unsafe_mutations(true);
+            let bytecode = generator.generate().unwrap();
+            let instructions = disassemble(&bytecode).unwrap();
+
+            let index_of = |arg: &Argument| match arg {
+                Argument::Int(index) => *index,
+                other => panic!("memo opcode with argument {other}"),
+            };
+            for (at, instruction) in instructions.iter().enumerate() {
+                if !instruction.name.ends_with("GET") {
+                    continue;
+                }
+                let index = index_of(&instruction.arg);
+                let is_put = |other: &&pickle_fuzzer::disasm::Instruction| {
+                    other.name.ends_with("PUT") && index_of(&other.arg) == index
+                };
+                if instructions[..at].iter().any(|other| is_put(&other)) {
+                    continue;
+                }
+                if instructions[at..].iter().any(|other| is_put(&other)) {
+                    forward += 1;
+                } else {
+                    dangling += 1;
+                }
+            }
+        }
+    }
+    assert!(forward > 0, "no GET referenced a later PUT");
+    assert!(dangling > 0, "no GET referenced a slot that is never PUT");
+}
+
+#[test]
+fn test_cli_dry_run_writes_nothing() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let out_dir = temp_dir.path().join("samples");
+    let out_file = temp_dir.path().join("out.pkl");
+
+    let output = cargo_bin_cmd!("pickle-fuzzer")
+        .args(["--dir", out_dir.to_str().unwrap(), "--dry-run"])
+        .args(["--samples", "12", "--seed", "4", "--shard-dirs", "3"])
+        .args(["--protocol-mix", "2:1,4:1"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    assert!(!out_dir.exists());
+
+    let report = String::from_utf8(output).unwrap();
+    assert!(
+        report.starts_with("Dry run: generated 12 pickles"),
+        "{report}"
+    );
+    for line in [
+        "Sizes: ",
+        "Protocols: 2: ",
+        "Mutated emissions: ",
+        "Throughput: ",
+    ] {
+        assert!(report.contains(line), "{line:?} missing from {report}");
+    }
+    assert!(!report.contains("Protocols: 0"), "{report}");
+    assert!(report.contains("  STOP "), "{report}");
+
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args([out_file.to_str().unwrap(), "--dry-run", "--seed", "4"])
+        .assert()
+        .success();
+    assert!(!out_file.exists());
+}
+
+#[test]
+fn test_cli_resume_finishes_an_interrupted_run() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let run = |name: &str, resume: bool| {
+        let out_dir = temp_dir.path().join(name);
+        let manifest = temp_dir.path().join(format!("{name}.jsonl"));
+        let mut cmd = cargo_bin_cmd!("pickle-fuzzer");
+        cmd.args(["--dir", out_dir.to_str().unwrap()])
+            .args(["--manifest", manifest.to_str().unwrap()])
+            .args(["--samples", "10", "--seed", "8", "--shard-dirs", "2"]);
+        if resume {
+            cmd.arg("--resume");
+        }
+        cmd.assert().success();
+        (out_dir, manifest)
+    };
+    let (full_dir, full_manifest) = run("full", false);
+    let (dir, manifest) = run("resumed", false);
+
+    // interrupt the second run: lose two samples, one of them mid-write, and
+    // the manifest's tail
+    fs::remove_file(dir.join("1/3.pkl")).unwrap();
+    fs::write(dir.join("1/3.pkl.partial"), b"\x80").unwrap();
+    fs::remove_file(dir.join("0/8.pkl")).unwrap();
+    let text = fs::read_to_string(&manifest).unwrap();
+    let cut: Vec<&str> = text.lines().take(6).collect();
+    fs::write(
+        &manifest,
+        format!("{}\n{{\"index\": 6, \"fi", cut.join("\n")),
+    )
+    .unwrap();
+
+    let output = cargo_bin_cmd!("pickle-fuzzer")
+        .args(["--dir", dir.to_str().unwrap()])
+        .args(["--manifest", manifest.to_str().unwrap()])
+        .args([
+            "--samples",
+            "10",
+            "--seed",
+            "8",
+            "--shard-dirs",
+            "2",
+            "--resume",
+        ])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let output = String::from_utf8(output).unwrap();
+    // indices 0-5 minus the deleted 3 are kept
+    assert!(
+        output.contains("(5 kept from the interrupted run)"),
+        "{output}"
+    );
+
+    assert_eq!(
+        fs::read_to_string(&manifest).unwrap(),
+        fs::read_to_string(&full_manifest).unwrap()
+    );
+    for idx in 0..10 {
+        let file = format!("{}/{idx}.pkl", idx % 2);
+        assert_eq!(
+            fs::read(dir.join(&file)).unwrap(),
+            fs::read(full_dir.join(&file)).unwrap(),
+            "{file}"
+        );
+    }
+    assert!(!dir.join("1/3.pkl.partial").exists());
+}
+
+#[test]
+fn test_cli_global_allowlist_and_forbid_reduce() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let allowlist = temp_dir.path().join("allowed.txt");
+    let corpus = temp_dir.path().join("corpus");
+    fs::write(
+        &allowlist,
+        "# what the loader permits\ncollections OrderedDict\ndatetime.date\n",
+    )
+    .unwrap();
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args([
+            "--dir",
+            corpus.to_str().unwrap(),
+            "--samples",
+            "30",
+            "--seed",
+            "5",
+        ])
+        .arg("--global-allowlist")
+        .arg(&allowlist)
+        .args(["--forbid-reduce", "--interesting-patterns"])
+        .assert()
+        .success();
+
+    let output = cargo_bin_cmd!("pickle-fuzzer")
+        .args(["analyze", corpus.to_str().unwrap(), "--top", "0"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let report = String::from_utf8(output).unwrap();
+    assert!(
+        report.contains(", call: 0 (0.0%), gadget: 0 (0.0%)"),
+        "{report}"
+    );
+    let globals = report
+        .split_once("Globals: ")
+        .unwrap()
+        .1
+        .lines()
+        .skip(1)
+        .take_while(|line| line.starts_with("  "));
+    for line in globals {
+        let global = line.split_whitespace().next().unwrap();
+        assert!(
+            ["collections.OrderedDict", "datetime.date"].contains(&global),
+            "{report}"
+        );
+    }
+
+    fs::write(&allowlist, "builtins\n").unwrap();
+    cargo_bin_cmd!("pickle-fuzzer")
+        .arg("--global-allowlist")
+        .arg(&allowlist)
+        .arg(temp_dir.path().join("out.pkl"))
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_cli_loadable() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let corpus = temp_dir.path().join("corpus");
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args([
+            "--dir",
+            corpus.to_str().unwrap(),
+            "--samples",
+            "30",
+            "--seed",
+            "8",
+            "--loadable",
+            "--interesting-patterns",
+        ])
+        .assert()
+        .success();
+
+    let output = cargo_bin_cmd!("pickle-fuzzer")
+        .args(["analyze", corpus.to_str().unwrap(), "--top", "0"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let report = String::from_utf8(output).unwrap();
+    assert!(report.contains(", gadget: 0 (0.0%)"), "{report}");
+    let globals = report
+        .split_once("Globals: ")
+        .unwrap()
+        .1
+        .lines()
+        .skip(1)
+        .take_while(|line| line.starts_with("  "));
+    for line in globals {
+        let global = line.split_whitespace().next().unwrap();
+        assert!(
+            global.starts_with("builtins.") || global == "copyreg._reconstructor",
+            "{report}"
+        );
+    }
+
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args(["--loadable", "--unsafe-mutations"])
+        .arg(temp_dir.path().join("out.pkl"))
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_cli_ignore_signatures() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let corpus = temp_dir.path().join("corpus");
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args([
+            "--dir",
+            corpus.to_str().unwrap(),
+            "--samples",
+            "20",
+            "--seed",
+            "3",
+            "--ignore-signatures",
+        ])
+        .assert()
+        .success();
+
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args(["validate", corpus.to_str().unwrap()])
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_cli_sample_timeout() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let plain = temp_dir.path().join("plain");
+    let guarded = temp_dir.path().join("guarded");
+
+    // a generous budget changes nothing
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args([
+            "--dir",
+            plain.to_str().unwrap(),
+            "--samples",
+            "5",
+            "--seed",
+            "2",
+        ])
+        .assert()
+        .success();
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args([
+            "--dir",
+            guarded.to_str().unwrap(),
+            "--samples",
+            "5",
+            "--seed",
+            "2",
+        ])
+        .args(["--sample-timeout", "600000"])
+        .assert()
+        .success();
+    for idx in 0..5 {
+        let file = format!("{idx}.pkl");
+        assert_eq!(
+            fs::read(plain.join(&file)).unwrap(),
+            fs::read(guarded.join(&file)).unwrap()
+        );
+    }
+
+    // a sample that times out on every try is an error, not a hang
+    let output = cargo_bin_cmd!("pickle-fuzzer")
+        .args(["--dir", guarded.to_str().unwrap(), "--samples", "2"])
+        .args(["--sample-timeout", "0"])
+        .assert()
+        .failure()
+        .get_output()
+        .stderr
+        .clone();
+    let stderr = String::from_utf8(output).unwrap();
+    assert!(
+        stderr.contains("sample{idx=0}: timed out 4 times in a row"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn test_cli_metrics_file() {
+    let temp_dir = TempDir::new().unwrap();
+    let out_dir = temp_dir.path().join("samples");
+    let metrics = temp_dir.path().join("run.prom");
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args([
+            "--dir",
+            out_dir.to_str().unwrap(),
+            "--samples",
+            "20",
+            "--seed",
+            "1",
+        ])
+        .args(["--mutators", "bitflip", "--mutation-rate", "0.1"])
+        .args(["--metrics-file", metrics.to_str().unwrap()])
+        .assert()
+        .success();
+
+    let bytes: u64 = fs::read_dir(&out_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().metadata().unwrap().len())
+        .sum();
+    let text = fs::read_to_string(&metrics).unwrap();
+    let value = |name: &str| -> u64 {
+        text.lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(' '))
+            .unwrap_or_else(|| panic!("{name} missing from\n{text}"))
+            .parse()
+            .unwrap()
+    };
+    assert_eq!(value("pickle_fuzzer_samples_total"), 20);
+    assert_eq!(value("pickle_fuzzer_bytes_written_total"), bytes);
+    assert_eq!(value("pickle_fuzzer_failed_samples_total"), 0);
+    assert!(value("pickle_fuzzer_mutator_applications_total{mutator=\"bitflip\"}") > 0);
+}
+
+#[test]
+fn test_cli_json_logs() {
+    let temp_dir = TempDir::new().unwrap();
+    let output = cargo_bin_cmd!("pickle-fuzzer")
+        .args(["--dir", temp_dir.path().to_str().unwrap(), "--samples", "2"])
+        .args(["--sample-timeout", "0", "--log-format", "json"])
+        .env("RUST_LOG", "debug")
+        .assert()
+        .failure()
+        .get_output()
+        .stderr
+        .clone();
+    let events: Vec<serde_json::Value> = String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .take_while(|line| line.starts_with('{'))
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+
+    // every failure names its sample in the enclosing span
+    let failures: Vec<_> = events
+        .iter()
+        .filter(|event| event["fields"]["message"] == "timed out 4 times in a row")
+        .collect();
+    assert_eq!(failures.len(), 2, "{events:?}");
+    for (idx, event) in failures.into_iter().enumerate() {
+        assert_eq!(event["level"], "ERROR");
+        assert_eq!(
+            event["spans"][0],
+            serde_json::json!({"name": "sample", "idx": idx})
+        );
+    }
+    // and RUST_LOG=debug shows the retries behind them
+    assert!(events
+        .iter()
+        .any(|event| event["fields"]["message"] == "timed out, regenerating with fresh seeds"));
+}
+
+#[test]
+fn test_cli_dedupe_writes_unique_samples() {
+    use std::collections::HashSet;
+
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let run = |name: &str| {
+        let out_dir = temp_dir.path().join(name);
+        let manifest = temp_dir.path().join(format!("{name}.jsonl"));
+        // so few opcodes that plenty of samples come out byte-identical
+        let output = cargo_bin_cmd!("pickle-fuzzer")
+            .args(["--dir", out_dir.to_str().unwrap()])
+            .args(["--manifest", manifest.to_str().unwrap()])
+            .args(["--samples", "60", "--seed", "3", "--protocol", "1"])
+            .args(["--min-opcodes", "1", "--max-opcodes", "3", "--dedupe"])
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone();
+        (out_dir, manifest, String::from_utf8(output).unwrap())
+    };
+    let (out_dir, manifest, stdout) = run("first");
+    assert!(stdout.contains("Dedupe: "), "{stdout}");
+
+    let manifest = fs::read_to_string(&manifest).unwrap();
+    let mut pickles = HashSet::new();
+    let mut regenerated = 0;
+    for line in manifest.lines() {
+        let entry: serde_json::Value = serde_json::from_str(line).unwrap();
+        let pickle = fs::read(out_dir.join(entry["file"].as_str().unwrap())).unwrap();
+        assert!(pickles.insert(pickle), "{line}");
+        regenerated += usize::from(entry.get("duplicates").is_some());
+    }
+    assert!(regenerated > 0);
+    assert_eq!(fs::read_dir(&out_dir).unwrap().count(), pickles.len());
+
+    // deduplicating in index order keeps seeded runs reproducible
+    let (_, second_manifest, second_stdout) = run("second");
+    assert_eq!(stdout.replace("first", "second"), second_stdout);
+    assert_eq!(manifest, fs::read_to_string(second_manifest).unwrap());
+}
+
+#[test]
+fn test_cli_dedupe_structural_keeps_distinct_opcode_sequences() {
+    use pickle_fuzzer::disasm::structural_fingerprint;
+    use std::collections::HashSet;
+
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let out_dir = temp_dir.path().join("samples");
+    let output = cargo_bin_cmd!("pickle-fuzzer")
+        .args(["--dir", out_dir.to_str().unwrap()])
+        .args(["--samples", "40", "--seed", "5", "--protocol", "2"])
+        .args(["--min-opcodes", "4", "--max-opcodes", "6"])
+        .arg("--dedupe-structural")
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let stdout = String::from_utf8(output).unwrap();
+    assert!(
+        stdout.contains("repeated an earlier opcode sequence"),
+        "{stdout}"
+    );
+
+    let mut fingerprints = HashSet::new();
+    for entry in fs::read_dir(&out_dir).unwrap() {
+        let pickle = fs::read(entry.unwrap().path()).unwrap();
+        assert!(fingerprints.insert(structural_fingerprint(&pickle).unwrap()));
+    }
+    assert!(!fingerprints.is_empty());
+}
+
+#[test]
+fn test_cli_analyze_subcommand() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let corpus = temp_dir.path().join("corpus");
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args([
+            "--dir",
+            corpus.to_str().unwrap(),
+            "--samples",
+            "8",
+            "--seed",
+            "6",
+        ])
+        .args(["--protocol", "2", "--shard-dirs", "2"])
+        .assert()
+        .success();
+    // a GLOBAL, a STACK_GLOBAL of two pushed strings, and a broken file
+    fs::write(corpus.join("global.pkl"), b"cos\nsystem\n.").unwrap();
+    fs::write(
+        corpus.join("stack_global.pkl"),
+        b"\x80\x04\x8c\x02os\x94\x8c\x06system\x94\x93.",
+    )
+    .unwrap();
+    fs::write(corpus.join("broken.pkl"), b"\x80\x02K").unwrap();
+
+    let output = cargo_bin_cmd!("pickle-fuzzer")
+        .args(["analyze", corpus.to_str().unwrap(), "--top", "0"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let report = String::from_utf8(output).unwrap();
+    assert!(
+        report.contains("Analyzed 11 files") && report.contains(": 10 decode, 1 invalid"),
+        "{report}"
+    );
+    // the generated samples are protocol 2, the hand-written ones 0 and 4
+    assert!(
+        report.contains("Protocols: 0: 1 (10.0%), 2: 8 (80.0%), 4: 1 (10.0%)"),
+        "{report}"
+    );
+    assert!(report.contains("Sizes: min "), "{report}");
+    assert!(report.contains("Risks: data: "), "{report}");
+    assert!(report.contains("  STOP "), "{report}");
+    let os_system = report
+        .lines()
+        .find(|line| line.trim_start().starts_with("os.system "))
+        .unwrap_or_else(|| panic!("no os.system in {report}"));
+    assert!(
+        os_system
+            .split_whitespace()
+            .nth(1)
+            .unwrap()
+            .parse::<usize>()
+            .unwrap()
+            >= 2
+    );
+    assert!(report.contains("Invalid files:\n  "), "{report}");
+    assert!(report.contains("broken.pkl: "), "{report}");
+}
+
+#[test]
+fn test_cli_grep_subcommand() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let corpus = temp_dir.path().join("corpus");
+    fs::create_dir(&corpus).unwrap();
+    // a reduce of subprocess, an import of it without a call, a reduce of
+    // os.system, and bytes that only spell subprocess out in a string
+    fs::write(corpus.join("reduce.pkl"), b"csubprocess\ncall\n(S'ls'\ntR.").unwrap();
+    fs::write(corpus.join("import.pkl"), b"csubprocess\nPopen\n.").unwrap();
+    fs::write(corpus.join("system.pkl"), b"cos\nsystem\n(S'ls'\ntR.").unwrap();
+    fs::write(corpus.join("string.pkl"), b"S'csubprocess\\ncall'\n.").unwrap();
+
+    let grep = |patterns: &[&str]| {
+        let output = cargo_bin_cmd!("pickle-fuzzer")
+            .arg("grep")
+            .args(patterns)
+            .arg(&corpus)
+            .assert()
+            .success()
+            .get_output()
+            .clone();
+        let mut files: Vec<String> = String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .map(|line| {
+                let path = std::path::Path::new(line);
+                path.file_name().unwrap().to_string_lossy().into_owned()
+            })
+            .collect();
+        files.sort();
+        (files, String::from_utf8(output.stderr).unwrap())
+    };
+
+    let (files, summary) = grep(&["--global", "subprocess.*", "--opcode", "REDUCE"]);
+    assert_eq!(files, ["reduce.pkl"]);
+    assert!(summary.contains("1 of 4 files match"), "{summary}");
+    assert_eq!(
+        grep(&["--global", "subprocess.*"]).0,
+        ["import.pkl", "reduce.pkl"]
+    );
+    assert_eq!(grep(&["--global", "os.system"]).0, ["system.pkl"]);
+    assert_eq!(
+        grep(&[
+            "--global",
+            "subprocess.call",
+            "--global",
+            "os.system",
+            "--any"
+        ])
+        .0,
+        ["reduce.pkl", "system.pkl"]
+    );
+}
+
+#[test]
+fn test_cli_dataset_subcommand() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let corpus = temp_dir.path().join("corpus");
+    fs::create_dir_all(corpus.join("nested")).unwrap();
+    fs::write(corpus.join("data.pkl"), b"\x80\x02]q\x00K\x01a.").unwrap();
+    fs::write(
+        corpus.join("nested/system.pkl"),
+        b"cos\nsystem\n(S'ls'\ntR.",
+    )
+    .unwrap();
+    fs::write(corpus.join("broken.pkl"), b"\x80\x02K").unwrap();
+    let labels = temp_dir.path().join("labels.jsonl");
+
+    let summary = cargo_bin_cmd!("pickle-fuzzer")
+        .arg("dataset")
+        .arg(&corpus)
+        .arg("-o")
+        .arg(&labels)
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let summary = String::from_utf8(summary).unwrap();
+    assert!(
+        summary.contains("Labeled 3 pickles (2 valid; data: 2, import: 0, call: 0, gadget: 1)"),
+        "{summary}"
+    );
+
+    let labels: Vec<serde_json::Value> = fs::read_to_string(&labels)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let label = |file: &str| {
+        labels
+            .iter()
+            .find(|label| label["file"] == file)
+            .unwrap_or_else(|| panic!("no label for {file}: {labels:?}"))
+    };
+    assert_eq!(labels.len(), 3);
+
+    let data = label("data.pkl");
+    assert_eq!(data["protocol"], 2);
+    assert_eq!(data["valid"], true);
+    assert_eq!(data["risk"], "data");
+    assert_eq!(data["globals"], serde_json::json!([]));
+
+    let system = label(
+        &std::path::Path::new("nested")
+            .join("system.pkl")
+            .display()
+            .to_string(),
+    );
+    assert_eq!(system["protocol"], 0);
+    assert_eq!(system["risk"], "gadget");
+    assert_eq!(system["globals"], serde_json::json!(["os.system"]));
+    assert!(system["structure"].is_string());
+
+    let broken = label("broken.pkl");
+    assert_eq!(broken["valid"], false);
+    assert!(broken["error"].is_string());
+    assert!(broken["protocol"].is_null());
+}
+
+#[test]
+fn test_cli_distill_subcommand() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let corpus = temp_dir.path().join("corpus");
+    let distilled = temp_dir.path().join("distilled");
+    cargo_bin_cmd!("pickle-fuzzer")
+        .args([
+            "--dir",
+            corpus.to_str().unwrap(),
+            "--samples",
+            "60",
+            "--seed",
+            "3",
+        ])
+        .args([
+            "--protocol",
+            "2",
+            "--min-opcodes",
+            "5",
+            "--max-opcodes",
+            "12",
+        ])
+        .args(["--shard-dirs", "2"])
+        .assert()
+        .success();
+    // copies and broken files add nothing to cover
+    fs::copy(corpus.join("0/0.pkl"), corpus.join("copy.pkl")).unwrap();
+    fs::write(corpus.join("broken.pkl"), b"\x80\x02K").unwrap();
+
+    let distill = |input: &std::path::Path, output: &std::path::Path| {
+        let stdout = cargo_bin_cmd!("pickle-fuzzer")
+            .args(["distill", input.to_str().unwrap()])
+            .args(["-o", output.to_str().unwrap()])
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone();
+        String::from_utf8(stdout).unwrap()
+    };
+    let report = distill(&corpus, &distilled);
+    let kept: usize = report
+        .strip_prefix("Distilled ")
+        .and_then(|rest| rest.split(' ').next())
+        .and_then(|kept| kept.parse().ok())
+        .unwrap_or_else(|| panic!("{report}"));
+    assert!(kept < 60, "{report}");
+    assert!(report.contains(" of 62 files "), "{report}");
+    assert!(!distilled.join("broken.pkl").exists());
+    // the selection keeps its paths below the corpus ...
+    assert!(distilled.join("0").is_dir() || distilled.join("1").is_dir());
+
+    // ... and all of the corpus' coverage
+    let again = distill(&distilled, &temp_dir.path().join("again"));
+    let coverage = |report: &str| report.split_once("covering").unwrap().1.to_owned();
+    assert_eq!(coverage(&report), coverage(&again));
+    assert!(again.contains(&format!(" of {kept} files ")), "{again}");
+}
@@ -0,0 +1,443 @@
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright 2025 Cisco Systems, Inc. and its affiliates
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! property tests for generator invariants.
+//!
+//! random generator configurations are run through a small native pickle
+//! walker that checks the structural invariants otherwise only verified by
+//! shelling out to Python: STOP is the last byte with exactly one object on
+//! the stack, PROTO appears at most once and only first, every opcode belongs
+//! to the declared protocol, and memo GETs only read indices that were PUT.
+//! the walk also counts opcodes and the peak stack depth, which must match
+//! `Generator::stats` and stay within any configured stack depth limit.
+
+use proptest::prelude::*;
+
+use pickle_fuzzer::mutators::{
+    BitFlipMutator, BoundaryMutator, CharacterMutator, Mutator, OffByOneMutator,
+    StringLengthMutator,
+};
+use pickle_fuzzer::{CleanupPolicy, Generator, Version};
+
+/// how an opcode's inline argument is encoded.
+#[derive(Clone, Copy)]
+enum Arg {
+    None,
+    /// fixed number of bytes
+    Fixed(usize),
+    /// little-endian length prefix of the given width, then that many bytes
+    Counted(usize),
+    /// newline-terminated
+    Line,
+    /// two newline-terminated lines
+    TwoLines,
+}
+
+/// stack effect and protocol of an opcode, as described by `pickletools`.
+struct OpInfo {
+    code: u8,
+    name: &'static str,
+    proto: u8,
+    arg: Arg,
+    /// for MARK-consuming opcodes, how many items below the MARK are popped
+    below_mark: Option<usize>,
+    pops: usize,
+    pushes: usize,
+}
+
+#[rustfmt::skip]
+const OPCODES: &[OpInfo] = &[
+    OpInfo { code: 0x49, name: "INT", proto: 0, arg: Arg::Line, below_mark: None, pops: 0, pushes: 1 },
+    OpInfo { code: 0x4a, name: "BININT", proto: 1, arg: Arg::Fixed(4), below_mark: None, pops: 0, pushes: 1 },
+    OpInfo { code: 0x4b, name: "BININT1", proto: 1, arg: Arg::Fixed(1), below_mark: None, pops: 0, pushes: 1 },
+    OpInfo { code: 0x4d, name: "BININT2", proto: 1, arg: Arg::Fixed(2), below_mark: None, pops: 0, pushes: 1 },
+    OpInfo { code: 0x4c, name: "LONG", proto: 0, arg: Arg::Line, below_mark: None, pops: 0, pushes: 1 },
+    OpInfo { code: 0x8a, name: "LONG1", proto: 2, arg: Arg::Counted(1), below_mark: None, pops: 0, pushes: 1 },
+    OpInfo { code: 0x8b, name: "LONG4", proto: 2, arg: Arg::Counted(4), below_mark: None, pops: 0, pushes: 1 },
+    OpInfo { code: 0x53, name: "STRING", proto: 0, arg: Arg::Line, below_mark: None, pops: 0, pushes: 1 },
+    OpInfo { code: 0x54, name: "BINSTRING", proto: 1, arg: Arg::Counted(4), below_mark: None, pops: 0, pushes: 1 },
+    OpInfo { code: 0x55, name: "SHORT_BINSTRING", proto: 1, arg: Arg::Counted(1), below_mark: None, pops: 0, pushes: 1 },
+    OpInfo { code: 0x42, name: "BINBYTES", proto: 3, arg: Arg::Counted(4), below_mark: None, pops: 0, pushes: 1 },
+    OpInfo { code: 0x43, name: "SHORT_BINBYTES", proto: 3, arg: Arg::Counted(1), below_mark: None, pops: 0, pushes: 1 },
+    OpInfo { code: 0x8e, name: "BINBYTES8", proto: 4, arg: Arg::Counted(8), below_mark: None, pops: 0, pushes: 1 },
+    OpInfo { code: 0x96, name: "BYTEARRAY8", proto: 5, arg: Arg::Counted(8), below_mark: None, pops: 0, pushes: 1 },
+    OpInfo { code: 0x97, name: "NEXT_BUFFER", proto: 5, arg: Arg::None, below_mark: None, pops: 0, pushes: 1 },
+    OpInfo { code: 0x98, name: "READONLY_BUFFER", proto: 5, arg: Arg::None, below_mark: None, pops: 1, pushes: 1 },
+    OpInfo { code: 0x4e, name: "NONE", proto: 0, arg: Arg::None, below_mark: None, pops: 0, pushes: 1 },
+    OpInfo { code: 0x88, name: "NEWTRUE", proto: 2, arg: Arg::None, below_mark: None, pops: 0, pushes: 1 },
+    OpInfo { code: 0x89, name: "NEWFALSE", proto: 2, arg: Arg::None, below_mark: None, pops: 0, pushes: 1 },
+    OpInfo { code: 0x56, name: "UNICODE", proto: 0, arg: Arg::Line, below_mark: None, pops: 0, pushes: 1 },
+    OpInfo { code: 0x8c, name: "SHORT_BINUNICODE", proto: 4, arg: Arg::Counted(1), below_mark: None, pops: 0, pushes: 1 },
+    OpInfo { code: 0x58, name: "BINUNICODE", proto: 1, arg: Arg::Counted(4), below_mark: None, pops: 0, pushes: 1 },
+    OpInfo { code: 0x8d, name: "BINUNICODE8", proto: 4, arg: Arg::Counted(8), below_mark: None, pops: 0, pushes: 1 },
+    OpInfo { code: 0x46, name: "FLOAT", proto: 0, arg: Arg::Line, below_mark: None, pops: 0, pushes: 1 },
+    OpInfo { code: 0x47, name: "BINFLOAT", proto: 1, arg: Arg::Fixed(8), below_mark: None, pops: 0, pushes: 1 },
+    OpInfo { code: 0x5d, name: "EMPTY_LIST", proto: 1, arg: Arg::None, below_mark: None, pops: 0, pushes: 1 },
+    OpInfo { code: 0x61, name: "APPEND", proto: 0, arg: Arg::None, below_mark: None, pops: 2, pushes: 1 },
+    OpInfo { code: 0x65, name: "APPENDS", proto: 1, arg: Arg::None, below_mark: Some(1), pops: 0, pushes: 1 },
+    OpInfo { code: 0x6c, name: "LIST", proto: 0, arg: Arg::None, below_mark: Some(0), pops: 0, pushes: 1 },
+    OpInfo { code: 0x29, name: "EMPTY_TUPLE", proto: 1, arg: Arg::None, below_mark: None, pops: 0, pushes: 1 },
+    OpInfo { code: 0x74, name: "TUPLE", proto: 0, arg: Arg::None, below_mark: Some(0), pops: 0, pushes: 1 },
+    OpInfo { code: 0x85, name: "TUPLE1", proto: 2, arg: Arg::None, below_mark: None, pops: 1, pushes: 1 },
+    OpInfo { code: 0x86, name: "TUPLE2", proto: 2, arg: Arg::None, below_mark: None, pops: 2, pushes: 1 },
+    OpInfo { code: 0x87, name: "TUPLE3", proto: 2, arg: Arg::None, below_mark: None, pops: 3, pushes: 1 },
+    OpInfo { code: 0x7d, name: "EMPTY_DICT", proto: 1, arg: Arg::None, below_mark: None, pops: 0, pushes: 1 },
+    OpInfo { code: 0x64, name: "DICT", proto: 0, arg: Arg::None, below_mark: Some(0), pops: 0, pushes: 1 },
+    OpInfo { code: 0x73, name: "SETITEM", proto: 0, arg: Arg::None, below_mark: None, pops: 3, pushes: 1 },
+    OpInfo { code: 0x75, name: "SETITEMS", proto: 1, arg: Arg::None, below_mark: Some(1), pops: 0, pushes: 1 },
+    OpInfo { code: 0x8f, name: "EMPTY_SET", proto: 4, arg: Arg::None, below_mark: None, pops: 0, pushes: 1 },
+    OpInfo { code: 0x90, name: "ADDITEMS", proto: 4, arg: Arg::None, below_mark: Some(1), pops: 0, pushes: 1 },
+    OpInfo { code: 0x91, name: "FROZENSET", proto: 4, arg: Arg::None, below_mark: Some(0), pops: 0, pushes: 1 },
+    OpInfo { code: 0x30, name: "POP", proto: 0, arg: Arg::None, below_mark: None, pops: 1, pushes: 0 },
+    OpInfo { code: 0x32, name: "DUP", proto: 0, arg: Arg::None, below_mark: None, pops: 1, pushes: 2 },
+    OpInfo { code: 0x28, name: "MARK", proto: 0, arg: Arg::None, below_mark: None, pops: 0, pushes: 0 },
+    OpInfo { code: 0x31, name: "POP_MARK", proto: 1, arg: Arg::None, below_mark: Some(0), pops: 0, pushes: 0 },
+    OpInfo { code: 0x67, name: "GET", proto: 0, arg: Arg::Line, below_mark: None, pops: 0, pushes: 1 },
+    OpInfo { code: 0x68, name: "BINGET", proto: 1, arg: Arg::Fixed(1), below_mark: None, pops: 0, pushes: 1 },
+    OpInfo { code: 0x6a, name: "LONG_BINGET", proto: 1, arg: Arg::Fixed(4), below_mark: None, pops: 0, pushes: 1 },
+    OpInfo { code: 0x70, name: "PUT", proto: 0, arg: Arg::Line, below_mark: None, pops: 0, pushes: 0 },
+    OpInfo { code: 0x71, name: "BINPUT", proto: 1, arg: Arg::Fixed(1), below_mark: None, pops: 0, pushes: 0 },
+    OpInfo { code: 0x72, name: "LONG_BINPUT", proto: 1, arg: Arg::Fixed(4), below_mark: None, pops: 0, pushes: 0 },
+    OpInfo { code: 0x94, name: "MEMOIZE", proto: 4, arg: Arg::None, below_mark: None, pops: 1, pushes: 1 },
+    OpInfo { code: 0x82, name: "EXT1", proto: 2, arg: Arg::Fixed(1), below_mark: None, pops: 0, pushes: 1 },
+    OpInfo { code: 0x83, name: "EXT2", proto: 2, arg: Arg::Fixed(2), below_mark: None, pops: 0, pushes: 1 },
+    OpInfo { code: 0x84, name: "EXT4", proto: 2, arg: Arg::Fixed(4), below_mark: None, pops: 0, pushes: 1 },
+    OpInfo { code: 0x63, name: "GLOBAL", proto: 0, arg: Arg::TwoLines, below_mark: None, pops: 0, pushes: 1 },
+    OpInfo { code: 0x93, name: "STACK_GLOBAL", proto: 4, arg: Arg::None, below_mark: None, pops: 2, pushes: 1 },
+    OpInfo { code: 0x52, name: "REDUCE", proto: 0, arg: Arg::None, below_mark: None, pops: 2, pushes: 1 },
+    OpInfo { code: 0x62, name: "BUILD", proto: 0, arg: Arg::None, below_mark: None, pops: 2, pushes: 1 },
+    OpInfo { code: 0x69, name: "INST", proto: 0, arg: Arg::TwoLines, below_mark: Some(0), pops: 0, pushes: 1 },
+    OpInfo { code: 0x6f, name: "OBJ", proto: 1, arg: Arg::None, below_mark: Some(0), pops: 0, pushes: 1 },
+    OpInfo { code: 0x81, name: "NEWOBJ", proto: 2, arg: Arg::None, below_mark: None, pops: 2, pushes: 1 },
+    OpInfo { code: 0x92, name: "NEWOBJ_EX", proto: 4, arg: Arg::None, below_mark: None, pops: 3, pushes: 1 },
+    OpInfo { code: 0x80, name: "PROTO", proto: 2, arg: Arg::Fixed(1), below_mark: None, pops: 0, pushes: 0 },
+    OpInfo { code: 0x2e, name: "STOP", proto: 0, arg: Arg::None, below_mark: None, pops: 1, pushes: 0 },
+    OpInfo { code: 0x95, name: "FRAME", proto: 4, arg: Arg::Fixed(8), below_mark: None, pops: 0, pushes: 0 },
+    OpInfo { code: 0x50, name: "PERSID", proto: 0, arg: Arg::Line, below_mark: None, pops: 0, pushes: 1 },
+    OpInfo { code: 0x51, name: "BINPERSID", proto: 1, arg: Arg::None, below_mark: None, pops: 1, pushes: 1 },
+];
+
+/// a simulated stack slot.
+#[derive(Clone, Copy, PartialEq)]
+enum Slot {
+    Mark,
+    Object,
+}
+
+fn read_arg<'a>(pickle: &'a [u8], pos: &mut usize, arg: Arg) -> Result<&'a [u8], String> {
+    let take = |pos: &mut usize, len: usize| -> Result<&'a [u8], String> {
+        let bytes = pickle
+            .get(*pos..*pos + len)
+            .ok_or_else(|| format!("argument at {} runs past the end", *pos))?;
+        *pos += len;
+        Ok(bytes)
+    };
+    let line = |pos: &mut usize| -> Result<&'a [u8], String> {
+        let len = pickle[*pos..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .ok_or_else(|| format!("unterminated line argument at {}", *pos))?;
+        let bytes = take(pos, len)?;
+        *pos += 1;
+        Ok(bytes)
+    };
+
+    match arg {
+        Arg::None => Ok(&[]),
+        Arg::Fixed(len) => take(pos, len),
+        Arg::Counted(width) => {
+            let mut len = [0u8; 8];
+            len[..width].copy_from_slice(take(pos, width)?);
+            let len = usize::try_from(u64::from_le_bytes(len))
+                .map_err(|_| "argument length overflows usize".to_string())?;
+            take(pos, len)
+        }
+        Arg::Line => line(pos),
+        Arg::TwoLines => {
+            let start = *pos;
+            line(pos)?;
+            line(pos)?;
+            Ok(&pickle[start..*pos])
+        }
+    }
+}
+
+fn memo_index(info: &OpInfo, arg: &[u8]) -> Result<usize, String> {
+    match info.arg {
+        Arg::Line => std::str::from_utf8(arg)
+            .ok()
+            .and_then(|s| s.trim().replace('_', "").parse().ok())
+            .ok_or_else(|| format!("{} has non-decimal index {arg:?}", info.name)),
+        _ => {
+            let mut index = [0u8; 8];
+            index[..arg.len()].copy_from_slice(arg);
+            Ok(u64::from_le_bytes(index) as usize)
+        }
+    }
+}
+
+/// what a successful walk observed, for comparison with `Generator::stats`.
+#[derive(Debug, PartialEq, Eq)]
+struct Walk {
+    opcodes: usize,
+    peak_stack_depth: usize,
+}
+
+/// walk `pickle` and check the invariants listed in the module docs.
+fn check_invariants(pickle: &[u8], version: Version) -> Result<Walk, String> {
+    let protocol = version as u8;
+    let mut stack: Vec<Slot> = Vec::new();
+    let mut memo = std::collections::HashSet::new();
+    let mut pos = 0;
+    let mut opcodes = 0;
+    let mut peak_stack_depth = 0;
+
+    while pos < pickle.len() {
+        opcodes += 1;
+        let offset = pos;
+        let code = pickle[pos];
+        pos += 1;
+        let info = OPCODES
+            .iter()
+            .find(|op| op.code == code)
+            .ok_or_else(|| format!("unknown opcode {code:#04x} at {offset}"))?;
+        if info.proto > protocol {
+            return Err(format!(
+                "{} at {offset} needs protocol {} but the pickle is protocol {protocol}",
+                info.name, info.proto
+            ));
+        }
+        let arg = read_arg(pickle, &mut pos, info.arg)?;
+
+        match info.name {
+            "PROTO" if offset != 0 => {
+                return Err(format!("PROTO at {offset} is not the first opcode"));
+            }
+            "STOP" => {
+                if pos != pickle.len() {
+                    return Err(format!("{} trailing bytes after STOP", pickle.len() - pos));
+                }
+                if stack != [Slot::Object] {
+                    return Err(format!("{} stack slots at STOP", stack.len()));
+                }
+                return Ok(Walk {
+                    opcodes,
+                    peak_stack_depth,
+                });
+            }
+            "MARK" => {
+                stack.push(Slot::Mark);
+                peak_stack_depth = peak_stack_depth.max(stack.len());
+                continue;
+            }
+            "FRAME" => continue,
+            "PUT" | "BINPUT" | "LONG_BINPUT" | "MEMOIZE" => {
+                if stack.last() != Some(&Slot::Object) {
+                    return Err(format!(
+                        "{} at {offset} without an object on top",
+                        info.name
+                    ));
+                }
+                let index = if info.name == "MEMOIZE" {
+                    memo.len()
+                } else {
+                    memo_index(info, arg)?
+                };
+                memo.insert(index);
+                continue;
+            }
+            "GET" | "BINGET" | "LONG_BINGET" => {
+                let index = memo_index(info, arg)?;
+                if !memo.contains(&index) {
+                    return Err(format!(
+                        "{} at {offset} reads unset memo index {index}",
+                        info.name
+                    ));
+                }
+            }
+            _ => {}
+        }
+
+        if let Some(below) = info.below_mark {
+            let mark = stack
+                .iter()
+                .rposition(|&slot| slot == Slot::Mark)
+                .ok_or_else(|| format!("{} at {offset} without a MARK", info.name))?;
+            stack.truncate(mark);
+            if stack.len() < below || stack[stack.len() - below..].contains(&Slot::Mark) {
+                return Err(format!("{} at {offset} is missing its target", info.name));
+            }
+            stack.truncate(stack.len() - below);
+        } else {
+            if stack.len() < info.pops {
+                return Err(format!("{} at {offset} underflows the stack", info.name));
+            }
+            let popped = stack.split_off(stack.len() - info.pops);
+            if popped.contains(&Slot::Mark) && info.name != "POP" {
+                return Err(format!("{} at {offset} consumes a MARK", info.name));
+            }
+        }
+        stack.extend(std::iter::repeat_n(Slot::Object, info.pushes));
+        peak_stack_depth = peak_stack_depth.max(stack.len());
+    }
+
+    Err("pickle does not end with STOP".to_string())
+}
+
+/// a generator configuration drawn by proptest.
+#[derive(Debug, Clone)]
+struct Config {
+    version: Version,
+    seed: u64,
+    min_opcodes: usize,
+    max_opcodes: usize,
+    mutation_rate: f64,
+    mutators: u8,
+    ext_opcodes: bool,
+    buffer_opcodes: bool,
+    persistent_id_opcodes: bool,
+    cleanup_policy: CleanupPolicy,
+    max_stack_depth: Option<usize>,
+}
+
+impl Config {
+    fn build(&self) -> Generator {
+        // safe mutators only: the invariants describe valid pickles
+        let all: [fn() -> Box<dyn Mutator>; 5] = [
+            || Box::new(BitFlipMutator),
+            || Box::new(BoundaryMutator),
+            || Box::new(OffByOneMutator),
+            || Box::new(StringLengthMutator::default()),
+            || Box::new(CharacterMutator::default()),
+        ];
+        let mutators = all
+            .iter()
+            .enumerate()
+            .filter(|(bit, _)| self.mutators & (1 << bit) != 0)
+            .map(|(_, make)| make())
+            .collect();
+
+        let generator = Generator::new(self.version)
+            .with_seed(self.seed)
+            .with_opcode_range(self.min_opcodes, self.max_opcodes)
+            .with_mutation_rate(self.mutation_rate)
+            .with_mutators(mutators)
+            .with_ext_opcodes(self.ext_opcodes)
+            .with_buffer_opcodes(self.buffer_opcodes)
+            .with_persistent_id_opcodes(self.persistent_id_opcodes)
+            .with_cleanup_policy(self.cleanup_policy)
+            .with_strict_checks(true);
+        match self.max_stack_depth {
+            Some(depth) => generator.with_max_stack_depth(depth),
+            None => generator,
+        }
+    }
+
+    /// check `pickle` against the invariants and the generator's own stats.
+    fn check(&self, generator: &Generator, pickle: &[u8]) -> Result<(), String> {
+        let walk = check_invariants(pickle, self.version)?;
+        let stats = generator.stats();
+        if (walk.opcodes, walk.peak_stack_depth) != (stats.opcodes, stats.peak_stack_depth) {
+            return Err(format!("walked {walk:?}, generator reported {stats:?}"));
+        }
+        if let Some(limit) = self.max_stack_depth {
+            if walk.peak_stack_depth > limit {
+                return Err(format!(
+                    "stack depth {} exceeds limit {limit}",
+                    walk.peak_stack_depth
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn config() -> impl Strategy<Value = Config> {
+    (
+        (0usize..=5).prop_map(|v| Version::try_from(v).unwrap()),
+        any::<u64>(),
+        // small enough to keep cases fast, large enough for any protocol's
+        // minimum pickle so generation itself never fails
+        5usize..150,
+        5usize..150,
+        0.0f64..=1.0,
+        0u8..32,
+        any::<(bool, bool, bool, bool)>(),
+        proptest::option::of(1usize..12),
+    )
+        .prop_map(
+            |(
+                version,
+                seed,
+                min_opcodes,
+                max_opcodes,
+                mutation_rate,
+                mutators,
+                flags,
+                max_stack_depth,
+            )| Config {
+                version,
+                seed,
+                min_opcodes,
+                max_opcodes,
+                mutation_rate,
+                mutators,
+                ext_opcodes: flags.0,
+                buffer_opcodes: flags.1,
+                persistent_id_opcodes: flags.2,
+                cleanup_policy: if flags.3 {
+                    CleanupPolicy::KeepRoot
+                } else {
+                    CleanupPolicy::Tuple
+                },
+                max_stack_depth,
+            },
+        )
+}
+
+proptest! {
+    #[test]
+    fn seeded_generation_upholds_invariants(config in config()) {
+        let mut gen = config.build();
+        let pickle = gen.generate().unwrap();
+        prop_assert_eq!(config.check(&gen, &pickle), Ok(()));
+    }
+
+    #[test]
+    fn arbitrary_generation_upholds_invariants(
+        config in config(),
+        data in proptest::collection::vec(any::<u8>(), 0..2048),
+    ) {
+        let mut gen = config.build();
+        let pickle = gen.generate_from_arbitrary(&data).unwrap();
+        prop_assert_eq!(config.check(&gen, &pickle), Ok(()));
+    }
+}
+
+#[test]
+fn invariant_checker_rejects_broken_pickles() {
+    assert!(check_invariants(b"N.", Version::V0).is_ok());
+    // two objects at STOP
+    assert!(check_invariants(b"NN.", Version::V0).is_err());
+    // NEWTRUE is protocol 2
+    assert!(check_invariants(b"\x88.", Version::V1).is_err());
+    // PROTO after another opcode
+    assert!(check_invariants(b"N\x80\x02.", Version::V2).is_err());
+    // GET of a memo index that was never PUT
+    assert!(check_invariants(b"Np0\n0g1\n.", Version::V0).is_err());
+    // trailing bytes after STOP
+    assert!(check_invariants(b"N.N", Version::V0).is_err());
+}